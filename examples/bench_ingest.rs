@@ -0,0 +1,78 @@
+//! 基准：批量写入消息的吞吐（每批一个写事务，含变更计数递增）
+//!
+//! 运行: cargo run --release --example bench_ingest
+
+use ai_cli_session_db::db::MessageInput;
+use ai_cli_session_db::{DbConfig, MessageType, SessionDB};
+use std::time::Instant;
+
+const SESSION_COUNT: usize = 20;
+const BATCHES_PER_SESSION: usize = 50;
+const BATCH_SIZE: usize = 20;
+
+fn main() {
+    let tmp = tempfile::TempDir::new().unwrap();
+    let db = SessionDB::connect(DbConfig::local(tmp.path().join("bench.db"))).unwrap();
+
+    let project_id = db
+        .get_or_create_project("bench", "/bench", "claude")
+        .unwrap();
+    for s in 0..SESSION_COUNT {
+        db.upsert_session(&format!("bench-session-{}", s), project_id)
+            .unwrap();
+    }
+    let counter_before = db.change_counter().unwrap();
+
+    let start = Instant::now();
+    let mut total = 0;
+    for batch in 0..BATCHES_PER_SESSION {
+        for s in 0..SESSION_COUNT {
+            let session_id = format!("bench-session-{}", s);
+            let messages: Vec<MessageInput> = (0..BATCH_SIZE)
+                .map(|i| {
+                    let seq = (batch * BATCH_SIZE + i) as i64;
+                    MessageInput {
+                        uuid: format!("{}-{}", session_id, seq),
+                        r#type: if i % 2 == 0 {
+                            MessageType::User
+                        } else {
+                            MessageType::Assistant
+                        },
+                        content_text: format!("Message {}", seq),
+                        content_full: format!("Message {} in {}", seq, session_id),
+                        timestamp: 1_000_000 + seq,
+                        sequence: seq,
+                        source: None,
+                        channel: None,
+                        model: None,
+                        tool_call_id: None,
+                        tool_name: None,
+                        tool_args: None,
+                        raw: None,
+                        approval_status: None,
+                        approval_resolved_at: None,
+                    }
+                })
+                .collect();
+            total += db.insert_messages(&session_id, &messages).unwrap().0;
+        }
+    }
+    let elapsed = start.elapsed();
+    let transactions = SESSION_COUNT * BATCHES_PER_SESSION;
+
+    println!(
+        "=== {} 个事务，每个 {} 条消息 ===\n",
+        transactions, BATCH_SIZE
+    );
+    println!("总耗时:     {:>10.2?}", elapsed);
+    println!(
+        "消息吞吐:   {:>10.0} 条/秒",
+        total as f64 / elapsed.as_secs_f64()
+    );
+    println!("事务平均:   {:>10.2?}", elapsed / transactions as u32);
+    println!(
+        "变更计数:   {} → {}",
+        counter_before,
+        db.change_counter().unwrap()
+    );
+}
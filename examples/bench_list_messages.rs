@@ -0,0 +1,74 @@
+//! 基准：列出大 raw 会话的消息（加载 / 不加载 raw）
+//!
+//! 运行: cargo run --release --example bench_list_messages
+
+use ai_cli_session_db::db::MessageInput;
+use ai_cli_session_db::{DbConfig, MessageType, Order, SessionDB};
+use std::time::Instant;
+
+const MESSAGE_COUNT: usize = 500;
+const RAW_SIZE: usize = 100 * 1024; // 100KB
+const ROUNDS: usize = 5;
+
+fn main() {
+    let tmp = tempfile::TempDir::new().unwrap();
+    let db = SessionDB::connect(DbConfig::local(tmp.path().join("bench.db"))).unwrap();
+
+    let project_id = db
+        .get_or_create_project("bench", "/bench", "claude")
+        .unwrap();
+    db.upsert_session("bench-session", project_id).unwrap();
+
+    let payload = "x".repeat(RAW_SIZE);
+    let messages: Vec<MessageInput> = (0..MESSAGE_COUNT)
+        .map(|i| MessageInput {
+            uuid: format!("bench-{}", i),
+            r#type: if i % 2 == 0 {
+                MessageType::User
+            } else {
+                MessageType::Assistant
+            },
+            content_text: format!("Message {}", i),
+            content_full: format!("Message {}", i),
+            timestamp: 1_000_000 + i as i64,
+            sequence: i as i64,
+            source: None,
+            channel: None,
+            model: None,
+            tool_call_id: None,
+            tool_name: None,
+            tool_args: None,
+            raw: Some(payload.clone()),
+            approval_status: None,
+            approval_resolved_at: None,
+        })
+        .collect();
+    db.insert_messages("bench-session", &messages).unwrap();
+
+    println!(
+        "=== {} 条消息，每条 raw {}KB ===\n",
+        MESSAGE_COUNT,
+        RAW_SIZE / 1024
+    );
+
+    for with_raw in [true, false] {
+        let start = Instant::now();
+        let mut bytes = 0;
+        for _ in 0..ROUNDS {
+            let loaded = db
+                .list_messages_ordered("bench-session", MESSAGE_COUNT, 0, Order::Asc, with_raw)
+                .unwrap();
+            bytes = loaded
+                .iter()
+                .map(|m| m.raw.as_ref().map(|r| r.len()).unwrap_or(0))
+                .sum::<usize>();
+        }
+        let avg = start.elapsed() / ROUNDS as u32;
+        println!(
+            "with_raw={:<5}  平均耗时: {:>10.2?}  raw 总量: {} KB",
+            with_raw,
+            avg,
+            bytes / 1024
+        );
+    }
+}
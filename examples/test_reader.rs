@@ -55,7 +55,7 @@ fn main() {
     println!("\n3. 测试 list_projects\n");
 
     let mut reader = SessionReader::new(projects_path.clone());
-    let projects = reader.list_projects(Some(5));
+    let projects = reader.list_projects(Some(5)).unwrap_or_default();
 
     println!("前 5 个项目:");
     for project in projects {
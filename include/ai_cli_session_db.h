@@ -37,9 +37,32 @@ typedef enum FfiError {
     RequestFailed = 8,
     AgentNotFound = 9,
     RuntimeError = 10,
+    InvalidPageToken = 11,
+    NotFound = 12,
+    WriterFinished = 13,
+    InvalidArgument = 14,
     Unknown = 99,
 } FfiError;
 
+/**
+ * 忽略规则类型 C 枚举
+ * 0 = PathGlob, 1 = ProjectPath, 2 = SessionPrefix
+ */
+typedef enum IgnoreKindC {
+    PathGlob = 0,
+    ProjectPath = 1,
+    SessionPrefix = 2,
+} IgnoreKindC;
+
+/**
+ * 消息排序方向 C 枚举（对应 `reader::Order`）
+ * 0 = Asc (最早的在前), 1 = Desc (最新的在前)
+ */
+typedef enum OrderC {
+    Asc = 0,
+    Desc = 1,
+} OrderC;
+
 /**
  * 搜索排序方式 C 枚举
  * 0 = Score (相关性), 1 = TimeDesc (时间倒序), 2 = TimeAsc (时间正序)
@@ -50,6 +73,16 @@ typedef enum SearchOrderByC {
     TimeAsc = 2,
 } SearchOrderByC;
 
+/**
+ * 工具消息处理方式 C 枚举（对应 `ToolMessageMode`）
+ * 0 = Full (完整), 1 = Summarized (一行摘要), 2 = Omitted (不导出)
+ */
+typedef enum ToolMessageModeC {
+    Full = 0,
+    Summarized = 1,
+    Omitted = 2,
+} ToolMessageModeC;
+
 /**
  * 不透明句柄
  */
@@ -60,6 +93,14 @@ typedef struct AgentClientHandle AgentClientHandle;
  */
 typedef struct SessionDbHandle SessionDbHandle;
 
+/**
+ * 分块写入器（不透明句柄）
+ *
+ * 外部生成的大量消息（如从其他工具导入的对话）分多次 push，每次一个事务，
+ * 只保留一块消息的缓冲区；finish 时更新一次会话计数。
+ */
+typedef struct SessionDbWriter SessionDbWriter;
+
 /**
  * Project C 结构体
  */
@@ -159,6 +200,29 @@ typedef struct SearchResultArray {
     uintptr_t len;
 } SearchResultArray;
 
+/**
+ * 按会话分组的搜索结果 C 结构体
+ */
+typedef struct SessionSearchGroupC {
+    char *session_id;
+    int64_t project_id;
+    char *project_name;
+    char *project_path;
+    int64_t message_count;
+    int64_t last_message_at;
+    int64_t hit_count;
+    double best_score;
+    struct SearchResultArray hits;
+} SessionSearchGroupC;
+
+/**
+ * C 数组 wrapper
+ */
+typedef struct SessionSearchGroupArray {
+    struct SessionSearchGroupC *data;
+    uintptr_t len;
+} SessionSearchGroupArray;
+
 /**
  * IndexableMessage C 结构体
  */
@@ -281,6 +345,14 @@ typedef struct CollectResultC {
      * 第一个错误信息（如果有）
      */
     char *first_error;
+    /**
+     * 第一个错误的会话文件路径（没有错误或路径未知时为 NULL）
+     */
+    char *first_error_path;
+    /**
+     * 第一个错误的阶段：discover / parse / convert / insert（没有错误时为 NULL）
+     */
+    char *first_error_stage;
 } CollectResultC;
 
 /**
@@ -302,6 +374,69 @@ typedef struct SessionRelationArray {
     uintptr_t len;
 } SessionRelationArray;
 
+/**
+ * TalkSummary C 结构体
+ */
+typedef struct TalkSummaryC {
+    int64_t id;
+    char *session_id;
+    char *talk_id;
+    char *summary_l2;
+    char *summary_l3;
+    int64_t position;
+    int64_t first_message_sequence;
+    int64_t created_at;
+    int64_t updated_at;
+} TalkSummaryC;
+
+/**
+ * C 数组 wrapper
+ */
+typedef struct TalkSummaryArray {
+    struct TalkSummaryC *data;
+    uintptr_t len;
+} TalkSummaryArray;
+
+/**
+ * TurnSummary C 结构体
+ */
+typedef struct TurnSummaryC {
+    int64_t turn_index;
+    char *user_preview;
+    char *assistant_preview;
+    int64_t tool_count;
+    int64_t message_count;
+    int64_t start_timestamp;
+    int64_t end_timestamp;
+    int64_t pending_approval_count;
+} TurnSummaryC;
+
+/**
+ * C 数组 wrapper
+ */
+typedef struct TurnSummaryArray {
+    struct TurnSummaryC *data;
+    uintptr_t len;
+} TurnSummaryArray;
+
+/**
+ * CollectionIgnore C 结构体
+ */
+typedef struct CollectionIgnoreC {
+    int64_t id;
+    char *pattern;
+    enum IgnoreKindC kind;
+    int64_t created_at;
+} CollectionIgnoreC;
+
+/**
+ * CollectionIgnore 数组
+ */
+typedef struct CollectionIgnoreArray {
+    struct CollectionIgnoreC *data;
+    uintptr_t len;
+} CollectionIgnoreArray;
+
 /**
  * 连接数据库
  *
@@ -329,6 +464,102 @@ enum FfiError session_db_get_stats(const struct SessionDbHandle *handle,
                                    int64_t *out_sessions,
                                    int64_t *out_messages);
 
+/**
+ * 获取历史数据概览（JSON，结构同 `HistoryOverview`，字段为 camelCase）
+ *
+ * # Safety
+ * `handle`, `out_json` 必须有效
+ * 返回的字符串需要调用 `session_db_free_string` 释放
+ */
+enum FfiError session_db_history_overview(const struct SessionDbHandle *handle, char **out_json);
+
+/**
+ * 导入 claude.ai 数据导出（ZIP 或 `conversations.json`），结果为 JSON
+ * （结构同 `ImportReport`，字段为 camelCase）
+ *
+ * - `match_projects`: 所属 claude.ai 项目与已有项目同名的对话归入已有项目
+ *
+ * # Safety
+ * `handle`, `archive_path`, `out_json` 必须有效
+ * 返回的字符串需要调用 `session_db_free_string` 释放
+ */
+enum FfiError session_db_import_claude_export(const struct SessionDbHandle *handle,
+                                              const char *archive_path,
+                                              bool match_projects,
+                                              char **out_json);
+
+/**
+ * 创建会话分享包并写入 `out_path`（JSON，已存在时覆盖）
+ *
+ * - `range_start` / `range_end`: sequence 范围（含两端），`range_start < 0` 表示整个会话
+ * - `redact`: 按默认的 `RedactionPolicy` 脱敏（密钥 token、用户主目录）
+ * - `full_project_path`: 包含项目完整路径（否则只含项目名称）
+ *
+ * 会话不存在时返回 `NotFound`。
+ *
+ * # Safety
+ * `handle`, `session_id`, `out_path` 必须有效
+ */
+enum FfiError session_db_create_share_bundle(const struct SessionDbHandle *handle,
+                                             const char *session_id,
+                                             int64_t range_start,
+                                             int64_t range_end,
+                                             enum ToolMessageModeC tool_messages,
+                                             bool redact,
+                                             bool full_project_path,
+                                             const char *out_path);
+
+/**
+ * 把分享包文件渲染为 Markdown
+ *
+ * 文件不是有效的分享包时返回 `DatabaseError`（更新版本的分享包同样无法渲染）。
+ *
+ * # Safety
+ * `bundle_path`, `out_markdown` 必须有效
+ * 返回的字符串需要调用 `session_db_free_string` 释放
+ */
+enum FfiError session_db_render_share_bundle(const char *bundle_path, char **out_markdown);
+
+/**
+ * 获取库版本号（`VERSION_FULL`，格式 `{version}-{build_timestamp}`）
+ *
+ * 返回的字符串需要调用 `session_db_free_string` 释放
+ */
+char *session_db_version(void);
+
+/**
+ * 获取库编译时间戳（Unix 秒）
+ */
+uint64_t session_db_build_timestamp(void);
+
+/**
+ * 获取本库支持的 schema 版本
+ *
+ * 数据库的 schema 版本大于此值时说明由更新版本的库写入，应提示升级。
+ */
+int64_t session_db_supported_schema_version(void);
+
+/**
+ * 获取数据库文件的 schema 版本
+ *
+ * # Safety
+ * `handle`, `out_version` 必须有效
+ */
+enum FfiError session_db_schema_version(const struct SessionDbHandle *handle,
+                                        int64_t *out_version);
+
+/**
+ * 获取全局变更计数
+ *
+ * 每个提交了数据变更的写事务递增一次。与上次的值相同说明没有新数据，
+ * 可据此跳过重新查询。
+ *
+ * # Safety
+ * `handle`, `out_counter` 必须有效
+ */
+enum FfiError session_db_change_counter(const struct SessionDbHandle *handle,
+                                        uint64_t *out_counter);
+
 /**
  * 获取或创建 Project
  *
@@ -419,7 +650,50 @@ enum FfiError session_db_insert_messages(struct SessionDbHandle *handle,
                                          uintptr_t *out_inserted);
 
 /**
- * 列出 Session 的 Messages
+ * 开始分块写入
+ *
+ * # Safety
+ * `handle`, `session_id`, `out_writer` 必须是有效指针；
+ * `handle` 在写入器释放（`session_db_writer_free`）之前必须保持有效
+ */
+enum FfiError session_db_writer_begin(const struct SessionDbHandle *handle,
+                                      const char *session_id,
+                                      struct SessionDbWriter **out_writer);
+
+/**
+ * 写入一块消息（单个事务，已存在的 uuid 跳过）
+ *
+ * 可多次调用；finish 之后调用返回 `WriterFinished`。
+ *
+ * # Safety
+ * `writer` 必须是 `session_db_writer_begin` 返回的有效写入器，
+ * `messages` 必须指向 `count` 个有效的 MessageInputC
+ */
+enum FfiError session_db_writer_push(struct SessionDbWriter *writer,
+                                     const struct MessageInputC *messages,
+                                     uintptr_t count);
+
+/**
+ * 结束分块写入：更新会话计数，输出总插入数
+ *
+ * 写入器结束后仍需 `session_db_writer_free` 释放；重复调用返回 `WriterFinished`。
+ *
+ * # Safety
+ * `writer` 必须是 `session_db_writer_begin` 返回的有效写入器
+ */
+enum FfiError session_db_writer_finish(struct SessionDbWriter *writer,
+                                       uintptr_t *out_total_inserted);
+
+/**
+ * 释放分块写入器（未 finish 时已写入的块保留，会话计数不更新）
+ *
+ * # Safety
+ * `writer` 必须是 `session_db_writer_begin` 返回的写入器，且只能释放一次
+ */
+void session_db_writer_free(struct SessionDbWriter *writer);
+
+/**
+ * 列出 Session 的 Messages（包含 raw）
  *
  * # Safety
  * `handle`, `session_id` 必须是有效指针，返回的数组需要调用 `session_db_free_messages` 释放
@@ -430,11 +704,74 @@ enum FfiError session_db_list_messages(const struct SessionDbHandle *handle,
                                        uintptr_t offset,
                                        struct MessageArray **out_array);
 
+/**
+ * 列出 Session 的 Messages（可选排序方向、是否加载 raw）
+ *
+ * - `order`: 排序方向，`offset` 从该方向的一端计数（Desc 时 0 表示最新一条）
+ * - `with_raw`: false 时不读取 raw 列，返回的 `raw` 为 null，需要时用 `session_db_get_message_raw` 单独获取
+ *
+ * # Safety
+ * `handle`, `session_id` 必须是有效指针，返回的数组需要调用 `session_db_free_messages` 释放
+ */
+enum FfiError session_db_list_messages_with_options(const struct SessionDbHandle *handle,
+                                                    const char *session_id,
+                                                    uintptr_t limit,
+                                                    uintptr_t offset,
+                                                    enum OrderC order,
+                                                    bool with_raw,
+                                                    struct MessageArray **out_array);
+
+/**
+ * 分页列出 Session 的 Messages（按 sequence 升序）
+ *
+ * - `page_token`: 上一页输出的 `out_next_token`，null 表示第一页
+ * - `out_next_token`: 下一页 token，已是最后一页时为 null，需要调用 `session_db_free_string` 释放
+ *
+ * token 无效时返回 `InvalidPageToken`。
+ *
+ * # Safety
+ * `handle`, `session_id` 必须是有效指针，`page_token` 可以为 null，
+ * 返回的数组需要调用 `session_db_free_messages` 释放
+ */
+enum FfiError session_db_list_messages_page(const struct SessionDbHandle *handle,
+                                            const char *session_id,
+                                            uintptr_t limit,
+                                            bool with_raw,
+                                            const char *page_token,
+                                            struct MessageArray **out_array,
+                                            char **out_next_token);
+
+/**
+ * 按 uuid 获取消息的 raw（原始 JSONL）
+ *
+ * # Safety
+ * `handle`, `uuid` 必须有效，`out_raw` 为 null 表示消息不存在或没有 raw
+ * 返回的字符串需要调用 `session_db_free_string` 释放
+ */
+enum FfiError session_db_get_message_raw(const struct SessionDbHandle *handle,
+                                         const char *uuid,
+                                         char **out_raw);
+
+/**
+ * 按 ID 列表获取消息（包含 raw，按 ID 升序，不存在的 ID 被跳过）
+ *
+ * 用于取不含正文的搜索命中（`include_content = false`）的正文。
+ *
+ * # Safety
+ * `handle` 必须是有效指针，`ids` 指向 `ids_len` 个 i64（`ids_len` 为 0 时可以为 null），
+ * 返回的数组需要调用 `session_db_free_messages` 释放
+ */
+enum FfiError session_db_get_messages_by_ids(const struct SessionDbHandle *handle,
+                                             const int64_t *ids,
+                                             uintptr_t ids_len,
+                                             struct MessageArray **out_array);
+
 /**
  * 释放 Messages 数组
  *
  * # Safety
- * `array` 必须是 `session_db_list_messages` 返回的有效指针
+ * `array` 必须是 `session_db_list_messages` / `session_db_list_messages_with_options` /
+ * `session_db_get_turn_messages` / `session_db_get_messages_by_ids` 返回的有效指针
  */
 void session_db_free_messages(struct MessageArray *array);
 
@@ -480,6 +817,8 @@ void session_db_free_search_results(struct SearchResultArray *array);
  * - `order_by`: 排序方式（0=Score, 1=TimeDesc, 2=TimeAsc）
  * - `start_timestamp`: 开始时间戳（毫秒，-1 表示不过滤）
  * - `end_timestamp`: 结束时间戳（毫秒，-1 表示不过滤）
+ * - `max_per_project`: 每个项目的配额（0 表示不限制），配额内的命中优先，limit 未满时再补充
+ * - `include_content`: false 时 `content` 为空字符串，正文用 `session_db_get_messages_by_ids` 按需获取
  * - `out_array`: 输出搜索结果数组
  *
  * # Safety
@@ -492,8 +831,60 @@ enum FfiError session_db_search_fts_full(const struct SessionDbHandle *handle,
                                          enum SearchOrderByC order_by,
                                          int64_t start_timestamp,
                                          int64_t end_timestamp,
+                                         uintptr_t max_per_project,
+                                         bool include_content,
                                          struct SearchResultArray **out_array);
 
+/**
+ * FTS 全文搜索，日期范围按本地日历计算（其余参数同 `session_db_search_fts_full`）
+ *
+ * - `date_range`: `today`、`last{N}days`（如 `last7days`）、`thisWeek`、`thisMonth` 或 `YYYY-MM`
+ * - `tz_offset_minutes`: 相对 UTC 的分钟偏移（如 `TimeZone.current.secondsFromGMT() / 60`）
+ * - `week_starts_monday`: `thisWeek` 是否从周一开始（false 为周日）
+ *
+ * `date_range` 无法解析时返回 `InvalidArgument`。
+ *
+ * # Safety
+ * `handle`, `query`, `date_range` 必须是有效指针，返回的数组需要调用
+ * `session_db_free_search_results` 释放
+ */
+enum FfiError session_db_search_fts_in_range(const struct SessionDbHandle *handle,
+                                             const char *query,
+                                             uintptr_t limit,
+                                             int64_t project_id,
+                                             enum SearchOrderByC order_by,
+                                             const char *date_range,
+                                             int32_t tz_offset_minutes,
+                                             bool week_starts_monday,
+                                             uintptr_t max_per_project,
+                                             bool include_content,
+                                             struct SearchResultArray **out_array);
+
+/**
+ * FTS 全文搜索的一页（参数同 `session_db_search_fts_full`）
+ *
+ * - `page_token`: 上一页输出的 `out_next_token`，null 表示第一页
+ * - `out_next_token`: 下一页 token，已是最后一页时为 null，需要调用 `session_db_free_string` 释放
+ *
+ * token 无效（或换了搜索条件）时返回 `InvalidPageToken`。
+ *
+ * # Safety
+ * `handle`, `query` 必须是有效指针，`page_token` 可以为 null，
+ * 返回的数组需要调用 `session_db_free_search_results` 释放
+ */
+enum FfiError session_db_search_fts_page(const struct SessionDbHandle *handle,
+                                         const char *query,
+                                         uintptr_t limit,
+                                         int64_t project_id,
+                                         enum SearchOrderByC order_by,
+                                         int64_t start_timestamp,
+                                         int64_t end_timestamp,
+                                         uintptr_t max_per_project,
+                                         bool include_content,
+                                         const char *page_token,
+                                         struct SearchResultArray **out_array,
+                                         char **out_next_token);
+
 /**
  * FTS 全文搜索（完整参数版本，支持项目过滤和排序）
  *
@@ -515,6 +906,69 @@ enum FfiError session_db_search_fts_with_options(const struct SessionDbHandle *h
                                                  enum SearchOrderByC order_by,
                                                  struct SearchResultArray **out_array);
 
+/**
+ * FTS 全文搜索，按会话分组
+ *
+ * # 参数
+ * - `handle`: 数据库句柄
+ * - `query`: 搜索关键词
+ * - `session_limit`: 返回的会话数
+ * - `per_session_limit`: 每个会话返回的命中数
+ * - `project_id`: 项目 ID（-1 表示不过滤）
+ * - `order_by`: 分组排序方式（0=最佳分数, 1=最新命中, 2=最早命中）
+ * - `include_content`: false 时命中的 `content` 为空字符串（同 `session_db_search_fts_full`）
+ * - `out_array`: 输出分组数组
+ *
+ * # Safety
+ * `handle`, `query` 必须是有效指针，返回的数组需要调用 `session_db_free_search_groups` 释放
+ */
+enum FfiError session_db_search_fts_grouped(const struct SessionDbHandle *handle,
+                                            const char *query,
+                                            uintptr_t session_limit,
+                                            uintptr_t per_session_limit,
+                                            int64_t project_id,
+                                            enum SearchOrderByC order_by,
+                                            bool include_content,
+                                            struct SessionSearchGroupArray **out_array);
+
+/**
+ * 释放分组搜索结果
+ *
+ * # Safety
+ * `array` 必须是 `session_db_search_fts_grouped` 返回的有效指针
+ */
+void session_db_free_search_groups(struct SessionSearchGroupArray *array);
+
+/**
+ * 最近的搜索（JSON 数组，结构同 `SearchLogEntry`，字段为 camelCase，按时间倒序）
+ *
+ * - `limit`: 0 表示不限制（最多 `MAX_QUERY_LIMIT` 条）
+ * - `zero_results`: 为 true 时只返回没有结果的搜索
+ *
+ * 只有启用了 `DbConfig::log_searches` 的写入方（如 Agent）才会产生记录。
+ *
+ * # Safety
+ * `handle`, `out_json` 必须有效
+ * 返回的字符串需要调用 `session_db_free_string` 释放
+ */
+enum FfiError session_db_recent_searches(const struct SessionDbHandle *handle,
+                                         uintptr_t limit,
+                                         bool zero_results,
+                                         char **out_json);
+
+/**
+ * 按项目汇总待审批的消息（JSON 数组，结构同 `ApprovalDigestGroup`，字段为 camelCase）
+ *
+ * - `older_than_ms`: 只统计已等待超过该时长（毫秒）的消息，<= 0 表示不过滤
+ *
+ * # Safety
+ * `handle`, `out_json` 必须有效
+ * 返回的字符串需要调用 `session_db_free_string` 释放
+ */
+enum FfiError session_db_pending_approval_digest(const struct SessionDbHandle *handle,
+                                                 int64_t older_than_ms,
+                                                 char **out_json);
+
 /**
  * 释放 C 字符串
  *
@@ -611,10 +1065,11 @@ char *session_db_compute_session_path(const char *projects_path,
  * 列出所有项目（从文件系统）
  *
  * 会话数量不包含 agent session。
+ * 无权访问 projects 目录时返回 `PermissionDenied`（macOS 需要授予完全磁盘访问权限）。
  *
  * # 参数
  * - `projects_path`: Claude projects 目录路径，null 使用默认路径 (~/.claude/projects)
- * - `limit`: 最大返回数量，0 表示不限制
+ * - `limit`: 最大返回数量，0 表示不限制（最多 `MAX_QUERY_LIMIT` 个）
  *
  * # Safety
  * - 返回的数组需要调用 `session_db_free_project_list` 释放
@@ -632,10 +1087,32 @@ enum FfiError session_db_list_file_projects(const char *projects_path,
  */
 void session_db_free_project_list(struct ProjectInfoArray *array);
 
+/**
+ * 数据库与 projects 目录对账后的项目列表（JSON 数组，结构同 `ReconciledProject`，
+ * 字段为 camelCase，按最后活跃时间排序）
+ *
+ * `inDb` / `onDisk` 区分已归档（JSONL 已删除）和尚未采集的项目。
+ * 无权访问 projects 目录时返回 `PermissionDenied`。
+ *
+ * # 参数
+ * - `projects_path`: Claude projects 目录路径，null 使用默认路径 (~/.claude/projects)
+ * - `limit`: 最大返回数量，0 表示不限制（最多 `MAX_QUERY_LIMIT` 个）
+ *
+ * # Safety
+ * `handle`, `out_json` 必须有效
+ * 返回的字符串需要调用 `session_db_free_string` 释放
+ */
+enum FfiError session_db_list_projects_reconciled(const struct SessionDbHandle *handle,
+                                                  const char *projects_path,
+                                                  uintptr_t limit,
+                                                  uintptr_t offset,
+                                                  char **out_json);
+
 /**
  * 列出会话
  *
  * 默认过滤 agent session (agent-xxx)。
+ * 无权访问 projects 目录时返回 `PermissionDenied`（macOS 需要授予完全磁盘访问权限）。
  *
  * # 参数
  * - `projects_path`: Claude projects 目录路径，null 使用默认路径
@@ -691,9 +1168,12 @@ void session_db_free_session_meta(struct SessionMetaC *session);
  *
  * # 参数
  * - `session_path`: 会话文件完整路径
- * - `limit`: 每页消息数
- * - `offset`: 偏移量
- * - `order_asc`: true 升序，false 降序
+ * - `limit`: 每页消息数，0 表示不限制（最多 `MAX_QUERY_LIMIT` 条）
+ * - `offset`: 偏移量，从 `order` 所选的一端计数（降序时 0 表示最新一条）
+ * - `order`: 排序方向（页内顺序与之一致）
+ *
+ * 按相同方向递增 `offset` 翻页，每条消息恰好出现一次；
+ * `has_more` 为 false 表示该方向上已是最后一页，`total` 为会话消息总数。
  *
  * # Safety
  * - 返回的结果需要调用 `session_db_free_messages_result` 释放
@@ -701,7 +1181,7 @@ void session_db_free_session_meta(struct SessionMetaC *session);
 enum FfiError session_db_read_session_messages(const char *session_path,
                                                uintptr_t limit,
                                                uintptr_t offset,
-                                               bool order_asc,
+                                               enum OrderC order,
                                                struct MessagesResultC **out_result);
 
 /**
@@ -717,6 +1197,8 @@ void session_db_free_messages_result(struct MessagesResultC *result);
  * 执行全量采集
  *
  * 扫描所有 CLI 会话文件（Claude、OpenCode、Codex 等），增量写入数据库。
+ * 有数据目录无权访问时返回 `PermissionDenied`，不输出结果（可访问的目录仍会正常采集）。
+ * 其他进程正在采集（持有采集锁）时返回 `CoordinationError`，稍后重试。
  *
  * # Safety
  * `handle` 必须是有效句柄，`out_result` 必须是有效指针
@@ -734,6 +1216,17 @@ enum FfiError session_db_collect_by_path(struct SessionDbHandle *handle,
                                          const char *path,
                                          struct CollectResultC **out_result);
 
+/**
+ * 检测处理该路径的适配器（采集前判断文件是否为可识别的会话格式）
+ *
+ * 成功时 `out_source` 为适配器的来源名（如 "claude"），需要调用 `session_db_free_string` 释放；
+ * 没有适配器能处理该路径时返回 `NotFound`。
+ *
+ * # Safety
+ * `path` 必须是有效 C 字符串，`out_source` 必须有效
+ */
+enum FfiError session_db_detect_adapter(const char *path, char **out_source);
+
 /**
  * 释放采集结果
  *
@@ -780,6 +1273,120 @@ void session_db_free_session_relations(struct SessionRelationArray *array);
  */
 void session_db_free_session_relation(struct SessionRelationC *relation);
 
+/**
+ * 获取会话的 Talk 摘要（按 position 排序，分页）
+ *
+ * - `limit`: 单页条数（0 表示取最大单页条数，超过上限时截断）
+ * - `offset`: 偏移
+ *
+ * # Safety
+ * `handle`, `session_id` 必须有效，返回数组需要用 `session_db_free_talk_summaries` 释放
+ */
+enum FfiError session_db_get_talk_summaries(const struct SessionDbHandle *handle,
+                                            const char *session_id,
+                                            uintptr_t limit,
+                                            uintptr_t offset,
+                                            struct TalkSummaryArray **out_array);
+
+/**
+ * 获取单个 Talk 摘要
+ *
+ * # Safety
+ * `handle`, `session_id`, `talk_id` 必须有效，不存在时 `out_talk` 置为 null
+ * 返回的 talk 需要用 `session_db_free_talk_summary` 释放
+ */
+enum FfiError session_db_get_talk_summary(const struct SessionDbHandle *handle,
+                                          const char *session_id,
+                                          const char *talk_id,
+                                          struct TalkSummaryC **out_talk);
+
+/**
+ * 释放 TalkSummary 数组
+ *
+ * # Safety
+ * `array` 必须是 `session_db_get_talk_summaries` 返回的有效指针
+ */
+void session_db_free_talk_summaries(struct TalkSummaryArray *array);
+
+/**
+ * 释放单个 TalkSummary
+ *
+ * # Safety
+ * `talk` 必须是 `session_db_get_talk_summary` 返回的有效指针
+ */
+void session_db_free_talk_summary(struct TalkSummaryC *talk);
+
+/**
+ * 获取会话的对话轮次摘要（按 turn_index 排序，分页）
+ *
+ * # Safety
+ * `handle`, `session_id` 必须有效，返回数组需要用 `session_db_free_turns` 释放
+ */
+enum FfiError session_db_list_turns(const struct SessionDbHandle *handle,
+                                    const char *session_id,
+                                    uintptr_t limit,
+                                    uintptr_t offset,
+                                    struct TurnSummaryArray **out_array);
+
+/**
+ * 获取单个对话轮次的消息（按 sequence 排序，不含 raw）
+ *
+ * # Safety
+ * `handle`, `session_id` 必须有效，返回数组需要用 `session_db_free_messages` 释放
+ */
+enum FfiError session_db_get_turn_messages(const struct SessionDbHandle *handle,
+                                           const char *session_id,
+                                           int64_t turn_index,
+                                           struct MessageArray **out_array);
+
+/**
+ * 释放 TurnSummary 数组
+ *
+ * # Safety
+ * `array` 必须是 `session_db_list_turns` 返回的有效指针
+ */
+void session_db_free_turns(struct TurnSummaryArray *array);
+
+/**
+ * 添加采集忽略规则（幂等）
+ *
+ * 添加后刷新项目的 ignored 标记，被排除的项目不再出现在默认列表中。
+ *
+ * # Safety
+ * `handle`, `pattern` 必须有效，`out_id` 可以为 null
+ */
+enum FfiError session_db_add_ignore(const struct SessionDbHandle *handle,
+                                    const char *pattern,
+                                    enum IgnoreKindC kind,
+                                    int64_t *out_id);
+
+/**
+ * 删除采集忽略规则
+ *
+ * # Safety
+ * `handle` 必须有效，`out_removed` 可以为 null
+ */
+enum FfiError session_db_remove_ignore(const struct SessionDbHandle *handle,
+                                       int64_t id,
+                                       bool *out_removed);
+
+/**
+ * 列出采集忽略规则（不含环境变量规则）
+ *
+ * # Safety
+ * `handle`, `out_array` 必须有效，返回的数组需要调用 `session_db_free_ignores` 释放
+ */
+enum FfiError session_db_list_ignores(const struct SessionDbHandle *handle,
+                                      struct CollectionIgnoreArray **out_array);
+
+/**
+ * 释放 CollectionIgnore 数组
+ *
+ * # Safety
+ * `array` 必须是 `session_db_list_ignores` 返回的有效指针
+ */
+void session_db_free_ignores(struct CollectionIgnoreArray *array);
+
 /**
  * 创建 AgentClient 句柄
  *
@@ -0,0 +1,186 @@
+//! Socket activation 与就绪通知（Unix）
+//!
+//! 由 launchd / systemd 按需启动 Agent：服务管理器预先绑定 `~/.vimo/agent.sock`，
+//! 首个客户端连接时拉起 Agent 并移交监听 fd，Agent 不再自己 bind，退出时也不删除 socket 文件。
+//! 客户端设置 `ClientConfig::assume_socket_activated` 后不再自行启动 Agent，只重试连接。
+//!
+//! # systemd（用户级）
+//!
+//! `~/.config/systemd/user/vimo-agent.socket`:
+//!
+//! ```ini
+//! [Socket]
+//! ListenStream=%h/.vimo/agent.sock
+//! SocketMode=0600
+//!
+//! [Install]
+//! WantedBy=sockets.target
+//! ```
+//!
+//! `~/.config/systemd/user/vimo-agent.service`:
+//!
+//! ```ini
+//! [Service]
+//! Type=notify
+//! ExecStart=%h/.vimo/bin/vimo-agent
+//! ```
+//!
+//! systemd 通过 `LISTEN_PID` / `LISTEN_FDS` 传入 fd 3；`Type=notify` 下 Agent 初始化完成后
+//! 向 `NOTIFY_SOCKET` 发送 `READY=1`。
+//!
+//! # launchd
+//!
+//! `~/Library/LaunchAgents/ai.vimo.agent.plist`:
+//!
+//! ```xml
+//! <key>Label</key>
+//! <string>ai.vimo.agent</string>
+//! <key>ProgramArguments</key>
+//! <array><string>/Users/me/.vimo/bin/vimo-agent</string></array>
+//! <key>Sockets</key>
+//! <dict>
+//!     <key>AgentSocket</key>
+//!     <dict>
+//!         <key>SockPathName</key>
+//!         <string>/Users/me/.vimo/agent.sock</string>
+//!         <key>SockPathMode</key>
+//!         <integer>384</integer>
+//!     </dict>
+//! </dict>
+//! ```
+//!
+//! Agent 通过 `launch_activate_socket("AgentSocket")` 取回 fd（`SockPathMode` 384 即 0600）。
+//! launchd 没有就绪通知，以 PID 文件为准。
+
+use std::io;
+use std::os::unix::io::RawFd;
+use std::os::unix::net::UnixDatagram;
+
+/// launchd plist 中 `Sockets` 的键名
+pub const LAUNCHD_SOCKET_NAME: &str = "AgentSocket";
+
+/// systemd 移交的第一个 fd（SD_LISTEN_FDS_START）
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// 获取服务管理器预绑定的监听 fd（systemd `LISTEN_FDS` 或 launchd checkin）
+///
+/// 不是由 socket activation 启动时返回 None。
+pub fn inherited_listener_fd() -> Option<RawFd> {
+    systemd_listener_fd().or_else(launchd_listener_fd)
+}
+
+/// systemd：`LISTEN_PID` 等于当前进程时取 fd 3，并清除环境变量避免子进程误用
+fn systemd_listener_fd() -> Option<RawFd> {
+    let pid: u32 = std::env::var("LISTEN_PID").ok()?.parse().ok()?;
+    if pid != std::process::id() {
+        return None;
+    }
+    let count: i32 = std::env::var("LISTEN_FDS").ok()?.parse().ok()?;
+    for key in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        std::env::remove_var(key);
+    }
+
+    if count < 1 {
+        return None;
+    }
+    if count > 1 {
+        tracing::warn!("LISTEN_FDS={}, only the first socket is used", count);
+    }
+
+    // systemd 移交的 fd 没有 CLOEXEC
+    unsafe {
+        libc::fcntl(SD_LISTEN_FDS_START, libc::F_SETFD, libc::FD_CLOEXEC);
+    }
+    Some(SD_LISTEN_FDS_START)
+}
+
+/// launchd：按 plist 中的 socket 名 checkin
+#[cfg(target_os = "macos")]
+fn launchd_listener_fd() -> Option<RawFd> {
+    use std::ffi::CString;
+    use std::os::raw::{c_char, c_int};
+
+    extern "C" {
+        fn launch_activate_socket(
+            name: *const c_char,
+            fds: *mut *mut c_int,
+            cnt: *mut libc::size_t,
+        ) -> c_int;
+    }
+
+    let name = CString::new(LAUNCHD_SOCKET_NAME).ok()?;
+    let mut fds: *mut c_int = std::ptr::null_mut();
+    let mut cnt: libc::size_t = 0;
+    // 非 launchd 启动时返回 ESRCH，plist 中没有该 socket 时返回 ENOENT
+    let rc = unsafe { launch_activate_socket(name.as_ptr(), &mut fds, &mut cnt) };
+    if rc != 0 || fds.is_null() {
+        return None;
+    }
+
+    let all = unsafe { std::slice::from_raw_parts(fds, cnt) }.to_vec();
+    unsafe { libc::free(fds.cast()) };
+    for extra in all.iter().skip(1) {
+        unsafe { libc::close(*extra) };
+    }
+    all.first().copied()
+}
+
+#[cfg(not(target_os = "macos"))]
+fn launchd_listener_fd() -> Option<RawFd> {
+    None
+}
+
+/// 发送就绪通知（systemd `Type=notify`）
+///
+/// 未设置 `NOTIFY_SOCKET` 时不发送，返回 false。
+pub fn notify_ready() -> io::Result<bool> {
+    match std::env::var("NOTIFY_SOCKET") {
+        Ok(socket) => {
+            sd_notify(&socket, "READY=1")?;
+            Ok(true)
+        }
+        Err(_) => Ok(false),
+    }
+}
+
+/// 向 notify socket 发送状态（`@` 开头为 Linux 抽象命名空间）
+fn sd_notify(socket: &str, state: &str) -> io::Result<()> {
+    let datagram = UnixDatagram::unbound()?;
+    match socket.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            datagram.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "abstract NOTIFY_SOCKET is only supported on Linux",
+            ));
+        }
+        None => {
+            datagram.send_to(state.as_bytes(), socket)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sd_notify_writes_ready_to_socket() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("notify.sock");
+        let stub = UnixDatagram::bind(&path).unwrap();
+
+        sd_notify(path.to_str().unwrap(), "READY=1").unwrap();
+
+        let mut buf = [0u8; 64];
+        let n = stub.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+    }
+}
@@ -0,0 +1,217 @@
+//! 会话活跃状态
+//!
+//! 根据文件监听事件推导会话的实时活跃状态（Agent 内存状态，不落库）：
+//! - Streaming：最近 N 秒内有文件事件（Claude 正在生成）
+//! - RecentlyActive：静默期（默认 2 分钟）内有文件事件
+//! - Idle：其他
+//!
+//! 只在状态切换时产生通知，订阅方无需处理每次文件写入。
+//! 活跃过的会话进入 Idle 即视为回复完毕（`Push::SessionIdle`），每次活跃后只切换一次。
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::types::{SessionActivity, SessionActivityState};
+
+/// 默认静默期（超过后从 RecentlyActive 进入 Idle）
+pub const DEFAULT_IDLE_AFTER: Duration = Duration::from_secs(120);
+
+/// 当前时间（毫秒）
+pub(crate) fn now_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+/// 单个会话的活跃记录
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    /// 最近一次文件事件时间（毫秒）
+    last_event_ms: i64,
+    /// 最近一次通知的状态
+    state: SessionActivityState,
+}
+
+/// 会话活跃状态跟踪器
+pub struct ActivityTracker {
+    /// Streaming 窗口
+    streaming_window: Duration,
+    /// 静默期（RecentlyActive 窗口）
+    idle_after: Duration,
+    /// session_id → 活跃记录
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl ActivityTracker {
+    /// 创建跟踪器，`streaming_window` 为判定 Streaming 的时间窗口，
+    /// 超过 `idle_after` 没有文件事件的会话进入 Idle
+    pub fn new(streaming_window: Duration, idle_after: Duration) -> Self {
+        Self {
+            streaming_window,
+            idle_after,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 根据最近事件时间判定状态
+    fn classify(&self, last_event_ms: i64, now_ms: i64) -> SessionActivityState {
+        let elapsed = now_ms.saturating_sub(last_event_ms);
+        if elapsed < self.streaming_window.as_millis() as i64 {
+            SessionActivityState::Streaming
+        } else if elapsed < self.idle_after.as_millis() as i64 {
+            SessionActivityState::RecentlyActive
+        } else {
+            SessionActivityState::Idle
+        }
+    }
+
+    /// 记录文件事件，状态发生切换时返回新状态
+    pub fn record_event(&self, session_id: &str, now_ms: i64) -> Option<SessionActivity> {
+        let mut entries = self.entries.lock();
+        let state = self.classify(now_ms, now_ms);
+        let previous = entries.insert(
+            session_id.to_string(),
+            Entry {
+                last_event_ms: now_ms,
+                state,
+            },
+        );
+
+        match previous {
+            Some(entry) if entry.state == state => None,
+            _ => Some(SessionActivity {
+                session_id: session_id.to_string(),
+                state,
+                last_event_ms: Some(now_ms),
+            }),
+        }
+    }
+
+    /// 会话已确认结束：直接进入 Idle（之后的 tick 不再切换），状态发生切换时返回新状态
+    pub fn end_session(&self, session_id: &str) -> Option<SessionActivity> {
+        let mut entries = self.entries.lock();
+        let entry = entries.get_mut(session_id)?;
+        if entry.state == SessionActivityState::Idle {
+            return None;
+        }
+        entry.state = SessionActivityState::Idle;
+        Some(SessionActivity {
+            session_id: session_id.to_string(),
+            state: SessionActivityState::Idle,
+            last_event_ms: Some(entry.last_event_ms),
+        })
+    }
+
+    /// 按当前时间重新判定所有会话，返回状态发生切换的会话
+    pub fn tick(&self, now_ms: i64) -> Vec<SessionActivity> {
+        let mut entries = self.entries.lock();
+        let mut changed = Vec::new();
+
+        for (session_id, entry) in entries.iter_mut() {
+            let state = self.classify(entry.last_event_ms, now_ms);
+            if state != entry.state {
+                entry.state = state;
+                changed.push(SessionActivity {
+                    session_id: session_id.clone(),
+                    state,
+                    last_event_ms: Some(entry.last_event_ms),
+                });
+            }
+        }
+
+        changed
+    }
+
+    /// 查询会话活跃状态（Agent 启动后没有文件事件的会话为 Idle）
+    pub fn get(&self, session_ids: &[String], now_ms: i64) -> Vec<SessionActivity> {
+        let entries = self.entries.lock();
+        session_ids
+            .iter()
+            .map(|session_id| match entries.get(session_id) {
+                Some(entry) => SessionActivity {
+                    session_id: session_id.clone(),
+                    state: self.classify(entry.last_event_ms, now_ms),
+                    last_event_ms: Some(entry.last_event_ms),
+                },
+                None => SessionActivity {
+                    session_id: session_id.clone(),
+                    state: SessionActivityState::Idle,
+                    last_event_ms: None,
+                },
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> ActivityTracker {
+        ActivityTracker::new(Duration::from_secs(5), DEFAULT_IDLE_AFTER)
+    }
+
+    #[test]
+    fn test_transitions_only_on_state_change() {
+        let tracker = tracker();
+
+        // 第一次事件进入 Streaming，后续写入不重复通知
+        let first = tracker.record_event("s1", 1_000).unwrap();
+        assert_eq!(first.state, SessionActivityState::Streaming);
+        assert!(tracker.record_event("s1", 2_000).is_none());
+        assert!(tracker.record_event("s1", 3_000).is_none());
+        assert!(tracker.tick(4_000).is_empty());
+
+        // 超过 Streaming 窗口
+        let changed = tracker.tick(9_000);
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].state, SessionActivityState::RecentlyActive);
+        assert_eq!(changed[0].last_event_ms, Some(3_000));
+        assert!(tracker.tick(10_000).is_empty());
+
+        // 再次写入回到 Streaming
+        let again = tracker.record_event("s1", 11_000).unwrap();
+        assert_eq!(again.state, SessionActivityState::Streaming);
+
+        // 超过 2 分钟进入 Idle
+        let changed = tracker.tick(11_000 + 120_000);
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].state, SessionActivityState::Idle);
+        assert!(tracker.tick(11_000 + 200_000).is_empty());
+    }
+
+    #[test]
+    fn test_custom_idle_after() {
+        let tracker = ActivityTracker::new(Duration::from_secs(5), Duration::from_secs(30));
+        tracker.record_event("s1", 0);
+
+        assert_eq!(
+            tracker.tick(10_000)[0].state,
+            SessionActivityState::RecentlyActive
+        );
+        assert!(tracker.tick(29_000).is_empty());
+        assert_eq!(tracker.tick(30_000)[0].state, SessionActivityState::Idle);
+        assert!(tracker.tick(60_000).is_empty());
+    }
+
+    #[test]
+    fn test_get_activity() {
+        let tracker = tracker();
+        tracker.record_event("s1", 1_000);
+        tracker.record_event("s2", 1_000);
+        tracker.record_event("s2", 58_000);
+
+        let ids = vec!["s1".to_string(), "s2".to_string(), "unknown".to_string()];
+        let activity = tracker.get(&ids, 60_000);
+
+        assert_eq!(activity[0].state, SessionActivityState::RecentlyActive);
+        assert_eq!(activity[0].last_event_ms, Some(1_000));
+        assert_eq!(activity[1].state, SessionActivityState::Streaming);
+        assert_eq!(activity[1].last_event_ms, Some(58_000));
+        assert_eq!(activity[2].state, SessionActivityState::Idle);
+        assert_eq!(activity[2].last_event_ms, None);
+    }
+}
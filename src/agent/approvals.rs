@@ -0,0 +1,195 @@
+//! 待审批摘要推送
+//!
+//! 定期汇总各项目的待审批消息（`SessionDB::pending_approval_digest`），内容与上次推送
+//! 不同时向所有连接推送 `Push::ApprovalDigest`，客户端据此合并为一条系统通知，
+//! 而不是每个审批一条、每次检查一条。
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use super::broadcaster::ConnectionManager;
+use crate::db::content_hash;
+use crate::protocol::Push;
+use crate::{ApprovalDigestGroup, SessionDB};
+
+/// 待审批摘要推送
+pub struct ApprovalDigestMonitor {
+    /// 所有数据库（按来源拆分时各自汇总后合并，project_id 只在所属数据库内唯一）
+    dbs: Vec<Arc<SessionDB>>,
+    connections: Arc<ConnectionManager>,
+    /// 只统计已等待超过该时长（毫秒）的审批
+    older_than_ms: Option<i64>,
+    /// 上次推送的摘要哈希（初始为空摘要：没有待审批时不推送）
+    last_hash: Mutex<String>,
+}
+
+impl ApprovalDigestMonitor {
+    pub fn new(
+        dbs: Vec<Arc<SessionDB>>,
+        connections: Arc<ConnectionManager>,
+        older_than_ms: Option<i64>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            dbs,
+            connections,
+            older_than_ms,
+            last_hash: Mutex::new(digest_hash(&[])),
+        })
+    }
+
+    /// 按 `interval` 定期检查，直到任务被取消
+    pub async fn run(&self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            self.check().await;
+        }
+    }
+
+    /// 汇总一次待审批消息，变化时推送
+    async fn check(&self) {
+        let dbs = self.dbs.clone();
+        let older_than_ms = self.older_than_ms;
+        let result = tokio::task::spawn_blocking(move || collect_digest(&dbs, older_than_ms)).await;
+        match result {
+            Ok(Ok(groups)) => {
+                self.report(groups);
+            }
+            Ok(Err(e)) => tracing::warn!("Approval digest query failed: {}", e),
+            Err(e) => tracing::warn!("Approval digest task failed: {}", e),
+        }
+    }
+
+    /// 摘要与上次推送不同时广播 ApprovalDigest，返回是否已推送
+    fn report(&self, groups: Vec<ApprovalDigestGroup>) -> bool {
+        let hash = digest_hash(&groups);
+        {
+            let mut last_hash = self.last_hash.lock();
+            if *last_hash == hash {
+                return false;
+            }
+            *last_hash = hash;
+        }
+
+        let push = Push::ApprovalDigest {
+            groups,
+            change_counter: self
+                .dbs
+                .first()
+                .and_then(|db| db.change_counter().ok())
+                .unwrap_or_default(),
+        };
+        self.connections.broadcast_push(&push);
+        true
+    }
+}
+
+/// 汇总所有数据库的待审批消息（按最早的待审批时间升序）
+fn collect_digest(
+    dbs: &[Arc<SessionDB>],
+    older_than_ms: Option<i64>,
+) -> crate::Result<Vec<ApprovalDigestGroup>> {
+    let mut groups = Vec::new();
+    for db in dbs {
+        groups.extend(db.pending_approval_digest(older_than_ms)?);
+    }
+    groups.sort_by_key(|g| g.oldest_at);
+    Ok(groups)
+}
+
+fn digest_hash(groups: &[ApprovalDigestGroup]) -> String {
+    content_hash(&serde_json::to_string(groups).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DbConfig, MessageInput, MessageType};
+    use tokio::sync::mpsc;
+
+    fn setup() -> (
+        Arc<ApprovalDigestMonitor>,
+        Arc<SessionDB>,
+        mpsc::Receiver<String>,
+        tempfile::TempDir,
+    ) {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let db = Arc::new(SessionDB::connect(DbConfig::local(tmp.path().join("test.db"))).unwrap());
+        let connections = ConnectionManager::new();
+        let (tx, rx) = mpsc::channel(10);
+        connections.register(tx);
+        let monitor = ApprovalDigestMonitor::new(vec![db.clone()], connections, None);
+        (monitor, db, rx, tmp)
+    }
+
+    fn insert_pending(db: &SessionDB, tool_call_id: &str) {
+        let project_id = db
+            .get_or_create_project("project-x", "/path/project-x", "claude")
+            .unwrap();
+        db.upsert_session("session-1", project_id).unwrap();
+        db.insert_messages(
+            "session-1",
+            &[MessageInput {
+                uuid: format!("uuid-{}", tool_call_id),
+                r#type: MessageType::Assistant,
+                content_text: "Run command".to_string(),
+                content_full: "Run command".to_string(),
+                timestamp: 1000,
+                sequence: 0,
+                source: None,
+                channel: None,
+                model: None,
+                tool_call_id: Some(tool_call_id.to_string()),
+                tool_name: Some("Bash".to_string()),
+                tool_args: None,
+                raw: None,
+                approval_status: Some(crate::ApprovalStatus::Pending),
+                approval_resolved_at: None,
+            }],
+        )
+        .unwrap();
+    }
+
+    fn digest(db: &Arc<SessionDB>) -> Vec<ApprovalDigestGroup> {
+        collect_digest(&[db.clone()], None).unwrap()
+    }
+
+    #[test]
+    fn test_unchanged_digest_is_not_pushed_again() {
+        let (monitor, db, mut rx, _tmp) = setup();
+
+        // 没有待审批时不推送
+        assert!(!monitor.report(digest(&db)));
+
+        insert_pending(&db, "call-1");
+        assert!(monitor.report(digest(&db)));
+        let line = rx.try_recv().unwrap();
+        match serde_json::from_str::<Push>(line.trim_end()).unwrap() {
+            Push::ApprovalDigest { groups, .. } => {
+                assert_eq!(groups.len(), 1);
+                assert_eq!(groups[0].count, 1);
+            }
+            other => panic!("Expected ApprovalDigest, got {:?}", other),
+        }
+
+        // 内容不变：不重复推送
+        assert!(!monitor.report(digest(&db)));
+        assert!(rx.try_recv().is_err());
+
+        // 审批完成：推送一次空摘要
+        db.update_approval_status_by_tool_call_id("call-1", crate::ApprovalStatus::Approved, 2000)
+            .unwrap();
+        assert!(monitor.report(digest(&db)));
+        assert!(line_is_empty_digest(&rx.try_recv().unwrap()));
+        assert!(!monitor.report(digest(&db)));
+    }
+
+    fn line_is_empty_digest(line: &str) -> bool {
+        matches!(
+            serde_json::from_str::<Push>(line.trim_end()).unwrap(),
+            Push::ApprovalDigest { groups, .. } if groups.is_empty()
+        )
+    }
+}
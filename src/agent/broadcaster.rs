@@ -1,12 +1,21 @@
 //! 连接管理器
 //!
-//! 维护活跃连接的通道，用于发送响应消息
+//! 维护活跃连接的通道，用于发送响应消息；
+//! 按连接的订阅集合广播推送；
+//! 同时分发数据变更通知（供 WaitForChange 等待者使用）
+//!
+//! 数据变更来自数据库的进程内变更通知（`ConnectionManager::observe`），
+//! 与嵌入写入端的宿主应用使用同一条事件路径。
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use parking_lot::RwLock;
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc};
+
+use crate::db::SessionDB;
+use crate::observer::{self, SubscriptionId};
+use crate::protocol::Push;
 
 /// 连接 ID
 pub type ConnId = u64;
@@ -14,38 +23,69 @@ pub type ConnId = u64;
 /// 消息发送通道
 pub type MessageSender = mpsc::Sender<String>;
 
+/// 变更通知通道容量（落后的接收端会收到 Lagged，按有变更处理）
+const CHANGE_CHANNEL_CAPACITY: usize = 256;
+
+/// 数据变更通知
+#[derive(Debug, Clone)]
+pub struct ChangeEvent {
+    /// 发生变更的会话 ID
+    pub session_id: String,
+}
+
 /// 连接管理器
 pub struct ConnectionManager {
     /// 连接通道：ConnId → 发送通道
     senders: RwLock<HashMap<ConnId, MessageSender>>,
+    /// 推送订阅：ConnId → 订阅的推送类型（None 接收全部推送，空集合不接收推送）
+    subscriptions: RwLock<HashMap<ConnId, Option<HashSet<String>>>>,
     /// 下一个连接 ID
     next_conn_id: RwLock<ConnId>,
+    /// 变更通知
+    changes: broadcast::Sender<ChangeEvent>,
 }
 
 impl ConnectionManager {
     /// 创建新的连接管理器
     pub fn new() -> Arc<Self> {
+        let (changes, _) = broadcast::channel(CHANGE_CHANNEL_CAPACITY);
         Arc::new(Self {
             senders: RwLock::new(HashMap::new()),
+            subscriptions: RwLock::new(HashMap::new()),
             next_conn_id: RwLock::new(1),
+            changes,
         })
     }
 
-    /// 注册新连接，返回连接 ID
+    /// 注册新连接（不限连接数），返回连接 ID
+    #[cfg(test)]
     pub fn register(&self, sender: MessageSender) -> ConnId {
+        self.try_register(sender, usize::MAX)
+            .expect("unbounded registration never fails")
+    }
+
+    /// 连接数未达上限时注册新连接并返回连接 ID，否则返回 None
+    pub fn try_register(&self, sender: MessageSender, max_connections: usize) -> Option<ConnId> {
+        let mut senders = self.senders.write();
+        if senders.len() >= max_connections {
+            return None;
+        }
+
         let mut next_id = self.next_conn_id.write();
         let conn_id = *next_id;
         *next_id += 1;
-
-        self.senders.write().insert(conn_id, sender);
+        senders.insert(conn_id, sender);
+        // 加锁顺序与 cleanup_closed 一致：先 senders 后 subscriptions
+        self.subscriptions.write().insert(conn_id, None);
 
         tracing::debug!("📡 Connection registered: conn_id={}", conn_id);
-        conn_id
+        Some(conn_id)
     }
 
     /// 注销连接
     pub fn unregister(&self, conn_id: ConnId) {
         self.senders.write().remove(&conn_id);
+        self.subscriptions.write().remove(&conn_id);
         tracing::debug!("📡 Connection unregistered: conn_id={}", conn_id);
     }
 
@@ -66,6 +106,9 @@ impl ConnectionManager {
             alive
         });
         let after = senders.len();
+        self.subscriptions
+            .write()
+            .retain(|id, _| senders.contains_key(id));
         if before != after {
             tracing::info!("📡 Cleaned {} dead connections, {} remaining", before - after, after);
         }
@@ -105,13 +148,141 @@ impl ConnectionManager {
             false
         }
     }
+
+    /// 订阅数据变更通知
+    ///
+    /// 订阅之后发生的变更都会收到；先订阅再检查状态可避免遗漏。
+    pub fn subscribe_changes(&self) -> broadcast::Receiver<ChangeEvent> {
+        self.changes.subscribe()
+    }
+
+    /// 发布数据变更通知（无订阅者时直接丢弃）
+    pub fn notify_change(&self, session_id: &str) {
+        let _ = self.changes.send(ChangeEvent {
+            session_id: session_id.to_string(),
+        });
+    }
+
+    /// 订阅数据库变更：会话的变更唤醒等待者，项目状态变化推送 `ProjectUpdated`
+    ///
+    /// 回调只持有弱引用，不会让数据库和连接管理器互相保活。
+    pub fn observe(self: &Arc<Self>, db: &Arc<SessionDB>) -> SubscriptionId {
+        let connections = Arc::downgrade(self);
+        let weak_db = Arc::downgrade(db);
+        db.subscribe_changes(move |event| {
+            let Some(connections) = connections.upgrade() else {
+                return;
+            };
+            match event {
+                observer::ChangeEvent::NewMessages { session_id, .. }
+                | observer::ChangeEvent::MessagesUpdated { session_id, .. }
+                | observer::ChangeEvent::SessionUpserted { session_id } => {
+                    connections.notify_change(&session_id);
+                }
+                observer::ChangeEvent::ApprovalChanged { session_ids, .. } => {
+                    for session_id in &session_ids {
+                        connections.notify_change(session_id);
+                    }
+                }
+                observer::ChangeEvent::ProjectChanged {
+                    project_id,
+                    project_path,
+                    ignored,
+                } => {
+                    let change_counter = weak_db
+                        .upgrade()
+                        .and_then(|db| db.change_counter().ok())
+                        .unwrap_or_default();
+                    connections.broadcast_push(&Push::ProjectUpdated {
+                        project_id,
+                        project_path,
+                        ignored,
+                        change_counter,
+                    });
+                }
+            }
+        })
+    }
+
+    /// 订阅推送类型，返回订阅后的全部类型（排序）
+    ///
+    /// 从未订阅过的连接订阅后只接收订阅的类型；订阅集合在连接内保留，
+    /// 重复订阅同一类型不会重复投递。已注销的连接返回空列表。
+    pub fn subscribe(&self, conn_id: ConnId, events: &[String]) -> Vec<String> {
+        let mut subscriptions = self.subscriptions.write();
+        let Some(subscription) = subscriptions.get_mut(&conn_id) else {
+            return Vec::new();
+        };
+        let subscribed = subscription.get_or_insert_with(HashSet::new);
+        subscribed.extend(events.iter().cloned());
+        sorted(subscribed)
+    }
+
+    /// 取消订阅推送类型，返回剩余的全部类型（排序）
+    ///
+    /// 从未订阅过的连接（接收全部推送）取消后接收其余全部类型（`Push::EVENT_TYPES`）；
+    /// 全部取消后集合为空，不再接收推送。已注销的连接返回空列表。
+    pub fn unsubscribe(&self, conn_id: ConnId, events: &[String]) -> Vec<String> {
+        let mut subscriptions = self.subscriptions.write();
+        let Some(subscription) = subscriptions.get_mut(&conn_id) else {
+            return Vec::new();
+        };
+        let subscribed = subscription.get_or_insert_with(|| {
+            Push::EVENT_TYPES
+                .iter()
+                .map(|event| event.to_string())
+                .collect()
+        });
+        for event in events {
+            subscribed.remove(event);
+        }
+        sorted(subscribed)
+    }
+
+    /// 广播推送到订阅了该类型的连接（非阻塞），返回成功发送的连接数
+    pub fn broadcast_push(&self, push: &Push) -> usize {
+        let Ok(json) = serde_json::to_string(push) else {
+            return 0;
+        };
+        let message = format!("{}\n", json);
+        let event_type = push.event_type();
+
+        let senders: Vec<MessageSender> = {
+            // 加锁顺序与 cleanup_closed 一致：先 senders 后 subscriptions
+            let senders = self.senders.read();
+            let subscriptions = self.subscriptions.read();
+            senders
+                .iter()
+                .filter(|(id, _)| {
+                    subscriptions
+                        .get(id)
+                        .and_then(Option::as_ref)
+                        .map_or(true, |subscribed| subscribed.contains(event_type))
+                })
+                .map(|(_, sender)| sender.clone())
+                .collect()
+        };
+        senders
+            .iter()
+            .filter(|sender| sender.try_send(message.clone()).is_ok())
+            .count()
+    }
+}
+
+fn sorted(events: &HashSet<String>) -> Vec<String> {
+    let mut events: Vec<String> = events.iter().cloned().collect();
+    events.sort();
+    events
 }
 
 impl Default for ConnectionManager {
     fn default() -> Self {
+        let (changes, _) = broadcast::channel(CHANGE_CHANNEL_CAPACITY);
         Self {
             senders: RwLock::new(HashMap::new()),
+            subscriptions: RwLock::new(HashMap::new()),
             next_conn_id: RwLock::new(1),
+            changes,
         }
     }
 }
@@ -137,4 +308,151 @@ mod tests {
         manager.unregister(conn1);
         assert_eq!(manager.connection_count(), 1);
     }
+
+    #[test]
+    fn test_try_register_respects_limit() {
+        let manager = ConnectionManager::new();
+
+        let (tx1, _rx1) = mpsc::channel(10);
+        let conn1 = manager.try_register(tx1, 1).unwrap();
+        let (tx2, _rx2) = mpsc::channel(10);
+        assert!(manager.try_register(tx2.clone(), 1).is_none());
+        assert_eq!(manager.connection_count(), 1);
+
+        // 释放名额后可以再注册
+        manager.unregister(conn1);
+        assert!(manager.try_register(tx2, 1).is_some());
+    }
+
+    #[test]
+    fn test_broadcast() {
+        let manager = ConnectionManager::new();
+
+        let (tx1, mut rx1) = mpsc::channel(10);
+        manager.register(tx1);
+        let (tx2, mut rx2) = mpsc::channel(10);
+        manager.register(tx2);
+        let (tx3, rx3) = mpsc::channel(10);
+        manager.register(tx3);
+        drop(rx3);
+
+        // 未订阅的连接接收全部推送；接收端已关闭的连接不计入
+        assert_eq!(manager.broadcast_push(&session_ended("s1")), 2);
+        assert!(rx1.try_recv().unwrap().contains("s1"));
+        assert!(rx2.try_recv().unwrap().contains("s1"));
+    }
+
+    #[test]
+    fn test_change_notification() {
+        let manager = ConnectionManager::new();
+
+        // 无订阅者时不报错
+        manager.notify_change("s0");
+
+        let mut rx = manager.subscribe_changes();
+        manager.notify_change("s1");
+        assert_eq!(rx.try_recv().unwrap().session_id, "s1");
+        assert!(rx.try_recv().is_err());
+    }
+
+    fn session_ended(session_id: &str) -> Push {
+        Push::SessionEnded {
+            session_id: session_id.to_string(),
+            message_count: 1,
+            change_counter: 0,
+        }
+    }
+
+    fn integrity_warning() -> Push {
+        Push::IntegrityWarning {
+            detail: "corrupted".to_string(),
+            change_counter: 0,
+        }
+    }
+
+    #[test]
+    fn test_subscription_is_idempotent_set() {
+        let manager = ConnectionManager::new();
+        let (tx, mut rx) = mpsc::channel(10);
+        let conn = manager.register(tx);
+        let (legacy_tx, mut legacy_rx) = mpsc::channel(10);
+        manager.register(legacy_tx);
+
+        let events = vec!["SessionEnded".to_string()];
+        assert_eq!(manager.subscribe(conn, &events), events);
+        // 重复订阅不重复记录
+        assert_eq!(manager.subscribe(conn, &events), events);
+
+        // 订阅过的连接只收到一份订阅的类型；未订阅的连接收到全部
+        assert_eq!(manager.broadcast_push(&session_ended("s1")), 2);
+        assert_eq!(manager.broadcast_push(&integrity_warning()), 1);
+        assert!(rx.try_recv().unwrap().contains("\"s1\""));
+        assert!(rx.try_recv().is_err());
+        assert!(legacy_rx.try_recv().unwrap().contains("SessionEnded"));
+        assert!(legacy_rx.try_recv().unwrap().contains("IntegrityWarning"));
+
+        // 一次取消即完全取消，不再泄漏
+        assert!(manager.unsubscribe(conn, &events).is_empty());
+        assert!(manager.unsubscribe(conn, &events).is_empty());
+        assert_eq!(manager.broadcast_push(&session_ended("s2")), 1);
+        assert!(rx.try_recv().is_err());
+        assert!(legacy_rx.try_recv().unwrap().contains("\"s2\""));
+    }
+
+    #[test]
+    fn test_unregister_drops_subscription() {
+        let manager = ConnectionManager::new();
+        let (tx, _rx) = mpsc::channel(10);
+        let conn = manager.register(tx);
+        manager.subscribe(conn, &["SessionIdle".to_string()]);
+
+        manager.unregister(conn);
+        assert!(manager.subscriptions.read().is_empty());
+        // 已注销的连接订阅、取消订阅不留下记录
+        assert!(manager
+            .subscribe(conn, &["SessionIdle".to_string()])
+            .is_empty());
+        assert!(manager
+            .unsubscribe(conn, &["SessionIdle".to_string()])
+            .is_empty());
+        assert!(manager.subscriptions.read().is_empty());
+    }
+
+    #[test]
+    fn test_unsubscribe_without_subscription_keeps_other_types() {
+        let manager = ConnectionManager::new();
+        let (tx, mut rx) = mpsc::channel(10);
+        let conn = manager.register(tx);
+
+        // 接收全部推送的连接取消一种类型后，仍接收其余全部类型
+        let remaining = manager.unsubscribe(conn, &["IntegrityWarning".to_string()]);
+        assert_eq!(remaining.len(), Push::EVENT_TYPES.len() - 1);
+        assert!(!remaining.contains(&"IntegrityWarning".to_string()));
+        assert!(remaining.contains(&"SessionEnded".to_string()));
+
+        assert_eq!(manager.broadcast_push(&integrity_warning()), 0);
+        assert_eq!(manager.broadcast_push(&session_ended("s1")), 1);
+        assert!(rx.try_recv().unwrap().contains("SessionEnded"));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_unsubscribe_last_type_receives_nothing() {
+        let manager = ConnectionManager::new();
+        let (tx, mut rx) = mpsc::channel(10);
+        let conn = manager.register(tx);
+
+        let events = vec!["SessionEnded".to_string()];
+        manager.subscribe(conn, &events);
+        // 取消最后一种类型后不再接收任何推送，而不是回到接收全部
+        assert!(manager.unsubscribe(conn, &events).is_empty());
+        assert_eq!(manager.broadcast_push(&session_ended("s1")), 0);
+        assert_eq!(manager.broadcast_push(&integrity_warning()), 0);
+        assert!(rx.try_recv().is_err());
+
+        // 重新订阅后恢复
+        assert_eq!(manager.subscribe(conn, &events), events);
+        assert_eq!(manager.broadcast_push(&session_ended("s2")), 1);
+        assert!(rx.try_recv().unwrap().contains("\"s2\""));
+    }
 }
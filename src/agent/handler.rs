@@ -4,20 +4,92 @@
 
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
+use super::activity::now_ms;
 use super::broadcaster::{ConnectionManager, ConnId};
+use super::integrity::IntegrityMonitor;
+use super::replication::Replicator;
+use super::resources::ResourceMonitor;
+use super::waiter::{ChangeWaiters, WaitOutcome};
 use super::watcher::FileWatcher;
-use crate::protocol::{HookEvent, QueryType, Request, Response};
+use crate::audit::AuditFormat;
+use crate::collector::collection_lock_holder;
+use crate::db::MAX_TALK_SUMMARIES_LIMIT;
+use crate::importer::{import_claude_export, ImportOptions, CLAUDE_WEB_SOURCE};
+use crate::migrations::{PendingMigration, SchemaCompatibility};
+use crate::protocol::{
+    collect_trigger, error_code, hook_event_type, negotiate_protocol_version, writer_type,
+    HookEvent, IgnoreRuleInput, MaintenanceTask, Push, QueryType, Request, Response, WriterRole,
+    MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+use crate::reader::check_dir_access;
+use crate::rederive::extract_content_text;
+use crate::router::DbRouter;
+use crate::search_log::SearchLogInput;
 use crate::sync::{SyncDb, SyncWorker};
-use crate::SessionDB;
+use crate::types::{CollectionIgnore, IndexState, Page, SearchGroupOptions};
+use crate::{all_watch_configs, CollectBatch, Collector, IgnoreRules, SessionDB};
 
 /// Agent 版本号（跟随 crate 版本）
 pub const AGENT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// 无权访问的数据目录（UI 据此提示授予完全磁盘访问权限）
+fn access_issues() -> Vec<serde_json::Value> {
+    all_watch_configs()
+        .iter()
+        .filter_map(|config| match check_dir_access(&config.path) {
+            Err(crate::Error::AccessDenied(path)) => Some(serde_json::json!({
+                "source": config.name,
+                "path": path,
+            })),
+            _ => None,
+        })
+        .collect()
+}
+
+/// 分页查询的响应（token 无效时返回 INVALID_PAGE_TOKEN，客户端应从第一页重新开始）
+fn page_response<T: serde::Serialize>(action: &str, result: crate::Result<Page<T>>) -> Response {
+    match result {
+        Ok(page) => Response::QueryResult {
+            data: serde_json::to_value(page).unwrap_or_default(),
+        },
+        Err(crate::Error::InvalidPageToken(reason)) => Response::Error {
+            code: error_code::INVALID_PAGE_TOKEN,
+            message: format!("Failed to {}: invalid page token: {}", action, reason),
+        },
+        Err(e) => {
+            tracing::error!("Failed to {}: {}", action, e);
+            Response::Error {
+                code: 500,
+                message: format!("Failed to {}: {}", action, e),
+            }
+        }
+    }
+}
+
+/// 按来源拆分数据库时无法合并的查询
+fn routed_unsupported(what: &str) -> Response {
+    Response::Error {
+        code: error_code::UNSUPPORTED_WITH_ROUTING,
+        message: format!("{} are not supported with per-source databases", what),
+    }
+}
+
+/// Agent 协议的 Search 未指定 include_content 时不返回正文（见 `QueryType::Search`）
+fn search_options(options: SearchGroupOptions) -> SearchGroupOptions {
+    SearchGroupOptions {
+        include_content: Some(options.include_content.unwrap_or(false)),
+        ..options
+    }
+}
+
 /// 请求处理器
 pub struct Handler {
-    /// 数据库连接
+    /// 数据库连接（默认数据库）
     db: Arc<SessionDB>,
+    /// 按来源拆分的数据库
+    router: Arc<DbRouter>,
     /// 连接管理器
     connections: Arc<ConnectionManager>,
     /// 文件监听器
@@ -26,38 +98,110 @@ pub struct Handler {
     sync_worker: Arc<SyncWorker>,
     /// 同步状态 DB
     sync_db: Arc<SyncDb>,
+    /// 变更等待器
+    waiters: ChangeWaiters,
+    /// 启动时执行的迁移
+    startup_migrations: Vec<PendingMigration>,
+    /// 生效的配置（`AgentConfig::effective_config`）
+    effective_config: serde_json::Value,
+    /// 自身资源占用（Status 的 `resources`）
+    resources: Arc<ResourceMonitor>,
+    /// 完整性巡检（Status 的 `invariants`）
+    integrity: Arc<IntegrityMonitor>,
+    /// 消息复制（Status 的 `replication`），未配置复制目标时为 None
+    replication: Option<Arc<Replicator>>,
+    /// 数据库要求更新版本的写入方时只读运行（见 `SessionDB::compatibility_check`）
+    degraded: Option<SchemaCompatibility>,
+    /// 取得写入角色的时间（Agent 启动时，毫秒）
+    writer_since: i64,
 }
 
 impl Handler {
     /// 创建处理器
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        db: Arc<SessionDB>,
+        router: Arc<DbRouter>,
         connections: Arc<ConnectionManager>,
         watcher: Arc<FileWatcher>,
         sync_worker: Arc<SyncWorker>,
         sync_db: Arc<SyncDb>,
+        waiters: ChangeWaiters,
+        startup_migrations: Vec<PendingMigration>,
+        effective_config: serde_json::Value,
+        resources: Arc<ResourceMonitor>,
+        integrity: Arc<IntegrityMonitor>,
+        replication: Option<Arc<Replicator>>,
+        degraded: Option<SchemaCompatibility>,
     ) -> Self {
         Self {
-            db,
+            db: router.default_db().clone(),
+            router,
             connections,
             watcher,
             sync_worker,
             sync_db,
+            waiters,
+            startup_migrations,
+            effective_config,
+            resources,
+            integrity,
+            replication,
+            degraded,
+            writer_since: now_ms(),
         }
     }
 
+    /// 只读降级时握手后推送给该连接的 `Push::AgentDegraded`
+    pub fn degraded_push(&self) -> Option<Push> {
+        self.degraded.map(|compatibility| Push::AgentDegraded {
+            compatibility,
+            change_counter: self.db.change_counter().unwrap_or_default(),
+        })
+    }
+
     /// 处理请求
     pub async fn handle(&self, conn_id: ConnId, request: Request) -> Response {
+        if let Some(compatibility) = self.degraded.filter(|_| request.is_write()) {
+            tracing::warn!(
+                "Rejecting write from conn_id={}: database requires writer schema {}, supported {}",
+                conn_id,
+                compatibility.min_writer_schema_version,
+                compatibility.supported_schema_version
+            );
+            return Response::VersionMismatch { compatibility };
+        }
+
         match request {
-            Request::Handshake { component, version } => {
+            Request::Handshake {
+                component,
+                version,
+                protocol_version,
+            } => {
                 tracing::info!(
-                    "🤝 握手: conn_id={}, component={}, version={}",
+                    "🤝 握手: conn_id={}, component={}, version={}, protocol={}",
                     conn_id,
                     component,
-                    version
+                    version,
+                    protocol_version
                 );
-                Response::HandshakeOk {
-                    agent_version: AGENT_VERSION.to_string(),
+                match negotiate_protocol_version(protocol_version) {
+                    Some(negotiated) => Response::HandshakeOk {
+                        agent_version: AGENT_VERSION.to_string(),
+                        protocol_version: negotiated,
+                        degraded: self.degraded,
+                    },
+                    None => {
+                        tracing::warn!(
+                            "Rejecting handshake: conn_id={}, unsupported protocol version {}",
+                            conn_id,
+                            protocol_version
+                        );
+                        Response::ProtocolVersionUnsupported {
+                            protocol_version,
+                            min_supported: MIN_PROTOCOL_VERSION,
+                            max_supported: PROTOCOL_VERSION,
+                        }
+                    }
                 }
             }
 
@@ -72,14 +216,41 @@ impl Handler {
                 self.handle_write_index_result(&session_id, &indexed_message_ids)
             }
 
+            Request::MarkIndexed { message_ids, state } => {
+                self.handle_mark_indexed(&message_ids, state)
+            }
+
+            Request::WriteCollectBatch { batch } => {
+                self.handle_write_collect_batch(batch).await
+            }
+
+            Request::WriteSearchLog { entries } => self.handle_write_search_log(&entries),
+
+            Request::CollectAll => self.handle_collect_all().await,
+
+            Request::DrainVectorTombstones { limit } => {
+                self.handle_drain_vector_tombstones(crate::config::boundary_limit(limit))
+            }
+
+            Request::AckVectorTombstones { ids } => {
+                self.handle_ack_vector_tombstones(&ids)
+            }
+
             Request::WriteCompactResult {
                 session_id,
                 talk_id,
                 summary_l2,
                 summary_l3,
-            } => {
-                self.handle_write_compact_result(&session_id, &talk_id, &summary_l2, summary_l3.as_deref())
-            }
+                position,
+                first_message_sequence,
+            } => self.handle_write_compact_result(
+                &session_id,
+                &talk_id,
+                &summary_l2,
+                summary_l3.as_deref(),
+                position,
+                first_message_sequence,
+            ),
 
             Request::WriteApproveResult {
                 tool_call_id,
@@ -108,6 +279,54 @@ impl Handler {
                 self.sync_worker.resume(&self.sync_db);
                 Response::Ok
             }
+
+            Request::UpdateIgnores { add, remove } => {
+                self.handle_update_ignores(&add, &remove)
+            }
+
+            Request::Subscribe { events } => Response::QueryResult {
+                data: serde_json::json!(self.connections.subscribe(conn_id, &events)),
+            },
+
+            Request::Unsubscribe { events } => Response::QueryResult {
+                data: serde_json::json!(self.connections.unsubscribe(conn_id, &events)),
+            },
+
+            Request::ExportApprovalAudit {
+                path,
+                format,
+                project_id,
+                start_ms,
+                end_ms,
+            } => self.handle_export_approval_audit(&path, format, project_id, start_ms, end_ms),
+
+            Request::ForgetProject {
+                project_id,
+                secure,
+                confirm_token,
+            } => self.handle_forget_project(project_id, secure, confirm_token.as_deref()),
+
+            Request::ImportArchive {
+                path,
+                match_projects,
+            } => self.handle_import_archive(&path, match_projects),
+
+            Request::Maintenance { task } => self.handle_maintenance(task).await,
+
+            Request::WaitForChange {
+                session_id,
+                project_path,
+                after_state_hash,
+                timeout_ms,
+            } => {
+                self.handle_wait_for_change(
+                    session_id.as_deref(),
+                    project_path.as_deref(),
+                    after_state_hash,
+                    timeout_ms,
+                )
+                .await
+            }
         }
     }
 
@@ -151,6 +370,313 @@ impl Handler {
         }
     }
 
+    /// 处理设置向量索引状态
+    fn handle_mark_indexed(&self, message_ids: &[i64], state: IndexState) -> Response {
+        tracing::debug!(
+            "📊 Mark indexed: state={:?}, count={}",
+            state,
+            message_ids.len()
+        );
+
+        match self.db.set_index_state(message_ids, state) {
+            Ok(_) => Response::Ok,
+            Err(e) => {
+                tracing::error!("Failed to set index state: {}", e);
+                Response::Error {
+                    code: 500,
+                    message: format!("Failed to set index state: {}", e),
+                }
+            }
+        }
+    }
+
+    /// 处理写入采集批次
+    async fn handle_write_collect_batch(&self, batch: CollectBatch) -> Response {
+        let session_id = batch.session.session_id.clone();
+        tracing::debug!(
+            "📥 Write collect batch: session_id={}, messages={}",
+            session_id,
+            batch.messages.len()
+        );
+
+        let router = self.router.clone();
+        let limits = self.watcher.collect_limits();
+        let applied = tokio::task::spawn_blocking(move || {
+            Collector::routed(&router)
+                .with_limits(limits)
+                .apply_batch(&batch)
+        })
+        .await;
+
+        match applied {
+            Ok(Ok(result)) => {
+                if result.messages_inserted > 0 || result.revisions_detected > 0 {
+                    self.sync_worker.trigger_session(&session_id);
+                }
+                if let Some(err) = result.errors.first() {
+                    tracing::warn!("Collect batch for {} had errors: {}", session_id, err);
+                }
+                Response::QueryResult {
+                    data: serde_json::json!({
+                        "messages_inserted": result.messages_inserted,
+                        "revisions_detected": result.revisions_detected,
+                    }),
+                }
+            }
+            Ok(Err(e)) => {
+                tracing::error!("Failed to write collect batch: {}", e);
+                Response::Error {
+                    code: 500,
+                    message: format!("Failed to write collect batch: {}", e),
+                }
+            }
+            Err(e) => Response::Error {
+                code: 500,
+                message: format!("spawn_blocking failed: {}", e),
+            },
+        }
+    }
+
+    /// 处理搜索记录写入（记录到默认数据库）
+    fn handle_write_search_log(&self, entries: &[SearchLogInput]) -> Response {
+        tracing::debug!("🔍 Write search log: count={}", entries.len());
+
+        match self.db.write_search_log(entries) {
+            Ok(written) => Response::QueryResult {
+                data: serde_json::json!({ "written": written }),
+            },
+            Err(e) => {
+                tracing::error!("Failed to write search log: {}", e);
+                Response::Error {
+                    code: 500,
+                    message: format!("Failed to write search log: {}", e),
+                }
+            }
+        }
+    }
+
+    /// 处理全量采集请求（进度通过 CollectStarted / CollectFinished 推送）
+    async fn handle_collect_all(&self) -> Response {
+        tracing::info!("📊 Collect all requested");
+
+        match self.watcher.collect_all(collect_trigger::REQUEST).await {
+            Ok(summary) => Response::QueryResult {
+                data: serde_json::to_value(summary).unwrap_or_default(),
+            },
+            Err(e) if collection_lock_holder(&e).is_some() => Response::Error {
+                code: 503,
+                message: format!("{}, retry later", e),
+            },
+            Err(e) => {
+                tracing::error!("Failed to collect: {}", e);
+                Response::Error {
+                    code: 500,
+                    message: format!("Failed to collect: {}", e),
+                }
+            }
+        }
+    }
+
+    /// 处理读取向量墓碑
+    fn handle_drain_vector_tombstones(&self, limit: usize) -> Response {
+        match self.db.drain_vector_tombstones(limit) {
+            Ok(tombstones) => Response::QueryResult {
+                data: serde_json::to_value(tombstones).unwrap_or_default(),
+            },
+            Err(e) => {
+                tracing::error!("Failed to drain vector tombstones: {}", e);
+                Response::Error {
+                    code: 500,
+                    message: format!("Failed to drain vector tombstones: {}", e),
+                }
+            }
+        }
+    }
+
+    /// 处理向量墓碑确认
+    fn handle_ack_vector_tombstones(&self, ids: &[i64]) -> Response {
+        tracing::debug!("🪦 确认向量墓碑: count={}", ids.len());
+
+        match self.db.ack_vector_tombstones(ids) {
+            Ok(_) => Response::Ok,
+            Err(e) => {
+                tracing::error!("Failed to ack vector tombstones: {}", e);
+                Response::Error {
+                    code: 500,
+                    message: format!("Failed to ack vector tombstones: {}", e),
+                }
+            }
+        }
+    }
+
+    /// 处理审批审计导出（写入失败时删除不完整的文件）
+    fn handle_export_approval_audit(
+        &self,
+        path: &Path,
+        format: AuditFormat,
+        project_id: Option<i64>,
+        start_ms: Option<i64>,
+        end_ms: Option<i64>,
+    ) -> Response {
+        let result = std::fs::File::create(path)
+            .map_err(crate::Error::from)
+            .and_then(|file| {
+                self.db.export_approval_audit(
+                    project_id,
+                    start_ms,
+                    end_ms,
+                    format,
+                    std::io::BufWriter::new(file),
+                )
+            });
+        match result {
+            Ok(rows) => Response::QueryResult {
+                data: serde_json::json!({ "rows": rows }),
+            },
+            Err(e) => {
+                tracing::error!(
+                    "Failed to export approval audit to {}: {}",
+                    path.display(),
+                    e
+                );
+                let _ = std::fs::remove_file(path);
+                Response::Error {
+                    code: 500,
+                    message: format!("Failed to export approval audit: {}", e),
+                }
+            }
+        }
+    }
+
+    /// 处理项目删除：无 confirm_token 时预览，token 与当前预览一致时删除
+    fn handle_forget_project(
+        &self,
+        project_id: i64,
+        secure: bool,
+        confirm_token: Option<&str>,
+    ) -> Response {
+        if self.router.is_routed() {
+            return routed_unsupported("Project ID filters");
+        }
+
+        let result = self
+            .db
+            .plan_forget_project(project_id)
+            .and_then(|plan| match confirm_token {
+                None => Ok(Some(plan)),
+                Some(token) if token == plan.confirm_token => {
+                    self.db.forget_project(project_id, secure).map(Some)
+                }
+                Some(_) => Ok(None),
+            });
+        match result {
+            Ok(Some(report)) => {
+                if !report.dry_run {
+                    tracing::info!(
+                        "🗑️ Forgot project {} ({}): {} sessions, {} messages",
+                        report.project_id,
+                        report.project_path,
+                        report.sessions,
+                        report.messages
+                    );
+                }
+                Response::QueryResult {
+                    data: serde_json::to_value(report).unwrap_or_default(),
+                }
+            }
+            Ok(None) => Response::Error {
+                code: error_code::CONFIRM_TOKEN_MISMATCH,
+                message: format!(
+                    "Project {} changed since the preview; request a new confirm token",
+                    project_id
+                ),
+            },
+            Err(e) => {
+                tracing::error!("Failed to forget project {}: {}", project_id, e);
+                Response::Error {
+                    code: 500,
+                    message: format!("Failed to forget project: {}", e),
+                }
+            }
+        }
+    }
+
+    /// 处理 claude.ai 导出导入（按来源拆分时写入 `claude-web` 对应的数据库）
+    fn handle_import_archive(&self, path: &Path, match_projects: bool) -> Response {
+        let db = self.router.for_source(CLAUDE_WEB_SOURCE);
+        match import_claude_export(db, path, &ImportOptions { match_projects }) {
+            Ok(report) => {
+                tracing::info!(
+                    "📥 Imported {}: {} sessions, {} messages",
+                    path.display(),
+                    report.sessions_created,
+                    report.messages_created
+                );
+                Response::QueryResult {
+                    data: serde_json::to_value(report).unwrap_or_default(),
+                }
+            }
+            Err(e) => {
+                tracing::error!("Failed to import {}: {}", path.display(), e);
+                Response::Error {
+                    code: 500,
+                    message: format!("Failed to import archive: {}", e),
+                }
+            }
+        }
+    }
+
+    /// 处理维护任务（在阻塞线程中执行，按批提交）
+    async fn handle_maintenance(&self, task: MaintenanceTask) -> Response {
+        if self.router.is_routed() {
+            return routed_unsupported("Maintenance tasks");
+        }
+
+        let db = self.db.clone();
+        let result = tokio::task::spawn_blocking(move || match task {
+            MaintenanceTask::RederiveContentText {
+                batch_size,
+                max_batches,
+                restart,
+            } => {
+                if restart {
+                    db.reset_rederive_progress()?;
+                }
+                let max_batches = max_batches.unwrap_or(usize::MAX);
+                db.rederive_content_text_until(batch_size, extract_content_text, |progress| {
+                    progress.batches < max_batches
+                })
+            }
+        })
+        .await;
+
+        match result {
+            Ok(Ok(progress)) => {
+                tracing::info!(
+                    "🧹 Rederived content_text: {} examined, {} changed, {} queued for reindex (done: {})",
+                    progress.examined,
+                    progress.changed,
+                    progress.reindex_queued,
+                    progress.done
+                );
+                Response::QueryResult {
+                    data: serde_json::to_value(progress).unwrap_or_default(),
+                }
+            }
+            Ok(Err(e)) => {
+                tracing::error!("Maintenance task failed: {}", e);
+                Response::Error {
+                    code: 500,
+                    message: format!("Maintenance task failed: {}", e),
+                }
+            }
+            Err(e) => Response::Error {
+                code: 500,
+                message: format!("spawn_blocking failed: {}", e),
+            },
+        }
+    }
+
     /// 处理写入 Compact 结果
     fn handle_write_compact_result(
         &self,
@@ -158,6 +684,8 @@ impl Handler {
         talk_id: &str,
         summary_l2: &str,
         summary_l3: Option<&str>,
+        position: Option<i64>,
+        first_message_sequence: Option<i64>,
     ) -> Response {
         tracing::debug!(
             "📝 写入 Compact 结果: session_id={}, talk_id={}",
@@ -166,7 +694,16 @@ impl Handler {
         );
 
         // 写入 Talk 摘要
-        match self.db.upsert_talk_summary(session_id, talk_id, summary_l2, summary_l3) {
+        match self.router.for_session(session_id).and_then(|db| {
+            db.upsert_talk_summary(
+                session_id,
+                talk_id,
+                summary_l2,
+                summary_l3,
+                position,
+                first_message_sequence,
+            )
+        }) {
             Ok(_) => Response::Ok,
             Err(e) => {
                 tracing::error!("Failed to write compact result: {}", e);
@@ -178,7 +715,7 @@ impl Handler {
         }
     }
 
-    /// 处理写入 Approve 结果
+    /// 处理写入 Approve 结果，有更新时广播 `Push::ApprovalResolved`
     fn handle_write_approve_result(
         &self,
         tool_call_id: &str,
@@ -198,8 +735,31 @@ impl Handler {
             crate::protocol::ApprovalStatus::Timeout => crate::types::ApprovalStatus::Timeout,
         };
 
-        match self.db.update_approval_status_by_tool_call_id(tool_call_id, db_status, resolved_at) {
-            Ok(_) => Response::Ok,
+        // 工具调用所在的数据库未知，依次更新
+        let updated: crate::Result<usize> = self
+            .router
+            .all()
+            .map(|(_, db)| {
+                db.update_approval_status_by_tool_call_id(tool_call_id, db_status, resolved_at)
+            })
+            .sum();
+        match updated {
+            Ok(updated) => {
+                if updated > 0 {
+                    self.connections.broadcast_push(&Push::ApprovalResolved {
+                        tool_call_id: tool_call_id.to_string(),
+                        status,
+                        resolved_at,
+                        updated,
+                        change_counter: self.db.change_counter().unwrap_or_default(),
+                    });
+                } else {
+                    tracing::debug!("No message matched tool_call_id={}", tool_call_id);
+                }
+                Response::QueryResult {
+                    data: serde_json::json!({ "updated": updated }),
+                }
+            }
             Err(e) => {
                 tracing::error!("Failed to write approval result: {}", e);
                 Response::Error {
@@ -210,22 +770,394 @@ impl Handler {
         }
     }
 
+    /// 处理忽略规则更新
+    ///
+    /// 更新后刷新项目 ignored 标记，并向所有连接广播状态变化的项目
+    fn handle_update_ignores(&self, add: &[IgnoreRuleInput], remove: &[i64]) -> Response {
+        tracing::info!("🙈 Updating ignore rules: add={}, remove={}", add.len(), remove.len());
+
+        // 状态变化的项目由数据库变更通知推送 ProjectUpdated（见 `ConnectionManager::observe`）
+        match self.apply_ignore_updates(add, remove) {
+            Ok(ignores) => Response::QueryResult {
+                data: serde_json::to_value(ignores).unwrap_or_default(),
+            },
+            Err(e) => {
+                tracing::error!("Failed to update ignore rules: {}", e);
+                Response::Error {
+                    code: 500,
+                    message: format!("Failed to update ignore rules: {}", e),
+                }
+            }
+        }
+    }
+
+    /// 写入规则变更并刷新项目标记，返回全部规则
+    fn apply_ignore_updates(
+        &self,
+        add: &[IgnoreRuleInput],
+        remove: &[i64],
+    ) -> crate::Result<Vec<CollectionIgnore>> {
+        for rule in add {
+            self.db.add_ignore(&rule.pattern, rule.kind)?;
+        }
+        for id in remove {
+            self.db.remove_ignore(*id)?;
+        }
+        let rules = IgnoreRules::load(&self.db)?;
+        self.db.apply_project_ignores(&rules)?;
+        self.db.list_ignores()
+    }
+
+    /// 处理变更等待（长轮询）
+    async fn handle_wait_for_change(
+        &self,
+        session_id: Option<&str>,
+        project_path: Option<&str>,
+        after_state_hash: Option<u64>,
+        timeout_ms: u64,
+    ) -> Response {
+        let outcome = self
+            .waiters
+            .wait(
+                &self.db,
+                &self.connections,
+                session_id,
+                project_path,
+                after_state_hash,
+                Duration::from_millis(timeout_ms),
+            )
+            .await;
+
+        match outcome {
+            Ok(WaitOutcome::Changed(state)) => Response::Changed { state },
+            Ok(WaitOutcome::NotModified(state)) => Response::NotModified { state },
+            Ok(WaitOutcome::TooManyWaiters) => Response::Error {
+                code: 503,
+                message: "Too many concurrent waiters".to_string(),
+            },
+            Err(e) => {
+                tracing::error!("Failed to wait for change: {}", e);
+                Response::Error {
+                    code: 500,
+                    message: format!("Failed to wait for change: {}", e),
+                }
+            }
+        }
+    }
+
     /// 处理查询
     fn handle_query(&self, query_type: QueryType) -> Response {
-        match query_type {
+        match query_type.with_boundary_limits() {
             QueryType::Status => {
                 let status = serde_json::json!({
                     "agent_version": AGENT_VERSION,
+                    "protocol_version": PROTOCOL_VERSION,
+                    "library_version": crate::VERSION_FULL,
+                    "build_timestamp": crate::BUILD_TIMESTAMP,
+                    "schema_version": self.db.schema_version().ok(),
+                    "supported_schema_version": crate::migrations::SUPPORTED_SCHEMA_VERSION,
+                    "degraded": self.degraded,
+                    "sqlite_version": rusqlite::version(),
                     "connections": self.connections.connection_count(),
+                    "startup_migrations": self.startup_migrations,
+                    "access_issues": access_issues(),
+                    "collection_lock": self.db.get_collection_lock().ok().flatten(),
+                    "fts_backlog": self.db.fts_backlog_count().ok(),
+                    "sessions_with_gaps": self.db.count_sessions_with_gaps().ok(),
+                    "databases": self.router.databases(),
+                    "config": self.effective_config,
+                    "resources": self.resources.metrics(),
+                    "invariants": {
+                        "last_report": self.integrity.invariant_report(),
+                        "paranoid_violations": crate::invariants::paranoid_violation_count(),
+                    },
+                    "replication": self.replication.as_ref().map(|r| r.status()),
                 });
                 Response::QueryResult { data: status }
             }
+            QueryType::ApprovalAudit {
+                project_id,
+                start_ms,
+                end_ms,
+                limit,
+                offset,
+            } => match self
+                .db
+                .approval_audit(project_id, start_ms, end_ms, limit, offset)
+            {
+                Ok(rows) => Response::QueryResult {
+                    data: serde_json::to_value(rows).unwrap_or_default(),
+                },
+                Err(e) => {
+                    tracing::error!("Failed to query approval audit: {}", e);
+                    Response::Error {
+                        code: 500,
+                        message: format!("Failed to query approval audit: {}", e),
+                    }
+                }
+            },
+            QueryType::WriterRole => {
+                let role = WriterRole {
+                    is_writer: self.degraded.is_none(),
+                    writer_type: writer_type::AGENT.to_string(),
+                    since: self.writer_since,
+                };
+                Response::QueryResult {
+                    data: serde_json::to_value(role).unwrap_or_default(),
+                }
+            }
             QueryType::ConnectionCount => {
                 let count = self.connections.connection_count();
                 Response::QueryResult {
                     data: serde_json::json!({ "count": count }),
                 }
             }
+            QueryType::ChangeCounter => match self.db.change_counter() {
+                Ok(counter) => Response::QueryResult {
+                    data: serde_json::json!({ "counter": counter }),
+                },
+                Err(e) => {
+                    tracing::error!("Failed to get change counter: {}", e);
+                    Response::Error {
+                        code: 500,
+                        message: format!("Failed to get change counter: {}", e),
+                    }
+                }
+            },
+            QueryType::RecentSearches {
+                limit,
+                zero_results,
+            } => {
+                let searches = if zero_results {
+                    self.db.zero_result_searches(limit)
+                } else {
+                    self.db.recent_searches(limit)
+                };
+                match searches {
+                    Ok(searches) => Response::QueryResult {
+                        data: serde_json::to_value(searches).unwrap_or_default(),
+                    },
+                    Err(e) => {
+                        tracing::error!("Failed to get recent searches: {}", e);
+                        Response::Error {
+                            code: 500,
+                            message: format!("Failed to get recent searches: {}", e),
+                        }
+                    }
+                }
+            }
+            QueryType::ReconciledProjects {
+                limit,
+                offset,
+                projects_path,
+            } => {
+                let projects_path = projects_path.map(PathBuf::from).unwrap_or_else(|| {
+                    dirs::home_dir()
+                        .unwrap_or_default()
+                        .join(".claude/projects")
+                });
+                let db = self.router.for_source(&crate::Source::Claude.to_string());
+                match db.list_projects_reconciled(&projects_path, limit, offset) {
+                    Ok(projects) => Response::QueryResult {
+                        data: serde_json::to_value(projects).unwrap_or_default(),
+                    },
+                    Err(e) => {
+                        tracing::error!("Failed to list reconciled projects: {}", e);
+                        Response::Error {
+                            code: 500,
+                            message: format!("Failed to list reconciled projects: {}", e),
+                        }
+                    }
+                }
+            }
+            QueryType::HistoryOverview => match self.db.history_overview() {
+                Ok(overview) => Response::QueryResult {
+                    data: serde_json::to_value(overview).unwrap_or_default(),
+                },
+                Err(e) => {
+                    tracing::error!("Failed to get history overview: {}", e);
+                    Response::Error {
+                        code: 500,
+                        message: format!("Failed to get history overview: {}", e),
+                    }
+                }
+            },
+            QueryType::ListProjects { paged: true, .. }
+            | QueryType::Search { paged: true, .. }
+            | QueryType::SearchGrouped { .. }
+                if self.router.is_routed() =>
+            {
+                routed_unsupported("Paged and grouped queries")
+            }
+            QueryType::Search { ref options, .. } | QueryType::SearchCount { ref options, .. }
+                if self.router.is_routed() && options.project_id.is_some() =>
+            {
+                routed_unsupported("Project ID filters")
+            }
+            QueryType::ListProjects { limit, offset, .. } if self.router.is_routed() => {
+                match self.router.list_projects_with_stats(limit, offset) {
+                    Ok(projects) => Response::QueryResult {
+                        data: serde_json::to_value(projects).unwrap_or_default(),
+                    },
+                    Err(e) => {
+                        tracing::error!("Failed to list projects: {}", e);
+                        Response::Error {
+                            code: 500,
+                            message: format!("Failed to list projects: {}", e),
+                        }
+                    }
+                }
+            }
+            QueryType::ListProjects {
+                limit,
+                page_token,
+                paged: true,
+                ..
+            } => page_response(
+                "list projects",
+                self.db.list_projects_page(limit, page_token.as_ref()),
+            ),
+            QueryType::ListProjects { limit, offset, .. } => {
+                match self.db.list_projects_with_stats(limit, offset) {
+                    Ok(projects) => Response::QueryResult {
+                        data: serde_json::to_value(projects).unwrap_or_default(),
+                    },
+                    Err(e) => {
+                        tracing::error!("Failed to list projects: {}", e);
+                        Response::Error {
+                            code: 500,
+                            message: format!("Failed to list projects: {}", e),
+                        }
+                    }
+                }
+            }
+            QueryType::ListSessions {
+                project_path,
+                limit,
+                page_token,
+                paged: true,
+                ..
+            } => page_response(
+                "list sessions",
+                self.router.for_project_path(&project_path).and_then(|db| {
+                    db.list_sessions_page(&project_path, limit, page_token.as_ref())
+                }),
+            ),
+            QueryType::ListSessions {
+                project_path,
+                limit,
+                offset,
+                ..
+            } => match self
+                .router
+                .for_project_path(&project_path)
+                .and_then(|db| db.list_sessions_by_project_path(&project_path, limit, offset))
+            {
+                Ok(sessions) => Response::QueryResult {
+                    data: serde_json::to_value(sessions).unwrap_or_default(),
+                },
+                Err(e) => {
+                    tracing::error!("Failed to list sessions: {}", e);
+                    Response::Error {
+                        code: 500,
+                        message: format!("Failed to list sessions: {}", e),
+                    }
+                }
+            },
+            QueryType::ListMessages {
+                session_id,
+                limit,
+                page_token,
+                paged: true,
+                ..
+            } => page_response(
+                "list messages",
+                self.router.for_session(&session_id).and_then(|db| {
+                    db.list_messages_page(&session_id, limit, false, page_token.as_ref())
+                }),
+            ),
+            QueryType::ListMessages {
+                session_id,
+                limit,
+                offset,
+                ..
+            } => match self
+                .router
+                .for_session(&session_id)
+                .and_then(|db| db.list_messages(&session_id, limit, offset))
+            {
+                Ok(messages) => Response::QueryResult {
+                    data: serde_json::to_value(messages).unwrap_or_default(),
+                },
+                Err(e) => {
+                    tracing::error!("Failed to list messages: {}", e);
+                    Response::Error {
+                        code: 500,
+                        message: format!("Failed to list messages: {}", e),
+                    }
+                }
+            },
+            QueryType::ListTurns {
+                session_id,
+                limit,
+                offset,
+            } => match self
+                .router
+                .for_session(&session_id)
+                .and_then(|db| db.list_turns(&session_id, limit, offset))
+            {
+                Ok(turns) => Response::QueryResult {
+                    data: serde_json::to_value(turns).unwrap_or_default(),
+                },
+                Err(e) => {
+                    tracing::error!("Failed to list turns: {}", e);
+                    Response::Error {
+                        code: 500,
+                        message: format!("Failed to list turns: {}", e),
+                    }
+                }
+            },
+            QueryType::TurnMessages {
+                session_id,
+                turn_index,
+            } => match self
+                .router
+                .for_session(&session_id)
+                .and_then(|db| db.get_turn_messages(&session_id, turn_index))
+            {
+                Ok(messages) => Response::QueryResult {
+                    data: serde_json::to_value(messages).unwrap_or_default(),
+                },
+                Err(e) => {
+                    tracing::error!("Failed to get turn messages: {}", e);
+                    Response::Error {
+                        code: 500,
+                        message: format!("Failed to get turn messages: {}", e),
+                    }
+                }
+            },
+            // 消息 ID 只在单个数据库内唯一
+            QueryType::MessagesByIds { .. } if self.router.is_routed() => {
+                routed_unsupported("Message ID lookups")
+            }
+            QueryType::MessagesByIds { ids } => match self.db.get_messages_by_ids(&ids) {
+                Ok(messages) => {
+                    let messages: Vec<_> = messages
+                        .into_iter()
+                        .map(|m| crate::types::Message { raw: None, ..m })
+                        .collect();
+                    Response::QueryResult {
+                        data: serde_json::to_value(messages).unwrap_or_default(),
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("Failed to get messages by ids: {}", e);
+                    Response::Error {
+                        code: 500,
+                        message: format!("Failed to get messages by ids: {}", e),
+                    }
+                }
+            },
             QueryType::SyncStatus => {
                 let paused = self.sync_worker.is_paused();
                 let running = self.sync_worker.is_running();
@@ -236,12 +1168,168 @@ impl Handler {
                     }),
                 }
             }
+            QueryType::TalkSummaries {
+                session_id,
+                limit,
+                offset,
+            } => {
+                let limit = limit.unwrap_or(MAX_TALK_SUMMARIES_LIMIT);
+                match self
+                    .router
+                    .for_session(&session_id)
+                    .and_then(|db| db.get_talk_summaries(&session_id, limit, offset))
+                {
+                    Ok(talks) => Response::QueryResult {
+                        data: serde_json::to_value(talks).unwrap_or_default(),
+                    },
+                    Err(e) => {
+                        tracing::error!("Failed to get talk summaries: {}", e);
+                        Response::Error {
+                            code: 500,
+                            message: format!("Failed to get talk summaries: {}", e),
+                        }
+                    }
+                }
+            }
+            QueryType::TalkSummary {
+                session_id,
+                talk_id,
+            } => match self
+                .router
+                .for_session(&session_id)
+                .and_then(|db| db.get_talk_summary(&session_id, &talk_id))
+            {
+                Ok(talk) => Response::QueryResult {
+                    data: serde_json::to_value(talk).unwrap_or_default(),
+                },
+                Err(e) => {
+                    tracing::error!("Failed to get talk summary: {}", e);
+                    Response::Error {
+                        code: 500,
+                        message: format!("Failed to get talk summary: {}", e),
+                    }
+                }
+            },
+            QueryType::SessionActivity { session_ids } => Response::QueryResult {
+                data: serde_json::to_value(self.watcher.session_activity(&session_ids))
+                    .unwrap_or_default(),
+            },
+            QueryType::Search {
+                keyword,
+                limit,
+                options,
+                max_per_project,
+                page_token,
+                paged: true,
+            } => page_response(
+                "search",
+                self.db.search_fts_page(
+                    &keyword,
+                    limit,
+                    &search_options(options),
+                    max_per_project,
+                    page_token.as_ref(),
+                ),
+            ),
+            QueryType::Search {
+                keyword,
+                limit,
+                options,
+                max_per_project,
+                ..
+            } if self.router.is_routed() => {
+                match self.router.search_fts_filtered(
+                    &keyword,
+                    limit,
+                    &search_options(options),
+                    max_per_project,
+                ) {
+                    Ok(results) => Response::QueryResult {
+                        data: serde_json::to_value(results).unwrap_or_default(),
+                    },
+                    Err(e) => {
+                        tracing::error!("Failed to search: {}", e);
+                        Response::Error {
+                            code: 500,
+                            message: format!("Failed to search: {}", e),
+                        }
+                    }
+                }
+            }
+            QueryType::Search {
+                keyword,
+                limit,
+                options,
+                max_per_project,
+                ..
+            } => {
+                match self.db.search_fts_filtered(
+                    &keyword,
+                    limit,
+                    &search_options(options),
+                    max_per_project,
+                ) {
+                    Ok(results) => Response::QueryResult {
+                        data: serde_json::to_value(results).unwrap_or_default(),
+                    },
+                    Err(e) => {
+                        tracing::error!("Failed to search: {}", e);
+                        Response::Error {
+                            code: 500,
+                            message: format!("Failed to search: {}", e),
+                        }
+                    }
+                }
+            }
+            QueryType::SearchCount { keyword, options } => {
+                let total = if self.router.is_routed() {
+                    self.router.search_count(&keyword, &options)
+                } else {
+                    self.db.search_count(&keyword, &options)
+                };
+                match total {
+                    Ok(total) => Response::QueryResult {
+                        data: serde_json::json!({ "total": total }),
+                    },
+                    Err(e) => {
+                        tracing::error!("Failed to count search results: {}", e);
+                        Response::Error {
+                            code: 500,
+                            message: format!("Failed to count search results: {}", e),
+                        }
+                    }
+                }
+            }
+            QueryType::SearchGrouped {
+                keyword,
+                session_limit,
+                per_session_limit,
+                options,
+            } => {
+                match self.db.search_fts_grouped(
+                    &keyword,
+                    session_limit,
+                    per_session_limit,
+                    &options,
+                ) {
+                    Ok(groups) => Response::QueryResult {
+                        data: serde_json::to_value(groups).unwrap_or_default(),
+                    },
+                    Err(e) => {
+                        tracing::error!("Failed to search grouped: {}", e);
+                        Response::Error {
+                            code: 500,
+                            message: format!("Failed to search grouped: {}", e),
+                        }
+                    }
+                }
+            }
         }
     }
 
     /// 处理 Hook 事件
     ///
-    /// 如果有 transcript_path，触发即时 Collection
+    /// 如果有 transcript_path，触发即时 Collection；SessionEnd 事件推送 `Push::SessionEnded`
     async fn handle_hook_event(&self, event: HookEvent) -> Response {
         tracing::debug!(
             "🪝 HookEvent: type={}, session_id={}",
@@ -265,6 +1353,10 @@ impl Handler {
             }
         }
 
+        if event.event_type == hook_event_type::SESSION_END {
+            self.watcher.end_session(&event.session_id);
+        }
+
         Response::Ok
     }
 }
@@ -0,0 +1,209 @@
+//! 数据库完整性巡检
+//!
+//! 定期（或写入遇到损坏错误时）执行 `quick_check`，结果不是 ok 时向所有连接推送
+//! `Push::IntegrityWarning`，让 UI 提示用户修复，而不是让后续写入静默失败。
+//! 同时抽样检查数据一致性不变量（`SessionDB::check_global_invariants`），最近一次的结果
+//! 见 Status 的 `invariants`。
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use rusqlite::ErrorCode;
+use tokio::sync::Notify;
+
+use super::broadcaster::ConnectionManager;
+use crate::protocol::Push;
+use crate::{IntegrityCheckResult, InvariantReport, SessionDB};
+
+/// 每次巡检抽样检查不变量的会话数
+const INVARIANT_SAMPLE_SIZE: usize = 200;
+
+/// 完整性巡检
+pub struct IntegrityMonitor {
+    db: Arc<SessionDB>,
+    connections: Arc<ConnectionManager>,
+    /// 写入遇到损坏错误时唤醒巡检
+    wake: Notify,
+    /// 最近一次不变量检查的结果
+    invariants: Mutex<Option<InvariantReport>>,
+}
+
+impl IntegrityMonitor {
+    pub fn new(db: Arc<SessionDB>, connections: Arc<ConnectionManager>) -> Arc<Self> {
+        Arc::new(Self {
+            db,
+            connections,
+            wake: Notify::new(),
+            invariants: Mutex::new(None),
+        })
+    }
+
+    /// 最近一次不变量检查的结果（尚未检查时为 None）
+    pub fn invariant_report(&self) -> Option<InvariantReport> {
+        self.invariants.lock().clone()
+    }
+
+    /// 错误链中有 SQLite 损坏错误时，尽快执行一次检查（多次请求合并为一次）
+    pub fn check_on_error(&self, e: &anyhow::Error) {
+        if is_corruption_error(e) {
+            self.wake.notify_one();
+        }
+    }
+
+    /// 按 `interval` 定期检查，直到任务被取消
+    pub async fn run(&self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        // 第一次 tick 立即返回：启动时跳过，避免与启动扫描争用数据库
+        ticker.tick().await;
+
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = self.wake.notified() => {}
+            }
+            self.check().await;
+        }
+    }
+
+    /// 执行一次 quick_check（损坏时推送警告）和抽样的不变量检查
+    async fn check(&self) {
+        let db = self.db.clone();
+        let result = tokio::task::spawn_blocking(move || db.quick_check()).await;
+        match result {
+            Ok(Ok(result)) => {
+                self.report(result);
+            }
+            Ok(Err(e)) => tracing::warn!("Integrity check failed to run: {}", e),
+            Err(e) => tracing::warn!("Integrity check task failed: {}", e),
+        }
+
+        let db = self.db.clone();
+        let result =
+            tokio::task::spawn_blocking(move || db.check_global_invariants(INVARIANT_SAMPLE_SIZE))
+                .await;
+        match result {
+            Ok(Ok(report)) => self.record_invariants(report),
+            Ok(Err(e)) => tracing::warn!("Invariant check failed to run: {}", e),
+            Err(e) => tracing::warn!("Invariant check task failed: {}", e),
+        }
+    }
+
+    /// 记录不变量检查结果，违反的逐条记录日志
+    fn record_invariants(&self, report: InvariantReport) {
+        for violation in &report.violations {
+            tracing::warn!("🩺 Invariant violated: {}", violation);
+        }
+        *self.invariants.lock() = Some(report);
+    }
+
+    /// 上报检查结果，损坏时广播 IntegrityWarning，返回是否已推送
+    fn report(&self, result: IntegrityCheckResult) -> bool {
+        let IntegrityCheckResult::Corrupted(detail) = result else {
+            return false;
+        };
+        tracing::error!("🩺 Database integrity check failed: {}", detail);
+
+        let push = Push::IntegrityWarning {
+            detail,
+            change_counter: self.db.change_counter().unwrap_or_default(),
+        };
+        self.connections.broadcast_push(&push);
+        true
+    }
+}
+
+/// 错误链中是否有 SQLite 报告的数据库损坏
+fn is_corruption_error(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        cause
+            .downcast_ref::<rusqlite::Error>()
+            .and_then(|e| e.sqlite_error_code())
+            .is_some_and(|code| {
+                matches!(code, ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase)
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DbConfig;
+    use tokio::sync::mpsc;
+
+    fn setup() -> (
+        Arc<IntegrityMonitor>,
+        mpsc::Receiver<String>,
+        tempfile::TempDir,
+    ) {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let db = SessionDB::connect(DbConfig::local(tmp.path().join("test.db"))).unwrap();
+        let connections = ConnectionManager::new();
+        let (tx, rx) = mpsc::channel(10);
+        connections.register(tx);
+        (IntegrityMonitor::new(Arc::new(db), connections), rx, tmp)
+    }
+
+    #[test]
+    fn test_corrupted_result_pushes_warning() {
+        let (monitor, mut rx, _tmp) = setup();
+
+        let detail = "*** in database main ***\nPage 42: btreeInitPage() returns error code 11";
+        assert!(monitor.report(IntegrityCheckResult::Corrupted(detail.to_string())));
+
+        let line = rx.try_recv().unwrap();
+        match serde_json::from_str::<Push>(line.trim_end()).unwrap() {
+            Push::IntegrityWarning { detail: pushed, .. } => assert_eq!(pushed, detail),
+            other => panic!("Expected IntegrityWarning, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_ok_result_pushes_nothing() {
+        let (monitor, mut rx, _tmp) = setup();
+
+        assert!(!monitor.report(IntegrityCheckResult::Ok));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_invariant_report_is_recorded() {
+        let (monitor, _rx, _tmp) = setup();
+        assert!(monitor.invariant_report().is_none());
+
+        let project_id = monitor
+            .db
+            .get_or_create_project("project-x", "/path/project-x", "claude")
+            .unwrap();
+        monitor.db.upsert_session("session-1", project_id).unwrap();
+        monitor
+            .db
+            .conn
+            .lock()
+            .execute("UPDATE sessions SET message_count = 3", [])
+            .unwrap();
+
+        let report = monitor.db.check_global_invariants(10).unwrap();
+        monitor.record_invariants(report);
+        let report = monitor.invariant_report().unwrap();
+        assert_eq!(report.sessions_checked, 1);
+        assert_eq!(report.violations.len(), 1);
+        assert_eq!(report.violations[0].actual, 3);
+    }
+
+    #[test]
+    fn test_is_corruption_error() {
+        let corrupt = rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CORRUPT),
+            Some("database disk image is malformed".to_string()),
+        );
+        let e = anyhow::Error::from(crate::Error::from(corrupt)).context("Failed to insert");
+        assert!(is_corruption_error(&e));
+
+        let busy = rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+            None,
+        );
+        assert!(!is_corruption_error(&anyhow::Error::from(busy)));
+    }
+}
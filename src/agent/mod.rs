@@ -5,11 +5,23 @@
 //! - 执行 Collection（解析 JSONL → 写入 DB）
 //! - 接收业务写入请求（index 结果、approve 结果）
 
+#[cfg(unix)]
+mod activation;
+mod activity;
+mod approvals;
 mod broadcaster;
 mod handler;
+mod integrity;
+mod replication;
+mod resources;
 mod server;
+mod status_file;
+mod waiter;
+mod wake;
 mod watcher;
 
 // Re-export protocol types from crate root
 pub use crate::protocol::{Request, Response};
+#[cfg(unix)]
+pub use activation::{inherited_listener_fd, notify_ready, LAUNCHD_SOCKET_NAME};
 pub use server::{Agent, AgentConfig, cleanup_stale_agent, is_agent_running};
@@ -0,0 +1,261 @@
+//! 消息复制任务
+//!
+//! 把新消息投递到 `ReplicationSink`（见 `crate::replication`）。数据库变更通知
+//! （与推送、WaitForChange 同一条事件路径）唤醒复制，另按 `replication_interval_secs`
+//! 定期检查。投递失败时指数退避（`INITIAL_BACKOFF` 起翻倍，最多 `MAX_BACKOFF`），
+//! 游标未推进，恢复后从失败的那一批继续。
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use super::activity::now_ms;
+use super::broadcaster::ConnectionManager;
+use crate::replication::{ReplicationLag, ReplicationSink};
+use crate::SessionDB;
+
+/// 第一次失败后的重试间隔
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// 重试间隔上限
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// 复制状态（Status 的 `replication`）
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReplicationState {
+    /// Agent 启动以来投递的记录数
+    pub delivered: u64,
+    /// 最近一次成功投递的时间（毫秒）
+    pub last_delivered_at: Option<i64>,
+    /// 连续失败次数（成功后清零）
+    pub consecutive_failures: u32,
+    /// 最近一次失败的原因（成功后清除）
+    pub last_error: Option<String>,
+}
+
+/// 消息复制
+pub struct Replicator {
+    /// 所有数据库（按来源拆分时各有游标）
+    dbs: Vec<Arc<SessionDB>>,
+    sink: Arc<dyn ReplicationSink>,
+    batch_size: usize,
+    state: Mutex<ReplicationState>,
+}
+
+impl Replicator {
+    pub fn new(
+        dbs: Vec<Arc<SessionDB>>,
+        sink: Arc<dyn ReplicationSink>,
+        batch_size: usize,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            dbs,
+            sink,
+            batch_size: batch_size.max(1),
+            state: Mutex::new(ReplicationState::default()),
+        })
+    }
+
+    /// 复制状态和各数据库的复制进度（`lag` 为合计）
+    pub fn status(&self) -> serde_json::Value {
+        let lags: Vec<ReplicationLag> = self
+            .dbs
+            .iter()
+            .filter_map(|db| db.replication_lag().ok())
+            .collect();
+        serde_json::json!({
+            "state": *self.state.lock(),
+            "lag": lags.iter().map(|l| l.lag).sum::<i64>(),
+            "databases": lags,
+        })
+    }
+
+    /// 有变更或每隔 `interval` 复制一次，失败时退避，直到任务被取消
+    pub async fn run(&self, connections: Arc<ConnectionManager>, interval: Duration) {
+        // 先订阅再复制：复制期间写入的消息会唤醒下一轮
+        let mut changes = connections.subscribe_changes();
+        loop {
+            if let Err(e) = self.replicate().await {
+                let failures = self.record_failure(e);
+                let delay = backoff_delay(failures);
+                tracing::warn!(
+                    "Replication failed ({} in a row), retrying in {:?}",
+                    failures,
+                    delay
+                );
+                tokio::time::sleep(delay).await;
+                continue;
+            }
+
+            tokio::select! {
+                result = changes.recv() => {
+                    if let Err(RecvError::Closed) = result {
+                        tokio::time::sleep(interval).await;
+                    }
+                }
+                _ = tokio::time::sleep(interval) => {}
+            }
+        }
+    }
+
+    /// 投递所有数据库中游标之后的消息，直到追上或失败
+    async fn replicate(&self) -> crate::Result<()> {
+        for db in &self.dbs {
+            loop {
+                let db = db.clone();
+                let sink = self.sink.clone();
+                let batch_size = self.batch_size;
+                let delivered = tokio::task::spawn_blocking(move || {
+                    db.replicate_batch(sink.as_ref(), batch_size)
+                })
+                .await
+                .map_err(|e| crate::Error::Other(e.into()))??;
+                if delivered > 0 {
+                    self.record_success(delivered);
+                }
+                if delivered < self.batch_size {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn record_success(&self, delivered: usize) {
+        let mut state = self.state.lock();
+        state.delivered += delivered as u64;
+        state.last_delivered_at = Some(now_ms());
+        state.consecutive_failures = 0;
+        state.last_error = None;
+    }
+
+    /// 记录失败，返回连续失败次数
+    fn record_failure(&self, e: crate::Error) -> u32 {
+        let mut state = self.state.lock();
+        state.consecutive_failures += 1;
+        state.last_error = Some(e.to_string());
+        state.consecutive_failures
+    }
+}
+
+/// 连续第 `failures` 次失败后的重试间隔
+fn backoff_delay(failures: u32) -> Duration {
+    let exponent = failures.saturating_sub(1).min(16);
+    INITIAL_BACKOFF
+        .saturating_mul(1 << exponent)
+        .min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replication::ReplicationRecord;
+    use crate::{DbConfig, MessageInput, MessageType};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// 内存中的目标：前 `fail_times` 次投递失败
+    #[derive(Default)]
+    struct FlakySink {
+        fail_times: AtomicUsize,
+        attempts: AtomicUsize,
+        received: Mutex<Vec<String>>,
+    }
+
+    impl ReplicationSink for FlakySink {
+        fn deliver(&self, batch: &[ReplicationRecord]) -> crate::Result<()> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            let remaining = self.fail_times.load(Ordering::SeqCst);
+            if remaining > 0 {
+                self.fail_times.store(remaining - 1, Ordering::SeqCst);
+                return Err(crate::Error::Connection("sink unavailable".to_string()));
+            }
+            let mut received = self.received.lock();
+            received.extend(batch.iter().map(|r| r.message.uuid.clone()));
+            Ok(())
+        }
+    }
+
+    fn insert(db: &SessionDB, range: std::ops::Range<usize>) {
+        let messages: Vec<MessageInput> = range
+            .map(|i| MessageInput {
+                uuid: format!("m-{}", i),
+                r#type: MessageType::User,
+                content_text: format!("message {}", i),
+                content_full: format!("message {}", i),
+                timestamp: i as i64,
+                sequence: i as i64,
+                source: None,
+                channel: None,
+                model: None,
+                tool_call_id: None,
+                tool_name: None,
+                tool_args: None,
+                raw: None,
+                approval_status: None,
+                approval_resolved_at: None,
+            })
+            .collect();
+        db.insert_messages("session-1", &messages).unwrap();
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        assert_eq!(backoff_delay(1), Duration::from_secs(1));
+        assert_eq!(backoff_delay(2), Duration::from_secs(2));
+        assert_eq!(backoff_delay(4), Duration::from_secs(8));
+        assert_eq!(backoff_delay(20), MAX_BACKOFF);
+        assert_eq!(backoff_delay(u32::MAX), MAX_BACKOFF);
+    }
+
+    /// 等待累计投递 `count` 条记录
+    async fn wait_delivered(replicator: &Replicator, count: u64) {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while replicator.state.lock().delivered < count {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("records should be delivered");
+    }
+
+    #[tokio::test]
+    async fn test_recovers_after_sink_failures() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let db = Arc::new(SessionDB::connect(DbConfig::local(tmp.path().join("test.db"))).unwrap());
+        let project_id = db.get_or_create_project("p", "/p", "claude").unwrap();
+        db.upsert_session("session-1", project_id).unwrap();
+        insert(&db, 0..5);
+
+        let sink = Arc::new(FlakySink {
+            fail_times: AtomicUsize::new(2),
+            ..Default::default()
+        });
+        let replicator = Replicator::new(vec![db.clone()], sink.clone(), 2);
+        let connections = ConnectionManager::new();
+        connections.observe(&db);
+
+        let task = tokio::spawn({
+            let replicator = replicator.clone();
+            let connections = connections.clone();
+            async move { replicator.run(connections, Duration::from_secs(60)).await }
+        });
+
+        // 两次失败（退避 1s、2s）后投递全部 5 条
+        wait_delivered(&replicator, 5).await;
+        assert_eq!(sink.attempts.load(Ordering::SeqCst), 2 + 3);
+        assert_eq!(db.replication_cursor().unwrap(), 5);
+        assert_eq!(replicator.state.lock().consecutive_failures, 0);
+
+        // 新消息由变更通知唤醒复制，不必等待 interval
+        insert(&db, 5..6);
+        wait_delivered(&replicator, 6).await;
+        let expected: Vec<String> = (0..6).map(|i| format!("m-{}", i)).collect();
+        assert_eq!(*sink.received.lock(), expected);
+        assert_eq!(replicator.status()["lag"], 0);
+
+        task.abort();
+    }
+}
@@ -0,0 +1,452 @@
+//! Agent 自身资源占用监控
+//!
+//! 每 `SAMPLE_INTERVAL` 采样一次进程 RSS 和打开的文件描述符数，当前值和峰值通过 Status 返回。
+//!
+//! 配置了内存软上限（`AgentConfig::max_rss_mb`）时，超过上限的第一次采样依次执行防御动作：
+//! 释放 SQLite 读缓存（`shrink_memory`）→ WAL checkpoint → 缩小页缓存（`cache_size`），
+//! 每个动作执行后重新采样，效果记录到日志和指标中。
+//! 连续 `ESCALATE_AFTER_SAMPLES` 次采样仍超过上限时记录错误日志；硬上限模式
+//! （`AgentConfig::memory_hard_limit`）下先 checkpoint 再请求 Agent 正常退出，由客户端或
+//! 服务管理器重新启动，而不是等系统在事务中途 OOM kill。
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::sync::Notify;
+
+use super::activity::now_ms;
+use crate::SessionDB;
+
+/// 采样间隔
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 连续超过上限的采样次数达到该值时升级（错误日志 / 硬上限退出）
+pub const ESCALATE_AFTER_SAMPLES: u32 = 2;
+
+/// 缩小后的 SQLite 页缓存（KiB，SQLite 默认约 2000）
+pub const SHRUNK_CACHE_KIB: u32 = 512;
+
+/// 指标中保留的最近防御动作数
+const RECENT_ACTIONS: usize = 20;
+
+/// 一次资源采样
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ResourceSample {
+    pub rss_bytes: u64,
+    /// 打开的文件描述符数（不支持的平台为 None）
+    pub open_fds: Option<u64>,
+}
+
+/// 内存超限时的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DefenseAction {
+    /// 释放 SQLite 读缓存
+    DropCaches,
+    /// WAL checkpoint
+    Checkpoint,
+    /// 缩小 SQLite 页缓存
+    ShrinkCache,
+    /// 防御动作之后仍超过上限（错误日志）
+    CeilingExceeded,
+    /// 硬上限模式下请求退出
+    Exit,
+}
+
+/// 一次防御动作及其效果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DefenseRecord {
+    pub action: DefenseAction,
+    pub at_ms: i64,
+    pub rss_before: u64,
+    pub rss_after: u64,
+    /// 动作执行失败时的错误
+    pub error: Option<String>,
+}
+
+/// 资源指标（`QueryType::Status` 的 `resources`）
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResourceMetrics {
+    pub current: Option<ResourceSample>,
+    pub peak_rss_bytes: u64,
+    pub peak_open_fds: Option<u64>,
+    /// 内存软上限（未配置为 None）
+    pub max_rss_bytes: Option<u64>,
+    /// 连续超过上限的采样次数
+    pub samples_over_ceiling: u32,
+    /// 各动作的累计次数
+    pub action_counts: BTreeMap<DefenseAction, u64>,
+    /// 最近的防御动作（旧的在前）
+    pub recent_actions: VecDeque<DefenseRecord>,
+}
+
+type Sampler = Box<dyn Fn() -> Option<ResourceSample> + Send + Sync>;
+
+/// 资源监控
+pub struct ResourceMonitor {
+    /// 防御动作作用的数据库（按来源拆分时为全部数据库）
+    dbs: Vec<Arc<SessionDB>>,
+    max_rss_bytes: Option<u64>,
+    hard_limit: bool,
+    sampler: Sampler,
+    metrics: Mutex<ResourceMetrics>,
+    /// 硬上限模式下请求 Agent 退出
+    exit: Notify,
+}
+
+impl ResourceMonitor {
+    pub fn new(dbs: Vec<Arc<SessionDB>>, max_rss_mb: Option<u64>, hard_limit: bool) -> Arc<Self> {
+        Self::with_sampler(dbs, max_rss_mb, hard_limit, Box::new(sample_self))
+    }
+
+    fn with_sampler(
+        dbs: Vec<Arc<SessionDB>>,
+        max_rss_mb: Option<u64>,
+        hard_limit: bool,
+        sampler: Sampler,
+    ) -> Arc<Self> {
+        let max_rss_bytes = max_rss_mb.map(|mb| mb * 1024 * 1024);
+        Arc::new(Self {
+            dbs,
+            max_rss_bytes,
+            hard_limit,
+            sampler,
+            metrics: Mutex::new(ResourceMetrics {
+                max_rss_bytes,
+                ..Default::default()
+            }),
+            exit: Notify::new(),
+        })
+    }
+
+    /// 当前指标
+    pub fn metrics(&self) -> ResourceMetrics {
+        self.metrics.lock().clone()
+    }
+
+    /// 按 `interval` 定期采样，直到任务被取消
+    pub async fn run(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let monitor = self.clone();
+            // 防御动作会访问数据库，不占用异步线程
+            if let Err(e) = tokio::task::spawn_blocking(move || monitor.observe()).await {
+                tracing::warn!("Resource sampling task failed: {}", e);
+            }
+        }
+    }
+
+    /// 等待硬上限模式下的退出请求
+    pub async fn exit_requested(&self) {
+        self.exit.notified().await
+    }
+
+    /// 采样一次并处理，返回是否请求退出
+    fn observe(&self) -> bool {
+        match (self.sampler)() {
+            Some(sample) => self.handle_sample(sample),
+            None => false,
+        }
+    }
+
+    /// 记录采样，超过上限时执行防御动作，返回是否请求退出
+    fn handle_sample(&self, sample: ResourceSample) -> bool {
+        let over = {
+            let mut metrics = self.metrics.lock();
+            metrics.current = Some(sample);
+            metrics.peak_rss_bytes = metrics.peak_rss_bytes.max(sample.rss_bytes);
+            if let Some(fds) = sample.open_fds {
+                metrics.peak_open_fds = Some(metrics.peak_open_fds.unwrap_or(0).max(fds));
+            }
+
+            match self.max_rss_bytes {
+                Some(ceiling) if sample.rss_bytes > ceiling => {
+                    metrics.samples_over_ceiling += 1;
+                    metrics.samples_over_ceiling
+                }
+                _ => {
+                    if metrics.samples_over_ceiling > 0 {
+                        tracing::info!("🧠 RSS back under ceiling: {} MB", mb(sample.rss_bytes));
+                    }
+                    metrics.samples_over_ceiling = 0;
+                    return false;
+                }
+            }
+        };
+
+        if over == 1 {
+            tracing::warn!(
+                "🧠 RSS {} MB exceeds ceiling {} MB, releasing memory",
+                mb(sample.rss_bytes),
+                mb(self.max_rss_bytes.unwrap_or_default())
+            );
+            let mut rss = sample.rss_bytes;
+            for action in [
+                DefenseAction::DropCaches,
+                DefenseAction::Checkpoint,
+                DefenseAction::ShrinkCache,
+            ] {
+                rss = self.perform(action, rss);
+            }
+            return false;
+        }
+        if over != ESCALATE_AFTER_SAMPLES {
+            return false;
+        }
+
+        tracing::error!(
+            "🚨 RSS still {} MB after releasing memory ({} consecutive samples over the {} MB ceiling){}",
+            mb(sample.rss_bytes),
+            over,
+            mb(self.max_rss_bytes.unwrap_or_default()),
+            if self.hard_limit { ", exiting" } else { "" }
+        );
+        self.perform(DefenseAction::CeilingExceeded, sample.rss_bytes);
+        if !self.hard_limit {
+            return false;
+        }
+        // 退出前把 WAL 合并回主库
+        let rss = self.perform(DefenseAction::Checkpoint, sample.rss_bytes);
+        self.perform(DefenseAction::Exit, rss);
+        self.exit.notify_one();
+        true
+    }
+
+    /// 执行动作并重新采样，记录效果，返回动作后的 RSS
+    fn perform(&self, action: DefenseAction, rss_before: u64) -> u64 {
+        let result = match action {
+            DefenseAction::DropCaches => self.each_db(|db| db.release_memory()),
+            DefenseAction::Checkpoint => self.each_db(|db| db.checkpoint()),
+            DefenseAction::ShrinkCache => {
+                self.each_db(|db| db.set_cache_size_kib(SHRUNK_CACHE_KIB))
+            }
+            DefenseAction::CeilingExceeded | DefenseAction::Exit => Ok(()),
+        };
+        let rss_after = match action {
+            DefenseAction::CeilingExceeded | DefenseAction::Exit => rss_before,
+            _ => (self.sampler)().map_or(rss_before, |s| s.rss_bytes),
+        };
+        let error = result.err().map(|e| e.to_string());
+        match &error {
+            Some(e) => tracing::warn!("🧠 {:?} failed: {}", action, e),
+            None => tracing::info!(
+                "🧠 {:?}: RSS {} MB -> {} MB",
+                action,
+                mb(rss_before),
+                mb(rss_after)
+            ),
+        }
+
+        let mut metrics = self.metrics.lock();
+        *metrics.action_counts.entry(action).or_default() += 1;
+        if metrics.recent_actions.len() == RECENT_ACTIONS {
+            metrics.recent_actions.pop_front();
+        }
+        metrics.recent_actions.push_back(DefenseRecord {
+            action,
+            at_ms: now_ms(),
+            rss_before,
+            rss_after,
+            error,
+        });
+        rss_after
+    }
+
+    /// 对每个数据库执行，返回第一个错误（不中断其余数据库）
+    fn each_db(&self, f: impl Fn(&SessionDB) -> crate::Result<()>) -> crate::Result<()> {
+        let mut first_error = None;
+        for db in &self.dbs {
+            if let Err(e) = f(db) {
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+}
+
+fn mb(bytes: u64) -> u64 {
+    bytes / 1024 / 1024
+}
+
+/// 采样当前进程
+fn sample_self() -> Option<ResourceSample> {
+    use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+    let pid = Pid::from_u32(std::process::id());
+    let mut sys = System::new();
+    sys.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[pid]),
+        true,
+        ProcessRefreshKind::new().with_memory(),
+    );
+    Some(ResourceSample {
+        rss_bytes: sys.process(pid)?.memory(),
+        open_fds: open_fd_count(),
+    })
+}
+
+#[cfg(unix)]
+fn open_fd_count() -> Option<u64> {
+    // 读取目录本身占用一个描述符
+    let entries = std::fs::read_dir("/dev/fd").ok()?;
+    Some((entries.count() as u64).saturating_sub(1))
+}
+
+#[cfg(not(unix))]
+fn open_fd_count() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DbConfig;
+
+    const MB: u64 = 1024 * 1024;
+
+    /// 按顺序返回给定 RSS 读数（用完后重复最后一个）
+    fn fake_sampler(readings: &[u64]) -> Sampler {
+        let readings = Mutex::new(readings.iter().map(|r| r * MB).collect::<VecDeque<_>>());
+        Box::new(move || {
+            let mut readings = readings.lock();
+            let rss_bytes = if readings.len() > 1 {
+                readings.pop_front()
+            } else {
+                readings.front().copied()
+            }?;
+            Some(ResourceSample {
+                rss_bytes,
+                open_fds: Some(10),
+            })
+        })
+    }
+
+    fn setup(hard_limit: bool, readings: &[u64]) -> (Arc<ResourceMonitor>, tempfile::TempDir) {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let db = SessionDB::connect(DbConfig::local(tmp.path().join("test.db"))).unwrap();
+        let monitor = ResourceMonitor::with_sampler(
+            vec![Arc::new(db)],
+            Some(100),
+            hard_limit,
+            fake_sampler(readings),
+        );
+        (monitor, tmp)
+    }
+
+    fn actions(monitor: &ResourceMonitor) -> Vec<DefenseAction> {
+        monitor
+            .metrics()
+            .recent_actions
+            .iter()
+            .map(|r| r.action)
+            .collect()
+    }
+
+    #[test]
+    fn test_escalation_sequence() {
+        // 读数依次用于：第 1 次采样、三个动作后的重新采样
+        let (monitor, _tmp) = setup(false, &[140, 130, 120]);
+
+        assert!(!monitor.observe());
+        assert_eq!(
+            actions(&monitor),
+            vec![
+                DefenseAction::DropCaches,
+                DefenseAction::Checkpoint,
+                DefenseAction::ShrinkCache
+            ]
+        );
+        let records = monitor.metrics().recent_actions;
+        assert_eq!(records[0].rss_after, 130 * MB);
+        assert_eq!(records[2].rss_after, 120 * MB);
+        assert!(records.iter().all(|r| r.error.is_none()));
+
+        // 仍超过上限：只记录错误，软上限不退出
+        assert!(!monitor.handle_sample(ResourceSample {
+            rss_bytes: 120 * MB,
+            open_fds: None,
+        }));
+        assert_eq!(
+            actions(&monitor).last(),
+            Some(&DefenseAction::CeilingExceeded)
+        );
+        assert!(!monitor.handle_sample(ResourceSample {
+            rss_bytes: 120 * MB,
+            open_fds: None,
+        }));
+        assert_eq!(actions(&monitor).len(), 4);
+
+        // 回落后重置，再次超限时重新执行防御动作
+        assert!(!monitor.handle_sample(ResourceSample {
+            rss_bytes: 50 * MB,
+            open_fds: None,
+        }));
+        let metrics = monitor.metrics();
+        assert_eq!(metrics.samples_over_ceiling, 0);
+        assert_eq!(metrics.peak_rss_bytes, 140 * MB);
+        assert_eq!(metrics.current.unwrap().rss_bytes, 50 * MB);
+        assert!(!monitor.handle_sample(ResourceSample {
+            rss_bytes: 150 * MB,
+            open_fds: None,
+        }));
+        let counts = monitor.metrics().action_counts;
+        assert_eq!(counts[&DefenseAction::DropCaches], 2);
+        assert_eq!(counts[&DefenseAction::CeilingExceeded], 1);
+    }
+
+    #[test]
+    fn test_hard_limit_checkpoints_before_exit() {
+        let (monitor, _tmp) = setup(true, &[150]);
+
+        assert!(!monitor.observe());
+        assert!(monitor.observe());
+        assert_eq!(
+            actions(&monitor)[3..],
+            [
+                DefenseAction::CeilingExceeded,
+                DefenseAction::Checkpoint,
+                DefenseAction::Exit
+            ]
+        );
+        assert_eq!(
+            monitor.metrics().action_counts[&DefenseAction::Checkpoint],
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hard_limit_requests_exit() {
+        let (monitor, _tmp) = setup(true, &[150]);
+        monitor.observe();
+        monitor.observe();
+        tokio::time::timeout(Duration::from_secs(1), monitor.exit_requested())
+            .await
+            .expect("exit should be requested");
+    }
+
+    #[test]
+    fn test_no_ceiling_only_tracks_peaks() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let db = SessionDB::connect(DbConfig::local(tmp.path().join("test.db"))).unwrap();
+        let monitor =
+            ResourceMonitor::with_sampler(vec![Arc::new(db)], None, true, fake_sampler(&[500]));
+
+        assert!(!monitor.observe());
+        assert!(!monitor.observe());
+        let metrics = monitor.metrics();
+        assert_eq!(metrics.peak_rss_bytes, 500 * MB);
+        assert_eq!(metrics.peak_open_fds, Some(10));
+        assert!(metrics.recent_actions.is_empty());
+    }
+
+    #[test]
+    fn test_sample_self() {
+        let sample = sample_self().unwrap();
+        assert!(sample.rss_bytes > 0);
+        #[cfg(unix)]
+        assert!(sample.open_fds.unwrap() > 0);
+    }
+}
@@ -4,6 +4,7 @@
 //! - Unix: Unix Domain Socket
 //! - Windows: Named Pipe
 
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -11,27 +12,40 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{Context, Result};
-use interprocess::local_socket::{
-    tokio::{prelude::*, Stream},
-    ListenerOptions, Name,
-};
+use interprocess::local_socket::{tokio::prelude::*, ListenerOptions, Name};
 #[cfg(unix)]
 use interprocess::local_socket::GenericFilePath;
 #[cfg(windows)]
 use interprocess::local_socket::GenericNamespaced;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::sync::mpsc;
 use tokio::time::interval;
 
 #[cfg(unix)]
-use std::os::unix::fs::PermissionsExt;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
 
+use super::activity::DEFAULT_IDLE_AFTER;
+use super::approvals::ApprovalDigestMonitor;
 use super::broadcaster::ConnectionManager;
 use super::handler::Handler;
+use super::integrity::IntegrityMonitor;
+use super::replication::Replicator;
+use super::resources::{ResourceMonitor, SAMPLE_INTERVAL};
+use super::status_file::StatusFileWriter;
+use super::waiter::ChangeWaiters;
 use super::watcher::FileWatcher;
-use crate::protocol::{Request, Response};
+use crate::collector::collection_lock_holder;
+use crate::config::DEFAULT_WAL_AUTOCHECKPOINT_PAGES;
+use crate::config_file::ConfigFile;
+use crate::migrations::SchemaCompatibility;
+use crate::protocol::{collect_trigger, error_code, Request, Response};
+use crate::replication::{JsonlFileSink, ReplicationSink};
+use crate::router::DbRouter;
+use crate::search_log::DEFAULT_SEARCH_LOG_CAP;
 use crate::sync::SyncWorker;
-use crate::{DbConfig, SessionDB};
+use crate::{CollectLimits, CollectionFilter, DbConfig, SessionDB};
 
 /// Agent 配置
 #[derive(Debug, Clone)]
@@ -40,6 +54,56 @@ pub struct AgentConfig {
     pub data_dir: PathBuf,
     /// 空闲超时（秒）
     pub idle_timeout_secs: u64,
+    /// WaitForChange 最大并发等待数
+    pub max_waiters: usize,
+    /// 最大并发连接数（超过时新连接收到 TOO_MANY_CONNECTIONS 错误后被关闭）
+    pub max_connections: usize,
+    /// 采集过滤器（跳过噪声条目）
+    pub collection_filter: CollectionFilter,
+    /// 采集防护上限（文件大小、单条消息大小、单会话单次消息数）
+    pub collect_limits: CollectLimits,
+    /// 会话判定为 Streaming 的文件事件窗口（秒）
+    pub streaming_window_secs: u64,
+    /// 活跃过的会话超过该时长（秒）没有文件事件时进入 Idle 并推送 `Push::SessionIdle`
+    pub session_idle_secs: u64,
+    /// 数据库完整性巡检间隔（秒，0 表示不巡检）
+    ///
+    /// 写入遇到损坏错误时也会立即检查，发现损坏时推送 `Push::IntegrityWarning`。
+    pub integrity_check_interval_secs: u64,
+    /// 服务管理器预绑定的监听 socket fd（socket activation，仅 Unix）
+    ///
+    /// 设置后 Agent 直接在该 fd 上 accept，不再 bind，退出时也不删除 socket 文件。
+    pub listen_fd: Option<i32>,
+    /// 单条消息内容上限（字节，见 `DbConfig::max_content_bytes`），None 表示不限制
+    pub max_content_bytes: Option<usize>,
+    /// WAL 自动 checkpoint 阈值（页，见 `DbConfig::wal_autocheckpoint_pages`），None 表示默认值
+    pub wal_autocheckpoint_pages: Option<u32>,
+    /// 记录客户端的搜索（见 `DbConfig::log_searches`）
+    pub log_searches: bool,
+    /// 搜索记录保留的条数（见 `DbConfig::search_log_cap`）
+    pub search_log_cap: usize,
+    /// 按来源拆分数据库：来源名称（如 `codex`）→ 数据库文件，见 `DbRouter`
+    ///
+    /// 为空时所有来源写入 `db_path()`。
+    pub source_databases: BTreeMap<String, PathBuf>,
+    /// 内存软上限（MB，None 表示不限制），超过时释放缓存，见 `resources` 模块
+    pub max_rss_mb: Option<u64>,
+    /// 释放缓存后仍超过 `max_rss_mb` 时 checkpoint 并正常退出（由客户端重新启动）
+    pub memory_hard_limit: bool,
+    /// 待审批摘要检查间隔（秒，0 表示不检查），摘要变化时推送 `Push::ApprovalDigest`
+    pub approval_digest_interval_secs: u64,
+    /// 待审批摘要只统计已等待超过该时长（秒）的审批
+    pub approval_digest_min_age_secs: u64,
+    /// 状态文件（`status_file_path`）写入间隔（秒，0 表示不写入）
+    pub status_file_interval_secs: u64,
+    /// 把新消息复制到该 JSONL 文件（见 `crate::replication`），None 表示不复制
+    pub replication_file: Option<PathBuf>,
+    /// 把新消息 POST 到该 URL（需要 `replication-http` feature），同时设置时优先于 `replication_file`
+    pub replication_url: Option<String>,
+    /// 每批复制的消息数
+    pub replication_batch_size: usize,
+    /// 复制检查间隔（秒），数据库变更会提前唤醒
+    pub replication_interval_secs: u64,
 }
 
 impl Default for AgentConfig {
@@ -51,11 +115,185 @@ impl Default for AgentConfig {
         Self {
             data_dir,
             idle_timeout_secs: 30,
+            max_waiters: 32,
+            max_connections: 256,
+            collection_filter: CollectionFilter::default(),
+            collect_limits: CollectLimits::default(),
+            streaming_window_secs: 5,
+            session_idle_secs: DEFAULT_IDLE_AFTER.as_secs(),
+            integrity_check_interval_secs: 60 * 60,
+            listen_fd: None,
+            max_content_bytes: None,
+            wal_autocheckpoint_pages: None,
+            log_searches: false,
+            search_log_cap: DEFAULT_SEARCH_LOG_CAP,
+            source_databases: BTreeMap::new(),
+            max_rss_mb: None,
+            memory_hard_limit: false,
+            approval_digest_interval_secs: 60,
+            approval_digest_min_age_secs: 0,
+            status_file_interval_secs: 30,
+            replication_file: None,
+            replication_url: None,
+            replication_batch_size: 500,
+            replication_interval_secs: 30,
         }
     }
 }
 
 impl AgentConfig {
+    /// 默认配置叠加 `data_dir` 下的共享配置文件和环境变量（见 `config_file`）
+    pub fn load(data_dir: &Path) -> Self {
+        let mut config = Self {
+            data_dir: data_dir.to_path_buf(),
+            ..Default::default()
+        };
+        config.apply_config_file(&ConfigFile::load(data_dir));
+        config
+    }
+
+    /// 用配置文件中设置了的项覆盖当前值（`[agent]`、`[collector]`、`[db]`）
+    pub fn apply_config_file(&mut self, file: &ConfigFile) {
+        let agent = &file.agent;
+        if let Some(v) = agent.idle_timeout_secs {
+            self.idle_timeout_secs = v;
+        }
+        if let Some(v) = agent.max_waiters {
+            self.max_waiters = v;
+        }
+        if let Some(v) = agent.max_connections {
+            self.max_connections = v;
+        }
+        if let Some(v) = agent.streaming_window_secs {
+            self.streaming_window_secs = v;
+        }
+        if let Some(v) = agent.session_idle_secs {
+            self.session_idle_secs = v;
+        }
+        if let Some(v) = agent.integrity_check_interval_secs {
+            self.integrity_check_interval_secs = v;
+        }
+        if let Some(v) = agent.max_rss_mb {
+            self.max_rss_mb = Some(v);
+        }
+        if let Some(v) = agent.memory_hard_limit {
+            self.memory_hard_limit = v;
+        }
+        if let Some(v) = agent.approval_digest_interval_secs {
+            self.approval_digest_interval_secs = v;
+        }
+        if let Some(v) = agent.approval_digest_min_age_secs {
+            self.approval_digest_min_age_secs = v;
+        }
+        if let Some(v) = agent.status_file_interval_secs {
+            self.status_file_interval_secs = v;
+        }
+        if let Some(v) = &agent.replication_file {
+            self.replication_file = Some(v.clone());
+        }
+        if let Some(v) = &agent.replication_url {
+            self.replication_url = Some(v.clone());
+        }
+        if let Some(v) = agent.replication_batch_size {
+            self.replication_batch_size = v;
+        }
+        if let Some(v) = agent.replication_interval_secs {
+            self.replication_interval_secs = v;
+        }
+
+        let collector = &file.collector;
+        let filter = &mut self.collection_filter;
+        if let Some(v) = collector.skip_empty_messages {
+            filter.skip_empty_messages = v;
+        }
+        if let Some(v) = &collector.skip_types {
+            filter.skip_types = v.clone();
+        }
+        if let Some(v) = collector.min_content_chars {
+            filter.min_content_chars = v;
+        }
+        if let Some(v) = &collector.skip_tool_names {
+            filter.skip_tool_names = v.clone();
+        }
+        let limits = &mut self.collect_limits;
+        if let Some(v) = collector.max_file_bytes {
+            limits.max_file_bytes = v;
+        }
+        if let Some(v) = collector.max_message_bytes {
+            limits.max_message_bytes = v;
+        }
+        if let Some(v) = collector.max_messages_per_session {
+            limits.max_messages_per_session = v;
+        }
+        if let Some(v) = collector.max_collect_failures {
+            limits.max_collect_failures = v;
+        }
+        if let Some(v) = collector.collect_failure_cooldown_secs {
+            limits.collect_failure_cooldown_secs = v;
+        }
+
+        if let Some(v) = file.db.max_content_bytes {
+            self.max_content_bytes = Some(v);
+        }
+        if let Some(v) = file.db.wal_autocheckpoint_pages {
+            self.wal_autocheckpoint_pages = Some(v);
+        }
+        if let Some(v) = file.db.log_searches {
+            self.log_searches = v;
+        }
+        if let Some(v) = file.db.search_log_cap {
+            self.search_log_cap = v;
+        }
+        if let Some(v) = &file.db.source_databases {
+            self.source_databases = v.clone();
+        }
+    }
+
+    /// 生效的配置（与配置文件的结构相同，启动时记录日志并通过 Status 返回）
+    pub fn effective_config(&self) -> serde_json::Value {
+        let filter = &self.collection_filter;
+        let limits = &self.collect_limits;
+        serde_json::json!({
+            "data_dir": self.data_dir,
+            "config_file": ConfigFile::path(&self.data_dir),
+            "agent": {
+                "idle_timeout_secs": self.idle_timeout_secs,
+                "max_waiters": self.max_waiters,
+                "max_connections": self.max_connections,
+                "streaming_window_secs": self.streaming_window_secs,
+                "session_idle_secs": self.session_idle_secs,
+                "integrity_check_interval_secs": self.integrity_check_interval_secs,
+                "max_rss_mb": self.max_rss_mb,
+                "memory_hard_limit": self.memory_hard_limit,
+                "approval_digest_interval_secs": self.approval_digest_interval_secs,
+                "approval_digest_min_age_secs": self.approval_digest_min_age_secs,
+                "status_file_interval_secs": self.status_file_interval_secs,
+                "replication_file": self.replication_file,
+                "replication_url": self.replication_url,
+                "replication_batch_size": self.replication_batch_size,
+                "replication_interval_secs": self.replication_interval_secs,
+            },
+            "collector": {
+                "skip_empty_messages": filter.skip_empty_messages,
+                "skip_types": filter.skip_types,
+                "min_content_chars": filter.min_content_chars,
+                "skip_tool_names": filter.skip_tool_names,
+                "max_file_bytes": limits.max_file_bytes,
+                "max_message_bytes": limits.max_message_bytes,
+                "max_messages_per_session": limits.max_messages_per_session,
+                "max_collect_failures": limits.max_collect_failures,
+                "collect_failure_cooldown_secs": limits.collect_failure_cooldown_secs,
+            },
+            "db": {
+                "max_content_bytes": self.max_content_bytes,
+                "wal_autocheckpoint_pages": self.wal_autocheckpoint_pages,
+                "log_searches": self.log_searches,
+                "search_log_cap": self.search_log_cap,
+                "source_databases": self.source_databases,
+            },
+        })
+    }
+
     /// Socket 路径 (Unix only, for cleanup)
     pub fn socket_path(&self) -> PathBuf {
         self.data_dir.join("agent.sock")
@@ -86,6 +324,11 @@ impl AgentConfig {
         self.data_dir.join("agent.pid")
     }
 
+    /// 状态文件路径（见 `status_file_interval_secs`）
+    pub fn status_file_path(&self) -> PathBuf {
+        self.data_dir.join("agent.status.json")
+    }
+
     /// 数据库路径
     pub fn db_path(&self) -> PathBuf {
         self.data_dir.join("db").join("ai-cli-session.db")
@@ -99,6 +342,13 @@ pub struct Agent {
     db: Arc<SessionDB>,
     connections: Arc<ConnectionManager>,
     watcher: Arc<FileWatcher>,
+    integrity: Arc<IntegrityMonitor>,
+    resources: Arc<ResourceMonitor>,
+    approvals: Arc<ApprovalDigestMonitor>,
+    status_file: Arc<StatusFileWriter>,
+    replication: Option<Arc<Replicator>>,
+    /// 数据库要求更新版本的写入方时只读运行（不采集、不复制，拒绝写入请求）
+    degraded: Option<SchemaCompatibility>,
     handler: Arc<Handler>,
     #[allow(dead_code)]
     sync_worker: Arc<SyncWorker>,
@@ -106,23 +356,119 @@ pub struct Agent {
 }
 
 impl Agent {
-    /// 创建 Agent
+    /// 创建 Agent（复制目标按 `replication_url` / `replication_file` 配置）
     pub fn new(config: AgentConfig) -> Result<Self> {
+        let sink = configured_replication_sink(&config)?;
+        Self::build(config, sink)
+    }
+
+    /// 创建 Agent，新消息复制到自定义的目标（忽略配置的复制目标）
+    pub fn with_replication_sink(
+        config: AgentConfig,
+        sink: Arc<dyn ReplicationSink>,
+    ) -> Result<Self> {
+        Self::build(config, Some(sink))
+    }
+
+    fn build(config: AgentConfig, sink: Option<Arc<dyn ReplicationSink>>) -> Result<Self> {
         // 确保数据目录存在
         fs::create_dir_all(&config.data_dir)
             .context("Failed to create data directory")?;
         fs::create_dir_all(config.data_dir.join("db"))
             .context("Failed to create database directory")?;
 
-        // 连接数据库
-        let db_config = DbConfig::local(config.db_path().to_str().unwrap());
-        let db = Arc::new(SessionDB::connect(db_config)?);
+        // 记录待执行的迁移（连接时会立即执行），供 Status 报告
+        let startup_migrations = {
+            let conn = rusqlite::Connection::open(config.db_path())
+                .context("Failed to open database for migration plan")?;
+            crate::migrations::plan(&conn)?
+        };
+        if !startup_migrations.is_empty() {
+            tracing::info!("待执行迁移: {} 个", startup_migrations.len());
+        }
+
+        let effective_config = config.effective_config();
+        tracing::info!("⚙️ Effective config: {}", effective_config);
 
-        // 创建连接管理器
+        // 连接数据库（配置了按来源拆分时同时连接各来源的数据库）
+        let db_config = |path: &Path| DbConfig {
+            max_content_bytes: config.max_content_bytes,
+            wal_autocheckpoint_pages: config
+                .wal_autocheckpoint_pages
+                .unwrap_or(DEFAULT_WAL_AUTOCHECKPOINT_PAGES),
+            log_searches: config.log_searches,
+            search_log_cap: config.search_log_cap,
+            ..DbConfig::local(path)
+        };
+        let mut routes = Vec::new();
+        for (source, path) in &config.source_databases {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).with_context(|| {
+                    format!("Failed to create database directory for {}", source)
+                })?;
+            }
+            routes.push((source.clone(), db_config(path)));
+        }
+        let router = Arc::new(DbRouter::connect(db_config(&config.db_path()), routes)?);
+        if router.is_routed() {
+            tracing::info!("🗄️ Per-source databases: {:?}", router.databases());
+        }
+        let db = router.default_db().clone();
+        let degraded = incompatible_schema(&router)?;
+
+        // 创建连接管理器（数据库变更经由变更通知转为推送和等待者唤醒）
         let connections = ConnectionManager::new();
+        for (_, routed_db) in router.all() {
+            connections.observe(routed_db);
+        }
+
+        // 创建完整性巡检
+        let integrity = IntegrityMonitor::new(db.clone(), connections.clone());
+
+        // 创建资源监控（内存超限时作用于所有数据库）
+        let resources = ResourceMonitor::new(
+            router.all().map(|(_, db)| db.clone()).collect(),
+            config.max_rss_mb,
+            config.memory_hard_limit,
+        );
+
+        // 创建待审批摘要推送（汇总所有数据库）
+        let approvals = ApprovalDigestMonitor::new(
+            router.all().map(|(_, db)| db.clone()).collect(),
+            connections.clone(),
+            match config.approval_digest_min_age_secs {
+                0 => None,
+                secs => Some(secs as i64 * 1000),
+            },
+        );
 
         // 创建文件监听器
-        let watcher = FileWatcher::new(db.clone());
+        let watcher = FileWatcher::new(
+            router.clone(),
+            connections.clone(),
+            integrity.clone(),
+            config.collection_filter.clone(),
+            config.collect_limits,
+            Duration::from_secs(config.streaming_window_secs),
+            Duration::from_secs(config.session_idle_secs),
+        );
+
+        // 创建状态文件写入
+        let status_file = StatusFileWriter::new(
+            config.status_file_path(),
+            connections.clone(),
+            watcher.clone(),
+            degraded.is_none(),
+        );
+
+        // 创建消息复制（覆盖所有数据库）
+        let replication = sink.map(|sink| {
+            Replicator::new(
+                router.all().map(|(_, db)| db.clone()).collect(),
+                sink,
+                config.replication_batch_size,
+            )
+        });
 
         #[cfg(feature = "sync")]
         let _ = rustls::crypto::ring::default_provider().install_default();
@@ -138,13 +484,32 @@ impl Agent {
         };
 
         // 创建处理器
-        let handler = Arc::new(Handler::new(db.clone(), connections.clone(), watcher.clone(), sync_worker.clone(), sync_db));
+        let handler = Arc::new(Handler::new(
+            router,
+            connections.clone(),
+            watcher.clone(),
+            sync_worker.clone(),
+            sync_db,
+            ChangeWaiters::new(config.max_waiters),
+            startup_migrations,
+            effective_config,
+            resources.clone(),
+            integrity.clone(),
+            replication.clone(),
+            degraded,
+        ));
 
         Ok(Self {
             config,
             db,
             connections,
             watcher,
+            integrity,
+            resources,
+            approvals,
+            status_file,
+            replication,
+            degraded,
             handler,
             sync_worker,
             shutdown: Arc::new(AtomicBool::new(false)),
@@ -156,56 +521,141 @@ impl Agent {
         // 写入 PID 文件
         self.write_pid_file()?;
 
-        // 清理旧的 socket 文件 (Unix only)
-        #[cfg(unix)]
-        {
-            let socket_path = self.config.socket_path();
-            if socket_path.exists() {
-                fs::remove_file(&socket_path)?;
+        let listener = match self.config.listen_fd {
+            // socket activation：socket 由服务管理器绑定并持有
+            #[cfg(unix)]
+            Some(fd) => {
+                tracing::info!("🔌 Using socket-activated listener (fd={})", fd);
+                AgentListener::from_fd(fd).context("Failed to adopt activated socket")?
             }
-        }
+            _ => {
+                // 清理旧的 socket 文件 (Unix only)
+                #[cfg(unix)]
+                {
+                    let socket_path = self.config.socket_path();
+                    if socket_path.exists() {
+                        fs::remove_file(&socket_path)?;
+                    }
+                }
 
-        // 创建跨平台 IPC 监听器
-        let listener = ListenerOptions::new()
-            .name(self.config.socket_name())
-            .create_tokio()
-            .context("Failed to bind socket")?;
+                // 创建跨平台 IPC 监听器
+                // Unix 上 bind 期间收紧 umask：socket 创建时即只有属主可访问，不存在先公开再 chmod 的窗口
+                let listener = {
+                    #[cfg(unix)]
+                    let _umask = UmaskGuard::owner_only();
+                    ListenerOptions::new()
+                        .name(self.config.socket_name())
+                        .create_tokio()
+                        .context("Failed to bind socket")?
+                };
 
-        // 设置 socket 权限为 0600 (Unix only)
-        #[cfg(unix)]
-        fs::set_permissions(
-            self.config.socket_path(),
-            fs::Permissions::from_mode(0o600),
-        )?;
+                // 设置 socket 权限为 0600 (Unix only)
+                #[cfg(unix)]
+                fs::set_permissions(self.config.socket_path(), fs::Permissions::from_mode(0o600))?;
+
+                AgentListener::Bound(listener)
+            }
+        };
 
         tracing::info!("🚀 Agent started: {:?}", self.config.socket_path());
 
-        // 启动时执行全量扫描（mtime 剪枝会跳过未变化的文件）
-        {
+        // 启动时执行全量扫描（mtime 剪枝会跳过未变化的文件），只读降级时不采集
+        if self.degraded.is_none() {
             let db = self.db.clone();
             tokio::task::spawn_blocking(move || {
-                let collector = crate::Collector::new(&db);
-                match collector.collect_all() {
-                    Ok(result) => {
-                        if result.messages_inserted > 0 {
-                            tracing::info!(
-                                "📊 Startup scan complete: {} sessions, {} new messages",
-                                result.sessions_scanned,
-                                result.messages_inserted
-                            );
-                        }
+                // 刷新项目 ignored 标记（环境变量规则可能变化）
+                let applied = crate::IgnoreRules::load(&db)
+                    .and_then(|rules| db.apply_project_ignores(&rules));
+                match applied {
+                    Ok(changed) if !changed.is_empty() => {
+                        tracing::info!("🙈 Ignore rules updated {} projects", changed.len());
                     }
+                    Ok(_) => {}
                     Err(e) => {
-                        tracing::error!("Startup scan failed: {}", e);
+                        tracing::warn!("Failed to apply ignore rules: {}", e);
                     }
                 }
+
+                // 老数据回填对话轮次（仅首次升级时有实际工作）
+                if let Err(e) = db.backfill_turn_indexes() {
+                    tracing::warn!("Failed to backfill turn indexes: {}", e);
+                }
             })
             .await
             .ok();
+
+            match self.watcher.collect_all(collect_trigger::STARTUP).await {
+                Ok(summary) => {
+                    if summary.messages_inserted > 0 {
+                        tracing::info!(
+                            "📊 Startup scan complete: {} sessions, {} new messages",
+                            summary.sessions_scanned,
+                            summary.messages_inserted
+                        );
+                    }
+                }
+                Err(e) => match collection_lock_holder(&e) {
+                    Some(holder) => {
+                        tracing::info!("Startup scan deferred: collection lock held by {}", holder);
+                        self.watcher.defer_collect_all(collect_trigger::STARTUP);
+                    }
+                    None => tracing::error!("Startup scan failed: {}", e),
+                },
+            }
         }
 
         // 启动文件监听
-        self.watcher.clone().start().await?;
+        if self.degraded.is_none() {
+            self.watcher.clone().start().await?;
+        }
+
+        // 就绪通知（systemd Type=notify；launchd 以 PID 文件为准）
+        #[cfg(unix)]
+        match super::activation::notify_ready() {
+            Ok(true) => tracing::info!("📣 Readiness notification sent"),
+            Ok(false) => {}
+            Err(e) => tracing::warn!("Failed to send readiness notification: {}", e),
+        }
+
+        // 启动完整性巡检
+        if self.config.integrity_check_interval_secs > 0 {
+            let integrity = self.integrity.clone();
+            let check_interval = Duration::from_secs(self.config.integrity_check_interval_secs);
+            tokio::spawn(async move {
+                integrity.run(check_interval).await;
+            });
+        }
+
+        // 启动待审批摘要推送
+        if self.config.approval_digest_interval_secs > 0 {
+            let approvals = self.approvals.clone();
+            let digest_interval = Duration::from_secs(self.config.approval_digest_interval_secs);
+            tokio::spawn(async move {
+                approvals.run(digest_interval).await;
+            });
+        }
+
+        // 启动资源监控
+        tokio::spawn(self.resources.clone().run(SAMPLE_INTERVAL));
+
+        // 启动状态文件写入
+        if self.config.status_file_interval_secs > 0 {
+            let status_file = self.status_file.clone();
+            let write_interval = Duration::from_secs(self.config.status_file_interval_secs);
+            tokio::spawn(async move {
+                status_file.run(write_interval).await;
+            });
+        }
+
+        // 启动消息复制（只读降级时不推进游标）
+        if let (Some(replication), None) = (&self.replication, self.degraded) {
+            let replication = replication.clone();
+            let connections = self.connections.clone();
+            let check_interval = Duration::from_secs(self.config.replication_interval_secs.max(1));
+            tokio::spawn(async move {
+                replication.run(connections, check_interval).await;
+            });
+        }
 
         // 启动空闲检测
         let agent_for_idle = self.clone();
@@ -244,24 +694,18 @@ impl Agent {
             }
 
             tokio::select! {
-                result = listener.accept() => {
-                    match result {
-                        Ok(stream) => {
-                            let agent = self.clone();
-                            tokio::spawn(async move {
-                                if let Err(e) = agent.handle_connection(stream).await {
-                                    tracing::error!("Failed to handle connection: {}", e);
-                                }
-                            });
-                        }
-                        Err(e) => {
-                            tracing::error!("Failed to accept connection: {}", e);
-                        }
+                result = listener.accept(&self) => {
+                    if let Err(e) = result {
+                        tracing::error!("Failed to accept connection: {}", e);
                     }
                 }
                 _ = &mut shutdown_signal => {
                     break;
                 }
+                _ = self.resources.exit_requested() => {
+                    tracing::warn!("Memory ceiling exceeded, shutting down...");
+                    break;
+                }
                 _ = tokio::time::sleep(Duration::from_secs(5)) => {
                     continue;
                 }
@@ -272,16 +716,49 @@ impl Agent {
         Ok(())
     }
 
+    /// 在后台处理新连接
+    fn spawn_connection<S>(self: Arc<Self>, stream: S)
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        tokio::spawn(async move {
+            if let Err(e) = self.handle_connection(stream).await {
+                tracing::error!("Failed to handle connection: {}", e);
+            }
+        });
+    }
+
     /// 处理单个连接
-    async fn handle_connection(&self, stream: Stream) -> Result<()> {
+    async fn handle_connection<S>(&self, stream: S) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
         let (reader, mut writer) = tokio::io::split(stream);
         let mut reader = BufReader::new(reader);
 
         // 创建消息发送通道
         let (tx, mut rx) = mpsc::channel::<String>(100);
 
-        // 注册连接
-        let conn_id = self.connections.register(tx);
+        // 注册连接（达到上限时告知对方后关闭）
+        let Some(conn_id) = self
+            .connections
+            .try_register(tx, self.config.max_connections)
+        else {
+            tracing::warn!(
+                "Too many connections (max={}), rejecting",
+                self.config.max_connections
+            );
+            let response = Response::Error {
+                code: error_code::TOO_MANY_CONNECTIONS,
+                message: format!("Too many connections (max={})", self.config.max_connections),
+            };
+            let resp_json = serde_json::to_string(&response)?;
+            writer
+                .write_all(format!("{}\n", resp_json).as_bytes())
+                .await?;
+            writer.shutdown().await?;
+            return Ok(());
+        };
         tracing::debug!("📥 New connection: conn_id={}", conn_id);
 
         // 启动发送任务
@@ -326,6 +803,16 @@ impl Agent {
                     if !self.connections.send_to(conn_id, format!("{}\n", resp_json)).await {
                         break;
                     }
+
+                    // 只读降级：握手成功后告知该连接
+                    if let (Response::HandshakeOk { .. }, Some(push)) =
+                        (&response, self.handler.degraded_push())
+                    {
+                        let push_line = format!("{}\n", serde_json::to_string(&push)?);
+                        if !self.connections.send_to(conn_id, push_line).await {
+                            break;
+                        }
+                    }
                 }
                 Err(e) => {
                     tracing::error!("Read failed: {}", e);
@@ -373,13 +860,24 @@ impl Agent {
         }
     }
 
-    /// 写入 PID 文件
+    /// 写入 PID 文件（Unix 上以 0600 创建）
     fn write_pid_file(&self) -> Result<()> {
         let pid = std::process::id();
         let pid_path = self.config.pid_path();
-        fs::write(&pid_path, pid.to_string())?;
         #[cfg(unix)]
-        fs::set_permissions(&pid_path, fs::Permissions::from_mode(0o600))?;
+        {
+            fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(0o600)
+                .open(&pid_path)?
+                .write_all(pid.to_string().as_bytes())?;
+            // mode 只作用于新建文件，已存在的旧文件同样收紧
+            fs::set_permissions(&pid_path, fs::Permissions::from_mode(0o600))?;
+        }
+        #[cfg(not(unix))]
+        fs::write(&pid_path, pid.to_string())?;
         tracing::debug!("📝 Writing PID file: {} (pid={})", pid_path.display(), pid);
         Ok(())
     }
@@ -414,9 +912,9 @@ impl Agent {
 
     /// 清理资源
     fn cleanup(&self) {
-        // 删除 socket 文件
+        // 删除 socket 文件（socket activation 时由服务管理器持有，保留）
         let socket_path = self.config.socket_path();
-        if socket_path.exists() {
+        if self.config.listen_fd.is_none() && socket_path.exists() {
             let _ = fs::remove_file(&socket_path);
         }
 
@@ -426,10 +924,107 @@ impl Agent {
             let _ = fs::remove_file(&pid_path);
         }
 
+        // 删除状态文件（外部监控据此判断 Agent 已退出）
+        let status_path = self.config.status_file_path();
+        if status_path.exists() {
+            let _ = fs::remove_file(&status_path);
+        }
+
         tracing::info!("🧹 Agent cleanup complete");
     }
 }
 
+/// 临时将进程 umask 设为 077，drop 时恢复
+///
+/// umask 是进程级的：期间其他线程新建的文件同样只有属主权限（更严格，但仍可用）。
+#[cfg(unix)]
+struct UmaskGuard(libc::mode_t);
+
+#[cfg(unix)]
+impl UmaskGuard {
+    fn owner_only() -> Self {
+        Self(unsafe { libc::umask(0o077) })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for UmaskGuard {
+    fn drop(&mut self) {
+        unsafe { libc::umask(self.0) };
+    }
+}
+
+/// Agent 监听器：自行绑定的 local socket，或 socket activation 移交的 Unix socket
+enum AgentListener {
+    Bound(interprocess::local_socket::tokio::Listener),
+    #[cfg(unix)]
+    Activated(tokio::net::UnixListener),
+}
+
+impl AgentListener {
+    /// 接管服务管理器移交的监听 fd（所有权转移给 Agent）
+    #[cfg(unix)]
+    fn from_fd(fd: i32) -> std::io::Result<Self> {
+        use std::os::unix::io::FromRawFd;
+
+        let listener = unsafe { std::os::unix::net::UnixListener::from_raw_fd(fd) };
+        listener.set_nonblocking(true)?;
+        Ok(Self::Activated(tokio::net::UnixListener::from_std(listener)?))
+    }
+
+    /// 接受一个连接并交给 Agent 在后台处理
+    async fn accept(&self, agent: &Arc<Agent>) -> std::io::Result<()> {
+        match self {
+            Self::Bound(listener) => agent.clone().spawn_connection(listener.accept().await?),
+            #[cfg(unix)]
+            Self::Activated(listener) => {
+                let (stream, _) = listener.accept().await?;
+                agent.clone().spawn_connection(stream);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 第一个要求更新版本写入方的数据库的兼容性（都可写入时为 None）
+fn incompatible_schema(router: &DbRouter) -> Result<Option<SchemaCompatibility>> {
+    for (name, db) in router.all() {
+        let compatibility = db.compatibility_check()?;
+        if !compatibility.writable() {
+            tracing::error!(
+                "Database {} requires a writer supporting schema {} (this agent supports {}), \
+                 running read-only until vimo-agent is updated",
+                name,
+                compatibility.min_writer_schema_version,
+                compatibility.supported_schema_version
+            );
+            return Ok(Some(compatibility));
+        }
+    }
+    Ok(None)
+}
+
+/// 按配置创建复制目标（`replication_url` 优先），未配置时返回 None
+fn configured_replication_sink(config: &AgentConfig) -> Result<Option<Arc<dyn ReplicationSink>>> {
+    if let Some(url) = &config.replication_url {
+        #[cfg(feature = "replication-http")]
+        {
+            tracing::info!("🔁 Replicating new messages to {}", url);
+            let sink = crate::replication::HttpPostSink::new(url.clone())?;
+            return Ok(Some(Arc::new(sink)));
+        }
+        #[cfg(not(feature = "replication-http"))]
+        tracing::warn!(
+            "replication_url {} ignored: built without the replication-http feature",
+            url
+        );
+    }
+    Ok(config.replication_file.as_ref().map(|path| {
+        tracing::info!("🔁 Replicating new messages to {}", path.display());
+        Arc::new(JsonlFileSink::new(path)) as Arc<dyn ReplicationSink>
+    }))
+}
+
 /// 检查 Agent 是否正在运行
 pub fn is_agent_running(config: &AgentConfig) -> bool {
     let pid_path = config.pid_path();
@@ -0,0 +1,108 @@
+//! 状态文件
+//!
+//! 定期把 Agent 的运行状态写入 `agent.status.json`（`AgentConfig::status_file_path`），
+//! launchd / systemd 的健康检查、菜单栏等外部监控无需连接 socket 即可读取。
+//! 先写临时文件再 rename，读取方不会看到写了一半的文件；Agent 退出时删除。
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::activity::now_ms;
+use super::broadcaster::ConnectionManager;
+use super::handler::AGENT_VERSION;
+use super::watcher::FileWatcher;
+use crate::protocol::{writer_type, WriterRole};
+
+/// 状态文件写入
+pub struct StatusFileWriter {
+    path: PathBuf,
+    connections: Arc<ConnectionManager>,
+    watcher: Arc<FileWatcher>,
+    /// 持有写入角色（只读降级时为 false，见 `Push::AgentDegraded`）
+    is_writer: bool,
+    /// Agent 启动时间（毫秒），同时是取得写入角色的时间
+    started_at: i64,
+}
+
+impl StatusFileWriter {
+    pub fn new(
+        path: PathBuf,
+        connections: Arc<ConnectionManager>,
+        watcher: Arc<FileWatcher>,
+        is_writer: bool,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            path,
+            connections,
+            watcher,
+            is_writer,
+            started_at: now_ms(),
+        })
+    }
+
+    /// 立即写入一次，之后按 `interval` 定期写入，直到任务被取消
+    pub async fn run(&self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.write() {
+                tracing::warn!("Failed to write status file {}: {}", self.path.display(), e);
+            }
+        }
+    }
+
+    /// 当前状态
+    fn status(&self) -> serde_json::Value {
+        let now = now_ms();
+        serde_json::json!({
+            "agent_version": AGENT_VERSION,
+            "library_version": crate::VERSION_FULL,
+            "pid": std::process::id(),
+            "started_at": self.started_at,
+            "uptime_secs": (now - self.started_at).max(0) / 1000,
+            "updated_at": now,
+            "connections": self.connections.connection_count(),
+            "last_collect": self.watcher.last_collect(),
+            "writer_role": WriterRole {
+                is_writer: self.is_writer,
+                writer_type: writer_type::AGENT.to_string(),
+                since: self.started_at,
+            },
+        })
+    }
+
+    fn write(&self) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(&self.status())?;
+        write_atomic(&self.path, &json)
+    }
+}
+
+/// 先写同目录下的临时文件再 rename 到 `path`
+fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_atomic_replaces_file() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("agent.status.json");
+
+        write_atomic(&path, b"{\"a\":1}").unwrap();
+        write_atomic(&path, b"{\"a\":2}").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{\"a\":2}");
+        // 临时文件已被 rename，不会残留
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 1);
+    }
+}
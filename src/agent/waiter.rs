@@ -0,0 +1,240 @@
+//! 变更等待器
+//!
+//! 实现 WaitForChange 长轮询：挂起请求直到范围内发生变更或超时，
+//! 客户端无需维护订阅和推送处理循环。
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Semaphore;
+
+use super::broadcaster::ConnectionManager;
+use crate::types::ChangeState;
+use crate::SessionDB;
+
+/// 单次等待的最长时间（超过时按此值截断）
+pub const MAX_WAIT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// 等待结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WaitOutcome {
+    /// 状态已变化
+    Changed(ChangeState),
+    /// 超时，状态未变化
+    NotModified(ChangeState),
+    /// 等待者数量已达上限
+    TooManyWaiters,
+}
+
+/// 变更等待器（限制并发等待数）
+pub struct ChangeWaiters {
+    permits: Arc<Semaphore>,
+}
+
+impl ChangeWaiters {
+    /// 创建等待器，`max_waiters` 为最大并发等待数
+    pub fn new(max_waiters: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max_waiters)),
+        }
+    }
+
+    /// 等待范围内的状态变化
+    ///
+    /// - `after_state_hash` 与当前状态不同：立即返回 Changed
+    /// - 否则挂起，直到状态哈希变化（Changed）或超时（NotModified）
+    ///
+    /// 先订阅变更通知再读取当前状态，读取与挂起之间的变更不会丢失。
+    pub async fn wait(
+        &self,
+        db: &SessionDB,
+        connections: &ConnectionManager,
+        session_id: Option<&str>,
+        project_path: Option<&str>,
+        after_state_hash: Option<u64>,
+        timeout: Duration,
+    ) -> crate::Result<WaitOutcome> {
+        let Ok(_permit) = self.permits.clone().try_acquire_owned() else {
+            return Ok(WaitOutcome::TooManyWaiters);
+        };
+
+        let mut changes = connections.subscribe_changes();
+        let current = db.get_change_state(session_id, project_path)?;
+        let known_hash = match after_state_hash {
+            Some(hash) if hash != current.state_hash => return Ok(WaitOutcome::Changed(current)),
+            Some(hash) => hash,
+            None => current.state_hash,
+        };
+
+        let wait_changed = async {
+            loop {
+                match changes.recv().await {
+                    Ok(event) => {
+                        // 指定会话时只关心该会话；按项目过滤时由状态哈希判断
+                        if session_id.is_some_and(|sid| sid != event.session_id) {
+                            continue;
+                        }
+                    }
+                    // 落后时可能错过了相关变更，重新检查状态
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => std::future::pending::<()>().await,
+                }
+
+                let state = db.get_change_state(session_id, project_path)?;
+                if state.state_hash != known_hash {
+                    return Ok::<_, crate::Error>(state);
+                }
+            }
+        };
+
+        match tokio::time::timeout(timeout.min(MAX_WAIT_TIMEOUT), wait_changed).await {
+            Ok(result) => result.map(WaitOutcome::Changed),
+            Err(_) => Ok(WaitOutcome::NotModified(current)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MessageInput;
+    use crate::{DbConfig, MessageType};
+    use std::time::Instant;
+    use tempfile::TempDir;
+
+    fn setup() -> (Arc<SessionDB>, Arc<ConnectionManager>, TempDir) {
+        let tmp = TempDir::new().unwrap();
+        let db = SessionDB::connect(DbConfig::local(tmp.path().join("test.db"))).unwrap();
+        let project_id = db.get_or_create_project("p", "/p", "claude").unwrap();
+        db.upsert_session("session-a", project_id).unwrap();
+        db.upsert_session("session-b", project_id).unwrap();
+        (Arc::new(db), ConnectionManager::new(), tmp)
+    }
+
+    fn insert(db: &SessionDB, connections: &ConnectionManager, session_id: &str, seq: i64) {
+        let message = MessageInput {
+            uuid: format!("{}-{}", session_id, seq),
+            r#type: MessageType::User,
+            content_text: "hello".to_string(),
+            content_full: "hello".to_string(),
+            timestamp: 1000 + seq,
+            sequence: seq,
+            source: None,
+            channel: None,
+            model: None,
+            tool_call_id: None,
+            tool_name: None,
+            tool_args: None,
+            raw: None,
+            approval_status: None,
+            approval_resolved_at: None,
+        };
+        db.insert_messages(session_id, &[message]).unwrap();
+        connections.notify_change(session_id);
+    }
+
+    #[tokio::test]
+    async fn test_wait_woken_by_matching_session_only() {
+        let (db, connections, _tmp) = setup();
+        let waiters = Arc::new(ChangeWaiters::new(4));
+
+        let handle = {
+            let (db, connections, waiters) = (db.clone(), connections.clone(), waiters.clone());
+            tokio::spawn(async move {
+                waiters
+                    .wait(
+                        &db,
+                        &connections,
+                        Some("session-a"),
+                        None,
+                        None,
+                        Duration::from_secs(5),
+                    )
+                    .await
+                    .unwrap()
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // 其他会话的变更不唤醒
+        insert(&db, &connections, "session-b", 0);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!handle.is_finished());
+
+        insert(&db, &connections, "session-a", 0);
+        let outcome = tokio::time::timeout(Duration::from_secs(2), handle)
+            .await
+            .expect("waiter should be woken")
+            .unwrap();
+        match outcome {
+            WaitOutcome::Changed(state) => {
+                assert_eq!(state.message_count, 1);
+                assert_eq!(state.max_sequence, Some(0));
+            }
+            other => panic!("Expected Changed, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wait_timeout_returns_not_modified() {
+        let (db, connections, _tmp) = setup();
+        let waiters = ChangeWaiters::new(4);
+
+        let start = Instant::now();
+        let outcome = waiters
+            .wait(
+                &db,
+                &connections,
+                Some("session-a"),
+                None,
+                None,
+                Duration::from_millis(100),
+            )
+            .await
+            .unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(matches!(outcome, WaitOutcome::NotModified(state) if state.message_count == 0));
+    }
+
+    #[tokio::test]
+    async fn test_wait_stale_hash_returns_immediately() {
+        let (db, connections, _tmp) = setup();
+        let waiters = ChangeWaiters::new(4);
+
+        let before = db.get_change_state(Some("session-a"), None).unwrap();
+        insert(&db, &connections, "session-a", 0);
+
+        let outcome = waiters
+            .wait(
+                &db,
+                &connections,
+                Some("session-a"),
+                None,
+                Some(before.state_hash),
+                Duration::from_secs(5),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(outcome, WaitOutcome::Changed(state) if state.message_count == 1));
+    }
+
+    #[tokio::test]
+    async fn test_wait_limit() {
+        let (db, connections, _tmp) = setup();
+        let waiters = ChangeWaiters::new(0);
+
+        let outcome = waiters
+            .wait(
+                &db,
+                &connections,
+                None,
+                None,
+                None,
+                Duration::from_millis(100),
+            )
+            .await
+            .unwrap();
+        assert_eq!(outcome, WaitOutcome::TooManyWaiters);
+    }
+}
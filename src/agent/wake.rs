@@ -0,0 +1,103 @@
+//! 休眠唤醒检测
+//!
+//! 系统休眠期间单调时钟（`Instant`）停止计时，墙上时钟照常前进。定期比较两者的增量，
+//! 墙上时钟多走的部分超过阈值即认为刚从休眠中唤醒，无需平台的电源通知框架。
+//!
+//! 唤醒后 kqueue / inotify 的注册可能已静默丢失，客户端也可能持有失效的连接，
+//! 由 `FileWatcher` 重建监听、补采并推送 `Push::AgentResumed`。
+
+use std::time::{Duration, Instant};
+
+use super::activity::now_ms;
+
+/// 唤醒检测间隔
+pub const WAKE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 墙上时钟比单调时钟多走超过此值时判定为休眠
+pub const WAKE_DRIFT_THRESHOLD: Duration = Duration::from_secs(15);
+
+/// 一次时钟读数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockReading {
+    /// 单调时钟（休眠期间不计时）
+    pub monotonic: Duration,
+    /// 墙上时钟（毫秒时间戳）
+    pub wall_ms: i64,
+}
+
+impl ClockReading {
+    /// 读取当前时钟，单调时钟从 `origin` 起算
+    pub fn now(origin: Instant) -> Self {
+        Self {
+            monotonic: origin.elapsed(),
+            wall_ms: now_ms(),
+        }
+    }
+}
+
+/// 休眠唤醒检测器（时钟读数由调用方提供）
+#[derive(Debug)]
+pub struct WakeDetector {
+    threshold: Duration,
+    last: Option<ClockReading>,
+}
+
+impl WakeDetector {
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            last: None,
+        }
+    }
+
+    /// 记录读数，两次读数之间发生过休眠时返回估计的休眠时长
+    ///
+    /// 墙上时钟回拨（如 NTP 校时）不视为休眠。
+    pub fn observe(&mut self, now: ClockReading) -> Option<Duration> {
+        let last = self.last.replace(now)?;
+        let wall_elapsed = u64::try_from(now.wall_ms - last.wall_ms).ok()?;
+        let monotonic_elapsed = now.monotonic.saturating_sub(last.monotonic);
+        let drift = Duration::from_millis(wall_elapsed).saturating_sub(monotonic_elapsed);
+        (drift > self.threshold).then_some(drift)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(monotonic_secs: u64, wall_secs: i64) -> ClockReading {
+        ClockReading {
+            monotonic: Duration::from_secs(monotonic_secs),
+            wall_ms: 1_700_000_000_000 + wall_secs * 1000,
+        }
+    }
+
+    #[test]
+    fn test_no_wake_when_clocks_agree() {
+        let mut detector = WakeDetector::new(WAKE_DRIFT_THRESHOLD);
+        assert_eq!(detector.observe(reading(0, 0)), None);
+        assert_eq!(detector.observe(reading(30, 30)), None);
+        // 定时器延迟几秒不算休眠
+        assert_eq!(detector.observe(reading(62, 65)), None);
+    }
+
+    #[test]
+    fn test_wall_clock_jump_is_wake() {
+        let mut detector = WakeDetector::new(WAKE_DRIFT_THRESHOLD);
+        detector.observe(reading(0, 0));
+        // 单调时钟只走了 30 秒，墙上时钟走了 1 小时
+        let slept = detector.observe(reading(30, 3630)).unwrap();
+        assert_eq!(slept, Duration::from_secs(3600));
+        // 之后恢复正常
+        assert_eq!(detector.observe(reading(60, 3660)), None);
+    }
+
+    #[test]
+    fn test_wall_clock_set_back_is_not_wake() {
+        let mut detector = WakeDetector::new(WAKE_DRIFT_THRESHOLD);
+        detector.observe(reading(0, 3600));
+        assert_eq!(detector.observe(reading(30, 0)), None);
+        assert_eq!(detector.observe(reading(60, 30)), None);
+    }
+}
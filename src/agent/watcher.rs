@@ -1,30 +1,96 @@
 //! 文件监听器
 //!
-//! 监听 AI CLI 会话文件变化，触发 Collection
+//! 监听 AI CLI 会话文件变化，触发 Collection。
+//! 系统从休眠中唤醒后重建全部监听并补采（见 `wake`）。
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
-use notify::RecursiveMode;
-use notify_debouncer_mini::{new_debouncer, DebouncedEventKind};
-use tokio::sync::mpsc;
+use notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, DebouncedEventKind, Debouncer};
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::sync::{mpsc, Notify};
 
-use crate::{all_watch_configs, Collector, SessionDB};
+use super::activity::{now_ms, ActivityTracker};
+use super::broadcaster::ConnectionManager;
+use super::integrity::IntegrityMonitor;
+use super::wake::{ClockReading, WakeDetector, WAKE_CHECK_INTERVAL, WAKE_DRIFT_THRESHOLD};
+use crate::collector::collection_lock_holder;
+use crate::collector::RECENT_COLLECT_WINDOW;
+use crate::protocol::{collect_phase, collect_trigger, CollectSummary, Push};
+use crate::router::DbRouter;
+use crate::types::{HistoryTotals, SessionActivity, SessionActivityState};
+use crate::{
+    all_watch_configs, CollectLimits, CollectionFilter, Collector, MessageType, SessionDB,
+};
+
+/// 防抖时间
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// 监听校验间隔（重建失效/缺失目录的监听）
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 会话活跃状态刷新间隔
+const ACTIVITY_TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 采集锁被其他进程持有时的重试间隔
+const COLLECT_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// 采集锁被其他进程持有时的最大重试次数
+const COLLECT_RETRY_ATTEMPTS: u32 = 24;
+
+/// 最近一次全量采集的结果（状态文件的 `last_collect`）
+#[derive(Debug, Clone, Serialize)]
+pub struct LastCollect {
+    /// 触发来源，见 `collect_trigger`
+    pub trigger: String,
+    /// 结束时间（毫秒）
+    pub finished_at: i64,
+    pub summary: CollectSummary,
+    /// 采集失败时的错误信息
+    pub error: Option<String>,
+}
 
 /// 文件监听器
 pub struct FileWatcher {
-    /// 数据库连接
+    /// 数据库连接（默认数据库）
     db: Arc<SessionDB>,
+    /// 按来源拆分的数据库
+    router: Arc<DbRouter>,
+    /// 连接管理器（发布变更通知）
+    connections: Arc<ConnectionManager>,
+    /// 完整性巡检（采集遇到损坏错误时触发检查）
+    integrity: Arc<IntegrityMonitor>,
+    /// 采集过滤器
+    filter: CollectionFilter,
+    /// 采集防护上限
+    limits: CollectLimits,
+    /// 会话活跃状态
+    activity: ActivityTracker,
     /// 支持的文件扩展名
     supported_extensions: HashSet<String>,
+    /// 通知监听集合重建全部监听（休眠唤醒后）
+    rewatch: Arc<Notify>,
+    /// 最近一次全量采集的结果
+    last_collect: Mutex<Option<LastCollect>>,
 }
 
 impl FileWatcher {
     /// 创建文件监听器
-    pub fn new(db: Arc<SessionDB>) -> Arc<Self> {
+    pub fn new(
+        router: Arc<DbRouter>,
+        connections: Arc<ConnectionManager>,
+        integrity: Arc<IntegrityMonitor>,
+        filter: CollectionFilter,
+        limits: CollectLimits,
+        streaming_window: Duration,
+        idle_after: Duration,
+    ) -> Arc<Self> {
         // 从适配器收集所有支持的扩展名
         let supported_extensions: HashSet<String> = all_watch_configs()
             .iter()
@@ -32,72 +98,81 @@ impl FileWatcher {
             .collect();
 
         Arc::new(Self {
-            db,
+            db: router.default_db().clone(),
+            router,
+            connections,
+            integrity,
+            filter,
+            limits,
+            activity: ActivityTracker::new(streaming_window, idle_after),
             supported_extensions,
+            rewatch: Arc::new(Notify::new()),
+            last_collect: Mutex::new(None),
         })
     }
 
+    /// 最近一次全量采集的结果（尚未采集时为 None）
+    pub fn last_collect(&self) -> Option<LastCollect> {
+        self.last_collect.lock().clone()
+    }
+
     /// 启动文件监听
     pub async fn start(self: Arc<Self>) -> Result<()> {
         let (tx, mut rx) = mpsc::channel::<PathBuf>(100);
 
-        // 创建 debouncer（2秒防抖）
-        let tx_clone = tx.clone();
-        let mut debouncer = new_debouncer(Duration::from_secs(2), move |res: Result<Vec<notify_debouncer_mini::DebouncedEvent>, notify::Error>| {
-            if let Ok(events) = res {
-                for event in events {
-                    if event.kind == DebouncedEventKind::Any {
-                        let _ = tx_clone.blocking_send(event.path);
-                    }
-                }
-            }
-        })?;
-
         // 使用适配器自注册的监听配置
-        let watch_configs = all_watch_configs();
-
-        for config in &watch_configs {
-            let recursive_mode = if config.recursive {
-                RecursiveMode::Recursive
-            } else {
-                RecursiveMode::NonRecursive
-            };
-
-            match debouncer.watcher().watch(&config.path, recursive_mode) {
-                Ok(_) => {
-                    tracing::info!(
-                        "👁️ Watching {} directory: {:?} (extensions: {:?})",
-                        config.name,
-                        config.path,
-                        config.extensions
-                    );
-                }
-                Err(e) => {
-                    tracing::warn!(
-                        "⚠️ Failed to watch {} directory {:?}: {}",
-                        config.name,
-                        config.path,
-                        e
-                    );
-                }
-            }
-        }
+        let targets: Vec<WatchTarget> = all_watch_configs()
+            .iter()
+            .map(|config| WatchTarget {
+                name: config.name.to_string(),
+                path: config.path.to_path_buf(),
+                recursive: config.recursive,
+            })
+            .collect();
 
-        if watch_configs.is_empty() {
+        if targets.is_empty() {
             tracing::warn!("⚠️ No valid watch directories found");
         }
 
+        let mut watch_set = WatchSet::new(targets, DEBOUNCE, tx)?;
+        let watched = watch_set.reconcile(true);
+
         tracing::info!(
-            "🔄 File watcher service started ({} directories)",
-            watch_configs.len()
+            "🔄 File watcher service started ({}/{} directories)",
+            watched,
+            watch_set.targets.len()
         );
 
-        // 处理文件变化事件
+        // 定期校验监听（目录被删除/重建后恢复），唤醒后整体重建
+        tokio::spawn(watch_set.run(RECONCILE_INTERVAL, self.rewatch.clone()));
+
+        // 检测休眠唤醒
         let watcher = self.clone();
         tokio::spawn(async move {
-            // 保持 debouncer 存活
-            let _debouncer = debouncer;
+            let origin = Instant::now();
+            let mut detector = WakeDetector::new(WAKE_DRIFT_THRESHOLD);
+            let mut ticker = tokio::time::interval(WAKE_CHECK_INTERVAL);
+            loop {
+                ticker.tick().await;
+                watcher
+                    .check_wake(&mut detector, ClockReading::now(origin))
+                    .await;
+            }
+        });
+
+        // 刷新会话活跃状态（Streaming → RecentlyActive → Idle）
+        let watcher = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(ACTIVITY_TICK_INTERVAL);
+            loop {
+                ticker.tick().await;
+                watcher.refresh_activity(now_ms());
+            }
+        });
 
+        // 处理文件变化事件
+        let watcher = self.clone();
+        tokio::spawn(async move {
             while let Some(path) = rx.recv().await {
                 watcher.handle_file_change(&path).await;
             }
@@ -106,8 +181,41 @@ impl FileWatcher {
         Ok(())
     }
 
+    /// 记录时钟读数，检测到休眠唤醒时执行恢复，返回是否发生过休眠
+    async fn check_wake(self: &Arc<Self>, detector: &mut WakeDetector, now: ClockReading) -> bool {
+        match detector.observe(now) {
+            Some(slept) => {
+                self.handle_wake(slept).await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 休眠唤醒后：重建全部监听，通知客户端校验连接，补采休眠期间遗漏的变化
+    async fn handle_wake(self: &Arc<Self>, slept: Duration) {
+        tracing::info!(
+            "⏰ Woke from sleep (~{}s), re-registering watchers",
+            slept.as_secs()
+        );
+        self.rewatch.notify_one();
+
+        self.broadcast_push(&Push::AgentResumed {
+            slept_secs: slept.as_secs(),
+            change_counter: self.change_counter(),
+        });
+
+        match self.collect_all(collect_trigger::WAKE).await {
+            Ok(_) => {}
+            Err(e) if collection_lock_holder(&e).is_some() => {
+                self.defer_collect_all(collect_trigger::WAKE);
+            }
+            Err(e) => tracing::error!("Catch-up collection after wake failed: {}", e),
+        }
+    }
+
     /// 处理文件变化
-    async fn handle_file_change(&self, path: &Path) {
+    async fn handle_file_change(self: &Arc<Self>, path: &Path) {
         // 检查扩展名
         let ext = match path.extension().and_then(|e| e.to_str()) {
             Some(e) => e,
@@ -119,28 +227,324 @@ impl FileWatcher {
         }
 
         tracing::debug!("📝 File change detected: {:?}", path);
+        self.record_activity(path, now_ms());
 
         if let Err(e) = self.trigger_collect(path).await {
+            self.integrity.check_on_error(&e);
             tracing::error!("Failed to process file change {:?}: {}", path.file_name(), e);
         }
     }
 
+    /// 记录会话文件事件，活跃状态切换时广播 SessionActivityChanged
+    fn record_activity(&self, path: &Path, now_ms: i64) {
+        // session_id 与 collect_by_path 一致，取文件名
+        let Some(session_id) = path.file_stem().and_then(|s| s.to_str()) else {
+            return;
+        };
+        if let Some(activity) = self.activity.record_event(session_id, now_ms) {
+            self.broadcast_activity(activity);
+        }
+    }
+
+    /// 按当前时间刷新活跃状态并广播切换，进入 Idle 的会话额外推送 SessionIdle
+    fn refresh_activity(&self, now_ms: i64) {
+        for activity in self.activity.tick(now_ms) {
+            let idle = (activity.state == SessionActivityState::Idle)
+                .then(|| (activity.session_id.clone(), activity.last_event_ms));
+            self.broadcast_activity(activity);
+            if let Some((session_id, Some(last_event_ms))) = idle {
+                self.broadcast_session_idle(&session_id, last_event_ms);
+            }
+        }
+    }
+
+    /// 会话静默：推送最后一条助手消息的预览和消息数
+    fn broadcast_session_idle(&self, session_id: &str, last_event_ms: i64) {
+        let last_assistant_preview = self
+            .db
+            .get_last_message_preview(session_id, MessageType::Assistant)
+            .unwrap_or_else(|e| {
+                tracing::warn!("Failed to get preview of session {}: {}", session_id, e);
+                None
+            });
+        self.broadcast_push(&Push::SessionIdle {
+            session_id: session_id.to_string(),
+            last_assistant_preview,
+            message_count: self
+                .db
+                .get_session_message_count(session_id)
+                .unwrap_or_default(),
+            last_event_ms,
+            change_counter: self.change_counter(),
+        });
+    }
+
+    /// 会话首次采集到消息：推送项目、来源、标题（第一条用户消息）和开始时间
+    fn broadcast_session_started(&self, session_id: &str) {
+        let session = match self
+            .router
+            .for_session(session_id)
+            .and_then(|db| db.get_session_with_metrics(session_id))
+        {
+            Ok(Some(session)) => session,
+            Ok(None) => return,
+            Err(e) => {
+                tracing::warn!("Failed to get started session {}: {}", session_id, e);
+                return;
+            }
+        };
+        let started_at = session
+            .metrics
+            .as_ref()
+            .and_then(|m| m.first_message_at)
+            .unwrap_or(session.created_at);
+        let title = self
+            .db
+            .get_first_message_preview(session_id, MessageType::User)
+            .unwrap_or_default();
+        self.broadcast_push(&Push::SessionStarted {
+            session_id: session.session_id,
+            project_path: session.project_path,
+            source: session.source,
+            title,
+            started_at,
+            change_counter: self.change_counter(),
+        });
+    }
+
+    /// CLI 报告会话结束（SessionEnd hook）：会话直接进入 Idle（不再推送 SessionIdle），
+    /// 并推送 SessionEnded
+    pub fn end_session(&self, session_id: &str) {
+        if let Some(activity) = self.activity.end_session(session_id) {
+            self.broadcast_activity(activity);
+        }
+        self.broadcast_push(&Push::SessionEnded {
+            session_id: session_id.to_string(),
+            message_count: self
+                .db
+                .get_session_message_count(session_id)
+                .unwrap_or_default(),
+            change_counter: self.change_counter(),
+        });
+    }
+
+    fn broadcast_activity(&self, activity: SessionActivity) {
+        self.broadcast_push(&Push::SessionActivityChanged {
+            session_id: activity.session_id,
+            state: activity.state,
+            last_event_ms: activity.last_event_ms,
+            change_counter: self.change_counter(),
+        });
+    }
+
+    /// 采集防护上限（写入 Reader 转发的批次时同样适用）
+    pub fn collect_limits(&self) -> CollectLimits {
+        self.limits
+    }
+
+    /// 查询会话活跃状态
+    pub fn session_activity(&self, session_ids: &[String]) -> Vec<SessionActivity> {
+        self.activity.get(session_ids, now_ms())
+    }
+
+    /// 执行全量采集，前后向所有连接广播 CollectStarted / CollectFinished
+    ///
+    /// `trigger` 为触发来源（见 `protocol::collect_trigger`）。
+    /// 启动时分两阶段采集（先最近 7 天修改的文件），每个阶段各推送一对
+    /// CollectStarted / CollectFinished（带 `phase`），最近的项目可以更早显示。
+    pub async fn collect_all(&self, trigger: &str) -> Result<CollectSummary> {
+        let phased = trigger == collect_trigger::STARTUP;
+        let last_phase = phased.then(|| collect_phase::BACKLOG.to_string());
+        self.broadcast_push(&Push::CollectStarted {
+            trigger: trigger.to_string(),
+            phase: phased.then(|| collect_phase::RECENT.to_string()),
+            change_counter: self.change_counter(),
+        });
+
+        let db = self.db.clone();
+        let router = self.router.clone();
+        let connections = self.connections.clone();
+        let filter = self.filter.clone();
+        let limits = self.limits;
+        let phase_trigger = trigger.to_string();
+        let result = tokio::task::spawn_blocking(move || {
+            let collector = Collector::routed(&router)
+                .with_filter(filter)
+                .with_limits(limits);
+            if !phased {
+                return collector.collect_all();
+            }
+            collector.collect_all_phased(RECENT_COLLECT_WINDOW, |recent| {
+                let change_counter = db.change_counter().unwrap_or_default();
+                connections.broadcast_push(&Push::CollectFinished {
+                    trigger: phase_trigger.clone(),
+                    phase: Some(collect_phase::RECENT.to_string()),
+                    summary: recent.summary(),
+                    error: None,
+                    overview: None,
+                    change_counter,
+                });
+                connections.broadcast_push(&Push::CollectStarted {
+                    trigger: phase_trigger.clone(),
+                    phase: Some(collect_phase::BACKLOG.to_string()),
+                    change_counter,
+                });
+            })
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("spawn_blocking failed: {}", e))
+        .and_then(|r| r);
+
+        let (summary, error) = match &result {
+            Ok(result) => (result.summary(), None),
+            Err(e) => {
+                self.integrity.check_on_error(e);
+                (CollectSummary::default(), Some(e.to_string()))
+            }
+        };
+        *self.last_collect.lock() = Some(LastCollect {
+            trigger: trigger.to_string(),
+            finished_at: now_ms(),
+            summary: summary.clone(),
+            error: error.clone(),
+        });
+        // 启动时的全量采集完成后附带历史数据总量，供引导页展示
+        let overview = if phased && result.is_ok() {
+            self.history_totals().await
+        } else {
+            None
+        };
+        self.broadcast_push(&Push::CollectFinished {
+            trigger: trigger.to_string(),
+            phase: last_phase,
+            summary: summary.clone(),
+            error,
+            overview,
+            change_counter: self.change_counter(),
+        });
+
+        result.map(|_| summary)
+    }
+
+    /// 历史数据总量（读取失败时为空）
+    async fn history_totals(&self) -> Option<HistoryTotals> {
+        let db = self.db.clone();
+        match tokio::task::spawn_blocking(move || db.history_overview()).await {
+            Ok(Ok(overview)) => Some(overview.totals),
+            Ok(Err(e)) => {
+                tracing::warn!("Failed to get history overview: {}", e);
+                None
+            }
+            Err(e) => {
+                tracing::warn!("History overview task failed: {}", e);
+                None
+            }
+        }
+    }
+
+    /// 广播推送消息到所有连接
+    /// 当前全局变更计数（读取失败时为 0）
+    fn change_counter(&self) -> u64 {
+        self.db.change_counter().unwrap_or_default()
+    }
+
+    fn broadcast_push(&self, push: &Push) {
+        self.connections.broadcast_push(push);
+    }
+
+    /// 采集锁被其他进程持有时，延迟重试全量采集
+    pub fn defer_collect_all(self: &Arc<Self>, trigger: &'static str) {
+        let watcher = self.clone();
+        tokio::spawn(async move {
+            for _ in 0..COLLECT_RETRY_ATTEMPTS {
+                tokio::time::sleep(COLLECT_RETRY_DELAY).await;
+                match watcher.collect_all(trigger).await {
+                    Err(e) if collection_lock_holder(&e).is_some() => continue,
+                    Err(e) => {
+                        tracing::error!("Deferred collection failed: {}", e);
+                        return;
+                    }
+                    Ok(_) => return,
+                }
+            }
+            tracing::warn!("Deferred collection gave up: collection lock still held");
+        });
+    }
+
     /// 触发 Collection（供外部调用，如 Kit 通知）
-    pub async fn trigger_collect(&self, path: &Path) -> Result<()> {
+    ///
+    /// 采集锁被其他进程持有时稍后自动重试，本次调用直接返回 Ok。
+    pub async fn trigger_collect(self: &Arc<Self>, path: &Path) -> Result<()> {
+        self.collect_path(path, 0).await
+    }
+
+    /// 采集锁被其他进程持有时，延迟重试单个文件的采集
+    fn retry_collect_path(self: &Arc<Self>, path: PathBuf, attempt: u32) {
+        if attempt >= COLLECT_RETRY_ATTEMPTS {
+            tracing::warn!(
+                "Giving up collection of {:?}: collection lock still held",
+                path
+            );
+            return;
+        }
+        let watcher = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(COLLECT_RETRY_DELAY).await;
+            if let Err(e) = watcher.collect_path(&path, attempt + 1).await {
+                tracing::error!(
+                    "Failed to retry collection of {:?}: {}",
+                    path.file_name(),
+                    e
+                );
+            }
+        });
+    }
+
+    async fn collect_path(self: &Arc<Self>, path: &Path, attempt: u32) -> Result<()> {
         let path_str = path.to_str().ok_or_else(|| {
             anyhow::anyhow!("Cannot convert path: {:?}", path)
         })?.to_string();
 
         let path_clone = path.to_path_buf();
+        // session_id 与 collect_by_path 一致，取文件名
+        let session_id = path.file_stem().and_then(|s| s.to_str());
+        // 采集前没有消息：本次写入消息即为会话开始
+        let was_empty = session_id.is_some_and(|id| {
+            self.router
+                .for_session(id)
+                .and_then(|db| db.get_session_message_count(id))
+                .ok()
+                == Some(0)
+        });
 
         // 使用 spawn_blocking 避免阻塞 tokio runtime
-        let db = self.db.clone();
+        let router = self.router.clone();
+        let filter = self.filter.clone();
+        let limits = self.limits;
         let result = tokio::task::spawn_blocking(move || {
-            let collector = Collector::new(&db);
+            let collector = Collector::routed(&router)
+                .with_filter(filter)
+                .with_limits(limits);
             collector.collect_by_path(&path_str)
         })
         .await
-        .map_err(|e| anyhow::anyhow!("spawn_blocking failed: {}", e))??;
+        .map_err(|e| anyhow::anyhow!("spawn_blocking failed: {}", e))?;
+
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => match collection_lock_holder(&e) {
+                Some(holder) => {
+                    tracing::debug!(
+                        "Collection lock held by {}, retrying {:?} later",
+                        holder,
+                        path
+                    );
+                    self.retry_collect_path(path_clone, attempt);
+                    return Ok(());
+                }
+                None => return Err(e),
+            },
+        };
 
         if result.messages_inserted > 0 {
             tracing::debug!(
@@ -150,6 +554,570 @@ impl FileWatcher {
             );
         }
 
+        // 等待变更的客户端由数据库变更通知唤醒（见 `ConnectionManager::observe`）
+        if let Some(session_id) = session_id {
+            if was_empty && result.messages_inserted > 0 {
+                self.broadcast_session_started(session_id);
+            }
+        }
+
         Ok(())
     }
 }
+
+/// 监听目标
+#[derive(Debug, Clone)]
+struct WatchTarget {
+    name: String,
+    path: PathBuf,
+    recursive: bool,
+}
+
+/// 监听集合
+///
+/// 持有 debouncer，并记录每个目标当前监听的目录标识（inode/file_index）。
+/// 目录被删除或重建后，原监听失效且不会再有事件，需要 reconcile 重新建立。
+struct WatchSet {
+    debouncer: Debouncer<RecommendedWatcher>,
+    /// 防抖时间（重建 debouncer 时使用）
+    debounce: Duration,
+    targets: Vec<WatchTarget>,
+    /// 已建立的监听: path -> 建立监听时的目录标识
+    active: HashMap<PathBuf, file_id::FileId>,
+    /// watcher 回调报告了错误，下次 reconcile 强制重建
+    watch_error: Arc<AtomicBool>,
+    /// 事件发送端（用于检测接收端是否已关闭）
+    tx: mpsc::Sender<PathBuf>,
+}
+
+impl WatchSet {
+    fn new(
+        targets: Vec<WatchTarget>,
+        debounce: Duration,
+        tx: mpsc::Sender<PathBuf>,
+    ) -> Result<Self> {
+        let watch_error = Arc::new(AtomicBool::new(false));
+        let debouncer = Self::new_debouncer(debounce, &tx, &watch_error)?;
+
+        Ok(Self {
+            debouncer,
+            debounce,
+            targets,
+            active: HashMap::new(),
+            watch_error,
+            tx,
+        })
+    }
+
+    /// 创建 debouncer：事件发送到 `tx`，错误时设置 `watch_error`
+    fn new_debouncer(
+        debounce: Duration,
+        tx: &mpsc::Sender<PathBuf>,
+        watch_error: &Arc<AtomicBool>,
+    ) -> Result<Debouncer<RecommendedWatcher>> {
+        let error_flag = watch_error.clone();
+        let event_tx = tx.clone();
+        let debouncer = new_debouncer(debounce, move |res: DebounceEventResult| match res {
+            Ok(events) => {
+                for event in events {
+                    if event.kind == DebouncedEventKind::Any {
+                        let _ = event_tx.blocking_send(event.path);
+                    }
+                }
+            }
+            Err(e) => {
+                tracing::warn!("⚠️ File watcher error: {}", e);
+                error_flag.store(true, Ordering::Relaxed);
+            }
+        })?;
+        Ok(debouncer)
+    }
+
+    /// 丢弃全部监听，用新的 debouncer 重新建立，返回当前有效的监听数
+    ///
+    /// 休眠唤醒后内核中的监听可能已丢失，而目录标识没有变化，`reconcile` 无法察觉。
+    fn rebuild(&mut self) -> Result<usize> {
+        self.debouncer = Self::new_debouncer(self.debounce, &self.tx, &self.watch_error)?;
+        self.active.clear();
+        Ok(self.reconcile(true))
+    }
+
+    /// 校验并重建监听，返回当前有效的监听数
+    ///
+    /// - 目录不存在：移除旧监听，等待目录出现
+    /// - 目录标识变化（被删除后重建）或 force：重新建立监听
+    fn reconcile(&mut self, force: bool) -> usize {
+        for target in &self.targets {
+            let current_id = file_id::get_file_id(&target.path).ok();
+            let active_id = self.active.get(&target.path);
+
+            let Some(current_id) = current_id else {
+                if self.active.remove(&target.path).is_some() {
+                    let _ = self.debouncer.watcher().unwatch(&target.path);
+                    tracing::warn!(
+                        "⚠️ {} directory removed, waiting for it to reappear: {:?}",
+                        target.name,
+                        target.path
+                    );
+                }
+                continue;
+            };
+
+            if !force && active_id == Some(&current_id) {
+                continue;
+            }
+
+            let is_rewatch = active_id.is_some();
+            if is_rewatch {
+                let _ = self.debouncer.watcher().unwatch(&target.path);
+            }
+
+            let recursive_mode = if target.recursive {
+                RecursiveMode::Recursive
+            } else {
+                RecursiveMode::NonRecursive
+            };
+
+            match self.debouncer.watcher().watch(&target.path, recursive_mode) {
+                Ok(_) => {
+                    self.active.insert(target.path.clone(), current_id);
+                    if is_rewatch {
+                        tracing::info!(
+                            "👁️ Re-watching {} directory: {:?}",
+                            target.name,
+                            target.path
+                        );
+                    } else {
+                        tracing::info!("👁️ Watching {} directory: {:?}", target.name, target.path);
+                    }
+                }
+                Err(e) => {
+                    self.active.remove(&target.path);
+                    tracing::warn!(
+                        "⚠️ Failed to watch {} directory {:?}: {}",
+                        target.name,
+                        target.path,
+                        e
+                    );
+                }
+            }
+        }
+
+        self.active.len()
+    }
+
+    /// 定期 reconcile，收到 `rewatch` 通知时整体重建，直到事件接收端关闭
+    async fn run(mut self, interval: Duration, rewatch: Arc<Notify>) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await; // 第一次 tick 立即返回
+
+        loop {
+            let rebuild = tokio::select! {
+                _ = ticker.tick() => false,
+                _ = rewatch.notified() => true,
+            };
+
+            if self.tx.is_closed() {
+                break;
+            }
+
+            if rebuild {
+                match self.rebuild() {
+                    Ok(watched) => tracing::info!(
+                        "👁️ Re-registered watchers ({}/{} directories)",
+                        watched,
+                        self.targets.len()
+                    ),
+                    Err(e) => tracing::warn!("⚠️ Failed to re-register watchers: {}", e),
+                }
+                continue;
+            }
+
+            let force = self.watch_error.swap(false, Ordering::Relaxed);
+            self.reconcile(force);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::{sleep, timeout};
+
+    /// 等待指定文件名的事件
+    async fn wait_for_event(rx: &mut mpsc::Receiver<PathBuf>, name: &str) -> bool {
+        timeout(Duration::from_secs(5), async {
+            while let Some(path) = rx.recv().await {
+                if path.file_name().and_then(|n| n.to_str()) == Some(name) {
+                    return true;
+                }
+            }
+            false
+        })
+        .await
+        .unwrap_or(false)
+    }
+
+    #[tokio::test]
+    async fn test_watch_recovers_after_dir_recreated() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("projects");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let (tx, mut rx) = mpsc::channel::<PathBuf>(100);
+        let targets = vec![WatchTarget {
+            name: "test".to_string(),
+            path: dir.clone(),
+            recursive: true,
+        }];
+        let mut watch_set = WatchSet::new(targets, Duration::from_millis(100), tx).unwrap();
+        assert_eq!(watch_set.reconcile(true), 1);
+        tokio::spawn(watch_set.run(Duration::from_millis(200), Arc::new(Notify::new())));
+
+        std::fs::write(dir.join("before.jsonl"), "{}\n").unwrap();
+        assert!(wait_for_event(&mut rx, "before.jsonl").await);
+
+        // 删除并重建被监听的目录
+        std::fs::remove_dir_all(&dir).unwrap();
+        sleep(Duration::from_millis(500)).await;
+        std::fs::create_dir_all(&dir).unwrap();
+        sleep(Duration::from_millis(500)).await;
+
+        std::fs::write(dir.join("after.jsonl"), "{}\n").unwrap();
+        assert!(wait_for_event(&mut rx, "after.jsonl").await);
+    }
+
+    #[tokio::test]
+    async fn test_watch_waits_for_missing_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("not-yet");
+
+        let (tx, mut rx) = mpsc::channel::<PathBuf>(100);
+        let targets = vec![WatchTarget {
+            name: "test".to_string(),
+            path: dir.clone(),
+            recursive: true,
+        }];
+        let mut watch_set = WatchSet::new(targets, Duration::from_millis(100), tx).unwrap();
+        assert_eq!(watch_set.reconcile(true), 0);
+        tokio::spawn(watch_set.run(Duration::from_millis(200), Arc::new(Notify::new())));
+
+        std::fs::create_dir_all(&dir).unwrap();
+        sleep(Duration::from_millis(500)).await;
+
+        std::fs::write(dir.join("late.jsonl"), "{}\n").unwrap();
+        assert!(wait_for_event(&mut rx, "late.jsonl").await);
+    }
+
+    #[tokio::test]
+    async fn test_rewatch_recreates_lost_watches() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("projects");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let (tx, mut rx) = mpsc::channel::<PathBuf>(100);
+        let targets = vec![WatchTarget {
+            name: "test".to_string(),
+            path: dir.clone(),
+            recursive: true,
+        }];
+        let mut watch_set = WatchSet::new(targets, Duration::from_millis(100), tx).unwrap();
+        assert_eq!(watch_set.reconcile(true), 1);
+
+        // 模拟休眠后内核丢失监听：目录标识未变，定期 reconcile 不会重建
+        watch_set.debouncer.watcher().unwatch(&dir).unwrap();
+        assert_eq!(watch_set.reconcile(false), 1);
+
+        let rewatch = Arc::new(Notify::new());
+        tokio::spawn(watch_set.run(Duration::from_secs(60), rewatch.clone()));
+        rewatch.notify_one();
+        sleep(Duration::from_millis(300)).await;
+
+        std::fs::write(dir.join("after-wake.jsonl"), "{}\n").unwrap();
+        assert!(wait_for_event(&mut rx, "after-wake.jsonl").await);
+    }
+
+    #[tokio::test]
+    async fn test_clock_jump_triggers_wake_recovery() {
+        use crate::protocol::collect_trigger;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Arc::new(
+            SessionDB::connect(crate::DbConfig::local(tmp.path().join("test.db"))).unwrap(),
+        );
+        let connections = ConnectionManager::new();
+        let (tx, mut rx) = mpsc::channel::<String>(100);
+        connections.register(tx);
+        let watcher = FileWatcher::new(
+            Arc::new(DbRouter::new(db.clone())),
+            connections.clone(),
+            IntegrityMonitor::new(db, connections),
+            CollectionFilter::default(),
+            CollectLimits::default(),
+            Duration::from_secs(5),
+            Duration::from_secs(120),
+        );
+
+        let reading = |monotonic_secs: u64, wall_secs: i64| ClockReading {
+            monotonic: Duration::from_secs(monotonic_secs),
+            wall_ms: 1_700_000_000_000 + wall_secs * 1000,
+        };
+        let mut detector = WakeDetector::new(WAKE_DRIFT_THRESHOLD);
+        assert!(!watcher.check_wake(&mut detector, reading(0, 0)).await);
+        assert!(!watcher.check_wake(&mut detector, reading(30, 30)).await);
+        assert!(rx.try_recv().is_err());
+
+        // 单调时钟走了 30 秒，墙上时钟走了 1 小时
+        assert!(watcher.check_wake(&mut detector, reading(60, 3660)).await);
+
+        // 监听集合收到重建通知
+        timeout(Duration::from_secs(1), watcher.rewatch.notified())
+            .await
+            .expect("watchers should be re-registered");
+
+        // 先推送 AgentResumed，随后补采
+        let mut pushes = Vec::new();
+        while let Ok(line) = rx.try_recv() {
+            pushes.push(serde_json::from_str::<Push>(&line).unwrap());
+        }
+        assert_eq!(pushes.len(), 3, "{:?}", pushes);
+        assert!(matches!(
+            pushes[0],
+            Push::AgentResumed {
+                slept_secs: 3600,
+                ..
+            }
+        ));
+        assert!(matches!(
+            &pushes[1],
+            Push::CollectStarted { trigger, .. } if trigger == collect_trigger::WAKE
+        ));
+        assert!(matches!(
+            &pushes[2],
+            Push::CollectFinished { trigger, .. } if trigger == collect_trigger::WAKE
+        ));
+    }
+    #[tokio::test]
+    async fn test_activity_pushed_only_on_transition() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Arc::new(
+            SessionDB::connect(crate::DbConfig::local(tmp.path().join("test.db"))).unwrap(),
+        );
+        let connections = ConnectionManager::new();
+        let (tx, mut rx) = mpsc::channel::<String>(100);
+        connections.register(tx);
+
+        let watcher = FileWatcher::new(
+            Arc::new(DbRouter::new(db.clone())),
+            connections.clone(),
+            IntegrityMonitor::new(db, connections),
+            CollectionFilter::default(),
+            CollectLimits::default(),
+            Duration::from_secs(5),
+            Duration::from_secs(120),
+        );
+        let path = Path::new("/tmp/project/session-a.jsonl");
+
+        // 连续写入只推送一次 Streaming
+        watcher.record_activity(path, 1_000);
+        watcher.record_activity(path, 2_000);
+        watcher.record_activity(path, 3_000);
+        for activity in watcher.activity.tick(4_000) {
+            watcher.broadcast_activity(activity);
+        }
+        // 超过 Streaming 窗口推送 RecentlyActive
+        for activity in watcher.activity.tick(9_000) {
+            watcher.broadcast_activity(activity);
+        }
+
+        let mut pushes = Vec::new();
+        while let Ok(line) = rx.try_recv() {
+            pushes.push(serde_json::from_str::<Push>(&line).unwrap());
+        }
+        assert_eq!(pushes.len(), 2);
+        let states: Vec<_> = pushes
+            .iter()
+            .map(|push| match push {
+                Push::SessionActivityChanged {
+                    session_id, state, ..
+                } => {
+                    assert_eq!(session_id, "session-a");
+                    *state
+                }
+                other => panic!("Expected SessionActivityChanged, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                SessionActivityState::Streaming,
+                SessionActivityState::RecentlyActive
+            ]
+        );
+
+        let activity = watcher.session_activity(&["session-a".to_string()]);
+        assert_eq!(activity[0].last_event_ms, Some(3_000));
+    }
+
+    /// 写入一问一答的会话，返回注册了推送接收端的监听器（静默期 60 秒）
+    fn lifecycle_watcher(
+        tmp: &tempfile::TempDir,
+        session_id: &str,
+    ) -> (Arc<FileWatcher>, mpsc::Receiver<String>) {
+        use crate::db::{MessageInput, SessionInput};
+
+        let db = Arc::new(
+            SessionDB::connect(crate::DbConfig::local(tmp.path().join("test.db"))).unwrap(),
+        );
+        let project_id = db
+            .get_or_create_project("project", "/tmp/project", "claude")
+            .unwrap();
+        db.upsert_session_full(&SessionInput {
+            session_id: session_id.to_string(),
+            project_id,
+            source: Some("claude".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        let message = |i: i64, r#type: MessageType, text: &str| MessageInput {
+            uuid: format!("{}-{}", session_id, i),
+            r#type,
+            content_text: text.to_string(),
+            content_full: text.to_string(),
+            timestamp: 1_000 + i,
+            sequence: i,
+            source: None,
+            channel: None,
+            model: None,
+            tool_call_id: None,
+            tool_name: None,
+            tool_args: None,
+            raw: None,
+            approval_status: None,
+            approval_resolved_at: None,
+        };
+        db.insert_messages(
+            session_id,
+            &[
+                message(0, MessageType::User, "fix the build"),
+                message(1, MessageType::Assistant, "Done, the build passes."),
+            ],
+        )
+        .unwrap();
+
+        let connections = ConnectionManager::new();
+        let (tx, rx) = mpsc::channel::<String>(100);
+        connections.register(tx);
+        let watcher = FileWatcher::new(
+            Arc::new(DbRouter::new(db.clone())),
+            connections.clone(),
+            IntegrityMonitor::new(db, connections),
+            CollectionFilter::default(),
+            CollectLimits::default(),
+            Duration::from_secs(5),
+            Duration::from_secs(60),
+        );
+        (watcher, rx)
+    }
+
+    fn drain_pushes(rx: &mut mpsc::Receiver<String>) -> Vec<Push> {
+        let mut pushes = Vec::new();
+        while let Ok(line) = rx.try_recv() {
+            pushes.push(serde_json::from_str::<Push>(&line).unwrap());
+        }
+        pushes
+    }
+
+    #[tokio::test]
+    async fn test_session_idle_pushed_once_after_quiet_period() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (watcher, mut rx) = lifecycle_watcher(&tmp, "session-a");
+        let path = Path::new("/tmp/project/session-a.jsonl");
+
+        watcher.record_activity(path, 1_000);
+        watcher.record_activity(path, 4_000);
+        watcher.refresh_activity(30_000);
+        watcher.refresh_activity(63_000);
+        let is_idle = |push: &Push| matches!(push, Push::SessionIdle { .. });
+        assert!(!drain_pushes(&mut rx).iter().any(is_idle));
+
+        // 最后一次事件后 60 秒
+        watcher.refresh_activity(64_000);
+        let idle: Vec<_> = drain_pushes(&mut rx).into_iter().filter(is_idle).collect();
+        assert_eq!(idle.len(), 1);
+        match &idle[0] {
+            Push::SessionIdle {
+                session_id,
+                last_assistant_preview,
+                message_count,
+                last_event_ms,
+                ..
+            } => {
+                assert_eq!(session_id, "session-a");
+                assert_eq!(
+                    last_assistant_preview.as_deref(),
+                    Some("Done, the build passes.")
+                );
+                assert_eq!(*message_count, 2);
+                assert_eq!(*last_event_ms, 4_000);
+            }
+            other => panic!("Expected SessionIdle, got {:?}", other),
+        }
+
+        // 保持静默不再重复推送
+        watcher.refresh_activity(120_000);
+        watcher.refresh_activity(600_000);
+        assert!(drain_pushes(&mut rx).is_empty());
+
+        // 再次活跃后重新计时
+        watcher.record_activity(path, 700_000);
+        watcher.refresh_activity(760_000);
+        let pushes = drain_pushes(&mut rx);
+        assert_eq!(pushes.iter().filter(|p| is_idle(p)).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_session_started_and_ended_pushes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (watcher, mut rx) = lifecycle_watcher(&tmp, "session-b");
+
+        watcher.broadcast_session_started("session-b");
+        match drain_pushes(&mut rx).as_slice() {
+            [Push::SessionStarted {
+                session_id,
+                project_path,
+                source,
+                title,
+                started_at,
+                ..
+            }] => {
+                assert_eq!(session_id, "session-b");
+                assert_eq!(project_path, "/tmp/project");
+                assert_eq!(source.as_deref(), Some("claude"));
+                assert_eq!(title.as_deref(), Some("fix the build"));
+                assert_eq!(*started_at, 1_000);
+            }
+            other => panic!("Expected SessionStarted, got {:?}", other),
+        }
+
+        // 会话结束后直接进入 Idle，不再推送 SessionIdle
+        watcher.record_activity(Path::new("/tmp/project/session-b.jsonl"), 1_000);
+        drain_pushes(&mut rx);
+        watcher.end_session("session-b");
+        let pushes = drain_pushes(&mut rx);
+        assert_eq!(pushes.len(), 2, "{:?}", pushes);
+        assert!(matches!(
+            pushes[0],
+            Push::SessionActivityChanged {
+                state: SessionActivityState::Idle,
+                ..
+            }
+        ));
+        assert!(matches!(
+            &pushes[1],
+            Push::SessionEnded { session_id, message_count: 2, .. } if session_id == "session-b"
+        ));
+        watcher.refresh_activity(1_000_000);
+        assert!(drain_pushes(&mut rx).is_empty());
+    }
+}
@@ -0,0 +1,220 @@
+//! 审批审计
+//!
+//! 列出工具调用的审批请求及其结果（按项目和时间范围过滤），供合规导出使用。
+//! 每行包含消息、会话和项目标识，以及从请求到解决的耗时（`approval_resolved_at - timestamp`）。
+//!
+//! 导出逐行写入 `Write`，不把全部结果读入内存。
+
+use std::io::Write;
+
+use rusqlite::{params, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::db::SessionDB;
+use crate::error::Result;
+use crate::types::ApprovalStatus;
+
+/// tool_args 预览的最大字符数
+pub const AUDIT_ARGS_PREVIEW_CHARS: usize = 200;
+
+/// CSV 表头（列顺序同 `ApprovalAuditRow` 字段）
+const CSV_HEADER: &str = "message_id,uuid,session_id,project_id,project_name,project_path,\
+tool_call_id,tool_name,tool_args_preview,approval_status,requested_at,resolved_at,\
+resolution_latency_ms,timed_out";
+
+/// 一次审批请求及其结果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApprovalAuditRow {
+    pub message_id: i64,
+    pub uuid: String,
+    pub session_id: String,
+    pub project_id: i64,
+    pub project_name: String,
+    pub project_path: String,
+    pub tool_call_id: Option<String>,
+    pub tool_name: Option<String>,
+    /// tool_args 前 `AUDIT_ARGS_PREVIEW_CHARS` 个字符
+    pub tool_args_preview: Option<String>,
+    /// 无法识别的状态值原样保留
+    pub approval_status: String,
+    /// 请求时间（消息时间戳，毫秒）
+    pub requested_at: i64,
+    /// 解决时间（毫秒，未解决时为空）
+    pub resolved_at: Option<i64>,
+    /// 从请求到解决的耗时（毫秒，未解决时为空）
+    pub resolution_latency_ms: Option<i64>,
+    /// 是否因超时而解决
+    pub timed_out: bool,
+}
+
+/// 审计导出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditFormat {
+    #[default]
+    Csv,
+    /// JSON 数组
+    Json,
+}
+
+const AUDIT_SELECT: &str = r#"
+    SELECT m.id, m.uuid, m.session_id, p.id, p.name, p.path,
+           m.tool_call_id, m.tool_name, substr(m.tool_args, 1, ?4),
+           m.approval_status, m.timestamp, m.approval_resolved_at
+    FROM messages m
+    JOIN sessions s ON s.session_id = m.session_id
+    JOIN projects p ON p.id = s.project_id
+    WHERE m.approval_status IS NOT NULL
+      AND (?1 IS NULL OR p.id = ?1)
+      AND (?2 IS NULL OR m.timestamp >= ?2)
+      AND (?3 IS NULL OR m.timestamp <= ?3)
+    ORDER BY m.timestamp ASC, m.id ASC
+"#;
+
+fn audit_row(row: &Row) -> rusqlite::Result<ApprovalAuditRow> {
+    let approval_status: String = row.get(9)?;
+    let requested_at: i64 = row.get(10)?;
+    let resolved_at: Option<i64> = row.get(11)?;
+    Ok(ApprovalAuditRow {
+        message_id: row.get(0)?,
+        uuid: row.get(1)?,
+        session_id: row.get(2)?,
+        project_id: row.get(3)?,
+        project_name: row.get(4)?,
+        project_path: row.get(5)?,
+        tool_call_id: row.get(6)?,
+        tool_name: row.get(7)?,
+        tool_args_preview: row.get(8)?,
+        timed_out: approval_status.parse::<ApprovalStatus>() == Ok(ApprovalStatus::Timeout),
+        approval_status,
+        requested_at,
+        resolved_at,
+        resolution_latency_ms: resolved_at.map(|resolved| resolved - requested_at),
+    })
+}
+
+impl SessionDB {
+    /// 审批审计记录（按请求时间升序，分页）
+    ///
+    /// - project_id: 只返回该项目的记录，None 表示全部项目
+    /// - start_ms / end_ms: 请求时间范围（毫秒，闭区间），None 表示不限制
+    ///
+    /// 包含仍在等待的请求（`resolved_at` 为空）。
+    pub fn approval_audit(
+        &self,
+        project_id: Option<i64>,
+        start_ms: Option<i64>,
+        end_ms: Option<i64>,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<ApprovalAuditRow>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(&format!("{} LIMIT ?5 OFFSET ?6", AUDIT_SELECT))?;
+        let rows = stmt.query_map(
+            params![
+                project_id,
+                start_ms,
+                end_ms,
+                AUDIT_ARGS_PREVIEW_CHARS as i64,
+                limit as i64,
+                offset as i64
+            ],
+            audit_row,
+        )?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Into::into)
+    }
+
+    /// 导出审批审计记录（过滤条件同 `approval_audit`，不分页），返回导出的行数
+    ///
+    /// 逐行读取并写入 `writer`；导出期间持有数据库连接。
+    pub fn export_approval_audit(
+        &self,
+        project_id: Option<i64>,
+        start_ms: Option<i64>,
+        end_ms: Option<i64>,
+        format: AuditFormat,
+        mut writer: impl Write,
+    ) -> Result<usize> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(AUDIT_SELECT)?;
+        let mut rows = stmt.query(params![
+            project_id,
+            start_ms,
+            end_ms,
+            AUDIT_ARGS_PREVIEW_CHARS as i64
+        ])?;
+
+        match format {
+            AuditFormat::Csv => writeln!(writer, "{}", CSV_HEADER)?,
+            AuditFormat::Json => write!(writer, "[")?,
+        }
+        let mut count = 0;
+        while let Some(row) = rows.next()? {
+            let row = audit_row(row)?;
+            match format {
+                AuditFormat::Csv => writeln!(writer, "{}", csv_line(&row))?,
+                AuditFormat::Json => {
+                    if count > 0 {
+                        write!(writer, ",")?;
+                    }
+                    serde_json::to_writer(&mut writer, &row)?;
+                }
+            }
+            count += 1;
+        }
+        if format == AuditFormat::Json {
+            writeln!(writer, "]")?;
+        }
+        writer.flush()?;
+        Ok(count)
+    }
+}
+
+/// 一行 CSV（不含换行符）
+fn csv_line(row: &ApprovalAuditRow) -> String {
+    let opt = |v: Option<i64>| v.map(|v| v.to_string()).unwrap_or_default();
+    [
+        row.message_id.to_string(),
+        csv_field(&row.uuid),
+        csv_field(&row.session_id),
+        row.project_id.to_string(),
+        csv_field(&row.project_name),
+        csv_field(&row.project_path),
+        csv_field(row.tool_call_id.as_deref().unwrap_or("")),
+        csv_field(row.tool_name.as_deref().unwrap_or("")),
+        csv_field(row.tool_args_preview.as_deref().unwrap_or("")),
+        csv_field(&row.approval_status),
+        row.requested_at.to_string(),
+        opt(row.resolved_at),
+        opt(row.resolution_latency_ms),
+        row.timed_out.to_string(),
+    ]
+    .join(",")
+}
+
+/// 按 RFC 4180 转义 CSV 字段：含逗号、引号或换行时加引号，引号加倍
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_field_escaping() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(
+            csv_field(r#"{"cmd":"echo \"hi\""}"#),
+            r#""{""cmd"":""echo \""hi\""""}""#
+        );
+        assert_eq!(csv_field("line1\nline2"), "\"line1\nline2\"");
+        assert_eq!(csv_field(""), "");
+    }
+}
@@ -3,7 +3,7 @@
 //! 从 Archive 目录读取 JSONL 文件，使用新的内容分离逻辑导入到数据库
 
 use ai_cli_session_collector::{ClaudeAdapter, IndexableSession};
-use ai_cli_session_db::migrations;
+use ai_cli_session_db::{migrations, schema};
 use anyhow::Result;
 use rusqlite::{params, Connection};
 use std::collections::HashSet;
@@ -87,26 +87,15 @@ fn main() -> Result<()> {
     println!("Messages inserted: {}", total_messages);
     println!("Messages updated: {}", updated_messages);
 
-    // 重建 FTS 和触发器
+    // 重建 FTS（全部消息已建索引，清空补建队列）和触发器
     println!("\nRebuilding FTS index...");
-    conn.execute(
-        "INSERT INTO messages_fts(messages_fts) VALUES('rebuild')",
-        [],
-    )?;
-
     conn.execute_batch(
-        "CREATE TRIGGER IF NOT EXISTS messages_ai AFTER INSERT ON messages BEGIN
-            INSERT INTO messages_fts(rowid, content_full) VALUES (new.id, new.content_full);
-         END;
-         CREATE TRIGGER IF NOT EXISTS messages_ad AFTER DELETE ON messages BEGIN
-            INSERT INTO messages_fts(messages_fts, rowid, content_full) VALUES('delete', old.id, old.content_full);
-         END;
-         CREATE TRIGGER IF NOT EXISTS messages_au AFTER UPDATE ON messages BEGIN
-            INSERT INTO messages_fts(messages_fts, rowid, content_full) VALUES('delete', old.id, old.content_full);
-            INSERT INTO messages_fts(rowid, content_full) VALUES (new.id, new.content_full);
-         END;",
+        "INSERT INTO messages_fts(messages_fts) VALUES('rebuild');
+         DELETE FROM fts_backlog;",
     )?;
 
+    conn.execute_batch(schema::FTS_SCHEMA_SQL)?;
+
     println!("Done");
 
     Ok(())
@@ -26,15 +26,26 @@ async fn main() -> Result<()> {
 
     tracing::info!("🚀 vimo-agent v{}", env!("CARGO_PKG_VERSION"));
 
-    let config = AgentConfig::default();
+    // ~/.vimo/config.toml 和 VIMO_CONFIG_* 环境变量
+    #[allow(unused_mut)]
+    let mut config = AgentConfig::load(&AgentConfig::default().data_dir);
+
+    // launchd / systemd socket activation：接管预绑定的监听 fd
+    #[cfg(unix)]
+    {
+        config.listen_fd = ai_cli_session_db::agent::inherited_listener_fd();
+    }
 
     if is_agent_running(&config) {
         tracing::error!("❌ Agent is already running, exiting");
         std::process::exit(1);
     }
 
-    if let Err(e) = cleanup_stale_agent(&config) {
-        tracing::warn!("Failed to cleanup stale state: {}", e);
+    // socket activation 时 socket 文件由服务管理器持有，不能删除
+    if config.listen_fd.is_none() {
+        if let Err(e) = cleanup_stale_agent(&config) {
+            tracing::warn!("Failed to cleanup stale state: {}", e);
+        }
     }
 
     let agent = Arc::new(Agent::new(config)?);
@@ -0,0 +1,221 @@
+//! Agent 二进制架构检查
+//!
+//! 启动 Agent 前读取可执行文件头（ELF / Mach-O / PE），与当前进程架构比较，
+//! 架构不匹配时直接报错，而不是等 exec 失败或 Rosetta 转译后连接超时。
+//! 架构名与 `std::env::consts::ARCH` 一致。
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// 读取的文件头长度（覆盖 fat Mach-O 的前几个架构条目和 PE 头偏移）
+const HEADER_LEN: usize = 4096;
+
+/// 检查 Agent 二进制架构是否与当前进程一致
+///
+/// 无法识别的格式（如脚本）不检查，交给操作系统处理。
+pub(super) fn check_agent_arch(path: &Path) -> Result<()> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    File::open(path)
+        .and_then(|f| f.take(HEADER_LEN as u64).read_to_end(&mut header))
+        .with_context(|| format!("Failed to read Agent binary {}", path.display()))?;
+
+    let Some(arches) = binary_arches(&header) else {
+        return Ok(());
+    };
+    let current = std::env::consts::ARCH;
+    if arches.contains(&current) {
+        return Ok(());
+    }
+    Err(anyhow::anyhow!(
+        "Agent binary {} is built for {} but this process runs on {}. \
+         Deploy a vimo-agent built for {}, or set ClientConfig::allow_agent_arch_mismatch \
+         to run it anyway (e.g. under Rosetta).",
+        path.display(),
+        arches.join("/"),
+        current,
+        current
+    ))
+}
+
+/// 解析可执行文件头，返回包含的架构（fat Mach-O 可能有多个），无法识别时返回 None
+fn binary_arches(header: &[u8]) -> Option<Vec<&'static str>> {
+    match header.get(..4)? {
+        [0x7f, b'E', b'L', b'F'] => {
+            // e_ident[EI_DATA]: 1 = 小端, 2 = 大端；e_machine 位于偏移 18
+            let machine = read_u16(header, 18, *header.get(5)? == 2)?;
+            Some(vec![elf_arch(machine)?])
+        }
+        [0xcf, 0xfa, 0xed, 0xfe] | [0xce, 0xfa, 0xed, 0xfe] => {
+            Some(vec![macho_arch(read_u32(header, 4, false)?)?])
+        }
+        [0xca, 0xfe, 0xba, 0xbe] | [0xca, 0xfe, 0xba, 0xbf] => {
+            // fat_arch 条目 20 字节（fat_arch_64 为 32 字节），cputype 在条目开头
+            let entry_len = if header[3] == 0xbf { 32 } else { 20 };
+            let count = read_u32(header, 4, true)? as usize;
+            // 与 Java class 文件（同样以 CAFEBABE 开头）区分：架构数不会很大
+            if count == 0 || count > 16 {
+                return None;
+            }
+            let arches: Vec<_> = (0..count)
+                .filter_map(|i| read_u32(header, 8 + i * entry_len, true))
+                .filter_map(macho_arch)
+                .collect();
+            (!arches.is_empty()).then_some(arches)
+        }
+        [b'M', b'Z', ..] => {
+            let pe_offset = read_u32(header, 0x3c, false)? as usize;
+            if header.get(pe_offset..pe_offset + 4)? != b"PE\0\0" {
+                return None;
+            }
+            Some(vec![pe_arch(read_u16(header, pe_offset + 4, false)?)?])
+        }
+        _ => None,
+    }
+}
+
+fn elf_arch(machine: u16) -> Option<&'static str> {
+    match machine {
+        3 => Some("x86"),
+        40 => Some("arm"),
+        62 => Some("x86_64"),
+        183 => Some("aarch64"),
+        243 => Some("riscv64"),
+        _ => None,
+    }
+}
+
+fn macho_arch(cputype: u32) -> Option<&'static str> {
+    match cputype {
+        0x0000_0007 => Some("x86"),
+        0x0000_000c => Some("arm"),
+        0x0100_0007 => Some("x86_64"),
+        0x0100_000c => Some("aarch64"),
+        _ => None,
+    }
+}
+
+fn pe_arch(machine: u16) -> Option<&'static str> {
+    match machine {
+        0x014c => Some("x86"),
+        0x8664 => Some("x86_64"),
+        0xaa64 => Some("aarch64"),
+        _ => None,
+    }
+}
+
+fn read_u16(buf: &[u8], offset: usize, big_endian: bool) -> Option<u16> {
+    let bytes: [u8; 2] = buf.get(offset..offset + 2)?.try_into().ok()?;
+    Some(if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    })
+}
+
+fn read_u32(buf: &[u8], offset: usize, big_endian: bool) -> Option<u32> {
+    let bytes: [u8; 4] = buf.get(offset..offset + 4)?.try_into().ok()?;
+    Some(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 64 位小端 ELF 头（只填到 e_machine）
+    fn elf(machine: u16) -> Vec<u8> {
+        let mut header = vec![0x7f, b'E', b'L', b'F', 2, 1, 1, 0];
+        header.resize(18, 0);
+        header.extend_from_slice(&machine.to_le_bytes());
+        header
+    }
+
+    /// 64 位 Mach-O 头（只填到 cputype）
+    fn macho(cputype: u32) -> Vec<u8> {
+        let mut header = vec![0xcf, 0xfa, 0xed, 0xfe];
+        header.extend_from_slice(&cputype.to_le_bytes());
+        header
+    }
+
+    /// PE 头：DOS 头 e_lfanew 指向 0x80
+    fn pe(machine: u16) -> Vec<u8> {
+        let mut header = vec![b'M', b'Z'];
+        header.resize(0x3c, 0);
+        header.extend_from_slice(&0x80u32.to_le_bytes());
+        header.resize(0x80, 0);
+        header.extend_from_slice(b"PE\0\0");
+        header.extend_from_slice(&machine.to_le_bytes());
+        header
+    }
+
+    #[test]
+    fn test_elf_arches() {
+        assert_eq!(binary_arches(&elf(62)), Some(vec!["x86_64"]));
+        assert_eq!(binary_arches(&elf(183)), Some(vec!["aarch64"]));
+
+        // 大端 ELF
+        let mut header = vec![0x7f, b'E', b'L', b'F', 2, 2, 1, 0];
+        header.resize(18, 0);
+        header.extend_from_slice(&62u16.to_be_bytes());
+        assert_eq!(binary_arches(&header), Some(vec!["x86_64"]));
+    }
+
+    #[test]
+    fn test_macho_arches() {
+        assert_eq!(binary_arches(&macho(0x0100_0007)), Some(vec!["x86_64"]));
+        assert_eq!(binary_arches(&macho(0x0100_000c)), Some(vec!["aarch64"]));
+    }
+
+    #[test]
+    fn test_fat_macho_arches() {
+        let mut header = vec![0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 2];
+        for cputype in [0x0100_0007u32, 0x0100_000c] {
+            header.extend_from_slice(&cputype.to_be_bytes());
+            header.extend_from_slice(&[0; 16]);
+        }
+        assert_eq!(binary_arches(&header), Some(vec!["x86_64", "aarch64"]));
+
+        // Java class 文件（版本号被当作架构数）不识别
+        let class = [0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 65];
+        assert_eq!(binary_arches(&class), None);
+    }
+
+    #[test]
+    fn test_pe_arches() {
+        assert_eq!(binary_arches(&pe(0x8664)), Some(vec!["x86_64"]));
+        assert_eq!(binary_arches(&pe(0xaa64)), Some(vec!["aarch64"]));
+    }
+
+    #[test]
+    fn test_unknown_formats() {
+        assert_eq!(binary_arches(b"#!/bin/sh\nexit 0\n"), None);
+        assert_eq!(binary_arches(&[0x7f, b'E']), None);
+        assert_eq!(binary_arches(&elf(0xffff)), None);
+    }
+
+    #[test]
+    fn test_check_agent_arch_mismatch() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let other = if std::env::consts::ARCH == "x86_64" {
+            183
+        } else {
+            62
+        };
+        let path = tmp.path().join("vimo-agent");
+        std::fs::write(&path, elf(other)).unwrap();
+
+        let message = check_agent_arch(&path).unwrap_err().to_string();
+        assert!(message.contains(std::env::consts::ARCH));
+        assert!(message.contains(&path.display().to_string()));
+
+        let script = tmp.path().join("script");
+        std::fs::write(&script, "#!/bin/sh\n").unwrap();
+        assert!(check_agent_arch(&script).is_ok());
+    }
+}
@@ -6,7 +6,7 @@
 
 use std::fs::{self, OpenOptions};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::process::{Child, Command, Stdio};
 use std::time::Duration;
 
 use anyhow::{Context, Result};
@@ -22,6 +22,19 @@ use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, WriteHalf};
 use tokio::sync::mpsc;
 use tokio::time::sleep;
 
+use super::arch::check_agent_arch;
+use crate::config_file::ConfigFile;
+use crate::migrations::SchemaCompatibility;
+
+/// socket activation 模式下等待 Agent 可连接的最长时间
+const SOCKET_ACTIVATION_DEADLINE: Duration = Duration::from_secs(10);
+
+/// Agent 启动失败时错误信息中附带的日志行数
+const AGENT_LOG_TAIL_LINES: usize = 20;
+
+/// 收到 `Push::AgentResumed` 后校验连接的 Heartbeat 超时
+const RESUME_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Client 配置
 #[derive(Debug, Clone)]
 pub struct ClientConfig {
@@ -39,6 +52,18 @@ pub struct ClientConfig {
     pub agent_binary_override: Option<PathBuf>,
     /// Agent 源目录（用于首次部署，如 plugin bundle 的 Lib 目录）
     pub agent_source_dir: Option<PathBuf>,
+    /// Agent 由 launchd / systemd socket activation 管理
+    ///
+    /// 设置后 Client 不再自行启动、重启 Agent，只在超时内重试连接。
+    pub assume_socket_activated: bool,
+    /// 允许启动与当前进程架构不同的 Agent 二进制（如有意在 Rosetta 下运行）
+    pub allow_agent_arch_mismatch: bool,
+    /// 本地找不到 Agent 时允许从 GitHub Release 下载
+    pub allow_download: bool,
+    /// 允许把 Cargo target / App bundle 中的 Agent 复制到 `data_dir/bin`
+    ///
+    /// 关闭后只使用显式配置（`agent_binary_override`、`VIMO_AGENT_PATH`）或已安装的二进制。
+    pub allow_deploy: bool,
 }
 
 impl Default for ClientConfig {
@@ -55,6 +80,10 @@ impl Default for ClientConfig {
             retry_interval_ms: 500,
             agent_binary_override: None,
             agent_source_dir: None,
+            assume_socket_activated: false,
+            allow_agent_arch_mismatch: false,
+            allow_download: true,
+            allow_deploy: true,
         }
     }
 }
@@ -80,11 +109,75 @@ impl ClientConfig {
         self
     }
 
+    /// 声明 Agent 由服务管理器按需启动（socket activation）
+    pub fn with_socket_activation(mut self) -> Self {
+        self.assume_socket_activated = true;
+        self
+    }
+
+    /// 允许启动与当前进程架构不同的 Agent 二进制
+    pub fn with_agent_arch_mismatch_allowed(mut self) -> Self {
+        self.allow_agent_arch_mismatch = true;
+        self
+    }
+
+    /// 禁止从 GitHub Release 下载 Agent
+    pub fn without_download(mut self) -> Self {
+        self.allow_download = false;
+        self
+    }
+
+    /// 禁止自动部署 Agent 到 `data_dir/bin`
+    pub fn without_deploy(mut self) -> Self {
+        self.allow_deploy = false;
+        self
+    }
+
+    /// 使用 `data_dir`，并叠加其中共享配置文件的 `[client]` 部分和环境变量（见 `config_file`）
+    ///
+    /// 启动的 Agent 自行读取同一个文件，无需额外传参。
+    pub fn load_overrides(mut self, data_dir: &Path) -> Self {
+        self.data_dir = data_dir.to_path_buf();
+        self.apply_config_file(&ConfigFile::load(data_dir));
+        self
+    }
+
+    /// 用配置文件中设置了的项覆盖当前值（`[client]`）
+    pub fn apply_config_file(&mut self, file: &ConfigFile) {
+        let client = &file.client;
+        if let Some(v) = client.connect_retries {
+            self.connect_retries = v;
+        }
+        if let Some(v) = client.retry_interval_ms {
+            self.retry_interval_ms = v;
+        }
+        if let Some(v) = &client.agent_binary {
+            self.agent_binary_override = Some(v.clone());
+        }
+        if let Some(v) = client.assume_socket_activated {
+            self.assume_socket_activated = v;
+        }
+        if let Some(v) = client.allow_agent_arch_mismatch {
+            self.allow_agent_arch_mismatch = v;
+        }
+        if let Some(v) = client.allow_download {
+            self.allow_download = v;
+        }
+        if let Some(v) = client.allow_deploy {
+            self.allow_deploy = v;
+        }
+    }
+
     /// Socket 路径 (Unix only, for cleanup)
     pub fn socket_path(&self) -> PathBuf {
         self.data_dir.join("agent.sock")
     }
 
+    /// Agent 日志路径
+    pub fn log_path(&self) -> PathBuf {
+        self.data_dir.join("agent.log")
+    }
+
     /// 获取跨平台 socket name
     #[cfg(unix)]
     pub fn socket_name(&self) -> Name<'static> {
@@ -120,7 +213,7 @@ impl ClientConfig {
     /// 1. agent_binary_override（配置覆盖）
     /// 2. VIMO_AGENT_PATH 环境变量
     /// 3. ~/.vimo/bin/vimo-agent（默认安装路径）
-    /// 4. 源路径（Cargo target / App bundle）→ 自动部署到 ~/.vimo/bin/
+    /// 4. 源路径（Cargo target / App bundle）→ 自动部署到 ~/.vimo/bin/（`allow_deploy` 为 false 时跳过）
     pub fn find_agent_binary(&self) -> Option<PathBuf> {
         // 1. 配置覆盖
         if let Some(ref path) = self.agent_binary_override {
@@ -144,6 +237,10 @@ impl ClientConfig {
         }
 
         // 4. 查找源路径并自动部署
+        if !self.allow_deploy {
+            tracing::debug!("Agent auto-deploy disabled, skipping source lookup");
+            return None;
+        }
         if let Some(source_path) = self.find_agent_source() {
             if let Err(e) = self.deploy_agent(&source_path) {
                 tracing::warn!("Failed to auto-deploy Agent: {}", e);
@@ -233,10 +330,16 @@ pub struct AgentClient {
     writer: WriteHalf<Stream>,
     /// Response 接收通道（用于 request/response 模式）
     response_rx: mpsc::Receiver<String>,
+    /// Push 接收通道（Agent 主动推送）
+    push_rx: mpsc::Receiver<crate::protocol::Push>,
+    /// 连接校验失败（Agent 唤醒后 Heartbeat 无响应），应重新连接
+    degraded: bool,
 }
 
 impl AgentClient {
     /// 发送请求并等待响应
+    ///
+    /// 只读降级的 Agent 拒绝写入时（`Response::VersionMismatch`）返回错误。
     pub async fn request(&mut self, request: &crate::protocol::Request) -> Result<crate::protocol::Response> {
         // 序列化请求
         let request_json = serde_json::to_string(request)?;
@@ -251,9 +354,55 @@ impl AgentClient {
 
         // 解析响应
         let response: crate::protocol::Response = serde_json::from_str(&response_line)?;
+        if let crate::protocol::Response::VersionMismatch { compatibility } = response {
+            return Err(anyhow::anyhow!(
+                "Write rejected: vimo-agent is read-only because the database requires a writer \
+                 supporting schema {} (this agent supports {}); update the vimo-agent binary",
+                compatibility.min_writer_schema_version,
+                compatibility.supported_schema_version
+            ));
+        }
         Ok(response)
     }
 
+    /// 接收 Agent 推送（连接关闭时返回 None）
+    ///
+    /// 收到 `Push::AgentResumed` 时先发送 Heartbeat 校验连接，失败时标记为 degraded。
+    pub async fn recv_push(&mut self) -> Option<crate::protocol::Push> {
+        let push = self.push_rx.recv().await?;
+        if matches!(push, crate::protocol::Push::AgentResumed { .. }) {
+            self.validate_connection().await;
+        }
+        Some(push)
+    }
+
+    /// 尝试接收 Agent 推送（非阻塞）
+    ///
+    /// 不会自动校验连接：收到 `Push::AgentResumed` 后由调用方调用 `validate_connection`。
+    pub fn try_recv_push(&mut self) -> Option<crate::protocol::Push> {
+        self.push_rx.try_recv().ok()
+    }
+
+    /// 发送 Heartbeat 校验连接，返回连接是否可用（失败时标记为 degraded）
+    pub async fn validate_connection(&mut self) -> bool {
+        let result = tokio::time::timeout(
+            RESUME_HEARTBEAT_TIMEOUT,
+            self.request(&crate::protocol::Request::Heartbeat),
+        )
+        .await;
+        let healthy = matches!(result, Ok(Ok(crate::protocol::Response::Ok)));
+        if !healthy {
+            tracing::warn!("Agent connection failed validation after resume, marking degraded");
+        }
+        self.degraded = !healthy;
+        healthy
+    }
+
+    /// 连接是否已失效（应丢弃当前 client 并重新 `connect_or_start_agent`）
+    pub fn is_degraded(&self) -> bool {
+        self.degraded
+    }
+
     /// 通知文件变化
     pub async fn notify_file_change(&mut self, path: PathBuf) -> Result<()> {
         let request = crate::protocol::Request::NotifyFileChange { path };
@@ -268,13 +417,13 @@ impl AgentClient {
         }
     }
 
-    /// 写入 Approve 结果
+    /// 写入 Approve 结果，返回更新的消息数（tool_call_id 不存在时为 0）
     pub async fn write_approve_result(
         &mut self,
         tool_call_id: String,
         status: crate::protocol::ApprovalStatus,
         resolved_at: i64,
-    ) -> Result<()> {
+    ) -> Result<usize> {
         let request = crate::protocol::Request::WriteApproveResult {
             tool_call_id,
             status,
@@ -283,13 +432,840 @@ impl AgentClient {
         let response = self.request(&request).await?;
 
         match response {
-            crate::protocol::Response::Ok => Ok(()),
+            crate::protocol::Response::QueryResult { data } => {
+                Ok(data.get("updated").and_then(|v| v.as_u64()).unwrap_or(0) as usize)
+            }
             crate::protocol::Response::Error { code, message } => {
                 Err(anyhow::anyhow!("WriteApproveResult failed: {} (code={})", message, code))
             }
             _ => Err(anyhow::anyhow!("Unexpected response")),
         }
     }
+
+    /// 读取待清理的向量墓碑
+    pub async fn drain_vector_tombstones(
+        &mut self,
+        limit: usize,
+    ) -> Result<Vec<crate::types::VectorTombstone>> {
+        let request = crate::protocol::Request::DrainVectorTombstones { limit };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("DrainVectorTombstones failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 将采集批次交给 Agent 写入，返回新插入的消息数
+    ///
+    /// 供没有写权限的组件使用，批次由 `Collector::prepare_by_path` 生成。
+    pub async fn write_collect_batch(&mut self, batch: crate::db::CollectBatch) -> Result<usize> {
+        let request = crate::protocol::Request::WriteCollectBatch { batch };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(data
+                .get("messages_inserted")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0) as usize),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("WriteCollectBatch failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 将搜索记录交给 Agent 写入，返回写入的条数
+    ///
+    /// 供只读连接的组件使用，记录由 `SessionDB::take_buffered_searches` 取出。
+    pub async fn write_search_log(
+        &mut self,
+        entries: Vec<crate::search_log::SearchLogInput>,
+    ) -> Result<usize> {
+        let request = crate::protocol::Request::WriteSearchLog { entries };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => {
+                Ok(data.get("written").and_then(|v| v.as_u64()).unwrap_or(0) as usize)
+            }
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("WriteSearchLog failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 查询 Agent 的写入角色（是否为 Writer、写入者类型、取得时间）
+    pub async fn writer_role(&mut self) -> Result<crate::protocol::WriterRole> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::WriterRole,
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("WriterRole failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 查询会话实时活跃状态（Streaming / RecentlyActive / Idle）
+    ///
+    /// 状态切换时 Agent 还会推送 SessionActivityChanged。
+    pub async fn session_activity(
+        &mut self,
+        session_ids: Vec<String>,
+    ) -> Result<Vec<crate::types::SessionActivity>> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::SessionActivity { session_ids },
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("SessionActivity failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 获取会话的 Talk 摘要（按 position 排序，分页）
+    pub async fn get_talk_summaries(
+        &mut self,
+        session_id: &str,
+        limit: Option<usize>,
+        offset: usize,
+    ) -> Result<Vec<crate::types::TalkSummary>> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::TalkSummaries {
+                session_id: session_id.to_string(),
+                limit,
+                offset,
+            },
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("TalkSummaries failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 获取单个 Talk 摘要
+    pub async fn get_talk_summary(
+        &mut self,
+        session_id: &str,
+        talk_id: &str,
+    ) -> Result<Option<crate::types::TalkSummary>> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::TalkSummary {
+                session_id: session_id.to_string(),
+                talk_id: talk_id.to_string(),
+            },
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("TalkSummary failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 获取全局变更计数
+    ///
+    /// 与上次获取的值（或 Push 携带的 `change_counter`）相同说明没有新数据，无需重新拉取。
+    pub async fn change_counter(&mut self) -> Result<u64> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::ChangeCounter,
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => data
+                .get("counter")
+                .and_then(|v| v.as_u64())
+                .ok_or_else(|| anyhow::anyhow!("Invalid ChangeCounter response")),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("ChangeCounter failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 历史数据概览（总量、时间跨度、按数据源 / 年份的分布、最活跃的项目）
+    pub async fn history_overview(&mut self) -> Result<crate::types::HistoryOverview> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::HistoryOverview,
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("HistoryOverview failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 项目列表（带统计，按最后活跃时间排序）
+    pub async fn list_projects(
+        &mut self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<crate::types::ProjectWithStats>> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::ListProjects {
+                limit,
+                offset,
+                paged: false,
+                page_token: None,
+            },
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("ListProjects failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 项目列表的一页（带统计，按最后活跃时间排序）
+    ///
+    /// 第一页传 None，之后传上一页的 `next_page_token`；token 无效时返回 code=400 的错误
+    pub async fn list_projects_page(
+        &mut self,
+        limit: usize,
+        page_token: Option<crate::types::PageToken>,
+    ) -> Result<crate::types::Page<crate::types::ProjectWithStats>> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::ListProjects {
+                limit,
+                offset: 0,
+                paged: true,
+                page_token,
+            },
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("ListProjects failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 数据库与 Claude projects 目录对账后的项目列表（按最后活跃时间排序）
+    ///
+    /// `projects_path` 为空时 Agent 使用 `~/.claude/projects`
+    pub async fn list_projects_reconciled(
+        &mut self,
+        limit: usize,
+        offset: usize,
+        projects_path: Option<String>,
+    ) -> Result<Vec<crate::reader::ReconciledProject>> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::ReconciledProjects {
+                limit,
+                offset,
+                projects_path,
+            },
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("ReconciledProjects failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 项目下的会话列表（不含 agent session）
+    pub async fn list_sessions(
+        &mut self,
+        project_path: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<crate::types::SessionWithProject>> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::ListSessions {
+                project_path: project_path.to_string(),
+                limit,
+                offset,
+                paged: false,
+                page_token: None,
+            },
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("ListSessions failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 项目下会话列表的一页（不含 agent session）
+    ///
+    /// 第一页传 None，之后传上一页的 `next_page_token`；token 无效时返回 code=400 的错误
+    pub async fn list_sessions_page(
+        &mut self,
+        project_path: &str,
+        limit: usize,
+        page_token: Option<crate::types::PageToken>,
+    ) -> Result<crate::types::Page<crate::types::SessionWithProject>> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::ListSessions {
+                project_path: project_path.to_string(),
+                limit,
+                offset: 0,
+                paged: true,
+                page_token,
+            },
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("ListSessions failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 会话消息（按 sequence 排序，不含 raw）
+    pub async fn list_messages(
+        &mut self,
+        session_id: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<crate::types::Message>> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::ListMessages {
+                session_id: session_id.to_string(),
+                limit,
+                offset,
+                paged: false,
+                page_token: None,
+            },
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("ListMessages failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 会话消息的一页（按 sequence 排序，不含 raw）
+    ///
+    /// 第一页传 None，之后传上一页的 `next_page_token`；token 无效时返回 code=400 的错误
+    pub async fn list_messages_page(
+        &mut self,
+        session_id: &str,
+        limit: usize,
+        page_token: Option<crate::types::PageToken>,
+    ) -> Result<crate::types::Page<crate::types::Message>> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::ListMessages {
+                session_id: session_id.to_string(),
+                limit,
+                offset: 0,
+                paged: true,
+                page_token,
+            },
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("ListMessages failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 会话的对话轮次摘要（按 turn_index 排序）
+    pub async fn list_turns(
+        &mut self,
+        session_id: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<crate::types::TurnSummary>> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::ListTurns {
+                session_id: session_id.to_string(),
+                limit,
+                offset,
+            },
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("ListTurns failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 单个对话轮次的消息（按 sequence 排序，不含 raw）
+    pub async fn get_turn_messages(
+        &mut self,
+        session_id: &str,
+        turn_index: i64,
+    ) -> Result<Vec<crate::types::Message>> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::TurnMessages {
+                session_id: session_id.to_string(),
+                turn_index,
+            },
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("TurnMessages failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 按 ID 获取消息（不含 raw），用于取搜索命中的正文
+    pub async fn get_messages_by_ids(&mut self, ids: &[i64]) -> Result<Vec<crate::types::Message>> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::MessagesByIds { ids: ids.to_vec() },
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("MessagesByIds failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 立即触发全量采集（如 UI 的"刷新"按钮），返回结果摘要
+    ///
+    /// 采集期间 Agent 会向所有连接推送 CollectStarted / CollectFinished。
+    /// 其他进程正在采集时返回 code=503 错误，稍后重试。
+    pub async fn collect_now(&mut self) -> Result<crate::protocol::CollectSummary> {
+        let request = crate::protocol::Request::CollectAll;
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("CollectAll failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 设置消息的向量索引状态
+    pub async fn mark_indexed(
+        &mut self,
+        message_ids: Vec<i64>,
+        state: crate::types::IndexState,
+    ) -> Result<()> {
+        let request = crate::protocol::Request::MarkIndexed { message_ids, state };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::Ok => Ok(()),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("MarkIndexed failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 确认向量墓碑已处理
+    pub async fn ack_vector_tombstones(&mut self, ids: Vec<i64>) -> Result<()> {
+        let request = crate::protocol::Request::AckVectorTombstones { ids };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::Ok => Ok(()),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("AckVectorTombstones failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 等待变更（长轮询）
+    ///
+    /// 范围内状态变化时返回 `Some(新状态)`，超时返回 `None`。
+    /// 下次调用传入上次得到的 `state_hash`，可避免遗漏两次调用之间的变更。
+    pub async fn wait_for_change(
+        &mut self,
+        session_id: Option<String>,
+        project_path: Option<String>,
+        after_state_hash: Option<u64>,
+        timeout: Duration,
+    ) -> Result<Option<crate::types::ChangeState>> {
+        let request = crate::protocol::Request::WaitForChange {
+            session_id,
+            project_path,
+            after_state_hash,
+            timeout_ms: timeout.as_millis() as u64,
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::Changed { state } => Ok(Some(state)),
+            crate::protocol::Response::NotModified { .. } => Ok(None),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("WaitForChange failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 更新采集忽略规则，返回更新后的全部规则
+    pub async fn update_ignores(
+        &mut self,
+        add: Vec<crate::protocol::IgnoreRuleInput>,
+        remove: Vec<i64>,
+    ) -> Result<Vec<crate::types::CollectionIgnore>> {
+        let request = crate::protocol::Request::UpdateIgnores { add, remove };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("UpdateIgnores failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 订阅推送类型（见 `Push::event_type`），返回订阅后的全部类型
+    ///
+    /// 未调用过时接收全部推送。
+    pub async fn subscribe(&mut self, events: Vec<String>) -> Result<Vec<String>> {
+        let request = crate::protocol::Request::Subscribe { events };
+        self.subscription_request(&request).await
+    }
+
+    /// 取消订阅推送类型，返回剩余的全部类型
+    pub async fn unsubscribe(&mut self, events: Vec<String>) -> Result<Vec<String>> {
+        let request = crate::protocol::Request::Unsubscribe { events };
+        self.subscription_request(&request).await
+    }
+
+    async fn subscription_request(
+        &mut self,
+        request: &crate::protocol::Request,
+    ) -> Result<Vec<String>> {
+        match self.request(request).await? {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("Subscribe failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 全文搜索（`max_per_project` 为每个项目的配额）
+    ///
+    /// `options.include_content` 未指定时命中不含正文，需要时用 `get_messages_by_ids` 获取
+    pub async fn search(
+        &mut self,
+        query: &str,
+        limit: usize,
+        options: crate::types::SearchGroupOptions,
+        max_per_project: Option<usize>,
+    ) -> Result<Vec<crate::types::SearchResult>> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::Search {
+                keyword: query.to_string(),
+                limit,
+                options,
+                max_per_project,
+                paged: false,
+                page_token: None,
+            },
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("Search failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 全文搜索的一页（参数同 `search`）
+    ///
+    /// 第一页传 None，之后传上一页的 `next_page_token`；token 无效时返回 code=400 的错误
+    pub async fn search_page(
+        &mut self,
+        query: &str,
+        limit: usize,
+        options: crate::types::SearchGroupOptions,
+        max_per_project: Option<usize>,
+        page_token: Option<crate::types::PageToken>,
+    ) -> Result<crate::types::Page<crate::types::SearchResult>> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::Search {
+                keyword: query.to_string(),
+                limit,
+                options,
+                max_per_project,
+                paged: true,
+                page_token,
+            },
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("Search failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 全文搜索的命中总数（额外的一次 FTS 查询，只在需要展示总数时调用）
+    pub async fn search_count(
+        &mut self,
+        query: &str,
+        options: crate::types::SearchGroupOptions,
+    ) -> Result<i64> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::SearchCount {
+                keyword: query.to_string(),
+                options,
+            },
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => data
+                .get("total")
+                .and_then(|v| v.as_i64())
+                .ok_or_else(|| anyhow::anyhow!("Invalid SearchCount response")),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("SearchCount failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 按会话分组的全文搜索
+    pub async fn search_grouped(
+        &mut self,
+        query: &str,
+        session_limit: usize,
+        per_session_limit: usize,
+        options: crate::types::SearchGroupOptions,
+    ) -> Result<Vec<crate::types::SessionSearchGroup>> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::SearchGrouped {
+                keyword: query.to_string(),
+                session_limit,
+                per_session_limit,
+                options,
+            },
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("SearchGrouped failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 最近的搜索（按时间倒序，`zero_results` 为 true 时只返回没有结果的搜索）
+    pub async fn recent_searches(
+        &mut self,
+        limit: usize,
+        zero_results: bool,
+    ) -> Result<Vec<crate::search_log::SearchLogEntry>> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::RecentSearches {
+                limit,
+                zero_results,
+            },
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("RecentSearches failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 审批审计记录（按请求时间升序，start_ms / end_ms 为闭区间）
+    pub async fn approval_audit(
+        &mut self,
+        project_id: Option<i64>,
+        start_ms: Option<i64>,
+        end_ms: Option<i64>,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<crate::audit::ApprovalAuditRow>> {
+        let request = crate::protocol::Request::Query {
+            query_type: crate::protocol::QueryType::ApprovalAudit {
+                project_id,
+                start_ms,
+                end_ms,
+                limit,
+                offset,
+            },
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("ApprovalAudit failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 由 Agent 将审批审计记录导出到 `path`，返回导出的行数
+    pub async fn export_approval_audit(
+        &mut self,
+        path: std::path::PathBuf,
+        format: crate::audit::AuditFormat,
+        project_id: Option<i64>,
+        start_ms: Option<i64>,
+        end_ms: Option<i64>,
+    ) -> Result<usize> {
+        let request = crate::protocol::Request::ExportApprovalAudit {
+            path,
+            format,
+            project_id,
+            start_ms,
+            end_ms,
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => {
+                Ok(data.get("rows").and_then(|v| v.as_u64()).unwrap_or(0) as usize)
+            }
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("ExportApprovalAudit failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 由 Agent 导入 claude.ai 数据导出（ZIP 或 `conversations.json`）
+    pub async fn import_archive(
+        &mut self,
+        path: std::path::PathBuf,
+        match_projects: bool,
+    ) -> Result<crate::types::ImportReport> {
+        let request = crate::protocol::Request::ImportArchive {
+            path,
+            match_projects,
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("ImportArchive failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 由 Agent 从 raw 重新提取 content_text（`done = false` 时再次调用继续）
+    pub async fn rederive_content_text(
+        &mut self,
+        batch_size: usize,
+        max_batches: Option<usize>,
+        restart: bool,
+    ) -> Result<crate::RederiveProgress> {
+        let request = crate::protocol::Request::Maintenance {
+            task: crate::protocol::MaintenanceTask::RederiveContentText {
+                batch_size,
+                max_batches,
+                restart,
+            },
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("Maintenance failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
+
+    /// 彻底删除项目
+    ///
+    /// `confirm_token` 为空时只预览（`dry_run = true`），带上预览返回的 token 才删除。
+    pub async fn forget_project(
+        &mut self,
+        project_id: i64,
+        secure: bool,
+        confirm_token: Option<String>,
+    ) -> Result<crate::forget::ForgetReport> {
+        let request = crate::protocol::Request::ForgetProject {
+            project_id,
+            secure,
+            confirm_token,
+        };
+        let response = self.request(&request).await?;
+
+        match response {
+            crate::protocol::Response::QueryResult { data } => Ok(serde_json::from_value(data)?),
+            crate::protocol::Response::Error { code, message } => {
+                Err(anyhow::anyhow!("ForgetProject failed: {} (code={})", message, code))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response")),
+        }
+    }
 }
 
 /// 连接或启动 Agent
@@ -300,7 +1276,13 @@ impl AgentClient {
 /// 3. 清理残留 → 启动 Agent
 /// 4. 等待 Agent ready → 连接
 /// 5. 版本检查 → 如果本地二进制更新，重启 Agent（最多重启一次）
+///
+/// `assume_socket_activated` 时跳过 2-5：Agent 由服务管理器拉起，只重试连接。
 pub async fn connect_or_start_agent(config: ClientConfig) -> Result<AgentClient> {
+    if config.assume_socket_activated {
+        return connect_socket_activated(config).await;
+    }
+
     // 最多尝试一次版本不匹配重启
     let mut version_restart_attempted = false;
 
@@ -313,15 +1295,12 @@ pub async fn connect_or_start_agent(config: ClientConfig) -> Result<AgentClient>
                     tracing::debug!("Connected to Agent successfully (attempt={})", attempt);
                     match finish_connect(config.clone(), stream).await {
                         Ok(client) => return Ok(client),
-                        Err(e) if e.to_string() == "AGENT_VERSION_MISMATCH" => {
+                        Err(e) if needs_restart(&e) => {
                             if version_restart_attempted {
-                                return Err(anyhow::anyhow!(
-                                    "Agent version mismatch persists after restart. \
-                                     Check if the correct vimo-agent binary is deployed."
-                                ));
+                                return Err(restart_failed_error(e));
                             }
                             // 触发重启流程
-                            tracing::info!("Restarting Agent due to version mismatch...");
+                            tracing::info!("Restarting Agent due to version mismatch: {}", e);
                             version_restart_attempted = true;
                             cleanup_stale(&config)?;
                             deploy_and_start_agent(&config)?;
@@ -361,9 +1340,9 @@ pub async fn connect_or_start_agent(config: ClientConfig) -> Result<AgentClient>
         }
 
         // 3. 启动 Agent
-        start_agent(&config)?;
+        let mut child = start_agent(&config)?;
 
-        // 4. 等待 Agent ready 并连接
+        // 4. 等待 Agent ready 并连接（约 2 秒，期间进程退出则直接报告退出码和日志）
         for attempt in 1..=10 {
             sleep(Duration::from_millis(200)).await;
 
@@ -371,23 +1350,27 @@ pub async fn connect_or_start_agent(config: ClientConfig) -> Result<AgentClient>
                 tracing::info!("Agent started successfully, connected");
                 match finish_connect(config.clone(), stream).await {
                     Ok(client) => return Ok(client),
-                    Err(e) if e.to_string() == "AGENT_VERSION_MISMATCH" => {
+                    Err(e) if needs_restart(&e) => {
                         if version_restart_attempted {
-                            return Err(anyhow::anyhow!(
-                                "Agent version mismatch persists after restart. \
-                                 Check if the correct vimo-agent binary is deployed."
-                            ));
+                            return Err(restart_failed_error(e));
                         }
-                        tracing::warn!("Newly started Agent has version mismatch, restarting...");
+                        tracing::warn!(
+                            "Newly started Agent has version mismatch, restarting: {}",
+                            e
+                        );
                         version_restart_attempted = true;
                         cleanup_stale(&config)?;
-                        deploy_and_start_agent(&config)?;
+                        child = deploy_and_start_agent(&config)?;
                         continue; // 重新等待
                     }
                     Err(e) => return Err(e),
                 }
             }
 
+            if let Ok(Some(status)) = child.try_wait() {
+                return Err(agent_exited_error(&config, status));
+            }
+
             tracing::debug!("Waiting for Agent to be ready (attempt={})", attempt);
         }
 
@@ -395,10 +1378,61 @@ pub async fn connect_or_start_agent(config: ClientConfig) -> Result<AgentClient>
     }
 }
 
+/// 只连接已在运行的 Agent（不启动、不重试）
+///
+/// 用于探测 Agent 是否可用：socket 不可达或握手失败（含版本不匹配）时返回错误。
+pub async fn connect_running_agent(config: ClientConfig) -> Result<AgentClient> {
+    let stream = Stream::connect(config.socket_name()).await?;
+    finish_connect(config, stream).await
+}
+
+/// 连接由 socket activation 管理的 Agent
+///
+/// 首次连接会触发服务管理器启动 Agent，连接失败（如 socket 尚未就绪）时重试直到超时。
+/// 不清理残留状态、不启动也不重启 Agent。
+async fn connect_socket_activated(config: ClientConfig) -> Result<AgentClient> {
+    let deadline = tokio::time::Instant::now() + SOCKET_ACTIVATION_DEADLINE;
+    let mut attempt = 0u32;
+
+    loop {
+        attempt += 1;
+        match Stream::connect(config.socket_name()).await {
+            Ok(stream) => {
+                tracing::debug!("Connected to socket-activated Agent (attempt={})", attempt);
+                return match finish_connect(config, stream).await {
+                    Err(e) if e.to_string() == "AGENT_VERSION_MISMATCH" => Err(anyhow::anyhow!(
+                        "Agent version mismatch. Agent is managed by the service manager, \
+                         restart the service to pick up the new binary."
+                    )),
+                    result => result,
+                };
+            }
+            Err(e) => {
+                if tokio::time::Instant::now() >= deadline {
+                    return Err(anyhow::anyhow!(
+                        "Timeout connecting to socket-activated Agent: {}",
+                        e
+                    ));
+                }
+                tracing::debug!(
+                    "Waiting for socket-activated Agent (attempt={}): {}",
+                    attempt,
+                    e
+                );
+                sleep(Duration::from_millis(config.retry_interval_ms)).await;
+            }
+        }
+    }
+}
+
 /// 部署最新二进制并启动 Agent
-fn deploy_and_start_agent(config: &ClientConfig) -> Result<()> {
-    // 部署最新的 agent 二进制（如果有源路径）
-    if let Some(source_path) = config.find_agent_source() {
+fn deploy_and_start_agent(config: &ClientConfig) -> Result<Child> {
+    // 部署最新的 agent 二进制（如果有源路径且允许部署）
+    if let Some(source_path) = config
+        .allow_deploy
+        .then(|| config.find_agent_source())
+        .flatten()
+    {
         tracing::info!("Deploying newer Agent binary from: {:?}", source_path);
         if let Err(e) = config.deploy_agent(&source_path) {
             tracing::warn!("Failed to deploy Agent: {}", e);
@@ -409,6 +1443,45 @@ fn deploy_and_start_agent(config: &ClientConfig) -> Result<()> {
     start_agent(config)
 }
 
+/// 握手表明 Agent 比数据库要求的写入方旧（`HandshakeOk::degraded`）
+#[derive(Debug)]
+struct AgentDegradedError {
+    agent_version: String,
+    compatibility: SchemaCompatibility,
+}
+
+impl std::fmt::Display for AgentDegradedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "vimo-agent {} is too old for this database: it was migrated by a newer build \
+             and requires a writer supporting schema {} (this agent supports {}). \
+             The agent is running read-only; update the vimo-agent binary.",
+            self.agent_version,
+            self.compatibility.min_writer_schema_version,
+            self.compatibility.supported_schema_version
+        )
+    }
+}
+
+impl std::error::Error for AgentDegradedError {}
+
+/// 需要重启 Agent 的握手错误：版本不匹配，或 Agent 只读降级（本地可能有更新的二进制）
+fn needs_restart(e: &anyhow::Error) -> bool {
+    e.to_string() == "AGENT_VERSION_MISMATCH" || e.is::<AgentDegradedError>()
+}
+
+/// 重启一次后仍需重启时返回的错误
+fn restart_failed_error(e: anyhow::Error) -> anyhow::Error {
+    if e.is::<AgentDegradedError>() {
+        return e;
+    }
+    anyhow::anyhow!(
+        "Agent version mismatch persists after restart. \
+         Check if the correct vimo-agent binary is deployed."
+    )
+}
+
 /// 从版本字符串中提取时间戳
 ///
 /// 版本格式：`{semver}-{timestamp}` 或 `{semver}`
@@ -427,6 +1500,7 @@ async fn finish_connect(config: ClientConfig, stream: Stream) -> Result<AgentCli
     let handshake = crate::protocol::Request::Handshake {
         component: config.component.clone(),
         version: config.version.clone(),
+        protocol_version: crate::protocol::PROTOCOL_VERSION,
     };
     let handshake_json = serde_json::to_string(&handshake)?;
     writer.write_all(format!("{}\n", handshake_json).as_bytes()).await?;
@@ -437,10 +1511,49 @@ async fn finish_connect(config: ClientConfig, stream: Stream) -> Result<AgentCli
 
     let response: crate::protocol::Response = serde_json::from_str(&line)?;
     let agent_version = match response {
-        crate::protocol::Response::HandshakeOk { agent_version } => {
-            tracing::info!("Handshake successful: agent_version={}", agent_version);
+        crate::protocol::Response::HandshakeOk {
+            agent_version,
+            protocol_version,
+            degraded,
+        } => {
+            tracing::info!(
+                "Handshake successful: agent_version={}, protocol_version={}",
+                agent_version,
+                protocol_version
+            );
+            // Agent 协议版本过旧（低于本端支持下限），与编译时间戳无关，同样需要重启
+            if protocol_version < crate::protocol::MIN_PROTOCOL_VERSION {
+                tracing::warn!(
+                    "Agent protocol version {} is below supported minimum {}",
+                    protocol_version,
+                    crate::protocol::MIN_PROTOCOL_VERSION
+                );
+                drop(writer);
+                return Err(anyhow::anyhow!("AGENT_VERSION_MISMATCH"));
+            }
+            // Agent 比数据库要求的写入方旧（数据库已由更新版本迁移），只读运行
+            if let Some(compatibility) = degraded {
+                drop(writer);
+                return Err(AgentDegradedError {
+                    agent_version,
+                    compatibility,
+                }
+                .into());
+            }
             agent_version
         }
+        crate::protocol::Response::ProtocolVersionUnsupported {
+            protocol_version,
+            min_supported,
+            max_supported,
+        } => {
+            return Err(anyhow::anyhow!(
+                "Handshake rejected: protocol version {} not supported by agent (supported {}..={})",
+                protocol_version,
+                min_supported,
+                max_supported
+            ));
+        }
         crate::protocol::Response::Error { code, message } => {
             return Err(anyhow::anyhow!("Handshake failed: {} (code={})", message, code));
         }
@@ -479,10 +1592,11 @@ async fn finish_connect(config: ClientConfig, stream: Stream) -> Result<AgentCli
         }
     }
 
-    // 创建响应通道
+    // 创建响应通道和推送通道
     let (response_tx, response_rx) = mpsc::channel(100);
+    let (push_tx, push_rx) = mpsc::channel(100);
 
-    // 启动读取任务，所有消息发送到 response 通道
+    // 启动读取任务：推送消息发送到 push 通道，其余发送到 response 通道
     tokio::spawn(async move {
         let mut line = String::new();
         loop {
@@ -491,6 +1605,11 @@ async fn finish_connect(config: ClientConfig, stream: Stream) -> Result<AgentCli
                 Ok(0) => break, // 连接关闭
                 Ok(_) => {
                     let trimmed = line.trim().to_string();
+                    if let Ok(push) = serde_json::from_str::<crate::protocol::Push>(&trimmed) {
+                        // 无人消费时丢弃，不阻塞响应
+                        let _ = push_tx.try_send(push);
+                        continue;
+                    }
                     if response_tx.send(trimmed).await.is_err() {
                         break;
                     }
@@ -504,6 +1623,8 @@ async fn finish_connect(config: ClientConfig, stream: Stream) -> Result<AgentCli
         config,
         writer,
         response_rx,
+        push_rx,
+        degraded: false,
     })
 }
 
@@ -598,11 +1719,17 @@ fn kill_process(pid: u32) {
         .status();
 }
 
-/// 启动 Agent
-fn start_agent(config: &ClientConfig) -> Result<()> {
+/// 启动 Agent，返回子进程（用于检测启动后立即退出）
+fn start_agent(config: &ClientConfig) -> Result<Child> {
     // 尝试查找或下载 Agent
     let agent_path = match config.find_agent_binary() {
         Some(path) => path,
+        None if !config.allow_download => {
+            return Err(agent_not_found_error(
+                config,
+                "auto-download is disabled (ClientConfig::allow_download)",
+            ));
+        }
         None => {
             tracing::info!("vimo-agent not found locally, attempting to download from GitHub Release...");
 
@@ -612,30 +1739,22 @@ fn start_agent(config: &ClientConfig) -> Result<()> {
                     path
                 }
                 Err(e) => {
-                    return Err(anyhow::anyhow!(
-                        "Agent binary not found and auto-download failed: {}\n\
-                         \n\
-                         Tried paths:\n\
-                         - Config override: {:?}\n\
-                         - Environment variable VIMO_AGENT_PATH: {:?}\n\
-                         - Default path: {:?}\n\
-                         - Cargo target directory\n\
-                         - GitHub Release auto-download\n\
-                         \n\
-                         Please set VIMO_AGENT_PATH environment variable or run `cargo build -p ai-cli-session-db --features agent --bin vimo-agent`",
-                        e,
-                        config.agent_binary_override,
-                        std::env::var("VIMO_AGENT_PATH").ok(),
-                        config.default_agent_binary_path()
+                    return Err(agent_not_found_error(
+                        config,
+                        &format!("auto-download failed: {}", e),
                     ));
                 }
             }
         }
     };
 
+    if !config.allow_agent_arch_mismatch {
+        check_agent_arch(&agent_path)?;
+    }
+
     tracing::info!("Starting Agent: {:?}", agent_path);
 
-    let log_path = config.data_dir.join("agent.log");
+    let log_path = config.log_path();
     rotate_log(&log_path, 30);
     let log_file = OpenOptions::new()
         .create(true)
@@ -647,9 +1766,59 @@ fn start_agent(config: &ClientConfig) -> Result<()> {
         .stdout(Stdio::null())
         .stderr(Stdio::from(log_file))
         .spawn()
-        .context("Failed to start Agent")?;
+        .with_context(|| format!("Failed to start Agent {}", agent_path.display()))
+}
 
-    Ok(())
+/// 找不到 Agent 二进制的错误（列出查找过的位置）
+fn agent_not_found_error(config: &ClientConfig, reason: &str) -> anyhow::Error {
+    let deploy = if config.allow_deploy {
+        "- Cargo target directory / App bundle"
+    } else {
+        "- Cargo target directory / App bundle: skipped, auto-deploy is disabled (ClientConfig::allow_deploy)"
+    };
+    let download = if config.allow_download {
+        "- GitHub Release auto-download"
+    } else {
+        "- GitHub Release auto-download: skipped"
+    };
+    anyhow::anyhow!(
+        "Agent binary not found and {}\n\
+         \n\
+         Tried paths:\n\
+         - Config override: {:?}\n\
+         - Environment variable VIMO_AGENT_PATH: {:?}\n\
+         - Default path: {:?}\n\
+         {}\n\
+         {}\n\
+         \n\
+         Please set VIMO_AGENT_PATH environment variable or run `cargo build -p ai-cli-session-db --features agent --bin vimo-agent`",
+        reason,
+        config.agent_binary_override,
+        std::env::var("VIMO_AGENT_PATH").ok(),
+        config.default_agent_binary_path(),
+        deploy,
+        download
+    )
+}
+
+/// Agent 启动后立即退出的错误（附带退出码和日志末尾）
+fn agent_exited_error(config: &ClientConfig, status: std::process::ExitStatus) -> anyhow::Error {
+    let log_path = config.log_path();
+    let log = fs::read_to_string(&log_path).unwrap_or_default();
+    let lines: Vec<&str> = log.lines().collect();
+    let tail = lines[lines.len().saturating_sub(AGENT_LOG_TAIL_LINES)..].join("\n");
+
+    let mut message = format!("Agent exited during startup ({})", status);
+    if tail.is_empty() {
+        message.push_str(&format!("; {} is empty", log_path.display()));
+    } else {
+        message.push_str(&format!(
+            "; last lines of {}:\n{}",
+            log_path.display(),
+            tail
+        ));
+    }
+    anyhow::anyhow!(message)
 }
 
 /// 日志轮转：归档当前日志，清理过期文件
@@ -2,9 +2,10 @@
 //!
 //! 提供连接 Agent 的客户端功能
 
+mod arch;
 mod connect;
 
 #[cfg(feature = "ffi")]
 pub mod ffi;
 
-pub use connect::{AgentClient, ClientConfig, connect_or_start_agent};
+pub use connect::{AgentClient, ClientConfig, connect_or_start_agent, connect_running_agent};
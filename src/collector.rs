@@ -3,13 +3,104 @@
 //! 从 memex-rs/collector 下沉，统一业务逻辑。
 //! 支持多数据源：Claude、OpenCode、Codex 等。
 
-use crate::db::{MessageInput, SessionDB, SessionInput};
+use crate::db::{current_time_ms, CollectBatch, MessageInput, SessionDB, SessionInput};
+use crate::ignore::IgnoreRules;
+use crate::protocol::{CollectErrorEntry, CollectSummary, MAX_COLLECT_ERRORS};
+use crate::reader::{check_dir_access, read_summary_entries};
+use crate::router::DbRouter;
+use crate::types::CollectFailure;
+use crate::writer::{CollectionFilter, SkipReason};
 use crate::{
-    all_adapters, ConversationAdapter, FileIdentity, IncrementalAdapter, ReaderState, SessionMeta,
+    all_adapters, all_watch_configs, ClaudeAdapter, ConversationAdapter, FileIdentity,
+    IncrementalAdapter, ParsedMessage, ReaderState, SessionMeta, Source,
 };
 use anyhow::Result;
-use std::path::Path;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub use crate::protocol::CollectStage;
+
+/// 采集锁心跳超时（超过后其他进程可接管）
+pub const COLLECTION_LOCK_STALE_MS: i64 = 2 * 60 * 1000;
+
+/// 采集锁心跳间隔
+const COLLECTION_LOCK_HEARTBEAT: Duration = Duration::from_secs(10);
+
+/// 采集锁被其他进程持有时返回持有者（`Error::CollectionInProgress`）
+pub fn collection_lock_holder(e: &anyhow::Error) -> Option<&str> {
+    match e.downcast_ref::<crate::Error>() {
+        Some(crate::Error::CollectionInProgress { holder }) => Some(holder),
+        _ => None,
+    }
+}
+
+/// 从采集错误链中取出结构化的 `CollectError`（`collect_by_path` 解析失败等）
+pub fn collect_error(e: &anyhow::Error) -> Option<&CollectError> {
+    e.downcast_ref::<CollectError>()
+}
+
+/// 采集错误（带来源文件、会话和失败阶段）
+#[derive(Debug, Clone)]
+pub struct CollectError {
+    /// 出错的会话文件（数据目录级错误为目录路径，适配器级错误为空）
+    pub path: PathBuf,
+    pub session_id: Option<String>,
+    /// 数据源，无法归属到单个数据源（如数据目录访问失败）时为 None
+    pub source: Option<Source>,
+    pub stage: CollectStage,
+    pub message: String,
+}
+
+impl CollectError {
+    fn new(
+        path: impl Into<PathBuf>,
+        session_id: Option<&str>,
+        source: Option<Source>,
+        stage: CollectStage,
+        message: String,
+    ) -> Self {
+        Self {
+            path: path.into(),
+            session_id: session_id.map(str::to_string),
+            source,
+            stage,
+            message,
+        }
+    }
+
+    /// 协议中的结构化条目
+    pub fn to_entry(&self) -> CollectErrorEntry {
+        CollectErrorEntry {
+            path: self.path.clone(),
+            session_id: self.session_id.clone(),
+            source: self.source.map(|s| s.to_string()),
+            stage: self.stage,
+            message: self.message.clone(),
+        }
+    }
+}
+
+/// 只输出错误信息（与结构化之前的日志格式一致）
+impl fmt::Display for CollectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CollectError {}
+
+/// 为向上传播的错误附加 `CollectError` 上下文（保留原错误链）
+fn attach_collect_error<E>(e: E, context: impl FnOnce(&E) -> CollectError) -> anyhow::Error
+where
+    E: Into<anyhow::Error>,
+{
+    let context = context(&e);
+    e.into().context(context)
+}
 
 /// 采集结果
 #[derive(Debug, Default, Clone)]
@@ -18,7 +109,214 @@ pub struct CollectResult {
     pub sessions_scanned: usize,
     pub messages_inserted: usize,
     pub new_message_ids: Vec<i64>,
-    pub errors: Vec<String>,
+    /// 检测到的消息内容修订数（uuid 已存在但内容变化）
+    pub revisions_detected: usize,
+    /// 被忽略规则跳过的会话数
+    pub sessions_ignored: usize,
+    /// 被采集过滤器跳过的条目数
+    pub skipped_by_filter: usize,
+    /// 无权访问的数据目录（macOS 上需要授予完全磁盘访问权限）
+    pub permission_errors: Vec<PathBuf>,
+    /// 超过文件大小上限而跳过的会话文件数
+    pub files_skipped_size: usize,
+    /// 超过单条消息上限而被截断的消息数
+    pub messages_truncated: usize,
+    /// 连续失败处于退避期而跳过的会话数（同时记入 errors）
+    pub sessions_backed_off: usize,
+    pub errors: Vec<CollectError>,
+}
+
+impl CollectResult {
+    /// 结果摘要（用于推送和协议响应）
+    pub fn summary(&self) -> CollectSummary {
+        CollectSummary {
+            projects_scanned: self.projects_scanned,
+            sessions_scanned: self.sessions_scanned,
+            messages_inserted: self.messages_inserted,
+            revisions_detected: self.revisions_detected,
+            sessions_ignored: self.sessions_ignored,
+            skipped_by_filter: self.skipped_by_filter,
+            permission_errors: self.permission_errors.clone(),
+            files_skipped_size: self.files_skipped_size,
+            messages_truncated: self.messages_truncated,
+            sessions_backed_off: self.sessions_backed_off,
+            error_count: self.errors.len(),
+            errors: self
+                .errors
+                .iter()
+                .take(MAX_COLLECT_ERRORS)
+                .map(CollectError::to_entry)
+                .collect(),
+        }
+    }
+}
+
+/// 采集过滤器影响预估（dry-run，不写入数据库）
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FilterImpact {
+    /// 解析出的条目总数
+    pub total: usize,
+    /// 保留的条目数
+    pub kept: usize,
+    /// 因内容为空跳过
+    pub skipped_empty: usize,
+    /// 因条目类型跳过
+    pub skipped_by_type: usize,
+    /// 因内容过短跳过
+    pub skipped_too_short: usize,
+    /// 因工具名跳过
+    pub skipped_by_tool_name: usize,
+}
+
+impl FilterImpact {
+    /// 跳过的条目总数
+    pub fn skipped(&self) -> usize {
+        self.total - self.kept
+    }
+}
+
+/// 采集防护上限
+///
+/// 防止失控的会话文件（例如工具输出被整段追加进 JSONL）拖垮数据库和 Agent。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CollectLimits {
+    /// 单个会话文件大小上限（字节），超过则跳过整个文件
+    pub max_file_bytes: u64,
+    /// 单条消息内容上限（字节），超过则截断内容并丢弃 raw
+    pub max_message_bytes: usize,
+    /// 单次采集每个会话最多新增的消息数，超过则停止，下次采集从已保存的偏移继续
+    pub max_messages_per_session: usize,
+    /// 会话连续采集失败达到该次数后退避（0 表示不退避），见 `SessionDB::reset_collect_failures`
+    pub max_collect_failures: u32,
+    /// 退避时长（秒），之后重试一次，仍失败则再次退避
+    pub collect_failure_cooldown_secs: u64,
+}
+
+impl Default for CollectLimits {
+    fn default() -> Self {
+        Self {
+            max_file_bytes: 512 * 1024 * 1024,
+            max_message_bytes: 4 * 1024 * 1024,
+            max_messages_per_session: 200_000,
+            max_collect_failures: 3,
+            collect_failure_cooldown_secs: 6 * 60 * 60,
+        }
+    }
+}
+
+impl CollectLimits {
+    /// 单条消息超过上限时截断 content_text / content_full 并丢弃 raw，返回是否截断
+    fn cap_message(&self, msg: &mut MessageInput) -> bool {
+        let max = self.max_message_bytes;
+        let full_truncated = truncate_with_marker(&mut msg.content_full, max);
+        let text_truncated = truncate_with_marker(&mut msg.content_text, max);
+        let raw_oversized = msg.raw.as_ref().is_some_and(|raw| raw.len() > max);
+        if full_truncated || text_truncated || raw_oversized {
+            msg.raw = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 会话处于退避期时返回错误信息（仍然报告，但不重新解析）
+    fn check_backoff(&self, failure: &CollectFailure) -> Option<String> {
+        let max = self.max_collect_failures as i64;
+        let cooldown_ms = i64::try_from(self.collect_failure_cooldown_secs.saturating_mul(1000))
+            .unwrap_or(i64::MAX);
+        let retry_at = failure.last_failure_at.saturating_add(cooldown_ms);
+        (max > 0 && failure.failures >= max && current_time_ms() < retry_at).then(|| {
+            format!(
+                "skipped after {} consecutive failures, retrying after {}: {}",
+                failure.failures, retry_at, failure.last_error
+            )
+        })
+    }
+
+    /// 文件超过大小上限时返回错误信息
+    fn check_file_size(&self, size: u64) -> Option<String> {
+        (size > self.max_file_bytes).then(|| {
+            format!(
+                "file exceeds size limit: {} bytes > {} bytes",
+                size, self.max_file_bytes
+            )
+        })
+    }
+}
+
+/// 按字节上限截断内容（退到字符边界）并追加截断标记，返回是否截断
+fn truncate_with_marker(content: &mut String, max_bytes: usize) -> bool {
+    if content.len() <= max_bytes {
+        return false;
+    }
+    let original_len = content.len();
+    let mut end = max_bytes;
+    while !content.is_char_boundary(end) {
+        end -= 1;
+    }
+    content.truncate(end);
+    content.push_str(&format!("\n[truncated: {} bytes total]", original_len));
+    true
+}
+
+/// 指纹读取的文件尾部长度（最后一行超过时只取尾部）
+const FINGERPRINT_TAIL_BYTES: u64 = 64 * 1024;
+
+/// 计算会话文件指纹：文件大小 + 最后一个非空行的哈希
+///
+/// mtime/size 在跨文件系统复制或备份恢复后可能变化而内容不变，指纹作为额外的跳过依据。
+/// 路径不是可读文件时返回 None。
+pub fn file_content_hash(path: &Path) -> Option<String> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let mut file = std::fs::File::open(path).ok()?;
+    let metadata = file.metadata().ok()?;
+    if !metadata.is_file() {
+        return None;
+    }
+    let size = metadata.len();
+    file.seek(SeekFrom::Start(size.saturating_sub(FINGERPRINT_TAIL_BYTES)))
+        .ok()?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail).ok()?;
+
+    let tail = String::from_utf8_lossy(&tail);
+    let last_line = tail
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or_default();
+    Some(crate::db::content_hash(&format!("{}:{}", size, last_line)))
+}
+
+/// 分阶段全量采集中"最近"阶段的时间窗口（见 `Collector::collect_all_phased`）
+pub const RECENT_COLLECT_WINDOW: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// 全量采集的会话文件处理顺序
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CollectOrder {
+    /// 跨数据源按文件 mtime 从新到旧（最近的项目最先入库）
+    #[default]
+    NewestFirst,
+    /// 按适配器注册顺序和目录顺序（旧行为）
+    DirectoryOrder,
+}
+
+/// 全量采集的候选会话文件
+struct SessionCandidate {
+    adapter: Arc<dyn ConversationAdapter>,
+    meta: SessionMeta,
+    /// 文件修改时间（毫秒），无法获取时为 None
+    mtime: Option<u64>,
+}
+
+/// `window` 之前的时间点（毫秒时间戳），用于划分最近修改的文件
+fn mtime_cutoff(window: Duration) -> u64 {
+    std::time::SystemTime::now()
+        .checked_sub(window)
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
 }
 
 /// 采集服务
@@ -26,7 +324,67 @@ pub struct CollectResult {
 /// 封装多数据源采集逻辑，支持全量和增量采集。
 pub struct Collector<'a> {
     db: &'a SessionDB,
+    /// 按来源拆分数据库时的路由（None 时全部写入 `db`）
+    router: Option<&'a DbRouter>,
     adapters: Vec<Arc<dyn ConversationAdapter>>,
+    /// 适配器的数据根目录（采集前检查访问权限）
+    data_roots: Vec<PathBuf>,
+    update_changed_messages: bool,
+    filter: CollectionFilter,
+    limits: CollectLimits,
+    /// 全量采集的处理顺序
+    order: CollectOrder,
+    /// 采集锁持有者标识
+    lock_holder: String,
+}
+
+/// 采集锁守卫（Drop 时释放）
+struct CollectionLockGuard<'a> {
+    db: &'a SessionDB,
+    holder: &'a str,
+    last_heartbeat: Cell<Instant>,
+}
+
+impl<'a> CollectionLockGuard<'a> {
+    fn acquire(db: &'a SessionDB, holder: &'a str) -> crate::Result<Self> {
+        db.acquire_collection_lock(holder, COLLECTION_LOCK_STALE_MS)?;
+        Ok(Self {
+            db,
+            holder,
+            last_heartbeat: Cell::new(Instant::now()),
+        })
+    }
+
+    /// 长时间采集期间定期续期，避免被当作失效锁接管
+    fn heartbeat(&self) {
+        if self.last_heartbeat.get().elapsed() < COLLECTION_LOCK_HEARTBEAT {
+            return;
+        }
+        self.last_heartbeat.set(Instant::now());
+        match self.db.heartbeat_collection_lock(self.holder) {
+            Ok(true) => {}
+            Ok(false) => tracing::warn!("Collection lock was taken over: {}", self.holder),
+            Err(e) => tracing::warn!("Failed to heartbeat collection lock: {}", e),
+        }
+    }
+}
+
+impl Drop for CollectionLockGuard<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.db.release_collection_lock(self.holder) {
+            tracing::warn!("Failed to release collection lock: {}", e);
+        }
+    }
+}
+
+/// 当前进程的采集锁持有者标识：`进程名@pid#随机后缀`
+fn default_lock_holder() -> String {
+    let exe = std::env::current_exe()
+        .ok()
+        .and_then(|p| p.file_stem().map(|s| s.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "unknown".to_string());
+    let suffix = uuid::Uuid::new_v4().simple().to_string();
+    format!("{}@{}#{}", exe, std::process::id(), &suffix[..8])
 }
 
 impl<'a> Collector<'a> {
@@ -34,228 +392,756 @@ impl<'a> Collector<'a> {
     pub fn new(db: &'a SessionDB) -> Self {
         Self {
             db,
+            router: None,
             adapters: all_adapters(),
+            data_roots: all_watch_configs()
+                .iter()
+                .map(|config| config.path.to_path_buf())
+                .collect(),
+            update_changed_messages: false,
+            filter: CollectionFilter::default(),
+            limits: CollectLimits::default(),
+            order: CollectOrder::default(),
+            lock_holder: default_lock_holder(),
+        }
+    }
+
+    /// 创建按来源拆分数据库的采集服务
+    ///
+    /// 会话写入其来源对应的数据库；采集锁和忽略规则使用默认数据库。
+    pub fn routed(router: &'a DbRouter) -> Self {
+        Self {
+            router: Some(router),
+            ..Self::new(router.default_db())
+        }
+    }
+
+    /// 设置是否覆盖内容变化的已存在消息
+    ///
+    /// 重采集时 uuid 已存在但内容变化的消息总会记录到 message_revisions；
+    /// 默认保留旧行，开启后原地更新 content/raw。
+    pub fn with_update_changed_messages(mut self, enabled: bool) -> Self {
+        self.update_changed_messages = enabled;
+        self
+    }
+
+    /// 设置采集过滤器（默认只跳过内容为空的条目）
+    pub fn with_filter(mut self, filter: CollectionFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// 设置采集防护上限（文件大小、单条消息大小、单会话单次消息数）
+    pub fn with_limits(mut self, limits: CollectLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// 设置全量采集的处理顺序（默认 `CollectOrder::NewestFirst`）
+    pub fn with_order(mut self, order: CollectOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// 只采集指定 Claude projects 目录（自定义数据目录时使用）
+    pub fn with_claude_path(mut self, projects_path: PathBuf) -> Self {
+        self.adapters = vec![Arc::new(ClaudeAdapter::with_path(projects_path.clone()))];
+        self.data_roots = vec![projects_path];
+        self
+    }
+
+    /// 来源对应的数据库
+    fn db_for(&self, source: &str) -> &'a SessionDB {
+        match self.router {
+            Some(router) => router.for_source(source),
+            None => self.db,
+        }
+    }
+
+    /// 会话所在的数据库
+    fn db_for_session(&self, session_id: &str) -> crate::Result<&'a SessionDB> {
+        match self.router {
+            Some(router) => router.for_session(session_id).map(|db| &**db),
+            None => Ok(self.db),
         }
     }
 
+    /// 加载忽略规则（数据库 + 环境变量），加载失败时不忽略任何会话
+    fn load_ignore_rules(&self) -> IgnoreRules {
+        IgnoreRules::load(self.db).unwrap_or_else(|e| {
+            tracing::warn!("Failed to load ignore rules: {}", e);
+            IgnoreRules::default()
+        })
+    }
+
     /// 执行全量采集
     ///
-    /// 遍历所有适配器，扫描所有会话文件，增量写入数据库。
+    /// 先列出所有适配器的会话文件，按 `CollectOrder` 排序后逐个增量写入数据库。
     /// 使用时间戳增量采集：只采集比数据库中最新消息更新的消息（提前量 30 分钟）。
+    /// 采集锁被其他进程持有时返回 `Error::CollectionInProgress`。
     pub fn collect_all(&self) -> Result<CollectResult> {
-        const BUFFER_MS: i64 = 30 * 60 * 1000; // 30 分钟提前量
+        self.collect_all_inner(None, |_| {})
+    }
 
+    /// 分两阶段执行全量采集
+    ///
+    /// 先采集 `recent_window` 内修改过的会话文件，完成后以累计结果回调 `on_recent_done`，
+    /// 再采集其余文件。首次采集时最近的项目可以更早出现在 UI 中。
+    pub fn collect_all_phased(
+        &self,
+        recent_window: Duration,
+        on_recent_done: impl FnMut(&CollectResult),
+    ) -> Result<CollectResult> {
+        self.collect_all_inner(Some(recent_window), on_recent_done)
+    }
+
+    fn collect_all_inner(
+        &self,
+        recent_window: Option<Duration>,
+        mut on_recent_done: impl FnMut(&CollectResult),
+    ) -> Result<CollectResult> {
+        let lock = CollectionLockGuard::acquire(self.db, &self.lock_holder)?;
         let mut result = CollectResult::default();
+        let ignore_rules = self.load_ignore_rules();
+        let failures = self.load_collect_failures();
+
+        // 区分"目录不存在"和"无权访问"：后者适配器只会返回空列表
+        for root in &self.data_roots {
+            match check_dir_access(root) {
+                Ok(_) => {}
+                Err(crate::Error::AccessDenied(path)) => {
+                    tracing::warn!("🔒 No permission to read {}", path.display());
+                    result.permission_errors.push(path);
+                }
+                Err(e) => {
+                    result.errors.push(CollectError::new(
+                        root,
+                        None,
+                        None,
+                        CollectStage::Discover,
+                        format!("Failed to access {}: {}", root.display(), e),
+                    ));
+                }
+            }
+        }
+
+        let mut candidates = self.discover_sessions(&mut result);
+        if self.order == CollectOrder::NewestFirst {
+            // 稳定排序：mtime 未知的排在最后，同 mtime 保持目录顺序
+            candidates.sort_by(|a, b| b.mtime.cmp(&a.mtime));
+        }
+
+        match recent_window {
+            None => {
+                for candidate in &candidates {
+                    lock.heartbeat();
+                    self.collect_session_isolated(candidate, &ignore_rules, &failures, &mut result);
+                }
+            }
+            Some(window) => {
+                let cutoff = mtime_cutoff(window);
+                let (recent, backlog): (Vec<_>, Vec<_>) = candidates
+                    .iter()
+                    .partition(|c| c.mtime.is_some_and(|mtime| mtime >= cutoff));
+                for candidate in recent {
+                    lock.heartbeat();
+                    self.collect_session_isolated(candidate, &ignore_rules, &failures, &mut result);
+                }
+                on_recent_done(&result);
+                for candidate in backlog {
+                    lock.heartbeat();
+                    self.collect_session_isolated(candidate, &ignore_rules, &failures, &mut result);
+                }
+            }
+        }
+
+        // Only print when there are new messages
+        if result.messages_inserted > 0 {
+            tracing::info!(
+                "Collect: {} sessions, {} new messages",
+                result.sessions_scanned,
+                result.messages_inserted
+            );
+        }
+
+        Ok(result)
+    }
 
-        // 遍历所有适配器
+    /// 列出所有适配器的会话文件（按适配器注册顺序和目录顺序）
+    fn discover_sessions(&self, result: &mut CollectResult) -> Vec<SessionCandidate> {
+        let mut candidates = Vec::new();
         for adapter in &self.adapters {
             let source = adapter.source();
-
-            // 列出所有会话
             let sessions = match adapter.list_sessions() {
                 Ok(s) => s,
                 Err(e) => {
                     let err_msg = format!("{:?} failed to list sessions: {}", source, e);
                     tracing::warn!("{}", err_msg);
-                    result.errors.push(err_msg);
+                    result.errors.push(CollectError::new(
+                        PathBuf::new(),
+                        None,
+                        Some(source),
+                        CollectStage::Discover,
+                        err_msg,
+                    ));
                     continue;
                 }
             };
 
-            for meta in sessions {
-                // 跳过空 project_path 的会话（文件可能不完整，下次采集会重试）
-                if meta.project_path.is_empty() {
-                    tracing::debug!("Skipping empty project_path: session_id={}", meta.id);
-                    continue;
+            candidates.extend(sessions.into_iter().map(|meta| {
+                let mtime = meta.file_mtime.or_else(|| {
+                    meta.session_path
+                        .as_deref()
+                        .and_then(|path| std::fs::metadata(path).ok())
+                        .and_then(|m| m.modified().ok())
+                        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                        .map(|d| d.as_millis() as u64)
+                });
+                SessionCandidate {
+                    adapter: adapter.clone(),
+                    meta,
+                    mtime,
                 }
+            }));
+            result.projects_scanned += 1;
+        }
+        candidates
+    }
 
-                // mtime 剪枝：文件未变化则跳过
-                if let Some(file_mtime) = meta.file_mtime {
-                    if let Ok(Some(db_mtime)) = self.db.get_session_file_mtime(&meta.id) {
-                        if file_mtime == db_mtime as u64 {
-                            continue; // 文件未变化，跳过
-                        }
-                    }
-                }
+    /// 采集单个会话文件，适配器解析时 panic（畸形文件触发的解析器 bug）也记为该文件的错误，
+    /// 不中断整轮采集
+    ///
+    /// 连续失败的会话处于退避期时不再解析，只记录一条错误（`failures` 为本轮开始时的失败记录）。
+    fn collect_session_isolated(
+        &self,
+        candidate: &SessionCandidate,
+        ignore_rules: &IgnoreRules,
+        failures: &HashMap<String, CollectFailure>,
+        result: &mut CollectResult,
+    ) {
+        let meta = &candidate.meta;
+        let source = candidate.adapter.source();
+        let session_path = PathBuf::from(meta.session_path.as_deref().unwrap_or_default());
+        let previous = failures.get(&meta.id);
+        if let Some(message) = previous.and_then(|f| self.limits.check_backoff(f)) {
+            tracing::debug!("Skipping session {}: {}", meta.id, message);
+            result.sessions_backed_off += 1;
+            result.errors.push(CollectError::new(
+                &session_path,
+                Some(&meta.id),
+                Some(source),
+                CollectStage::Discover,
+                message,
+            ));
+            return;
+        }
 
-                // 获取或创建项目
-                let project_name = meta
-                    .project_name
-                    .as_deref()
-                    .unwrap_or_else(|| extract_project_name(&meta.project_path));
-                let source_str = source.to_string();
-
-                let project_id = match self.db.get_or_create_project_with_encoded(
-                    project_name,
-                    &meta.project_path,
-                    &source_str,
-                    meta.encoded_dir_name.as_deref(),
-                ) {
-                    Ok(id) => id,
-                    Err(e) => {
-                        result
-                            .errors
-                            .push(format!("Failed to create project: {}", e));
-                        continue;
-                    }
-                };
-
-                // 获取数据库中该会话的最新消息时间戳（时间戳增量采集）
-                let latest_ts = self
-                    .db
-                    .get_session_latest_timestamp(&meta.id)
-                    .unwrap_or(None);
-                let cutoff_ts = latest_ts.map(|ts| ts - BUFFER_MS).unwrap_or(0);
-
-                // 解析会话
-                let parse_result = match adapter.parse_session(&meta) {
-                    Ok(Some(r)) => r,
-                    Ok(None) => continue,
-                    Err(e) => {
-                        let err_msg = format!("Failed to parse session {}: {}", meta.id, e);
-                        tracing::debug!("{}", err_msg);
-                        result.errors.push(err_msg);
-                        continue;
-                    }
-                };
-
-                // 创建会话
-                let session_input = SessionInput {
-                    session_id: meta.id.clone(),
-                    project_id,
-                    cwd: parse_result.cwd.clone(),
-                    model: parse_result.model.clone(),
-                    channel: meta.channel.clone(),
-                    message_count: Some(parse_result.messages.len() as i64),
-                    file_mtime: meta.file_mtime.map(|t| t as i64),
-                    file_size: meta.file_size.map(|s| s as i64),
-                    file_offset: None, // 全量扫描不使用增量读取
-                    file_inode: None,
-                    meta: None,
-                    session_type: meta.session_type.clone(),
-                    source: Some(source_str.clone()),
-                };
-                if let Err(e) = self.db.upsert_session_full(&session_input) {
-                    result
-                        .errors
-                        .push(format!("Failed to create session: {}", e));
-                    continue;
-                }
+        let errors_before = result.errors.len();
+        let caught = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            self.collect_session(candidate, ignore_rules, result)
+        }));
+        if let Err(panic) = caught {
+            let reason = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            let err_msg = format!("Panicked while collecting session {}: {}", meta.id, reason);
+            tracing::error!("{}", err_msg);
+            result.errors.push(CollectError::new(
+                &session_path,
+                Some(&meta.id),
+                Some(source),
+                CollectStage::Parse,
+                err_msg,
+            ));
+        }
 
-                // 写入 session_relations（如果有 parent，即 subagent）
-                if let Some(ref parent_id) = meta.parent_session_id {
-                    if let Err(e) = self.db.insert_session_relation(
-                        parent_id,
-                        &meta.id,
-                        meta.session_type.as_deref().unwrap_or("subagent"),
-                        &source_str,
-                    ) {
-                        tracing::warn!("Failed to insert session relation: {}", e);
-                    }
-                }
+        self.track_failures(
+            self.db_for(&source.to_string()),
+            &meta.id,
+            &session_path,
+            &result.errors[errors_before..],
+            previous.is_some(),
+        );
+    }
+
+    /// 加载所有数据库中的采集失败记录，加载失败时视为没有
+    fn load_collect_failures(&self) -> HashMap<String, CollectFailure> {
+        let dbs: Vec<&SessionDB> = match self.router {
+            Some(router) => router.all().map(|(_, db)| &**db).collect(),
+            None => vec![self.db],
+        };
+        let mut failures = HashMap::new();
+        for db in dbs {
+            match db.list_collect_failures() {
+                Ok(list) => failures.extend(list.into_iter().map(|f| (f.session_id.clone(), f))),
+                Err(e) => tracing::warn!("Failed to load collect failures: {}", e),
+            }
+        }
+        failures
+    }
 
-                // 写入 continuation chain（如果有 continuation_from）
-                if let Some(ref prev_id) = meta.continuation_from {
-                    if let Err(e) = self.db.insert_continuation(&meta.id, prev_id) {
-                        tracing::warn!("Failed to insert continuation: {}", e);
+    /// 根据会话本次采集的错误更新失败计数：解析 / 写入失败时累加，成功时清除已有记录
+    ///
+    /// Discover 阶段的错误（文件超过大小上限、退避）不计入。
+    fn track_failures(
+        &self,
+        db: &SessionDB,
+        session_id: &str,
+        session_path: &Path,
+        errors: &[CollectError],
+        had_failure: bool,
+    ) {
+        let failed = errors.iter().find(|e| e.stage != CollectStage::Discover);
+        let tracked = match failed {
+            Some(error) => db
+                .record_collect_failure(session_id, &session_path.to_string_lossy(), &error.message)
+                .map(|failures| {
+                    if failures == self.limits.max_collect_failures as i64 {
+                        tracing::warn!(
+                            "Session {} failed {} times in a row, backing off for {}s",
+                            session_id,
+                            failures,
+                            self.limits.collect_failure_cooldown_secs
+                        );
                     }
+                }),
+            None if had_failure => db.reset_collect_failures(Some(session_id)).map(drop),
+            None => Ok(()),
+        };
+        if let Err(e) = tracked {
+            tracing::warn!("Failed to track collect failures of {}: {}", session_id, e);
+        }
+    }
+
+    /// 全量采集单个会话文件（错误记录在 `result` 中）
+    fn collect_session(
+        &self,
+        candidate: &SessionCandidate,
+        ignore_rules: &IgnoreRules,
+        result: &mut CollectResult,
+    ) {
+        const BUFFER_MS: i64 = 30 * 60 * 1000; // 30 分钟提前量
+
+        let adapter = &candidate.adapter;
+        let meta = &candidate.meta;
+        let source = adapter.source();
+        let source_str = source.to_string();
+        let db = self.db_for(&source_str);
+        let session_path = PathBuf::from(meta.session_path.as_deref().unwrap_or_default());
+        let session_error = |stage: CollectStage, message: String| {
+            CollectError::new(&session_path, Some(&meta.id), Some(source), stage, message)
+        };
+
+        // 跳过空 project_path 的会话（文件可能不完整，下次采集会重试）
+        if meta.project_path.is_empty() {
+            tracing::debug!("Skipping empty project_path: session_id={}", meta.id);
+            return;
+        }
+
+        // 忽略规则：解析前跳过
+        if ignore_rules.is_session_ignored(&meta.id)
+            || ignore_rules.is_project_ignored(&meta.project_path)
+        {
+            result.sessions_ignored += 1;
+            return;
+        }
+
+        // 文件大小上限：失控的会话文件整体跳过
+        let file_size = meta
+            .file_size
+            .or_else(|| std::fs::metadata(&session_path).ok().map(|m| m.len()));
+        if let Some(message) = file_size.and_then(|size| self.limits.check_file_size(size)) {
+            tracing::warn!("Skipping session {}: {}", meta.id, message);
+            result.files_skipped_size += 1;
+            result
+                .errors
+                .push(session_error(CollectStage::Discover, message));
+            return;
+        }
+
+        // mtime 剪枝：文件未变化则跳过
+        if let Some(file_mtime) = meta.file_mtime {
+            if let Ok(Some(db_mtime)) = db.get_session_file_mtime(&meta.id) {
+                if file_mtime == db_mtime as u64 {
+                    return; // 文件未变化，跳过
                 }
+            }
+        }
 
-                // 获取当前最大 sequence，增量写入时从 max+1 开始
-                let max_sequence = self
-                    .db
-                    .get_session_max_sequence(&meta.id)
-                    .unwrap_or(None)
-                    .unwrap_or(-1);
-                let start_sequence = max_sequence + 1;
-
-                // 转换并插入消息（时间戳增量过滤）
-                let messages: Vec<MessageInput> = parse_result
-                    .messages
-                    .iter()
-                    .enumerate()
-                    .filter_map(|(i, msg)| {
-                        let timestamp = msg
-                            .timestamp
-                            .as_ref()
-                            .and_then(|s| s.parse::<i64>().ok())
-                            .unwrap_or_else(|| {
-                                std::time::SystemTime::now()
-                                    .duration_since(std::time::UNIX_EPOCH)
-                                    .map(|d| d.as_millis() as i64)
-                                    .unwrap_or(0)
-                            });
-
-                        // 只保留比 cutoff_ts 更新的消息
-                        if timestamp <= cutoff_ts {
-                            return None;
-                        }
-
-                        Some(MessageInput {
-                            uuid: msg.uuid.clone(),
-                            r#type: msg.message_type,
-                            content_text: msg.content.text.clone(),
-                            content_full: msg.content.full.clone(),
-                            timestamp,
-                            sequence: start_sequence + i as i64,
-                            source: Some(msg.source.to_string()),
-                            channel: msg.channel.clone(),
-                            model: msg.model.clone(),
-                            tool_call_id: msg.tool_call_id.clone(),
-                            tool_name: msg.tool_name.clone(),
-                            tool_args: msg.tool_args.clone(),
-                            raw: msg.raw.clone(),
-                            approval_status: None,
-                            approval_resolved_at: None,
-                        })
-                    })
-                    .collect();
+        // 指纹剪枝：mtime 变了但大小和最后一行没变（跨文件系统复制、备份恢复等）
+        let content_hash = file_content_hash(&session_path);
+        if content_hash.is_some()
+            && db.get_session_content_hash(&meta.id).ok().flatten() == content_hash
+        {
+            tracing::debug!("Session {} content unchanged, skipping", meta.id);
+            return;
+        }
 
-                // 如果没有新消息，跳过
-                if messages.is_empty() {
-                    continue;
+        // 获取或创建项目
+        let project_name = meta
+            .project_name
+            .as_deref()
+            .unwrap_or_else(|| extract_project_name(&meta.project_path));
+        let project_id = match db.get_or_create_project_with_encoded(
+            project_name,
+            &meta.project_path,
+            &source_str,
+            meta.encoded_dir_name.as_deref(),
+        ) {
+            Ok(id) => id,
+            Err(e) => {
+                result.errors.push(session_error(
+                    CollectStage::Insert,
+                    format!("Failed to create project: {}", e),
+                ));
+                return;
+            }
+        };
+
+        // 获取数据库中该会话的最新消息时间戳（时间戳增量采集）
+        let latest_ts = db.get_session_latest_timestamp(&meta.id).unwrap_or(None);
+        let cutoff_ts = latest_ts.map(|ts| ts - BUFFER_MS).unwrap_or(0);
+
+        // 解析会话
+        let mut parse_result = match adapter.parse_session(meta) {
+            Ok(Some(r)) => r,
+            Ok(None) => return,
+            Err(e) => {
+                let err_msg = format!("Failed to parse session {}: {}", meta.id, e);
+                tracing::debug!("{}", err_msg);
+                result
+                    .errors
+                    .push(session_error(CollectStage::Parse, err_msg));
+                return;
+            }
+        };
+        if source == Source::Claude {
+            prepend_summaries(&mut parse_result.messages, &session_path, &meta.id);
+        }
+
+        // 过滤噪声条目（被过滤的条目不占用 sequence）
+        let (kept_messages, skipped) = self.filter.apply(&parse_result.messages);
+        result.skipped_by_filter += skipped;
+
+        // 获取当前最大 sequence，增量写入时从 max+1 开始
+        let max_sequence = db
+            .get_session_max_sequence(&meta.id)
+            .unwrap_or(None)
+            .unwrap_or(-1);
+        let start_sequence = max_sequence + 1;
+
+        // 转换消息（时间戳增量过滤，超长消息截断）
+        let mut messages: Vec<MessageInput> = kept_messages
+            .iter()
+            .enumerate()
+            .filter_map(|(i, msg)| {
+                let timestamp = msg
+                    .timestamp
+                    .as_ref()
+                    .and_then(|s| s.parse::<i64>().ok())
+                    .unwrap_or_else(|| {
+                        std::time::SystemTime::now()
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .map(|d| d.as_millis() as i64)
+                            .unwrap_or(0)
+                    });
+
+                // 只保留比 cutoff_ts 更新的消息
+                if timestamp <= cutoff_ts {
+                    return None;
                 }
 
-                match self.db.insert_messages(&meta.id, &messages) {
-                    Ok((inserted, new_ids)) => {
-                        if inserted > 0 {
-                            result.sessions_scanned += 1;
-                            result.messages_inserted += inserted;
-                            result.new_message_ids.extend(new_ids);
-                            tracing::debug!("Session {} inserted {} messages", meta.id, inserted);
-                        }
-                    }
-                    Err(e) => {
-                        result
-                            .errors
-                            .push(format!("Failed to insert messages: {}", e));
+                Some(MessageInput {
+                    uuid: msg.uuid.clone(),
+                    r#type: msg.message_type,
+                    content_text: msg.content.text.clone(),
+                    content_full: msg.content.full.clone(),
+                    timestamp,
+                    sequence: start_sequence + i as i64,
+                    source: Some(msg.source.to_string()),
+                    channel: msg.channel.clone(),
+                    model: msg.model.clone(),
+                    tool_call_id: msg.tool_call_id.clone(),
+                    tool_name: msg.tool_name.clone(),
+                    tool_args: msg.tool_args.clone(),
+                    raw: msg.raw.clone(),
+                    approval_status: None,
+                    approval_resolved_at: None,
+                })
+            })
+            .collect();
+        for msg in &mut messages {
+            if self.limits.cap_message(msg) {
+                result.messages_truncated += 1;
+            }
+        }
+
+        // 消息数可能超过单次上限时先不写文件状态，避免 mtime 剪枝跳过未采完的文件
+        let defer_file_state = messages.len() > self.limits.max_messages_per_session;
+
+        // 创建会话
+        let session_input = SessionInput {
+            session_id: meta.id.clone(),
+            project_id,
+            cwd: parse_result.cwd.clone(),
+            model: parse_result.model.clone(),
+            channel: meta.channel.clone(),
+            message_count: Some(kept_messages.len() as i64),
+            file_mtime: meta.file_mtime.map(|t| t as i64),
+            file_size: meta.file_size.map(|s| s as i64),
+            file_offset: None, // 全量扫描不使用增量读取
+            file_inode: None,
+            content_hash,
+            session_path: meta.session_path.clone(),
+            meta: None,
+            session_type: meta.session_type.clone(),
+            source: Some(source_str.clone()),
+        };
+        let upsert_input = if defer_file_state {
+            SessionInput {
+                file_mtime: None,
+                file_size: None,
+                content_hash: None,
+                ..session_input.clone()
+            }
+        } else {
+            session_input.clone()
+        };
+        if let Err(e) = db.upsert_session_full(&upsert_input) {
+            result.errors.push(session_error(
+                CollectStage::Insert,
+                format!("Failed to create session: {}", e),
+            ));
+            return;
+        }
+
+        // 写入 session_relations（如果有 parent，即 subagent）
+        if let Some(ref parent_id) = meta.parent_session_id {
+            if let Err(e) = db.insert_session_relation(
+                parent_id,
+                &meta.id,
+                meta.session_type.as_deref().unwrap_or("subagent"),
+                &source_str,
+            ) {
+                tracing::warn!("Failed to insert session relation: {}", e);
+            }
+        }
+
+        // 写入 continuation chain（如果有 continuation_from）
+        if let Some(ref prev_id) = meta.continuation_from {
+            if let Err(e) = db.insert_continuation(&meta.id, prev_id) {
+                tracing::warn!("Failed to insert continuation: {}", e);
+            }
+        }
+
+        // 如果没有新消息，跳过
+        if messages.is_empty() {
+            return;
+        }
+
+        match self.insert_messages_capped(db, &meta.id, &messages) {
+            Ok((inserted, new_ids, revisions, capped)) => {
+                if revisions > 0 {
+                    result.revisions_detected += revisions;
+                    tracing::warn!("Session {} has {} rewritten messages", meta.id, revisions);
+                }
+                if inserted > 0 {
+                    result.sessions_scanned += 1;
+                    result.messages_inserted += inserted;
+                    result.new_message_ids.extend(new_ids);
+                    tracing::debug!("Session {} inserted {} messages", meta.id, inserted);
+                }
+                if capped {
+                    tracing::warn!(
+                        "Session {} reached the per-run message limit ({}), resuming next run",
+                        meta.id,
+                        self.limits.max_messages_per_session
+                    );
+                } else if defer_file_state {
+                    if let Err(e) = db.upsert_session_full(&session_input) {
+                        tracing::warn!("Failed to update session file state: {}", e);
                     }
                 }
             }
+            Err(e) => {
+                result.errors.push(session_error(
+                    CollectStage::Insert,
+                    format!("Failed to insert messages: {}", e),
+                ));
+            }
+        }
+    }
 
-            result.projects_scanned += 1;
+    /// 按路径采集单个会话（精确索引）
+    ///
+    /// 直接从文件路径解析，不扫描目录。
+    /// 使用字节偏移量增量采集：只读取文件新增的部分。
+    /// 采集锁被其他进程持有时返回 `Error::CollectionInProgress`。
+    pub fn collect_by_path(&self, path: &str) -> Result<CollectResult> {
+        let _lock = CollectionLockGuard::acquire(self.db, &self.lock_holder)?;
+        let mut result = CollectResult::default();
+        let outcome = match self.prepare_by_path_inner(path, &mut result) {
+            Ok(Some(batch)) => self.apply_batch(&batch),
+            Ok(None) => Ok(result),
+            Err(e) => Err(e),
+        };
+        self.track_path_failures(path, &outcome);
+        outcome
+    }
+
+    /// 按路径采集后更新失败计数（见 `track_failures`）
+    fn track_path_failures(&self, path: &str, outcome: &Result<CollectResult>) {
+        let file_path = Path::new(path);
+        let Some(session_id) = file_path.file_stem().and_then(|s| s.to_str()) else {
+            return;
+        };
+        let Some(adapter) = self.adapters.iter().find(|a| a.should_handle(file_path)) else {
+            return;
+        };
+        let errors: Vec<CollectError> = match outcome {
+            Ok(result) if result.sessions_backed_off > 0 => return,
+            Ok(result) => result.errors.clone(),
+            Err(e) => collect_error(e).cloned().into_iter().collect(),
+        };
+        let db = self.db_for(&adapter.source().to_string());
+        let had_failure = db.get_collect_failure(session_id).ok().flatten().is_some();
+        self.track_failures(db, session_id, file_path, &errors, had_failure);
+    }
+
+    /// 补采会话中缺失的中间消息
+    ///
+    /// 从偏移 0 重新解析会话文件，只写入 uuid 不存在的消息，已有消息不变：
+    /// 缺失的消息按文件顺序填入前后已有消息之间的 sequence 缺口（缺口放不下时跳过并警告），
+    /// 文件末尾尚未采集的消息接在最大 sequence 之后。写入后重算对话轮次并更新增量读取状态。
+    /// 会话没有缺口时不读取文件；找不到会话文件时返回错误。
+    pub fn refill_gaps(&self, session_id: &str) -> Result<CollectResult> {
+        let _lock = CollectionLockGuard::acquire(self.db, &self.lock_holder)?;
+        let db = self.db_for_session(session_id)?;
+        let mut result = CollectResult::default();
+        if db.find_sequence_gaps(session_id)?.is_empty() {
+            return Ok(result);
         }
 
-        // Only print when there are new messages
-        if result.messages_inserted > 0 {
+        let (adapter, meta) = self
+            .adapters
+            .iter()
+            .find_map(|adapter| {
+                let sessions = adapter.list_sessions().ok()?;
+                let meta = sessions.into_iter().find(|m| m.id == session_id)?;
+                Some((adapter.clone(), meta))
+            })
+            .ok_or_else(|| anyhow::anyhow!("Session file not found: {}", session_id))?;
+        let source = adapter.source();
+        let session_path = PathBuf::from(meta.session_path.as_deref().unwrap_or_default());
+
+        // 全量解析；Claude 同时得到读到文件末尾的增量状态
+        let (parse_result, state) = if source == crate::Source::Claude {
+            let incremental = ClaudeAdapter::new().parse_session_incremental(&meta, None)?;
+            (incremental.result, Some(incremental.state))
+        } else {
+            (adapter.parse_session(&meta)?, None)
+        };
+        let Some(parse_result) = parse_result else {
+            return Ok(result);
+        };
+        let (kept_messages, skipped) = self.filter.apply(&parse_result.messages);
+        result.skipped_by_filter = skipped;
+
+        // 按文件顺序扫描，缺失的消息暂存，遇到已有消息时填入两者之间的缺口
+        let existing = db.get_session_message_sequences(session_id)?;
+        let mut refill = Vec::new();
+        let mut pending = Vec::new();
+        let mut prev_sequence = -1;
+        for msg in kept_messages {
+            let Some(&sequence) = existing.get(&msg.uuid) else {
+                pending.push(msg);
+                continue;
+            };
+            let slots = (sequence - prev_sequence - 1).max(0) as usize;
+            if pending.len() <= slots {
+                refill.extend(
+                    pending
+                        .drain(..)
+                        .enumerate()
+                        .map(|(i, msg)| message_input(msg, prev_sequence + 1 + i as i64)),
+                );
+            } else {
+                tracing::warn!(
+                    "Session {}: {} missing messages do not fit the gap before sequence {}, skipping",
+                    session_id,
+                    pending.len(),
+                    sequence
+                );
+                pending.clear();
+            }
+            prev_sequence = prev_sequence.max(sequence);
+        }
+        refill.extend(
+            pending
+                .into_iter()
+                .enumerate()
+                .map(|(i, msg)| message_input(msg, prev_sequence + 1 + i as i64)),
+        );
+        result.messages_truncated = refill
+            .iter_mut()
+            .filter(|msg| self.limits.cap_message(msg))
+            .count();
+
+        let (inserted, new_ids, _) = db
+            .insert_messages_audited(session_id, &refill, false)
+            .map_err(|e| {
+                attach_collect_error(e, |e| {
+                    CollectError::new(
+                        &session_path,
+                        Some(session_id),
+                        Some(source),
+                        CollectStage::Insert,
+                        format!("Failed to refill messages: {}", e),
+                    )
+                })
+            })?;
+        if inserted > 0 {
+            db.reassign_session_turns(session_id)?;
             tracing::info!(
-                "Collect: {} sessions, {} new messages",
-                result.sessions_scanned,
-                result.messages_inserted
+                "Refilled {} missing messages in session {}",
+                inserted,
+                session_id
             );
         }
 
+        if let Some((offset, Some(file_id))) = state.map(|s| (s.offset, s.file_id)) {
+            db.update_session_incremental_state(
+                session_id,
+                offset as i64,
+                file_id.mtime as i64,
+                file_id.size as i64,
+                file_id.inode as i64,
+            )?;
+        }
+
+        result.sessions_scanned = 1;
+        result.messages_inserted = inserted;
+        result.new_message_ids = new_ids;
         Ok(result)
     }
 
-    /// 按路径采集单个会话（精确索引）
+    /// 按路径解析单个会话，生成采集批次（只读，不写入数据库）
     ///
-    /// 直接从文件路径解析，不扫描目录。
-    /// 使用字节偏移量增量采集：只读取文件新增的部分。
-    pub fn collect_by_path(&self, path: &str) -> Result<CollectResult> {
+    /// 供没有写权限的组件使用：解析后通过 `AgentClient::write_collect_batch`
+    /// 交给 Agent（唯一 Writer）写入。会话被忽略或无可采集内容时返回 None。
+    pub fn prepare_by_path(&self, path: &str) -> Result<Option<CollectBatch>> {
+        self.prepare_by_path_inner(path, &mut CollectResult::default())
+    }
+
+    fn prepare_by_path_inner(
+        &self,
+        path: &str,
+        result: &mut CollectResult,
+    ) -> Result<Option<CollectBatch>> {
         use std::fs;
 
-        let mut result = CollectResult::default();
         let file_path = Path::new(path);
 
         // 从路径提取 session_id
@@ -263,16 +1149,24 @@ impl<'a> Collector<'a> {
             Some(id) => id.to_string(),
             None => {
                 tracing::debug!("Invalid file path: {}", path);
-                return Ok(result);
+                return Ok(None);
             }
         };
 
+        // 忽略规则：解析前按会话 ID 和文件路径跳过
+        let ignore_rules = self.load_ignore_rules();
+        if ignore_rules.is_session_ignored(&session_id) || ignore_rules.is_path_ignored(path) {
+            tracing::debug!("Ignored by collection rules: {}", path);
+            result.sessions_ignored = 1;
+            return Ok(None);
+        }
+
         // 获取文件元数据
         let file_metadata = match fs::metadata(file_path) {
             Ok(meta) => meta,
             Err(e) => {
                 tracing::debug!("Cannot get file metadata {}: {}", path, e);
-                return Ok(result);
+                return Ok(None);
             }
         };
 
@@ -299,12 +1193,51 @@ impl<'a> Collector<'a> {
             Some(a) => a.clone(),
             None => {
                 tracing::debug!("No adapter found for path: {}", path);
-                return Ok(result);
+                return Ok(None);
             }
         };
 
         let source = adapter.source();
         let source_str = source.to_string();
+        let db = self.db_for(&source_str);
+
+        // 文件大小上限：失控的会话文件整体跳过
+        if let Some(message) = self.limits.check_file_size(file_size as u64) {
+            tracing::warn!("Skipping {}: {}", path, message);
+            result.files_skipped_size = 1;
+            result.errors.push(CollectError::new(
+                file_path,
+                Some(&session_id),
+                Some(source),
+                CollectStage::Discover,
+                message,
+            ));
+            return Ok(None);
+        }
+
+        // 连续失败的会话在退避期内不重新解析
+        let failure = db.get_collect_failure(&session_id).ok().flatten();
+        if let Some(message) = failure.and_then(|f| self.limits.check_backoff(&f)) {
+            tracing::debug!("Skipping {}: {}", path, message);
+            result.sessions_backed_off = 1;
+            result.errors.push(CollectError::new(
+                file_path,
+                Some(&session_id),
+                Some(source),
+                CollectStage::Discover,
+                message,
+            ));
+            return Ok(None);
+        }
+
+        // 指纹未变（只有 mtime 变化）时不重新解析
+        let content_hash = file_content_hash(file_path);
+        if content_hash.is_some()
+            && db.get_session_content_hash(&session_id).ok().flatten() == content_hash
+        {
+            tracing::debug!("Session {} content unchanged, skipping", session_id);
+            return Ok(None);
+        }
 
         // 尝试获取 IncrementalAdapter（目前只有 ClaudeAdapter 支持）
         let incremental_adapter: Option<&dyn IncrementalAdapter> =
@@ -317,37 +1250,27 @@ impl<'a> Collector<'a> {
             };
 
         // 直接构造 SessionMeta，不扫描目录
-        let meta = SessionMeta {
-            id: session_id.clone(),
-            source,
-            channel: Some("code".to_string()),
-            project_path: String::new(), // 后面从解析结果获取
-            project_name: None,
-            encoded_dir_name: encoded_dir_name.clone(),
-            session_path: Some(path.to_string()),
-            file_mtime: None,
-            file_size: None,
-            message_count: None,
-            cwd: None,
-            model: None,
-            meta: None,
-            created_at: None,
-            updated_at: None,
-            last_message_type: None,
-            last_message_preview: None,
-            last_message_at: None,
-            parent_session_id: None,
-            session_type: None,
-            continuation_from: None,
-        };
+        let meta = session_meta_for_path(&session_id, source, path);
 
         // 检查是否支持增量读取
         let use_incremental = source == crate::Source::Claude && incremental_adapter.is_none();
 
-        // 如果是 Claude 源，使用增量读取
-        let (parse_result, new_state) = if use_incremental {
+        // 失败时附带文件、会话和阶段（调用方可用 `collect_error` 取出）
+        let collect_error = |stage: CollectStage, message: String| {
+            CollectError::new(file_path, Some(&session_id), Some(source), stage, message)
+        };
+
+        // 如果是 Claude 源，使用增量读取（第三项为是否从文件开头解析）
+        let (parse_result, new_state, full_parse) = if use_incremental {
             // 获取数据库中保存的增量状态
-            let saved_state = self.db.get_session_incremental_state(&session_id)?;
+            let saved_state = db.get_session_incremental_state(&session_id).map_err(|e| {
+                attach_collect_error(e, |e| {
+                    collect_error(
+                        CollectStage::Discover,
+                        format!("Failed to load incremental state {}: {}", session_id, e),
+                    )
+                })
+            })?;
 
             // 构建 ReaderState
             let reader_state = saved_state.map(|(offset, mtime, size, inode)| {
@@ -358,10 +1281,20 @@ impl<'a> Collector<'a> {
                 );
                 ReaderState::from_saved(offset as u64, file_id)
             });
+            let from_start = reader_state.is_none();
 
             // 使用 ClaudeAdapter 的增量读取
             let claude_adapter = crate::ClaudeAdapter::new();
-            let incremental_result = claude_adapter.parse_session_incremental(&meta, reader_state)?;
+            let incremental_result = claude_adapter
+                .parse_session_incremental(&meta, reader_state)
+                .map_err(|e| {
+                    attach_collect_error(e, |e| {
+                        collect_error(
+                            CollectStage::Parse,
+                            format!("Failed to parse session {}: {}", session_id, e),
+                        )
+                    })
+                })?;
 
             if incremental_result.was_reset {
                 tracing::info!(
@@ -370,150 +1303,241 @@ impl<'a> Collector<'a> {
                 );
             }
 
-            (incremental_result.result, Some(incremental_result.state))
+            let full_parse = from_start || incremental_result.was_reset;
+            (
+                incremental_result.result,
+                Some(incremental_result.state),
+                full_parse,
+            )
         } else {
             // 使用传统的全量解析
-            let result = adapter.parse_session(&meta)?;
-            (result, None)
+            let result = adapter.parse_session(&meta).map_err(|e| {
+                attach_collect_error(e, |e| {
+                    collect_error(
+                        CollectStage::Parse,
+                        format!("Failed to parse session {}: {}", session_id, e),
+                    )
+                })
+            })?;
+            (result, None, true)
         };
 
-        let parse_result = match parse_result {
+        let mut parse_result = match parse_result {
             Some(r) => r,
-            None => return Ok(result),
+            None => return Ok(None),
         };
+        // summary 条目位于文件开头，只在从头解析时补入
+        if full_parse && source == crate::Source::Claude {
+            prepend_summaries(&mut parse_result.messages, file_path, &session_id);
+        }
 
         // 从解析结果获取 project_path（cwd）
         let project_path = match &parse_result.cwd {
             Some(cwd) if !cwd.is_empty() => cwd.clone(),
             _ => {
                 tracing::debug!("Skipping empty cwd: session_id={}", session_id);
-                return Ok(result);
+                return Ok(None);
             }
         };
 
-        // 获取或创建项目
-        let project_name = extract_project_name(&project_path);
+        // 忽略规则：按解析出的项目路径跳过（写入前）
+        if ignore_rules.is_project_ignored(&project_path) {
+            tracing::debug!("Ignored by collection rules: project {}", project_path);
+            result.sessions_ignored = 1;
+            return Ok(None);
+        }
 
-        let project_id = match self.db.get_or_create_project_with_encoded(
-            project_name,
-            &project_path,
-            &source_str,
-            encoded_dir_name.as_deref(),
-        ) {
-            Ok(id) => id,
-            Err(e) => {
-                result
-                    .errors
-                    .push(format!("Failed to create project: {}", e));
-                return Ok(result);
-            }
-        };
+        let project_name = extract_project_name(&project_path).to_string();
 
         // 从路径推断是否为 subagent（路径中包含 /subagents/）
         let (session_type, parent_session_id) = detect_subagent_from_path(path);
 
-        // 创建/更新会话
-        let session_input = SessionInput {
+        // 过滤噪声条目（被过滤的条目不占用 sequence）
+        let (kept_messages, skipped) = self.filter.apply(&parse_result.messages);
+
+        let session = SessionInput {
             session_id: session_id.clone(),
-            project_id,
+            project_id: 0, // 写入时创建项目后填充
             cwd: parse_result.cwd.clone(),
             model: parse_result.model.clone(),
             channel: Some("code".to_string()),
-            message_count: Some(parse_result.messages.len() as i64),
+            message_count: Some(kept_messages.len() as i64),
             file_mtime,
             file_size: Some(file_size),
             file_offset: new_state.as_ref().map(|s| s.offset as i64),
             file_inode: Some(file_inode),
+            content_hash,
+            session_path: Some(path.to_string()),
             meta: None,
             session_type: Some(session_type.to_string()),
-            source: Some(source_str.clone()),
+            source: Some(source_str),
+        };
+
+        // 读取 continuation chain（Claude 源，检测 JSONL marker）
+        let continuation_from = if source == crate::Source::Claude {
+            crate::ClaudeAdapter::read_continuation_from_jsonl(file_path)
+        } else {
+            None
         };
-        if let Err(e) = self.db.upsert_session_full(&session_input) {
+
+        // 转换消息格式（sequence 为相对序号，写入时偏移）
+        let mut messages: Vec<MessageInput> = kept_messages
+            .iter()
+            .enumerate()
+            .map(|(i, msg)| message_input(msg, i as i64))
+            .collect();
+        let messages_truncated = messages
+            .iter_mut()
+            .filter(|msg| self.limits.cap_message(msg))
+            .count();
+
+        let incremental_state = new_state.and_then(|state| {
+            state.file_id.as_ref().map(|file_id| {
+                (
+                    state.offset as i64,
+                    file_id.mtime as i64,
+                    file_id.size as i64,
+                    file_id.inode as i64,
+                )
+            })
+        });
+
+        Ok(Some(CollectBatch {
+            project_name,
+            project_path,
+            session_path: file_path.to_path_buf(),
+            encoded_dir_name,
+            session,
+            parent_session_id,
+            continuation_from,
+            messages,
+            skipped_by_filter: skipped,
+            messages_truncated,
+            incremental_state,
+        }))
+    }
+
+    /// 写入采集批次
+    ///
+    /// 创建项目和会话，写入关系链和消息（sequence 从会话当前最大值 + 1 开始），
+    /// 最后保存增量读取状态。Agent 收到 Reader 转发的批次时也走这里。
+    pub fn apply_batch(&self, batch: &CollectBatch) -> Result<CollectResult> {
+        let mut result = CollectResult {
+            skipped_by_filter: batch.skipped_by_filter,
+            messages_truncated: batch.messages_truncated,
+            ..Default::default()
+        };
+        let session_id = &batch.session.session_id;
+        let source_str = batch.session.source.clone().unwrap_or_default();
+        let db = self.db_for(&source_str);
+        let source = self
+            .adapters
+            .iter()
+            .find(|a| a.should_handle(&batch.session_path))
+            .map(|a| a.source());
+        let insert_error = |message: String| {
+            CollectError::new(
+                &batch.session_path,
+                Some(session_id),
+                source,
+                CollectStage::Insert,
+                message,
+            )
+        };
+
+        // 获取或创建项目
+        let project_id = match db.get_or_create_project_with_encoded(
+            &batch.project_name,
+            &batch.project_path,
+            &source_str,
+            batch.encoded_dir_name.as_deref(),
+        ) {
+            Ok(id) => id,
+            Err(e) => {
+                result
+                    .errors
+                    .push(insert_error(format!("Failed to create project: {}", e)));
+                return Ok(result);
+            }
+        };
+
+        // 创建/更新会话（消息数可能超过单次上限时先不写文件状态，未采完的部分下次从旧偏移重读）
+        let defer_file_state = batch.messages.len() > self.limits.max_messages_per_session;
+        let session_input = SessionInput {
+            project_id,
+            ..batch.session.clone()
+        };
+        let upsert_input = if defer_file_state {
+            SessionInput {
+                file_mtime: None,
+                file_size: None,
+                file_offset: None,
+                file_inode: None,
+                content_hash: None,
+                ..session_input.clone()
+            }
+        } else {
+            session_input.clone()
+        };
+        if let Err(e) = db.upsert_session_full(&upsert_input) {
             result
                 .errors
-                .push(format!("Failed to create session: {}", e));
+                .push(insert_error(format!("Failed to create session: {}", e)));
             return Ok(result);
         }
 
         // 写入 session_relations（如果有 parent）
-        if let Some(ref parent_id) = parent_session_id {
-            if let Err(e) = self.db.insert_session_relation(
-                parent_id,
-                &session_id,
-                "subagent",
-                &source_str,
-            ) {
+        if let Some(ref parent_id) = batch.parent_session_id {
+            if let Err(e) =
+                db.insert_session_relation(parent_id, session_id, "subagent", &source_str)
+            {
                 tracing::warn!("Failed to insert session relation: {}", e);
             }
         }
 
-        // 写入 continuation chain（Claude 源，检测 JSONL marker）
-        if source == crate::Source::Claude {
-            if let Some(prev_id) =
-                crate::ClaudeAdapter::read_continuation_from_jsonl(file_path)
-            {
-                if let Err(e) = self.db.insert_continuation(&session_id, &prev_id) {
-                    tracing::warn!("Failed to insert continuation: {}", e);
-                }
+        // 写入 continuation chain
+        if let Some(ref prev_id) = batch.continuation_from {
+            if let Err(e) = db.insert_continuation(session_id, prev_id) {
+                tracing::warn!("Failed to insert continuation: {}", e);
             }
         }
 
+        if batch.messages.is_empty() {
+            result.projects_scanned = 1;
+            return Ok(result);
+        }
+
         // 获取当前最大 sequence，增量写入时从 max+1 开始
-        let max_sequence = self
-            .db
-            .get_session_max_sequence(&session_id)
+        let max_sequence = db
+            .get_session_max_sequence(session_id)
             .unwrap_or(None)
             .unwrap_or(-1);
         let start_sequence = max_sequence + 1;
-
-        // 转换消息格式
-        let messages: Vec<MessageInput> = parse_result
+        let messages: Vec<MessageInput> = batch
             .messages
             .iter()
-            .enumerate()
-            .map(|(i, msg)| {
-                let timestamp = msg
-                    .timestamp
-                    .as_ref()
-                    .and_then(|s| s.parse::<i64>().ok())
-                    .unwrap_or_else(|| {
-                        std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .map(|d| d.as_millis() as i64)
-                            .unwrap_or(0)
-                    });
-
-                MessageInput {
-                    uuid: msg.uuid.clone(),
-                    r#type: msg.message_type,
-                    content_text: msg.content.text.clone(),
-                    content_full: msg.content.full.clone(),
-                    timestamp,
-                    sequence: start_sequence + i as i64,
-                    source: Some(msg.source.to_string()),
-                    channel: msg.channel.clone(),
-                    model: msg.model.clone(),
-                    tool_call_id: msg.tool_call_id.clone(),
-                    tool_name: msg.tool_name.clone(),
-                    tool_args: msg.tool_args.clone(),
-                    raw: msg.raw.clone(),
-                    approval_status: None,
-                    approval_resolved_at: None,
-                }
+            .map(|msg| MessageInput {
+                sequence: start_sequence + msg.sequence,
+                ..msg.clone()
             })
             .collect();
 
-        if messages.is_empty() {
-            result.projects_scanned = 1;
-            return Ok(result);
-        }
-
-        // 插入消息（ON CONFLICT DO NOTHING 保证不重复）
-        match self.db.insert_messages(&session_id, &messages) {
-            Ok((inserted, new_ids)) => {
+        // 插入消息（ON CONFLICT DO NOTHING 保证不重复，内容变化记录修订）
+        let mut capped = false;
+        match self.insert_messages_capped(db, session_id, &messages) {
+            Ok((inserted, new_ids, revisions, hit_limit)) => {
+                capped = hit_limit;
                 result.sessions_scanned = 1;
                 result.messages_inserted = inserted;
                 result.new_message_ids = new_ids;
+                result.revisions_detected = revisions;
+                if revisions > 0 {
+                    tracing::warn!(
+                        "Session {} has {} rewritten messages",
+                        session_id,
+                        revisions
+                    );
+                }
                 if inserted > 0 {
                     tracing::info!(
                         "Incremental indexing [{}]: session {} inserted {} messages",
@@ -526,28 +1550,190 @@ impl<'a> Collector<'a> {
             Err(e) => {
                 result
                     .errors
-                    .push(format!("Failed to insert messages: {}", e));
+                    .push(insert_error(format!("Failed to insert messages: {}", e)));
+            }
+        }
+
+        if capped {
+            tracing::warn!(
+                "Session {} reached the per-run message limit ({}), resuming next run",
+                session_id,
+                self.limits.max_messages_per_session
+            );
+            result.projects_scanned = 1;
+            return Ok(result);
+        }
+        if defer_file_state {
+            if let Err(e) = db.upsert_session_full(&session_input) {
+                tracing::warn!("Failed to update session file state: {}", e);
             }
         }
 
         // 更新增量状态
-        if let Some(state) = new_state {
-            if let Some(file_id) = &state.file_id {
-                if let Err(e) = self.db.update_session_incremental_state(
-                    &session_id,
-                    state.offset as i64,
-                    file_id.mtime as i64,
-                    file_id.size as i64,
-                    file_id.inode as i64,
-                ) {
-                    tracing::warn!("Failed to update incremental state: {}", e);
-                }
+        if let Some((offset, mtime, size, inode)) = batch.incremental_state {
+            if let Err(e) =
+                db.update_session_incremental_state(session_id, offset, mtime, size, inode)
+            {
+                tracing::warn!("Failed to update incremental state: {}", e);
             }
         }
 
         result.projects_scanned = 1;
         Ok(result)
     }
+
+    /// 写入消息，单次最多新增 `max_messages_per_session` 条
+    ///
+    /// 按剩余额度分块写入，已存在的消息不占额度：下次从旧偏移重读时会跳过已写入的部分继续推进。
+    /// 返回 (新增数, 新消息 ID, 修订数, 是否触发上限)。
+    fn insert_messages_capped(
+        &self,
+        db: &SessionDB,
+        session_id: &str,
+        messages: &[MessageInput],
+    ) -> crate::Result<(usize, Vec<i64>, usize, bool)> {
+        let limit = self.limits.max_messages_per_session;
+        let mut inserted = 0;
+        let mut new_ids = Vec::new();
+        let mut revisions = 0;
+        let mut rest = messages;
+
+        while !rest.is_empty() {
+            if inserted >= limit {
+                return Ok((inserted, new_ids, revisions, true));
+            }
+            let (chunk, tail) = rest.split_at((limit - inserted).min(rest.len()));
+            let (n, ids, r) =
+                db.insert_messages_audited(session_id, chunk, self.update_changed_messages)?;
+            inserted += n;
+            new_ids.extend(ids);
+            revisions += r;
+            rest = tail;
+        }
+
+        Ok((inserted, new_ids, revisions, false))
+    }
+
+    /// 预估采集过滤器的影响（dry-run）
+    ///
+    /// 全量解析会话文件并统计 `filter` 会跳过哪些条目，不写入数据库，
+    /// 用于在启用更严格的过滤器前调参。
+    pub fn preview_filter_impact(
+        &self,
+        session_path: &str,
+        filter: &CollectionFilter,
+    ) -> Result<FilterImpact> {
+        let file_path = Path::new(session_path);
+        let session_id = file_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| anyhow::anyhow!("Invalid file path: {}", session_path))?;
+
+        let adapter = self
+            .adapters
+            .iter()
+            .find(|a| a.should_handle(file_path))
+            .ok_or_else(|| anyhow::anyhow!("No adapter found for path: {}", session_path))?;
+
+        let meta = session_meta_for_path(session_id, adapter.source(), session_path);
+        let parse_result = if meta.source == crate::Source::Claude {
+            crate::ClaudeAdapter::new()
+                .parse_session_incremental(&meta, None)?
+                .result
+        } else {
+            adapter.parse_session(&meta)?
+        };
+
+        let mut impact = FilterImpact::default();
+        for msg in parse_result.iter().flat_map(|r| r.messages.iter()) {
+            impact.total += 1;
+            match filter.skip_reason(msg) {
+                None => impact.kept += 1,
+                Some(SkipReason::Empty) => impact.skipped_empty += 1,
+                Some(SkipReason::Type) => impact.skipped_by_type += 1,
+                Some(SkipReason::TooShort) => impact.skipped_too_short += 1,
+                Some(SkipReason::ToolName) => impact.skipped_by_tool_name += 1,
+            }
+        }
+
+        Ok(impact)
+    }
+}
+
+/// 解析结果转换为待写入的消息（时间戳无法解析时取当前时间）
+fn message_input(msg: &ParsedMessage, sequence: i64) -> MessageInput {
+    let timestamp = msg
+        .timestamp
+        .as_ref()
+        .and_then(|s| s.parse::<i64>().ok())
+        .unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as i64)
+                .unwrap_or(0)
+        });
+
+    MessageInput {
+        uuid: msg.uuid.clone(),
+        r#type: msg.message_type,
+        content_text: msg.content.text.clone(),
+        content_full: msg.content.full.clone(),
+        timestamp,
+        sequence,
+        source: Some(msg.source.to_string()),
+        channel: msg.channel.clone(),
+        model: msg.model.clone(),
+        tool_call_id: msg.tool_call_id.clone(),
+        tool_name: msg.tool_name.clone(),
+        tool_args: msg.tool_args.clone(),
+        raw: msg.raw.clone(),
+        approval_status: None,
+        approval_resolved_at: None,
+    }
+}
+
+/// 把 Claude 会话文件中的 summary 条目作为 system 消息插到解析结果开头
+///
+/// adapter 解析时丢弃 summary 行；summary 行没有时间戳，取第一条消息的时间。
+fn prepend_summaries(messages: &mut Vec<ParsedMessage>, path: &Path, session_id: &str) {
+    let summaries = read_summary_entries(path, false);
+    if summaries.is_empty() {
+        return;
+    }
+    let timestamp = messages.first().and_then(|m| m.timestamp.clone());
+    let entries: Vec<ParsedMessage> = summaries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| entry.to_parsed_message(session_id, i, timestamp.clone()))
+        .collect();
+    messages.splice(0..0, entries);
+}
+
+/// 按文件路径构造 SessionMeta（project_path 等字段由解析结果补充）
+fn session_meta_for_path(session_id: &str, source: crate::Source, path: &str) -> SessionMeta {
+    SessionMeta {
+        id: session_id.to_string(),
+        source,
+        channel: Some("code".to_string()),
+        project_path: String::new(), // 后面从解析结果获取
+        project_name: None,
+        encoded_dir_name: extract_encoded_dir_name(path),
+        session_path: Some(path.to_string()),
+        file_mtime: None,
+        file_size: None,
+        message_count: None,
+        cwd: None,
+        model: None,
+        meta: None,
+        created_at: None,
+        updated_at: None,
+        last_message_type: None,
+        last_message_preview: None,
+        last_message_at: None,
+        parent_session_id: None,
+        session_type: None,
+        continuation_from: None,
+    }
 }
 
 /// 从 JSONL 文件路径提取 encoded_dir_name
@@ -622,4 +1808,36 @@ mod tests {
         assert_eq!(extract_project_name("simple"), "simple");
         assert_eq!(extract_project_name(""), "");
     }
+
+    #[test]
+    fn test_summary_caps_structured_errors() {
+        let errors = (0..MAX_COLLECT_ERRORS + 10)
+            .map(|i| {
+                CollectError::new(
+                    format!("/tmp/s-{}.jsonl", i),
+                    Some(format!("s-{}", i).as_str()),
+                    Some(Source::Claude),
+                    CollectStage::Parse,
+                    format!("Failed to parse session s-{}: bad line", i),
+                )
+            })
+            .collect();
+        let result = CollectResult {
+            errors,
+            ..Default::default()
+        };
+
+        let summary = result.summary();
+        assert_eq!(summary.error_count, MAX_COLLECT_ERRORS + 10);
+        assert_eq!(summary.errors.len(), MAX_COLLECT_ERRORS);
+        assert_eq!(summary.errors[0].path, PathBuf::from("/tmp/s-0.jsonl"));
+        assert_eq!(summary.errors[0].session_id.as_deref(), Some("s-0"));
+        assert_eq!(summary.errors[0].stage, CollectStage::Parse);
+
+        // Display 保持原来的日志格式
+        assert_eq!(
+            result.errors[0].to_string(),
+            "Failed to parse session s-0: bad line"
+        );
+    }
 }
@@ -2,6 +2,9 @@
 
 use std::path::PathBuf;
 
+use crate::invariants::InvariantChecks;
+use crate::search_log::DEFAULT_SEARCH_LOG_CAP;
+
 /// 数据库连接配置
 #[derive(Debug, Clone)]
 pub struct DbConfig {
@@ -13,8 +16,48 @@ pub struct DbConfig {
 
     /// 连接模式
     pub mode: ConnectionMode,
+
+    /// 单条消息内容上限（字节），超过时截断 content_text / content_full 并标记 truncated
+    ///
+    /// raw 不截断（保留原始数据用于重解析）。截断后 FTS 只能匹配保留的前缀部分。
+    /// None 表示不限制（默认）。
+    pub max_content_bytes: Option<usize>,
+
+    /// 搜索结果 snippet 的最大字符数（默认 `DEFAULT_SNIPPET_MAX_CHARS`），超出时截断
+    pub snippet_max_chars: usize,
+
+    /// WAL 自动 checkpoint 阈值（页，`PRAGMA wal_autocheckpoint`），0 表示关闭
+    ///
+    /// 提交后 WAL 超过该页数时，提交的连接自动执行一次 PASSIVE checkpoint，与
+    /// `SessionDB::checkpoint` 相同：不等待其他连接，也不截断或删除 WAL 文件，多连接时
+    /// WAL 的 inode 保持不变。其他连接仍在读取旧快照时只能合并一部分，WAL 文件本身不会
+    /// 缩小（被重新使用）。调小可让 WAL 更早合并，代价是更频繁的 checkpoint I/O；
+    /// 关闭后只在手动 `checkpoint()` 时合并。只读连接不执行 checkpoint，不受影响。
+    pub wal_autocheckpoint_pages: u32,
+
+    /// 启用的不变量检查（默认全部启用，见 `invariants` 模块）
+    ///
+    /// 同时作用于显式调用的检查和 `paranoid` feature 下写入后的自动检查。
+    pub invariant_checks: InvariantChecks,
+
+    /// 项目路径规范化时解析符号链接（路径存在时，见 `SessionDB::canonical_project_path`）
+    ///
+    /// 默认关闭：只按字面规范化，经由符号链接访问的同一目录仍是不同的项目。
+    pub resolve_project_symlinks: bool,
+
+    /// 记录 `search_fts_*` 的搜索（见 `search_log` 模块），默认关闭
+    pub log_searches: bool,
+
+    /// 搜索记录保留的条数，超过时删除最旧的
+    pub search_log_cap: usize,
 }
 
+/// 搜索结果 snippet 的默认最大字符数
+pub const DEFAULT_SNIPPET_MAX_CHARS: usize = 300;
+
+/// WAL 自动 checkpoint 的默认阈值（页，与 SQLite 默认值相同）
+pub const DEFAULT_WAL_AUTOCHECKPOINT_PAGES: u32 = 1000;
+
 /// 连接模式
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ConnectionMode {
@@ -31,9 +74,58 @@ impl DbConfig {
         Self {
             url: path.display().to_string(),
             mode: ConnectionMode::Local,
+            max_content_bytes: None,
+            snippet_max_chars: DEFAULT_SNIPPET_MAX_CHARS,
+            wal_autocheckpoint_pages: DEFAULT_WAL_AUTOCHECKPOINT_PAGES,
+            invariant_checks: InvariantChecks::default(),
+            resolve_project_symlinks: false,
+            log_searches: false,
+            search_log_cap: DEFAULT_SEARCH_LOG_CAP,
         }
     }
 
+    /// 设置单条消息内容上限（字节）
+    pub fn with_max_content_bytes(mut self, max_content_bytes: usize) -> Self {
+        self.max_content_bytes = Some(max_content_bytes);
+        self
+    }
+
+    /// 设置搜索结果 snippet 的最大字符数
+    pub fn with_snippet_max_chars(mut self, snippet_max_chars: usize) -> Self {
+        self.snippet_max_chars = snippet_max_chars;
+        self
+    }
+
+    /// 设置 WAL 自动 checkpoint 阈值（页，0 表示关闭）
+    pub fn with_wal_autocheckpoint_pages(mut self, pages: u32) -> Self {
+        self.wal_autocheckpoint_pages = pages;
+        self
+    }
+
+    /// 设置启用的不变量检查
+    pub fn with_invariant_checks(mut self, checks: InvariantChecks) -> Self {
+        self.invariant_checks = checks;
+        self
+    }
+
+    /// 项目路径规范化时解析符号链接
+    pub fn with_resolve_project_symlinks(mut self, resolve: bool) -> Self {
+        self.resolve_project_symlinks = resolve;
+        self
+    }
+
+    /// 记录搜索（见 `search_log` 模块）
+    pub fn with_log_searches(mut self, enabled: bool) -> Self {
+        self.log_searches = enabled;
+        self
+    }
+
+    /// 设置搜索记录保留的条数
+    pub fn with_search_log_cap(mut self, cap: usize) -> Self {
+        self.search_log_cap = cap;
+        self
+    }
+
     /// 从环境变量或默认路径创建配置
     pub fn from_env() -> Self {
         if let Ok(url) = std::env::var("CLAUDE_SESSION_DB_URL") {
@@ -41,6 +133,13 @@ impl DbConfig {
                 return Self {
                     url,
                     mode: ConnectionMode::Remote,
+                    max_content_bytes: None,
+                    snippet_max_chars: DEFAULT_SNIPPET_MAX_CHARS,
+                    wal_autocheckpoint_pages: DEFAULT_WAL_AUTOCHECKPOINT_PAGES,
+                    invariant_checks: InvariantChecks::default(),
+                    resolve_project_symlinks: false,
+                    log_searches: false,
+                    search_log_cap: DEFAULT_SEARCH_LOG_CAP,
                 };
             }
             return Self::local(url);
@@ -68,3 +167,21 @@ impl Default for DbConfig {
         Self::from_env()
     }
 }
+
+/// FFI / Agent 协议中 `limit = 0` 对应的安全上限
+///
+/// limit 约定：
+/// - Rust API：`limit: usize` 是确切的最大条数，0 返回空结果；
+///   需要"不限制"的接口使用 `Option<usize>`，None 表示不限制
+/// - FFI 与 Agent 协议：0 表示"不限制"，实际最多返回 `MAX_QUERY_LIMIT` 条，
+///   避免一次把超大会话读入内存
+pub const MAX_QUERY_LIMIT: usize = 10_000;
+
+/// 把 FFI / Agent 协议传入的 limit 转换为 Rust API 的 limit（0 映射为 `MAX_QUERY_LIMIT`）
+pub fn boundary_limit(limit: usize) -> usize {
+    if limit == 0 {
+        MAX_QUERY_LIMIT
+    } else {
+        limit
+    }
+}
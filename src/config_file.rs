@@ -0,0 +1,340 @@
+//! 共享配置文件（`~/.vimo/config.toml`）
+//!
+//! Agent 和 Client 读取同一个文件，由 ETerm 启动的 Agent 也能被最终用户调整。
+//! 分为 `[agent]`、`[client]`、`[collector]`、`[db]` 四个部分，所有键都可省略。
+//!
+//! 优先级：默认值 < 配置文件 < 环境变量。环境变量名为
+//! `VIMO_CONFIG_<部分>_<键>`（大写），如 `VIMO_CONFIG_AGENT_IDLE_TIMEOUT_SECS=60`，
+//! 值按 TOML 解析（`true`、`30`、`["a", "b"]`），解析失败时作为字符串。
+//!
+//! 读取配置从不失败：文件不存在时使用默认值，文件格式错误时警告并忽略整个文件，
+//! 未知的键只警告。
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// 配置文件名（位于数据目录下）
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// 覆盖配置项的环境变量前缀
+pub const CONFIG_ENV_PREFIX: &str = "VIMO_CONFIG_";
+
+/// 配置文件的各部分
+const SECTIONS: [&str; 4] = ["agent", "client", "collector", "db"];
+
+/// 配置文件内容（未设置的键为 None，由使用方保留默认值）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfigFile {
+    pub agent: AgentSection,
+    pub client: ClientSection,
+    pub collector: CollectorSection,
+    pub db: DbSection,
+}
+
+/// `[agent]`：见 `AgentConfig` 的同名字段
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgentSection {
+    pub idle_timeout_secs: Option<u64>,
+    pub max_waiters: Option<usize>,
+    pub max_connections: Option<usize>,
+    pub streaming_window_secs: Option<u64>,
+    pub session_idle_secs: Option<u64>,
+    pub integrity_check_interval_secs: Option<u64>,
+    pub max_rss_mb: Option<u64>,
+    pub memory_hard_limit: Option<bool>,
+    pub approval_digest_interval_secs: Option<u64>,
+    pub approval_digest_min_age_secs: Option<u64>,
+    pub status_file_interval_secs: Option<u64>,
+    pub replication_file: Option<PathBuf>,
+    pub replication_url: Option<String>,
+    pub replication_batch_size: Option<usize>,
+    pub replication_interval_secs: Option<u64>,
+}
+
+/// `[client]`：见 `ClientConfig` 的同名字段
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientSection {
+    pub connect_retries: Option<u32>,
+    pub retry_interval_ms: Option<u64>,
+    /// Agent 二进制路径（对应 `agent_binary_override`）
+    pub agent_binary: Option<PathBuf>,
+    pub assume_socket_activated: Option<bool>,
+    pub allow_agent_arch_mismatch: Option<bool>,
+    pub allow_download: Option<bool>,
+    pub allow_deploy: Option<bool>,
+}
+
+/// `[collector]`：见 `CollectionFilter` 和 `CollectLimits` 的同名字段
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CollectorSection {
+    pub skip_empty_messages: Option<bool>,
+    pub skip_types: Option<Vec<String>>,
+    pub min_content_chars: Option<usize>,
+    pub skip_tool_names: Option<Vec<String>>,
+    pub max_file_bytes: Option<u64>,
+    pub max_message_bytes: Option<usize>,
+    pub max_messages_per_session: Option<usize>,
+    pub max_collect_failures: Option<u32>,
+    pub collect_failure_cooldown_secs: Option<u64>,
+}
+
+/// `[db]`：见 `DbConfig` 的同名字段
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DbSection {
+    pub max_content_bytes: Option<usize>,
+    pub wal_autocheckpoint_pages: Option<u32>,
+    pub log_searches: Option<bool>,
+    pub search_log_cap: Option<usize>,
+    /// 来源名称 → 数据库文件（对应 `source_databases`）
+    pub source_databases: Option<BTreeMap<String, PathBuf>>,
+}
+
+impl ConfigFile {
+    /// 数据目录下的配置文件路径
+    pub fn path(data_dir: &Path) -> PathBuf {
+        data_dir.join(CONFIG_FILE_NAME)
+    }
+
+    /// 读取数据目录下的配置文件，并用环境变量覆盖
+    pub fn load(data_dir: &Path) -> Self {
+        Self::load_with_env(&Self::path(data_dir), std::env::vars())
+    }
+
+    /// 读取 `path`，并用 `env` 中的 `VIMO_CONFIG_*` 变量覆盖
+    pub fn load_with_env(path: &Path, env: impl IntoIterator<Item = (String, String)>) -> Self {
+        let file = read_table(path);
+        let overrides = env_table(env);
+
+        let mut merged = file.clone();
+        for (section, values) in &overrides {
+            let Some(values) = values.as_table() else {
+                continue;
+            };
+            let target = merged
+                .entry(section.clone())
+                .or_insert(toml::Value::Table(toml::Table::new()));
+            match target.as_table_mut() {
+                Some(target) => target.extend(values.clone()),
+                // 文件中的同名键不是表（格式错误），由环境变量整体替换
+                None => *target = toml::Value::Table(values.clone()),
+            }
+        }
+
+        let source = path.display().to_string();
+        if let Some(config) = deserialize(merged, &source) {
+            return config;
+        }
+        // 类型不匹配（如 idle_timeout_secs = "abc"）：忽略出错的一方
+        deserialize(overrides, "environment")
+            .or_else(|| deserialize(file, &source))
+            .unwrap_or_default()
+    }
+}
+
+/// 读取配置文件为 TOML 表（不存在或格式错误时为空表）
+fn read_table(path: &Path) -> toml::Table {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return toml::Table::new(),
+        Err(e) => {
+            tracing::warn!("Failed to read config file {}: {}", path.display(), e);
+            return toml::Table::new();
+        }
+    };
+    match content.parse::<toml::Table>() {
+        Ok(table) => table,
+        Err(e) => {
+            tracing::warn!("Ignoring malformed config file {}: {}", path.display(), e);
+            toml::Table::new()
+        }
+    }
+}
+
+/// 环境变量中的覆盖项（`VIMO_CONFIG_<部分>_<键>`）
+fn env_table(env: impl IntoIterator<Item = (String, String)>) -> toml::Table {
+    let mut table = toml::Table::new();
+    for (name, raw) in env {
+        let Some(rest) = name.strip_prefix(CONFIG_ENV_PREFIX) else {
+            continue;
+        };
+        let rest = rest.to_lowercase();
+        let Some((section, key)) = SECTIONS.iter().find_map(|section| {
+            rest.strip_prefix(section)
+                .and_then(|r| r.strip_prefix('_'))
+                .filter(|key| !key.is_empty())
+                .map(|key| (*section, key))
+        }) else {
+            tracing::warn!("Ignoring unknown config environment variable {}", name);
+            continue;
+        };
+
+        table
+            .entry(section)
+            .or_insert(toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .expect("section is a table")
+            .insert(key.to_string(), parse_env_value(&raw));
+    }
+    table
+}
+
+/// 按 TOML 值解析环境变量（`30`、`true`、`["a"]`），失败时作为字符串
+fn parse_env_value(raw: &str) -> toml::Value {
+    format!("value = {}", raw)
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+/// 反序列化配置，未知的键警告后忽略，类型错误时返回 None
+fn deserialize(table: toml::Table, source: &str) -> Option<ConfigFile> {
+    let result = serde_ignored::deserialize(toml::Value::Table(table), |path| {
+        tracing::warn!("Unknown config key in {}: {}", source, path);
+    });
+    match result {
+        Ok(config) => Some(config),
+        Err(e) => {
+            tracing::warn!("Ignoring invalid config from {}: {}", source, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn write_config(content: &str) -> (tempfile::TempDir, PathBuf) {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = ConfigFile::path(tmp.path());
+        std::fs::write(&path, content).unwrap();
+        (tmp, path)
+    }
+
+    #[test]
+    fn test_missing_file_is_default() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let config = ConfigFile::load_with_env(&ConfigFile::path(tmp.path()), env(&[]));
+        assert_eq!(config, ConfigFile::default());
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        let (_tmp, path) = write_config(
+            r#"
+            [agent]
+            idle_timeout_secs = 120
+            max_connections = 64
+
+            [collector]
+            skip_types = ["progress"]
+            "#,
+        );
+        let config = ConfigFile::load_with_env(
+            &path,
+            env(&[
+                ("VIMO_CONFIG_AGENT_IDLE_TIMEOUT_SECS", "300"),
+                (
+                    "VIMO_CONFIG_COLLECTOR_SKIP_TYPES",
+                    r#"["progress", "snapshot"]"#,
+                ),
+                ("VIMO_CONFIG_CLIENT_AGENT_BINARY", "/opt/vimo/vimo-agent"),
+                ("VIMO_CONFIG_DB_MAX_CONTENT_BYTES", "65536"),
+                ("VIMO_AGENT_PATH", "/unrelated"),
+            ]),
+        );
+
+        assert_eq!(config.agent.idle_timeout_secs, Some(300));
+        // 文件中未被覆盖的键保留
+        assert_eq!(config.agent.max_connections, Some(64));
+        assert_eq!(
+            config.collector.skip_types,
+            Some(vec!["progress".to_string(), "snapshot".to_string()])
+        );
+        assert_eq!(
+            config.client.agent_binary,
+            Some(PathBuf::from("/opt/vimo/vimo-agent"))
+        );
+        assert_eq!(config.db.max_content_bytes, Some(65536));
+        assert_eq!(config.agent.max_waiters, None);
+    }
+
+    #[test]
+    fn test_source_databases_table() {
+        let (_tmp, path) = write_config(
+            r#"
+            [db.source_databases]
+            codex = "/data/codex.db"
+            "#,
+        );
+        let config = ConfigFile::load_with_env(&path, env(&[]));
+        let routes = config.db.source_databases.unwrap();
+        assert_eq!(routes.get("codex"), Some(&PathBuf::from("/data/codex.db")));
+    }
+
+    #[test]
+    fn test_unknown_keys_are_not_fatal() {
+        let (_tmp, path) = write_config(
+            r#"
+            future_section = 1
+
+            [agent]
+            idle_timeout_secs = 45
+            future_knob = true
+            "#,
+        );
+        let config = ConfigFile::load_with_env(&path, env(&[]));
+        assert_eq!(config.agent.idle_timeout_secs, Some(45));
+    }
+
+    #[test]
+    fn test_malformed_file_is_ignored() {
+        let (_tmp, path) = write_config("[agent\nidle_timeout_secs = ");
+        let config =
+            ConfigFile::load_with_env(&path, env(&[("VIMO_CONFIG_AGENT_MAX_WAITERS", "8")]));
+        // 文件整体忽略，环境变量仍然生效
+        assert_eq!(config.agent.idle_timeout_secs, None);
+        assert_eq!(config.agent.max_waiters, Some(8));
+    }
+
+    #[test]
+    fn test_invalid_value_type_falls_back() {
+        let (_tmp, path) = write_config(
+            r#"
+            [agent]
+            idle_timeout_secs = 90
+            "#,
+        );
+        // 环境变量类型错误：忽略环境变量，保留文件
+        let config =
+            ConfigFile::load_with_env(&path, env(&[("VIMO_CONFIG_AGENT_MAX_WAITERS", "many")]));
+        assert_eq!(config.agent.idle_timeout_secs, Some(90));
+        assert_eq!(config.agent.max_waiters, None);
+
+        // 文件类型错误：忽略文件，保留环境变量
+        let (_tmp, path) = write_config(
+            r#"
+            [agent]
+            idle_timeout_secs = "soon"
+            "#,
+        );
+        let config =
+            ConfigFile::load_with_env(&path, env(&[("VIMO_CONFIG_AGENT_MAX_WAITERS", "4")]));
+        assert_eq!(config.agent.idle_timeout_secs, None);
+        assert_eq!(config.agent.max_waiters, Some(4));
+    }
+}
@@ -3,21 +3,50 @@
 use crate::config::{ConnectionMode, DbConfig};
 use crate::error::{Error, Result};
 use crate::migrations;
-use crate::types::{ChainNode, ContinuationChain, Message, Project, ProjectWithStats, Session, SessionRelation, SessionWithProject, Stats, TalkSummary};
+use crate::observer::{ChangeEvent, Observers};
+use crate::reader::Order;
+use crate::schema;
+use crate::search_log::SearchLogBuffer;
+use crate::ignore::IgnoreRules;
+use crate::types::{ApprovalDigestGroup, ChainNode, ChangeState, CollectFailure, CollectionIgnore, CollectionLock, ContinuationChain, HistoryOverview, HistoryTotals, IgnoreKind, IndexState, Message, MessageRevision, Project, ProjectWithStats, ResumeInfo, Session, SessionMessageMetrics, SessionRelation, SessionTree, SessionWithProject, SourceHistory, Stats, TalkSummary, TurnSummary, VectorTombstone, YearHistory};
 use ai_cli_session_collector::MessageType;
 use parking_lot::Mutex;
-use rusqlite::{Connection, OptionalExtension, params};
-use std::path::Path;
+use rusqlite::{Connection, OpenFlags, OptionalExtension, params};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
 
+/// `get_talk_summaries` 单页最大条数
+pub const MAX_TALK_SUMMARIES_LIMIT: usize = 500;
+
+/// `history_overview` 返回的项目数
+pub const HISTORY_TOP_PROJECTS: usize = 5;
+
 /// Session 增量读取状态: (offset, mtime, size, inode)
 pub type IncrementalState = (i64, Option<i64>, Option<i64>, Option<i64>);
 
 /// 数据库连接
 pub struct SessionDB {
     pub(crate) conn: Arc<Mutex<Connection>>,
-    #[allow(dead_code)]
     config: DbConfig,
+    /// 变更订阅者（共享句柄共用）
+    pub(crate) observers: Arc<Observers>,
+    /// 无法写入的搜索记录（共享句柄共用，见 `search_log` 模块）
+    pub(crate) search_log_buffer: Arc<SearchLogBuffer>,
+}
+
+/// 检查运行时 SQLite 版本不低于 `schema::MIN_SQLITE_VERSION`
+///
+/// 系统 SQLite 过旧时查询会在运行中报语法错误，这里在连接时直接拒绝。
+fn check_sqlite_version() -> Result<()> {
+    if rusqlite::version_number() < schema::MIN_SQLITE_VERSION_NUMBER {
+        return Err(Error::Unsupported(format!(
+            "SQLite {} is older than the minimum supported version {}",
+            rusqlite::version(),
+            schema::MIN_SQLITE_VERSION
+        )));
+    }
+    Ok(())
 }
 
 impl SessionDB {
@@ -38,42 +67,107 @@ impl SessionDB {
             std::fs::create_dir_all(parent)?;
         }
 
-        let conn = match Connection::open(path) {
+        check_sqlite_version()?;
+
+        // 损坏可能在打开时暴露，也可能在首次读取 schema 时才暴露
+        let conn = match Self::open_local(path, config.wal_autocheckpoint_pages) {
             Ok(c) => c,
-            Err(e) if Self::is_malformed_error(&e) => {
-                let wal_exists = path.with_extension("db-wal").exists();
-                let shm_exists = path.with_extension("db-shm").exists();
-                let diag = format!(
-                    "{} (WAL: {}, SHM: {})",
-                    e,
-                    if wal_exists { "exists" } else { "missing" },
-                    if shm_exists { "exists" } else { "missing" },
-                );
-                tracing::error!("数据库损坏，需要修复: {}", diag);
-                Self::write_repair_marker(path, &diag);
-                return Err(Error::DatabaseMalformed(diag));
-            }
+            Err(e) if Self::is_malformed_error(&e) => return Err(Self::malformed_error(path, e)),
             Err(e) => return Err(e.into()),
         };
 
+        tracing::info!(
+            "Database connected: {:?} (SQLite {})",
+            path,
+            rusqlite::version()
+        );
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            config: config.clone(),
+            observers: Default::default(),
+            search_log_buffer: Default::default(),
+        })
+    }
+
+    /// 打开本地数据库并确保 schema
+    fn open_local(path: &Path, wal_autocheckpoint_pages: u32) -> rusqlite::Result<Connection> {
+        let conn = Connection::open(path)?;
+
         // 启用 WAL 模式，防止写入中断导致数据库损坏
         // - WAL: 写入先到 -wal 文件，主文件不直接修改，即使进程被 kill 也安全
         // - synchronous=NORMAL: 平衡性能和安全（WAL 模式下足够安全）
         // - busy_timeout: 多连接时等待锁的超时时间
-        conn.execute_batch(
+        // - wal_autocheckpoint: 见 `DbConfig::wal_autocheckpoint_pages`
+        conn.execute_batch(&format!(
             "PRAGMA journal_mode=WAL;
              PRAGMA synchronous=NORMAL;
-             PRAGMA busy_timeout=5000;",
-        )?;
+             PRAGMA busy_timeout=5000;
+             PRAGMA wal_autocheckpoint={};",
+            wal_autocheckpoint_pages
+        ))?;
 
         // 执行幂等迁移（确保 schema 完整）
         migrations::ensure_schema(&conn)?;
 
-        tracing::info!("Database connected: {:?}", path);
+        Ok(conn)
+    }
+
+    /// 构造损坏错误：WAL 中有数据时可能是 WAL 不匹配，否则是主文件损坏
+    fn malformed_error(path: &Path, e: rusqlite::Error) -> Error {
+        let wal_path = path.with_extension("db-wal");
+        let wal_exists = wal_path.exists();
+        let wal_has_frames = std::fs::metadata(&wal_path)
+            .map(|m| m.len() > 0)
+            .unwrap_or(false);
+        let shm_exists = path.with_extension("db-shm").exists();
+        let diag = format!(
+            "{} (WAL: {}, SHM: {})",
+            e,
+            if wal_exists { "exists" } else { "missing" },
+            if shm_exists { "exists" } else { "missing" },
+        );
+        tracing::error!("数据库损坏，需要修复: {}", diag);
+        Self::write_repair_marker(path, &diag);
+
+        if wal_has_frames {
+            Error::DatabaseMalformed {
+                diagnostic: diag,
+                source: e,
+            }
+        } else {
+            Error::Corrupted {
+                path: path.to_path_buf(),
+                diagnostic: diag,
+                source: e,
+            }
+        }
+    }
+
+    /// 只读打开本地数据库
+    ///
+    /// 不创建目录、不切换 WAL、不执行迁移，供没有写权限的组件直接读取
+    /// （数据库由 Agent 维护）。数据库文件不存在时返回错误。
+    pub fn connect_read_only(config: DbConfig) -> Result<Self> {
+        let path = config
+            .path()
+            .ok_or_else(|| Error::Config("Remote connection not supported yet".into()))?;
+        check_sqlite_version()?;
+        let conn = Connection::open_with_flags(
+            &path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX
+                | OpenFlags::SQLITE_OPEN_URI,
+        )?;
+        conn.execute_batch("PRAGMA busy_timeout=5000;")?;
+
+        tracing::info!("Database opened read-only: {:?}", path);
 
         Ok(Self {
             conn: Arc::new(Mutex::new(conn)),
-            config: config.clone(),
+            config,
+            observers: Default::default(),
+            search_log_buffer: Default::default(),
         })
     }
 
@@ -102,9 +196,40 @@ impl SessionDB {
         &self.conn
     }
 
+    /// 共享同一连接的句柄（供需要长期持有数据库的组件使用）
+    pub(crate) fn share(&self) -> Self {
+        Self {
+            conn: Arc::clone(&self.conn),
+            config: self.config.clone(),
+            observers: Arc::clone(&self.observers),
+            search_log_buffer: Arc::clone(&self.search_log_buffer),
+        }
+    }
+
+    /// 连接配置
+    pub fn config(&self) -> &DbConfig {
+        &self.config
+    }
+
     // ==================== Project 操作 ====================
 
+    /// 项目路径的规范形式（`normalize_project_path`，配置了
+    /// `DbConfig::resolve_project_symlinks` 且路径存在时再解析符号链接）
+    pub fn canonical_project_path(&self, path: &str) -> String {
+        let normalized = normalize_project_path(path);
+        if self.config.resolve_project_symlinks {
+            if let Ok(resolved) = std::fs::canonicalize(&normalized) {
+                return resolved.to_string_lossy().into_owned();
+            }
+        }
+        normalized
+    }
+
     /// 获取或创建 Project
+    ///
+    /// 按规范路径（`canonical_project_path`）查找和存储，`/a/b`、`/a/b/`、`/a/c/../b`
+    /// 是同一个项目。规范化之前写入的路径由迁移 v9 规范化；配置了
+    /// `DbConfig::resolve_project_symlinks` 时，未解析符号链接的路径也能找到。
     pub fn get_or_create_project(&self, name: &str, path: &str, source: &str) -> Result<i64> {
         self.get_or_create_project_with_encoded(name, path, source, None)
     }
@@ -117,13 +242,15 @@ impl SessionDB {
         source: &str,
         encoded_dir_name: Option<&str>,
     ) -> Result<i64> {
-        let conn = self.conn.lock();
+        let normalized = normalize_project_path(path);
+        let path = &self.canonical_project_path(path);
+        let mut conn = self.conn.lock();
 
-        // 先查找
+        // 先查找（规范路径优先，其次是未解析符号链接的路径）
         let existing: Option<i64> = conn
             .query_row(
-                "SELECT id FROM projects WHERE path = ?1",
-                params![path],
+                "SELECT id FROM projects WHERE path IN (?1, ?2) ORDER BY path = ?1 DESC LIMIT 1",
+                params![path, normalized],
                 |row| row.get(0),
             )
             .optional()?;
@@ -147,22 +274,61 @@ impl SessionDB {
 
         // 创建
         let now = current_time_ms();
-        conn.execute(
+        let tx = conn.transaction()?;
+        tx.execute(
             "INSERT INTO projects (name, path, source, encoded_dir_name, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?5)",
             params![name, path, source, encoded_dir_name, now],
         )?;
-
-        Ok(conn.last_insert_rowid())
+        let id = tx.last_insert_rowid();
+        bump_change_counter(&tx)?;
+        tx.commit()?;
+        drop(conn);
+
+        self.notify(vec![ChangeEvent::ProjectChanged {
+            project_id: id,
+            project_path: path.clone(),
+            ignored: false,
+        }]);
+        Ok(id)
     }
 
-    /// 获取所有 Projects
+    /// 获取所有 Projects（不含被忽略规则排除的项目）
     pub fn list_projects(&self) -> Result<Vec<Project>> {
+        // SQLite 中 LIMIT -1 表示不限制
+        self.query_projects(None, -1, 0)
+    }
+
+    /// 获取 Projects（按数据源过滤，支持分页，不含被忽略的项目）
+    ///
+    /// - source: 只返回该数据源的项目（如 "claude"），None 表示不过滤
+    /// - 按 updated_at 降序排列
+    pub fn list_projects_filtered(
+        &self,
+        source: Option<&str>,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<Project>> {
+        self.query_projects(source, limit as i64, offset as i64)
+    }
+
+    fn query_projects(
+        &self,
+        source: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Project>> {
         let conn = self.conn.lock();
         let mut stmt = conn.prepare(
-            "SELECT id, name, path, source, encoded_dir_name, repo_url, created_at, updated_at FROM projects ORDER BY updated_at DESC",
+            r#"
+            SELECT id, name, path, source, encoded_dir_name, repo_url, created_at, updated_at
+            FROM projects
+            WHERE ignored = 0 AND (?1 IS NULL OR source = ?1)
+            ORDER BY updated_at DESC, id DESC
+            LIMIT ?2 OFFSET ?3
+            "#,
         )?;
 
-        let rows = stmt.query_map([], |row| {
+        let rows = stmt.query_map(params![source, limit, offset], |row| {
             Ok(Project {
                 id: row.get(0)?,
                 name: row.get(1)?,
@@ -179,7 +345,7 @@ impl SessionDB {
             .map_err(Into::into)
     }
 
-    /// 获取所有 Projects（带统计信息，支持分页）
+    /// 获取所有 Projects（带统计信息，支持分页，不含被忽略的项目）
     pub fn list_projects_with_stats(
         &self,
         limit: usize,
@@ -197,8 +363,9 @@ impl SessionDB {
                 MAX(COALESCE(s.last_message_at, s.updated_at)) as last_active
             FROM projects p
             LEFT JOIN sessions s ON s.project_id = p.id
+            WHERE p.ignored = 0
             GROUP BY p.id
-            ORDER BY last_active DESC NULLS LAST
+            ORDER BY (last_active IS NULL), last_active DESC, p.id DESC
             LIMIT ?1 OFFSET ?2
             "#,
         )?;
@@ -241,12 +408,48 @@ impl SessionDB {
         .map_err(Into::into)
     }
 
-    /// 根据路径获取 Project
+    /// 获取单个 Project（带统计信息）
+    ///
+    /// 统计口径与 `list_projects_with_stats` 一致，只聚合该项目的会话。
+    pub fn get_project_with_stats(&self, project_id: i64) -> Result<Option<ProjectWithStats>> {
+        let conn = self.conn.lock();
+        conn.query_row(
+            r#"
+            SELECT
+                p.id,
+                p.name,
+                p.path,
+                COUNT(DISTINCT s.id) as session_count,
+                COALESCE(SUM(s.message_count), 0) as message_count,
+                MAX(COALESCE(s.last_message_at, s.updated_at)) as last_active
+            FROM projects p
+            LEFT JOIN sessions s ON s.project_id = p.id
+            WHERE p.id = ?1
+            GROUP BY p.id
+            "#,
+            params![project_id],
+            |row| {
+                Ok(ProjectWithStats {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    path: row.get(2)?,
+                    session_count: row.get(3)?,
+                    message_count: row.get(4)?,
+                    last_active: row.get(5)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(Into::into)
+    }
+
+    /// 根据路径获取 Project（与 `get_or_create_project` 一样按规范路径查找）
     pub fn get_project_by_path(&self, path: &str) -> Result<Option<Project>> {
+        let canonical = self.canonical_project_path(path);
         let conn = self.conn.lock();
         conn.query_row(
-            "SELECT id, name, path, source, encoded_dir_name, repo_url, created_at, updated_at FROM projects WHERE path = ?1",
-            params![path],
+            "SELECT id, name, path, source, encoded_dir_name, repo_url, created_at, updated_at FROM projects WHERE path IN (?1, ?2) ORDER BY path = ?1 DESC LIMIT 1",
+            params![canonical, normalize_project_path(path)],
             |row| {
                 Ok(Project {
                     id: row.get(0)?,
@@ -264,14 +467,73 @@ impl SessionDB {
         .map_err(Into::into)
     }
 
+    /// 重命名项目（仓库移动或改名后使用）
+    ///
+    /// 更新名称，`new_path` 不为 None 时同时更新路径（存储规范路径）；会话通过 project_id
+    /// 关联，不受影响。
+    /// 项目不存在时返回 `Error::NotFound`；新路径已属于其他项目时返回
+    /// `Error::ProjectPathConflict`，不做任何修改（需要合并时用 `update_sessions_project_id`
+    /// 把会话移到已有项目，再 `delete_project`）。
+    pub fn rename_project(
+        &self,
+        project_id: i64,
+        new_name: &str,
+        new_path: Option<&str>,
+    ) -> Result<()> {
+        let new_path = new_path.map(|path| self.canonical_project_path(path));
+        let new_path = new_path.as_deref();
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        if let Some(path) = new_path {
+            let owner: Option<i64> = tx
+                .query_row(
+                    "SELECT id FROM projects WHERE path = ?1 AND id != ?2",
+                    params![path, project_id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(owner) = owner {
+                return Err(Error::ProjectPathConflict {
+                    path: path.to_string(),
+                    project_id: owner,
+                });
+            }
+        }
+
+        let updated = tx.execute(
+            "UPDATE projects SET name = ?1, path = COALESCE(?2, path), updated_at = ?3 WHERE id = ?4",
+            params![new_name, new_path, current_time_ms(), project_id],
+        )?;
+        if updated == 0 {
+            return Err(Error::NotFound(format!("project {}", project_id)));
+        }
+        let (project_path, ignored): (String, bool) = tx.query_row(
+            "SELECT path, ignored FROM projects WHERE id = ?1",
+            params![project_id],
+            |row| Ok((row.get(0)?, row.get::<_, i64>(1)? != 0)),
+        )?;
+        bump_change_counter(&tx)?;
+
+        tx.commit()?;
+        drop(conn);
+        self.notify(vec![ChangeEvent::ProjectChanged {
+            project_id,
+            project_path,
+            ignored,
+        }]);
+        Ok(())
+    }
+
     // ==================== Session 操作 ====================
 
     /// 创建或更新 Session (简化版，仅 session_id 和 project_id)
     pub fn upsert_session(&self, session_id: &str, project_id: i64) -> Result<()> {
-        let conn = self.conn.lock();
+        let mut conn = self.conn.lock();
         let now = current_time_ms();
 
-        conn.execute(
+        let tx = conn.transaction()?;
+        tx.execute(
             r#"
             INSERT INTO sessions (session_id, project_id, created_at, updated_at)
             VALUES (?1, ?2, ?3, ?3)
@@ -280,19 +542,26 @@ impl SessionDB {
             "#,
             params![session_id, project_id, now],
         )?;
+        bump_change_counter(&tx)?;
+        tx.commit()?;
+        drop(conn);
 
+        self.notify(vec![ChangeEvent::SessionUpserted {
+            session_id: session_id.to_string(),
+        }]);
         Ok(())
     }
 
     /// 创建或更新 Session (完整版，支持所有元数据字段)
     pub fn upsert_session_full(&self, input: &SessionInput) -> Result<()> {
-        let conn = self.conn.lock();
+        let mut conn = self.conn.lock();
         let now = current_time_ms();
 
-        conn.execute(
+        let tx = conn.transaction()?;
+        tx.execute(
             r#"
-            INSERT INTO sessions (session_id, project_id, cwd, model, channel, message_count, file_mtime, file_size, file_offset, file_inode, meta, session_type, source, created_at, updated_at)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?14)
+            INSERT INTO sessions (session_id, project_id, cwd, model, channel, message_count, file_mtime, file_size, file_offset, file_inode, meta, session_type, source, content_hash, session_path, created_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?15, ?16, ?14, ?14)
             ON CONFLICT(session_id) DO UPDATE SET
                 cwd = COALESCE(excluded.cwd, sessions.cwd),
                 model = COALESCE(excluded.model, sessions.model),
@@ -302,6 +571,8 @@ impl SessionDB {
                 file_size = COALESCE(excluded.file_size, sessions.file_size),
                 file_offset = COALESCE(excluded.file_offset, sessions.file_offset),
                 file_inode = COALESCE(excluded.file_inode, sessions.file_inode),
+                content_hash = COALESCE(excluded.content_hash, sessions.content_hash),
+                session_path = COALESCE(excluded.session_path, sessions.session_path),
                 meta = COALESCE(excluded.meta, sessions.meta),
                 session_type = COALESCE(excluded.session_type, sessions.session_type),
                 source = COALESCE(excluded.source, sessions.source),
@@ -322,9 +593,17 @@ impl SessionDB {
                 input.session_type,
                 input.source,
                 now,
+                input.content_hash,
+                input.session_path,
             ],
         )?;
+        bump_change_counter(&tx)?;
+        tx.commit()?;
+        drop(conn);
 
+        self.notify(vec![ChangeEvent::SessionUpserted {
+            session_id: input.session_id.clone(),
+        }]);
         Ok(())
     }
 
@@ -372,6 +651,17 @@ impl SessionDB {
         project_path: &str,
         limit: usize,
         offset: usize,
+    ) -> Result<Vec<SessionWithProject>> {
+        self.list_sessions_by_project_path_inner(project_path, limit, offset, None)
+    }
+
+    /// 根据项目路径列出会话（`after` 为上一页最后一行的 (updated_at, id)，用于键集分页）
+    pub(crate) fn list_sessions_by_project_path_inner(
+        &self,
+        project_path: &str,
+        limit: usize,
+        offset: usize,
+        after: Option<(i64, i64)>,
     ) -> Result<Vec<SessionWithProject>> {
         let conn = self.conn.lock();
         let mut stmt = conn.prepare(
@@ -384,12 +674,14 @@ impl SessionDB {
             FROM sessions s
             INNER JOIN projects p ON s.project_id = p.id
             WHERE p.path = ?1 AND s.session_id NOT LIKE 'agent-%'
-            ORDER BY s.updated_at DESC
+              AND (?4 IS NULL OR s.updated_at < ?4 OR (s.updated_at = ?4 AND s.id < ?5))
+            ORDER BY s.updated_at DESC, s.id DESC
             LIMIT ?2 OFFSET ?3
             "#,
         )?;
 
-        let mut sessions: Vec<SessionWithProject> = stmt.query_map(params![project_path, limit as i64, offset as i64], |row| {
+        let (after_key, after_id) = after.unzip();
+        let mut sessions: Vec<SessionWithProject> = stmt.query_map(params![project_path, limit as i64, offset as i64, after_key, after_id], |row| {
             Ok(SessionWithProject {
                 id: row.get(0)?,
                 session_id: row.get(1)?,
@@ -416,6 +708,7 @@ impl SessionDB {
                 child_session_ids: None,
                 continuation_prev_id: None,
                 continuation_next_ids: None,
+                metrics: None,
             })
         })?.collect::<std::result::Result<Vec<_>, _>>()?;
 
@@ -568,9 +861,97 @@ impl SessionDB {
         }
     }
 
+    /// 获取会话第一条指定类型消息的预览（前 100 字符，如用户的第一个问题）
+    pub fn get_first_message_preview(
+        &self,
+        session_id: &str,
+        message_type: MessageType,
+    ) -> Result<Option<String>> {
+        self.message_preview(session_id, message_type, "ASC")
+    }
+
+    /// 获取会话最后一条指定类型消息的预览（前 100 字符，如助手的最终回复）
+    pub fn get_last_message_preview(
+        &self,
+        session_id: &str,
+        message_type: MessageType,
+    ) -> Result<Option<String>> {
+        self.message_preview(session_id, message_type, "DESC")
+    }
+
+    fn message_preview(
+        &self,
+        session_id: &str,
+        message_type: MessageType,
+        order: &str,
+    ) -> Result<Option<String>> {
+        let conn = self.conn.lock();
+        let text: Option<String> = conn
+            .query_row(
+                &format!(
+                    "SELECT CASE WHEN content_text != '' THEN content_text ELSE content_full END \
+                     FROM messages WHERE session_id = ?1 AND type = ?2 \
+                     ORDER BY sequence {order}, id {order} LIMIT 1"
+                ),
+                params![session_id, message_type.to_string()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(text.map(|text| Self::truncate_preview(&text, 100)))
+    }
+
     /// 按 session_id 获取单个 SessionWithProject（JOIN 项目信息）
     pub fn get_session_with_project(&self, session_id: &str) -> Result<Option<SessionWithProject>> {
         let conn = self.conn.lock();
+        self.get_session_with_project_inner(&conn, session_id)
+    }
+
+    /// 同 `get_session_with_project`，并在同一把锁内计算 `metrics`（按类型的消息数、首末消息时间）
+    ///
+    /// 供会话头部视图使用，省去额外的统计查询往返。
+    pub fn get_session_with_metrics(&self, session_id: &str) -> Result<Option<SessionWithProject>> {
+        let conn = self.conn.lock();
+        let mut session = self.get_session_with_project_inner(&conn, session_id)?;
+        if let Some(ref mut s) = session {
+            s.metrics = Some(conn.query_row(
+                r#"
+                SELECT COUNT(*),
+                       COALESCE(SUM(type = 'user' AND sidechain = 0), 0),
+                       COALESCE(SUM(type = 'assistant' AND sidechain = 0), 0),
+                       MIN(timestamp),
+                       MAX(timestamp),
+                       COALESCE(SUM(sidechain), 0),
+                       COALESCE(SUM(is_summary), 0)
+                FROM messages
+                WHERE session_id = ?1
+                "#,
+                params![session_id],
+                |row| {
+                    let first_message_at: Option<i64> = row.get(3)?;
+                    let last_message_at: Option<i64> = row.get(4)?;
+                    Ok(SessionMessageMetrics {
+                        message_count: row.get(0)?,
+                        user_message_count: row.get(1)?,
+                        assistant_message_count: row.get(2)?,
+                        first_message_at,
+                        last_message_at,
+                        duration_ms: first_message_at
+                            .zip(last_message_at)
+                            .map(|(first, last)| last - first),
+                        sidechain_message_count: row.get(5)?,
+                        summary_count: row.get(6)?,
+                    })
+                },
+            )?);
+        }
+        Ok(session)
+    }
+
+    fn get_session_with_project_inner(
+        &self,
+        conn: &parking_lot::MutexGuard<Connection>,
+        session_id: &str,
+    ) -> Result<Option<SessionWithProject>> {
         let mut session = conn.query_row(
             r#"
             SELECT s.id, s.session_id, s.project_id, p.name, p.path,
@@ -610,6 +991,7 @@ impl SessionDB {
                     child_session_ids: None,
                     continuation_prev_id: None,
                     continuation_next_ids: None,
+                    metrics: None,
                 })
             },
         ).optional()?;
@@ -664,55 +1046,224 @@ impl SessionDB {
         Ok(session)
     }
 
-    /// 获取单个 Session
-    pub fn get_session(&self, session_id: &str) -> Result<Option<Session>> {
+    /// 按 session_id 列表批量获取 SessionWithProject（JOIN 项目信息 + 最后消息预览）
+    ///
+    /// 按输入顺序返回；不存在的 ID 跳过，重复的 ID 只返回一次。
+    pub fn get_sessions_by_ids(&self, ids: &[String]) -> Result<Vec<SessionWithProject>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
         let conn = self.conn.lock();
-        conn.query_row(
+        let placeholders: String = (0..ids.len())
+            .map(|i| format!("?{}", i + 1))
+            .collect::<Vec<_>>()
+            .join(",");
+        let sql = format!(
             r#"
-            SELECT id, session_id, project_id, message_count, last_message_at,
-                   cwd, model, channel, file_mtime, file_size, meta,
-                   session_type, source, created_at, updated_at
-            FROM sessions
-            WHERE session_id = ?1
+            SELECT s.id, s.session_id, s.project_id, p.name, p.path,
+                   s.message_count, s.last_message_at,
+                   s.cwd, s.model, s.channel, s.file_mtime, s.file_size, s.encoded_dir_name, s.meta,
+                   s.session_type, s.source,
+                   s.created_at, s.updated_at
+            FROM sessions s
+            INNER JOIN projects p ON s.project_id = p.id
+            WHERE s.session_id IN ({})
             "#,
-            params![session_id],
-            |row| {
-                Ok(Session {
+            placeholders
+        );
+
+        let mut stmt = conn.prepare(&sql)?;
+        let params: Vec<&dyn rusqlite::ToSql> =
+            ids.iter().map(|id| id as &dyn rusqlite::ToSql).collect();
+        let mut by_id: std::collections::HashMap<String, SessionWithProject> = stmt
+            .query_map(params.as_slice(), |row| {
+                Ok(SessionWithProject {
                     id: row.get(0)?,
                     session_id: row.get(1)?,
                     project_id: row.get(2)?,
-                    message_count: row.get(3)?,
-                    last_message_at: row.get(4)?,
-                    cwd: row.get(5)?,
-                    model: row.get(6)?,
-                    channel: row.get(7)?,
-                    file_mtime: row.get(8)?,
-                    file_size: row.get(9)?,
-                    meta: row.get(10)?,
-                    session_type: row.get(11)?,
-                    source: row.get(12)?,
-                    created_at: row.get(13)?,
-                    updated_at: row.get(14)?,
+                    project_name: row.get(3)?,
+                    project_path: row.get(4)?,
+                    message_count: row.get(5)?,
+                    last_message_at: row.get(6)?,
+                    cwd: row.get(7)?,
+                    model: row.get(8)?,
+                    channel: row.get(9)?,
+                    file_mtime: row.get(10)?,
+                    file_size: row.get(11)?,
+                    encoded_dir_name: row.get(12)?,
+                    meta: row.get(13)?,
+                    session_type: row.get(14)?,
+                    source: row.get(15)?,
+                    created_at: row.get(16)?,
+                    updated_at: row.get(17)?,
+                    last_message_type: None,
+                    last_message_preview: None,
+                    children_count: None,
+                    parent_session_id: None,
+                    child_session_ids: None,
+                    continuation_prev_id: None,
+                    continuation_next_ids: None,
+                    metrics: None,
                 })
-            },
-        )
-        .optional()
-        .map_err(Into::into)
-    }
+            })?
+            .map(|r| r.map(|s| (s.session_id.clone(), s)))
+            .collect::<std::result::Result<_, _>>()?;
+
+        let mut sessions = Vec::with_capacity(by_id.len());
+        for id in ids {
+            if let Some(mut session) = by_id.remove(id) {
+                if let Some((msg_type, preview)) =
+                    self.get_last_message_preview_inner(&conn, &session.session_id)
+                {
+                    session.last_message_type = Some(msg_type);
+                    session.last_message_preview = Some(preview);
+                }
+                sessions.push(session);
+            }
+        }
 
-    /// 检查 Session 是否存在
-    pub fn session_exists(&self, session_id: &str) -> Result<bool> {
-        let conn = self.conn.lock();
-        let count: i64 = conn.query_row(
-            "SELECT COUNT(*) FROM sessions WHERE session_id = ?1",
-            params![session_id],
-            |row| row.get(0),
-        )?;
-        Ok(count > 0)
+        Ok(sessions)
     }
 
-    /// 获取 Session 的消息数量
-    pub fn get_session_message_count(&self, session_id: &str) -> Result<i64> {
+    /// 查找使用过指定模型的会话（按最近更新降序）
+    ///
+    /// 会话的 `model` 列，或会话中任一消息的 model 与 `model` 完全相同时匹配
+    /// （会话中途切换过模型时，`sessions.model` 只记录其中一个）。
+    pub fn find_sessions_by_model(
+        &self,
+        model: &str,
+        limit: usize,
+    ) -> Result<Vec<SessionWithProject>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT s.id, s.session_id, s.project_id, p.name, p.path,
+                   s.message_count, s.last_message_at,
+                   s.cwd, s.model, s.channel, s.file_mtime, s.file_size, s.encoded_dir_name, s.meta,
+                   s.session_type, s.source,
+                   s.created_at, s.updated_at
+            FROM sessions s
+            INNER JOIN projects p ON s.project_id = p.id
+            WHERE s.model = ?1
+               OR EXISTS (SELECT 1 FROM messages m WHERE m.session_id = s.session_id AND m.model = ?1)
+            ORDER BY s.updated_at DESC, s.id DESC
+            LIMIT ?2
+            "#,
+        )?;
+
+        let mut sessions = stmt
+            .query_map(params![model, limit as i64], |row| {
+                Ok(SessionWithProject {
+                    id: row.get(0)?,
+                    session_id: row.get(1)?,
+                    project_id: row.get(2)?,
+                    project_name: row.get(3)?,
+                    project_path: row.get(4)?,
+                    message_count: row.get(5)?,
+                    last_message_at: row.get(6)?,
+                    cwd: row.get(7)?,
+                    model: row.get(8)?,
+                    channel: row.get(9)?,
+                    file_mtime: row.get(10)?,
+                    file_size: row.get(11)?,
+                    encoded_dir_name: row.get(12)?,
+                    meta: row.get(13)?,
+                    session_type: row.get(14)?,
+                    source: row.get(15)?,
+                    created_at: row.get(16)?,
+                    updated_at: row.get(17)?,
+                    last_message_type: None,
+                    last_message_preview: None,
+                    children_count: None,
+                    parent_session_id: None,
+                    child_session_ids: None,
+                    continuation_prev_id: None,
+                    continuation_next_ids: None,
+                    metrics: None,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        for session in &mut sessions {
+            if let Some((msg_type, preview)) =
+                self.get_last_message_preview_inner(&conn, &session.session_id)
+            {
+                session.last_message_type = Some(msg_type);
+                session.last_message_preview = Some(preview);
+            }
+        }
+
+        Ok(sessions)
+    }
+
+    /// 获取单个 Session
+    pub fn get_session(&self, session_id: &str) -> Result<Option<Session>> {
+        let conn = self.conn.lock();
+        conn.query_row(
+            r#"
+            SELECT id, session_id, project_id, message_count, last_message_at,
+                   cwd, model, channel, file_mtime, file_size, meta,
+                   session_type, source, created_at, updated_at
+            FROM sessions
+            WHERE session_id = ?1
+            "#,
+            params![session_id],
+            |row| {
+                Ok(Session {
+                    id: row.get(0)?,
+                    session_id: row.get(1)?,
+                    project_id: row.get(2)?,
+                    message_count: row.get(3)?,
+                    last_message_at: row.get(4)?,
+                    cwd: row.get(5)?,
+                    model: row.get(6)?,
+                    channel: row.get(7)?,
+                    file_mtime: row.get(8)?,
+                    file_size: row.get(9)?,
+                    meta: row.get(10)?,
+                    session_type: row.get(11)?,
+                    source: row.get(12)?,
+                    created_at: row.get(13)?,
+                    updated_at: row.get(14)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(Into::into)
+    }
+
+    /// 获取会话所在的编码目录名（优先会话自身记录，其次所属项目）
+    pub fn get_session_encoded_dir_name(&self, session_id: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock();
+        conn.query_row(
+            r#"
+            SELECT COALESCE(s.encoded_dir_name, p.encoded_dir_name)
+            FROM sessions s
+            JOIN projects p ON p.id = s.project_id
+            WHERE s.session_id = ?1
+            "#,
+            params![session_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map(Option::flatten)
+        .map_err(Into::into)
+    }
+
+    /// 检查 Session 是否存在
+    pub fn session_exists(&self, session_id: &str) -> Result<bool> {
+        let conn = self.conn.lock();
+        let count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM sessions WHERE session_id = ?1",
+            params![session_id],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    /// 获取 Session 的消息数量
+    pub fn get_session_message_count(&self, session_id: &str) -> Result<i64> {
         let conn = self.conn.lock();
         conn.query_row(
             "SELECT COUNT(*) FROM messages WHERE session_id = ?1",
@@ -748,8 +1299,157 @@ impl SessionDB {
         .map_err(Into::into)
     }
 
+    /// 获取 Session 消息 sequence 的缺口（闭区间，按 sequence 升序）
+    ///
+    /// 增量读取丢失一段内容（如读取期间文件被轮转）时，会话会留下 0..500、800..900
+    /// 这样的断档，此时返回 `[(501, 799)]`。首条消息之前的缺失不计入。
+    pub fn find_sequence_gaps(&self, session_id: &str) -> Result<Vec<(i64, i64)>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT sequence + 1, next_sequence - 1 FROM (
+                SELECT sequence, LEAD(sequence) OVER (ORDER BY sequence) AS next_sequence
+                FROM messages WHERE session_id = ?1
+            )
+            WHERE next_sequence > sequence + 1
+            ORDER BY sequence
+            "#,
+        )?;
+        let rows = stmt.query_map(params![session_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Into::into)
+    }
+
+    /// 列出 sequence 有缺口的会话，返回 (session_id, 缺失的 sequence 数)，缺失多的在前
+    ///
+    /// 只比较 COUNT 与 max - min + 1，不逐行扫描缺口位置。
+    pub fn find_sessions_with_gaps(&self, limit: usize) -> Result<Vec<(String, i64)>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT session_id, MAX(sequence) - MIN(sequence) + 1 - COUNT(DISTINCT sequence) AS missing
+            FROM messages
+            GROUP BY session_id
+            HAVING missing > 0
+            ORDER BY missing DESC, session_id
+            LIMIT ?1
+            "#,
+        )?;
+        let rows = stmt.query_map(params![limit as i64], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Into::into)
+    }
+
+    /// sequence 有缺口的会话数（诊断用）
+    pub fn count_sessions_with_gaps(&self) -> Result<i64> {
+        let conn = self.conn.lock();
+        conn.query_row(
+            r#"
+            SELECT COUNT(*) FROM (
+                SELECT 1 FROM messages
+                GROUP BY session_id
+                HAVING MAX(sequence) - MIN(sequence) + 1 > COUNT(DISTINCT sequence)
+            )
+            "#,
+            [],
+            |row| row.get(0),
+        )
+        .map_err(Into::into)
+    }
+
+    /// 获取 Session 已有消息的 uuid -> sequence
+    pub fn get_session_message_sequences(&self, session_id: &str) -> Result<HashMap<String, i64>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare("SELECT uuid, sequence FROM messages WHERE session_id = ?1")?;
+        let rows = stmt.query_map(params![session_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<std::result::Result<HashMap<_, _>, _>>()
+            .map_err(Into::into)
+    }
+
+    /// 获取 Session 时长（毫秒，last_message_at - first_message_at）
+    ///
+    /// 返回:
+    /// - `Ok(None)` - session 不存在，或缺少首条/最后消息时间
+    /// - `Ok(Some(ms))` - 会话时长
+    pub fn get_session_duration(&self, session_id: &str) -> Result<Option<i64>> {
+        let conn = self.conn.lock();
+        conn.query_row(
+            "SELECT last_message_at - first_message_at FROM sessions WHERE session_id = ?1",
+            params![session_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map(Option::flatten)
+        .map_err(Into::into)
+    }
+
+    /// 获取变更状态摘要
+    ///
+    /// 按 session_id / project_path 过滤（都为 None 时为全局）。
+    pub fn get_change_state(
+        &self,
+        session_id: Option<&str>,
+        project_path: Option<&str>,
+    ) -> Result<ChangeState> {
+        let mut conditions = Vec::new();
+        let mut params_vec: Vec<&dyn rusqlite::ToSql> = Vec::new();
+        if let Some(ref sid) = session_id {
+            conditions.push(format!("t.session_id = ?{}", params_vec.len() + 1));
+            params_vec.push(sid);
+        }
+        if let Some(ref path) = project_path {
+            conditions.push(format!(
+                "t.session_id IN (SELECT s.session_id FROM sessions s JOIN projects p ON p.id = s.project_id WHERE p.path = ?{})",
+                params_vec.len() + 1
+            ));
+            params_vec.push(path);
+        }
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+
+        let conn = self.conn.lock();
+        let (message_count, max_sequence, max_id): (i64, Option<i64>, Option<i64>) = conn
+            .query_row(
+                &format!(
+                    "SELECT COUNT(*), MAX(t.sequence), MAX(t.id) FROM messages t {}",
+                    where_clause
+                ),
+                params_vec.as_slice(),
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )?;
+        let revision_count: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM message_revisions t {}", where_clause),
+            params_vec.as_slice(),
+            |row| row.get(0),
+        )?;
+
+        let state = format!(
+            "{}:{}:{}:{}",
+            message_count,
+            max_sequence.unwrap_or(-1),
+            max_id.unwrap_or(-1),
+            revision_count
+        );
+        Ok(ChangeState {
+            message_count,
+            max_sequence,
+            state_hash: fnv1a_64(state.as_bytes()),
+        })
+    }
+
     /// 获取 Sessions (支持可选的 project_id 过滤)
-    pub fn get_sessions(&self, project_id: Option<i64>, limit: usize) -> Result<Vec<Session>> {
+    ///
+    /// - include_agents: 是否包含 agent 会话（`agent-` 前缀）；与 `list_sessions_by_project_path`
+    ///   一致，通常传 false
+    pub fn get_sessions(
+        &self,
+        project_id: Option<i64>,
+        limit: usize,
+        include_agents: bool,
+    ) -> Result<Vec<Session>> {
         let conn = self.conn.lock();
 
         let (sql, params_vec): (&str, Vec<Box<dyn rusqlite::ToSql>>) = if let Some(pid) = project_id
@@ -760,13 +1460,14 @@ impl SessionDB {
                        cwd, model, channel, file_mtime, file_size, meta,
                        session_type, source, created_at, updated_at
                 FROM sessions
-                WHERE project_id = ?1
+                WHERE project_id = ?1 AND (?3 OR session_id NOT LIKE 'agent-%')
                 ORDER BY updated_at DESC
                 LIMIT ?2
                 "#,
                 vec![
                     Box::new(pid) as Box<dyn rusqlite::ToSql>,
                     Box::new(limit as i64),
+                    Box::new(include_agents),
                 ],
             )
         } else {
@@ -776,10 +1477,11 @@ impl SessionDB {
                        cwd, model, channel, file_mtime, file_size, meta,
                        session_type, source, created_at, updated_at
                 FROM sessions
+                WHERE ?2 OR session_id NOT LIKE 'agent-%'
                 ORDER BY updated_at DESC
                 LIMIT ?1
                 "#,
-                vec![Box::new(limit as i64)],
+                vec![Box::new(limit as i64), Box::new(include_agents)],
             )
         };
 
@@ -914,6 +1616,22 @@ impl SessionDB {
         Ok(result.flatten())
     }
 
+    /// 获取 session 的文件指纹（大小 + 最后一行的哈希）
+    ///
+    /// 会话不存在或尚未记录指纹时返回 None。
+    pub fn get_session_content_hash(&self, session_id: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock();
+        let result: Option<Option<String>> = conn
+            .query_row(
+                "SELECT content_hash FROM sessions WHERE session_id = ?1",
+                params![session_id],
+                |row| row.get::<_, Option<String>>(0),
+            )
+            .optional()?;
+
+        Ok(result.flatten())
+    }
+
     /// 获取 session 的增量读取状态 (用于增量读取)
     ///
     /// 返回:
@@ -942,6 +1660,36 @@ impl SessionDB {
         Ok(result)
     }
 
+    /// 获取恢复增量读取所需的全部信息（增量状态 + 会话文件路径 + 编码目录名）
+    ///
+    /// 返回 `Ok(None)` 表示 session 不存在；编码目录名优先取会话自身记录，其次所属项目。
+    pub fn get_session_resume_info(&self, session_id: &str) -> Result<Option<ResumeInfo>> {
+        let conn = self.conn.lock();
+        conn.query_row(
+            r#"
+            SELECT s.file_offset, s.file_mtime, s.file_size, s.file_inode, s.session_path,
+                   COALESCE(s.encoded_dir_name, p.encoded_dir_name), p.path
+            FROM sessions s
+            JOIN projects p ON p.id = s.project_id
+            WHERE s.session_id = ?1
+            "#,
+            params![session_id],
+            |row| {
+                Ok(ResumeInfo {
+                    file_offset: row.get::<_, Option<i64>>(0)?.unwrap_or(0),
+                    file_mtime: row.get(1)?,
+                    file_size: row.get(2)?,
+                    file_inode: row.get(3)?,
+                    session_path: row.get(4)?,
+                    encoded_dir_name: row.get(5)?,
+                    project_path: row.get(6)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(Into::into)
+    }
+
     /// 更新 session 的增量读取状态
     ///
     /// - session_id: 会话 ID
@@ -978,13 +1726,18 @@ impl SessionDB {
 
     /// 更新 session 的最后消息时间
     pub fn update_session_last_message(&self, session_id: &str, timestamp: i64) -> Result<()> {
-        let conn = self.conn.lock();
+        let mut conn = self.conn.lock();
         let now = current_time_ms();
 
-        conn.execute(
+        let tx = conn.transaction()?;
+        let updated = tx.execute(
             "UPDATE sessions SET last_message_at = ?1, updated_at = ?2 WHERE session_id = ?3",
             params![timestamp, now, session_id],
         )?;
+        if updated > 0 {
+            bump_change_counter(&tx)?;
+        }
+        tx.commit()?;
 
         Ok(())
     }
@@ -994,99 +1747,573 @@ impl SessionDB {
     /// 批量写入 Messages (自动去重)
     /// 返回 (实际插入的数量, 新插入的 message_ids)
     pub fn insert_messages(&self, session_id: &str, messages: &[MessageInput]) -> Result<(usize, Vec<i64>)> {
-        let mut conn = self.conn.lock();
-        let tx = conn.transaction()?;
+        let report = self.insert_messages_report(session_id, messages)?;
+        Ok((report.inserted, report.new_ids))
+    }
 
-        let mut inserted = 0;
-        let mut new_ids = Vec::new();
-        for msg in messages {
-            let result = tx.execute(
-                r#"
-                INSERT INTO messages (session_id, uuid, type, content_text, content_full, timestamp, sequence, source, channel, model, tool_call_id, tool_name, tool_args, raw, approval_status, approval_resolved_at)
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)
-                ON CONFLICT(uuid) DO NOTHING
-                "#,
-                params![
-                    session_id,
-                    &msg.uuid,
-                    msg.r#type.to_string(),
-                    &msg.content_text,
-                    &msg.content_full,
-                    msg.timestamp,
-                    msg.sequence,
-                    &msg.source,
-                    &msg.channel,
-                    &msg.model,
-                    &msg.tool_call_id,
-                    &msg.tool_name,
-                    &msg.tool_args,
-                    &msg.raw,
-                    &msg.approval_status.map(|s| s.to_string()),
-                    &msg.approval_resolved_at,
-                ],
-            );
+    /// 同 `insert_messages`，额外返回因 uuid 已存在或写入失败而跳过的消息（用于排查去重）
+    pub fn insert_messages_report(
+        &self,
+        session_id: &str,
+        messages: &[MessageInput],
+    ) -> Result<InsertReport> {
+        let (report, _, _) = self.insert_messages_internal(
+            session_id,
+            messages,
+            false,
+            ConflictPolicy::Ignore,
+            true,
+        )?;
+        Ok(report)
+    }
 
-            if let Ok(n) = result {
-                if n > 0 {
-                    inserted += n;
-                    // 获取刚插入的 message id
-                    let new_id = tx.last_insert_rowid();
-                    new_ids.push(new_id);
-                }
-            }
-        }
+    /// 批量写入 Messages，按 `on_conflict` 处理 uuid 已存在的消息
+    ///
+    /// `ConflictPolicy::UpdateContent` 用于重采集刷新上游修正过的消息：
+    /// 更新 content_text / content_full / raw / approval_status，内容变化时重置向量索引。
+    /// 更新不改变 message_count。
+    /// 返回 (实际插入的数量, 新插入的 message_ids, 被更新的已存在消息数量)
+    pub fn insert_messages_with_policy(
+        &self,
+        session_id: &str,
+        messages: &[MessageInput],
+        on_conflict: ConflictPolicy,
+    ) -> Result<(usize, Vec<i64>, usize)> {
+        let (report, _, updated) =
+            self.insert_messages_internal(session_id, messages, false, on_conflict, true)?;
+        Ok((report.inserted, report.new_ids, updated))
+    }
 
-        // 更新 session 的 message_count
-        tx.execute(
-            r#"
-            UPDATE sessions SET
+    /// 批量写入 Messages（自动去重），不更新会话的 message_count / first_message_at
+    ///
+    /// 分块写入大量消息时使用：每块一个事务，全部写完后调用一次 `refresh_session_counters`。
+    /// 返回 (实际插入的数量, 新插入的 message_ids)
+    pub fn insert_messages_deferred(
+        &self,
+        session_id: &str,
+        messages: &[MessageInput],
+    ) -> Result<(usize, Vec<i64>)> {
+        let (report, _, _) = self.insert_messages_internal(
+            session_id,
+            messages,
+            false,
+            ConflictPolicy::Ignore,
+            false,
+        )?;
+        Ok((report.inserted, report.new_ids))
+    }
+
+    /// 按已入库的消息重新计算会话的 message_count 和 first_message_at
+    pub fn refresh_session_counters(&self, session_id: &str) -> Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        let updated = tx.execute(
+            r#"
+            UPDATE sessions SET
                 message_count = (SELECT COUNT(*) FROM messages WHERE session_id = ?1),
+                first_message_at = COALESCE(
+                    (SELECT MIN(timestamp) FROM messages WHERE session_id = ?1),
+                    first_message_at
+                ),
                 updated_at = ?2
             WHERE session_id = ?1
             "#,
             params![session_id, current_time_ms()],
         )?;
+        if updated > 0 {
+            bump_change_counter(&tx)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// 批量写入 Messages，并审计内容变化的已存在消息
+    ///
+    /// uuid 已存在但 content_full 哈希不同时（如 Claude compact 时改写历史），
+    /// 在 message_revisions 表记录一条修订（同一变更只记录一次）。
+    /// - update_changed: false 保留旧行（默认行为）；true 原地更新 content/raw 并重置向量索引
+    ///
+    /// 返回 (实际插入的数量, 新插入的 message_ids, 新记录的修订数量)
+    pub fn insert_messages_audited(
+        &self,
+        session_id: &str,
+        messages: &[MessageInput],
+        update_changed: bool,
+    ) -> Result<(usize, Vec<i64>, usize)> {
+        let on_conflict = if update_changed {
+            ConflictPolicy::UpdateContent
+        } else {
+            ConflictPolicy::Ignore
+        };
+        let (report, revisions, _) =
+            self.insert_messages_internal(session_id, messages, true, on_conflict, true)?;
+        Ok((report.inserted, report.new_ids, revisions))
+    }
+
+    /// - audit: 是否为内容变化的已存在消息记录修订
+    /// - update_session: 是否在同一事务中更新会话的 message_count / first_message_at
+    ///
+    /// 返回 (插入结果, 修订数量, 被更新的已存在消息数量)
+    fn insert_messages_internal(
+        &self,
+        session_id: &str,
+        messages: &[MessageInput],
+        audit: bool,
+        on_conflict: ConflictPolicy,
+        update_session: bool,
+    ) -> Result<(InsertReport, usize, usize)> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        let mut inserted = 0;
+        let mut new_ids = Vec::new();
+        let mut skipped_uuids = Vec::new();
+        let mut failed_uuids = Vec::new();
+        let mut revisions = 0;
+        let mut updated = 0;
+        let update_existing = on_conflict == ConflictPolicy::UpdateContent;
+        let mut first_inserted_at: Option<i64> = None;
+        let fts = fts_enabled(&tx)?;
+        // 轮次从会话已有的最后一条消息继续，增量批次不会误开新轮次
+        let (mut turn, mut last_is_user) = current_turn_state(&tx, session_id)?;
+        for msg in messages {
+            let is_user = msg.r#type == MessageType::User;
+            let turn_index = next_turn_index(turn, last_is_user, is_user);
+
+            // 超过上限时截断内容（raw 保留完整）
+            let (content_text, text_truncated) =
+                truncate_content(&msg.content_text, self.config.max_content_bytes);
+            let (content_full, full_truncated) =
+                truncate_content(&msg.content_full, self.config.max_content_bytes);
+            let truncated = text_truncated || full_truncated;
+            let (sidechain, is_summary) = raw_entry_flags(msg.raw.as_deref());
+
+            // uuid 冲突由 ON CONFLICT DO NOTHING 处理；单条消息写入失败（约束、触发器拒绝）
+            // 只回滚该条语句，记入 failed_uuids 后继续，不影响同批次的其他消息
+            let result = tx.execute(
+                r#"
+                INSERT INTO messages (session_id, uuid, type, content_text, content_full, timestamp, sequence, source, channel, model, tool_call_id, tool_name, tool_args, raw, approval_status, approval_resolved_at, truncated, turn_index, sidechain, is_summary)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)
+                ON CONFLICT(uuid) DO NOTHING
+                "#,
+                params![
+                    session_id,
+                    &msg.uuid,
+                    msg.r#type.to_string(),
+                    content_text,
+                    content_full,
+                    msg.timestamp,
+                    msg.sequence,
+                    &msg.source,
+                    &msg.channel,
+                    &msg.model,
+                    &msg.tool_call_id,
+                    &msg.tool_name,
+                    &msg.tool_args,
+                    &msg.raw,
+                    &msg.approval_status.map(|s| s.to_string()),
+                    &msg.approval_resolved_at,
+                    truncated,
+                    turn_index,
+                    sidechain,
+                    is_summary,
+                ],
+            );
+            let n = match result {
+                Ok(n) => n,
+                Err(e) if !tx.is_autocommit() => {
+                    tracing::warn!("Skipping message {}: {}", msg.uuid, e);
+                    failed_uuids.push(msg.uuid.clone());
+                    continue;
+                }
+                // 整个事务已被回滚（如磁盘已满），中止批次
+                Err(e) => return Err(e.into()),
+            };
+
+            if n > 0 {
+                inserted += n;
+                turn = Some(turn_index);
+                last_is_user = is_user;
+                // 获取刚插入的 message id
+                let new_id = tx.last_insert_rowid();
+                new_ids.push(new_id);
+                if fts {
+                    index_message_fts(&tx, new_id, &msg.uuid)?;
+                }
+                first_inserted_at =
+                    Some(first_inserted_at.map_or(msg.timestamp, |t| t.min(msg.timestamp)));
+                continue;
+            }
+            // 影响 0 行：uuid 已存在（含同一批次内重复的 uuid）
+            skipped_uuids.push(msg.uuid.clone());
+
+            if !audit && !update_existing {
+                continue;
+            }
+
+            // uuid 已存在：比较 content_full 哈希，检测磁盘内容是否被改写
+            let existing: Option<(i64, String, String, Option<String>, Option<String>)> = tx
+                .query_row(
+                    "SELECT id, content_text, content_full, raw, approval_status FROM messages WHERE uuid = ?1",
+                    params![&msg.uuid],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+                )
+                .optional()?;
+            let Some((id, old_text, old_full, old_raw, old_approval)) = existing else {
+                continue;
+            };
+
+            let old_hash = content_hash(&old_full);
+            let new_hash = content_hash(content_full);
+            let content_changed = old_hash != new_hash;
+            let approval_status = msg.approval_status.map(|s| s.to_string());
+            // 新值为空的 raw / approval_status 不覆盖已有值
+            let metadata_changed = (msg.raw.is_some() && msg.raw != old_raw)
+                || (approval_status.is_some() && approval_status != old_approval);
+
+            if update_existing && (content_changed || metadata_changed) {
+                // 内容变了需要重新向量化
+                updated += tx.execute(
+                    r#"
+                    UPDATE messages SET
+                        content_text = ?1,
+                        content_full = ?2,
+                        raw = COALESCE(?3, raw),
+                        truncated = ?4,
+                        approval_status = COALESCE(?5, approval_status),
+                        approval_resolved_at = COALESCE(?6, approval_resolved_at),
+                        vector_indexed = CASE WHEN ?7 THEN 0 ELSE vector_indexed END
+                    WHERE uuid = ?8
+                    "#,
+                    params![
+                        content_text,
+                        content_full,
+                        &msg.raw,
+                        truncated,
+                        approval_status,
+                        &msg.approval_resolved_at,
+                        content_changed,
+                        &msg.uuid,
+                    ],
+                )?;
+                // messages_au 触发器已删除旧索引，新内容在 SAVEPOINT 中建索引
+                if fts && (old_full != content_full || old_text != content_text) {
+                    index_message_fts(&tx, id, &msg.uuid)?;
+                }
+            }
+
+            if !audit || !content_changed {
+                continue;
+            }
+
+            revisions += tx.execute(
+                r#"
+                INSERT OR IGNORE INTO message_revisions (session_id, uuid, old_hash, new_hash, new_raw, applied, detected_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                "#,
+                params![
+                    session_id,
+                    &msg.uuid,
+                    old_hash,
+                    new_hash,
+                    &msg.raw,
+                    update_existing,
+                    current_time_ms(),
+                ],
+            )?;
+        }
+
+        // 更新 session 的 message_count 和 first_message_at（取已有值与本批插入的最小值）
+        if update_session {
+            tx.execute(
+                r#"
+                UPDATE sessions SET
+                    message_count = (SELECT COUNT(*) FROM messages WHERE session_id = ?1),
+                    first_message_at = COALESCE(MIN(first_message_at, ?3), first_message_at, ?3),
+                    updated_at = ?2
+                WHERE session_id = ?1
+                "#,
+                params![session_id, current_time_ms(), first_inserted_at],
+            )?;
+        }
+
+        // 全部重复时不算新数据
+        if inserted > 0 || revisions > 0 || updated > 0 {
+            bump_change_counter(&tx)?;
+        }
 
         tx.commit()?;
-        Ok((inserted, new_ids))
+        drop(conn);
+
+        // last_message_at 由调用方在写入后更新，这里只检查 message_count
+        #[cfg(feature = "paranoid")]
+        if update_session {
+            self.paranoid_check(session_id, false);
+        }
+
+        let mut events = Vec::new();
+        if inserted > 0 {
+            events.push(ChangeEvent::NewMessages {
+                session_id: session_id.to_string(),
+                count: inserted,
+            });
+        }
+        if revisions > 0 || updated > 0 {
+            events.push(ChangeEvent::MessagesUpdated {
+                session_id: session_id.to_string(),
+                count: revisions.max(updated),
+            });
+        }
+        self.notify(events);
+        let report = InsertReport {
+            inserted,
+            new_ids,
+            skipped_uuids,
+            failed_uuids,
+        };
+        Ok((report, revisions, updated))
     }
 
-    /// 获取 Session 的 Messages
+    /// 获取 Session 的消息修订记录（按检测时间升序）
+    pub fn list_message_revisions(&self, session_id: &str) -> Result<Vec<MessageRevision>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT id, session_id, uuid, old_hash, new_hash, new_raw, applied, detected_at
+            FROM message_revisions
+            WHERE session_id = ?1
+            ORDER BY detected_at ASC, id ASC
+            "#,
+        )?;
+
+        let rows = stmt.query_map(params![session_id], |row| {
+            Ok(MessageRevision {
+                id: row.get(0)?,
+                session_id: row.get(1)?,
+                uuid: row.get(2)?,
+                old_hash: row.get(3)?,
+                new_hash: row.get(4)?,
+                new_raw: row.get(5)?,
+                applied: row.get::<_, i64>(6)? != 0,
+                detected_at: row.get(7)?,
+            })
+        })?;
+
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Into::into)
+    }
+
+    /// 获取 Session 的 Messages（不加载 raw，需要时用 `get_message_raw`）
     pub fn list_messages(
         &self,
         session_id: &str,
         limit: usize,
         offset: usize,
     ) -> Result<Vec<Message>> {
-        self.list_messages_ordered(session_id, limit, offset, false)
+        self.list_messages_ordered(session_id, limit, offset, Order::Asc, false)
     }
 
     /// 列出会话消息（支持排序）
-    /// - desc: true 表示倒序（最新的在前）
+    /// - order: `Order::Desc` 表示倒序（最新的在前）
+    /// - with_raw: 是否加载 raw 列（可能很大），false 时 `raw` 为 None
     pub fn list_messages_ordered(
         &self,
         session_id: &str,
         limit: usize,
         offset: usize,
-        desc: bool,
+        order: Order,
+        with_raw: bool,
+    ) -> Result<Vec<Message>> {
+        self.list_messages_inner(session_id, limit, offset, None, order, with_raw, false, &[])
+    }
+
+    /// 列出会话消息，只包含 `roles` 中的类型（如只列出 user 和 assistant，隐藏工具消息）
+    ///
+    /// `roles` 为空时不过滤；不加载 raw，排序同 `list_messages_ordered`
+    pub fn list_messages_filtered(
+        &self,
+        session_id: &str,
+        roles: &[MessageType],
+        limit: usize,
+        offset: usize,
+        order: Order,
+    ) -> Result<Vec<Message>> {
+        self.list_messages_inner(session_id, limit, offset, None, order, false, false, roles)
+    }
+
+    /// 列出会话消息，包含 sidechain 消息（其他列表方法默认不包含）
+    pub fn list_messages_including_sidechain(
+        &self,
+        session_id: &str,
+        limit: usize,
+        offset: usize,
+        order: Order,
+        with_raw: bool,
+    ) -> Result<Vec<Message>> {
+        self.list_messages_inner(session_id, limit, offset, None, order, with_raw, true, &[])
+    }
+
+    /// 列出会话消息（`after` 为上一页最后一行的 (sequence, id)，用于键集分页；
+    /// `roles` 非空时只包含这些类型）
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn list_messages_inner(
+        &self,
+        session_id: &str,
+        limit: usize,
+        offset: usize,
+        after: Option<(i64, i64)>,
+        order: Order,
+        with_raw: bool,
+        include_sidechain: bool,
+        roles: &[MessageType],
     ) -> Result<Vec<Message>> {
         let conn = self.conn.lock();
-        let order = if desc { "DESC" } else { "ASC" };
+        let (cmp, order) = (order.seek_cmp(), order.sql());
+        let raw_column = if with_raw { "raw" } else { "NULL" };
+        let roles: Vec<String> = roles.iter().map(|r| r.to_string()).collect();
+        let role_filter = if roles.is_empty() {
+            String::new()
+        } else {
+            let placeholders: String = (0..roles.len())
+                .map(|i| format!("?{}", i + 7))
+                .collect::<Vec<_>>()
+                .join(",");
+            format!("AND type IN ({})", placeholders)
+        };
         let sql = format!(
             r#"
             SELECT id, session_id, uuid, type, content_text, content_full, timestamp, sequence,
-                   source, channel, model, tool_call_id, tool_name, tool_args, raw, vector_indexed,
-                   approval_status, approval_resolved_at
+                   source, channel, model, tool_call_id, tool_name, tool_args, {}, vector_indexed,
+                   approval_status, approval_resolved_at, truncated, sidechain, is_summary
             FROM messages
             WHERE session_id = ?1
-            ORDER BY sequence {}
+              AND (?4 IS NULL OR sequence {cmp} ?4 OR (sequence = ?4 AND id {cmp} ?5))
+              AND (?6 OR sidechain = 0)
+              {}
+            ORDER BY sequence {}, id {}
             LIMIT ?2 OFFSET ?3
             "#,
-            order
+            raw_column,
+            role_filter,
+            order,
+            order,
+            cmp = cmp
         );
         let mut stmt = conn.prepare(&sql)?;
 
+        let (after_key, after_id) = after.unzip();
+        let (limit, offset) = (limit as i64, offset as i64);
+        let mut params_vec: Vec<&dyn rusqlite::ToSql> = vec![
+            &session_id,
+            &limit,
+            &offset,
+            &after_key,
+            &after_id,
+            &include_sidechain,
+        ];
+        params_vec.extend(roles.iter().map(|r| r as &dyn rusqlite::ToSql));
+        let rows = stmt.query_map(params_vec.as_slice(), |row| {
+            let type_str: String = row.get(3)?;
+            let vector_indexed: i64 = row.get(15)?;
+            Ok(Message {
+                id: row.get(0)?,
+                session_id: row.get(1)?,
+                uuid: row.get(2)?,
+                r#type: type_str.parse().unwrap_or(MessageType::User),
+                content_text: row.get(4)?,
+                content_full: row.get(5)?,
+                timestamp: row.get(6)?,
+                sequence: row.get(7)?,
+                source: row.get(8)?,
+                channel: row.get(9)?,
+                model: row.get(10)?,
+                tool_call_id: row.get(11)?,
+                tool_name: row.get(12)?,
+                tool_args: row.get(13)?,
+                raw: row.get(14)?,
+                vector_indexed: vector_indexed != 0,
+                approval_status: row
+                    .get::<_, Option<String>>(16)?
+                    .and_then(|s| s.parse().ok()),
+                approval_resolved_at: row.get(17)?,
+                truncated: row.get::<_, i64>(18)? != 0,
+                sidechain: row.get::<_, i64>(19)? != 0,
+                is_summary: row.get::<_, i64>(20)? != 0,
+            })
+        })?;
+
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Into::into)
+    }
+
+    // ==================== 对话轮次 ====================
+
+    /// 获取会话的对话轮次摘要（按 turn_index 升序，分页）
+    ///
+    /// 一个轮次为一条（或连续多条）用户消息及其后直到下一条用户消息之前的助手/工具消息。
+    pub fn list_turns(
+        &self,
+        session_id: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<TurnSummary>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT m.turn_index,
+                   (SELECT substr(u.content_text, 1, 100) FROM messages u
+                    WHERE u.session_id = m.session_id AND u.turn_index = m.turn_index
+                      AND u.type = 'user'
+                    ORDER BY u.sequence ASC LIMIT 1),
+                   (SELECT substr(a.content_text, 1, 100) FROM messages a
+                    WHERE a.session_id = m.session_id AND a.turn_index = m.turn_index
+                      AND a.type = 'assistant' AND a.content_text != ''
+                    ORDER BY a.sequence DESC LIMIT 1),
+                   SUM(CASE WHEN m.type = 'tool' THEN 1 ELSE 0 END),
+                   COUNT(*),
+                   MIN(m.timestamp),
+                   MAX(m.timestamp),
+                   SUM(CASE WHEN m.approval_status = 'pending' THEN 1 ELSE 0 END)
+            FROM messages m
+            WHERE m.session_id = ?1 AND m.turn_index IS NOT NULL
+            GROUP BY m.turn_index
+            ORDER BY m.turn_index ASC
+            LIMIT ?2 OFFSET ?3
+            "#,
+        )?;
+
         let rows = stmt.query_map(params![session_id, limit as i64, offset as i64], |row| {
+            Ok(TurnSummary {
+                turn_index: row.get(0)?,
+                user_preview: row.get(1)?,
+                assistant_preview: row.get(2)?,
+                tool_count: row.get(3)?,
+                message_count: row.get(4)?,
+                start_timestamp: row.get(5)?,
+                end_timestamp: row.get(6)?,
+                pending_approval_count: row.get(7)?,
+            })
+        })?;
+
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Into::into)
+    }
+
+    /// 获取某个对话轮次的全部消息（按 sequence 升序，不加载 raw）
+    pub fn get_turn_messages(&self, session_id: &str, turn_index: i64) -> Result<Vec<Message>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT id, session_id, uuid, type, content_text, content_full, timestamp, sequence,
+                   source, channel, model, tool_call_id, tool_name, tool_args, NULL, vector_indexed,
+                   approval_status, approval_resolved_at, truncated, sidechain, is_summary
+            FROM messages
+            WHERE session_id = ?1 AND turn_index = ?2
+            ORDER BY sequence ASC
+            "#,
+        )?;
+
+        let rows = stmt.query_map(params![session_id, turn_index], |row| {
             let type_str: String = row.get(3)?;
             let vector_indexed: i64 = row.get(15)?;
             Ok(Message {
@@ -1110,95 +2337,542 @@ impl SessionDB {
                     .get::<_, Option<String>>(16)?
                     .and_then(|s| s.parse().ok()),
                 approval_resolved_at: row.get(17)?,
+                truncated: row.get::<_, i64>(18)? != 0,
+                sidechain: row.get::<_, i64>(19)? != 0,
+                is_summary: row.get::<_, i64>(20)? != 0,
             })
         })?;
 
-        rows.collect::<std::result::Result<Vec<_>, _>>()
-            .map_err(Into::into)
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Into::into)
+    }
+
+    /// 为 turn_index 为空的老数据回填对话轮次
+    ///
+    /// 按会话逐个在独立事务中重算，返回更新的消息数。写入新消息时也会按需回填所在会话。
+    pub fn backfill_turn_indexes(&self) -> Result<usize> {
+        let session_ids: Vec<String> = {
+            let conn = self.conn.lock();
+            let mut stmt =
+                conn.prepare("SELECT DISTINCT session_id FROM messages WHERE turn_index IS NULL")?;
+            let rows = stmt.query_map([], |row| row.get(0))?;
+            rows.collect::<std::result::Result<Vec<_>, _>>()?
+        };
+
+        let mut updated = 0;
+        for session_id in &session_ids {
+            let mut conn = self.conn.lock();
+            let tx = conn.transaction()?;
+            let n = assign_session_turns(&tx, session_id)?;
+            if n > 0 {
+                bump_change_counter(&tx)?;
+            }
+            tx.commit()?;
+            updated += n;
+        }
+
+        if updated > 0 {
+            tracing::info!(
+                "Backfilled turn_index for {} messages in {} sessions",
+                updated,
+                session_ids.len()
+            );
+        }
+        Ok(updated)
+    }
+
+    /// 按 sequence 重算单个会话的对话轮次，返回更新的消息数
+    ///
+    /// 在已有消息之间补写消息（如 `Collector::refill_gaps`）后调用。
+    pub fn reassign_session_turns(&self, session_id: &str) -> Result<usize> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        let updated = assign_session_turns(&tx, session_id)?;
+        if updated > 0 {
+            bump_change_counter(&tx)?;
+        }
+        tx.commit()?;
+        Ok(updated)
+    }
+
+    /// 获取 Session 的所有 Messages (无分页，不加载 raw)
+    pub fn get_messages(&self, session_id: &str) -> Result<Vec<Message>> {
+        self.get_messages_with_options(session_id, None, Order::Asc, false)
+    }
+
+    /// 获取 Session 的 Messages (带分页和排序选项)
+    /// - limit: 返回数量限制，None 表示不限制
+    /// - order: `Order::Desc` 表示倒序（最新的在前）
+    /// - with_raw: 是否加载 raw 列（可能很大），false 时 `raw` 为 None
+    pub fn get_messages_with_options(
+        &self,
+        session_id: &str,
+        limit: Option<usize>,
+        order: Order,
+        with_raw: bool,
+    ) -> Result<Vec<Message>> {
+        let conn = self.conn.lock();
+        let order = order.sql();
+        let raw_column = if with_raw { "raw" } else { "NULL" };
+
+        let sql = format!(
+            r#"
+            SELECT id, session_id, uuid, type, content_text, content_full, timestamp, sequence,
+                   source, channel, model, tool_call_id, tool_name, tool_args, {}, vector_indexed,
+                   approval_status, approval_resolved_at, truncated, sidechain, is_summary
+            FROM messages
+            WHERE session_id = ?1
+            ORDER BY sequence {}
+            LIMIT ?2
+            "#,
+            raw_column, order
+        );
+
+        let limit_val = limit.unwrap_or(i64::MAX as usize) as i64;
+        let mut stmt = conn.prepare(&sql)?;
+
+        let rows = stmt.query_map(params![session_id, limit_val], |row| {
+            let type_str: String = row.get(3)?;
+            let vector_indexed: i64 = row.get(15)?;
+            Ok(Message {
+                id: row.get(0)?,
+                session_id: row.get(1)?,
+                uuid: row.get(2)?,
+                r#type: type_str.parse().unwrap_or(MessageType::User),
+                content_text: row.get(4)?,
+                content_full: row.get(5)?,
+                timestamp: row.get(6)?,
+                sequence: row.get(7)?,
+                source: row.get(8)?,
+                channel: row.get(9)?,
+                model: row.get(10)?,
+                tool_call_id: row.get(11)?,
+                tool_name: row.get(12)?,
+                tool_args: row.get(13)?,
+                raw: row.get(14)?,
+                vector_indexed: vector_indexed != 0,
+                approval_status: row
+                    .get::<_, Option<String>>(16)?
+                    .and_then(|s| s.parse().ok()),
+                approval_resolved_at: row.get(17)?,
+                truncated: row.get::<_, i64>(18)? != 0,
+                sidechain: row.get::<_, i64>(19)? != 0,
+                is_summary: row.get::<_, i64>(20)? != 0,
+            })
+        })?;
+
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Into::into)
+    }
+
+    /// 获取某条消息前后的消息窗口（按 sequence 正序，不含 raw）
+    ///
+    /// 返回 sequence 小于 `center_sequence` 的最近 `before` 条、center 本身及其后 `after` 条；
+    /// 靠近会话首尾时窗口相应缩短。
+    pub fn get_messages_around(
+        &self,
+        session_id: &str,
+        center_sequence: i64,
+        before: usize,
+        after: usize,
+    ) -> Result<Vec<Message>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT * FROM (
+                SELECT id, session_id, uuid, type, content_text, content_full, timestamp, sequence,
+                       source, channel, model, tool_call_id, tool_name, tool_args, NULL, vector_indexed,
+                       approval_status, approval_resolved_at, truncated, sidechain, is_summary
+                FROM messages
+                WHERE session_id = ?1 AND sequence < ?2
+                ORDER BY sequence DESC
+                LIMIT ?3
+            )
+            UNION ALL
+            SELECT * FROM (
+                SELECT id, session_id, uuid, type, content_text, content_full, timestamp, sequence,
+                       source, channel, model, tool_call_id, tool_name, tool_args, NULL, vector_indexed,
+                       approval_status, approval_resolved_at, truncated, sidechain, is_summary
+                FROM messages
+                WHERE session_id = ?1 AND sequence >= ?2
+                ORDER BY sequence ASC
+                LIMIT ?4
+            )
+            ORDER BY sequence ASC
+            "#,
+        )?;
+
+        let rows = stmt.query_map(
+            params![session_id, center_sequence, before as i64, after as i64 + 1],
+            |row| {
+                let type_str: String = row.get(3)?;
+                let vector_indexed: i64 = row.get(15)?;
+                Ok(Message {
+                    id: row.get(0)?,
+                    session_id: row.get(1)?,
+                    uuid: row.get(2)?,
+                    r#type: type_str.parse().unwrap_or(MessageType::User),
+                    content_text: row.get(4)?,
+                    content_full: row.get(5)?,
+                    timestamp: row.get(6)?,
+                    sequence: row.get(7)?,
+                    source: row.get(8)?,
+                    channel: row.get(9)?,
+                    model: row.get(10)?,
+                    tool_call_id: row.get(11)?,
+                    tool_name: row.get(12)?,
+                    tool_args: row.get(13)?,
+                    raw: row.get(14)?,
+                    vector_indexed: vector_indexed != 0,
+                    approval_status: row
+                        .get::<_, Option<String>>(16)?
+                        .and_then(|s| s.parse().ok()),
+                    approval_resolved_at: row.get(17)?,
+                    truncated: row.get::<_, i64>(18)? != 0,
+                    sidechain: row.get::<_, i64>(19)? != 0,
+                    is_summary: row.get::<_, i64>(20)? != 0,
+                })
+            },
+        )?;
+
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Into::into)
+    }
+
+    /// 按 uuid 获取消息的 raw（原始 JSONL）
+    ///
+    /// 列表查询默认不加载 raw，需要解析 contentBlocks 时单独获取。
+    pub fn get_message_raw(&self, uuid: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock();
+        conn.query_row(
+            "SELECT raw FROM messages WHERE uuid = ?1",
+            params![uuid],
+            |row| row.get::<_, Option<String>>(0),
+        )
+        .optional()
+        .map(Option::flatten)
+        .map_err(Into::into)
+    }
+
+    // ==================== 统计 ====================
+
+    /// 获取统计信息
+    pub fn get_stats(&self) -> Result<Stats> {
+        let conn = self.conn.lock();
+
+        let project_count: i64 =
+            conn.query_row("SELECT COUNT(*) FROM projects", [], |row| row.get(0))?;
+        let session_count: i64 =
+            conn.query_row("SELECT COUNT(*) FROM sessions", [], |row| row.get(0))?;
+        let message_count: i64 =
+            conn.query_row("SELECT COUNT(*) FROM messages", [], |row| row.get(0))?;
+
+        Ok(Stats {
+            project_count,
+            session_count,
+            message_count,
+        })
+    }
+
+    /// 历史数据概览：总量、最早 / 最新消息时间、按数据源和年份的分布、消息数最多的项目
+    ///
+    /// 所有聚合在同一个读事务中执行，数字之间一致（采集进行中也不会出现总数与分布对不上）。
+    pub fn history_overview(&self) -> Result<HistoryOverview> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        let totals = tx.query_row(
+            r#"
+            SELECT
+                (SELECT COUNT(*) FROM projects),
+                (SELECT COUNT(*) FROM sessions),
+                COUNT(*),
+                MIN(timestamp),
+                MAX(timestamp)
+            FROM messages
+            "#,
+            [],
+            |row| {
+                Ok(HistoryTotals {
+                    project_count: row.get(0)?,
+                    session_count: row.get(1)?,
+                    message_count: row.get(2)?,
+                    oldest_message_at: row.get(3)?,
+                    newest_message_at: row.get(4)?,
+                })
+            },
+        )?;
+
+        let by_source = tx
+            .prepare(
+                r#"
+                SELECT
+                    COALESCE(p.source, 'claude') as source,
+                    COUNT(DISTINCT p.id),
+                    COUNT(DISTINCT s.id),
+                    COUNT(m.id) as message_count
+                FROM projects p
+                LEFT JOIN sessions s ON s.project_id = p.id
+                LEFT JOIN messages m ON m.session_id = s.session_id
+                GROUP BY source
+                ORDER BY message_count DESC, source
+                "#,
+            )?
+            .query_map([], |row| {
+                Ok(SourceHistory {
+                    source: row.get(0)?,
+                    project_count: row.get(1)?,
+                    session_count: row.get(2)?,
+                    message_count: row.get(3)?,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let by_year = tx
+            .prepare(
+                r#"
+                SELECT
+                    CAST(strftime('%Y', timestamp / 1000, 'unixepoch') AS INTEGER) as year,
+                    COUNT(DISTINCT session_id),
+                    COUNT(*)
+                FROM messages
+                GROUP BY year
+                ORDER BY year
+                "#,
+            )?
+            .query_map([], |row| {
+                Ok(YearHistory {
+                    year: row.get(0)?,
+                    session_count: row.get(1)?,
+                    message_count: row.get(2)?,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let top_projects = tx
+            .prepare(
+                r#"
+                SELECT
+                    p.id,
+                    p.name,
+                    p.path,
+                    COUNT(DISTINCT s.id),
+                    COUNT(m.id) as message_count,
+                    MAX(COALESCE(s.last_message_at, s.updated_at))
+                FROM projects p
+                JOIN sessions s ON s.project_id = p.id
+                JOIN messages m ON m.session_id = s.session_id
+                WHERE p.ignored = 0
+                GROUP BY p.id
+                ORDER BY message_count DESC, p.id
+                LIMIT ?1
+                "#,
+            )?
+            .query_map(params![HISTORY_TOP_PROJECTS as i64], |row| {
+                Ok(ProjectWithStats {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    path: row.get(2)?,
+                    session_count: row.get(3)?,
+                    message_count: row.get(4)?,
+                    last_active: row.get(5)?,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        tx.commit()?;
+        Ok(HistoryOverview {
+            totals,
+            by_source,
+            by_year,
+            top_projects,
+        })
+    }
+
+    // ==================== 采集锁 ====================
+
+    /// 获取采集锁（跨进程互斥，与 Writer 角色无关）
+    ///
+    /// 锁空闲、已由 `holder` 持有、或持有者心跳超过 `stale_after_ms` 时获取成功（接管），
+    /// 否则返回 `Error::CollectionInProgress`。
+    pub fn acquire_collection_lock(&self, holder: &str, stale_after_ms: i64) -> Result<()> {
+        let conn = self.conn.lock();
+        let now = current_time_ms();
+        let acquired = conn.execute(
+            r#"
+            INSERT INTO collection_lock (id, holder, acquired_at, heartbeat_at)
+            VALUES (1, ?1, ?2, ?2)
+            ON CONFLICT(id) DO UPDATE SET
+                holder = excluded.holder,
+                acquired_at = excluded.acquired_at,
+                heartbeat_at = excluded.heartbeat_at
+            WHERE collection_lock.holder = excluded.holder
+               OR collection_lock.heartbeat_at < ?3
+            "#,
+            params![holder, now, now - stale_after_ms],
+        )?;
+        if acquired > 0 {
+            return Ok(());
+        }
+
+        let current: Option<String> = conn
+            .query_row(
+                "SELECT holder FROM collection_lock WHERE id = 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Err(Error::CollectionInProgress {
+            holder: current.unwrap_or_default(),
+        })
+    }
+
+    /// 续期采集锁，返回是否仍由 `holder` 持有
+    pub fn heartbeat_collection_lock(&self, holder: &str) -> Result<bool> {
+        let conn = self.conn.lock();
+        let updated = conn.execute(
+            "UPDATE collection_lock SET heartbeat_at = ?2 WHERE id = 1 AND holder = ?1",
+            params![holder, current_time_ms()],
+        )?;
+        Ok(updated > 0)
     }
 
-    /// 获取 Session 的所有 Messages (无分页)
-    pub fn get_messages(&self, session_id: &str) -> Result<Vec<Message>> {
-        self.get_messages_with_options(session_id, None, false)
+    /// 释放采集锁（只释放 `holder` 自己持有的锁）
+    pub fn release_collection_lock(&self, holder: &str) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.execute(
+            "DELETE FROM collection_lock WHERE id = 1 AND holder = ?1",
+            params![holder],
+        )?;
+        Ok(())
     }
 
-    /// 获取 Session 的 Messages (带分页和排序选项)
-    /// - limit: 返回数量限制，None 表示不限制
-    /// - desc: true 表示倒序（最新的在前）
-    pub fn get_messages_with_options(
-        &self,
-        session_id: &str,
-        limit: Option<usize>,
-        desc: bool,
-    ) -> Result<Vec<Message>> {
+    /// 获取采集锁状态（未被持有时为 None）
+    pub fn get_collection_lock(&self) -> Result<Option<CollectionLock>> {
         let conn = self.conn.lock();
-        let order = if desc { "DESC" } else { "ASC" };
+        conn.query_row(
+            "SELECT holder, acquired_at, heartbeat_at FROM collection_lock WHERE id = 1",
+            [],
+            |row| {
+                Ok(CollectionLock {
+                    holder: row.get(0)?,
+                    acquired_at: row.get(1)?,
+                    heartbeat_at: row.get(2)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(Into::into)
+    }
 
-        let sql = format!(
+    // ==================== 采集失败 ====================
+
+    /// 记录会话采集失败，返回连续失败次数
+    pub fn record_collect_failure(&self, session_id: &str, path: &str, error: &str) -> Result<i64> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        tx.execute(
             r#"
-            SELECT id, session_id, uuid, type, content_text, content_full, timestamp, sequence,
-                   source, channel, model, tool_call_id, tool_name, tool_args, raw, vector_indexed,
-                   approval_status, approval_resolved_at
-            FROM messages
-            WHERE session_id = ?1
-            ORDER BY sequence {}
-            LIMIT ?2
+            INSERT INTO collect_failures (session_id, path, failures, last_failure_at, last_error)
+            VALUES (?1, ?2, 1, ?3, ?4)
+            ON CONFLICT(session_id) DO UPDATE SET
+                path = excluded.path,
+                failures = collect_failures.failures + 1,
+                last_failure_at = excluded.last_failure_at,
+                last_error = excluded.last_error
             "#,
-            order
-        );
+            params![session_id, path, current_time_ms(), error],
+        )?;
+        let failures = tx.query_row(
+            "SELECT failures FROM collect_failures WHERE session_id = ?1",
+            params![session_id],
+            |row| row.get(0),
+        )?;
+        tx.commit()?;
+        Ok(failures)
+    }
 
-        let limit_val = limit.unwrap_or(i64::MAX as usize) as i64;
-        let mut stmt = conn.prepare(&sql)?;
+    /// 获取会话的采集失败记录
+    pub fn get_collect_failure(&self, session_id: &str) -> Result<Option<CollectFailure>> {
+        Ok(self
+            .query_collect_failures("WHERE session_id = ?1", params![session_id])?
+            .pop())
+    }
 
-        let rows = stmt.query_map(params![session_id, limit_val], |row| {
-            let type_str: String = row.get(3)?;
-            let vector_indexed: i64 = row.get(15)?;
-            Ok(Message {
-                id: row.get(0)?,
-                session_id: row.get(1)?,
-                uuid: row.get(2)?,
-                r#type: type_str.parse().unwrap_or(MessageType::User),
-                content_text: row.get(4)?,
-                content_full: row.get(5)?,
-                timestamp: row.get(6)?,
-                sequence: row.get(7)?,
-                source: row.get(8)?,
-                channel: row.get(9)?,
-                model: row.get(10)?,
-                tool_call_id: row.get(11)?,
-                tool_name: row.get(12)?,
-                tool_args: row.get(13)?,
-                raw: row.get(14)?,
-                vector_indexed: vector_indexed != 0,
-                approval_status: row
-                    .get::<_, Option<String>>(16)?
-                    .and_then(|s| s.parse().ok()),
-                approval_resolved_at: row.get(17)?,
+    /// 列出所有采集失败的会话（最近失败的在前）
+    pub fn list_collect_failures(&self) -> Result<Vec<CollectFailure>> {
+        self.query_collect_failures("ORDER BY last_failure_at DESC, session_id", [])
+    }
+
+    fn query_collect_failures(
+        &self,
+        clause: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<CollectFailure>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT session_id, path, failures, last_failure_at, last_error
+             FROM collect_failures {}",
+            clause
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            Ok(CollectFailure {
+                session_id: row.get(0)?,
+                path: row.get(1)?,
+                failures: row.get(2)?,
+                last_failure_at: row.get(3)?,
+                last_error: row.get(4)?,
             })
         })?;
-
         rows.collect::<std::result::Result<Vec<_>, _>>()
             .map_err(Into::into)
     }
 
-    // ==================== 统计 ====================
+    /// 清除采集失败记录（`session_id` 为 None 时清除全部），下次采集立即重试，返回清除的会话数
+    pub fn reset_collect_failures(&self, session_id: Option<&str>) -> Result<usize> {
+        let conn = self.conn.lock();
+        let removed = match session_id {
+            Some(id) => conn.execute(
+                "DELETE FROM collect_failures WHERE session_id = ?1",
+                params![id],
+            )?,
+            None => conn.execute("DELETE FROM collect_failures", [])?,
+        };
+        Ok(removed)
+    }
 
-    /// 获取统计信息
-    pub fn get_stats(&self) -> Result<Stats> {
+    /// 数据库文件的 schema 版本（`PRAGMA user_version`）
+    ///
+    /// 大于 `migrations::SUPPORTED_SCHEMA_VERSION` 时说明数据库由更新版本的库写入。
+    pub fn schema_version(&self) -> Result<i32> {
         let conn = self.conn.lock();
+        migrations::user_version(&conn).map_err(Into::into)
+    }
 
-        let project_count: i64 =
-            conn.query_row("SELECT COUNT(*) FROM projects", [], |row| row.get(0))?;
-        let session_count: i64 =
-            conn.query_row("SELECT COUNT(*) FROM sessions", [], |row| row.get(0))?;
-        let message_count: i64 =
-            conn.query_row("SELECT COUNT(*) FROM messages", [], |row| row.get(0))?;
+    /// 数据库与当前代码的版本兼容性
+    ///
+    /// 更新版本的库迁移后会提高最低写入方版本（`migrations::MIN_WRITER_SCHEMA_VERSION`），
+    /// `writable()` 为 false 时当前代码写入的行可能缺少新版本读取方依赖的字段，不应写入。
+    /// Agent 启动时检查，不可写入时只提供读取（见 `Push::AgentDegraded`）。
+    pub fn compatibility_check(&self) -> Result<migrations::SchemaCompatibility> {
+        let conn = self.conn.lock();
+        migrations::compatibility(&conn).map_err(Into::into)
+    }
 
-        Ok(Stats {
-            project_count,
-            session_count,
-            message_count,
+    // ==================== 变更计数 ====================
+
+    /// 全局变更计数
+    ///
+    /// 每个提交了数据变更的写事务（消息写入、审批更新、会话/项目/Talk 变更）递增一次，
+    /// 只读操作和回滚的事务不变。客户端保存上次的值，比较即可判断是否有新数据。
+    pub fn change_counter(&self) -> Result<u64> {
+        let conn = self.conn.lock();
+        conn.query_row("SELECT value FROM change_counter WHERE id = 1", [], |row| {
+            row.get::<_, i64>(0)
         })
+        .optional()
+        .map(|value| value.unwrap_or(0) as u64)
+        .map_err(Into::into)
     }
 
     // ==================== 向量索引 ====================
@@ -1211,7 +2885,7 @@ impl SessionDB {
             r#"
             SELECT id, session_id, uuid, type, content_text, content_full, timestamp, sequence,
                    source, channel, model, tool_call_id, tool_name, tool_args, raw, vector_indexed,
-                   approval_status, approval_resolved_at
+                   approval_status, approval_resolved_at, truncated, sidechain, is_summary
             FROM messages
             WHERE vector_indexed = 0 AND type = 'assistant'
             ORDER BY id ASC
@@ -1243,6 +2917,9 @@ impl SessionDB {
                     .get::<_, Option<String>>(16)?
                     .and_then(|s| s.parse().ok()),
                 approval_resolved_at: row.get(17)?,
+                truncated: row.get::<_, i64>(18)? != 0,
+                sidechain: row.get::<_, i64>(19)? != 0,
+                is_summary: row.get::<_, i64>(20)? != 0,
             })
         })?;
 
@@ -1312,6 +2989,11 @@ impl SessionDB {
 
     /// 批量标记消息向量索引失败
     pub fn mark_messages_index_failed(&self, message_ids: &[i64]) -> Result<usize> {
+        self.set_index_state(message_ids, IndexState::Failed)
+    }
+
+    /// 批量设置消息的向量索引状态，返回更新的行数（不存在的 ID 忽略）
+    pub fn set_index_state(&self, message_ids: &[i64], state: IndexState) -> Result<usize> {
         if message_ids.is_empty() {
             return Ok(0);
         }
@@ -1323,7 +3005,8 @@ impl SessionDB {
             .collect::<Vec<_>>()
             .join(",");
         let sql = format!(
-            "UPDATE messages SET vector_indexed = -1 WHERE id IN ({})",
+            "UPDATE messages SET vector_indexed = {} WHERE id IN ({})",
+            state.as_db_value(),
             placeholders
         );
 
@@ -1345,7 +3028,7 @@ impl SessionDB {
             r#"
             SELECT id, session_id, uuid, type, content_text, content_full, timestamp, sequence,
                    source, channel, model, tool_call_id, tool_name, tool_args, raw, vector_indexed,
-                   approval_status, approval_resolved_at
+                   approval_status, approval_resolved_at, truncated, sidechain, is_summary
             FROM messages
             WHERE vector_indexed = -1
             ORDER BY id ASC
@@ -1377,6 +3060,9 @@ impl SessionDB {
                     .get::<_, Option<String>>(16)?
                     .and_then(|s| s.parse().ok()),
                 approval_resolved_at: row.get(17)?,
+                truncated: row.get::<_, i64>(18)? != 0,
+                sidechain: row.get::<_, i64>(19)? != 0,
+                is_summary: row.get::<_, i64>(20)? != 0,
             })
         })?;
 
@@ -1405,6 +3091,56 @@ impl SessionDB {
         Ok(count)
     }
 
+    /// 统计待清理的向量墓碑数量
+    pub fn count_pending_vector_tombstones(&self) -> Result<i64> {
+        let conn = self.conn.lock();
+        conn.query_row("SELECT COUNT(*) FROM vector_tombstones", [], |row| {
+            row.get(0)
+        })
+        .map_err(Into::into)
+    }
+
+    /// 读取待清理的向量墓碑（按删除顺序）
+    ///
+    /// 不会移除记录：向量库删除完成后调用 `ack_vector_tombstones` 确认。
+    pub fn drain_vector_tombstones(&self, limit: usize) -> Result<Vec<VectorTombstone>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(
+            "SELECT id, uuid, message_id, deleted_at FROM vector_tombstones ORDER BY id ASC LIMIT ?1",
+        )?;
+
+        let rows = stmt.query_map(params![limit as i64], |row| {
+            Ok(VectorTombstone {
+                id: row.get(0)?,
+                uuid: row.get(1)?,
+                message_id: row.get(2)?,
+                deleted_at: row.get(3)?,
+            })
+        })?;
+
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Into::into)
+    }
+
+    /// 确认向量墓碑已处理（删除记录），返回删除数量
+    pub fn ack_vector_tombstones(&self, ids: &[i64]) -> Result<usize> {
+        if ids.is_empty() {
+            return Ok(0);
+        }
+
+        let conn = self.conn.lock();
+        let placeholders = ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+        let sql = format!(
+            "DELETE FROM vector_tombstones WHERE id IN ({})",
+            placeholders
+        );
+        let params: Vec<&dyn rusqlite::ToSql> =
+            ids.iter().map(|id| id as &dyn rusqlite::ToSql).collect();
+
+        let count = conn.execute(&sql, params.as_slice())?;
+        Ok(count)
+    }
+
     /// 按 ID 列表获取消息
     pub fn get_messages_by_ids(&self, ids: &[i64]) -> Result<Vec<Message>> {
         if ids.is_empty() {
@@ -1417,7 +3153,7 @@ impl SessionDB {
             r#"
             SELECT id, session_id, uuid, type, content_text, content_full, timestamp, sequence,
                    source, channel, model, tool_call_id, tool_name, tool_args, raw, vector_indexed,
-                   approval_status, approval_resolved_at
+                   approval_status, approval_resolved_at, truncated, sidechain, is_summary
             FROM messages
             WHERE id IN ({})
             ORDER BY id ASC
@@ -1453,6 +3189,9 @@ impl SessionDB {
                     .get::<_, Option<String>>(16)?
                     .and_then(|s| s.parse().ok()),
                 approval_resolved_at: row.get(17)?,
+                truncated: row.get::<_, i64>(18)? != 0,
+                sidechain: row.get::<_, i64>(19)? != 0,
+                is_summary: row.get::<_, i64>(20)? != 0,
             })
         })?;
 
@@ -1468,59 +3207,133 @@ impl SessionDB {
     /// - talk_id: Talk 唯一标识
     /// - summary_l2: L2 摘要（每个 Talk 的摘要）
     /// - summary_l3: L3 摘要（Session 级别汇总，可选）
+    /// - position: Talk 在会话中的序号（可选，显式指定时优先）
+    /// - first_message_sequence: Talk 覆盖的第一条消息 sequence（可选）
+    ///
+    /// 未指定 position 时：提供了 first_message_sequence 的 Talk 按其在会话中的顺序重新编号
+    /// （摘要乱序写入也能得到正确顺序）；都未提供的新 Talk 追加到末尾。
     pub fn upsert_talk_summary(
         &self,
         session_id: &str,
         talk_id: &str,
         summary_l2: &str,
         summary_l3: Option<&str>,
+        position: Option<i64>,
+        first_message_sequence: Option<i64>,
     ) -> Result<()> {
-        let conn = self.conn.lock();
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
         let now = current_time_ms();
 
-        conn.execute(
+        tx.execute(
             r#"
-            INSERT INTO talks (session_id, talk_id, summary_l2, summary_l3, created_at, updated_at)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+            INSERT INTO talks (session_id, talk_id, summary_l2, summary_l3, position,
+                               first_message_sequence, created_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4,
+                    COALESCE(?5, (SELECT COALESCE(MAX(position) + 1, 0) FROM talks WHERE session_id = ?1)),
+                    ?6, ?7, ?7)
             ON CONFLICT(session_id, talk_id) DO UPDATE SET
                 summary_l2 = excluded.summary_l2,
                 summary_l3 = COALESCE(excluded.summary_l3, talks.summary_l3),
+                position = COALESCE(?5, talks.position),
+                first_message_sequence = COALESCE(excluded.first_message_sequence, talks.first_message_sequence),
                 updated_at = excluded.updated_at
             "#,
-            params![session_id, talk_id, summary_l2, summary_l3, now],
+            params![
+                session_id,
+                talk_id,
+                summary_l2,
+                summary_l3,
+                position,
+                first_message_sequence,
+                now
+            ],
         )?;
 
+        // 按 first_message_sequence 重新编号
+        if position.is_none() && first_message_sequence.is_some() {
+            tx.execute(
+                r#"
+                UPDATE talks SET position = (
+                    SELECT COUNT(*) FROM talks AS t2
+                    WHERE t2.session_id = talks.session_id
+                      AND t2.first_message_sequence IS NOT NULL
+                      AND (t2.first_message_sequence, t2.created_at, t2.id)
+                          < (talks.first_message_sequence, talks.created_at, talks.id)
+                )
+                WHERE session_id = ?1 AND first_message_sequence IS NOT NULL
+                "#,
+                params![session_id],
+            )?;
+        }
+
+        bump_change_counter(&tx)?;
+        tx.commit()?;
         Ok(())
     }
 
-    /// 获取 Session 的所有 Talk 摘要
-    pub fn get_talk_summaries(&self, session_id: &str) -> Result<Vec<TalkSummary>> {
+    /// 获取 Session 的 Talk 摘要（分页）
+    ///
+    /// 按 position、created_at 排序；limit 最大为 `MAX_TALK_SUMMARIES_LIMIT`。
+    pub fn get_talk_summaries(
+        &self,
+        session_id: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<TalkSummary>> {
+        let limit = limit.min(MAX_TALK_SUMMARIES_LIMIT);
         let conn = self.conn.lock();
         let mut stmt = conn.prepare(
             r#"
-            SELECT id, session_id, talk_id, summary_l2, summary_l3, created_at, updated_at
+            SELECT id, session_id, talk_id, summary_l2, summary_l3, position,
+                   first_message_sequence, created_at, updated_at
             FROM talks
             WHERE session_id = ?1
-            ORDER BY created_at ASC
+            ORDER BY (position IS NULL), position ASC, created_at ASC
+            LIMIT ?2 OFFSET ?3
             "#,
         )?;
 
-        let rows = stmt.query_map(params![session_id], |row| {
-            Ok(TalkSummary {
-                id: row.get(0)?,
-                session_id: row.get(1)?,
-                talk_id: row.get(2)?,
-                summary_l2: row.get(3)?,
-                summary_l3: row.get(4)?,
-                created_at: row.get(5)?,
-                updated_at: row.get(6)?,
-            })
-        })?;
+        let rows = stmt.query_map(
+            params![session_id, limit as i64, offset as i64],
+            Self::row_to_talk_summary,
+        )?;
 
         rows.collect::<std::result::Result<Vec<_>, _>>()
             .map_err(Into::into)
     }
 
+    /// 获取单个 Talk 摘要
+    pub fn get_talk_summary(&self, session_id: &str, talk_id: &str) -> Result<Option<TalkSummary>> {
+        let conn = self.conn.lock();
+        conn.query_row(
+            r#"
+            SELECT id, session_id, talk_id, summary_l2, summary_l3, position,
+                   first_message_sequence, created_at, updated_at
+            FROM talks
+            WHERE session_id = ?1 AND talk_id = ?2
+            "#,
+            params![session_id, talk_id],
+            Self::row_to_talk_summary,
+        )
+        .optional()
+        .map_err(Into::into)
+    }
+
+    fn row_to_talk_summary(row: &rusqlite::Row) -> rusqlite::Result<TalkSummary> {
+        Ok(TalkSummary {
+            id: row.get(0)?,
+            session_id: row.get(1)?,
+            talk_id: row.get(2)?,
+            summary_l2: row.get(3)?,
+            summary_l3: row.get(4)?,
+            position: row.get(5)?,
+            first_message_sequence: row.get(6)?,
+            created_at: row.get(7)?,
+            updated_at: row.get(8)?,
+        })
+    }
+
     // ==================== 审批操作 ====================
 
     /// 获取待审批的消息
@@ -1534,7 +3347,7 @@ impl SessionDB {
             r#"
             SELECT id, session_id, uuid, type, content_text, content_full, timestamp, sequence,
                    source, channel, model, tool_call_id, tool_name, tool_args, raw, vector_indexed,
-                   approval_status, approval_resolved_at
+                   approval_status, approval_resolved_at, truncated, sidechain, is_summary
             FROM messages
             WHERE session_id = ?1 AND approval_status = 'pending'
             ORDER BY sequence ASC
@@ -1565,6 +3378,9 @@ impl SessionDB {
                     .get::<_, Option<String>>(16)?
                     .and_then(|s| s.parse().ok()),
                 approval_resolved_at: row.get(17)?,
+                truncated: row.get::<_, i64>(18)? != 0,
+                sidechain: row.get::<_, i64>(19)? != 0,
+                is_summary: row.get::<_, i64>(20)? != 0,
             })
         })?;
 
@@ -1582,8 +3398,14 @@ impl SessionDB {
         status: crate::types::ApprovalStatus,
         resolved_at: i64,
     ) -> Result<usize> {
-        let conn = self.conn.lock();
-        let count = conn.execute(
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        let session_ids = distinct_session_ids(
+            &tx,
+            "SELECT DISTINCT session_id FROM messages WHERE uuid = ?1",
+            params![uuid],
+        )?;
+        let count = tx.execute(
             r#"
             UPDATE messages
             SET approval_status = ?1, approval_resolved_at = ?2
@@ -1591,6 +3413,13 @@ impl SessionDB {
             "#,
             params![status.to_string(), resolved_at, uuid],
         )?;
+        if count > 0 {
+            bump_change_counter(&tx)?;
+        }
+        tx.commit()?;
+        drop(conn);
+
+        self.notify_approval(session_ids, status, count);
         Ok(count)
     }
 
@@ -1607,8 +3436,14 @@ impl SessionDB {
         status: crate::types::ApprovalStatus,
         resolved_at: i64,
     ) -> Result<usize> {
-        let conn = self.conn.lock();
-        let count = conn.execute(
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        let session_ids = distinct_session_ids(
+            &tx,
+            "SELECT DISTINCT session_id FROM messages WHERE tool_call_id = ?1",
+            params![tool_call_id],
+        )?;
+        let count = tx.execute(
             r#"
             UPDATE messages
             SET approval_status = ?1, approval_resolved_at = ?2
@@ -1616,6 +3451,13 @@ impl SessionDB {
             "#,
             params![status.to_string(), resolved_at, tool_call_id],
         )?;
+        if count > 0 {
+            bump_change_counter(&tx)?;
+        }
+        tx.commit()?;
+        drop(conn);
+
+        self.notify_approval(session_ids, status, count);
         Ok(count)
     }
 
@@ -1633,7 +3475,7 @@ impl SessionDB {
             return Ok(0);
         }
 
-        let conn = self.conn.lock();
+        let mut conn = self.conn.lock();
         let placeholders: String = uuids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
         let sql = format!(
             r#"
@@ -1656,29 +3498,170 @@ impl SessionDB {
             .map(|p| p.as_ref() as &dyn rusqlite::ToSql)
             .collect();
 
-        let mut stmt = conn.prepare(&sql)?;
-        let count = stmt.execute(params_refs.as_slice())?;
+        let tx = conn.transaction()?;
+        let session_ids = distinct_session_ids(
+            &tx,
+            &format!(
+                "SELECT DISTINCT session_id FROM messages WHERE uuid IN ({})",
+                placeholders
+            ),
+            rusqlite::params_from_iter(uuids),
+        )?;
+        let count = tx.execute(&sql, params_refs.as_slice())?;
+        if count > 0 {
+            bump_change_counter(&tx)?;
+        }
+        tx.commit()?;
+        drop(conn);
+
+        self.notify_approval(session_ids, status, count);
         Ok(count)
     }
 
+    /// 审批状态更新后通知订阅者（没有更新时不通知）
+    fn notify_approval(
+        &self,
+        session_ids: Vec<String>,
+        status: crate::types::ApprovalStatus,
+        count: usize,
+    ) {
+        if count > 0 {
+            self.notify(vec![ChangeEvent::ApprovalChanged {
+                session_ids,
+                status,
+                count,
+            }]);
+        }
+    }
+
     /// 统计待审批的消息数量
     /// - session_id: 可选的会话 ID，如果提供则只统计该会话的待审批消息
     pub fn count_pending_approvals(&self, session_id: Option<&str>) -> Result<i64> {
         let conn = self.conn.lock();
-        let count = if let Some(sid) = session_id {
-            conn.query_row(
-                "SELECT COUNT(*) FROM messages WHERE approval_status = 'pending' AND session_id = ?1",
-                params![sid],
-                |row| row.get(0),
-            )?
-        } else {
-            conn.query_row(
-                "SELECT COUNT(*) FROM messages WHERE approval_status = 'pending'",
-                [],
-                |row| row.get(0),
-            )?
-        };
-        Ok(count)
+        let count = if let Some(sid) = session_id {
+            conn.query_row(
+                "SELECT COUNT(*) FROM messages WHERE approval_status = 'pending' AND session_id = ?1",
+                params![sid],
+                |row| row.get(0),
+            )?
+        } else {
+            conn.query_row(
+                "SELECT COUNT(*) FROM messages WHERE approval_status = 'pending'",
+                [],
+                |row| row.get(0),
+            )?
+        };
+        Ok(count)
+    }
+
+    /// 列出所有会话中待审批的 tool_call_id（按消息写入顺序）
+    ///
+    /// 返回 (tool_call_id, session_id)，没有 tool_call_id 的消息不包含。
+    /// 用于重启后与审批写入方对账。
+    pub fn list_pending_tool_call_ids(&self, limit: usize) -> Result<Vec<(String, String)>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT tool_call_id, session_id
+            FROM messages
+            WHERE approval_status = 'pending' AND tool_call_id IS NOT NULL
+            ORDER BY id
+            LIMIT ?1
+            "#,
+        )?;
+        let rows = stmt.query_map(params![limit as i64], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Into::into)
+    }
+
+    /// 按审批状态统计消息数量
+    /// - session_id: 可选的会话 ID，如果提供则只统计该会话
+    ///
+    /// 没有该状态的消息时不包含对应的键；无法识别的状态值忽略。
+    pub fn approvals_summary(
+        &self,
+        session_id: Option<&str>,
+    ) -> Result<HashMap<crate::types::ApprovalStatus, i64>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT approval_status, COUNT(*)
+            FROM messages
+            WHERE approval_status IS NOT NULL AND (?1 IS NULL OR session_id = ?1)
+            GROUP BY approval_status
+            "#,
+        )?;
+        let rows = stmt.query_map(params![session_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?;
+
+        let mut summary = HashMap::new();
+        for row in rows {
+            let (status, count) = row?;
+            match status.parse::<crate::types::ApprovalStatus>() {
+                Ok(status) => *summary.entry(status).or_insert(0) += count,
+                Err(e) => tracing::warn!("Skipping approvals with {}", e),
+            }
+        }
+        Ok(summary)
+    }
+
+    /// 按项目汇总待审批的消息（用于合并通知，如 "project-x 有 3 个待审批"）
+    /// - older_than_ms: 可选，只统计已等待超过该时长（毫秒，按消息时间计算）的消息
+    ///
+    /// 按最早的待审批时间升序返回，没有待审批消息的项目不包含。
+    pub fn pending_approval_digest(
+        &self,
+        older_than_ms: Option<i64>,
+    ) -> Result<Vec<ApprovalDigestGroup>> {
+        let cutoff = older_than_ms.map(|age| current_time_ms() - age);
+        let conn = self.conn.lock();
+        // 按 (项目, tool_name) 分组，一次查询得到工具分布，项目级汇总在内存中合并
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT s.project_id, p.name, COALESCE(m.tool_name, ''),
+                   COUNT(*), MIN(m.timestamp), MAX(m.timestamp)
+            FROM messages m
+            JOIN sessions s ON s.session_id = m.session_id
+            JOIN projects p ON p.id = s.project_id
+            WHERE m.approval_status = 'pending' AND (?1 IS NULL OR m.timestamp <= ?1)
+            GROUP BY s.project_id, p.name, COALESCE(m.tool_name, '')
+            "#,
+        )?;
+        let rows = stmt.query_map(params![cutoff], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, i64>(5)?,
+            ))
+        })?;
+
+        let mut groups: Vec<ApprovalDigestGroup> = Vec::new();
+        for row in rows {
+            let (project_id, project_name, tool_name, count, oldest_at, newest_at) = row?;
+            match groups.iter_mut().find(|g| g.project_id == project_id) {
+                Some(group) => {
+                    group.count += count;
+                    group.oldest_at = group.oldest_at.min(oldest_at);
+                    group.newest_at = group.newest_at.max(newest_at);
+                    group.tools.insert(tool_name, count);
+                }
+                None => groups.push(ApprovalDigestGroup {
+                    project_id,
+                    project_name,
+                    count,
+                    oldest_at,
+                    newest_at,
+                    tools: [(tool_name, count)].into_iter().collect(),
+                }),
+            }
+        }
+        groups.sort_by_key(|g| (g.oldest_at, g.project_id));
+        Ok(groups)
     }
 
     // ==================== 管理操作 ====================
@@ -1718,21 +3701,59 @@ impl SessionDB {
         from_project_id: i64,
         to_project_id: i64,
     ) -> Result<usize> {
-        let conn = self.conn.lock();
-        let count = conn.execute(
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        let count = tx.execute(
             "UPDATE sessions SET project_id = ?1 WHERE project_id = ?2",
             params![to_project_id, from_project_id],
         )?;
+        if count > 0 {
+            bump_change_counter(&tx)?;
+        }
+        tx.commit()?;
         Ok(count)
     }
 
     /// 删除项目
     pub fn delete_project(&self, project_id: i64) -> Result<()> {
-        let conn = self.conn.lock();
-        conn.execute("DELETE FROM projects WHERE id = ?1", params![project_id])?;
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        if tx.execute("DELETE FROM projects WHERE id = ?1", params![project_id])? > 0 {
+            bump_change_counter(&tx)?;
+        }
+        tx.commit()?;
         Ok(())
     }
 
+    /// 删除会话及其消息、修订记录和 Talk 摘要，返回删除的消息数
+    ///
+    /// 已向量索引的消息由触发器在同一事务内写入 vector_tombstones。
+    pub fn delete_session(&self, session_id: &str) -> Result<usize> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        let deleted = tx.execute(
+            "DELETE FROM messages WHERE session_id = ?1",
+            params![session_id],
+        )?;
+        tx.execute(
+            "DELETE FROM message_revisions WHERE session_id = ?1",
+            params![session_id],
+        )?;
+        tx.execute(
+            "DELETE FROM talks WHERE session_id = ?1",
+            params![session_id],
+        )?;
+        tx.execute(
+            "DELETE FROM sessions WHERE session_id = ?1",
+            params![session_id],
+        )?;
+        bump_change_counter(&tx)?;
+
+        tx.commit()?;
+        Ok(deleted)
+    }
+
     /// 去重项目 - 按 path 合并，保留 session 最多的记录
     /// 返回 (合并数量, 删除的项目 ID 列表)
     pub fn deduplicate_projects(&self) -> Result<(usize, Vec<i64>)> {
@@ -1811,6 +3832,98 @@ impl SessionDB {
 
         Ok((merged_count, deleted_ids))
     }
+
+    // ==================== 采集忽略规则操作 ====================
+
+    /// 添加忽略规则（幂等），返回规则 ID
+    pub fn add_ignore(&self, pattern: &str, kind: IgnoreKind) -> Result<i64> {
+        let conn = self.conn.lock();
+        conn.execute(
+            "INSERT OR IGNORE INTO collection_ignores (pattern, kind, created_at) VALUES (?1, ?2, ?3)",
+            params![pattern, kind.to_string(), current_time_ms()],
+        )?;
+
+        conn.query_row(
+            "SELECT id FROM collection_ignores WHERE pattern = ?1 AND kind = ?2",
+            params![pattern, kind.to_string()],
+            |row| row.get(0),
+        )
+        .map_err(Into::into)
+    }
+
+    /// 删除忽略规则，返回是否存在
+    pub fn remove_ignore(&self, id: i64) -> Result<bool> {
+        let conn = self.conn.lock();
+        let affected = conn.execute("DELETE FROM collection_ignores WHERE id = ?1", params![id])?;
+        Ok(affected > 0)
+    }
+
+    /// 列出所有忽略规则（不含环境变量规则）
+    pub fn list_ignores(&self) -> Result<Vec<CollectionIgnore>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(
+            "SELECT id, pattern, kind, created_at FROM collection_ignores ORDER BY id ASC",
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            let kind_str: String = row.get(2)?;
+            // 未知类型（新版本写入）跳过
+            let Ok(kind) = kind_str.parse::<IgnoreKind>() else {
+                return Ok(None);
+            };
+            Ok(Some(CollectionIgnore {
+                id: row.get(0)?,
+                pattern: row.get(1)?,
+                kind,
+                created_at: row.get(3)?,
+            }))
+        })?;
+
+        let ignores = rows.collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(ignores.into_iter().flatten().collect())
+    }
+
+    /// 按忽略规则刷新所有项目的 ignored 标记
+    ///
+    /// 返回状态发生变化的项目: (project_id, path, ignored)
+    pub fn apply_project_ignores(&self, rules: &IgnoreRules) -> Result<Vec<(i64, String, bool)>> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        let projects: Vec<(i64, String, bool)> = {
+            let mut stmt = tx.prepare("SELECT id, path, ignored FROM projects")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get::<_, i64>(2)? != 0))
+            })?;
+            rows.collect::<std::result::Result<Vec<_>, _>>()?
+        };
+
+        let mut changed = Vec::new();
+        for (id, path, was_ignored) in projects {
+            let ignored = rules.is_project_ignored(&path);
+            if ignored != was_ignored {
+                tx.execute(
+                    "UPDATE projects SET ignored = ?1, updated_at = ?2 WHERE id = ?3",
+                    params![ignored, current_time_ms(), id],
+                )?;
+                changed.push((id, path, ignored));
+            }
+        }
+
+        tx.commit()?;
+        drop(conn);
+
+        let events = changed
+            .iter()
+            .map(|(id, path, ignored)| ChangeEvent::ProjectChanged {
+                project_id: *id,
+                project_path: path.clone(),
+                ignored: *ignored,
+            })
+            .collect();
+        self.notify(events);
+        Ok(changed)
+    }
 }
 
 /// 带 source 的项目信息
@@ -1823,7 +3936,7 @@ pub struct ProjectWithSource {
 }
 
 /// 会话输入 (写入用)
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
 pub struct SessionInput {
     pub session_id: String,
     pub project_id: i64,
@@ -1837,6 +3950,12 @@ pub struct SessionInput {
     pub file_size: Option<i64>,
     pub file_offset: Option<i64>,
     pub file_inode: Option<i64>,
+    /// 文件指纹（见 `collector::file_content_hash`）
+    #[serde(default)]
+    pub content_hash: Option<String>,
+    /// 会话文件路径（`get_session_resume_info` 返回）
+    #[serde(default)]
+    pub session_path: Option<String>,
     // 额外元信息
     pub meta: Option<String>,
     // 会话分类
@@ -1845,7 +3964,7 @@ pub struct SessionInput {
 }
 
 /// 消息输入 (写入用)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct MessageInput {
     pub uuid: String,
     pub r#type: MessageType,
@@ -1864,14 +3983,312 @@ pub struct MessageInput {
     pub approval_resolved_at: Option<i64>,                     // 审批解决时间戳（毫秒）
 }
 
+/// 写入消息时 uuid 已存在的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// 保留已有行（默认）
+    #[default]
+    Ignore,
+    /// 用新内容更新已有行（content / raw / approval_status），内容变化时重置向量索引
+    UpdateContent,
+}
+
+/// `insert_messages_report` 的结果
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InsertReport {
+    /// 实际插入的数量
+    pub inserted: usize,
+    /// 新插入的 message_ids（与插入顺序一致）
+    pub new_ids: Vec<i64>,
+    /// 因 uuid 已存在而跳过的消息（按输入顺序）
+    pub skipped_uuids: Vec<String>,
+    /// 写入失败而跳过的消息（按输入顺序），同批次的其他消息照常写入
+    #[serde(default)]
+    pub failed_uuids: Vec<String>,
+}
+
+/// 单个会话的采集批次 (写入用)
+///
+/// 解析结果尚未写入：Writer 直接写入，Reader 通过 Agent 转发给唯一的 Writer。
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CollectBatch {
+    pub project_name: String,
+    pub project_path: String,
+    /// 会话文件路径（写入失败时用于定位来源）
+    #[serde(default)]
+    pub session_path: PathBuf,
+    pub encoded_dir_name: Option<String>,
+    /// 会话输入（project_id 由写入方创建项目后填充）
+    pub session: SessionInput,
+    pub parent_session_id: Option<String>,
+    pub continuation_from: Option<String>,
+    /// 消息（sequence 为批次内相对序号，写入时从会话当前最大 sequence + 1 开始）
+    pub messages: Vec<MessageInput>,
+    /// 被采集过滤器跳过的条目数
+    pub skipped_by_filter: usize,
+    /// 超过单条消息上限而被截断的消息数
+    #[serde(default)]
+    pub messages_truncated: usize,
+    /// 增量读取状态 (offset, mtime, size, inode)，写入消息后保存
+    pub incremental_state: Option<(i64, i64, i64, i64)>,
+}
+
+/// 查询结果第一列的会话 ID 列表
+fn distinct_session_ids(
+    conn: &Connection,
+    sql: &str,
+    params: impl rusqlite::Params,
+) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map(params, |row| row.get(0))?;
+    rows.collect()
+}
+
+/// 递增全局变更计数（单条 UPDATE），需在写操作所在事务内调用
+pub(crate) fn bump_change_counter(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE change_counter SET value = value + 1 WHERE id = 1",
+        [],
+    )?;
+    Ok(())
+}
+
+/// 下一条消息的轮次：用户消息紧跟在非用户消息之后时开启新轮次，会话第一条消息为 0
+fn next_turn_index(turn: Option<i64>, last_is_user: bool, is_user: bool) -> i64 {
+    match turn {
+        None => 0,
+        Some(turn) if is_user && !last_is_user => turn + 1,
+        Some(turn) => turn,
+    }
+}
+
+/// 会话当前的轮次状态：(最后一条消息的 turn_index, 是否为用户消息)
+///
+/// 最后一条消息尚未分配轮次（老数据）时先回填整个会话。
+fn current_turn_state(
+    conn: &Connection,
+    session_id: &str,
+) -> rusqlite::Result<(Option<i64>, bool)> {
+    let query = |conn: &Connection| {
+        conn.query_row(
+            "SELECT turn_index, type FROM messages WHERE session_id = ?1 ORDER BY sequence DESC, id DESC LIMIT 1",
+            params![session_id],
+            |row| Ok((row.get::<_, Option<i64>>(0)?, row.get::<_, String>(1)?)),
+        )
+        .optional()
+    };
+
+    let mut last = query(conn)?;
+    if matches!(last, Some((None, _))) {
+        assign_session_turns(conn, session_id)?;
+        last = query(conn)?;
+    }
+    Ok(match last {
+        Some((turn, message_type)) => (turn, message_type == "user"),
+        None => (None, false),
+    })
+}
+
+/// 按 sequence 重算会话所有消息的 turn_index，返回实际变化的行数
+fn assign_session_turns(conn: &Connection, session_id: &str) -> rusqlite::Result<usize> {
+    let rows: Vec<(i64, String, Option<i64>)> = {
+        let mut stmt = conn.prepare(
+            "SELECT id, type, turn_index FROM messages WHERE session_id = ?1 ORDER BY sequence ASC, id ASC",
+        )?;
+        let rows = stmt.query_map(params![session_id], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+
+    let mut update = conn.prepare("UPDATE messages SET turn_index = ?1 WHERE id = ?2")?;
+    let (mut turn, mut last_is_user) = (None, false);
+    let mut updated = 0;
+    for (id, message_type, old) in rows {
+        let is_user = message_type == "user";
+        let turn_index = next_turn_index(turn, last_is_user, is_user);
+        if old != Some(turn_index) {
+            updated += update.execute(params![turn_index, id])?;
+        }
+        turn = Some(turn_index);
+        last_is_user = is_user;
+    }
+    Ok(updated)
+}
+
+/// 按字面规范化项目路径：去掉结尾的分隔符和多余的 `.`，解析 `..`
+///
+/// 不访问文件系统，路径不存在时同样适用；根目录之上的 `..` 忽略，相对路径开头的 `..` 保留。
+/// 空路径原样返回。
+pub fn normalize_project_path(path: &str) -> String {
+    let mut normalized = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normalized.push(".."),
+            },
+            other => normalized.push(other),
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        return if path.is_empty() {
+            String::new()
+        } else {
+            ".".to_string()
+        };
+    }
+    normalized.to_string_lossy().into_owned()
+}
+
+/// 读取 metadata 表中的值
+pub(crate) fn get_metadata(conn: &Connection, key: &str) -> rusqlite::Result<Option<String>> {
+    conn.query_row(
+        "SELECT value FROM metadata WHERE key = ?1",
+        params![key],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// 写入 metadata 表（已存在时覆盖），需在写操作所在事务内调用
+pub(crate) fn set_metadata(conn: &Connection, key: &str, value: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        r#"
+        INSERT INTO metadata (key, value, updated_at) VALUES (?1, ?2, ?3)
+        ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at
+        "#,
+        params![key, value, current_time_ms()],
+    )?;
+    Ok(())
+}
+
+/// 是否启用了 FTS（fts_backlog 与 messages_fts 由同一段 schema 创建）
+pub(crate) fn fts_enabled(conn: &Connection) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'fts_backlog')",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// 按 messages 全量重建 FTS 索引并清空 fts_backlog（未启用 FTS 时什么都不做）
+///
+/// 用于绕过写入路径批量复制消息之后（抢救、修复）。
+pub(crate) fn rebuild_messages_fts(conn: &Connection) -> rusqlite::Result<()> {
+    if !fts_enabled(conn)? {
+        return Ok(());
+    }
+    conn.execute_batch(
+        "INSERT INTO messages_fts(messages_fts) VALUES('rebuild');
+         DELETE FROM fts_backlog;",
+    )
+}
+
+/// 为消息建立 FTS 索引（内容从已写入的消息行读取），返回是否成功
+///
+/// 在 SAVEPOINT 中插入：失败（索引损坏、分词器错误）时只回滚索引部分，
+/// 消息记入 fts_backlog 等待 `SessionDB::reindex_fts_backlog`，同一事务中的消息行不受影响。
+pub(crate) fn index_message_fts(
+    conn: &Connection,
+    message_id: i64,
+    uuid: &str,
+) -> rusqlite::Result<bool> {
+    conn.execute_batch("SAVEPOINT fts_index")?;
+    let error = match conn.execute(
+        "INSERT INTO messages_fts(rowid, content_full, content_text)
+         SELECT id, content_full, content_text FROM messages WHERE id = ?1",
+        params![message_id],
+    ) {
+        Ok(_) => {
+            conn.execute_batch("RELEASE fts_index")?;
+            return Ok(true);
+        }
+        Err(e) => e,
+    };
+    conn.execute_batch("ROLLBACK TO fts_index; RELEASE fts_index")?;
+
+    tracing::warn!(
+        "FTS index failed for message {}, queued for reindex: {}",
+        uuid,
+        error
+    );
+    conn.execute(
+        r#"
+        INSERT INTO fts_backlog (message_id, uuid, error, created_at)
+        VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT(message_id) DO UPDATE SET
+            error = excluded.error,
+            attempts = attempts + 1
+        "#,
+        params![message_id, uuid, error.to_string(), current_time_ms()],
+    )?;
+    Ok(false)
+}
+
 /// 获取当前时间戳 (毫秒)
-fn current_time_ms() -> i64 {
+pub(crate) fn current_time_ms() -> i64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .map(|d| d.as_millis() as i64)
         .unwrap_or(0)
 }
 
+/// 计算内容哈希（FNV-1a 64 位，十六进制）
+///
+/// 仅用于检测内容变化，不要求抗碰撞；跨版本稳定，可持久化。
+pub(crate) fn content_hash(content: &str) -> String {
+    format!("{:016x}", fnv1a_64(content.as_bytes()))
+}
+
+/// FNV-1a 64 位哈希
+fn fnv1a_64(bytes: &[u8]) -> u64 {
+    const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    bytes.iter().fold(FNV_OFFSET, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// 从原始 JSONL 条目识别 (是否为 sidechain, 是否为 summary 条目)
+fn raw_entry_flags(raw: Option<&str>) -> (bool, bool) {
+    // 先按子串粗筛，大多数条目不需要解析 JSON
+    let Some(raw) = raw.filter(|r| r.contains("\"isSidechain\"") || r.contains("\"summary\""))
+    else {
+        return (false, false);
+    };
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(raw) else {
+        return (false, false);
+    };
+    let sidechain = value
+        .get("isSidechain")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let summary = value.get("type").and_then(|v| v.as_str()) == Some("summary");
+    (sidechain, summary)
+}
+
+/// 按字节上限截断内容（退到字符边界），返回 (内容, 是否被截断)
+fn truncate_content(content: &str, max_bytes: Option<usize>) -> (&str, bool) {
+    match max_bytes {
+        Some(max) if content.len() > max => {
+            let mut end = max;
+            while !content.is_char_boundary(end) {
+                end -= 1;
+            }
+            (&content[..end], true)
+        }
+        _ => (content, false),
+    }
+}
+
 /// 数据库完整性检查结果
 #[derive(Debug, Clone)]
 pub enum IntegrityCheckResult {
@@ -1893,6 +4310,27 @@ impl SessionDB {
         Ok(())
     }
 
+    /// 当前连接的 WAL 自动 checkpoint 阈值（页，0 表示关闭）
+    pub fn wal_autocheckpoint_pages(&self) -> Result<u32> {
+        let conn = self.conn.lock();
+        let pages: i64 = conn.query_row("PRAGMA wal_autocheckpoint;", [], |row| row.get(0))?;
+        Ok(pages.max(0) as u32)
+    }
+
+    /// 释放连接占用的可回收内存（页缓存中的未使用页面）
+    pub fn release_memory(&self) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.execute_batch("PRAGMA shrink_memory;")?;
+        Ok(())
+    }
+
+    /// 设置连接的页缓存上限（KiB）
+    pub fn set_cache_size_kib(&self, kib: u32) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.execute_batch(&format!("PRAGMA cache_size = -{};", kib))?;
+        Ok(())
+    }
+
     /// 检查数据库完整性
     ///
     /// 使用 quick_check 进行快速检查（只检查 B-tree 结构）
@@ -1921,6 +4359,65 @@ impl SessionDB {
         }
     }
 
+    // ==================== FTS 补建 ====================
+
+    /// 待补建 FTS 索引的消息数（未启用 FTS 时为 0）
+    pub fn fts_backlog_count(&self) -> Result<i64> {
+        let conn = self.conn.lock();
+        if !fts_enabled(&conn)? {
+            return Ok(0);
+        }
+        conn.query_row("SELECT COUNT(*) FROM fts_backlog", [], |row| row.get(0))
+            .map_err(Into::into)
+    }
+
+    /// 为 fts_backlog 中的消息补建 FTS 索引（按入队顺序，最多 limit 条）
+    ///
+    /// 仍然失败的消息留在队列中（记录最新错误和尝试次数）。返回成功补建的数量。
+    pub fn reindex_fts_backlog(&self, limit: usize) -> Result<usize> {
+        let mut conn = self.conn.lock();
+        if !fts_enabled(&conn)? {
+            return Ok(0);
+        }
+        let tx = conn.transaction()?;
+
+        let entries: Vec<(i64, String, Option<i64>)> = tx
+            .prepare(
+                r#"
+                SELECT b.message_id, b.uuid, m.id
+                FROM fts_backlog b
+                LEFT JOIN messages m ON m.id = b.message_id
+                ORDER BY b.created_at, b.message_id
+                LIMIT ?1
+                "#,
+            )?
+            .query_map(params![limit as i64], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })?
+            .collect::<std::result::Result<_, _>>()?;
+
+        let mut reindexed = 0;
+        for (message_id, uuid, existing) in entries {
+            // 消息已删除时直接出队
+            if existing.is_some() {
+                if !index_message_fts(&tx, message_id, &uuid)? {
+                    continue;
+                }
+                reindexed += 1;
+            }
+            tx.execute(
+                "DELETE FROM fts_backlog WHERE message_id = ?1",
+                params![message_id],
+            )?;
+        }
+
+        tx.commit()?;
+        if reindexed > 0 {
+            tracing::info!("Reindexed {} messages from FTS backlog", reindexed);
+        }
+        Ok(reindexed)
+    }
+
     // ==================== Session Relations 操作 ====================
 
     /// 插入会话关系（幂等，INSERT OR IGNORE）
@@ -1942,6 +4439,34 @@ impl SessionDB {
         Ok(())
     }
 
+    /// 批量插入会话关系（单个事务，INSERT OR IGNORE）
+    ///
+    /// rows: (parent_id, child_id, relation_type, source)；返回新插入的行数（重复的不计）
+    pub fn insert_session_relations(&self, rows: &[(&str, &str, &str, &str)]) -> Result<usize> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        let mut inserted = 0;
+        {
+            let mut stmt = tx.prepare(
+                r#"
+                INSERT OR IGNORE INTO session_relations (parent_session_id, child_session_id, relation_type, source)
+                VALUES (?1, ?2, ?3, ?4)
+                "#,
+            )?;
+            for (parent_id, child_id, relation_type, source) in rows {
+                inserted += stmt.execute(params![parent_id, child_id, relation_type, source])?;
+            }
+        }
+
+        if inserted > 0 {
+            bump_change_counter(&tx)?;
+        }
+
+        tx.commit()?;
+        Ok(inserted)
+    }
+
     /// 获取子会话列表
     pub fn get_children_sessions(&self, parent_session_id: &str) -> Result<Vec<SessionRelation>> {
         let conn = self.conn.lock();
@@ -1954,20 +4479,94 @@ impl SessionDB {
             "#,
         )?;
 
-        let rows = stmt.query_map(params![parent_session_id], |row| {
-            Ok(SessionRelation {
-                parent_session_id: row.get(0)?,
-                child_session_id: row.get(1)?,
-                relation_type: row.get(2)?,
-                source: row.get(3)?,
-                created_at: row.get(4)?,
-            })
-        })?;
+        let rows = stmt.query_map(params![parent_session_id], Self::row_to_session_relation)?;
 
         rows.collect::<std::result::Result<Vec<_>, _>>()
             .map_err(Into::into)
     }
 
+    /// 获取会话 fork 树
+    ///
+    /// 从 root 广度优先遍历 session_relations，最多展开 `max_depth` 层（0 表示只返回根节点）。
+    /// 已访问过的会话不会重复展开，关系中存在环时也能正常结束。
+    pub fn get_session_tree(&self, root_session_id: &str, max_depth: usize) -> Result<SessionTree> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT parent_session_id, child_session_id, relation_type, source, created_at
+            FROM session_relations
+            WHERE parent_session_id = ?1
+            ORDER BY created_at ASC
+            "#,
+        )?;
+
+        let mut visited = HashSet::from([root_session_id.to_string()]);
+        let mut children_of: HashMap<String, Vec<SessionRelation>> = HashMap::new();
+        let mut truncated = HashSet::new();
+        let mut queue = VecDeque::from([(root_session_id.to_string(), 0usize)]);
+
+        while let Some((session_id, depth)) = queue.pop_front() {
+            let relations = stmt
+                .query_map(params![&session_id], Self::row_to_session_relation)?
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+
+            if depth >= max_depth {
+                if relations
+                    .iter()
+                    .any(|r| !visited.contains(&r.child_session_id))
+                {
+                    truncated.insert(session_id);
+                }
+                continue;
+            }
+
+            let mut children = Vec::new();
+            for relation in relations {
+                if visited.insert(relation.child_session_id.clone()) {
+                    queue.push_back((relation.child_session_id.clone(), depth + 1));
+                    children.push(relation);
+                }
+            }
+            children_of.insert(session_id, children);
+        }
+
+        Ok(Self::build_session_tree(
+            root_session_id.to_string(),
+            None,
+            &mut children_of,
+            &truncated,
+        ))
+    }
+
+    /// 按遍历结果组装嵌套结构（深度受 max_depth 限制）
+    fn build_session_tree(
+        session_id: String,
+        relation: Option<SessionRelation>,
+        children_of: &mut HashMap<String, Vec<SessionRelation>>,
+        truncated: &HashSet<String>,
+    ) -> SessionTree {
+        let children = children_of
+            .remove(&session_id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|r| {
+                Self::build_session_tree(
+                    r.child_session_id.clone(),
+                    Some(r),
+                    children_of,
+                    truncated,
+                )
+            })
+            .collect();
+
+        SessionTree {
+            truncated: truncated.contains(&session_id),
+            session_id,
+            relation,
+            children,
+        }
+    }
+
     /// 获取父会话
     pub fn get_parent_session(&self, child_session_id: &str) -> Result<Option<SessionRelation>> {
         let conn = self.conn.lock();
@@ -1979,20 +4578,22 @@ impl SessionDB {
             LIMIT 1
             "#,
             params![child_session_id],
-            |row| {
-                Ok(SessionRelation {
-                    parent_session_id: row.get(0)?,
-                    child_session_id: row.get(1)?,
-                    relation_type: row.get(2)?,
-                    source: row.get(3)?,
-                    created_at: row.get(4)?,
-                })
-            },
+            Self::row_to_session_relation,
         )
         .optional()
         .map_err(Into::into)
     }
 
+    fn row_to_session_relation(row: &rusqlite::Row) -> rusqlite::Result<SessionRelation> {
+        Ok(SessionRelation {
+            parent_session_id: row.get(0)?,
+            child_session_id: row.get(1)?,
+            relation_type: row.get(2)?,
+            source: row.get(3)?,
+            created_at: row.get(4)?,
+        })
+    }
+
     // ==================== Continuation Chain 操作 ====================
 
     /// 将 session 加入 continuation chain
@@ -1,4 +1,9 @@
 //! 错误类型定义
+//!
+//! 底层错误（rusqlite / io / serde_json / anyhow）保留为 `source()`，
+//! 调用方可以沿错误链 downcast 或完整打印；`Display` 只给出简短说明。
+
+use std::path::PathBuf;
 
 use thiserror::Error;
 
@@ -30,15 +35,59 @@ pub enum Error {
     Connection(String),
 
     /// 数据库损坏（需要用户介入修复）
-    #[error("数据库损坏: {0}")]
-    DatabaseMalformed(String),
+    ///
+    /// 存在 WAL 文件，通常是 WAL 与主文件不匹配，`vimo-agent --repair` 可处理。
+    #[error("数据库损坏: {diagnostic}")]
+    DatabaseMalformed {
+        /// 诊断信息（含 WAL/SHM 文件状态）
+        diagnostic: String,
+        #[source]
+        source: rusqlite::Error,
+    },
+
+    /// 数据库文件本身损坏（没有 WAL，不是 WAL 不匹配）
+    ///
+    /// 调用方可用 `SessionDB::salvage_into(path, dest)` 抢救数据到新文件，再替换原文件。
+    #[error("数据库文件损坏: {diagnostic}")]
+    Corrupted {
+        /// 损坏的数据库文件
+        path: PathBuf,
+        /// 诊断信息（含 WAL/SHM 文件状态）
+        diagnostic: String,
+        #[source]
+        source: rusqlite::Error,
+    },
 
     /// 权限错误 (Reader 尝试写入)
     #[error("权限错误: 当前角色为 Reader，无法执行写入操作")]
     PermissionDenied,
 
-    /// 其他错误
-    #[error("{0}")]
+    /// 无权访问会话目录（macOS 上通常是未授予完全磁盘访问权限）
+    #[error("无权访问: {}", .0.display())]
+    AccessDenied(PathBuf),
+
+    /// 其他进程正在采集（持有采集锁），稍后重试
+    #[error("采集进行中: 采集锁由 {holder} 持有")]
+    CollectionInProgress { holder: String },
+
+    /// 分页 token 无法解析、被篡改，或不是由当前 API / 查询签发
+    #[error("无效的分页 token: {0}")]
+    InvalidPageToken(String),
+
+    /// 当前后端不支持该操作（如文件系统后端的全文搜索）
+    #[error("不支持的操作: {0}")]
+    Unsupported(String),
+
+    /// 请求的对象不存在
+    #[error("不存在: {0}")]
+    NotFound(String),
+
+    /// 项目路径已被另一个项目使用
+    #[error("项目路径冲突: {path} 已属于项目 {project_id}")]
+    ProjectPathConflict { path: String, project_id: i64 },
+
+    /// 其他错误（Display 和 source 透传给内部错误）
+    #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
 
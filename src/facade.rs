@@ -0,0 +1,583 @@
+//! 统一读取门面（供 CLI 等下游二进制使用）
+//!
+//! 下游常见的选择逻辑：Agent 在运行 → 通过 Agent 查询；数据库文件存在 → 只读打开数据库；
+//! 否则 → 直接读取 Claude JSONL 文件（`SessionReader`）。
+//! `SessionStore::auto()` 按此顺序探测，各后端返回同一组 DTO，
+//! `capabilities()` 说明所选后端支持哪些操作。
+//!
+//! 选择可覆盖：`StoreOptions::backend`（或环境变量 `VIMO_SESSION_BACKEND=agent|db|fs`）
+//! 指定优先使用的后端，`SessionStore::open` 强制使用指定后端，`kind()` 返回实际选中的后端。
+//!
+//! 所有方法都是同步的；Agent 后端内部持有独立的 tokio runtime，不能在 async 上下文中使用
+//! （此时探测会跳过 Agent）。
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::db::SessionDB;
+use crate::error::{Error, Result};
+use crate::reader::{parse_timestamp_to_millis, Order, ProjectInfo, SessionReader};
+use crate::types::{Message, ProjectWithStats, SearchResult, SessionWithProject};
+use crate::{DbConfig, MessageType, ParsedMessage, SessionMeta};
+
+/// 指定后端的环境变量（agent / db / fs）
+pub const BACKEND_ENV: &str = "VIMO_SESSION_BACKEND";
+
+/// 后端类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    /// 通过运行中的 Agent 查询
+    Agent,
+    /// 只读打开数据库
+    Db,
+    /// 直接读取 Claude JSONL 文件
+    Fs,
+}
+
+impl BackendKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackendKind::Agent => "agent",
+            BackendKind::Db => "db",
+            BackendKind::Fs => "fs",
+        }
+    }
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "agent" => Ok(BackendKind::Agent),
+            "db" => Ok(BackendKind::Db),
+            "fs" => Ok(BackendKind::Fs),
+            other => Err(Error::Config(format!("Unknown session backend: {}", other))),
+        }
+    }
+}
+
+/// 后端能力
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// 支持全文搜索
+    pub search: bool,
+    /// 包含 Claude 以外的数据源（OpenCode、Codex 等）
+    pub multi_source: bool,
+}
+
+/// 项目
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreProject {
+    pub name: String,
+    pub path: String,
+    /// 会话数量
+    pub session_count: usize,
+    /// 最后活跃时间（毫秒时间戳）
+    pub last_active: Option<i64>,
+}
+
+/// 会话
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreSession {
+    pub session_id: String,
+    pub project_path: String,
+    /// 消息数量（文件系统后端未解析时为 None）
+    pub message_count: Option<usize>,
+    /// 最后活跃时间（毫秒时间戳）
+    pub last_active: Option<i64>,
+}
+
+/// 消息
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoreMessage {
+    pub uuid: String,
+    pub r#type: MessageType,
+    /// 纯对话文本
+    pub content: String,
+    /// 消息时间（毫秒时间戳）
+    pub timestamp: Option<i64>,
+}
+
+/// 搜索命中
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoreSearchHit {
+    pub session_id: String,
+    pub project_name: String,
+    pub snippet: String,
+    /// 消息时间（毫秒时间戳）
+    pub timestamp: Option<i64>,
+}
+
+impl From<ProjectWithStats> for StoreProject {
+    fn from(p: ProjectWithStats) -> Self {
+        Self {
+            name: p.name,
+            path: p.path,
+            session_count: p.session_count as usize,
+            last_active: p.last_active,
+        }
+    }
+}
+
+impl From<ProjectInfo> for StoreProject {
+    fn from(p: ProjectInfo) -> Self {
+        Self {
+            name: p.name,
+            path: p.path,
+            session_count: p.session_count,
+            last_active: p.last_active.map(|t| t as i64),
+        }
+    }
+}
+
+impl From<SessionWithProject> for StoreSession {
+    fn from(s: SessionWithProject) -> Self {
+        Self {
+            session_id: s.session_id,
+            project_path: s.project_path,
+            message_count: Some(s.message_count as usize),
+            last_active: s.last_message_at,
+        }
+    }
+}
+
+impl From<SessionMeta> for StoreSession {
+    fn from(s: SessionMeta) -> Self {
+        Self {
+            session_id: s.id,
+            project_path: s.project_path,
+            message_count: s.message_count.map(|c| c as usize),
+            last_active: s.file_mtime.map(|t| t as i64),
+        }
+    }
+}
+
+impl From<Message> for StoreMessage {
+    fn from(m: Message) -> Self {
+        Self {
+            uuid: m.uuid,
+            r#type: m.r#type,
+            content: m.content_text,
+            timestamp: Some(m.timestamp),
+        }
+    }
+}
+
+impl From<ParsedMessage> for StoreMessage {
+    fn from(m: ParsedMessage) -> Self {
+        Self {
+            timestamp: m.timestamp.as_deref().and_then(parse_timestamp_to_millis),
+            uuid: m.uuid,
+            r#type: m.message_type,
+            content: m.content.text,
+        }
+    }
+}
+
+impl From<SearchResult> for StoreSearchHit {
+    fn from(r: SearchResult) -> Self {
+        Self {
+            session_id: r.session_id,
+            project_name: r.project_name,
+            snippet: r.snippet,
+            timestamp: r.timestamp,
+        }
+    }
+}
+
+/// 读取后端
+pub trait SessionBackend {
+    /// 后端类型
+    fn kind(&self) -> BackendKind;
+
+    /// 后端能力
+    fn capabilities(&self) -> Capabilities;
+
+    /// 项目列表（按最后活跃时间降序）
+    fn list_projects(&mut self, limit: usize, offset: usize) -> Result<Vec<StoreProject>>;
+
+    /// 项目下的会话列表（不含 agent session）
+    fn list_sessions(
+        &mut self,
+        project_path: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<StoreSession>>;
+
+    /// 会话消息（按时间正序）；会话不存在时返回空列表
+    fn get_messages(
+        &mut self,
+        session_id: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<StoreMessage>>;
+
+    /// 全文搜索（`capabilities().search` 为 false 时返回 `Error::Unsupported`）
+    fn search(&mut self, query: &str, limit: usize) -> Result<Vec<StoreSearchHit>> {
+        let _ = (query, limit);
+        Err(Error::Unsupported(format!(
+            "search ({} backend)",
+            self.kind()
+        )))
+    }
+}
+
+/// 只读数据库后端
+pub struct DbBackend {
+    db: SessionDB,
+}
+
+impl DbBackend {
+    /// 只读打开数据库（文件不存在时返回错误，不会创建）
+    pub fn open(db_path: &Path) -> Result<Self> {
+        if !db_path.is_file() {
+            return Err(Error::Config(format!(
+                "Database not found: {}",
+                db_path.display()
+            )));
+        }
+        let db = SessionDB::connect_read_only(DbConfig::local(db_path))?;
+        Ok(Self { db })
+    }
+}
+
+impl SessionBackend for DbBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Db
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            search: cfg!(feature = "search"),
+            multi_source: true,
+        }
+    }
+
+    fn list_projects(&mut self, limit: usize, offset: usize) -> Result<Vec<StoreProject>> {
+        let projects = self.db.list_projects_with_stats(limit, offset)?;
+        Ok(projects.into_iter().map(Into::into).collect())
+    }
+
+    fn list_sessions(
+        &mut self,
+        project_path: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<StoreSession>> {
+        let sessions = self
+            .db
+            .list_sessions_by_project_path(project_path, limit, offset)?;
+        Ok(sessions.into_iter().map(Into::into).collect())
+    }
+
+    fn get_messages(
+        &mut self,
+        session_id: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<StoreMessage>> {
+        let messages = self.db.list_messages(session_id, limit, offset)?;
+        Ok(messages.into_iter().map(Into::into).collect())
+    }
+
+    #[cfg(feature = "search")]
+    fn search(&mut self, query: &str, limit: usize) -> Result<Vec<StoreSearchHit>> {
+        let results = self.db.search_fts(query, limit)?;
+        Ok(results.into_iter().map(Into::into).collect())
+    }
+}
+
+/// 文件系统后端（只读取 Claude JSONL）
+pub struct FsBackend {
+    reader: SessionReader,
+}
+
+impl FsBackend {
+    /// 读取指定 Claude projects 目录
+    pub fn new(projects_path: PathBuf) -> Self {
+        Self {
+            reader: SessionReader::new(projects_path),
+        }
+    }
+}
+
+impl SessionBackend for FsBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Fs
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            search: false,
+            multi_source: false,
+        }
+    }
+
+    fn list_projects(&mut self, limit: usize, offset: usize) -> Result<Vec<StoreProject>> {
+        let projects = self
+            .reader
+            .list_projects(Some(offset.saturating_add(limit)))?;
+        Ok(projects.into_iter().skip(offset).map(Into::into).collect())
+    }
+
+    fn list_sessions(
+        &mut self,
+        project_path: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<StoreSession>> {
+        let sessions = self.reader.list_sessions(Some(project_path), false)?;
+        Ok(sessions
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(Into::into)
+            .collect())
+    }
+
+    fn get_messages(
+        &mut self,
+        session_id: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<StoreMessage>> {
+        let messages = self
+            .reader
+            .get_session_path(session_id)
+            .and_then(|path| {
+                self.reader
+                    .read_messages(&path, Some(limit), offset, Order::Asc)
+            })
+            .map(|result| result.messages)
+            .unwrap_or_default();
+        Ok(messages.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Agent 后端（通过运行中的 Agent 查询，不会启动 Agent）
+#[cfg(feature = "client")]
+pub struct AgentBackend {
+    // 先于 runtime 释放
+    client: crate::client::AgentClient,
+    runtime: tokio::runtime::Runtime,
+}
+
+#[cfg(feature = "client")]
+impl AgentBackend {
+    /// 连接 `data_dir` 下已在运行的 Agent
+    ///
+    /// Agent 不可达、握手失败或当前处于 async 上下文时返回错误。
+    pub fn connect(data_dir: &Path) -> Result<Self> {
+        if tokio::runtime::Handle::try_current().is_ok() {
+            return Err(Error::Unsupported(
+                "agent backend inside an async runtime".to_string(),
+            ));
+        }
+
+        let mut config = crate::client::ClientConfig::new("session-store");
+        config.data_dir = data_dir.to_path_buf();
+        let runtime = tokio::runtime::Runtime::new()?;
+        let client = runtime.block_on(crate::client::connect_running_agent(config))?;
+        Ok(Self { client, runtime })
+    }
+}
+
+#[cfg(feature = "client")]
+impl SessionBackend for AgentBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Agent
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            search: true,
+            multi_source: true,
+        }
+    }
+
+    fn list_projects(&mut self, limit: usize, offset: usize) -> Result<Vec<StoreProject>> {
+        let projects = self
+            .runtime
+            .block_on(self.client.list_projects(limit, offset))?;
+        Ok(projects.into_iter().map(Into::into).collect())
+    }
+
+    fn list_sessions(
+        &mut self,
+        project_path: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<StoreSession>> {
+        let sessions =
+            self.runtime
+                .block_on(self.client.list_sessions(project_path, limit, offset))?;
+        Ok(sessions.into_iter().map(Into::into).collect())
+    }
+
+    fn get_messages(
+        &mut self,
+        session_id: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<StoreMessage>> {
+        let messages = self
+            .runtime
+            .block_on(self.client.list_messages(session_id, limit, offset))?;
+        Ok(messages.into_iter().map(Into::into).collect())
+    }
+
+    fn search(&mut self, query: &str, limit: usize) -> Result<Vec<StoreSearchHit>> {
+        let groups = self.runtime.block_on(self.client.search_grouped(
+            query,
+            limit,
+            limit,
+            Default::default(),
+        ))?;
+        Ok(groups
+            .into_iter()
+            .flat_map(|group| group.hits)
+            .take(limit)
+            .map(Into::into)
+            .collect())
+    }
+}
+
+/// 探测选项
+#[derive(Debug, Clone)]
+pub struct StoreOptions {
+    /// Agent 数据目录（socket 所在目录，默认 ~/.vimo）
+    pub data_dir: PathBuf,
+    /// 数据库路径（默认同 `DbConfig::from_env`）
+    pub db_path: PathBuf,
+    /// Claude projects 目录（默认 ~/.claude/projects）
+    pub projects_path: PathBuf,
+    /// 优先使用的后端（不可用时回退到自动探测），None 表示按 Agent → Db → Fs 探测
+    pub backend: Option<BackendKind>,
+}
+
+impl Default for StoreOptions {
+    fn default() -> Self {
+        let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+        let data_dir = home.join(".vimo");
+        let db_path = DbConfig::from_env()
+            .path()
+            .unwrap_or_else(|| data_dir.join("db").join("ai-cli-session.db"));
+        let backend = std::env::var(BACKEND_ENV).ok().and_then(|value| {
+            value
+                .parse()
+                .map_err(|e| tracing::warn!("Ignoring {}: {}", BACKEND_ENV, e))
+                .ok()
+        });
+
+        Self {
+            data_dir,
+            db_path,
+            projects_path: home.join(".claude/projects"),
+            backend,
+        }
+    }
+}
+
+/// 统一读取门面
+pub struct SessionStore {
+    backend: Box<dyn SessionBackend>,
+}
+
+impl SessionStore {
+    /// 使用默认选项自动探测后端
+    pub fn auto() -> Self {
+        Self::probe(&StoreOptions::default())
+    }
+
+    /// 按 Agent → Db → Fs 顺序探测后端（`options.backend` 指定时优先尝试）
+    ///
+    /// 文件系统后端总是可用，因此探测不会失败。
+    pub fn probe(options: &StoreOptions) -> Self {
+        if let Some(kind) = options.backend {
+            match Self::open(kind, options) {
+                Ok(store) => return store,
+                Err(e) => tracing::warn!("Requested {} backend unavailable: {}", kind, e),
+            }
+        }
+
+        for kind in [BackendKind::Agent, BackendKind::Db] {
+            match Self::open(kind, options) {
+                Ok(store) => return store,
+                Err(e) => tracing::debug!("{} backend unavailable: {}", kind, e),
+            }
+        }
+
+        Self::from_backend(Box::new(FsBackend::new(options.projects_path.clone())))
+    }
+
+    /// 强制使用指定后端，不可用时返回错误
+    pub fn open(kind: BackendKind, options: &StoreOptions) -> Result<Self> {
+        let backend: Box<dyn SessionBackend> = match kind {
+            #[cfg(feature = "client")]
+            BackendKind::Agent => Box::new(AgentBackend::connect(&options.data_dir)?),
+            #[cfg(not(feature = "client"))]
+            BackendKind::Agent => {
+                return Err(Error::Unsupported(
+                    "agent backend (client feature disabled)".to_string(),
+                ))
+            }
+            BackendKind::Db => Box::new(DbBackend::open(&options.db_path)?),
+            BackendKind::Fs => Box::new(FsBackend::new(options.projects_path.clone())),
+        };
+        Ok(Self::from_backend(backend))
+    }
+
+    /// 使用自定义后端
+    pub fn from_backend(backend: Box<dyn SessionBackend>) -> Self {
+        tracing::info!("Session store backend: {}", backend.kind());
+        Self { backend }
+    }
+
+    /// 实际选中的后端
+    pub fn kind(&self) -> BackendKind {
+        self.backend.kind()
+    }
+
+    /// 所选后端支持的操作
+    pub fn capabilities(&self) -> Capabilities {
+        self.backend.capabilities()
+    }
+
+    /// 项目列表（按最后活跃时间降序）
+    pub fn list_projects(&mut self, limit: usize, offset: usize) -> Result<Vec<StoreProject>> {
+        self.backend.list_projects(limit, offset)
+    }
+
+    /// 项目下的会话列表（不含 agent session）
+    pub fn list_sessions(
+        &mut self,
+        project_path: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<StoreSession>> {
+        self.backend.list_sessions(project_path, limit, offset)
+    }
+
+    /// 会话消息（按时间正序）
+    pub fn get_messages(
+        &mut self,
+        session_id: &str,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<StoreMessage>> {
+        self.backend.get_messages(session_id, limit, offset)
+    }
+
+    /// 全文搜索（不支持时返回 `Error::Unsupported`）
+    pub fn search(&mut self, query: &str, limit: usize) -> Result<Vec<StoreSearchHit>> {
+        self.backend.search(query, limit)
+    }
+}
@@ -3,6 +3,9 @@
 //! 为 MemexKit / VlaudeKit 提供 C ABI 接口
 //!
 //! FFI 层只做类型转换，业务逻辑统一在 reader 模块实现。
+//!
+//! 所有 `limit` 参数为 0 时表示不限制，但最多返回 `config::MAX_QUERY_LIMIT` 条
+//! （`session_db_get_talk_summaries` 为最大单页条数）。
 
 use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
@@ -10,9 +13,9 @@ use std::panic::{self, AssertUnwindSafe};
 use std::path::PathBuf;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::config::DbConfig;
+use crate::config::{boundary_limit, DbConfig};
 use crate::db::{MessageInput, SessionDB};
-use crate::reader::SessionReader;
+use crate::reader::{Order, SessionReader};
 use crate::{ClaudeAdapter, ConversationAdapter};
 use ai_cli_session_collector::MessageType;
 
@@ -33,6 +36,14 @@ pub enum FfiError {
     RequestFailed = 8,
     AgentNotFound = 9,
     RuntimeError = 10,
+    // 分页 token 无效（被篡改，或不是由该查询签发），应从第一页重新开始
+    InvalidPageToken = 11,
+    // 请求的对象不存在（如路径没有对应的适配器）
+    NotFound = 12,
+    // 分块写入器已结束（finish 之后再次 push 或 finish）
+    WriterFinished = 13,
+    // 参数无法解析（如无效的日期范围字符串）
+    InvalidArgument = 14,
     // 通用
     Unknown = 99,
 }
@@ -41,7 +52,11 @@ pub enum FfiError {
 fn map_error(e: crate::error::Error) -> FfiError {
     match e {
         crate::error::Error::PermissionDenied => FfiError::PermissionDenied,
+        crate::error::Error::AccessDenied(_) => FfiError::PermissionDenied,
         crate::error::Error::Coordination(_) => FfiError::CoordinationError,
+        crate::error::Error::CollectionInProgress { .. } => FfiError::CoordinationError,
+        crate::error::Error::InvalidPageToken(_) => FfiError::InvalidPageToken,
+        crate::error::Error::NotFound(_) => FfiError::NotFound,
         _ => FfiError::DatabaseError,
     }
 }
@@ -128,6 +143,281 @@ pub unsafe extern "C" fn session_db_get_stats(
     }
 }
 
+/// 获取历史数据概览（JSON，结构同 `HistoryOverview`，字段为 camelCase）
+///
+/// # Safety
+/// `handle`, `out_json` 必须有效
+/// 返回的字符串需要调用 `session_db_free_string` 释放
+#[no_mangle]
+pub unsafe extern "C" fn session_db_history_overview(
+    handle: *const SessionDbHandle,
+    out_json: *mut *mut c_char,
+) -> FfiError {
+    if handle.is_null() || out_json.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*handle;
+        let overview = handle.db.history_overview().map_err(map_error)?;
+        serde_json::to_string(&overview).map_err(|_| FfiError::Unknown)
+    }));
+
+    match result {
+        Ok(Ok(json)) => match CString::new(json) {
+            Ok(s) => {
+                *out_json = s.into_raw();
+                FfiError::Success
+            }
+            Err(_) => FfiError::InvalidUtf8,
+        },
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
+
+/// 导入 claude.ai 数据导出（ZIP 或 `conversations.json`），结果为 JSON
+/// （结构同 `ImportReport`，字段为 camelCase）
+///
+/// - `match_projects`: 所属 claude.ai 项目与已有项目同名的对话归入已有项目
+///
+/// # Safety
+/// `handle`, `archive_path`, `out_json` 必须有效
+/// 返回的字符串需要调用 `session_db_free_string` 释放
+#[no_mangle]
+pub unsafe extern "C" fn session_db_import_claude_export(
+    handle: *const SessionDbHandle,
+    archive_path: *const c_char,
+    match_projects: bool,
+    out_json: *mut *mut c_char,
+) -> FfiError {
+    if handle.is_null() || archive_path.is_null() || out_json.is_null() {
+        return FfiError::NullPointer;
+    }
+    let archive_path = match CStr::from_ptr(archive_path).to_str() {
+        Ok(s) => PathBuf::from(s),
+        Err(_) => return FfiError::InvalidUtf8,
+    };
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*handle;
+        let options = crate::importer::ImportOptions { match_projects };
+        let report = crate::importer::import_claude_export(&handle.db, &archive_path, &options)
+            .map_err(map_error)?;
+        serde_json::to_string(&report).map_err(|_| FfiError::Unknown)
+    }));
+
+    match result {
+        Ok(Ok(json)) => match CString::new(json) {
+            Ok(s) => {
+                *out_json = s.into_raw();
+                FfiError::Success
+            }
+            Err(_) => FfiError::InvalidUtf8,
+        },
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
+
+// ==================== 会话分享 ====================
+
+/// 工具消息处理方式 C 枚举（对应 `ToolMessageMode`）
+/// 0 = Full (完整), 1 = Summarized (一行摘要), 2 = Omitted (不导出)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToolMessageModeC {
+    Full = 0,
+    Summarized = 1,
+    Omitted = 2,
+}
+
+impl From<ToolMessageModeC> for crate::share::ToolMessageMode {
+    fn from(mode: ToolMessageModeC) -> Self {
+        match mode {
+            ToolMessageModeC::Full => crate::share::ToolMessageMode::Full,
+            ToolMessageModeC::Summarized => crate::share::ToolMessageMode::Summarized,
+            ToolMessageModeC::Omitted => crate::share::ToolMessageMode::Omitted,
+        }
+    }
+}
+
+/// 创建会话分享包并写入 `out_path`（JSON，已存在时覆盖）
+///
+/// - `range_start` / `range_end`: sequence 范围（含两端），`range_start < 0` 表示整个会话
+/// - `redact`: 按默认的 `RedactionPolicy` 脱敏（密钥 token、用户主目录）
+/// - `full_project_path`: 包含项目完整路径（否则只含项目名称）
+///
+/// 会话不存在时返回 `NotFound`。
+///
+/// # Safety
+/// `handle`, `session_id`, `out_path` 必须有效
+#[no_mangle]
+pub unsafe extern "C" fn session_db_create_share_bundle(
+    handle: *const SessionDbHandle,
+    session_id: *const c_char,
+    range_start: i64,
+    range_end: i64,
+    tool_messages: ToolMessageModeC,
+    redact: bool,
+    full_project_path: bool,
+    out_path: *const c_char,
+) -> FfiError {
+    if handle.is_null() || session_id.is_null() || out_path.is_null() {
+        return FfiError::NullPointer;
+    }
+    let (session_id, out_path) = match (
+        CStr::from_ptr(session_id).to_str(),
+        CStr::from_ptr(out_path).to_str(),
+    ) {
+        (Ok(session_id), Ok(out_path)) => (session_id, PathBuf::from(out_path)),
+        _ => return FfiError::InvalidUtf8,
+    };
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*handle;
+        let options = crate::share::ShareOptions {
+            tool_messages: tool_messages.into(),
+            redaction: redact.then(crate::share::RedactionPolicy::default),
+            project_context: if full_project_path {
+                crate::share::ProjectContext::FullPath
+            } else {
+                crate::share::ProjectContext::NameOnly
+            },
+        };
+        let range = (range_start >= 0).then_some((range_start, range_end));
+        let json = handle
+            .db
+            .create_share_bundle(session_id, range, &options)
+            .and_then(|bundle| bundle.to_json())
+            .map_err(map_error)?;
+        std::fs::write(&out_path, json).map_err(|_| FfiError::Unknown)
+    }));
+
+    match result {
+        Ok(Ok(())) => FfiError::Success,
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
+
+/// 把分享包文件渲染为 Markdown
+///
+/// 文件不是有效的分享包时返回 `DatabaseError`（更新版本的分享包同样无法渲染）。
+///
+/// # Safety
+/// `bundle_path`, `out_markdown` 必须有效
+/// 返回的字符串需要调用 `session_db_free_string` 释放
+#[no_mangle]
+pub unsafe extern "C" fn session_db_render_share_bundle(
+    bundle_path: *const c_char,
+    out_markdown: *mut *mut c_char,
+) -> FfiError {
+    if bundle_path.is_null() || out_markdown.is_null() {
+        return FfiError::NullPointer;
+    }
+    let bundle_path = match CStr::from_ptr(bundle_path).to_str() {
+        Ok(s) => PathBuf::from(s),
+        Err(_) => return FfiError::InvalidUtf8,
+    };
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let json = std::fs::read_to_string(&bundle_path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => FfiError::NotFound,
+            _ => FfiError::Unknown,
+        })?;
+        let bundle = crate::share::ShareBundle::from_json(&json).map_err(map_error)?;
+        Ok(crate::share::render_share_bundle_markdown(&bundle))
+    }));
+
+    match result {
+        Ok(Ok(markdown)) => match CString::new(markdown) {
+            Ok(s) => {
+                *out_markdown = s.into_raw();
+                FfiError::Success
+            }
+            Err(_) => FfiError::InvalidUtf8,
+        },
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
+
+// ==================== 版本信息 ====================
+
+/// 获取库版本号（`VERSION_FULL`，格式 `{version}-{build_timestamp}`）
+///
+/// 返回的字符串需要调用 `session_db_free_string` 释放
+#[no_mangle]
+pub extern "C" fn session_db_version() -> *mut c_char {
+    CString::new(crate::VERSION_FULL)
+        .map(CString::into_raw)
+        .unwrap_or(std::ptr::null_mut())
+}
+
+/// 获取库编译时间戳（Unix 秒）
+#[no_mangle]
+pub extern "C" fn session_db_build_timestamp() -> u64 {
+    crate::BUILD_TIMESTAMP
+}
+
+/// 获取本库支持的 schema 版本
+///
+/// 数据库的 schema 版本大于此值时说明由更新版本的库写入，应提示升级。
+#[no_mangle]
+pub extern "C" fn session_db_supported_schema_version() -> i64 {
+    crate::migrations::SUPPORTED_SCHEMA_VERSION as i64
+}
+
+/// 获取数据库文件的 schema 版本
+///
+/// # Safety
+/// `handle`, `out_version` 必须有效
+#[no_mangle]
+pub unsafe extern "C" fn session_db_schema_version(
+    handle: *const SessionDbHandle,
+    out_version: *mut i64,
+) -> FfiError {
+    if handle.is_null() || out_version.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let handle = &*handle;
+    match handle.db.schema_version() {
+        Ok(version) => {
+            *out_version = version as i64;
+            FfiError::Success
+        }
+        Err(e) => map_error(e),
+    }
+}
+
+/// 获取全局变更计数
+///
+/// 每个提交了数据变更的写事务递增一次。与上次的值相同说明没有新数据，
+/// 可据此跳过重新查询。
+///
+/// # Safety
+/// `handle`, `out_counter` 必须有效
+#[no_mangle]
+pub unsafe extern "C" fn session_db_change_counter(
+    handle: *const SessionDbHandle,
+    out_counter: *mut u64,
+) -> FfiError {
+    if handle.is_null() || out_counter.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let handle = &*handle;
+    match handle.db.change_counter() {
+        Ok(counter) => {
+            *out_counter = counter;
+            FfiError::Success
+        }
+        Err(e) => map_error(e),
+    }
+}
+
 // ==================== Project CRUD ====================
 
 /// 获取或创建 Project
@@ -546,44 +836,10 @@ pub unsafe extern "C" fn session_db_insert_messages(
         };
 
         let messages_slice = std::slice::from_raw_parts(messages, message_count);
-        let mut rust_messages = Vec::new();
-
-        for msg in messages_slice {
-            let uuid = match CStr::from_ptr(msg.uuid).to_str() {
-                Ok(s) => s.to_string(),
-                Err(_) => return Err(FfiError::InvalidUtf8),
-            };
-            let content = match CStr::from_ptr(msg.content).to_str() {
-                Ok(s) => s.to_string(),
-                Err(_) => return Err(FfiError::InvalidUtf8),
-            };
-            let msg_type = match msg.role {
-                0 => MessageType::User,
-                1 => MessageType::Assistant,
-                2 => MessageType::Tool,
-                3 => MessageType::System,
-                _ => return Err(FfiError::Unknown),
-            };
-
-            // FFI 层简化：单一 content 映射到 content_text 和 content_full
-            rust_messages.push(MessageInput {
-                uuid,
-                r#type: msg_type,
-                content_text: content.clone(), // 向量化用
-                content_full: content,         // FTS 用
-                timestamp: msg.timestamp,
-                sequence: msg.sequence,
-                source: None,
-                channel: None,
-                model: None,
-                tool_call_id: None,
-                tool_name: None,
-                tool_args: None,
-                raw: None,
-                approval_status: None,
-                approval_resolved_at: None,
-            });
-        }
+        let rust_messages = messages_slice
+            .iter()
+            .map(|msg| message_input_from_c(msg))
+            .collect::<Result<Vec<_>, _>>()?;
 
         match handle.db.insert_messages(session_id_str, &rust_messages) {
             Ok((inserted, _)) => Ok(inserted),
@@ -603,13 +859,215 @@ pub unsafe extern "C" fn session_db_insert_messages(
     }
 }
 
+/// MessageInputC 转换为 MessageInput
+///
+/// FFI 层简化：单一 content 映射到 content_text（向量化用）和 content_full（FTS 用）。
+unsafe fn message_input_from_c(msg: &MessageInputC) -> Result<MessageInput, FfiError> {
+    if msg.uuid.is_null() || msg.content.is_null() {
+        return Err(FfiError::NullPointer);
+    }
+    let uuid = match CStr::from_ptr(msg.uuid).to_str() {
+        Ok(s) => s.to_string(),
+        Err(_) => return Err(FfiError::InvalidUtf8),
+    };
+    let content = match CStr::from_ptr(msg.content).to_str() {
+        Ok(s) => s.to_string(),
+        Err(_) => return Err(FfiError::InvalidUtf8),
+    };
+    let msg_type = match msg.role {
+        0 => MessageType::User,
+        1 => MessageType::Assistant,
+        2 => MessageType::Tool,
+        3 => MessageType::System,
+        _ => return Err(FfiError::Unknown),
+    };
+
+    Ok(MessageInput {
+        uuid,
+        r#type: msg_type,
+        content_text: content.clone(),
+        content_full: content,
+        timestamp: msg.timestamp,
+        sequence: msg.sequence,
+        source: None,
+        channel: None,
+        model: None,
+        tool_call_id: None,
+        tool_name: None,
+        tool_args: None,
+        raw: None,
+        approval_status: None,
+        approval_resolved_at: None,
+    })
+}
+
+// ==================== 分块写入 ====================
+
+/// 分块写入器（不透明句柄）
+///
+/// 外部生成的大量消息（如从其他工具导入的对话）分多次 push，每次一个事务，
+/// 只保留一块消息的缓冲区；finish 时更新一次会话计数。
+pub struct SessionDbWriter {
+    handle: *const SessionDbHandle,
+    session_id: String,
+    /// 当前块的转换缓冲区（push 之间复用，容量不随总写入量增长）
+    buffer: Vec<MessageInput>,
+    total_inserted: usize,
+    finished: bool,
+}
+
+impl SessionDbWriter {
+    /// 已插入的消息数（跨块去重后）
+    pub fn total_inserted(&self) -> usize {
+        self.total_inserted
+    }
+
+    /// 缓冲区容量（消息条数），由最大的一块决定
+    pub fn buffer_capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+}
+
+/// 开始分块写入
+///
+/// # Safety
+/// `handle`, `session_id`, `out_writer` 必须是有效指针；
+/// `handle` 在写入器释放（`session_db_writer_free`）之前必须保持有效
+#[no_mangle]
+pub unsafe extern "C" fn session_db_writer_begin(
+    handle: *const SessionDbHandle,
+    session_id: *const c_char,
+    out_writer: *mut *mut SessionDbWriter,
+) -> FfiError {
+    if handle.is_null() || session_id.is_null() || out_writer.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let session_id = match CStr::from_ptr(session_id).to_str() {
+        Ok(s) => s.to_string(),
+        Err(_) => return FfiError::InvalidUtf8,
+    };
+    let writer = Box::new(SessionDbWriter {
+        handle,
+        session_id,
+        buffer: Vec::new(),
+        total_inserted: 0,
+        finished: false,
+    });
+    *out_writer = Box::into_raw(writer);
+    FfiError::Success
+}
+
+/// 写入一块消息（单个事务，已存在的 uuid 跳过）
+///
+/// 可多次调用；finish 之后调用返回 `WriterFinished`。
+///
+/// # Safety
+/// `writer` 必须是 `session_db_writer_begin` 返回的有效写入器，
+/// `messages` 必须指向 `count` 个有效的 MessageInputC
+#[no_mangle]
+pub unsafe extern "C" fn session_db_writer_push(
+    writer: *mut SessionDbWriter,
+    messages: *const MessageInputC,
+    count: usize,
+) -> FfiError {
+    if writer.is_null() || (messages.is_null() && count > 0) {
+        return FfiError::NullPointer;
+    }
+    let writer = &mut *writer;
+    if writer.finished {
+        return FfiError::WriterFinished;
+    }
+    if count == 0 {
+        return FfiError::Success;
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*writer.handle;
+        writer.buffer.clear();
+        for msg in std::slice::from_raw_parts(messages, count) {
+            writer.buffer.push(message_input_from_c(msg)?);
+        }
+
+        let inserted = handle
+            .db
+            .insert_messages_deferred(&writer.session_id, &writer.buffer)
+            .map(|(inserted, _)| inserted)
+            .map_err(map_error);
+        // 释放本块的字符串，只保留缓冲区容量
+        writer.buffer.clear();
+        inserted
+    }));
+
+    match result {
+        Ok(Ok(inserted)) => {
+            writer.total_inserted += inserted;
+            FfiError::Success
+        }
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
+
+/// 结束分块写入：更新会话计数，输出总插入数
+///
+/// 写入器结束后仍需 `session_db_writer_free` 释放；重复调用返回 `WriterFinished`。
+///
+/// # Safety
+/// `writer` 必须是 `session_db_writer_begin` 返回的有效写入器
+#[no_mangle]
+pub unsafe extern "C" fn session_db_writer_finish(
+    writer: *mut SessionDbWriter,
+    out_total_inserted: *mut usize,
+) -> FfiError {
+    if writer.is_null() {
+        return FfiError::NullPointer;
+    }
+    let writer = &mut *writer;
+    if writer.finished {
+        return FfiError::WriterFinished;
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*writer.handle;
+        handle
+            .db
+            .refresh_session_counters(&writer.session_id)
+            .map_err(map_error)
+    }));
+
+    match result {
+        Ok(Ok(())) => {
+            writer.finished = true;
+            writer.buffer = Vec::new();
+            if !out_total_inserted.is_null() {
+                *out_total_inserted = writer.total_inserted;
+            }
+            FfiError::Success
+        }
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
+
+/// 释放分块写入器（未 finish 时已写入的块保留，会话计数不更新）
+///
+/// # Safety
+/// `writer` 必须是 `session_db_writer_begin` 返回的写入器，且只能释放一次
+#[no_mangle]
+pub unsafe extern "C" fn session_db_writer_free(writer: *mut SessionDbWriter) {
+    if !writer.is_null() {
+        drop(Box::from_raw(writer));
+    }
+}
+
 /// Message C 输出结构体
 #[repr(C)]
 pub struct MessageC {
     pub id: i64,
     pub session_id: *mut c_char,
     pub uuid: *mut c_char,
-    pub role: i32, // 0 = Human, 1 = Assistant
+    pub role: i32, // 0 = Human, 1 = Assistant, 2 = Tool, 3 = System, 4 = Summary
     pub content: *mut c_char,
     pub timestamp: i64,
     pub sequence: i64,
@@ -623,7 +1081,25 @@ pub struct MessageArray {
     pub len: usize,
 }
 
-/// 列出 Session 的 Messages
+/// 消息排序方向 C 枚举（对应 `reader::Order`）
+/// 0 = Asc (最早的在前), 1 = Desc (最新的在前)
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderC {
+    Asc = 0,
+    Desc = 1,
+}
+
+impl From<OrderC> for Order {
+    fn from(order: OrderC) -> Self {
+        match order {
+            OrderC::Asc => Order::Asc,
+            OrderC::Desc => Order::Desc,
+        }
+    }
+}
+
+/// 列出 Session 的 Messages（包含 raw）
 ///
 /// # Safety
 /// `handle`, `session_id` 必须是有效指针，返回的数组需要调用 `session_db_free_messages` 释放
@@ -635,73 +1111,228 @@ pub unsafe extern "C" fn session_db_list_messages(
     offset: usize,
     out_array: *mut *mut MessageArray,
 ) -> FfiError {
-    if handle.is_null() || session_id.is_null() || out_array.is_null() {
-        return FfiError::NullPointer;
-    }
+    session_db_list_messages_with_options(
+        handle,
+        session_id,
+        limit,
+        offset,
+        OrderC::Asc,
+        true,
+        out_array,
+    )
+}
 
-    let result = panic::catch_unwind(AssertUnwindSafe(|| {
-        let handle = &*handle;
-        let session_id_str = match CStr::from_ptr(session_id).to_str() {
+/// 列出 Session 的 Messages（可选排序方向、是否加载 raw）
+///
+/// - `order`: 排序方向，`offset` 从该方向的一端计数（Desc 时 0 表示最新一条）
+/// - `with_raw`: false 时不读取 raw 列，返回的 `raw` 为 null，需要时用 `session_db_get_message_raw` 单独获取
+///
+/// # Safety
+/// `handle`, `session_id` 必须是有效指针，返回的数组需要调用 `session_db_free_messages` 释放
+#[no_mangle]
+pub unsafe extern "C" fn session_db_list_messages_with_options(
+    handle: *const SessionDbHandle,
+    session_id: *const c_char,
+    limit: usize,
+    offset: usize,
+    order: OrderC,
+    with_raw: bool,
+    out_array: *mut *mut MessageArray,
+) -> FfiError {
+    if handle.is_null() || session_id.is_null() || out_array.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*handle;
+        let session_id_str = match CStr::from_ptr(session_id).to_str() {
             Ok(s) => s,
             Err(_) => return Err(FfiError::InvalidUtf8),
         };
-        match handle.db.list_messages(session_id_str, limit, offset) {
+        match handle.db.list_messages_ordered(
+            session_id_str,
+            boundary_limit(limit),
+            offset,
+            order.into(),
+            with_raw,
+        ) {
             Ok(messages) => Ok(messages),
             Err(_) => Err(FfiError::DatabaseError),
         }
     }));
 
     match result {
-        Ok(Ok(messages)) => {
-            let mut c_messages: Vec<MessageC> = Vec::new();
-            for m in messages {
-                let session_id = match CString::new(m.session_id) {
-                    Ok(s) => s.into_raw(),
-                    Err(_) => return FfiError::InvalidUtf8,
-                };
-                let uuid = match CString::new(m.uuid) {
-                    Ok(s) => s.into_raw(),
-                    Err(_) => return FfiError::InvalidUtf8,
-                };
-                // FFI 输出使用 content_full
-                let content = match CString::new(m.content_full) {
-                    Ok(s) => s.into_raw(),
-                    Err(_) => return FfiError::InvalidUtf8,
-                };
+        Ok(Ok(messages)) => write_message_array(messages, out_array),
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
 
-                let role = match m.r#type {
-                    MessageType::User => 0,
-                    MessageType::Assistant => 1,
-                    MessageType::Tool => 2,
-                    MessageType::System => 3,
-                };
+/// 读取可为 null 的分页 token
+unsafe fn read_page_token(
+    page_token: *const c_char,
+) -> Result<Option<crate::types::PageToken>, FfiError> {
+    if page_token.is_null() {
+        return Ok(None);
+    }
+    match CStr::from_ptr(page_token).to_str() {
+        Ok(s) => Ok(Some(crate::types::PageToken::from(s.to_string()))),
+        Err(_) => Err(FfiError::InvalidUtf8),
+    }
+}
 
-                let raw = match m.raw {
-                    Some(r) => match CString::new(r) {
-                        Ok(s) => s.into_raw(),
-                        Err(_) => std::ptr::null_mut(),
-                    },
-                    None => std::ptr::null_mut(),
-                };
+/// 分页列出 Session 的 Messages（按 sequence 升序）
+///
+/// - `page_token`: 上一页输出的 `out_next_token`，null 表示第一页
+/// - `out_next_token`: 下一页 token，已是最后一页时为 null，需要调用 `session_db_free_string` 释放
+///
+/// token 无效时返回 `InvalidPageToken`。
+///
+/// # Safety
+/// `handle`, `session_id` 必须是有效指针，`page_token` 可以为 null，
+/// 返回的数组需要调用 `session_db_free_messages` 释放
+#[no_mangle]
+pub unsafe extern "C" fn session_db_list_messages_page(
+    handle: *const SessionDbHandle,
+    session_id: *const c_char,
+    limit: usize,
+    with_raw: bool,
+    page_token: *const c_char,
+    out_array: *mut *mut MessageArray,
+    out_next_token: *mut *mut c_char,
+) -> FfiError {
+    if handle.is_null() || session_id.is_null() || out_array.is_null() || out_next_token.is_null() {
+        return FfiError::NullPointer;
+    }
 
-                c_messages.push(MessageC {
-                    id: m.id,
-                    session_id,
-                    uuid,
-                    role,
-                    content,
-                    timestamp: m.timestamp,
-                    sequence: m.sequence,
-                    raw,
-                });
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*handle;
+        let session_id_str = match CStr::from_ptr(session_id).to_str() {
+            Ok(s) => s,
+            Err(_) => return Err(FfiError::InvalidUtf8),
+        };
+        let token = read_page_token(page_token)?;
+        handle
+            .db
+            .list_messages_page(
+                session_id_str,
+                boundary_limit(limit),
+                with_raw,
+                token.as_ref(),
+            )
+            .map_err(map_error)
+    }));
+
+    match result {
+        Ok(Ok(page)) => {
+            let status = write_message_array(page.items, out_array);
+            if status == FfiError::Success {
+                *out_next_token =
+                    optional_c_string(page.next_page_token.as_ref().map(|t| t.as_str()));
             }
+            status
+        }
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
 
-            let len = c_messages.len();
-            let data = c_messages.as_mut_ptr();
-            std::mem::forget(c_messages);
+/// 将 Messages 转为 C 数组写入 `out_array`
+unsafe fn write_message_array(
+    messages: Vec<crate::types::Message>,
+    out_array: *mut *mut MessageArray,
+) -> FfiError {
+    let mut c_messages: Vec<MessageC> = Vec::new();
+    for m in messages {
+        let session_id = match CString::new(m.session_id) {
+            Ok(s) => s.into_raw(),
+            Err(_) => return FfiError::InvalidUtf8,
+        };
+        let uuid = match CString::new(m.uuid) {
+            Ok(s) => s.into_raw(),
+            Err(_) => return FfiError::InvalidUtf8,
+        };
+        // FFI 输出使用 content_full
+        let content = match CString::new(m.content_full) {
+            Ok(s) => s.into_raw(),
+            Err(_) => return FfiError::InvalidUtf8,
+        };
 
-            let array = Box::new(MessageArray { data, len });
-            *out_array = Box::into_raw(array);
+        let role = match m.r#type {
+            // compact 摘要条目以 system 类型存储
+            _ if m.is_summary => 4,
+            MessageType::User => 0,
+            MessageType::Assistant => 1,
+            MessageType::Tool => 2,
+            MessageType::System => 3,
+        };
+
+        let raw = match m.raw {
+            Some(r) => match CString::new(r) {
+                Ok(s) => s.into_raw(),
+                Err(_) => std::ptr::null_mut(),
+            },
+            None => std::ptr::null_mut(),
+        };
+
+        c_messages.push(MessageC {
+            id: m.id,
+            session_id,
+            uuid,
+            role,
+            content,
+            timestamp: m.timestamp,
+            sequence: m.sequence,
+            raw,
+        });
+    }
+
+    let len = c_messages.len();
+    let data = c_messages.as_mut_ptr();
+    std::mem::forget(c_messages);
+
+    let array = Box::new(MessageArray { data, len });
+    *out_array = Box::into_raw(array);
+    FfiError::Success
+}
+
+/// 按 uuid 获取消息的 raw（原始 JSONL）
+///
+/// # Safety
+/// `handle`, `uuid` 必须有效，`out_raw` 为 null 表示消息不存在或没有 raw
+/// 返回的字符串需要调用 `session_db_free_string` 释放
+#[no_mangle]
+pub unsafe extern "C" fn session_db_get_message_raw(
+    handle: *const SessionDbHandle,
+    uuid: *const c_char,
+    out_raw: *mut *mut c_char,
+) -> FfiError {
+    if handle.is_null() || uuid.is_null() || out_raw.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*handle;
+        let uuid_str = match CStr::from_ptr(uuid).to_str() {
+            Ok(s) => s,
+            Err(_) => return Err(FfiError::InvalidUtf8),
+        };
+        match handle.db.get_message_raw(uuid_str) {
+            Ok(raw) => Ok(raw),
+            Err(_) => Err(FfiError::DatabaseError),
+        }
+    }));
+
+    match result {
+        Ok(Ok(Some(raw))) => match CString::new(raw) {
+            Ok(s) => {
+                *out_raw = s.into_raw();
+                FfiError::Success
+            }
+            Err(_) => FfiError::InvalidUtf8,
+        },
+        Ok(Ok(None)) => {
+            *out_raw = std::ptr::null_mut();
             FfiError::Success
         }
         Ok(Err(e)) => e,
@@ -709,10 +1340,46 @@ pub unsafe extern "C" fn session_db_list_messages(
     }
 }
 
+/// 按 ID 列表获取消息（包含 raw，按 ID 升序，不存在的 ID 被跳过）
+///
+/// 用于取不含正文的搜索命中（`include_content = false`）的正文。
+///
+/// # Safety
+/// `handle` 必须是有效指针，`ids` 指向 `ids_len` 个 i64（`ids_len` 为 0 时可以为 null），
+/// 返回的数组需要调用 `session_db_free_messages` 释放
+#[no_mangle]
+pub unsafe extern "C" fn session_db_get_messages_by_ids(
+    handle: *const SessionDbHandle,
+    ids: *const i64,
+    ids_len: usize,
+    out_array: *mut *mut MessageArray,
+) -> FfiError {
+    if handle.is_null() || (ids.is_null() && ids_len > 0) || out_array.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*handle;
+        let ids = if ids_len == 0 {
+            &[][..]
+        } else {
+            std::slice::from_raw_parts(ids, ids_len)
+        };
+        handle.db.get_messages_by_ids(ids).map_err(map_error)
+    }));
+
+    match result {
+        Ok(Ok(messages)) => write_message_array(messages, out_array),
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
+
 /// 释放 Messages 数组
 ///
 /// # Safety
-/// `array` 必须是 `session_db_list_messages` 返回的有效指针
+/// `array` 必须是 `session_db_list_messages` / `session_db_list_messages_with_options` /
+/// `session_db_get_turn_messages` / `session_db_get_messages_by_ids` 返回的有效指针
 #[no_mangle]
 pub unsafe extern "C" fn session_db_free_messages(array: *mut MessageArray) {
     if array.is_null() {
@@ -811,7 +1478,7 @@ pub unsafe extern "C" fn session_db_search_fts(
             Err(_) => return Err(FfiError::InvalidUtf8),
         };
         let escaped_query = escape_fts5_query(query_str);
-        match handle.db.search_fts(&escaped_query, limit) {
+        match handle.db.search_fts(&escaped_query, boundary_limit(limit)) {
             Ok(results) => Ok(results),
             Err(_) => Err(FfiError::DatabaseError),
         }
@@ -900,7 +1567,7 @@ pub unsafe extern "C" fn session_db_search_fts_with_project(
         };
         match handle
             .db
-            .search_fts_with_project(&escaped_query, limit, pid)
+            .search_fts_with_project(&escaped_query, boundary_limit(limit), pid)
         {
             Ok(results) => Ok(results),
             Err(_) => Err(FfiError::DatabaseError),
@@ -971,22 +1638,27 @@ pub unsafe extern "C" fn session_db_free_search_results(array: *mut SearchResult
 
     let array = Box::from_raw(array);
     let results = Vec::from_raw_parts(array.data, array.len, array.len);
-    for r in results {
-        if !r.session_id.is_null() {
-            drop(CString::from_raw(r.session_id));
-        }
-        if !r.project_name.is_null() {
-            drop(CString::from_raw(r.project_name));
-        }
-        if !r.role.is_null() {
-            drop(CString::from_raw(r.role));
-        }
-        if !r.content.is_null() {
-            drop(CString::from_raw(r.content));
-        }
-        if !r.snippet.is_null() {
-            drop(CString::from_raw(r.snippet));
-        }
+    for r in &results {
+        free_search_result_c(r);
+    }
+}
+
+/// 释放 SearchResultC 内部的字符串
+unsafe fn free_search_result_c(r: &SearchResultC) {
+    if !r.session_id.is_null() {
+        drop(CString::from_raw(r.session_id));
+    }
+    if !r.project_name.is_null() {
+        drop(CString::from_raw(r.project_name));
+    }
+    if !r.role.is_null() {
+        drop(CString::from_raw(r.role));
+    }
+    if !r.content.is_null() {
+        drop(CString::from_raw(r.content));
+    }
+    if !r.snippet.is_null() {
+        drop(CString::from_raw(r.snippet));
     }
 }
 
@@ -1000,12 +1672,15 @@ pub unsafe extern "C" fn session_db_free_search_results(array: *mut SearchResult
 /// - `order_by`: 排序方式（0=Score, 1=TimeDesc, 2=TimeAsc）
 /// - `start_timestamp`: 开始时间戳（毫秒，-1 表示不过滤）
 /// - `end_timestamp`: 结束时间戳（毫秒，-1 表示不过滤）
+/// - `max_per_project`: 每个项目的配额（0 表示不限制），配额内的命中优先，limit 未满时再补充
+/// - `include_content`: false 时 `content` 为空字符串，正文用 `session_db_get_messages_by_ids` 按需获取
 /// - `out_array`: 输出搜索结果数组
 ///
 /// # Safety
 /// `handle`, `query` 必须是有效指针，返回的数组需要调用 `session_db_free_search_results` 释放
 #[cfg(feature = "fts")]
 #[no_mangle]
+#[allow(clippy::too_many_arguments)]
 pub unsafe extern "C" fn session_db_search_fts_full(
     handle: *const SessionDbHandle,
     query: *const c_char,
@@ -1014,6 +1689,8 @@ pub unsafe extern "C" fn session_db_search_fts_full(
     order_by: SearchOrderByC,
     start_timestamp: i64,
     end_timestamp: i64,
+    max_per_project: usize,
+    include_content: bool,
     out_array: *mut *mut SearchResultArray,
 ) -> FfiError {
     if handle.is_null() || query.is_null() || out_array.is_null() {
@@ -1027,25 +1704,18 @@ pub unsafe extern "C" fn session_db_search_fts_full(
             Err(_) => return Err(FfiError::InvalidUtf8),
         };
         let escaped_query = escape_fts5_query(query_str);
-        let pid = if project_id >= 0 {
-            Some(project_id)
-        } else {
-            None
-        };
-        let start_ts = if start_timestamp >= 0 {
-            Some(start_timestamp)
-        } else {
-            None
-        };
-        let end_ts = if end_timestamp >= 0 {
-            Some(end_timestamp)
-        } else {
-            None
+        let options = crate::types::SearchGroupOptions {
+            project_id: (project_id >= 0).then_some(project_id),
+            order_by: order_by.into(),
+            start_timestamp: (start_timestamp >= 0).then_some(start_timestamp),
+            end_timestamp: (end_timestamp >= 0).then_some(end_timestamp),
+            include_content: Some(include_content),
+            ..Default::default()
         };
-        let order: crate::types::SearchOrderBy = order_by.into();
+        let quota = (max_per_project > 0).then_some(max_per_project);
         match handle
             .db
-            .search_fts_full(&escaped_query, limit, pid, order, start_ts, end_ts)
+            .search_fts_filtered(&escaped_query, boundary_limit(limit), &options, quota)
         {
             Ok(results) => Ok(results),
             Err(_) => Err(FfiError::DatabaseError),
@@ -1103,6 +1773,172 @@ pub unsafe extern "C" fn session_db_search_fts_full(
     }
 }
 
+/// FTS 全文搜索，日期范围按本地日历计算（其余参数同 `session_db_search_fts_full`）
+///
+/// - `date_range`: `today`、`last{N}days`（如 `last7days`）、`thisWeek`、`thisMonth` 或 `YYYY-MM`
+/// - `tz_offset_minutes`: 相对 UTC 的分钟偏移（如 `TimeZone.current.secondsFromGMT() / 60`）
+/// - `week_starts_monday`: `thisWeek` 是否从周一开始（false 为周日）
+///
+/// `date_range` 无法解析时返回 `InvalidArgument`。
+///
+/// # Safety
+/// `handle`, `query`, `date_range` 必须是有效指针，返回的数组需要调用
+/// `session_db_free_search_results` 释放
+#[cfg(feature = "fts")]
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn session_db_search_fts_in_range(
+    handle: *const SessionDbHandle,
+    query: *const c_char,
+    limit: usize,
+    project_id: i64,
+    order_by: SearchOrderByC,
+    date_range: *const c_char,
+    tz_offset_minutes: i32,
+    week_starts_monday: bool,
+    max_per_project: usize,
+    include_content: bool,
+    out_array: *mut *mut SearchResultArray,
+) -> FfiError {
+    if handle.is_null() || query.is_null() || date_range.is_null() || out_array.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*handle;
+        let query_str = CStr::from_ptr(query)
+            .to_str()
+            .map_err(|_| FfiError::InvalidUtf8)?;
+        let date_range: crate::types::DateRangeSpec = CStr::from_ptr(date_range)
+            .to_str()
+            .map_err(|_| FfiError::InvalidUtf8)?
+            .parse()
+            .map_err(|_| FfiError::InvalidArgument)?;
+        let options = crate::types::SearchGroupOptions {
+            project_id: (project_id >= 0).then_some(project_id),
+            order_by: order_by.into(),
+            include_content: Some(include_content),
+            date_range: Some(date_range),
+            tz_offset_minutes: Some(tz_offset_minutes),
+            week_starts_monday,
+            ..Default::default()
+        };
+        let quota = (max_per_project > 0).then_some(max_per_project);
+        let results = handle
+            .db
+            .search_fts_filtered(
+                &escape_fts5_query(query_str),
+                boundary_limit(limit),
+                &options,
+                quota,
+            )
+            .map_err(map_error)?;
+        let mut c_results: Vec<SearchResultC> = Vec::with_capacity(results.len());
+        for r in &results {
+            c_results.push(search_result_to_c(r).ok_or(FfiError::InvalidUtf8)?);
+        }
+        Ok(c_results)
+    }));
+
+    match result {
+        Ok(Ok(mut c_results)) => {
+            let len = c_results.len();
+            let data = c_results.as_mut_ptr();
+            std::mem::forget(c_results);
+
+            let array = Box::new(SearchResultArray { data, len });
+            *out_array = Box::into_raw(array);
+            FfiError::Success
+        }
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
+
+/// FTS 全文搜索的一页（参数同 `session_db_search_fts_full`）
+///
+/// - `page_token`: 上一页输出的 `out_next_token`，null 表示第一页
+/// - `out_next_token`: 下一页 token，已是最后一页时为 null，需要调用 `session_db_free_string` 释放
+///
+/// token 无效（或换了搜索条件）时返回 `InvalidPageToken`。
+///
+/// # Safety
+/// `handle`, `query` 必须是有效指针，`page_token` 可以为 null，
+/// 返回的数组需要调用 `session_db_free_search_results` 释放
+#[cfg(feature = "fts")]
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn session_db_search_fts_page(
+    handle: *const SessionDbHandle,
+    query: *const c_char,
+    limit: usize,
+    project_id: i64,
+    order_by: SearchOrderByC,
+    start_timestamp: i64,
+    end_timestamp: i64,
+    max_per_project: usize,
+    include_content: bool,
+    page_token: *const c_char,
+    out_array: *mut *mut SearchResultArray,
+    out_next_token: *mut *mut c_char,
+) -> FfiError {
+    if handle.is_null() || query.is_null() || out_array.is_null() || out_next_token.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*handle;
+        let query_str = match CStr::from_ptr(query).to_str() {
+            Ok(s) => s,
+            Err(_) => return Err(FfiError::InvalidUtf8),
+        };
+        let token = read_page_token(page_token)?;
+        let options = crate::types::SearchGroupOptions {
+            project_id: (project_id >= 0).then_some(project_id),
+            order_by: order_by.into(),
+            start_timestamp: (start_timestamp >= 0).then_some(start_timestamp),
+            end_timestamp: (end_timestamp >= 0).then_some(end_timestamp),
+            include_content: Some(include_content),
+            ..Default::default()
+        };
+        let quota = (max_per_project > 0).then_some(max_per_project);
+        handle
+            .db
+            .search_fts_page(
+                &escape_fts5_query(query_str),
+                boundary_limit(limit),
+                &options,
+                quota,
+                token.as_ref(),
+            )
+            .map_err(map_error)
+    }));
+
+    match result {
+        Ok(Ok(page)) => {
+            let Some(mut c_results) = page
+                .items
+                .iter()
+                .map(search_result_to_c)
+                .collect::<Option<Vec<_>>>()
+            else {
+                return FfiError::InvalidUtf8;
+            };
+            *out_next_token = optional_c_string(page.next_page_token.as_ref().map(|t| t.as_str()));
+
+            let len = c_results.len();
+            let data = c_results.as_mut_ptr();
+            std::mem::forget(c_results);
+
+            let array = Box::new(SearchResultArray { data, len });
+            *out_array = Box::into_raw(array);
+            FfiError::Success
+        }
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
+
 /// FTS 全文搜索（完整参数版本，支持项目过滤和排序）
 ///
 /// # 参数
@@ -1144,7 +1980,7 @@ pub unsafe extern "C" fn session_db_search_fts_with_options(
         let order: crate::types::SearchOrderBy = order_by.into();
         match handle
             .db
-            .search_fts_with_options(&escaped_query, limit, pid, order)
+            .search_fts_with_options(&escaped_query, boundary_limit(limit), pid, order)
         {
             Ok(results) => Ok(results),
             Err(_) => Err(FfiError::DatabaseError),
@@ -1202,13 +2038,239 @@ pub unsafe extern "C" fn session_db_search_fts_with_options(
     }
 }
 
-// ==================== 审批操作 ====================
+// ==================== 分组搜索 ====================
 
-/// 审批状态 C 枚举
-/// 0 = Pending, 1 = Approved, 2 = Rejected, 3 = Timeout
+/// 按会话分组的搜索结果 C 结构体
 #[repr(C)]
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum ApprovalStatusC {
+pub struct SessionSearchGroupC {
+    pub session_id: *mut c_char,
+    pub project_id: i64,
+    pub project_name: *mut c_char,
+    pub project_path: *mut c_char,
+    pub message_count: i64,
+    pub last_message_at: i64, // -1 表示 NULL
+    pub hit_count: i64,
+    pub best_score: f64,
+    pub hits: SearchResultArray,
+}
+
+/// C 数组 wrapper
+#[repr(C)]
+pub struct SessionSearchGroupArray {
+    pub data: *mut SessionSearchGroupC,
+    pub len: usize,
+}
+
+/// 将 Rust SearchResult 转为 C 结构体
+fn search_result_to_c(r: &crate::types::SearchResult) -> Option<SearchResultC> {
+    Some(SearchResultC {
+        message_id: r.message_id,
+        session_id: CString::new(r.session_id.clone()).ok()?.into_raw(),
+        project_id: r.project_id,
+        project_name: CString::new(r.project_name.clone()).ok()?.into_raw(),
+        role: CString::new(r.r#type.clone()).ok()?.into_raw(),
+        content: CString::new(r.content_full.clone()).ok()?.into_raw(),
+        snippet: CString::new(r.snippet.clone()).ok()?.into_raw(),
+        score: r.score,
+        timestamp: r.timestamp.unwrap_or(-1),
+    })
+}
+
+/// 将 Rust SessionSearchGroup 转为 C 结构体
+fn search_group_to_c(g: &crate::types::SessionSearchGroup) -> Option<SessionSearchGroupC> {
+    let mut hits: Vec<SearchResultC> = Vec::with_capacity(g.hits.len());
+    for hit in &g.hits {
+        hits.push(search_result_to_c(hit)?);
+    }
+    let session = &g.session;
+    let session_id = CString::new(session.session_id.clone()).ok()?.into_raw();
+    let project_name = CString::new(session.project_name.clone()).ok()?.into_raw();
+    let project_path = CString::new(session.project_path.clone()).ok()?.into_raw();
+
+    let hits_len = hits.len();
+    let hits_data = hits.as_mut_ptr();
+    std::mem::forget(hits);
+
+    Some(SessionSearchGroupC {
+        session_id,
+        project_id: session.project_id,
+        project_name,
+        project_path,
+        message_count: session.message_count,
+        last_message_at: session.last_message_at.unwrap_or(-1),
+        hit_count: g.hit_count,
+        best_score: g.best_score,
+        hits: SearchResultArray {
+            data: hits_data,
+            len: hits_len,
+        },
+    })
+}
+
+/// FTS 全文搜索，按会话分组
+///
+/// # 参数
+/// - `handle`: 数据库句柄
+/// - `query`: 搜索关键词
+/// - `session_limit`: 返回的会话数
+/// - `per_session_limit`: 每个会话返回的命中数
+/// - `project_id`: 项目 ID（-1 表示不过滤）
+/// - `order_by`: 分组排序方式（0=最佳分数, 1=最新命中, 2=最早命中）
+/// - `include_content`: false 时命中的 `content` 为空字符串（同 `session_db_search_fts_full`）
+/// - `out_array`: 输出分组数组
+///
+/// # Safety
+/// `handle`, `query` 必须是有效指针，返回的数组需要调用 `session_db_free_search_groups` 释放
+#[cfg(feature = "fts")]
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn session_db_search_fts_grouped(
+    handle: *const SessionDbHandle,
+    query: *const c_char,
+    session_limit: usize,
+    per_session_limit: usize,
+    project_id: i64,
+    order_by: SearchOrderByC,
+    include_content: bool,
+    out_array: *mut *mut SessionSearchGroupArray,
+) -> FfiError {
+    if handle.is_null() || query.is_null() || out_array.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*handle;
+        let query_str = match CStr::from_ptr(query).to_str() {
+            Ok(s) => s,
+            Err(_) => return Err(FfiError::InvalidUtf8),
+        };
+        let pid = if project_id >= 0 {
+            Some(project_id)
+        } else {
+            None
+        };
+        let options = crate::types::SearchGroupOptions {
+            project_id: pid,
+            order_by: order_by.into(),
+            include_content: Some(include_content),
+            ..Default::default()
+        };
+        match handle.db.search_fts_grouped(
+            query_str,
+            boundary_limit(session_limit),
+            boundary_limit(per_session_limit),
+            &options,
+        ) {
+            Ok(groups) => Ok(groups),
+            Err(_) => Err(FfiError::DatabaseError),
+        }
+    }));
+
+    match result {
+        Ok(Ok(groups)) => {
+            let mut c_groups: Vec<SessionSearchGroupC> = Vec::new();
+            for g in &groups {
+                match search_group_to_c(g) {
+                    Some(c) => c_groups.push(c),
+                    None => return FfiError::InvalidUtf8,
+                }
+            }
+
+            let len = c_groups.len();
+            let data = c_groups.as_mut_ptr();
+            std::mem::forget(c_groups);
+
+            let array = Box::new(SessionSearchGroupArray { data, len });
+            *out_array = Box::into_raw(array);
+            FfiError::Success
+        }
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
+
+/// 释放分组搜索结果
+///
+/// # Safety
+/// `array` 必须是 `session_db_search_fts_grouped` 返回的有效指针
+#[no_mangle]
+pub unsafe extern "C" fn session_db_free_search_groups(array: *mut SessionSearchGroupArray) {
+    if array.is_null() {
+        return;
+    }
+
+    let array = Box::from_raw(array);
+    let groups = Vec::from_raw_parts(array.data, array.len, array.len);
+    for g in groups {
+        if !g.session_id.is_null() {
+            drop(CString::from_raw(g.session_id));
+        }
+        if !g.project_name.is_null() {
+            drop(CString::from_raw(g.project_name));
+        }
+        if !g.project_path.is_null() {
+            drop(CString::from_raw(g.project_path));
+        }
+        let hits = Vec::from_raw_parts(g.hits.data, g.hits.len, g.hits.len);
+        for hit in &hits {
+            free_search_result_c(hit);
+        }
+    }
+}
+
+/// 最近的搜索（JSON 数组，结构同 `SearchLogEntry`，字段为 camelCase，按时间倒序）
+///
+/// - `limit`: 0 表示不限制（最多 `MAX_QUERY_LIMIT` 条）
+/// - `zero_results`: 为 true 时只返回没有结果的搜索
+///
+/// 只有启用了 `DbConfig::log_searches` 的写入方（如 Agent）才会产生记录。
+///
+/// # Safety
+/// `handle`, `out_json` 必须有效
+/// 返回的字符串需要调用 `session_db_free_string` 释放
+#[no_mangle]
+pub unsafe extern "C" fn session_db_recent_searches(
+    handle: *const SessionDbHandle,
+    limit: usize,
+    zero_results: bool,
+    out_json: *mut *mut c_char,
+) -> FfiError {
+    if handle.is_null() || out_json.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*handle;
+        let limit = boundary_limit(limit);
+        let searches = if zero_results {
+            handle.db.zero_result_searches(limit)
+        } else {
+            handle.db.recent_searches(limit)
+        };
+        let searches = searches.map_err(map_error)?;
+        serde_json::to_string(&searches).map_err(|_| FfiError::Unknown)
+    }));
+
+    match result {
+        Ok(Ok(json)) => match CString::new(json) {
+            Ok(s) => {
+                *out_json = s.into_raw();
+                FfiError::Success
+            }
+            Err(_) => FfiError::InvalidUtf8,
+        },
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
+
+// ==================== 审批操作 ====================
+
+/// 审批状态 C 枚举
+/// 0 = Pending, 1 = Approved, 2 = Rejected, 3 = Timeout
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ApprovalStatusC {
     Pending = 0,
     Approved = 1,
     Rejected = 2,
@@ -1238,6 +2300,46 @@ impl From<ApprovalStatusC> for crate::protocol::ApprovalStatus {
     }
 }
 
+/// 按项目汇总待审批的消息（JSON 数组，结构同 `ApprovalDigestGroup`，字段为 camelCase）
+///
+/// - `older_than_ms`: 只统计已等待超过该时长（毫秒）的消息，<= 0 表示不过滤
+///
+/// # Safety
+/// `handle`, `out_json` 必须有效
+/// 返回的字符串需要调用 `session_db_free_string` 释放
+#[no_mangle]
+pub unsafe extern "C" fn session_db_pending_approval_digest(
+    handle: *const SessionDbHandle,
+    older_than_ms: i64,
+    out_json: *mut *mut c_char,
+) -> FfiError {
+    if handle.is_null() || out_json.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*handle;
+        let older_than_ms = (older_than_ms > 0).then_some(older_than_ms);
+        let digest = handle
+            .db
+            .pending_approval_digest(older_than_ms)
+            .map_err(map_error)?;
+        serde_json::to_string(&digest).map_err(|_| FfiError::Unknown)
+    }));
+
+    match result {
+        Ok(Ok(json)) => match CString::new(json) {
+            Ok(s) => {
+                *out_json = s.into_raw();
+                FfiError::Success
+            }
+            Err(_) => FfiError::InvalidUtf8,
+        },
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
+
 // ==================== 辅助函数 ====================
 
 /// 释放 C 字符串
@@ -1738,10 +2840,11 @@ pub struct ProjectInfoArray {
 /// 列出所有项目（从文件系统）
 ///
 /// 会话数量不包含 agent session。
+/// 无权访问 projects 目录时返回 `PermissionDenied`（macOS 需要授予完全磁盘访问权限）。
 ///
 /// # 参数
 /// - `projects_path`: Claude projects 目录路径，null 使用默认路径 (~/.claude/projects)
-/// - `limit`: 最大返回数量，0 表示不限制
+/// - `limit`: 最大返回数量，0 表示不限制（最多 `MAX_QUERY_LIMIT` 个）
 ///
 /// # Safety
 /// - 返回的数组需要调用 `session_db_free_project_list` 释放
@@ -1769,12 +2872,8 @@ pub unsafe extern "C" fn session_db_list_file_projects(
 
         // 使用 SessionReader 统一的业务逻辑
         let mut reader = SessionReader::new(path);
-        let limit_opt = if limit > 0 {
-            Some(limit as usize)
-        } else {
-            None
-        };
-        let projects = reader.list_projects(limit_opt);
+        let limit = boundary_limit(limit as usize);
+        let projects = reader.list_projects(Some(limit)).map_err(map_error)?;
 
         Ok(projects)
     }));
@@ -1858,6 +2957,62 @@ pub unsafe extern "C" fn session_db_free_project_list(array: *mut ProjectInfoArr
     }
 }
 
+/// 数据库与 projects 目录对账后的项目列表（JSON 数组，结构同 `ReconciledProject`，
+/// 字段为 camelCase，按最后活跃时间排序）
+///
+/// `inDb` / `onDisk` 区分已归档（JSONL 已删除）和尚未采集的项目。
+/// 无权访问 projects 目录时返回 `PermissionDenied`。
+///
+/// # 参数
+/// - `projects_path`: Claude projects 目录路径，null 使用默认路径 (~/.claude/projects)
+/// - `limit`: 最大返回数量，0 表示不限制（最多 `MAX_QUERY_LIMIT` 个）
+///
+/// # Safety
+/// `handle`, `out_json` 必须有效
+/// 返回的字符串需要调用 `session_db_free_string` 释放
+#[no_mangle]
+pub unsafe extern "C" fn session_db_list_projects_reconciled(
+    handle: *const SessionDbHandle,
+    projects_path: *const c_char,
+    limit: usize,
+    offset: usize,
+    out_json: *mut *mut c_char,
+) -> FfiError {
+    if handle.is_null() || out_json.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*handle;
+        let path = if projects_path.is_null() {
+            let home = dirs::home_dir().ok_or(FfiError::Unknown)?;
+            home.join(".claude/projects")
+        } else {
+            let path_str = CStr::from_ptr(projects_path)
+                .to_str()
+                .map_err(|_| FfiError::InvalidUtf8)?;
+            PathBuf::from(path_str)
+        };
+        let projects = handle
+            .db
+            .list_projects_reconciled(&path, boundary_limit(limit), offset)
+            .map_err(map_error)?;
+        serde_json::to_string(&projects).map_err(|_| FfiError::Unknown)
+    }));
+
+    match result {
+        Ok(Ok(json)) => match CString::new(json) {
+            Ok(s) => {
+                *out_json = s.into_raw();
+                FfiError::Success
+            }
+            Err(_) => FfiError::InvalidUtf8,
+        },
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
+
 /// SessionMetaC 结构体
 #[repr(C)]
 pub struct SessionMetaC {
@@ -1886,6 +3041,7 @@ pub struct SessionMetaArray {
 /// 列出会话
 ///
 /// 默认过滤 agent session (agent-xxx)。
+/// 无权访问 projects 目录时返回 `PermissionDenied`（macOS 需要授予完全磁盘访问权限）。
 ///
 /// # 参数
 /// - `projects_path`: Claude projects 目录路径，null 使用默认路径
@@ -1928,7 +3084,9 @@ pub unsafe extern "C" fn session_db_list_session_metas(
         // 使用 SessionReader 统一的业务逻辑（默认过滤 agent session）
         // 使用 with_preview 版本获取最后消息预览
         let mut reader = SessionReader::new(path);
-        let sessions = reader.list_sessions_with_preview(filter_project, false); // include_agents = false
+        let sessions = reader
+            .list_sessions_with_preview(filter_project, false) // include_agents = false
+            .map_err(map_error)?;
 
         Ok(sessions)
     }));
@@ -2237,9 +3395,12 @@ pub struct MessagesResultC {
 ///
 /// # 参数
 /// - `session_path`: 会话文件完整路径
-/// - `limit`: 每页消息数
-/// - `offset`: 偏移量
-/// - `order_asc`: true 升序，false 降序
+/// - `limit`: 每页消息数，0 表示不限制（最多 `MAX_QUERY_LIMIT` 条）
+/// - `offset`: 偏移量，从 `order` 所选的一端计数（降序时 0 表示最新一条）
+/// - `order`: 排序方向（页内顺序与之一致）
+///
+/// 按相同方向递增 `offset` 翻页，每条消息恰好出现一次；
+/// `has_more` 为 false 表示该方向上已是最后一页，`total` 为会话消息总数。
 ///
 /// # Safety
 /// - 返回的结果需要调用 `session_db_free_messages_result` 释放
@@ -2248,7 +3409,7 @@ pub unsafe extern "C" fn session_db_read_session_messages(
     session_path: *const c_char,
     limit: usize,
     offset: usize,
-    order_asc: bool,
+    order: OrderC,
     out_result: *mut *mut MessagesResultC,
 ) -> FfiError {
     if session_path.is_null() || out_result.is_null() {
@@ -2302,28 +3463,20 @@ pub unsafe extern "C" fn session_db_read_session_messages(
             .map_err(|_| FfiError::DatabaseError)?;
 
         let Some(parse_result) = parse_result else {
-            return Ok((Vec::new(), 0));
+            return Ok((Vec::new(), 0, false));
         };
 
-        let mut all_messages = parse_result.messages;
-        let total = all_messages.len();
-
-        // 排序
-        if !order_asc {
-            all_messages.reverse();
-        }
-
-        // 分页（limit=0 表示无限制）
-        let start = offset.min(total);
-        let actual_limit = if limit == 0 { usize::MAX } else { limit };
-        let end = start.saturating_add(actual_limit).min(total);
-        let messages: Vec<_> = all_messages[start..end].to_vec();
-
-        Ok((messages, total))
+        // 分页（offset 从所选方向的一端计数）
+        Ok(crate::reader::paginate(
+            parse_result.messages,
+            Some(boundary_limit(limit)),
+            offset,
+            order.into(),
+        ))
     }));
 
     match result {
-        Ok(Ok((messages, total))) => {
+        Ok(Ok((messages, total, has_more))) => {
             let mut c_messages: Vec<ParsedMessageC> = Vec::new();
             for m in messages {
                 let uuid_c = match CString::new(m.uuid) {
@@ -2370,8 +3523,8 @@ pub unsafe extern "C" fn session_db_read_session_messages(
                 });
             }
 
+            // has_more 以分页结果为准，不受个别消息转换失败影响
             let message_count = c_messages.len();
-            let has_more = offset + message_count < total;
 
             let messages_ptr = if message_count > 0 {
                 let ptr = c_messages.as_mut_ptr();
@@ -2438,11 +3591,42 @@ pub struct CollectResultC {
     pub error_count: usize,
     /// 第一个错误信息（如果有）
     pub first_error: *mut c_char,
+    /// 第一个错误的会话文件路径（没有错误或路径未知时为 NULL）
+    pub first_error_path: *mut c_char,
+    /// 第一个错误的阶段：discover / parse / convert / insert（没有错误时为 NULL）
+    pub first_error_stage: *mut c_char,
+}
+
+/// 可选字符串转为 C 字符串（None 或含 NUL 时为 NULL）
+fn optional_c_string(s: Option<&str>) -> *mut c_char {
+    s.and_then(|s| CString::new(s).ok())
+        .map(|s| s.into_raw())
+        .unwrap_or(std::ptr::null_mut())
+}
+
+/// 将 Rust CollectResult 转为 C 结构体
+fn collect_result_to_c(r: &crate::collector::CollectResult) -> CollectResultC {
+    let first = r.errors.first();
+    let first_error = first.map(|e| e.to_string());
+    let first_error_path = first
+        .filter(|e| !e.path.as_os_str().is_empty())
+        .map(|e| e.path.to_string_lossy().into_owned());
+    CollectResultC {
+        projects_scanned: r.projects_scanned,
+        sessions_scanned: r.sessions_scanned,
+        messages_inserted: r.messages_inserted,
+        error_count: r.errors.len(),
+        first_error: optional_c_string(first_error.as_deref()),
+        first_error_path: optional_c_string(first_error_path.as_deref()),
+        first_error_stage: optional_c_string(first.map(|e| e.stage.as_str())),
+    }
 }
 
 /// 执行全量采集
 ///
 /// 扫描所有 CLI 会话文件（Claude、OpenCode、Codex 等），增量写入数据库。
+/// 有数据目录无权访问时返回 `PermissionDenied`，不输出结果（可访问的目录仍会正常采集）。
+/// 其他进程正在采集（持有采集锁）时返回 `CoordinationError`，稍后重试。
 ///
 /// # Safety
 /// `handle` 必须是有效句柄，`out_result` 必须是有效指针
@@ -2465,30 +3649,35 @@ pub unsafe extern "C" fn session_db_collect(
     }));
 
     match result {
+        Ok(Ok(collect_result)) if !collect_result.permission_errors.is_empty() => {
+            FfiError::PermissionDenied
+        }
         Ok(Ok(collect_result)) => {
-            let first_error = if let Some(err) = collect_result.errors.first() {
-                CString::new(err.as_str())
-                    .map(|s| s.into_raw())
-                    .unwrap_or(std::ptr::null_mut())
-            } else {
-                std::ptr::null_mut()
-            };
-
-            let c_result = Box::new(CollectResultC {
-                projects_scanned: collect_result.projects_scanned,
-                sessions_scanned: collect_result.sessions_scanned,
-                messages_inserted: collect_result.messages_inserted,
-                error_count: collect_result.errors.len(),
-                first_error,
-            });
-            *out_result = Box::into_raw(c_result);
+            *out_result = Box::into_raw(Box::new(collect_result_to_c(&collect_result)));
             FfiError::Success
         }
-        Ok(Err(_)) => FfiError::DatabaseError,
+        Ok(Err(e)) => collect_error_code(&e),
         Err(_) => FfiError::Unknown,
     }
 }
 
+/// 采集错误映射：文件无权访问时返回 PermissionDenied
+fn collect_error_code(e: &anyhow::Error) -> FfiError {
+    if let Some(crate::Error::CollectionInProgress { .. }) = e.downcast_ref::<crate::Error>() {
+        return FfiError::CoordinationError;
+    }
+    let denied = e.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|io| io.kind() == std::io::ErrorKind::PermissionDenied)
+    });
+    if denied {
+        FfiError::PermissionDenied
+    } else {
+        FfiError::DatabaseError
+    }
+}
+
 /// 按路径采集单个会话
 ///
 /// # Safety
@@ -2519,25 +3708,48 @@ pub unsafe extern "C" fn session_db_collect_by_path(
 
     match result {
         Ok(Ok(collect_result)) => {
-            let first_error = if let Some(err) = collect_result.errors.first() {
-                CString::new(err.as_str())
-                    .map(|s| s.into_raw())
-                    .unwrap_or(std::ptr::null_mut())
-            } else {
-                std::ptr::null_mut()
-            };
-
-            let c_result = Box::new(CollectResultC {
-                projects_scanned: collect_result.projects_scanned,
-                sessions_scanned: collect_result.sessions_scanned,
-                messages_inserted: collect_result.messages_inserted,
-                error_count: collect_result.errors.len(),
-                first_error,
-            });
-            *out_result = Box::into_raw(c_result);
+            *out_result = Box::into_raw(Box::new(collect_result_to_c(&collect_result)));
             FfiError::Success
         }
-        Ok(Err(_)) => FfiError::DatabaseError,
+        Ok(Err(e)) => collect_error_code(&e),
+        Err(_) => FfiError::Unknown,
+    }
+}
+
+/// 检测处理该路径的适配器（采集前判断文件是否为可识别的会话格式）
+///
+/// 成功时 `out_source` 为适配器的来源名（如 "claude"），需要调用 `session_db_free_string` 释放；
+/// 没有适配器能处理该路径时返回 `NotFound`。
+///
+/// # Safety
+/// `path` 必须是有效 C 字符串，`out_source` 必须有效
+#[no_mangle]
+pub unsafe extern "C" fn session_db_detect_adapter(
+    path: *const c_char,
+    out_source: *mut *mut c_char,
+) -> FfiError {
+    if path.is_null() || out_source.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let path_str = match CStr::from_ptr(path).to_str() {
+        Ok(s) => s,
+        Err(_) => return FfiError::InvalidUtf8,
+    };
+
+    let result = panic::catch_unwind(|| {
+        crate::adapter_for_path(std::path::Path::new(path_str)).map(|a| a.source().to_string())
+    });
+
+    match result {
+        Ok(Some(source)) => match CString::new(source) {
+            Ok(s) => {
+                *out_source = s.into_raw();
+                FfiError::Success
+            }
+            Err(_) => FfiError::InvalidUtf8,
+        },
+        Ok(None) => FfiError::NotFound,
         Err(_) => FfiError::Unknown,
     }
 }
@@ -2554,8 +3766,10 @@ pub unsafe extern "C" fn session_db_free_collect_result(result: *mut CollectResu
     }
 
     let r = Box::from_raw(result);
-    if !r.first_error.is_null() {
-        drop(CString::from_raw(r.first_error));
+    for s in [r.first_error, r.first_error_path, r.first_error_stage] {
+        if !s.is_null() {
+            drop(CString::from_raw(s));
+        }
     }
 }
 
@@ -2738,3 +3952,558 @@ pub unsafe extern "C" fn session_db_free_session_relation(relation: *mut Session
         drop(CString::from_raw(r.source));
     }
 }
+
+// ==================== Talk 摘要 ====================
+
+/// TalkSummary C 结构体
+#[repr(C)]
+pub struct TalkSummaryC {
+    pub id: i64,
+    pub session_id: *mut c_char,
+    pub talk_id: *mut c_char,
+    pub summary_l2: *mut c_char,
+    pub summary_l3: *mut c_char,     // null 表示无
+    pub position: i64,               // -1 表示 NULL
+    pub first_message_sequence: i64, // -1 表示 NULL
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// C 数组 wrapper
+#[repr(C)]
+pub struct TalkSummaryArray {
+    pub data: *mut TalkSummaryC,
+    pub len: usize,
+}
+
+/// 将 Rust TalkSummary 转为 C 结构体
+fn talk_summary_to_c(t: &crate::types::TalkSummary) -> Option<TalkSummaryC> {
+    let session_id = CString::new(t.session_id.clone()).ok()?.into_raw();
+    let talk_id = CString::new(t.talk_id.clone()).ok()?.into_raw();
+    let summary_l2 = CString::new(t.summary_l2.clone()).ok()?.into_raw();
+    let summary_l3 = match &t.summary_l3 {
+        Some(s) => CString::new(s.clone()).ok()?.into_raw(),
+        None => std::ptr::null_mut(),
+    };
+    Some(TalkSummaryC {
+        id: t.id,
+        session_id,
+        talk_id,
+        summary_l2,
+        summary_l3,
+        position: t.position.unwrap_or(-1),
+        first_message_sequence: t.first_message_sequence.unwrap_or(-1),
+        created_at: t.created_at,
+        updated_at: t.updated_at,
+    })
+}
+
+/// 释放 TalkSummaryC 内部字符串
+unsafe fn free_talk_summary_fields(t: &TalkSummaryC) {
+    for ptr in [t.session_id, t.talk_id, t.summary_l2, t.summary_l3] {
+        if !ptr.is_null() {
+            drop(CString::from_raw(ptr));
+        }
+    }
+}
+
+/// 获取会话的 Talk 摘要（按 position 排序，分页）
+///
+/// - `limit`: 单页条数（0 表示取最大单页条数，超过上限时截断）
+/// - `offset`: 偏移
+///
+/// # Safety
+/// `handle`, `session_id` 必须有效，返回数组需要用 `session_db_free_talk_summaries` 释放
+#[no_mangle]
+pub unsafe extern "C" fn session_db_get_talk_summaries(
+    handle: *const SessionDbHandle,
+    session_id: *const c_char,
+    limit: usize,
+    offset: usize,
+    out_array: *mut *mut TalkSummaryArray,
+) -> FfiError {
+    if handle.is_null() || session_id.is_null() || out_array.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*handle;
+        let session_id = match CStr::from_ptr(session_id).to_str() {
+            Ok(s) => s,
+            Err(_) => return Err(FfiError::InvalidUtf8),
+        };
+        let limit = if limit == 0 {
+            crate::db::MAX_TALK_SUMMARIES_LIMIT
+        } else {
+            limit
+        };
+        handle
+            .db
+            .get_talk_summaries(session_id, limit, offset)
+            .map_err(map_error)
+    }));
+
+    match result {
+        Ok(Ok(talks)) => {
+            let mut c_talks: Vec<TalkSummaryC> = Vec::with_capacity(talks.len());
+            for t in &talks {
+                match talk_summary_to_c(t) {
+                    Some(c) => c_talks.push(c),
+                    None => {
+                        for c in &c_talks {
+                            free_talk_summary_fields(c);
+                        }
+                        return FfiError::InvalidUtf8;
+                    }
+                }
+            }
+
+            let len = c_talks.len();
+            let data = c_talks.as_mut_ptr();
+            std::mem::forget(c_talks);
+
+            let array = Box::new(TalkSummaryArray { data, len });
+            *out_array = Box::into_raw(array);
+            FfiError::Success
+        }
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
+
+/// 获取单个 Talk 摘要
+///
+/// # Safety
+/// `handle`, `session_id`, `talk_id` 必须有效，不存在时 `out_talk` 置为 null
+/// 返回的 talk 需要用 `session_db_free_talk_summary` 释放
+#[no_mangle]
+pub unsafe extern "C" fn session_db_get_talk_summary(
+    handle: *const SessionDbHandle,
+    session_id: *const c_char,
+    talk_id: *const c_char,
+    out_talk: *mut *mut TalkSummaryC,
+) -> FfiError {
+    if handle.is_null() || session_id.is_null() || talk_id.is_null() || out_talk.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*handle;
+        let session_id = match CStr::from_ptr(session_id).to_str() {
+            Ok(s) => s,
+            Err(_) => return Err(FfiError::InvalidUtf8),
+        };
+        let talk_id = match CStr::from_ptr(talk_id).to_str() {
+            Ok(s) => s,
+            Err(_) => return Err(FfiError::InvalidUtf8),
+        };
+        handle
+            .db
+            .get_talk_summary(session_id, talk_id)
+            .map_err(map_error)
+    }));
+
+    match result {
+        Ok(Ok(Some(talk))) => match talk_summary_to_c(&talk) {
+            Some(c) => {
+                *out_talk = Box::into_raw(Box::new(c));
+                FfiError::Success
+            }
+            None => FfiError::InvalidUtf8,
+        },
+        Ok(Ok(None)) => {
+            *out_talk = std::ptr::null_mut();
+            FfiError::Success
+        }
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
+
+/// 释放 TalkSummary 数组
+///
+/// # Safety
+/// `array` 必须是 `session_db_get_talk_summaries` 返回的有效指针
+#[no_mangle]
+pub unsafe extern "C" fn session_db_free_talk_summaries(array: *mut TalkSummaryArray) {
+    if array.is_null() {
+        return;
+    }
+
+    let array = Box::from_raw(array);
+    let talks = Vec::from_raw_parts(array.data, array.len, array.len);
+    for t in &talks {
+        free_talk_summary_fields(t);
+    }
+}
+
+/// 释放单个 TalkSummary
+///
+/// # Safety
+/// `talk` 必须是 `session_db_get_talk_summary` 返回的有效指针
+#[no_mangle]
+pub unsafe extern "C" fn session_db_free_talk_summary(talk: *mut TalkSummaryC) {
+    if talk.is_null() {
+        return;
+    }
+
+    let t = Box::from_raw(talk);
+    free_talk_summary_fields(&t);
+}
+
+// ==================== 对话轮次 ====================
+
+/// TurnSummary C 结构体
+#[repr(C)]
+pub struct TurnSummaryC {
+    pub turn_index: i64,
+    pub user_preview: *mut c_char,      // null 表示无
+    pub assistant_preview: *mut c_char, // null 表示无
+    pub tool_count: i64,
+    pub message_count: i64,
+    pub start_timestamp: i64,
+    pub end_timestamp: i64,
+    pub pending_approval_count: i64,
+}
+
+/// C 数组 wrapper
+#[repr(C)]
+pub struct TurnSummaryArray {
+    pub data: *mut TurnSummaryC,
+    pub len: usize,
+}
+
+/// 将 Rust TurnSummary 转为 C 结构体
+fn turn_summary_to_c(t: &crate::types::TurnSummary) -> Option<TurnSummaryC> {
+    let user_preview = match &t.user_preview {
+        Some(s) => CString::new(s.clone()).ok()?.into_raw(),
+        None => std::ptr::null_mut(),
+    };
+    let assistant_preview = match &t.assistant_preview {
+        Some(s) => CString::new(s.clone()).ok()?.into_raw(),
+        None => std::ptr::null_mut(),
+    };
+    Some(TurnSummaryC {
+        turn_index: t.turn_index,
+        user_preview,
+        assistant_preview,
+        tool_count: t.tool_count,
+        message_count: t.message_count,
+        start_timestamp: t.start_timestamp,
+        end_timestamp: t.end_timestamp,
+        pending_approval_count: t.pending_approval_count,
+    })
+}
+
+/// 释放 TurnSummaryC 内部字符串
+unsafe fn free_turn_summary_fields(t: &TurnSummaryC) {
+    for ptr in [t.user_preview, t.assistant_preview] {
+        if !ptr.is_null() {
+            drop(CString::from_raw(ptr));
+        }
+    }
+}
+
+/// 获取会话的对话轮次摘要（按 turn_index 排序，分页）
+///
+/// # Safety
+/// `handle`, `session_id` 必须有效，返回数组需要用 `session_db_free_turns` 释放
+#[no_mangle]
+pub unsafe extern "C" fn session_db_list_turns(
+    handle: *const SessionDbHandle,
+    session_id: *const c_char,
+    limit: usize,
+    offset: usize,
+    out_array: *mut *mut TurnSummaryArray,
+) -> FfiError {
+    if handle.is_null() || session_id.is_null() || out_array.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*handle;
+        let session_id = match CStr::from_ptr(session_id).to_str() {
+            Ok(s) => s,
+            Err(_) => return Err(FfiError::InvalidUtf8),
+        };
+        handle
+            .db
+            .list_turns(session_id, boundary_limit(limit), offset)
+            .map_err(map_error)
+    }));
+
+    match result {
+        Ok(Ok(turns)) => {
+            let mut c_turns: Vec<TurnSummaryC> = Vec::with_capacity(turns.len());
+            for t in &turns {
+                match turn_summary_to_c(t) {
+                    Some(c) => c_turns.push(c),
+                    None => {
+                        for c in &c_turns {
+                            free_turn_summary_fields(c);
+                        }
+                        return FfiError::InvalidUtf8;
+                    }
+                }
+            }
+
+            let len = c_turns.len();
+            let data = c_turns.as_mut_ptr();
+            std::mem::forget(c_turns);
+
+            let array = Box::new(TurnSummaryArray { data, len });
+            *out_array = Box::into_raw(array);
+            FfiError::Success
+        }
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
+
+/// 获取单个对话轮次的消息（按 sequence 排序，不含 raw）
+///
+/// # Safety
+/// `handle`, `session_id` 必须有效，返回数组需要用 `session_db_free_messages` 释放
+#[no_mangle]
+pub unsafe extern "C" fn session_db_get_turn_messages(
+    handle: *const SessionDbHandle,
+    session_id: *const c_char,
+    turn_index: i64,
+    out_array: *mut *mut MessageArray,
+) -> FfiError {
+    if handle.is_null() || session_id.is_null() || out_array.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*handle;
+        let session_id = match CStr::from_ptr(session_id).to_str() {
+            Ok(s) => s,
+            Err(_) => return Err(FfiError::InvalidUtf8),
+        };
+        handle
+            .db
+            .get_turn_messages(session_id, turn_index)
+            .map_err(map_error)
+    }));
+
+    match result {
+        Ok(Ok(messages)) => write_message_array(messages, out_array),
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
+
+/// 释放 TurnSummary 数组
+///
+/// # Safety
+/// `array` 必须是 `session_db_list_turns` 返回的有效指针
+#[no_mangle]
+pub unsafe extern "C" fn session_db_free_turns(array: *mut TurnSummaryArray) {
+    if array.is_null() {
+        return;
+    }
+
+    let array = Box::from_raw(array);
+    let turns = Vec::from_raw_parts(array.data, array.len, array.len);
+    for t in &turns {
+        free_turn_summary_fields(t);
+    }
+}
+
+// ==================== 采集忽略规则 ====================
+
+/// 忽略规则类型 C 枚举
+/// 0 = PathGlob, 1 = ProjectPath, 2 = SessionPrefix
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IgnoreKindC {
+    PathGlob = 0,
+    ProjectPath = 1,
+    SessionPrefix = 2,
+}
+
+impl From<IgnoreKindC> for crate::types::IgnoreKind {
+    fn from(kind: IgnoreKindC) -> Self {
+        match kind {
+            IgnoreKindC::PathGlob => crate::types::IgnoreKind::PathGlob,
+            IgnoreKindC::ProjectPath => crate::types::IgnoreKind::ProjectPath,
+            IgnoreKindC::SessionPrefix => crate::types::IgnoreKind::SessionPrefix,
+        }
+    }
+}
+
+impl From<crate::types::IgnoreKind> for IgnoreKindC {
+    fn from(kind: crate::types::IgnoreKind) -> Self {
+        match kind {
+            crate::types::IgnoreKind::PathGlob => IgnoreKindC::PathGlob,
+            crate::types::IgnoreKind::ProjectPath => IgnoreKindC::ProjectPath,
+            crate::types::IgnoreKind::SessionPrefix => IgnoreKindC::SessionPrefix,
+        }
+    }
+}
+
+/// CollectionIgnore C 结构体
+#[repr(C)]
+pub struct CollectionIgnoreC {
+    pub id: i64,
+    pub pattern: *mut c_char,
+    pub kind: IgnoreKindC,
+    pub created_at: i64,
+}
+
+/// CollectionIgnore 数组
+#[repr(C)]
+pub struct CollectionIgnoreArray {
+    pub data: *mut CollectionIgnoreC,
+    pub len: usize,
+}
+
+/// 刷新项目 ignored 标记（规则变化后调用）
+fn refresh_project_ignores(db: &SessionDB) -> crate::error::Result<()> {
+    let rules = crate::ignore::IgnoreRules::load(db)?;
+    db.apply_project_ignores(&rules)?;
+    Ok(())
+}
+
+/// 添加采集忽略规则（幂等）
+///
+/// 添加后刷新项目的 ignored 标记，被排除的项目不再出现在默认列表中。
+///
+/// # Safety
+/// `handle`, `pattern` 必须有效，`out_id` 可以为 null
+#[no_mangle]
+pub unsafe extern "C" fn session_db_add_ignore(
+    handle: *const SessionDbHandle,
+    pattern: *const c_char,
+    kind: IgnoreKindC,
+    out_id: *mut i64,
+) -> FfiError {
+    if handle.is_null() || pattern.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*handle;
+        let pattern_str = match CStr::from_ptr(pattern).to_str() {
+            Ok(s) => s,
+            Err(_) => return Err(FfiError::InvalidUtf8),
+        };
+        let id = handle
+            .db
+            .add_ignore(pattern_str, kind.into())
+            .map_err(map_error)?;
+        refresh_project_ignores(&handle.db).map_err(map_error)?;
+        Ok(id)
+    }));
+
+    match result {
+        Ok(Ok(id)) => {
+            if !out_id.is_null() {
+                *out_id = id;
+            }
+            FfiError::Success
+        }
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
+
+/// 删除采集忽略规则
+///
+/// # Safety
+/// `handle` 必须有效，`out_removed` 可以为 null
+#[no_mangle]
+pub unsafe extern "C" fn session_db_remove_ignore(
+    handle: *const SessionDbHandle,
+    id: i64,
+    out_removed: *mut bool,
+) -> FfiError {
+    if handle.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*handle;
+        let removed = handle.db.remove_ignore(id).map_err(map_error)?;
+        refresh_project_ignores(&handle.db).map_err(map_error)?;
+        Ok(removed)
+    }));
+
+    match result {
+        Ok(Ok(removed)) => {
+            if !out_removed.is_null() {
+                *out_removed = removed;
+            }
+            FfiError::Success
+        }
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
+
+/// 列出采集忽略规则（不含环境变量规则）
+///
+/// # Safety
+/// `handle`, `out_array` 必须有效，返回的数组需要调用 `session_db_free_ignores` 释放
+#[no_mangle]
+pub unsafe extern "C" fn session_db_list_ignores(
+    handle: *const SessionDbHandle,
+    out_array: *mut *mut CollectionIgnoreArray,
+) -> FfiError {
+    if handle.is_null() || out_array.is_null() {
+        return FfiError::NullPointer;
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let handle = &*handle;
+        handle.db.list_ignores().map_err(map_error)
+    }));
+
+    match result {
+        Ok(Ok(ignores)) => {
+            let mut c_ignores: Vec<CollectionIgnoreC> = Vec::with_capacity(ignores.len());
+            for ignore in ignores {
+                let pattern = match CString::new(ignore.pattern) {
+                    Ok(s) => s.into_raw(),
+                    Err(_) => return FfiError::InvalidUtf8,
+                };
+                c_ignores.push(CollectionIgnoreC {
+                    id: ignore.id,
+                    pattern,
+                    kind: ignore.kind.into(),
+                    created_at: ignore.created_at,
+                });
+            }
+
+            let len = c_ignores.len();
+            let data = c_ignores.as_mut_ptr();
+            std::mem::forget(c_ignores);
+
+            let array = Box::new(CollectionIgnoreArray { data, len });
+            *out_array = Box::into_raw(array);
+            FfiError::Success
+        }
+        Ok(Err(e)) => e,
+        Err(_) => FfiError::Unknown,
+    }
+}
+
+/// 释放 CollectionIgnore 数组
+///
+/// # Safety
+/// `array` 必须是 `session_db_list_ignores` 返回的有效指针
+#[no_mangle]
+pub unsafe extern "C" fn session_db_free_ignores(array: *mut CollectionIgnoreArray) {
+    if array.is_null() {
+        return;
+    }
+
+    let array = Box::from_raw(array);
+    let ignores = Vec::from_raw_parts(array.data, array.len, array.len);
+    for i in ignores {
+        if !i.pattern.is_null() {
+            drop(CString::from_raw(i.pattern));
+        }
+    }
+}
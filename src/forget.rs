@@ -0,0 +1,268 @@
+//! 彻底删除项目（数据保护请求）
+//!
+//! 删除一个项目的全部痕迹：会话、消息、FTS 索引、Talk 摘要、修订记录、会话关系、接续链
+//! 和按该项目过滤的搜索记录，并为项目路径写入忽略规则（`IgnoreKind::ProjectPath`），之后的采集不会重新写入。
+//!
+//! - 删除按会话分批提交（每批 `FORGET_BATCH_SESSIONS` 个会话），不长时间阻塞其他写入
+//! - 已向量索引的消息照常由触发器写入 vector_tombstones（只含 uuid 和消息 ID），
+//!   外部向量库据此删除副本
+//! - `secure = true` 时删除期间开启 `secure_delete`（释放的页面清零），之后合并 FTS 段
+//!   （清除旧段中的倒排索引）并执行 `wal_checkpoint(TRUNCATE)`（清除 WAL 中的旧页面）
+//!
+//! 先用 `plan_forget_project` 预览，`ForgetReport::confirm_token` 绑定项目和预览时的行数，
+//! 供调用方做两步确认（见 `Request::ForgetProject`）。
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{bump_change_counter, content_hash, fts_enabled, SessionDB};
+use crate::error::{Error, Result};
+use crate::observer::ChangeEvent;
+use crate::types::IgnoreKind;
+
+/// 每个删除事务包含的会话数
+pub const FORGET_BATCH_SESSIONS: usize = 50;
+
+/// 项目删除结果（预览时为将要删除的行数）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForgetReport {
+    pub project_id: i64,
+    pub project_path: String,
+    /// 只预览，未删除
+    pub dry_run: bool,
+    pub sessions: usize,
+    pub messages: usize,
+    pub talks: usize,
+    pub message_revisions: usize,
+    pub session_relations: usize,
+    pub continuation_chains: usize,
+    pub continuation_chain_nodes: usize,
+    /// 为已向量索引的消息写入的向量墓碑数
+    pub vector_tombstones: usize,
+    /// 释放的数据页字节数（预览时为 0）
+    pub bytes_reclaimed: u64,
+    /// 预览时签发的确认 token（项目数据变化后失效）
+    pub confirm_token: String,
+}
+
+impl ForgetReport {
+    fn sign(&mut self) {
+        self.confirm_token = content_hash(&format!(
+            "forget|{}|{}|{}|{}|{}|{}|{}|{}|{}",
+            self.project_id,
+            self.project_path,
+            self.sessions,
+            self.messages,
+            self.talks,
+            self.message_revisions,
+            self.session_relations,
+            self.continuation_chains,
+            self.continuation_chain_nodes
+        ));
+    }
+}
+
+/// 项目下的会话（`IN` 子查询，?1 为项目 ID）
+const PROJECT_SESSIONS: &str = "SELECT session_id FROM sessions WHERE project_id = ?1";
+
+impl SessionDB {
+    /// 预览 `forget_project` 将删除的内容（不修改数据库）
+    pub fn plan_forget_project(&self, project_id: i64) -> Result<ForgetReport> {
+        let conn = self.conn.lock();
+        plan(&conn, project_id)
+    }
+
+    /// 删除项目的全部数据，并忽略该路径的后续采集
+    ///
+    /// - secure: 释放的页面清零，合并 FTS 段并截断 WAL（见模块文档）
+    ///
+    /// 返回实际删除的行数；`confirm_token` 为删除前状态的 token（与预览一致）。
+    pub fn forget_project(&self, project_id: i64, secure: bool) -> Result<ForgetReport> {
+        let secure_before: Option<i64> = if secure {
+            let conn = self.conn.lock();
+            let previous = conn.query_row("PRAGMA secure_delete", [], |row| row.get(0))?;
+            conn.execute_batch("PRAGMA secure_delete = 1;")?;
+            Some(previous)
+        } else {
+            None
+        };
+
+        let result = self.forget_project_inner(project_id, secure);
+        // 出错时也恢复连接设置
+        if let Some(previous) = secure_before {
+            self.conn
+                .lock()
+                .execute_batch(&format!("PRAGMA secure_delete = {};", previous))?;
+        }
+        result
+    }
+
+    fn forget_project_inner(&self, project_id: i64, secure: bool) -> Result<ForgetReport> {
+        let planned = self.plan_forget_project(project_id)?;
+        // 先写忽略规则：删除期间开始的采集也不会重新写入
+        self.add_ignore(&planned.project_path, IgnoreKind::ProjectPath)?;
+
+        let (session_ids, used_before) = {
+            let conn = self.conn.lock();
+            let mut stmt = conn.prepare(PROJECT_SESSIONS)?;
+            let rows = stmt.query_map(params![project_id], |row| row.get(0))?;
+            let session_ids = rows.collect::<std::result::Result<Vec<String>, _>>()?;
+            (session_ids, used_bytes(&conn)?)
+        };
+
+        let mut report = ForgetReport {
+            project_id,
+            project_path: planned.project_path,
+            confirm_token: planned.confirm_token,
+            ..Default::default()
+        };
+        self.forget_sessions(&session_ids, &mut report)?;
+
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM projects WHERE id = ?1", params![project_id])?;
+        tx.execute(
+            "DELETE FROM search_log WHERE project_filter = ?1",
+            params![project_id],
+        )?;
+        bump_change_counter(&tx)?;
+        tx.commit()?;
+
+        if secure {
+            shred_free_data(&conn)?;
+        }
+        report.bytes_reclaimed = used_before.saturating_sub(used_bytes(&conn)?);
+        drop(conn);
+
+        self.notify(vec![ChangeEvent::ProjectChanged {
+            project_id,
+            project_path: report.project_path.clone(),
+            ignored: true,
+        }]);
+        Ok(report)
+    }
+
+    /// 分批删除会话及其关联数据，行数累加到 `report`
+    fn forget_sessions(&self, session_ids: &[String], report: &mut ForgetReport) -> Result<()> {
+        for batch in session_ids.chunks(FORGET_BATCH_SESSIONS) {
+            let mut conn = self.conn.lock();
+            let tx = conn.transaction()?;
+            for session_id in batch {
+                report.vector_tombstones += tx.query_row(
+                    "SELECT COUNT(*) FROM messages WHERE session_id = ?1 AND vector_indexed = 1",
+                    params![session_id],
+                    |row| row.get::<_, i64>(0),
+                )? as usize;
+                report.messages += tx.execute(
+                    "DELETE FROM messages WHERE session_id = ?1",
+                    params![session_id],
+                )?;
+                report.message_revisions += tx.execute(
+                    "DELETE FROM message_revisions WHERE session_id = ?1",
+                    params![session_id],
+                )?;
+                report.talks += tx.execute(
+                    "DELETE FROM talks WHERE session_id = ?1",
+                    params![session_id],
+                )?;
+                report.session_relations += tx.execute(
+                    "DELETE FROM session_relations
+                     WHERE parent_session_id = ?1 OR child_session_id = ?1",
+                    params![session_id],
+                )?;
+                // 以该会话为链头的整条链（chain_id 即链头会话 ID），以及该会话在其他链中的节点
+                report.continuation_chain_nodes += tx.execute(
+                    "DELETE FROM continuation_chain_nodes
+                     WHERE session_id = ?1
+                        OR chain_id IN (SELECT chain_id FROM continuation_chains WHERE root_session_id = ?1)",
+                    params![session_id],
+                )?;
+                report.continuation_chains += tx.execute(
+                    "DELETE FROM continuation_chains WHERE root_session_id = ?1",
+                    params![session_id],
+                )?;
+                // 失败记录含文件路径和错误信息，不计入报告
+                tx.execute(
+                    "DELETE FROM collect_failures WHERE session_id = ?1",
+                    params![session_id],
+                )?;
+                report.sessions += tx.execute(
+                    "DELETE FROM sessions WHERE session_id = ?1",
+                    params![session_id],
+                )?;
+            }
+            bump_change_counter(&tx)?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+}
+
+/// 统计项目的数据并签发确认 token
+fn plan(conn: &Connection, project_id: i64) -> Result<ForgetReport> {
+    let project_path: String = conn
+        .query_row(
+            "SELECT path FROM projects WHERE id = ?1",
+            params![project_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| Error::NotFound(format!("project {}", project_id)))?;
+
+    let count = |sql: &str| -> Result<usize> {
+        let sql = sql.replace("{sessions}", PROJECT_SESSIONS);
+        let count: i64 = conn.query_row(&sql, params![project_id], |row| row.get(0))?;
+        Ok(count as usize)
+    };
+    let mut report = ForgetReport {
+        project_id,
+        project_path,
+        dry_run: true,
+        sessions: count("SELECT COUNT(*) FROM sessions WHERE project_id = ?1")?,
+        messages: count("SELECT COUNT(*) FROM messages WHERE session_id IN ({sessions})")?,
+        talks: count("SELECT COUNT(*) FROM talks WHERE session_id IN ({sessions})")?,
+        message_revisions: count(
+            "SELECT COUNT(*) FROM message_revisions WHERE session_id IN ({sessions})",
+        )?,
+        session_relations: count(
+            "SELECT COUNT(*) FROM session_relations
+             WHERE parent_session_id IN ({sessions}) OR child_session_id IN ({sessions})",
+        )?,
+        continuation_chains: count(
+            "SELECT COUNT(*) FROM continuation_chains WHERE root_session_id IN ({sessions})",
+        )?,
+        continuation_chain_nodes: count(
+            "SELECT COUNT(*) FROM continuation_chain_nodes
+             WHERE session_id IN ({sessions})
+                OR chain_id IN (SELECT chain_id FROM continuation_chains WHERE root_session_id IN ({sessions}))",
+        )?,
+        vector_tombstones: count(
+            "SELECT COUNT(*) FROM messages WHERE session_id IN ({sessions}) AND vector_indexed = 1",
+        )?,
+        ..Default::default()
+    };
+    report.sign();
+    Ok(report)
+}
+
+/// 已使用的数据页字节数
+fn used_bytes(conn: &Connection) -> Result<u64> {
+    let pragma = |name: &str| -> Result<i64> {
+        conn.query_row(&format!("PRAGMA {}", name), [], |row| row.get(0))
+            .map_err(Into::into)
+    };
+    let used_pages = pragma("page_count")? - pragma("freelist_count")?;
+    Ok((used_pages.max(0) * pragma("page_size")?) as u64)
+}
+
+/// 合并 FTS 段并截断 WAL，不在数据库文件中残留已删除的内容
+fn shred_free_data(conn: &Connection) -> Result<()> {
+    if fts_enabled(conn)? {
+        conn.execute_batch(
+            "INSERT INTO messages_fts(messages_fts) VALUES('optimize');
+             INSERT INTO talks_fts(talks_fts) VALUES('optimize');",
+        )?;
+    }
+    conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+    Ok(())
+}
@@ -0,0 +1,191 @@
+//! 采集忽略规则
+//!
+//! 某些项目（临时目录、保密的客户项目）即使 Claude 写了会话文件也不应被采集。
+//! 规则来源：
+//! - `collection_ignores` 表（持久化，通过 SessionDB / Agent / FFI 管理）
+//! - 环境变量 `VIMO_COLLECT_IGNORE`（逗号分隔的 glob，运行时合并，不持久化）
+
+use std::collections::HashSet;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::error::Result;
+use crate::types::{CollectionIgnore, IgnoreKind};
+use crate::SessionDB;
+
+/// 忽略规则环境变量（逗号分隔的 glob）
+pub const COLLECT_IGNORE_ENV: &str = "VIMO_COLLECT_IGNORE";
+
+/// 编译后的忽略规则
+#[derive(Debug, Default)]
+pub struct IgnoreRules {
+    globs: Option<GlobSet>,
+    project_paths: HashSet<String>,
+    session_prefixes: Vec<String>,
+}
+
+impl IgnoreRules {
+    /// 从规则列表构建（无效的 glob 跳过并告警）
+    pub fn new(rules: &[CollectionIgnore]) -> Self {
+        let mut builder = GlobSetBuilder::new();
+        let mut glob_count = 0;
+        let mut project_paths = HashSet::new();
+        let mut session_prefixes = Vec::new();
+
+        for rule in rules {
+            match rule.kind {
+                IgnoreKind::PathGlob => match Glob::new(&rule.pattern) {
+                    Ok(glob) => {
+                        builder.add(glob);
+                        glob_count += 1;
+                    }
+                    Err(e) => {
+                        tracing::warn!("Invalid ignore glob {:?}: {}", rule.pattern, e);
+                    }
+                },
+                IgnoreKind::ProjectPath => {
+                    project_paths.insert(normalize_path(&rule.pattern).to_string());
+                }
+                IgnoreKind::SessionPrefix => {
+                    session_prefixes.push(rule.pattern.clone());
+                }
+            }
+        }
+
+        let globs = if glob_count == 0 {
+            None
+        } else {
+            match builder.build() {
+                Ok(set) => Some(set),
+                Err(e) => {
+                    tracing::warn!("Failed to build ignore glob set: {}", e);
+                    None
+                }
+            }
+        };
+
+        Self {
+            globs,
+            project_paths,
+            session_prefixes,
+        }
+    }
+
+    /// 加载数据库规则并合并环境变量规则
+    pub fn load(db: &SessionDB) -> Result<Self> {
+        let mut rules = db.list_ignores()?;
+        rules.extend(env_ignore_rules());
+        Ok(Self::new(&rules))
+    }
+
+    /// 是否没有任何规则
+    pub fn is_empty(&self) -> bool {
+        self.globs.is_none() && self.project_paths.is_empty() && self.session_prefixes.is_empty()
+    }
+
+    /// 项目路径是否被忽略（glob 或精确路径）
+    pub fn is_project_ignored(&self, project_path: &str) -> bool {
+        let path = normalize_path(project_path);
+        self.project_paths.contains(path) || self.is_path_ignored(path)
+    }
+
+    /// 任意路径（如会话文件路径）是否匹配忽略 glob
+    pub fn is_path_ignored(&self, path: &str) -> bool {
+        self.globs.as_ref().is_some_and(|g| g.is_match(path))
+    }
+
+    /// 会话 ID 是否匹配忽略前缀
+    pub fn is_session_ignored(&self, session_id: &str) -> bool {
+        self.session_prefixes
+            .iter()
+            .any(|prefix| session_id.starts_with(prefix.as_str()))
+    }
+}
+
+/// 解析环境变量中的忽略 glob（逗号分隔，忽略空项）
+pub fn parse_ignore_globs(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect()
+}
+
+/// 环境变量 `VIMO_COLLECT_IGNORE` 中的规则（id 为 0，不持久化）
+pub fn env_ignore_rules() -> Vec<CollectionIgnore> {
+    std::env::var(COLLECT_IGNORE_ENV)
+        .map(|v| parse_ignore_globs(&v))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|pattern| CollectionIgnore {
+            id: 0,
+            pattern,
+            kind: IgnoreKind::PathGlob,
+            created_at: 0,
+        })
+        .collect()
+}
+
+/// 去掉末尾的路径分隔符（根路径除外）
+fn normalize_path(path: &str) -> &str {
+    let trimmed = path.trim_end_matches(['/', '\\']);
+    if trimmed.is_empty() {
+        path
+    } else {
+        trimmed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, kind: IgnoreKind) -> CollectionIgnore {
+        CollectionIgnore {
+            id: 0,
+            pattern: pattern.to_string(),
+            kind,
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_path_glob() {
+        let rules = IgnoreRules::new(&[rule("/Users/*/scratch/**", IgnoreKind::PathGlob)]);
+        assert!(rules.is_project_ignored("/Users/test/scratch/demo"));
+        assert!(!rules.is_project_ignored("/Users/test/work/demo"));
+        assert!(rules.is_path_ignored("/Users/test/scratch/demo/session.jsonl"));
+    }
+
+    #[test]
+    fn test_project_path_exact() {
+        let rules = IgnoreRules::new(&[rule("/Users/test/secret/", IgnoreKind::ProjectPath)]);
+        assert!(rules.is_project_ignored("/Users/test/secret"));
+        assert!(rules.is_project_ignored("/Users/test/secret/"));
+        assert!(!rules.is_project_ignored("/Users/test/secret/sub"));
+    }
+
+    #[test]
+    fn test_session_prefix() {
+        let rules = IgnoreRules::new(&[rule("agent-", IgnoreKind::SessionPrefix)]);
+        assert!(rules.is_session_ignored("agent-123"));
+        assert!(!rules.is_session_ignored("abc-agent-123"));
+    }
+
+    #[test]
+    fn test_invalid_glob_skipped() {
+        let rules = IgnoreRules::new(&[rule("[unclosed", IgnoreKind::PathGlob)]);
+        assert!(rules.is_empty());
+        assert!(!rules.is_project_ignored("/any"));
+    }
+
+    #[test]
+    fn test_parse_ignore_globs() {
+        assert_eq!(
+            parse_ignore_globs(" /tmp/** , ,/Users/*/scratch/**,"),
+            vec!["/tmp/**".to_string(), "/Users/*/scratch/**".to_string()]
+        );
+        assert!(parse_ignore_globs("").is_empty());
+    }
+}
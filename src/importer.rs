@@ -0,0 +1,431 @@
+//! 导入 claude.ai 数据导出
+//!
+//! claude.ai（Claude Desktop 同一账号）的数据导出是一个 ZIP，其中 `conversations.json`
+//! 为对话数组，每个对话含 `uuid`、`name` 和 `chat_messages`（`sender` / `created_at` / `text`）。
+//! 导入后与 CLI 会话一起参与搜索。
+//!
+//! - 对话 → 会话：session_id 为对话 uuid，source 为 `claude-web`，channel 为 `web`
+//! - 每个导出文件对应一个合成项目（路径 `claude-web://{文件名}`）；
+//!   `ImportOptions::match_projects` 时，所属 claude.ai 项目与已有项目同名的对话归入已有项目
+//! - 消息 uuid 由 (对话 uuid, 消息序号) 确定性生成（UUID v5），重复导入同一导出只跳过，不重复写入
+//! - 逐个对话流式解析并写入，不把整个 JSON 读入内存（导出可达数百 MB）
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+
+use ai_cli_session_collector::MessageType;
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::db::{MessageInput, SessionDB, SessionInput};
+use crate::error::{Error, Result};
+use crate::types::ImportReport;
+
+/// 导入会话的来源
+pub const CLAUDE_WEB_SOURCE: &str = "claude-web";
+
+/// 导入会话的渠道
+pub const CLAUDE_WEB_CHANNEL: &str = "web";
+
+/// 导出中的对话文件
+const CONVERSATIONS_FILE: &str = "conversations.json";
+
+/// 消息 uuid 的命名空间（UUID v5）
+const MESSAGE_NAMESPACE: Uuid = Uuid::from_u128(0x6c1f_0a3e_9b52_4d1e_8f07_2a4c_5e6b_7d90);
+
+/// 导入选项
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportOptions {
+    /// 所属 claude.ai 项目与已有项目同名的对话归入已有项目（否则都归入导出的合成项目）
+    #[serde(default)]
+    pub match_projects: bool,
+}
+
+/// 导出中的对话（只解析用到的字段）
+#[derive(Debug, Deserialize)]
+pub(crate) struct ExportConversation {
+    #[serde(default)]
+    uuid: String,
+    #[serde(default)]
+    created_at: Option<String>,
+    #[serde(default)]
+    project: Option<ExportProject>,
+    #[serde(default)]
+    chat_messages: Vec<ExportMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportProject {
+    #[serde(default)]
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportMessage {
+    #[serde(default)]
+    sender: String,
+    #[serde(default)]
+    created_at: Option<String>,
+    #[serde(default)]
+    text: Option<String>,
+    /// 新版导出的内容块（`text` 为空时取其中的文本块）
+    #[serde(default)]
+    content: Vec<ExportContent>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportContent {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    text: Option<String>,
+}
+
+impl ExportMessage {
+    fn message_type(&self) -> Option<MessageType> {
+        match self.sender.as_str() {
+            "human" => Some(MessageType::User),
+            "assistant" => Some(MessageType::Assistant),
+            _ => None,
+        }
+    }
+
+    fn body(&self) -> String {
+        if let Some(text) = self.text.as_deref().filter(|text| !text.is_empty()) {
+            return text.to_string();
+        }
+        self.content
+            .iter()
+            .filter(|block| block.kind == "text")
+            .filter_map(|block| block.text.as_deref())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+/// 导入 claude.ai 数据导出（ZIP，或解压出的 `conversations.json`）
+pub fn import_claude_export(
+    db: &SessionDB,
+    archive_path: &Path,
+    options: &ImportOptions,
+) -> Result<ImportReport> {
+    let mut file = File::open(archive_path)?;
+    let mut magic = [0u8; 4];
+    let is_zip = file.read(&mut magic)? == 4 && magic == *b"PK\x03\x04";
+    file.rewind()?;
+
+    let mut importer = Importer::new(db, archive_path, options)?;
+    if is_zip {
+        let mut archive = zip::ZipArchive::new(file).map_err(|e| zip_error(archive_path, e))?;
+        let name = archive
+            .file_names()
+            .filter(|name| {
+                *name == CONVERSATIONS_FILE || name.ends_with(&format!("/{}", CONVERSATIONS_FILE))
+            })
+            .min_by_key(|name| name.len())
+            .map(str::to_string)
+            .ok_or_else(|| {
+                Error::NotFound(format!(
+                    "{} in {}",
+                    CONVERSATIONS_FILE,
+                    archive_path.display()
+                ))
+            })?;
+        let entry = archive
+            .by_name(&name)
+            .map_err(|e| zip_error(archive_path, e))?;
+        for_each_conversation(entry, |conversation| importer.import(conversation))?;
+    } else {
+        for_each_conversation(file, |conversation| importer.import(conversation))?;
+    }
+
+    tracing::info!("Imported {}: {:?}", archive_path.display(), importer.report);
+    Ok(importer.report)
+}
+
+fn zip_error(archive_path: &Path, e: zip::result::ZipError) -> Error {
+    Error::Other(anyhow::Error::new(e).context(format!(
+        "Failed to read export archive {}",
+        archive_path.display()
+    )))
+}
+
+/// 单次导入的状态
+struct Importer<'a> {
+    db: &'a SessionDB,
+    /// 导出的合成项目 (名称, 路径)，第一次用到时创建
+    export_project: (String, String),
+    export_project_id: Option<i64>,
+    /// 已有项目 名称 → ID（`match_projects` 时加载）
+    projects_by_name: HashMap<String, i64>,
+    report: ImportReport,
+}
+
+impl<'a> Importer<'a> {
+    fn new(db: &'a SessionDB, archive_path: &Path, options: &ImportOptions) -> Result<Self> {
+        let file_name = archive_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let stem = archive_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let mut projects_by_name = HashMap::new();
+        if options.match_projects {
+            for project in db.get_all_projects_with_source()? {
+                projects_by_name.entry(project.name).or_insert(project.id);
+            }
+        }
+
+        Ok(Self {
+            db,
+            export_project: (
+                format!("Claude Web ({})", stem),
+                format!("{}://{}", CLAUDE_WEB_SOURCE, file_name),
+            ),
+            export_project_id: None,
+            projects_by_name,
+            report: ImportReport::default(),
+        })
+    }
+
+    fn project_id(&mut self, conversation: &ExportConversation) -> Result<i64> {
+        let matched = conversation
+            .project
+            .as_ref()
+            .and_then(|project| self.projects_by_name.get(&project.name));
+        if let Some(&id) = matched {
+            return Ok(id);
+        }
+        match self.export_project_id {
+            Some(id) => Ok(id),
+            None => {
+                let (name, path) = &self.export_project;
+                let id = self
+                    .db
+                    .get_or_create_project(name, path, CLAUDE_WEB_SOURCE)?;
+                self.export_project_id = Some(id);
+                Ok(id)
+            }
+        }
+    }
+
+    fn import(&mut self, conversation: ExportConversation) -> Result<()> {
+        self.report.conversations += 1;
+        let messages = convert_messages(&conversation);
+        if conversation.uuid.is_empty() || messages.is_empty() {
+            self.report.conversations_skipped += 1;
+            return Ok(());
+        }
+
+        let session_id = &conversation.uuid;
+        if !self.db.session_exists(session_id)? {
+            self.report.sessions_created += 1;
+        }
+        let project_id = self.project_id(&conversation)?;
+        self.db.upsert_session_full(&SessionInput {
+            session_id: session_id.clone(),
+            project_id,
+            channel: Some(CLAUDE_WEB_CHANNEL.to_string()),
+            source: Some(CLAUDE_WEB_SOURCE.to_string()),
+            ..Default::default()
+        })?;
+
+        let (inserted, _) = self.db.insert_messages(session_id, &messages)?;
+        self.report.messages_created += inserted;
+        self.report.messages_skipped += messages.len() - inserted;
+        Ok(())
+    }
+}
+
+/// 对话中的消息转为 MessageInput
+///
+/// 未知 sender 和空消息跳过（sequence 保持连续）；uuid 按消息在对话中的原始序号生成。
+fn convert_messages(conversation: &ExportConversation) -> Vec<MessageInput> {
+    let fallback_timestamp = parse_timestamp(conversation.created_at.as_deref()).unwrap_or(0);
+    conversation
+        .chat_messages
+        .iter()
+        .enumerate()
+        .filter_map(|(index, message)| {
+            let message_type = message.message_type()?;
+            let body = message.body();
+            (!body.is_empty()).then_some((index, message, message_type, body))
+        })
+        .enumerate()
+        .map(
+            |(sequence, (index, message, message_type, body))| MessageInput {
+                uuid: message_uuid(&conversation.uuid, index),
+                r#type: message_type,
+                content_text: body.clone(),
+                content_full: body,
+                timestamp: parse_timestamp(message.created_at.as_deref())
+                    .unwrap_or(fallback_timestamp),
+                sequence: sequence as i64,
+                source: Some(CLAUDE_WEB_SOURCE.to_string()),
+                channel: Some(CLAUDE_WEB_CHANNEL.to_string()),
+                model: None,
+                tool_call_id: None,
+                tool_name: None,
+                tool_args: None,
+                raw: None,
+                approval_status: None,
+                approval_resolved_at: None,
+            },
+        )
+        .collect()
+}
+
+/// 消息的确定性 uuid（同一对话的同一序号总是相同）
+pub fn message_uuid(conversation_uuid: &str, index: usize) -> String {
+    Uuid::new_v5(
+        &MESSAGE_NAMESPACE,
+        format!("{}:{}", conversation_uuid, index).as_bytes(),
+    )
+    .to_string()
+}
+
+/// ISO 8601 → 毫秒时间戳
+fn parse_timestamp(value: Option<&str>) -> Option<i64> {
+    value
+        .and_then(|ts| chrono::DateTime::parse_from_rfc3339(ts).ok())
+        .map(|dt| dt.timestamp_millis())
+}
+
+/// 逐个解析对话数组并回调（回调出错时停止解析并返回该错误），返回对话数
+pub(crate) fn for_each_conversation<R: Read>(
+    reader: R,
+    mut callback: impl FnMut(ExportConversation) -> Result<()>,
+) -> Result<usize> {
+    let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(reader));
+    let mut callback_error = None;
+    let result = de::Deserializer::deserialize_seq(
+        &mut deserializer,
+        ConversationVisitor {
+            callback: &mut callback,
+            error: &mut callback_error,
+        },
+    );
+    if let Some(e) = callback_error {
+        return Err(e);
+    }
+    let count = result?;
+    deserializer.end()?;
+    Ok(count)
+}
+
+struct ConversationVisitor<'a, F> {
+    callback: &'a mut F,
+    error: &'a mut Option<Error>,
+}
+
+impl<'de, F> Visitor<'de> for ConversationVisitor<'_, F>
+where
+    F: FnMut(ExportConversation) -> Result<()>,
+{
+    type Value = usize;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array of conversations")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<usize, A::Error> {
+        let mut count = 0;
+        while let Some(conversation) = seq.next_element::<ExportConversation>()? {
+            if let Err(e) = (self.callback)(conversation) {
+                *self.error = Some(e);
+                return Err(de::Error::custom("import aborted"));
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// 记录已读取字节数的 Reader
+    struct CountingReader {
+        inner: Cursor<Vec<u8>>,
+        read: Arc<AtomicUsize>,
+    }
+
+    impl Read for CountingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.read.fetch_add(n, Ordering::Relaxed);
+            Ok(n)
+        }
+    }
+
+    fn conversation_json(i: usize) -> String {
+        format!(
+            r#"{{"uuid":"conv-{i}","name":"Chat {i}","created_at":"2025-01-01T00:00:00Z","chat_messages":[{{"sender":"human","created_at":"2025-01-01T00:00:01Z","text":"question {i} {pad}"}},{{"sender":"assistant","created_at":"2025-01-01T00:00:02Z","text":"answer {i}"}}]}}"#,
+            pad = "x".repeat(1000)
+        )
+    }
+
+    #[test]
+    fn test_conversations_are_parsed_incrementally() {
+        let count = 5_000;
+        let json = format!(
+            "[{}]",
+            (0..count)
+                .map(conversation_json)
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        let total = json.len();
+        let read = Arc::new(AtomicUsize::new(0));
+        let reader = CountingReader {
+            inner: Cursor::new(json.into_bytes()),
+            read: read.clone(),
+        };
+
+        let mut seen = 0;
+        let parsed = for_each_conversation(reader, |conversation| {
+            // 回调时只读取了当前对话附近的数据，而不是整个文件
+            if seen == 0 {
+                assert!(read.load(Ordering::Relaxed) < total / 100);
+            }
+            assert_eq!(conversation.uuid, format!("conv-{}", seen));
+            assert_eq!(convert_messages(&conversation).len(), 2);
+            seen += 1;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(parsed, count);
+        assert_eq!(read.load(Ordering::Relaxed), total);
+    }
+
+    #[test]
+    fn test_callback_error_stops_parsing() {
+        let json = format!("[{},{}]", conversation_json(0), conversation_json(1));
+        let mut seen = 0;
+        let err = for_each_conversation(json.as_bytes(), |_| {
+            seen += 1;
+            Err(Error::NotFound("stop".to_string()))
+        })
+        .unwrap_err();
+        assert!(matches!(err, Error::NotFound(_)));
+        assert_eq!(seen, 1);
+    }
+
+    #[test]
+    fn test_message_uuid_is_deterministic() {
+        assert_eq!(message_uuid("conv-1", 0), message_uuid("conv-1", 0));
+        assert_ne!(message_uuid("conv-1", 0), message_uuid("conv-1", 1));
+        assert_ne!(message_uuid("conv-1", 0), message_uuid("conv-2", 0));
+    }
+}
@@ -0,0 +1,271 @@
+//! 数据一致性不变量检查
+//!
+//! 写入路径的 bug（message_count 漂移、last_message_at 早于最新消息、FTS 索引行数不符）
+//! 往往在生产数据库中几周后才被发现。这里提供低开销的检查：
+//!
+//! - `SessionDB::check_session_invariants`：单个会话，一条走索引的查询
+//! - `SessionDB::check_global_invariants`：随机抽样的会话 + 全局的 FTS 行数
+//!
+//! 每项检查可通过 `DbConfig::invariant_checks` 单独关闭。启用 `paranoid` feature 后，
+//! `insert_messages` 与 `scan_session_incremental` 结束时自动检查所写会话：debug 构建
+//! （测试）中违反时 panic，release 构建中记录错误日志并计数（`paranoid_violation_count`）。
+//! 未启用时写入路径上没有任何额外开销。
+
+use std::fmt;
+#[cfg(feature = "paranoid")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use parking_lot::MutexGuard;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{current_time_ms, fts_enabled, SessionDB};
+use crate::error::{Error, Result};
+
+/// 不变量检查开关（默认全部启用）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InvariantChecks {
+    /// `sessions.message_count` 等于会话的消息行数
+    pub message_count: bool,
+    /// `sessions.last_message_at`（非空时）不早于会话最新消息的时间
+    pub last_message_at: bool,
+    /// FTS 已索引的行数 + fts_backlog 中待补建的行数等于消息行数（仅全局检查）
+    pub fts_row_count: bool,
+}
+
+impl Default for InvariantChecks {
+    fn default() -> Self {
+        Self {
+            message_count: true,
+            last_message_at: true,
+            fts_row_count: true,
+        }
+    }
+}
+
+impl InvariantChecks {
+    /// 关闭所有检查
+    pub fn none() -> Self {
+        Self {
+            message_count: false,
+            last_message_at: false,
+            fts_row_count: false,
+        }
+    }
+}
+
+/// 不变量类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InvariantKind {
+    MessageCount,
+    LastMessageAt,
+    FtsRowCount,
+}
+
+/// 一次违反
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvariantViolation {
+    pub kind: InvariantKind,
+    /// 所属会话（全局不变量为 None）
+    pub session_id: Option<String>,
+    /// 按消息表计算的值
+    pub expected: i64,
+    /// 实际记录的值
+    pub actual: i64,
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self.kind {
+            InvariantKind::MessageCount => "message_count",
+            InvariantKind::LastMessageAt => "last_message_at",
+            InvariantKind::FtsRowCount => "FTS row count",
+        };
+        match &self.session_id {
+            Some(session_id) => write!(
+                f,
+                "session {}: {} is {}, expected {}",
+                session_id, what, self.actual, self.expected
+            ),
+            None => write!(f, "{} is {}, expected {}", what, self.actual, self.expected),
+        }
+    }
+}
+
+/// 全局检查结果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvariantReport {
+    /// 检查的会话数
+    pub sessions_checked: usize,
+    pub violations: Vec<InvariantViolation>,
+    /// 检查时间（毫秒）
+    pub checked_at: i64,
+}
+
+/// paranoid 模式下（release 构建）发现的违反次数
+#[cfg(feature = "paranoid")]
+static PARANOID_VIOLATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// paranoid 模式下发现的违反次数（未启用 `paranoid` feature 时始终为 0）
+pub fn paranoid_violation_count() -> usize {
+    #[cfg(feature = "paranoid")]
+    {
+        PARANOID_VIOLATIONS.load(Ordering::Relaxed)
+    }
+    #[cfg(not(feature = "paranoid"))]
+    {
+        0
+    }
+}
+
+impl SessionDB {
+    /// 检查单个会话的不变量（按 `DbConfig::invariant_checks`）
+    ///
+    /// 会话不存在时返回 `Error::NotFound`。
+    pub fn check_session_invariants(&self, session_id: &str) -> Result<Vec<InvariantViolation>> {
+        let checks = self.config().invariant_checks;
+        let conn = self.conn.lock();
+        session_violations(&conn, session_id, &checks)?
+            .ok_or_else(|| Error::NotFound(format!("session {}", session_id)))
+    }
+
+    /// 随机抽取最多 `sample_size` 个会话检查不变量，并检查全局的 FTS 行数
+    pub fn check_global_invariants(&self, sample_size: usize) -> Result<InvariantReport> {
+        let checks = self.config().invariant_checks;
+        let conn = self.conn.lock();
+
+        let session_ids: Vec<String> = {
+            let mut stmt =
+                conn.prepare("SELECT session_id FROM sessions ORDER BY RANDOM() LIMIT ?1")?;
+            let rows = stmt.query_map(params![sample_size as i64], |row| row.get(0))?;
+            rows.collect::<std::result::Result<Vec<_>, _>>()?
+        };
+
+        let mut violations = Vec::new();
+        for session_id in &session_ids {
+            if let Some(found) = session_violations(&conn, session_id, &checks)? {
+                violations.extend(found);
+            }
+        }
+        if checks.fts_row_count {
+            violations.extend(fts_violation(&conn)?);
+        }
+
+        Ok(InvariantReport {
+            sessions_checked: session_ids.len(),
+            violations,
+            checked_at: current_time_ms(),
+        })
+    }
+
+    /// paranoid 模式：写入后检查会话，违反时 panic（debug）或记录并计数（release）
+    ///
+    /// - check_last_message_at: last_message_at 由调用方稍后更新时（如单独的
+    ///   `insert_messages`）传 false
+    #[cfg(feature = "paranoid")]
+    pub(crate) fn paranoid_check(&self, session_id: &str, check_last_message_at: bool) {
+        let checks = InvariantChecks {
+            last_message_at: check_last_message_at
+                && self.config().invariant_checks.last_message_at,
+            fts_row_count: false,
+            ..self.config().invariant_checks
+        };
+        let result = {
+            let conn = self.conn.lock();
+            session_violations(&conn, session_id, &checks)
+        };
+        let violations = match result {
+            Ok(Some(violations)) => violations,
+            Ok(None) => return,
+            Err(e) => {
+                tracing::warn!("Invariant check failed to run for {}: {}", session_id, e);
+                return;
+            }
+        };
+        for violation in violations {
+            if cfg!(debug_assertions) {
+                panic!("invariant violated: {}", violation);
+            }
+            PARANOID_VIOLATIONS.fetch_add(1, Ordering::Relaxed);
+            tracing::error!("Invariant violated: {}", violation);
+        }
+    }
+}
+
+/// 检查单个会话（会话不存在时返回 None）
+fn session_violations(
+    conn: &MutexGuard<Connection>,
+    session_id: &str,
+    checks: &InvariantChecks,
+) -> Result<Option<Vec<InvariantViolation>>> {
+    let row = conn
+        .query_row(
+            r#"
+            SELECT s.message_count, s.last_message_at,
+                   (SELECT COUNT(*) FROM messages WHERE session_id = s.session_id),
+                   (SELECT MAX(timestamp) FROM messages WHERE session_id = s.session_id)
+            FROM sessions s
+            WHERE s.session_id = ?1
+            "#,
+            params![session_id],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, Option<i64>>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, Option<i64>>(3)?,
+                ))
+            },
+        )
+        .optional()?;
+    let Some((message_count, last_message_at, actual_count, newest_at)) = row else {
+        return Ok(None);
+    };
+
+    let mut violations = Vec::new();
+    if checks.message_count && message_count != actual_count {
+        violations.push(InvariantViolation {
+            kind: InvariantKind::MessageCount,
+            session_id: Some(session_id.to_string()),
+            expected: actual_count,
+            actual: message_count,
+        });
+    }
+    // last_message_at 为空表示尚未记录（只经 insert_messages 写入的会话），不算违反
+    if let (true, Some(last), Some(newest)) = (checks.last_message_at, last_message_at, newest_at) {
+        if last < newest {
+            violations.push(InvariantViolation {
+                kind: InvariantKind::LastMessageAt,
+                session_id: Some(session_id.to_string()),
+                expected: newest,
+                actual: last,
+            });
+        }
+    }
+    Ok(Some(violations))
+}
+
+/// FTS 已索引行数（docsize 影子表）+ 待补建行数 应等于消息行数（未启用 FTS 时不检查）
+fn fts_violation(conn: &MutexGuard<Connection>) -> Result<Option<InvariantViolation>> {
+    if !fts_enabled(conn)? {
+        return Ok(None);
+    }
+    let (messages, indexed): (i64, i64) = conn.query_row(
+        r#"
+        SELECT (SELECT COUNT(*) FROM messages),
+               (SELECT COUNT(*) FROM messages_fts_docsize) + (SELECT COUNT(*) FROM fts_backlog)
+        "#,
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    Ok((messages != indexed).then_some(InvariantViolation {
+        kind: InvariantKind::FtsRowCount,
+        session_id: None,
+        expected: messages,
+        actual: indexed,
+    }))
+}
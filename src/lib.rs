@@ -8,6 +8,7 @@
 //! - **全文搜索**: FTS5 支持
 //! - **增量扫描**: 基于时间戳的增量更新
 //! - **Agent 模式**: 唯一 Writer + 文件监听 + 事件推送
+//! - **统一读取门面**: `SessionStore::auto()` 自动选择 Agent / 数据库 / 文件系统后端
 //!
 //! # Feature Flags
 //!
@@ -24,13 +25,26 @@
 //! 所有写入操作统一通过 vimo-agent 处理，其他组件使用 AgentClient 进行通信。
 //! 这消除了多组件同时写入 DB 的冲突问题。
 
+pub mod audit;
 pub mod config;
+pub mod config_file;
 pub mod db;
 pub mod error;
+pub mod facade;
+pub mod forget;
+pub mod ignore;
+pub mod invariants;
 pub mod migrations;
+pub mod observer;
+pub mod pagination;
 pub mod protocol;
 pub mod reader;
+pub mod rederive;
+pub mod replication;
+pub mod salvage;
 pub mod schema;
+pub mod search_log;
+pub mod share;
 pub mod types;
 
 #[cfg(feature = "writer")]
@@ -39,6 +53,12 @@ pub mod writer;
 #[cfg(feature = "writer")]
 pub mod collector;
 
+#[cfg(feature = "writer")]
+pub mod importer;
+
+#[cfg(feature = "writer")]
+pub mod router;
+
 #[cfg(feature = "search")]
 pub mod search;
 
@@ -60,19 +80,49 @@ pub mod sync;
 pub mod repair;
 
 // Re-exports
+pub use audit::{ApprovalAuditRow, AuditFormat};
 pub use config::DbConfig;
-pub use db::{IntegrityCheckResult, MessageInput, ProjectWithSource, SessionDB, SessionInput};
+pub use db::{
+    CollectBatch, ConflictPolicy, InsertReport, IntegrityCheckResult, MessageInput,
+    ProjectWithSource, SessionDB, SessionInput,
+};
 pub use error::{Error, Result};
+pub use facade::SessionStore;
+pub use forget::ForgetReport;
+pub use ignore::IgnoreRules;
+pub use invariants::{InvariantChecks, InvariantKind, InvariantReport, InvariantViolation};
+pub use migrations::SchemaCompatibility;
+pub use observer::{ChangeEvent, SubscriptionId};
 pub use reader::{
-    MessagesResult, Order, ProjectInfo, RawMessagesResult, SessionMetrics, SessionReader,
+    diff_messages, CharsPerTokenEstimator, MessageDiff, MessagesResult, Order, ProjectInfo,
+    RawMessagesResult, ReconciledProject, SessionMetrics, SessionReader, TokenEstimator,
+};
+pub use rederive::{extract_content_text, RederiveProgress};
+pub use replication::{JsonlFileSink, ReplicationLag, ReplicationRecord, ReplicationSink};
+#[cfg(feature = "replication-http")]
+pub use replication::HttpPostSink;
+pub use salvage::{SalvageReport, TableSalvage};
+pub use search_log::{SearchLogEntry, SearchLogInput};
+pub use share::{
+    import_share_bundle, render_share_bundle_markdown, ProjectContext, RedactionPolicy,
+    ShareBundle, ShareOptions, ToolMessageMode,
 };
 pub use types::*;
 
 #[cfg(feature = "writer")]
-pub use collector::{CollectResult, Collector};
+pub use collector::{CollectLimits, CollectOrder, CollectResult, Collector, FilterImpact};
+
+#[cfg(feature = "writer")]
+pub use writer::CollectionFilter;
+
+#[cfg(feature = "writer")]
+pub use importer::{import_claude_export, ImportOptions};
+
+#[cfg(feature = "writer")]
+pub use router::{DatabaseInfo, DbRouter, Routed};
 
 // Protocol types (always available)
-pub use protocol::{ApprovalStatus as AgentApprovalStatus, QueryType, Request, Response};
+pub use protocol::{ApprovalStatus as AgentApprovalStatus, Push, QueryType, Request, Response};
 
 #[cfg(feature = "agent")]
 pub use agent::{Agent, AgentConfig, cleanup_stale_agent, is_agent_running};
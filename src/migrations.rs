@@ -5,13 +5,487 @@
 //! - 表用 CREATE TABLE IF NOT EXISTS
 //! - 列用 ensure_column 检查并补充
 //! - 清理旧的 schema_migrations 系统
+//!
+//! 无法幂等表达的数据迁移（重建表、删除列、改写数据）使用版本化的 [`MigrationStep`]：
+//! - 按版本顺序执行，`user_version` 记录已应用的版本
+//! - 破坏性迁移执行前自动备份到 `backups/pre-migration-{version}-{ts}.db`（保留最近 3 个）
+//! - 新库（没有任何表）由当前 DDL 直接创建为最新 schema，标记为最新版本，不执行历史迁移
+//! - 每个迁移在事务中执行，失败时回滚并在 `migrations_log` 记录错误和恢复指引
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 use crate::schema;
-use rusqlite::{Connection, Result as SqliteResult};
-use tracing::info;
+use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+/// 基线 schema 版本（幂等 DDL 覆盖的部分）
+const BASE_SCHEMA_VERSION: i32 = 1;
+
+/// 当前代码支持的 schema 版本（最后一个版本化迁移）
+///
+/// 数据库的 `user_version` 大于此值说明由更新版本的库写入。
+pub const SUPPORTED_SCHEMA_VERSION: i32 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+/// 写入方至少需要支持的 schema 版本，`ensure_schema` 时写入 metadata 表（只升不降）
+///
+/// v7 起 messages_fts 多了 content_text 列，只支持到更早版本的写入方插入 FTS 时列数不符；
+/// v8 起更新消息内容后由写入方重新建索引，更早版本的写入方更新后消息会从索引中消失。
+/// 新的迁移改变了写入方的前提（新增必填列、写入路径需要维护的表）时同步提高。
+pub const MIN_WRITER_SCHEMA_VERSION: i32 = 8;
+
+/// 最低写入方版本在 metadata 表中的 key
+pub const MIN_WRITER_SCHEMA_KEY: &str = "min_writer_schema_version";
+
+/// 迁移前备份保留数量
+const BACKUP_RETENTION: usize = 3;
+
+/// 备份文件名前缀
+const BACKUP_PREFIX: &str = "pre-migration-";
+
+/// 版本化迁移步骤
+pub struct MigrationStep {
+    /// 目标版本（必须大于基线版本且递增）
+    pub version: i32,
+    /// 描述
+    pub description: &'static str,
+    /// 是否为破坏性迁移（重建表、删除列、改写已有行），执行前自动备份
+    ///
+    /// 只替换触发器、不改动任何行的迁移为 false。
+    pub destructive: bool,
+    /// 迁移实现
+    pub apply: fn(&Connection) -> SqliteResult<()>,
+}
+
+/// 版本化迁移列表（按版本递增）
+const MIGRATIONS: &[MigrationStep] = &[
+    MigrationStep {
+        version: 2,
+        description: "按 created_at 回填 talks.position",
+        destructive: true,
+        apply: backfill_talk_positions,
+    },
+    MigrationStep {
+        version: 3,
+        description: "messages_fts 改为外部内容表并重建索引",
+        destructive: true,
+        apply: convert_messages_fts_to_external_content,
+    },
+    MigrationStep {
+        version: 4,
+        description: "按已有消息回填 sessions.first_message_at",
+        destructive: true,
+        apply: backfill_session_first_message_at,
+    },
+    MigrationStep {
+        version: 5,
+        description: "messages_fts 改为写入路径显式插入（FTS 失败不丢消息）",
+        destructive: false,
+        apply: decouple_messages_fts_insert,
+    },
+    MigrationStep {
+        version: 6,
+        description: "按 raw 回填 messages.sidechain",
+        destructive: true,
+        apply: backfill_message_sidechain,
+    },
+    MigrationStep {
+        version: 7,
+        description: "messages_fts 增加 content_text 列并重建索引",
+        destructive: true,
+        apply: add_content_text_to_messages_fts,
+    },
+    MigrationStep {
+        version: 8,
+        description: "messages_au 只在内容变化时删除旧索引（重新索引由写入路径完成）",
+        destructive: false,
+        apply: decouple_messages_fts_update,
+    },
+    MigrationStep {
+        version: 9,
+        description: "规范化 projects.path 并合并规范化后重复的项目",
+        destructive: true,
+        apply: normalize_project_paths,
+    },
+];
+
+/// v2: 已有 Talk 按创建顺序回填 position
+fn backfill_talk_positions(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        r#"
+        UPDATE talks SET position = (
+            SELECT COUNT(*) FROM talks AS t2
+            WHERE t2.session_id = talks.session_id
+              AND (t2.created_at, t2.id) < (talks.created_at, talks.id)
+        )
+        "#,
+    )
+}
+
+/// v3 创建的 messages_fts 和触发器（编写 v3 时的定义）
+///
+/// 迁移只执行自己的 SQL：`schema::FTS_SCHEMA_SQL` 之后的修改（如 v7 新增的 content_text 列）
+/// 不能改变已发布的迁移的行为。
+const V3_MESSAGES_FTS_SQL: &str = r#"
+CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
+    content_full,
+    content='messages',
+    content_rowid='id',
+    tokenize='unicode61'
+);
+
+CREATE TRIGGER IF NOT EXISTS messages_ai AFTER INSERT ON messages BEGIN
+    INSERT INTO messages_fts(rowid, content_full) VALUES (new.id, new.content_full);
+END;
+
+CREATE TRIGGER IF NOT EXISTS messages_ad AFTER DELETE ON messages BEGIN
+    INSERT INTO messages_fts(messages_fts, rowid, content_full) VALUES('delete', old.id, old.content_full);
+END;
+
+CREATE TRIGGER IF NOT EXISTS messages_au AFTER UPDATE ON messages BEGIN
+    INSERT INTO messages_fts(messages_fts, rowid, content_full) VALUES('delete', old.id, old.content_full);
+    INSERT INTO messages_fts(rowid, content_full) VALUES (new.id, new.content_full);
+END;
+"#;
+
+/// v5 创建的 fts_backlog 和触发器（编写 v5 时的定义，只索引 content_full）
+const V5_MESSAGES_FTS_SQL: &str = r#"
+CREATE TABLE IF NOT EXISTS fts_backlog (
+    message_id INTEGER PRIMARY KEY, -- messages.id
+    uuid TEXT NOT NULL,
+    error TEXT,                     -- 最近一次失败原因
+    attempts INTEGER NOT NULL DEFAULT 1,
+    created_at INTEGER NOT NULL
+);
+
+CREATE TRIGGER IF NOT EXISTS messages_ad AFTER DELETE ON messages BEGIN
+    INSERT INTO messages_fts(messages_fts, rowid, content_full)
+        SELECT 'delete', old.id, old.content_full
+        WHERE NOT EXISTS (SELECT 1 FROM fts_backlog WHERE message_id = old.id);
+    DELETE FROM fts_backlog WHERE message_id = old.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS messages_au AFTER UPDATE ON messages BEGIN
+    INSERT INTO messages_fts(messages_fts, rowid, content_full)
+        SELECT 'delete', old.id, old.content_full
+        WHERE NOT EXISTS (SELECT 1 FROM fts_backlog WHERE message_id = old.id);
+    DELETE FROM fts_backlog WHERE message_id = old.id;
+    INSERT INTO messages_fts(rowid, content_full) VALUES (new.id, new.content_full);
+END;
+"#;
+
+/// v7 创建的 messages_fts 和触发器（编写 v7 时的定义，增加 content_text 列）
+const V7_MESSAGES_FTS_SQL: &str = r#"
+CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
+    content_full,
+    content_text,
+    content='messages',
+    content_rowid='id',
+    tokenize='unicode61'
+);
+
+CREATE TRIGGER IF NOT EXISTS messages_ad AFTER DELETE ON messages BEGIN
+    INSERT INTO messages_fts(messages_fts, rowid, content_full, content_text)
+        SELECT 'delete', old.id, old.content_full, old.content_text
+        WHERE NOT EXISTS (SELECT 1 FROM fts_backlog WHERE message_id = old.id);
+    DELETE FROM fts_backlog WHERE message_id = old.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS messages_au AFTER UPDATE ON messages BEGIN
+    INSERT INTO messages_fts(messages_fts, rowid, content_full, content_text)
+        SELECT 'delete', old.id, old.content_full, old.content_text
+        WHERE NOT EXISTS (SELECT 1 FROM fts_backlog WHERE message_id = old.id);
+    DELETE FROM fts_backlog WHERE message_id = old.id;
+    INSERT INTO messages_fts(rowid, content_full, content_text)
+        VALUES (new.id, new.content_full, new.content_text);
+END;
+"#;
+
+/// v8 创建的 messages_au 触发器（编写 v8 时的定义）
+const V8_MESSAGES_FTS_SQL: &str = r#"
+CREATE TRIGGER IF NOT EXISTS messages_au AFTER UPDATE OF content_full, content_text ON messages
+WHEN old.content_full IS NOT new.content_full OR old.content_text IS NOT new.content_text
+BEGIN
+    INSERT INTO messages_fts(messages_fts, rowid, content_full, content_text)
+        SELECT 'delete', old.id, old.content_full, old.content_text
+        WHERE NOT EXISTS (SELECT 1 FROM fts_backlog WHERE message_id = old.id);
+    DELETE FROM fts_backlog WHERE message_id = old.id;
+END;
+"#;
+
+/// v3: 独立存储内容的旧 messages_fts 改为外部内容表（content='messages'）
+///
+/// 旧表会复制一份 content_full，改为外部内容表后只保存倒排索引。
+/// 重建索引需要扫描全部消息，大库耗时较长。
+fn convert_messages_fts_to_external_content(conn: &Connection) -> SqliteResult<()> {
+    let sql: Option<String> = conn
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'messages_fts'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(sql) = sql else {
+        // 未启用 FTS
+        return Ok(());
+    };
+
+    let normalized: String = sql
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    if normalized.contains("content='messages'") || normalized.contains("content=\"messages\"") {
+        return Ok(());
+    }
 
-/// 当前 schema 版本（用于未来可能的数据迁移）
-const SCHEMA_VERSION: i32 = 1;
+    info!("messages_fts 为独立内容表，转换为外部内容表并重建索引...");
+    conn.execute_batch(
+        r#"
+        DROP TRIGGER IF EXISTS messages_ai;
+        DROP TRIGGER IF EXISTS messages_ad;
+        DROP TRIGGER IF EXISTS messages_au;
+        DROP TABLE messages_fts;
+        "#,
+    )?;
+    conn.execute_batch(V3_MESSAGES_FTS_SQL)?;
+    conn.execute_batch("INSERT INTO messages_fts(messages_fts) VALUES('rebuild')")
+}
+
+/// v5: 删除 messages_ai 触发器，按新定义重建 messages_ad / messages_au
+///
+/// 触发器中的 FTS 插入失败会让整条消息写入失败；改为写入路径在 SAVEPOINT 中显式插入，
+/// 失败的消息记入 fts_backlog。删除 / 更新触发器需要跳过 fts_backlog 中尚未建索引的消息。
+fn decouple_messages_fts_insert(conn: &Connection) -> SqliteResult<()> {
+    let fts_exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'messages_fts')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !fts_exists {
+        // 未启用 FTS
+        return Ok(());
+    }
+
+    conn.execute_batch(
+        r#"
+        DROP TRIGGER IF EXISTS messages_ai;
+        DROP TRIGGER IF EXISTS messages_ad;
+        DROP TRIGGER IF EXISTS messages_au;
+        "#,
+    )?;
+    conn.execute_batch(V5_MESSAGES_FTS_SQL)
+}
+
+/// v4: 已有会话按消息的最早时间回填 first_message_at
+fn backfill_session_first_message_at(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        r#"
+        UPDATE sessions SET first_message_at = (
+            SELECT MIN(timestamp) FROM messages WHERE messages.session_id = sessions.session_id
+        )
+        WHERE first_message_at IS NULL
+        "#,
+    )
+}
+
+/// v6: 已有消息按原始 JSONL 的 isSidechain 回填 sidechain
+fn backfill_message_sidechain(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        r#"
+        UPDATE messages SET sidechain = 1
+        WHERE raw LIKE '%"isSidechain":true%'
+          AND CASE WHEN json_valid(raw) THEN json_extract(raw, '$.isSidechain') END = 1
+        "#,
+    )
+}
+
+/// v7: messages_fts 增加 content_text 列（按纯对话文本搜索），重建索引
+///
+/// 与 v3 相同，重建索引需要扫描全部消息；fts_backlog 中的消息随重建一并索引。
+/// 新库的 messages_fts 由 `FTS_SCHEMA_SQL` 创建时已有 content_text 列，但 v5 按旧定义
+/// 重建了 messages_ad / messages_au，因此触发器总是按 v7 的定义重建。
+fn add_content_text_to_messages_fts(conn: &Connection) -> SqliteResult<()> {
+    let sql: Option<String> = conn
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'messages_fts'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(sql) = sql else {
+        // 未启用 FTS
+        return Ok(());
+    };
+    let rebuild = !sql.contains("content_text");
+
+    conn.execute_batch(
+        r#"
+        DROP TRIGGER IF EXISTS messages_ad;
+        DROP TRIGGER IF EXISTS messages_au;
+        "#,
+    )?;
+    if rebuild {
+        info!("messages_fts 增加 content_text 列，重建索引...");
+        conn.execute_batch("DROP TABLE messages_fts")?;
+    }
+    conn.execute_batch(V7_MESSAGES_FTS_SQL)?;
+    if rebuild {
+        conn.execute_batch(
+            "INSERT INTO messages_fts(messages_fts) VALUES('rebuild');
+             DELETE FROM fts_backlog;",
+        )?;
+    }
+    Ok(())
+}
+
+/// v8: messages_au 只在内容变化时删除旧索引，新内容由写入路径在 SAVEPOINT 中建索引
+///
+/// 与 v5 的插入相同：FTS 失败时消息记入 fts_backlog，不再让更新失败；
+/// 审批状态、vector_indexed 等与内容无关的更新不再写 FTS。
+/// 删除旧索引需要更新前的内容，仍由触发器完成（与 messages_ad 相同）。
+fn decouple_messages_fts_update(conn: &Connection) -> SqliteResult<()> {
+    if !table_exists(conn, "fts_backlog")? {
+        // 未启用 FTS
+        return Ok(());
+    }
+    conn.execute_batch("DROP TRIGGER IF EXISTS messages_au")?;
+    conn.execute_batch(V8_MESSAGES_FTS_SQL)
+}
+
+/// v9: 已有项目路径按 `normalize_project_path` 规范化，规范化后相同的项目合并
+///
+/// 查找项目只比较规范路径，规范化之前写入的 `/a/b/` 等路径不迁移会被当作新项目。
+/// 保留的项目依次优先：路径已规范、会话最多、id 最小；其余项目的会话移到保留的项目，
+/// ignored 取任一，encoded_dir_name / repo_url 缺失时取被合并项目的值。
+fn normalize_project_paths(conn: &Connection) -> SqliteResult<()> {
+    let mut stmt = conn.prepare(
+        "SELECT p.id, p.path, (SELECT COUNT(*) FROM sessions s WHERE s.project_id = p.id)
+         FROM projects p",
+    )?;
+    let projects = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })?
+        .collect::<SqliteResult<Vec<_>>>()?;
+    drop(stmt);
+
+    let mut groups: BTreeMap<String, Vec<(i64, String, i64)>> = BTreeMap::new();
+    for (id, path, sessions) in projects {
+        groups
+            .entry(crate::db::normalize_project_path(&path))
+            .or_default()
+            .push((id, path, sessions));
+    }
+
+    let mut renamed = 0;
+    let mut merged = 0;
+    for (normalized, mut group) in groups {
+        if group.len() == 1 && group[0].1 == normalized {
+            continue;
+        }
+        group.sort_by_key(|(id, path, sessions)| (*path != normalized, -sessions, *id));
+        let keep = group[0].0;
+        for (dup, _, _) in &group[1..] {
+            conn.execute(
+                "UPDATE sessions SET project_id = ?1 WHERE project_id = ?2",
+                params![keep, dup],
+            )?;
+            conn.execute(
+                r#"
+                UPDATE projects SET
+                    ignored = MAX(ignored, (SELECT ignored FROM projects WHERE id = ?2)),
+                    encoded_dir_name = COALESCE(encoded_dir_name, (SELECT encoded_dir_name FROM projects WHERE id = ?2)),
+                    repo_url = COALESCE(repo_url, (SELECT repo_url FROM projects WHERE id = ?2)),
+                    created_at = MIN(created_at, (SELECT created_at FROM projects WHERE id = ?2))
+                WHERE id = ?1
+                "#,
+                params![keep, dup],
+            )?;
+            conn.execute("DELETE FROM projects WHERE id = ?1", params![dup])?;
+            merged += 1;
+        }
+        if group[0].1 != normalized {
+            conn.execute(
+                "UPDATE projects SET path = ?1 WHERE id = ?2",
+                params![normalized, keep],
+            )?;
+            renamed += 1;
+        }
+    }
+    if renamed > 0 || merged > 0 {
+        info!("规范化 {} 个项目路径，合并 {} 个重复项目", renamed, merged);
+    }
+    Ok(())
+}
+
+/// 待执行的迁移
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PendingMigration {
+    pub version: i32,
+    pub description: String,
+    pub destructive: bool,
+}
+
+/// 数据库与当前代码的版本兼容性（见 `SessionDB::compatibility_check`）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchemaCompatibility {
+    /// 数据库的 schema 版本（`PRAGMA user_version`）
+    pub schema_version: i32,
+    /// 数据库要求写入方至少支持的 schema 版本（未记录时为 0）
+    pub min_writer_schema_version: i32,
+    /// 当前代码支持的 schema 版本（`SUPPORTED_SCHEMA_VERSION`）
+    pub supported_schema_version: i32,
+}
+
+impl SchemaCompatibility {
+    /// 当前代码可以写入该数据库
+    pub fn writable(&self) -> bool {
+        self.supported_schema_version >= self.min_writer_schema_version
+    }
+}
+
+/// 读取数据库的版本兼容性（不修改数据库）
+pub fn compatibility(conn: &Connection) -> SqliteResult<SchemaCompatibility> {
+    let min_writer_schema_version = if table_exists(conn, "metadata")? {
+        conn.query_row(
+            "SELECT CAST(value AS INTEGER) FROM metadata WHERE key = ?1",
+            params![MIN_WRITER_SCHEMA_KEY],
+            |row| row.get(0),
+        )
+        .optional()?
+        .unwrap_or(0)
+    } else {
+        0
+    };
+    Ok(SchemaCompatibility {
+        schema_version: user_version(conn)?,
+        min_writer_schema_version,
+        supported_schema_version: SUPPORTED_SCHEMA_VERSION,
+    })
+}
+
+/// 把最低写入方版本提高到 `MIN_WRITER_SCHEMA_VERSION`（更新版本写入的更高值保持不变）
+fn raise_min_writer_version(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        r#"
+        INSERT INTO metadata (key, value, updated_at) VALUES (?1, ?2, ?3)
+        ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at
+        WHERE CAST(metadata.value AS INTEGER) < CAST(excluded.value AS INTEGER)
+        "#,
+        params![
+            MIN_WRITER_SCHEMA_KEY,
+            MIN_WRITER_SCHEMA_VERSION.to_string(),
+            crate::db::current_time_ms()
+        ],
+    )?;
+    Ok(())
+}
 
 /// 确保数据库 schema 完整（幂等）
 ///
@@ -22,6 +496,7 @@ pub fn ensure_schema(conn: &Connection) -> SqliteResult<()> {
 
     let fts = cfg!(feature = "fts");
     let (tables_sql, indexes_sql, fts_sql) = schema::full_schema_parts(fts);
+    let fresh = is_empty_database(conn)?;
 
     // 1. 创建表（IF NOT EXISTS，幂等）
     conn.execute_batch(&tables_sql)?;
@@ -32,6 +507,7 @@ pub fn ensure_schema(conn: &Connection) -> SqliteResult<()> {
     ensure_projects_columns(conn)?;
     ensure_sessions_columns(conn)?;
     ensure_messages_columns(conn)?;
+    ensure_talks_columns(conn)?;
     info!("列结构已确保");
 
     // 3. 创建索引（IF NOT EXISTS，幂等）
@@ -47,17 +523,171 @@ pub fn ensure_schema(conn: &Connection) -> SqliteResult<()> {
     // 5. 清理旧的迁移系统
     cleanup_old_migration_system(conn)?;
 
-    // 6. 更新 user_version（用于未来可能的数据迁移）
-    let current_version: i32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
-    if current_version < SCHEMA_VERSION {
-        conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
-        info!("user_version 更新: {} -> {}", current_version, SCHEMA_VERSION);
+    // 6. 更新 user_version：新库已是最新 schema，直接标记为最新版本（不执行历史迁移、不备份空库）
+    let current_version = user_version(conn)?;
+    let target_version = if fresh {
+        SUPPORTED_SCHEMA_VERSION
+    } else {
+        BASE_SCHEMA_VERSION
+    };
+    if current_version < target_version {
+        conn.pragma_update(None, "user_version", target_version)?;
+        info!(
+            "user_version 更新: {} -> {}",
+            current_version, target_version
+        );
     }
 
+    // 7. 版本化数据迁移
+    apply_migrations(conn, MIGRATIONS)?;
+
+    // 8. 记录写入方需要支持的最低版本（旧版本写入方据此拒绝写入）
+    raise_min_writer_version(conn)?;
+
     info!("数据库 schema 确保完成");
     Ok(())
 }
 
+/// 列出尚未应用的版本化迁移（不修改数据库）
+///
+/// Agent 在连接数据库（会立即执行迁移）之前调用，用于在 Status 中报告。
+pub fn plan(conn: &Connection) -> SqliteResult<Vec<PendingMigration>> {
+    plan_steps(conn, MIGRATIONS)
+}
+
+fn plan_steps(conn: &Connection, steps: &[MigrationStep]) -> SqliteResult<Vec<PendingMigration>> {
+    // 新库连接时直接标记为最新版本
+    if is_empty_database(conn)? {
+        return Ok(Vec::new());
+    }
+    let current_version = user_version(conn)?;
+    Ok(steps
+        .iter()
+        .filter(|step| step.version > current_version)
+        .map(|step| PendingMigration {
+            version: step.version,
+            description: step.description.to_string(),
+            destructive: step.destructive,
+        })
+        .collect())
+}
+
+pub(crate) fn user_version(conn: &Connection) -> SqliteResult<i32> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+/// 按版本顺序执行未应用的迁移
+///
+/// 破坏性迁移先备份；每个迁移在事务中执行，失败时回滚、记录 migrations_log 并返回错误，
+/// 后续迁移不再执行。
+fn apply_migrations(conn: &Connection, steps: &[MigrationStep]) -> SqliteResult<()> {
+    for step in steps {
+        if step.version <= user_version(conn)? {
+            continue;
+        }
+
+        let backup_path = if step.destructive {
+            backup_before_migration(conn, step.version)?
+        } else {
+            None
+        };
+        let backup_str = backup_path.as_ref().map(|p| p.display().to_string());
+
+        info!("执行迁移 v{}: {}", step.version, step.description);
+        conn.execute_batch("BEGIN IMMEDIATE")?;
+        let applied =
+            (step.apply)(conn).and_then(|_| conn.pragma_update(None, "user_version", step.version));
+
+        match applied {
+            Ok(()) => {
+                conn.execute_batch("COMMIT")?;
+                log_migration(conn, step, "applied", backup_str.as_deref(), None)?;
+            }
+            Err(e) => {
+                conn.execute_batch("ROLLBACK")?;
+                let guidance = match &backup_str {
+                    Some(path) => format!("{}; 已回滚，迁移前备份: {}", e, path),
+                    None => format!("{}; 已回滚", e),
+                };
+                warn!("迁移 v{} 失败: {}", step.version, guidance);
+                log_migration(conn, step, "failed", backup_str.as_deref(), Some(&guidance))?;
+                return Err(e);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn log_migration(
+    conn: &Connection,
+    step: &MigrationStep,
+    status: &str,
+    backup_path: Option<&str>,
+    error: Option<&str>,
+) -> SqliteResult<()> {
+    conn.execute(
+        "INSERT INTO migrations_log (version, description, status, backup_path, error)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![step.version, step.description, status, backup_path, error],
+    )?;
+    Ok(())
+}
+
+/// 迁移前备份目录
+///
+/// 数据库位于 `{data_dir}/db/` 时为 `{data_dir}/backups`，否则为数据库所在目录下的 `backups`。
+fn backup_dir(db_path: &Path) -> Option<PathBuf> {
+    let db_dir = db_path.parent()?;
+    let base = match db_dir.file_name().and_then(|s| s.to_str()) {
+        Some("db") => db_dir.parent().unwrap_or(db_dir),
+        _ => db_dir,
+    };
+    Some(base.join("backups"))
+}
+
+/// 在线备份当前数据库（VACUUM INTO，一致性快照），内存数据库返回 None
+fn backup_before_migration(conn: &Connection, version: i32) -> SqliteResult<Option<PathBuf>> {
+    let db_path = match conn.path() {
+        Some(p) if !p.is_empty() => PathBuf::from(p),
+        _ => return Ok(None),
+    };
+    let Some(dir) = backup_dir(&db_path) else {
+        return Ok(None);
+    };
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+    let ts = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let backup_path = dir.join(format!("{}{}-{}.db", BACKUP_PREFIX, version, ts));
+    conn.execute("VACUUM INTO ?1", [backup_path.display().to_string()])?;
+    info!("迁移前备份: {}", backup_path.display());
+
+    prune_backups(&dir);
+    Ok(Some(backup_path))
+}
+
+/// 只保留最近的 BACKUP_RETENTION 个迁移前备份
+fn prune_backups(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut backups: Vec<(std::time::SystemTime, PathBuf)> = entries
+        .flatten()
+        .filter(|e| e.file_name().to_string_lossy().starts_with(BACKUP_PREFIX))
+        .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+        .collect();
+    backups.sort_by(|a, b| b.0.cmp(&a.0));
+
+    for (_, path) in backups.into_iter().skip(BACKUP_RETENTION) {
+        if let Err(e) = std::fs::remove_file(&path) {
+            warn!("清理旧备份失败 {}: {}", path.display(), e);
+        }
+    }
+}
+
 /// 检查列是否存在
 fn column_exists(conn: &Connection, table: &str, column: &str) -> SqliteResult<bool> {
     let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
@@ -75,6 +705,15 @@ fn column_exists(conn: &Connection, table: &str, column: &str) -> SqliteResult<b
     Ok(false)
 }
 
+/// 数据库中没有任何表（新建的数据库）
+fn is_empty_database(conn: &Connection) -> SqliteResult<bool> {
+    conn.query_row(
+        "SELECT NOT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%')",
+        [],
+        |row| row.get(0),
+    )
+}
+
 /// 检查表是否存在
 fn table_exists(conn: &Connection, table: &str) -> SqliteResult<bool> {
     let count: i64 = conn.query_row(
@@ -109,6 +748,7 @@ fn ensure_column(conn: &Connection, table: &str, column: &str, definition: &str)
 /// 确保 projects 表的所有列存在
 fn ensure_projects_columns(conn: &Connection) -> SqliteResult<()> {
     ensure_column(conn, "projects", "repo_url", "TEXT")?;
+    ensure_column(conn, "projects", "ignored", "INTEGER NOT NULL DEFAULT 0")?;
     Ok(())
 }
 
@@ -116,6 +756,7 @@ fn ensure_projects_columns(conn: &Connection) -> SqliteResult<()> {
 fn ensure_sessions_columns(conn: &Connection) -> SqliteResult<()> {
     // 基础字段（可能在老表中缺失）
     ensure_column(conn, "sessions", "message_count", "INTEGER DEFAULT 0")?;
+    ensure_column(conn, "sessions", "first_message_at", "INTEGER")?;
     ensure_column(conn, "sessions", "last_message_at", "INTEGER")?;
     ensure_column(conn, "sessions", "cwd", "TEXT")?;
     ensure_column(conn, "sessions", "model", "TEXT")?;
@@ -124,6 +765,8 @@ fn ensure_sessions_columns(conn: &Connection) -> SqliteResult<()> {
     ensure_column(conn, "sessions", "file_size", "INTEGER")?;
     ensure_column(conn, "sessions", "file_offset", "INTEGER DEFAULT 0")?;
     ensure_column(conn, "sessions", "file_inode", "INTEGER")?;
+    ensure_column(conn, "sessions", "content_hash", "TEXT")?;
+    ensure_column(conn, "sessions", "session_path", "TEXT")?;
     ensure_column(conn, "sessions", "encoded_dir_name", "TEXT")?;
     ensure_column(conn, "sessions", "meta", "TEXT")?;
     ensure_column(
@@ -159,7 +802,18 @@ fn ensure_messages_columns(conn: &Connection) -> SqliteResult<()> {
     ensure_column(conn, "messages", "vector_indexed", "INTEGER DEFAULT 0")?;
     ensure_column(conn, "messages", "approval_status", "TEXT")?;
     ensure_column(conn, "messages", "approval_resolved_at", "INTEGER")?;
+    ensure_column(conn, "messages", "truncated", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column(conn, "messages", "turn_index", "INTEGER")?;
+    ensure_column(conn, "messages", "sidechain", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column(conn, "messages", "is_summary", "INTEGER NOT NULL DEFAULT 0")?;
+
+    Ok(())
+}
 
+/// 确保 talks 表的所有列存在
+fn ensure_talks_columns(conn: &Connection) -> SqliteResult<()> {
+    ensure_column(conn, "talks", "position", "INTEGER")?;
+    ensure_column(conn, "talks", "first_message_sequence", "INTEGER")?;
     Ok(())
 }
 
@@ -198,6 +852,12 @@ mod tests {
         assert!(table_exists(&conn, "sessions").unwrap());
         assert!(table_exists(&conn, "messages").unwrap());
         assert!(table_exists(&conn, "talks").unwrap());
+        assert!(table_exists(&conn, "message_revisions").unwrap());
+        assert!(table_exists(&conn, "collection_ignores").unwrap());
+        assert!(table_exists(&conn, "vector_tombstones").unwrap());
+        assert!(table_exists(&conn, "migrations_log").unwrap());
+        assert!(table_exists(&conn, "collection_lock").unwrap());
+        assert!(column_exists(&conn, "projects", "ignored").unwrap());
 
         // 验证关键列存在
         assert!(column_exists(&conn, "sessions", "file_offset").unwrap());
@@ -244,11 +904,42 @@ mod tests {
         assert!(column_exists(&conn, "sessions", "cwd").unwrap());
         assert!(column_exists(&conn, "messages", "approval_status").unwrap());
         assert!(column_exists(&conn, "messages", "source").unwrap());
+        assert!(column_exists(&conn, "messages", "vector_indexed").unwrap());
+        assert!(column_exists(&conn, "messages", "truncated").unwrap());
+        assert!(table_exists(&conn, "vector_tombstones").unwrap());
 
         // 验证旧迁移系统被清理
         assert!(!table_exists(&conn, "schema_migrations").unwrap());
     }
 
+    #[test]
+    fn test_min_writer_version_never_lowered() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert_eq!(compatibility(&conn).unwrap().min_writer_schema_version, 0);
+
+        ensure_schema(&conn).unwrap();
+        let compatibility_now = compatibility(&conn).unwrap();
+        assert_eq!(
+            compatibility_now.min_writer_schema_version,
+            MIN_WRITER_SCHEMA_VERSION
+        );
+        assert!(compatibility_now.writable());
+
+        // 更新版本的库提高了标记：再次 ensure_schema 不降低，当前代码不可写入
+        conn.execute(
+            "UPDATE metadata SET value = ?1 WHERE key = ?2",
+            params![SUPPORTED_SCHEMA_VERSION + 1, MIN_WRITER_SCHEMA_KEY],
+        )
+        .unwrap();
+        ensure_schema(&conn).unwrap();
+        let newer = compatibility(&conn).unwrap();
+        assert_eq!(
+            newer.min_writer_schema_version,
+            SUPPORTED_SCHEMA_VERSION + 1
+        );
+        assert!(!newer.writable());
+    }
+
     #[test]
     fn test_ensure_schema_idempotent() {
         let conn = Connection::open_in_memory().unwrap();
@@ -304,4 +995,437 @@ mod tests {
         assert!(column_exists(&conn, "sessions", "file_offset").unwrap());
         assert!(column_exists(&conn, "sessions", "file_inode").unwrap());
     }
+
+    #[test]
+    fn test_backfill_talk_positions() {
+        // 模拟老数据库：talks 表没有 position 列
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            r#"
+            CREATE TABLE talks (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id TEXT NOT NULL,
+                talk_id TEXT NOT NULL,
+                summary_l2 TEXT NOT NULL,
+                summary_l3 TEXT,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL,
+                UNIQUE(session_id, talk_id)
+            );
+            INSERT INTO talks (session_id, talk_id, summary_l2, created_at, updated_at)
+            VALUES ('s1', 't-late', 'late', 300, 300),
+                   ('s1', 't-early', 'early', 100, 100),
+                   ('s2', 't-only', 'only', 200, 200);
+            "#,
+        )
+        .unwrap();
+
+        ensure_schema(&conn).unwrap();
+
+        let position = |talk_id: &str| -> Option<i64> {
+            conn.query_row(
+                "SELECT position FROM talks WHERE talk_id = ?1",
+                [talk_id],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(position("t-early"), Some(0));
+        assert_eq!(position("t-late"), Some(1));
+        assert_eq!(position("t-only"), Some(0));
+    }
+
+    #[test]
+    fn test_backfill_session_first_message_at() {
+        // 模拟老数据库：sessions 表没有 first_message_at 列
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            r#"
+            CREATE TABLE sessions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id TEXT NOT NULL UNIQUE,
+                project_id INTEGER NOT NULL,
+                message_count INTEGER NOT NULL DEFAULT 0,
+                last_message_at INTEGER
+            );
+            CREATE TABLE messages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id TEXT NOT NULL,
+                uuid TEXT NOT NULL UNIQUE,
+                type TEXT NOT NULL,
+                content_text TEXT NOT NULL,
+                content_full TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                sequence INTEGER NOT NULL
+            );
+            INSERT INTO sessions (session_id, project_id, message_count, last_message_at)
+            VALUES ('s1', 1, 2, 500), ('s2', 1, 0, NULL);
+            INSERT INTO messages (session_id, uuid, type, content_text, content_full, timestamp, sequence)
+            VALUES ('s1', 'm1', 'user', 'a', 'a', 300, 1),
+                   ('s1', 'm0', 'user', 'b', 'b', 200, 0);
+            "#,
+        )
+        .unwrap();
+
+        ensure_schema(&conn).unwrap();
+
+        let first = |session_id: &str| -> Option<i64> {
+            conn.query_row(
+                "SELECT first_message_at FROM sessions WHERE session_id = ?1",
+                [session_id],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(first("s1"), Some(200));
+        assert_eq!(first("s2"), None);
+    }
+
+    #[test]
+    fn test_normalize_project_paths_merges_duplicates() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+
+        // 模拟规范化之前写入的路径
+        conn.execute_batch(
+            r#"
+            INSERT INTO projects (id, path, name, repo_url) VALUES
+                (1, '/a/b/', 'b', 'git@example.com:b.git'),
+                (2, '/a/b', 'b', NULL),
+                (3, '/x/./y', 'y', NULL);
+            UPDATE projects SET ignored = 1 WHERE id = 1;
+            INSERT INTO sessions (session_id, project_id) VALUES ('s1', 1), ('s2', 2), ('s3', 3);
+            PRAGMA user_version = 8;
+            "#,
+        )
+        .unwrap();
+
+        ensure_schema(&conn).unwrap();
+        assert_eq!(user_version(&conn).unwrap(), SUPPORTED_SCHEMA_VERSION);
+
+        let project = |session_id: &str| -> (i64, String) {
+            conn.query_row(
+                "SELECT p.id, p.path FROM sessions s JOIN projects p ON p.id = s.project_id
+                 WHERE s.session_id = ?1",
+                [session_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap()
+        };
+        // 会话数相同，保留路径已规范的项目
+        assert_eq!(project("s1"), (2, "/a/b".to_string()));
+        assert_eq!(project("s2"), (2, "/a/b".to_string()));
+        assert_eq!(project("s3"), (3, "/x/y".to_string()));
+
+        let (ignored, repo_url): (bool, Option<String>) = conn
+            .query_row(
+                "SELECT ignored, repo_url FROM projects WHERE id = 2",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert!(ignored);
+        assert_eq!(repo_url.as_deref(), Some("git@example.com:b.git"));
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM projects", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    /// 数据库实际占用（VACUUM 后按页统计）
+    #[cfg(feature = "fts")]
+    fn db_size(conn: &Connection) -> i64 {
+        conn.execute_batch("VACUUM").unwrap();
+        let page_count: i64 = conn
+            .query_row("PRAGMA page_count", [], |row| row.get(0))
+            .unwrap();
+        let page_size: i64 = conn
+            .query_row("PRAGMA page_size", [], |row| row.get(0))
+            .unwrap();
+        page_count * page_size
+    }
+
+    #[cfg(feature = "fts")]
+    fn insert_message(conn: &Connection, i: usize, content: &str) {
+        conn.execute(
+            "INSERT INTO messages (session_id, uuid, type, content_text, content_full, timestamp, sequence)
+             VALUES ('s1', ?1, 'user', ?2, ?2, ?3, ?3)",
+            params![format!("m-{}", i), content, i as i64],
+        )
+        .unwrap();
+    }
+
+    #[cfg(feature = "fts")]
+    fn match_count(conn: &Connection, keyword: &str) -> i64 {
+        conn.query_row(
+            "SELECT COUNT(*) FROM messages_fts JOIN messages m ON messages_fts.rowid = m.id
+             WHERE messages_fts MATCH ?1",
+            [keyword],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[cfg(feature = "fts")]
+    #[test]
+    fn test_convert_standalone_fts_to_external_content() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+
+        // 模拟旧库：独立存储内容的 FTS 表
+        conn.execute_batch(
+            r#"
+            DROP TRIGGER messages_ad;
+            DROP TRIGGER messages_au;
+            DROP TABLE messages_fts;
+            CREATE VIRTUAL TABLE messages_fts USING fts5(content_full, tokenize='unicode61');
+            CREATE TRIGGER messages_ai AFTER INSERT ON messages BEGIN
+                INSERT INTO messages_fts(rowid, content_full) VALUES (new.id, new.content_full);
+            END;
+            PRAGMA user_version = 2;
+            "#,
+        )
+        .unwrap();
+
+        let filler = "lorem ipsum dolor sit amet consectetur ".repeat(50);
+        for i in 0..200 {
+            let content = if i % 10 == 0 {
+                format!("needle {}", filler)
+            } else {
+                filler.clone()
+            };
+            insert_message(&conn, i, &content);
+        }
+        assert_eq!(match_count(&conn, "needle"), 20);
+        let standalone_size = db_size(&conn);
+
+        ensure_schema(&conn).unwrap();
+        assert_eq!(user_version(&conn).unwrap(), SUPPORTED_SCHEMA_VERSION);
+
+        let sql: String = conn
+            .query_row(
+                "SELECT sql FROM sqlite_master WHERE name = 'messages_fts'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(sql.contains("content='messages'"));
+
+        // 旧的插入触发器已删除，新消息由写入路径建索引
+        let ai_exists: bool = conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = 'messages_ai')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(!ai_exists);
+
+        // 重建后搜索结果一致，且新消息继续同步
+        assert_eq!(match_count(&conn, "needle"), 20);
+        insert_message(&conn, 200, "needle after migration");
+        let id = conn.last_insert_rowid();
+        assert!(crate::db::index_message_fts(&conn, id, "m-200").unwrap());
+        assert_eq!(match_count(&conn, "needle"), 21);
+        conn.execute("DELETE FROM messages WHERE uuid = 'm-0'", [])
+            .unwrap();
+        assert_eq!(match_count(&conn, "needle"), 20);
+
+        assert!(db_size(&conn) < standalone_size);
+    }
+
+    #[cfg(feature = "fts")]
+    #[test]
+    fn test_add_content_text_to_messages_fts() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+
+        // 模拟 v6：只索引 content_full
+        conn.execute_batch(
+            r#"
+            DROP TRIGGER messages_ad;
+            DROP TRIGGER messages_au;
+            DROP TABLE messages_fts;
+            CREATE VIRTUAL TABLE messages_fts USING fts5(
+                content_full, content='messages', content_rowid='id', tokenize='unicode61'
+            );
+            PRAGMA user_version = 6;
+            "#,
+        )
+        .unwrap();
+        insert_message(&conn, 1, "needle");
+        conn.execute_batch("INSERT INTO messages_fts(messages_fts) VALUES('rebuild');")
+            .unwrap();
+
+        ensure_schema(&conn).unwrap();
+        assert_eq!(user_version(&conn).unwrap(), SUPPORTED_SCHEMA_VERSION);
+        let hits: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM messages_fts WHERE messages_fts MATCH 'content_text : needle'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(hits, 1);
+        assert_eq!(match_count(&conn, "needle"), 1);
+
+        // v8：messages_au 只在内容变化时触发
+        let au: String = conn
+            .query_row(
+                "SELECT sql FROM sqlite_master WHERE name = 'messages_au'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(au.contains("UPDATE OF content_full, content_text"));
+    }
+
+    /// 内置迁移之后的下一个版本号
+    fn next_version() -> i32 {
+        MIGRATIONS
+            .last()
+            .map_or(BASE_SCHEMA_VERSION, |step| step.version)
+            + 1
+    }
+
+    fn drop_notes(conn: &Connection) -> SqliteResult<()> {
+        conn.execute_batch("DROP TABLE notes")?;
+        // 模拟迁移中途失败
+        conn.execute_batch("SELECT * FROM missing_table")
+    }
+
+    fn add_index(conn: &Connection) -> SqliteResult<()> {
+        conn.execute_batch("CREATE INDEX idx_notes_body ON notes(body)")
+    }
+
+    #[test]
+    fn test_plan_lists_pending_steps() {
+        let conn = Connection::open_in_memory().unwrap();
+        ensure_schema(&conn).unwrap();
+
+        let steps = [MigrationStep {
+            version: next_version(),
+            description: "drop notes",
+            destructive: true,
+            apply: drop_notes,
+        }];
+        let pending = plan_steps(&conn, &steps).unwrap();
+        assert_eq!(
+            pending,
+            vec![PendingMigration {
+                version: next_version(),
+                description: "drop notes".to_string(),
+                destructive: true,
+            }]
+        );
+        assert!(plan(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_failed_destructive_migration_rolls_back_with_backup() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir_all(tmp.path().join("db")).unwrap();
+        let conn = Connection::open(tmp.path().join("db").join("test.db")).unwrap();
+        ensure_schema(&conn).unwrap();
+        conn.execute_batch("CREATE TABLE notes (body TEXT); INSERT INTO notes VALUES ('keep me');")
+            .unwrap();
+
+        let steps = [MigrationStep {
+            version: next_version(),
+            description: "drop notes",
+            destructive: true,
+            apply: drop_notes,
+        }];
+        assert!(apply_migrations(&conn, &steps).is_err());
+
+        // 数据完整，版本未变
+        let body: String = conn
+            .query_row("SELECT body FROM notes", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(body, "keep me");
+        assert_eq!(user_version(&conn).unwrap(), next_version() - 1);
+
+        // 备份存在且包含数据
+        let (status, backup_path, error): (String, Option<String>, Option<String>) = conn
+            .query_row(
+                "SELECT status, backup_path, error FROM migrations_log WHERE version = ?1",
+                [next_version()],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(status, "failed");
+        let backup_path = PathBuf::from(backup_path.unwrap());
+        assert!(backup_path.starts_with(tmp.path().join("backups")));
+        assert!(error.unwrap().contains(&backup_path.display().to_string()));
+        let backup = Connection::open(&backup_path).unwrap();
+        let count: i64 = backup
+            .query_row("SELECT COUNT(*) FROM notes", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_upgrade_through_destructive_step_backs_up() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let conn = Connection::open(tmp.path().join("test.db")).unwrap();
+        ensure_schema(&conn).unwrap();
+
+        // 新库直接标记为最新版本：不执行历史迁移，也不备份空库
+        assert_eq!(user_version(&conn).unwrap(), SUPPORTED_SCHEMA_VERSION);
+        assert!(!tmp.path().join("backups").exists());
+
+        // 模拟 v6 的库升级：v7 重建 messages_fts（破坏性），v8 只替换触发器
+        conn.execute("INSERT INTO projects (path, name) VALUES ('/p', 'p')", [])
+            .unwrap();
+        conn.pragma_update(None, "user_version", 6).unwrap();
+        ensure_schema(&conn).unwrap();
+        assert_eq!(user_version(&conn).unwrap(), SUPPORTED_SCHEMA_VERSION);
+
+        let backup_path = |version: i32| -> Option<String> {
+            conn.query_row(
+                "SELECT backup_path FROM migrations_log WHERE version = ?1 AND status = 'applied'",
+                [version],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        let backup = Connection::open(backup_path(7).unwrap()).unwrap();
+        assert_eq!(user_version(&backup).unwrap(), 6);
+        let count: i64 = backup
+            .query_row("SELECT COUNT(*) FROM projects", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(backup_path(8), None);
+    }
+
+    #[test]
+    fn test_non_destructive_migration_applied_without_backup() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let conn = Connection::open(tmp.path().join("test.db")).unwrap();
+        ensure_schema(&conn).unwrap();
+        conn.execute_batch("CREATE TABLE notes (body TEXT);")
+            .unwrap();
+
+        let steps = [MigrationStep {
+            version: next_version(),
+            description: "index notes",
+            destructive: false,
+            apply: add_index,
+        }];
+        apply_migrations(&conn, &steps).unwrap();
+        // 再次执行跳过已应用的版本
+        apply_migrations(&conn, &steps).unwrap();
+
+        assert_eq!(user_version(&conn).unwrap(), next_version());
+        assert!(plan_steps(&conn, &steps).unwrap().is_empty());
+        assert!(!tmp.path().join("backups").exists());
+        let applied: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM migrations_log WHERE status = 'applied' AND version = ?1",
+                [next_version()],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(applied, 1);
+    }
 }
@@ -0,0 +1,109 @@
+//! 进程内变更通知
+//!
+//! 宿主应用直接嵌入写入端（不经过 Agent）时，通过 `SessionDB::subscribe_changes`
+//! 得知后台采集写入了什么。Agent 也经由同一机制把数据库变更转为推送和 WaitForChange 唤醒。
+//!
+//! - 回调在写事务提交、数据库锁释放之后同步调用：不会观察到未提交的状态，回调内可以查询数据库
+//! - 同一 `SessionDB` 的共享句柄共用订阅；另行 `connect` 的连接（包括其他进程）不会收到
+//! - 回调 panic 时捕获并移除该订阅（告警），不影响写入和其他订阅者
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+use crate::db::SessionDB;
+use crate::types::ApprovalStatus;
+
+/// 数据库变更事件
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ChangeEvent {
+    /// 会话写入了新消息
+    NewMessages { session_id: String, count: usize },
+    /// 已存在的消息内容被更新或记录了修订
+    MessagesUpdated { session_id: String, count: usize },
+    /// 会话被创建或更新
+    SessionUpserted { session_id: String },
+    /// 审批状态变化
+    ApprovalChanged {
+        /// 受影响消息所在的会话
+        session_ids: Vec<String>,
+        status: ApprovalStatus,
+        count: usize,
+    },
+    /// 项目状态变化（创建、重命名、被忽略规则排除/恢复、被删除）
+    ProjectChanged {
+        project_id: i64,
+        project_path: String,
+        ignored: bool,
+    },
+}
+
+/// 订阅 ID（`SessionDB::unsubscribe` 使用）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SubscriptionId(pub u64);
+
+type Callback = Arc<dyn Fn(ChangeEvent) + Send + Sync>;
+
+/// 订阅者列表
+#[derive(Default)]
+pub(crate) struct Observers {
+    next_id: AtomicU64,
+    callbacks: RwLock<Vec<(SubscriptionId, Callback)>>,
+}
+
+impl Observers {
+    /// 依次调用所有回调（调用方不得持有数据库锁），panic 的回调被移除
+    pub(crate) fn emit(&self, events: Vec<ChangeEvent>) {
+        if events.is_empty() {
+            return;
+        }
+        // 调用前释放列表锁：回调内可以订阅 / 取消订阅
+        let callbacks = self.callbacks.read().clone();
+        for (id, callback) in callbacks {
+            for event in &events {
+                let event = event.clone();
+                if catch_unwind(AssertUnwindSafe(|| callback(event))).is_err() {
+                    tracing::warn!("Change observer {:?} panicked; unsubscribing it", id);
+                    self.remove(id);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn remove(&self, id: SubscriptionId) -> bool {
+        let mut callbacks = self.callbacks.write();
+        let before = callbacks.len();
+        callbacks.retain(|(existing, _)| *existing != id);
+        callbacks.len() != before
+    }
+}
+
+impl SessionDB {
+    /// 订阅变更事件（见模块文档），返回用于取消订阅的 ID
+    pub fn subscribe_changes(
+        &self,
+        callback: impl Fn(ChangeEvent) + Send + Sync + 'static,
+    ) -> SubscriptionId {
+        let id = SubscriptionId(self.observers.next_id.fetch_add(1, Ordering::Relaxed));
+        self.observers
+            .callbacks
+            .write()
+            .push((id, Arc::new(callback)));
+        id
+    }
+
+    /// 取消订阅，返回订阅是否存在
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        self.observers.remove(id)
+    }
+
+    /// 通知订阅者（在提交且释放数据库锁之后调用）
+    pub(crate) fn notify(&self, events: Vec<ChangeEvent>) {
+        self.observers.emit(events);
+    }
+}
@@ -0,0 +1,249 @@
+//! 列表 API 的分页 token
+//!
+//! `*_page` 系列方法不再让调用方自己维护 limit/offset，而是在 `Page::next_page_token`
+//! 中返回不透明 token，原样传回即可取下一页。token 是 base64(JSON)，内含：
+//! - 签发的 API 与查询范围（项目路径、会话 ID、搜索条件的哈希），换一个 API 或查询使用时报错
+//! - 游标：偏移量，或上一页最后一行的排序键（键集分页，翻页期间插入新行不会让已翻过的行
+//!   重复或跳过；排序键本身变化的行不在此保证内，见 `list_sessions_page`）
+//! - 校验和：检测被截断或手工修改的 token
+//!
+//! token 不是安全边界（校验和不含密钥），只用于尽早发现误用。
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::db::{content_hash, SessionDB};
+use crate::error::{Error, Result};
+use crate::reader::Order;
+use crate::types::{Message, Page, PageToken, ProjectWithStats, SessionWithProject};
+
+/// 签发 token 的列表 API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PageApi {
+    Projects,
+    Sessions,
+    Messages,
+    #[cfg(feature = "search")]
+    Search,
+}
+
+/// 翻页位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PageCursor {
+    /// 跳过的行数
+    Offset(usize),
+    /// 上一页最后一行的 (排序键, id)
+    After(i64, i64),
+}
+
+#[derive(Serialize, Deserialize)]
+struct TokenPayload {
+    api: PageApi,
+    /// 查询范围的哈希
+    scope: String,
+    cursor: PageCursor,
+    /// api + scope + cursor 的校验和
+    sum: String,
+}
+
+fn checksum(api: PageApi, scope: &str, cursor: PageCursor) -> String {
+    content_hash(&format!("{:?}|{}|{:?}", api, scope, cursor))
+}
+
+impl PageToken {
+    pub(crate) fn encode(api: PageApi, scope: &str, cursor: PageCursor) -> Self {
+        let scope = content_hash(scope);
+        let payload = TokenPayload {
+            api,
+            sum: checksum(api, &scope, cursor),
+            scope,
+            cursor,
+        };
+        let json = serde_json::to_vec(&payload).expect("page token payload is serializable");
+        Self(URL_SAFE_NO_PAD.encode(json))
+    }
+
+    /// 解析 token，校验签发的 API、查询范围和校验和
+    pub(crate) fn decode(&self, api: PageApi, scope: &str) -> Result<PageCursor> {
+        let payload: TokenPayload = URL_SAFE_NO_PAD
+            .decode(&self.0)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or_else(|| Error::InvalidPageToken("malformed token".to_string()))?;
+
+        if payload.sum != checksum(payload.api, &payload.scope, payload.cursor) {
+            return Err(Error::InvalidPageToken("checksum mismatch".to_string()));
+        }
+        if payload.api != api {
+            return Err(Error::InvalidPageToken(format!(
+                "token was issued by {:?}, not {:?}",
+                payload.api, api
+            )));
+        }
+        if payload.scope != content_hash(scope) {
+            return Err(Error::InvalidPageToken(
+                "token was issued for a different query".to_string(),
+            ));
+        }
+        Ok(payload.cursor)
+    }
+
+    /// 解析偏移分页的 token（None 表示第一页）
+    pub(crate) fn decode_offset(token: Option<&Self>, api: PageApi, scope: &str) -> Result<usize> {
+        match token.map(|t| t.decode(api, scope)).transpose()? {
+            None => Ok(0),
+            Some(PageCursor::Offset(offset)) => Ok(offset),
+            Some(PageCursor::After(..)) => Err(Error::InvalidPageToken(
+                "unexpected keyset cursor".to_string(),
+            )),
+        }
+    }
+
+    /// 解析键集分页的 token（None 表示第一页）
+    pub(crate) fn decode_after(
+        token: Option<&Self>,
+        api: PageApi,
+        scope: &str,
+    ) -> Result<Option<(i64, i64)>> {
+        match token.map(|t| t.decode(api, scope)).transpose()? {
+            None => Ok(None),
+            Some(PageCursor::After(key, id)) => Ok(Some((key, id))),
+            Some(PageCursor::Offset(_)) => Err(Error::InvalidPageToken(
+                "unexpected offset cursor".to_string(),
+            )),
+        }
+    }
+}
+
+impl<T> Page<T> {
+    /// 由多取一行（limit + 1）的查询结果组成一页：有多余的行才签发下一页 token
+    pub(crate) fn from_overfetch(
+        mut items: Vec<T>,
+        limit: usize,
+        next: impl FnOnce(&T) -> PageToken,
+    ) -> Self {
+        let next_page_token = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(next)
+        } else {
+            None
+        };
+        Self {
+            items,
+            next_page_token,
+        }
+    }
+}
+
+impl SessionDB {
+    /// 项目列表的一页（排序同 `list_projects_with_stats`）
+    pub fn list_projects_page(
+        &self,
+        limit: usize,
+        page_token: Option<&PageToken>,
+    ) -> Result<Page<ProjectWithStats>> {
+        let offset = PageToken::decode_offset(page_token, PageApi::Projects, "")?;
+        let projects = self.list_projects_with_stats(limit.saturating_add(1), offset)?;
+        Ok(Page::from_overfetch(projects, limit, |_| {
+            PageToken::encode(PageApi::Projects, "", PageCursor::Offset(offset + limit))
+        }))
+    }
+
+    /// 项目下会话列表的一页（按 updated_at 倒序，键集分页）
+    ///
+    /// 翻页期间被更新的会话 updated_at 变大，会移到已翻过的位置：本次遍历中可能跳过它，
+    /// 已返回过的会话则不会再次出现。需要完整快照时从第一页重新遍历。
+    pub fn list_sessions_page(
+        &self,
+        project_path: &str,
+        limit: usize,
+        page_token: Option<&PageToken>,
+    ) -> Result<Page<SessionWithProject>> {
+        let after = PageToken::decode_after(page_token, PageApi::Sessions, project_path)?;
+        let sessions = self.list_sessions_by_project_path_inner(
+            project_path,
+            limit.saturating_add(1),
+            0,
+            after,
+        )?;
+        Ok(Page::from_overfetch(sessions, limit, |last| {
+            PageToken::encode(
+                PageApi::Sessions,
+                project_path,
+                PageCursor::After(last.updated_at, last.id),
+            )
+        }))
+    }
+
+    /// 会话消息的一页（按 sequence 升序，键集分页）
+    ///
+    /// - with_raw: 是否加载 raw 列（可能很大），false 时 `raw` 为 None
+    pub fn list_messages_page(
+        &self,
+        session_id: &str,
+        limit: usize,
+        with_raw: bool,
+        page_token: Option<&PageToken>,
+    ) -> Result<Page<Message>> {
+        let after = PageToken::decode_after(page_token, PageApi::Messages, session_id)?;
+        let messages = self.list_messages_inner(
+            session_id,
+            limit.saturating_add(1),
+            0,
+            after,
+            Order::Asc,
+            with_raw,
+            false,
+            &[],
+        )?;
+        Ok(Page::from_overfetch(messages, limit, |last| {
+            PageToken::encode(
+                PageApi::Messages,
+                session_id,
+                PageCursor::After(last.sequence, last.id),
+            )
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_roundtrip() {
+        let token = PageToken::encode(PageApi::Messages, "s1", PageCursor::After(5, 42));
+        assert_eq!(
+            token.decode(PageApi::Messages, "s1").unwrap(),
+            PageCursor::After(5, 42)
+        );
+        // URL 安全，可直接放进查询参数
+        assert!(token
+            .as_str()
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+    }
+
+    #[test]
+    fn test_token_rejects_other_scope() {
+        let token = PageToken::encode(PageApi::Sessions, "/a", PageCursor::After(1, 1));
+        assert!(matches!(
+            token.decode(PageApi::Sessions, "/b"),
+            Err(Error::InvalidPageToken(_))
+        ));
+    }
+
+    #[test]
+    fn test_token_rejects_garbage() {
+        for raw in ["", "not base64!", "e30"] {
+            let token = PageToken::from(raw.to_string());
+            assert!(matches!(
+                token.decode(PageApi::Projects, ""),
+                Err(Error::InvalidPageToken(_))
+            ));
+        }
+    }
+}
@@ -5,6 +5,11 @@
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
+use crate::migrations::SchemaCompatibility;
+use crate::types::{
+    ApprovalDigestGroup, ChangeState, HistoryTotals, IgnoreKind, IndexState, SessionActivityState,
+};
+
 /// Claude Code Hook 事件（L2 瞬时通知）
 ///
 /// 由 claude_hook.sh 发送，用于即时 UI 反馈（如 Tab 装饰）。
@@ -57,6 +62,63 @@ pub mod hook_event_type {
     pub const PERMISSION_REQUEST: &str = "PermissionRequest";
 }
 
+/// 全量采集触发来源
+pub mod collect_trigger {
+    /// Agent 启动时
+    pub const STARTUP: &str = "startup";
+    /// 客户端请求（Request::CollectAll）
+    pub const REQUEST: &str = "request";
+    /// 系统从休眠中唤醒后的补采（见 `Push::AgentResumed`）
+    pub const WAKE: &str = "wake";
+}
+
+/// 分阶段全量采集的阶段（CollectStarted / CollectFinished 的 `phase` 字段）
+pub mod collect_phase {
+    /// 最近修改的会话文件（见 `collector::RECENT_COLLECT_WINDOW`）
+    pub const RECENT: &str = "recent";
+    /// 其余会话文件
+    pub const BACKLOG: &str = "backlog";
+}
+
+/// Response::Error 的错误码
+pub mod error_code {
+    /// 连接数已达上限（`AgentConfig::max_connections`），Agent 发送后关闭连接
+    pub const TOO_MANY_CONNECTIONS: i32 = 503;
+    /// 分页 token 无效（被篡改，或不是由该查询签发），客户端应从第一页重新开始
+    pub const INVALID_PAGE_TOKEN: i32 = 400;
+    /// 查询无法跨多个数据库合并（按来源拆分数据库时的分页、分组和按项目 ID 过滤）
+    pub const UNSUPPORTED_WITH_ROUTING: i32 = 501;
+    /// 确认 token 与当前状态不符（预览之后数据有变化），需重新预览
+    pub const CONFIRM_TOKEN_MISMATCH: i32 = 409;
+}
+
+/// 当前协议版本
+///
+/// 消息结构不兼容变化时递增（与 BUILD_TIMESTAMP 无关）。
+/// - 1: 初始版本（握手不带 protocol_version）
+/// - 2: 握手携带 protocol_version
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// 支持的最低协议版本
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// 握手未携带 protocol_version 的旧客户端视为版本 1
+fn legacy_protocol_version() -> u32 {
+    1
+}
+
+fn default_rederive_batch_size() -> usize {
+    crate::rederive::REDERIVE_BATCH_SIZE
+}
+
+/// 协商协议版本
+///
+/// 对端版本高于本端时降级到本端版本；低于支持下限时返回 None（拒绝）。
+pub fn negotiate_protocol_version(peer_version: u32) -> Option<u32> {
+    let version = peer_version.min(PROTOCOL_VERSION);
+    (version >= MIN_PROTOCOL_VERSION).then_some(version)
+}
+
 /// 请求类型（Client → Agent）
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -67,6 +129,9 @@ pub enum Request {
         component: String,
         /// 组件版本（用于日志和诊断）
         version: String,
+        /// 客户端协议版本
+        #[serde(default = "legacy_protocol_version")]
+        protocol_version: u32,
     },
 
     /// Kit 通知文件变化（增强实时性）
@@ -82,6 +147,49 @@ pub enum Request {
         indexed_message_ids: Vec<i64>,
     },
 
+    /// 设置消息的向量索引状态（from memex-rs）
+    ///
+    /// 可标记失败或重置为待索引，不限于 WriteIndexResult 的“已索引”
+    MarkIndexed {
+        /// 消息 ID 列表
+        message_ids: Vec<i64>,
+        state: IndexState,
+    },
+
+    /// 写入采集批次（from 无写权限的组件）
+    ///
+    /// 组件自行解析会话文件（`Collector::prepare_by_path`），由 Agent 作为唯一 Writer 写入。
+    /// 响应 QueryResult，data 为 `{ "messages_inserted": n, "revisions_detected": n }`
+    WriteCollectBatch { batch: crate::db::CollectBatch },
+
+    /// 写入搜索记录（from 无写权限的组件）
+    ///
+    /// 组件在只读连接上的搜索记录暂存在内存中（`SessionDB::take_buffered_searches`），
+    /// 由 Agent 写入。响应 QueryResult，data 为 `{ "written": n }`
+    WriteSearchLog {
+        entries: Vec<crate::search_log::SearchLogInput>,
+    },
+
+    /// 触发全量采集
+    ///
+    /// 采集前后向所有连接广播 CollectStarted / CollectFinished。
+    /// 响应 QueryResult，data 为 `CollectSummary`
+    CollectAll,
+
+    /// 读取待清理的向量墓碑（from memex-rs）
+    ///
+    /// 响应 QueryResult，data 为墓碑列表；向量库删除后用 AckVectorTombstones 确认
+    DrainVectorTombstones {
+        /// 最多返回条数
+        limit: usize,
+    },
+
+    /// 确认向量墓碑已处理（from memex-rs）
+    AckVectorTombstones {
+        /// 墓碑 ID 列表
+        ids: Vec<i64>,
+    },
+
     /// 写入 Compact 结果（from memex-rs）
     WriteCompactResult {
         session_id: String,
@@ -91,9 +199,18 @@ pub enum Request {
         summary_l2: String,
         /// L3 摘要（可选）
         summary_l3: Option<String>,
+        /// Talk 在会话中的序号（可选）
+        #[serde(default)]
+        position: Option<i64>,
+        /// Talk 覆盖的第一条消息 sequence（可选，用于推导序号）
+        #[serde(default)]
+        first_message_sequence: Option<i64>,
     },
 
     /// 写入 Approve 结果（from vlaude/VlaudeKit）
+    ///
+    /// 响应 `Response::QueryResult { data: { updated } }`，`updated` 为更新的消息数
+    /// （tool_call_id 不存在时为 0）；有更新时向所有连接推送 `Push::ApprovalResolved`。
     WriteApproveResult {
         /// Tool call ID
         tool_call_id: String,
@@ -122,6 +239,229 @@ pub enum Request {
 
     /// 恢复同步
     SyncResume,
+
+    /// 更新采集忽略规则
+    ///
+    /// 响应 QueryResult，data 为更新后的全部规则
+    UpdateIgnores {
+        /// 新增规则
+        #[serde(default)]
+        add: Vec<IgnoreRuleInput>,
+        /// 删除规则（规则 ID）
+        #[serde(default)]
+        remove: Vec<i64>,
+    },
+
+    /// 订阅推送（`events` 为推送的 `type`，如 "SessionIdle"）
+    ///
+    /// 从未订阅过的连接接收全部推送；订阅后只接收订阅集合内的类型。
+    /// 订阅集合在连接内保留（重新握手不清空），重复订阅同一类型是幂等的。
+    /// 响应 QueryResult，data 为订阅后的全部类型（排序）。
+    Subscribe { events: Vec<String> },
+
+    /// 取消订阅（未订阅的类型忽略）
+    ///
+    /// 从未订阅过的连接取消后接收其余全部类型；全部取消后不再接收推送，
+    /// 直到重新订阅。响应同 Subscribe。
+    Unsubscribe { events: Vec<String> },
+
+    /// 导出审批审计记录到文件（过滤条件同 `QueryType::ApprovalAudit`，不分页）
+    ///
+    /// 由 Agent 写入 `path`（已存在时覆盖）。响应 QueryResult，data 为 `{"rows": usize}`
+    ExportApprovalAudit {
+        path: std::path::PathBuf,
+        #[serde(default)]
+        format: crate::audit::AuditFormat,
+        #[serde(default)]
+        project_id: Option<i64>,
+        #[serde(default)]
+        start_ms: Option<i64>,
+        #[serde(default)]
+        end_ms: Option<i64>,
+    },
+
+    /// 彻底删除项目（见 `SessionDB::forget_project`），由持有写入角色的 Agent 执行
+    ///
+    /// 两步确认：不带 confirm_token 时只预览，响应 QueryResult，data 为 `ForgetReport`
+    /// （`dry_run = true`）；带上预览返回的 confirm_token 再次请求才删除，data 为实际的
+    /// `ForgetReport`。预览之后项目数据有变化时响应 `CONFIRM_TOKEN_MISMATCH`。
+    ForgetProject {
+        project_id: i64,
+        /// 清零释放的页面并截断 WAL
+        #[serde(default)]
+        secure: bool,
+        #[serde(default)]
+        confirm_token: Option<String>,
+    },
+
+    /// 导入 claude.ai 数据导出（ZIP 或 `conversations.json`，见 `importer` 模块）
+    ///
+    /// 由 Agent 读取 `path`，重复导入同一导出只跳过已有消息。响应 QueryResult，
+    /// data 为 `ImportReport`
+    ImportArchive {
+        path: std::path::PathBuf,
+        /// 所属 claude.ai 项目与已有项目同名的对话归入已有项目
+        #[serde(default)]
+        match_projects: bool,
+    },
+
+    /// 执行维护任务（由持有写入角色的 Agent 执行，见 `MaintenanceTask`）
+    Maintenance { task: MaintenanceTask },
+
+    /// 等待变更（长轮询）
+    ///
+    /// 范围内发生变更时响应 Changed，超时响应 NotModified。
+    /// session_id / project_path 都为空时等待全局变更。
+    WaitForChange {
+        #[serde(default)]
+        session_id: Option<String>,
+        #[serde(default)]
+        project_path: Option<String>,
+        /// 客户端已知的状态哈希（与当前不同时立即返回；为空时从当前状态开始等待）
+        #[serde(default)]
+        after_state_hash: Option<u64>,
+        /// 超时（毫秒）
+        timeout_ms: u64,
+    },
+}
+
+impl Request {
+    /// 是否写入数据库（Agent 只读降级时以 `Response::VersionMismatch` 拒绝）
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            Request::NotifyFileChange { .. }
+                | Request::WriteIndexResult { .. }
+                | Request::MarkIndexed { .. }
+                | Request::WriteCollectBatch { .. }
+                | Request::WriteSearchLog { .. }
+                | Request::CollectAll
+                | Request::AckVectorTombstones { .. }
+                | Request::WriteCompactResult { .. }
+                | Request::WriteApproveResult { .. }
+                | Request::HookEvent(_)
+                | Request::UpdateIgnores { .. }
+                | Request::ForgetProject { .. }
+                | Request::ImportArchive { .. }
+                | Request::Maintenance { .. }
+        )
+    }
+}
+
+/// 维护任务（`Request::Maintenance`）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MaintenanceTask {
+    /// 用当前的提取规则从 raw 重新提取 content_text（见 `rederive` 模块）
+    ///
+    /// 从持久化的进度继续。响应 QueryResult，data 为本次的 `RederiveProgress`，
+    /// `done = false` 时再次请求继续
+    RederiveContentText {
+        #[serde(default = "default_rederive_batch_size")]
+        batch_size: usize,
+        /// 本次最多处理的批次数（None 表示处理到结束）
+        #[serde(default)]
+        max_batches: Option<usize>,
+        /// 清除进度，从第一条消息开始（提取规则再次改进后使用）
+        #[serde(default)]
+        restart: bool,
+    },
+}
+
+/// 全量采集结果摘要（CollectFinished 推送 / CollectAll 响应）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CollectSummary {
+    pub projects_scanned: usize,
+    pub sessions_scanned: usize,
+    pub messages_inserted: usize,
+    pub revisions_detected: usize,
+    pub sessions_ignored: usize,
+    pub skipped_by_filter: usize,
+    /// 无权访问的数据目录
+    pub permission_errors: Vec<PathBuf>,
+    /// 超过文件大小上限而跳过的会话文件数
+    pub files_skipped_size: usize,
+    /// 超过单条消息上限而被截断的消息数
+    pub messages_truncated: usize,
+    /// 连续失败处于退避期而跳过的会话数
+    pub sessions_backed_off: usize,
+    /// 错误总数
+    pub error_count: usize,
+    /// 结构化错误（最多 `MAX_COLLECT_ERRORS` 条，总数见 error_count）
+    pub errors: Vec<CollectErrorEntry>,
+}
+
+/// 写入角色（QueryType::WriterRole 响应）
+///
+/// Agent 是唯一 Writer：其他组件的写入都经由 Agent 转发。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WriterRole {
+    /// 当前连接的 Agent 是否持有写入角色
+    pub is_writer: bool,
+    /// 写入者类型，见 `writer_type`
+    pub writer_type: String,
+    /// 取得写入角色的时间（毫秒时间戳）
+    pub since: i64,
+}
+
+/// 写入者类型
+pub mod writer_type {
+    /// vimo-agent
+    pub const AGENT: &str = "agent";
+}
+
+/// CollectSummary 中最多携带的错误条数
+pub const MAX_COLLECT_ERRORS: usize = 50;
+
+/// 采集失败的阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CollectStage {
+    /// 发现会话（访问数据目录、列出会话、读取增量状态）
+    Discover,
+    /// 解析会话文件
+    Parse,
+    /// 转换为写入格式
+    Convert,
+    /// 写入数据库（项目、会话、消息）
+    Insert,
+}
+
+impl CollectStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CollectStage::Discover => "discover",
+            CollectStage::Parse => "parse",
+            CollectStage::Convert => "convert",
+            CollectStage::Insert => "insert",
+        }
+    }
+}
+
+impl std::fmt::Display for CollectStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 采集错误条目（带来源文件和会话）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CollectErrorEntry {
+    /// 出错的会话文件（数据目录级错误为目录路径，适配器级错误为空）
+    pub path: PathBuf,
+    pub session_id: Option<String>,
+    /// 数据源（如 claude），无法归属到单个数据源时为空
+    pub source: Option<String>,
+    pub stage: CollectStage,
+    pub message: String,
+}
+
+/// 忽略规则输入
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IgnoreRuleInput {
+    pub pattern: String,
+    pub kind: IgnoreKind,
 }
 
 /// 响应类型（Agent → Client）
@@ -141,12 +481,247 @@ pub enum Response {
     HandshakeOk {
         /// Agent 版本
         agent_version: String,
+        /// 协商后的协议版本
+        #[serde(default = "legacy_protocol_version")]
+        protocol_version: u32,
+        /// Agent 比数据库要求的写入方旧，只提供读取（见 `Push::AgentDegraded`）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        degraded: Option<SchemaCompatibility>,
+    },
+
+    /// 握手失败：客户端协议版本不在支持范围内
+    ProtocolVersionUnsupported {
+        /// 客户端协议版本
+        protocol_version: u32,
+        /// Agent 支持的最低版本
+        min_supported: u32,
+        /// Agent 支持的最高版本
+        max_supported: u32,
     },
 
     /// 查询结果
     QueryResult {
         data: serde_json::Value,
     },
+
+    /// 状态已变化（WaitForChange）
+    Changed {
+        state: ChangeState,
+    },
+
+    /// 等待超时，状态未变化（WaitForChange）
+    NotModified {
+        state: ChangeState,
+    },
+
+    /// 写入被拒绝：Agent 比数据库要求的写入方旧，只读运行（需要更新 vimo-agent）
+    VersionMismatch { compatibility: SchemaCompatibility },
+}
+
+/// 推送消息（Agent → 所有 Client，非请求响应）
+///
+/// 与 Response 共用 `type` 标签，变体名不能与 Response 重复。
+/// 每个推送都携带发送时的全局变更计数 `change_counter`。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Push {
+    /// 项目状态变化（如被忽略规则排除/恢复）
+    ProjectUpdated {
+        project_id: i64,
+        project_path: String,
+        ignored: bool,
+        /// 推送时的全局变更计数（见 `QueryType::ChangeCounter`）
+        #[serde(default)]
+        change_counter: u64,
+    },
+
+    /// 会话活跃状态切换（只在状态变化时推送，不随每次文件写入推送）
+    SessionActivityChanged {
+        session_id: String,
+        state: SessionActivityState,
+        last_event_ms: Option<i64>,
+        /// 推送时的全局变更计数（见 `QueryType::ChangeCounter`）
+        #[serde(default)]
+        change_counter: u64,
+    },
+
+    /// 全量采集开始（UI 可显示进度）
+    CollectStarted {
+        /// 触发来源，见 `collect_trigger`
+        trigger: String,
+        /// 分阶段采集时的阶段，见 `collect_phase`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        phase: Option<String>,
+        /// 推送时的全局变更计数（见 `QueryType::ChangeCounter`）
+        #[serde(default)]
+        change_counter: u64,
+    },
+
+    /// 全量采集结束（UI 可据此刷新）
+    CollectFinished {
+        /// 触发来源，见 `collect_trigger`
+        trigger: String,
+        /// 分阶段采集时的阶段，见 `collect_phase`（摘要为截至该阶段的累计结果）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        phase: Option<String>,
+        summary: CollectSummary,
+        /// 采集失败时的错误信息
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+        /// 启动时的全量采集完成后附带的历史数据总量（引导页展示，完整概览见
+        /// `QueryType::HistoryOverview`），其余推送为空
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        overview: Option<HistoryTotals>,
+        /// 推送时的全局变更计数（见 `QueryType::ChangeCounter`）
+        #[serde(default)]
+        change_counter: u64,
+    },
+
+    /// 数据库完整性检查失败（UI 应提示用户修复，如 `vimo-agent --repair`）
+    IntegrityWarning {
+        /// quick_check 返回的损坏详情
+        detail: String,
+        /// 推送时的全局变更计数（见 `QueryType::ChangeCounter`）
+        #[serde(default)]
+        change_counter: u64,
+    },
+
+    /// 新会话首次采集到消息
+    SessionStarted {
+        session_id: String,
+        project_path: String,
+        /// 数据源（claude / codex / opencode）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        source: Option<String>,
+        /// 标题（第一条用户消息的预览）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        /// 会话开始时间（毫秒，第一条消息的时间）
+        started_at: i64,
+        /// 推送时的全局变更计数（见 `QueryType::ChangeCounter`）
+        #[serde(default)]
+        change_counter: u64,
+    },
+
+    /// 活跃过的会话超过静默期（`AgentConfig::session_idle_secs`）没有文件事件，
+    /// 通常表示 AI 已回复完毕；每次活跃后只推送一次
+    SessionIdle {
+        session_id: String,
+        /// 最后一条助手消息的预览
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        last_assistant_preview: Option<String>,
+        message_count: i64,
+        /// 最后一次文件事件时间（毫秒）
+        last_event_ms: i64,
+        /// 推送时的全局变更计数（见 `QueryType::ChangeCounter`）
+        #[serde(default)]
+        change_counter: u64,
+    },
+
+    /// 会话确认结束（CLI 发送了 SessionEnd hook 事件）
+    SessionEnded {
+        session_id: String,
+        message_count: i64,
+        /// 推送时的全局变更计数（见 `QueryType::ChangeCounter`）
+        #[serde(default)]
+        change_counter: u64,
+    },
+
+    /// Agent 检测到系统从休眠中唤醒，已重建文件监听并开始补采
+    ///
+    /// 客户端应发送 Heartbeat 确认连接仍然可用（`AgentClient::recv_push` 会自动处理）。
+    AgentResumed {
+        /// 估计的休眠时长（秒）
+        slept_secs: u64,
+        /// 推送时的全局变更计数（见 `QueryType::ChangeCounter`）
+        #[serde(default)]
+        change_counter: u64,
+    },
+
+    /// 按项目汇总的待审批消息（`AgentConfig::approval_digest_interval_secs` 定期检查，
+    /// 只在内容与上次推送不同时推送；全部审批完成后推送一次空列表）
+    ApprovalDigest {
+        groups: Vec<ApprovalDigestGroup>,
+        /// 推送时的全局变更计数（见 `QueryType::ChangeCounter`）
+        #[serde(default)]
+        change_counter: u64,
+    },
+
+    /// 审批结果已写入（`Request::WriteApproveResult` 更新了消息时推送），
+    /// 其他客户端据此关闭同一审批的提示
+    ApprovalResolved {
+        tool_call_id: String,
+        status: ApprovalStatus,
+        resolved_at: i64,
+        /// 更新的消息数
+        updated: usize,
+        /// 推送时的全局变更计数（见 `QueryType::ChangeCounter`）
+        #[serde(default)]
+        change_counter: u64,
+    },
+
+    /// Agent 比数据库要求的写入方旧（数据库已由更新版本迁移），只提供读取，
+    /// 写入请求响应 `Response::VersionMismatch`；握手成功后向该连接推送一次
+    AgentDegraded {
+        compatibility: SchemaCompatibility,
+        /// 推送时的全局变更计数（见 `QueryType::ChangeCounter`）
+        #[serde(default)]
+        change_counter: u64,
+    },
+}
+
+impl Push {
+    /// 全部推送类型（与 `event_type` 一致，新增变体时同步添加）
+    pub const EVENT_TYPES: &'static [&'static str] = &[
+        "ProjectUpdated",
+        "SessionActivityChanged",
+        "CollectStarted",
+        "CollectFinished",
+        "IntegrityWarning",
+        "SessionStarted",
+        "SessionIdle",
+        "SessionEnded",
+        "AgentResumed",
+        "ApprovalDigest",
+        "ApprovalResolved",
+        "AgentDegraded",
+    ];
+
+    /// 推送时的全局变更计数
+    pub fn change_counter(&self) -> u64 {
+        match self {
+            Push::ProjectUpdated { change_counter, .. }
+            | Push::SessionActivityChanged { change_counter, .. }
+            | Push::CollectStarted { change_counter, .. }
+            | Push::CollectFinished { change_counter, .. }
+            | Push::IntegrityWarning { change_counter, .. }
+            | Push::SessionStarted { change_counter, .. }
+            | Push::SessionIdle { change_counter, .. }
+            | Push::SessionEnded { change_counter, .. }
+            | Push::AgentResumed { change_counter, .. }
+            | Push::ApprovalDigest { change_counter, .. }
+            | Push::ApprovalResolved { change_counter, .. }
+            | Push::AgentDegraded { change_counter, .. } => *change_counter,
+        }
+    }
+
+    /// 推送类型（序列化后的 `type` 字段，用于按订阅过滤）
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            Push::ProjectUpdated { .. } => "ProjectUpdated",
+            Push::SessionActivityChanged { .. } => "SessionActivityChanged",
+            Push::CollectStarted { .. } => "CollectStarted",
+            Push::CollectFinished { .. } => "CollectFinished",
+            Push::IntegrityWarning { .. } => "IntegrityWarning",
+            Push::SessionStarted { .. } => "SessionStarted",
+            Push::SessionIdle { .. } => "SessionIdle",
+            Push::SessionEnded { .. } => "SessionEnded",
+            Push::AgentResumed { .. } => "AgentResumed",
+            Push::ApprovalDigest { .. } => "ApprovalDigest",
+            Push::ApprovalResolved { .. } => "ApprovalResolved",
+            Push::AgentDegraded { .. } => "AgentDegraded",
+        }
+    }
 }
 
 /// 审批状态
@@ -159,15 +734,225 @@ pub enum ApprovalStatus {
 }
 
 /// 查询类型
+///
+/// limit 为 0 表示不限制，最多返回 `config::MAX_QUERY_LIMIT` 条（见 `with_boundary_limits`）
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "query")]
 pub enum QueryType {
     /// 获取 Agent 状态
+    ///
+    /// 包含 `access_issues`：无权访问的数据目录列表（`{source, path}`），
+    /// UI 据此提示授予完全磁盘访问权限。
+    /// 另含库版本（`library_version`、`build_timestamp`）和数据库 schema 版本
+    /// （`schema_version`、`supported_schema_version`），用于排查混合版本安装；
+    /// `degraded` 为只读降级时的 `SchemaCompatibility`（正常写入时为 null）。
+    /// `collection_lock` 为当前采集锁持有者（未被持有时为 null）。
+    /// `resources` 为 Agent 自身的 RSS / 文件描述符（当前值和峰值）及内存超限时的防御动作统计。
+    /// `invariants` 为最近一次抽样不变量检查的结果（`last_report`，随完整性巡检执行）和
+    /// `paranoid` feature 下写入后发现的违反次数（`paranoid_violations`）。
+    /// `replication` 为消息复制的状态和延迟（`lag` 为最新消息 ID 与已投递游标之差），
+    /// 未配置复制目标时为 null。
     Status,
     /// 获取连接数
     ConnectionCount,
     /// 获取同步状态
     SyncStatus,
+    /// 全文搜索
+    ///
+    /// 响应 QueryResult，data 为 `Vec<SearchResult>`（paged 时为 `Page<SearchResult>`）。
+    /// `max_per_project` 为每个项目的配额：配额内的命中优先，limit 未满时再补充超出配额的命中。
+    /// `options.include_content` 未指定时不返回正文（content_full 为空），用 `MessagesByIds` 按需获取。
+    /// `options.date_range`（如 `"last7days"`）在 Agent 端按 `options.tz_offset_minutes` 解析，
+    /// 客户端应传入自己的时区偏移。
+    Search {
+        /// 搜索关键词（不能命名为 query，与标签字段冲突）
+        keyword: String,
+        limit: usize,
+        #[serde(default)]
+        options: crate::types::SearchGroupOptions,
+        #[serde(default)]
+        max_per_project: Option<usize>,
+        /// 为 true 时响应 `Page`（items + next_page_token），忽略 offset
+        #[serde(default)]
+        paged: bool,
+        /// 上一页响应中的 next_page_token（仅 paged 时有效，为空表示第一页）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        page_token: Option<crate::types::PageToken>,
+    },
+    /// 全文搜索的命中总数（过滤条件同 Search，额外的一次 FTS 查询）
+    ///
+    /// 响应 QueryResult，data 为 `{"total": i64}`
+    SearchCount {
+        /// 搜索关键词（不能命名为 query，与标签字段冲突）
+        keyword: String,
+        #[serde(default)]
+        options: crate::types::SearchGroupOptions,
+    },
+    /// 按会话分组的全文搜索
+    ///
+    /// 响应 QueryResult，data 为 `Vec<SessionSearchGroup>`
+    SearchGrouped {
+        /// 搜索关键词（不能命名为 query，与标签字段冲突）
+        keyword: String,
+        session_limit: usize,
+        per_session_limit: usize,
+        #[serde(default)]
+        options: crate::types::SearchGroupOptions,
+    },
+    /// 最近的搜索（按时间倒序，见 `search_log` 模块；未启用搜索记录时为空）
+    ///
+    /// 响应 QueryResult，data 为 `Vec<SearchLogEntry>`；`zero_results` 为 true 时只返回没有结果的搜索
+    RecentSearches {
+        limit: usize,
+        #[serde(default)]
+        zero_results: bool,
+    },
+    /// 会话实时活跃状态（Streaming / RecentlyActive / Idle）
+    ///
+    /// 响应 QueryResult，data 为 `Vec<SessionActivity>`（顺序与 session_ids 一致）
+    SessionActivity { session_ids: Vec<String> },
+    /// 会话的 Talk 摘要（按 position 排序，分页）
+    ///
+    /// 响应 QueryResult，data 为 `Vec<TalkSummary>`；limit 为空时取最大单页条数
+    TalkSummaries {
+        session_id: String,
+        #[serde(default)]
+        limit: Option<usize>,
+        #[serde(default)]
+        offset: usize,
+    },
+    /// 单个 Talk 摘要
+    ///
+    /// 响应 QueryResult，data 为 `TalkSummary` 或 null
+    TalkSummary { session_id: String, talk_id: String },
+    /// 全局变更计数（单调递增，每个写事务 +1）
+    ///
+    /// 响应 QueryResult，data 为 `{"counter": u64}`；与上次的值相同说明没有新数据
+    ChangeCounter,
+    /// 历史数据概览（总量、时间跨度、按数据源 / 年份的分布、最活跃的项目）
+    ///
+    /// 响应 QueryResult，data 为 `HistoryOverview`
+    HistoryOverview,
+    /// 项目列表（带统计，按最后活跃时间排序，分页）
+    ///
+    /// 响应 QueryResult，data 为 `Vec<ProjectWithStats>`（paged 时为 `Page<ProjectWithStats>`）
+    ListProjects {
+        limit: usize,
+        #[serde(default)]
+        offset: usize,
+        /// 为 true 时响应 `Page`（items + next_page_token），忽略 offset
+        #[serde(default)]
+        paged: bool,
+        /// 上一页响应中的 next_page_token（仅 paged 时有效，为空表示第一页）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        page_token: Option<crate::types::PageToken>,
+    },
+    /// 数据库与 Claude projects 目录对账后的项目列表（按最后活跃时间排序，分页）
+    ///
+    /// 响应 QueryResult，data 为 `Vec<ReconciledProject>`，标注项目是否已采集、目录是否仍在，
+    /// 见 `SessionDB::list_projects_reconciled`
+    ReconciledProjects {
+        limit: usize,
+        #[serde(default)]
+        offset: usize,
+        /// Claude projects 目录，为空时使用 `~/.claude/projects`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        projects_path: Option<String>,
+    },
+    /// 项目下的会话列表（不含 agent session，分页）
+    ///
+    /// 响应 QueryResult，data 为 `Vec<SessionWithProject>`（paged 时为 `Page<SessionWithProject>`）
+    ListSessions {
+        project_path: String,
+        limit: usize,
+        #[serde(default)]
+        offset: usize,
+        /// 为 true 时响应 `Page`（items + next_page_token），忽略 offset
+        #[serde(default)]
+        paged: bool,
+        /// 上一页响应中的 next_page_token（仅 paged 时有效，为空表示第一页）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        page_token: Option<crate::types::PageToken>,
+    },
+    /// 会话消息（按 sequence 排序，分页，不含 raw）
+    ///
+    /// 响应 QueryResult，data 为 `Vec<Message>`（paged 时为 `Page<Message>`）
+    ListMessages {
+        session_id: String,
+        limit: usize,
+        #[serde(default)]
+        offset: usize,
+        /// 为 true 时响应 `Page`（items + next_page_token），忽略 offset
+        #[serde(default)]
+        paged: bool,
+        /// 上一页响应中的 next_page_token（仅 paged 时有效，为空表示第一页）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        page_token: Option<crate::types::PageToken>,
+    },
+    /// 会话的对话轮次摘要（按 turn_index 排序，分页）
+    ///
+    /// 响应 QueryResult，data 为 `Vec<TurnSummary>`
+    ListTurns {
+        session_id: String,
+        limit: usize,
+        #[serde(default)]
+        offset: usize,
+    },
+    /// 单个对话轮次的消息（按 sequence 排序，不含 raw）
+    ///
+    /// 响应 QueryResult，data 为 `Vec<Message>`
+    TurnMessages { session_id: String, turn_index: i64 },
+    /// 按 ID 获取消息（按 ID 升序，不含 raw；不存在的 ID 被跳过）
+    ///
+    /// 用于取不含正文的搜索命中的正文。响应 QueryResult，data 为 `Vec<Message>`
+    MessagesByIds { ids: Vec<i64> },
+    /// 当前 Agent 的写入角色（UI 据此显示由哪个进程负责写入）
+    ///
+    /// 响应 QueryResult，data 为 `WriterRole`
+    WriterRole,
+    /// 审批审计记录（按请求时间升序，分页）
+    ///
+    /// 响应 QueryResult，data 为 `Vec<ApprovalAuditRow>`；start_ms / end_ms 为闭区间
+    ApprovalAudit {
+        #[serde(default)]
+        project_id: Option<i64>,
+        #[serde(default)]
+        start_ms: Option<i64>,
+        #[serde(default)]
+        end_ms: Option<i64>,
+        limit: usize,
+        #[serde(default)]
+        offset: usize,
+    },
+}
+
+impl QueryType {
+    /// 把 limit 为 0 的字段替换为 `MAX_QUERY_LIMIT`（协议中 0 表示不限制）
+    ///
+    /// TalkSummaries 的 limit 为空时取最大单页条数，不在此处理。
+    pub fn with_boundary_limits(mut self) -> Self {
+        use crate::config::boundary_limit;
+        match &mut self {
+            QueryType::Search { limit, .. }
+            | QueryType::ListProjects { limit, .. }
+            | QueryType::ReconciledProjects { limit, .. }
+            | QueryType::ListSessions { limit, .. }
+            | QueryType::ListMessages { limit, .. }
+            | QueryType::ListTurns { limit, .. }
+            | QueryType::RecentSearches { limit, .. }
+            | QueryType::ApprovalAudit { limit, .. } => *limit = boundary_limit(*limit),
+            QueryType::SearchGrouped {
+                session_limit,
+                per_session_limit,
+                ..
+            } => {
+                *session_limit = boundary_limit(*session_limit);
+                *per_session_limit = boundary_limit(*per_session_limit);
+            }
+            _ => {}
+        }
+        self
+    }
 }
 
 #[cfg(test)]
@@ -314,4 +1099,451 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_update_ignores_deserialize() {
+        let json = r#"{
+            "type": "UpdateIgnores",
+            "add": [{"pattern": "/tmp/**", "kind": "path_glob"}]
+        }"#;
+
+        let request: Request = serde_json::from_str(json).unwrap();
+        match request {
+            Request::UpdateIgnores { add, remove } => {
+                assert_eq!(add.len(), 1);
+                assert_eq!(add[0].kind, IgnoreKind::PathGlob);
+                assert!(remove.is_empty());
+            }
+            _ => panic!("Expected UpdateIgnores"),
+        }
+    }
+
+    #[test]
+    fn test_subscribe_roundtrip() {
+        let json = r#"{"type": "Subscribe", "events": ["SessionIdle", "SessionEnded"]}"#;
+        let request: Request = serde_json::from_str(json).unwrap();
+        match request {
+            Request::Subscribe { events } => assert_eq!(events, ["SessionIdle", "SessionEnded"]),
+            _ => panic!("Expected Subscribe"),
+        }
+
+        // 订阅使用的类型名与推送序列化的 type 一致
+        let push = Push::SessionEnded {
+            session_id: "s1".to_string(),
+            message_count: 3,
+            change_counter: 1,
+        };
+        let value = serde_json::to_value(&push).unwrap();
+        assert_eq!(value["type"], push.event_type());
+        assert!(Push::EVENT_TYPES.contains(&push.event_type()));
+    }
+
+    #[test]
+    fn test_negotiate_protocol_version() {
+        let current = Some(PROTOCOL_VERSION);
+        assert_eq!(negotiate_protocol_version(PROTOCOL_VERSION), current);
+        // 对端更新：降级到本端版本
+        assert_eq!(negotiate_protocol_version(PROTOCOL_VERSION + 1), current);
+        let min = Some(MIN_PROTOCOL_VERSION);
+        assert_eq!(negotiate_protocol_version(MIN_PROTOCOL_VERSION), min);
+        assert_eq!(negotiate_protocol_version(0), None);
+    }
+
+    #[test]
+    fn test_legacy_handshake_defaults_to_v1() {
+        let json = r#"{"type": "Handshake", "component": "old-kit", "version": "0.1.0"}"#;
+        match serde_json::from_str::<Request>(json).unwrap() {
+            Request::Handshake {
+                protocol_version, ..
+            } => assert_eq!(protocol_version, 1),
+            _ => panic!("Expected Handshake"),
+        }
+
+        let json = r#"{"type": "HandshakeOk", "agent_version": "0.1.0"}"#;
+        match serde_json::from_str::<Response>(json).unwrap() {
+            Response::HandshakeOk {
+                protocol_version, ..
+            } => assert_eq!(protocol_version, 1),
+            _ => panic!("Expected HandshakeOk"),
+        }
+    }
+
+    #[test]
+    fn test_wait_for_change_roundtrip() {
+        let json = r#"{"type": "WaitForChange", "session_id": "s1", "timeout_ms": 30000}"#;
+        let request: Request = serde_json::from_str(json).unwrap();
+        match request {
+            Request::WaitForChange {
+                session_id,
+                project_path,
+                after_state_hash,
+                timeout_ms,
+            } => {
+                assert_eq!(session_id.as_deref(), Some("s1"));
+                assert!(project_path.is_none());
+                assert!(after_state_hash.is_none());
+                assert_eq!(timeout_ms, 30000);
+            }
+            _ => panic!("Expected WaitForChange"),
+        }
+
+        let response = Response::Changed {
+            state: ChangeState {
+                message_count: 3,
+                max_sequence: Some(2),
+                state_hash: u64::MAX,
+            },
+        };
+        let json = serde_json::to_string(&response).unwrap();
+        match serde_json::from_str::<Response>(&json).unwrap() {
+            Response::Changed { state } => assert_eq!(state.state_hash, u64::MAX),
+            _ => panic!("Expected Changed"),
+        }
+    }
+
+    #[test]
+    fn test_search_grouped_query_deserialize() {
+        let json = r#"{"type": "Query", "query_type": {"query": "SearchGrouped", "keyword": "rust", "session_limit": 10, "per_session_limit": 3}}"#;
+        let request: Request = serde_json::from_str(json).unwrap();
+        match request {
+            Request::Query {
+                query_type:
+                    QueryType::SearchGrouped {
+                        keyword,
+                        session_limit,
+                        per_session_limit,
+                        options,
+                    },
+            } => {
+                assert_eq!(keyword, "rust");
+                assert_eq!(session_limit, 10);
+                assert_eq!(per_session_limit, 3);
+                assert!(options.project_id.is_none());
+                assert_eq!(options.order_by, crate::types::SearchOrderBy::Score);
+            }
+            _ => panic!("Expected SearchGrouped query"),
+        }
+    }
+
+    #[test]
+    fn test_search_query_max_per_project() {
+        let json = r#"{"type": "Query", "query_type": {"query": "Search", "keyword": "rust", "limit": 30, "max_per_project": 5}}"#;
+        match serde_json::from_str::<Request>(json).unwrap() {
+            Request::Query {
+                query_type:
+                    QueryType::Search {
+                        limit,
+                        max_per_project,
+                        ..
+                    },
+            } => {
+                assert_eq!(limit, 30);
+                assert_eq!(max_per_project, Some(5));
+            }
+            _ => panic!("Expected Search query"),
+        }
+    }
+
+    #[test]
+    fn test_list_query_page_token() {
+        // 旧客户端只传 limit/offset
+        let json = r#"{"type": "Query", "query_type": {"query": "ListMessages", "session_id": "s1", "limit": 20, "offset": 40}}"#;
+        match serde_json::from_str::<Request>(json).unwrap() {
+            Request::Query {
+                query_type:
+                    QueryType::ListMessages {
+                        offset,
+                        paged,
+                        page_token,
+                        ..
+                    },
+            } => {
+                assert_eq!(offset, 40);
+                assert!(!paged);
+                assert!(page_token.is_none());
+            }
+            _ => panic!("Expected ListMessages query"),
+        }
+
+        let json = r#"{"type": "Query", "query_type": {"query": "ListProjects", "limit": 20, "paged": true, "page_token": "abc"}}"#;
+        match serde_json::from_str::<Request>(json).unwrap() {
+            Request::Query {
+                query_type:
+                    QueryType::ListProjects {
+                        paged, page_token, ..
+                    },
+            } => {
+                assert!(paged);
+                assert_eq!(page_token.unwrap().as_str(), "abc");
+            }
+            _ => panic!("Expected ListProjects query"),
+        }
+    }
+
+    #[test]
+    fn test_export_approval_audit_defaults() {
+        let json = r#"{"type":"ExportApprovalAudit","path":"/tmp/audit.csv"}"#;
+        match serde_json::from_str::<Request>(json).unwrap() {
+            Request::ExportApprovalAudit {
+                path,
+                format,
+                project_id,
+                ..
+            } => {
+                assert_eq!(path, std::path::PathBuf::from("/tmp/audit.csv"));
+                assert_eq!(format, crate::audit::AuditFormat::Csv);
+                assert_eq!(project_id, None);
+            }
+            _ => panic!("Expected ExportApprovalAudit request"),
+        }
+    }
+
+    #[test]
+    fn test_mark_indexed_roundtrip() {
+        let request = Request::MarkIndexed {
+            message_ids: vec![1, 2],
+            state: IndexState::Failed,
+        };
+        let json = serde_json::to_string(&request).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"MarkIndexed","message_ids":[1,2],"state":"failed"}"#
+        );
+        assert!(matches!(
+            serde_json::from_str::<Request>(&json).unwrap(),
+            Request::MarkIndexed {
+                state: IndexState::Failed,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_forget_project_defaults_to_preview() {
+        let request: Request =
+            serde_json::from_str(r#"{"type":"ForgetProject","project_id":7}"#).unwrap();
+        assert!(matches!(
+            request,
+            Request::ForgetProject {
+                project_id: 7,
+                secure: false,
+                confirm_token: None,
+            }
+        ));
+    }
+
+    #[test]
+    fn test_import_archive_defaults() {
+        let request: Request =
+            serde_json::from_str(r#"{"type":"ImportArchive","path":"/tmp/export.zip"}"#).unwrap();
+        match request {
+            Request::ImportArchive {
+                path,
+                match_projects,
+            } => {
+                assert_eq!(path, PathBuf::from("/tmp/export.zip"));
+                assert!(!match_projects);
+            }
+            other => panic!("Expected ImportArchive, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_maintenance_defaults() {
+        let request: Request = serde_json::from_str(
+            r#"{"type":"Maintenance","task":{"kind":"rederive_content_text"}}"#,
+        )
+        .unwrap();
+        match request {
+            Request::Maintenance { task } => assert_eq!(
+                task,
+                MaintenanceTask::RederiveContentText {
+                    batch_size: crate::rederive::REDERIVE_BATCH_SIZE,
+                    max_batches: None,
+                    restart: false,
+                }
+            ),
+            other => panic!("Expected Maintenance, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_zero_limit_maps_to_cap() {
+        let query = QueryType::ListMessages {
+            session_id: "s1".to_string(),
+            limit: 0,
+            offset: 0,
+            paged: false,
+            page_token: None,
+        };
+        match query.with_boundary_limits() {
+            QueryType::ListMessages { limit, .. } => {
+                assert_eq!(limit, crate::config::MAX_QUERY_LIMIT)
+            }
+            _ => panic!("Expected ListMessages query"),
+        }
+
+        let query = QueryType::SearchGrouped {
+            keyword: "rust".to_string(),
+            session_limit: 5,
+            per_session_limit: 0,
+            options: Default::default(),
+        };
+        match query.with_boundary_limits() {
+            QueryType::SearchGrouped {
+                session_limit,
+                per_session_limit,
+                ..
+            } => assert_eq!(
+                (session_limit, per_session_limit),
+                (5, crate::config::MAX_QUERY_LIMIT)
+            ),
+            _ => panic!("Expected SearchGrouped query"),
+        }
+    }
+
+    #[test]
+    fn test_push_not_parsed_as_response() {
+        let push = Push::ProjectUpdated {
+            project_id: 1,
+            project_path: "/tmp/scratch".to_string(),
+            ignored: true,
+            change_counter: 7,
+        };
+        let json = serde_json::to_string(&push).unwrap();
+        assert!(json.contains("\"type\":\"ProjectUpdated\""));
+        assert!(json.contains("\"change_counter\":7"));
+
+        // Client 据此区分推送和响应
+        assert!(serde_json::from_str::<Response>(&json).is_err());
+        assert!(serde_json::from_str::<Push>(r#"{"type":"Ok"}"#).is_err());
+    }
+
+    #[test]
+    fn test_collect_finished_roundtrip() {
+        let push = Push::CollectFinished {
+            trigger: collect_trigger::REQUEST.to_string(),
+            phase: None,
+            summary: CollectSummary {
+                sessions_scanned: 3,
+                messages_inserted: 12,
+                ..Default::default()
+            },
+            error: None,
+            overview: None,
+            change_counter: 42,
+        };
+        let json = serde_json::to_string(&push).unwrap();
+        assert!(json.contains("\"type\":\"CollectFinished\""));
+        assert!(!json.contains("\"error\""));
+        assert!(!json.contains("\"phase\""));
+        assert!(!json.contains("\"overview\""));
+        assert!(serde_json::from_str::<Response>(&json).is_err());
+
+        match serde_json::from_str::<Push>(&json).unwrap() {
+            Push::CollectFinished {
+                summary,
+                change_counter,
+                ..
+            } => {
+                assert_eq!(change_counter, 42);
+                assert_eq!(summary.sessions_scanned, 3);
+                assert_eq!(summary.messages_inserted, 12);
+            }
+            other => panic!("Expected CollectFinished, got {:?}", other),
+        }
+
+        // 启动采集完成时附带历史数据总量
+        let push = Push::CollectFinished {
+            trigger: collect_trigger::STARTUP.to_string(),
+            phase: Some(collect_phase::BACKLOG.to_string()),
+            summary: CollectSummary::default(),
+            error: None,
+            overview: Some(HistoryTotals {
+                project_count: 2,
+                message_count: 30,
+                oldest_message_at: Some(1_000),
+                ..Default::default()
+            }),
+            change_counter: 43,
+        };
+        let json = serde_json::to_string(&push).unwrap();
+        assert!(json.contains("\"oldestMessageAt\":1000"));
+        match serde_json::from_str::<Push>(&json).unwrap() {
+            Push::CollectFinished { overview, .. } => {
+                assert_eq!(overview.unwrap().message_count, 30)
+            }
+            other => panic!("Expected CollectFinished, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_agent_resumed_roundtrip() {
+        let push = Push::AgentResumed {
+            slept_secs: 3600,
+            change_counter: 9,
+        };
+        let json = serde_json::to_string(&push).unwrap();
+        assert!(json.contains("\"type\":\"AgentResumed\""));
+        assert!(serde_json::from_str::<Response>(&json).is_err());
+
+        let parsed = serde_json::from_str::<Push>(&json).unwrap();
+        assert_eq!(parsed.change_counter(), 9);
+        assert!(matches!(
+            parsed,
+            Push::AgentResumed {
+                slept_secs: 3600,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_session_lifecycle_push_roundtrip() {
+        let pushes = [
+            Push::SessionStarted {
+                session_id: "s1".to_string(),
+                project_path: "/tmp/project".to_string(),
+                source: Some("claude".to_string()),
+                title: Some("fix the build".to_string()),
+                started_at: 1_000,
+                change_counter: 1,
+            },
+            Push::SessionIdle {
+                session_id: "s1".to_string(),
+                last_assistant_preview: Some("Done.".to_string()),
+                message_count: 4,
+                last_event_ms: 2_000,
+                change_counter: 2,
+            },
+            Push::SessionEnded {
+                session_id: "s1".to_string(),
+                message_count: 4,
+                change_counter: 3,
+            },
+        ];
+        for (push, name) in pushes
+            .iter()
+            .zip(["SessionStarted", "SessionIdle", "SessionEnded"])
+        {
+            let json = serde_json::to_string(push).unwrap();
+            assert!(json.contains(&format!("\"type\":\"{}\"", name)));
+            assert!(serde_json::from_str::<Response>(&json).is_err());
+            let parsed = serde_json::from_str::<Push>(&json).unwrap();
+            assert_eq!(parsed.change_counter(), push.change_counter());
+        }
+
+        // 可选字段缺失时仍可解析
+        let parsed: Push = serde_json::from_str(
+            r#"{"type":"SessionIdle","session_id":"s1","message_count":0,"last_event_ms":5}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            parsed,
+            Push::SessionIdle {
+                last_assistant_preview: None,
+                change_counter: 0,
+                ..
+            }
+        ));
+    }
 }
@@ -8,23 +8,49 @@
 //!
 //! 所有业务逻辑在此实现，FFI 层只做类型转换。
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+use crate::db::{normalize_project_path, SessionDB};
+use crate::error::{Error, Result};
 use crate::{
     ClaudeAdapter, ConversationAdapter, MessageType, ParseResult, ParsedMessage, SessionMeta,
     Source,
 };
 
+/// 检查目录是否可读
+///
+/// - 可读：`Ok(true)`
+/// - 不存在：`Ok(false)`
+/// - 无权限（EPERM/EACCES）：`Err(Error::AccessDenied)`
+pub fn check_dir_access(path: &Path) -> Result<bool> {
+    match fs::read_dir(path) {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            Err(Error::AccessDenied(path.to_path_buf()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
 /// 生成消息预览（最多 100 个 Unicode 字符）
 fn generate_preview(message: &ParsedMessage) -> String {
     match message.message_type {
         MessageType::User => {
-            // 用户消息：直接使用纯文本内容
-            truncate_chars(&message.content.text, 100)
+            // 用户消息：直接使用纯文本内容；纯图片/附件消息没有文本，改用 content 数组
+            match &message.raw {
+                Some(raw) if message.content.text.trim().is_empty() => {
+                    generate_assistant_preview(raw, &message.content.text)
+                }
+                _ => truncate_chars(&message.content.text, 100),
+            }
         }
         MessageType::Assistant => {
             // 助手消息：尝试解析 content 数组生成摘要
@@ -83,9 +109,20 @@ fn generate_preview_from_content_blocks(blocks: &[serde_json::Value]) -> String
                 }
                 result.push_str(&preview);
             }
-            "thinking" => {
+            "thinking" | "redacted_thinking" => {
                 // 跳过 thinking，但记录存在
             }
+            "image" | "document" => {
+                has_thinking_only = false;
+                if !result.is_empty() {
+                    result.push(' ');
+                }
+                result.push_str(if block_type == "image" {
+                    "🖼️ image"
+                } else {
+                    "📄 document"
+                });
+            }
             _ => {
                 has_thinking_only = false;
             }
@@ -142,8 +179,20 @@ fn truncate_chars(s: &str, max_chars: usize) -> String {
     }
 }
 
+/// 统计 JSONL 文件的非空行数（不解析），文件不可读时返回 None
+fn count_lines(path: &str) -> Option<usize> {
+    let reader = BufReader::new(fs::File::open(path).ok()?);
+    let mut count = 0;
+    for line in reader.split(b'\n') {
+        if !line.ok()?.iter().all(u8::is_ascii_whitespace) {
+            count += 1;
+        }
+    }
+    Some(count)
+}
+
 /// 解析时间戳为毫秒
-fn parse_timestamp_to_millis(ts: &str) -> Option<i64> {
+pub(crate) fn parse_timestamp_to_millis(ts: &str) -> Option<i64> {
     // 尝试解析 RFC3339 格式
     if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(ts) {
         return Some(dt.timestamp_millis());
@@ -155,13 +204,156 @@ fn parse_timestamp_to_millis(ts: &str) -> Option<i64> {
     None
 }
 
-/// 排序方向
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Claude JSONL 中的 summary 条目（`{"type":"summary","summary":"...","leafUuid":"..."}`）
+///
+/// compact 后续接的会话以 summary 开头；adapter 解析时丢弃这些行，由采集和预览单独读取。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SummaryEntry {
+    /// 摘要覆盖的最后一条消息
+    pub leaf_uuid: Option<String>,
+    pub summary: String,
+    /// 原始 JSONL 行
+    pub raw: String,
+}
+
+impl SummaryEntry {
+    /// 转换为 system 类型的消息
+    ///
+    /// uuid 由会话 ID 和 leafUuid（缺失时为文件内序号）组成，重复采集时按 uuid 去重；
+    /// summary 行没有时间戳，由调用方传入（通常取会话第一条消息的时间）。
+    pub(crate) fn to_parsed_message(
+        &self,
+        session_id: &str,
+        index: usize,
+        timestamp: Option<String>,
+    ) -> ParsedMessage {
+        let key = self.leaf_uuid.clone().unwrap_or_else(|| index.to_string());
+        ParsedMessage {
+            uuid: format!("{}:summary:{}", session_id, key),
+            session_id: session_id.to_string(),
+            message_type: MessageType::System,
+            content: ai_cli_session_collector::ParsedContent {
+                text: self.summary.clone(),
+                full: self.summary.clone(),
+            },
+            timestamp,
+            source: Source::Claude,
+            channel: Some("code".to_string()),
+            model: None,
+            tool_call_id: None,
+            tool_name: None,
+            tool_args: None,
+            raw: Some(self.raw.clone()),
+            cwd: None,
+            stop_reason: None,
+        }
+    }
+}
+
+/// 读取会话文件中的 summary 条目（按文件顺序）
+///
+/// `leading_only` 时遇到第一条非 summary 条目即停止，只读文件开头。
+pub(crate) fn read_summary_entries(path: &Path, leading_only: bool) -> Vec<SummaryEntry> {
+    let Ok(file) = fs::File::open(path) else {
+        return Vec::new();
+    };
+
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        // 先按子串粗筛，避免逐行解析 JSON
+        match line
+            .contains("\"summary\"")
+            .then(|| parse_summary_entry(&line))
+            .flatten()
+        {
+            Some(entry) => entries.push(entry),
+            None if leading_only => break,
+            None => {}
+        }
+    }
+    entries
+}
+
+fn parse_summary_entry(line: &str) -> Option<SummaryEntry> {
+    let value: serde_json::Value = serde_json::from_str(line).ok()?;
+    if value.get("type")?.as_str()? != "summary" {
+        return None;
+    }
+    let summary = value.get("summary")?.as_str()?.trim();
+    if summary.is_empty() {
+        return None;
+    }
+    Some(SummaryEntry {
+        leaf_uuid: value
+            .get("leafUuid")
+            .and_then(|v| v.as_str())
+            .map(str::to_string),
+        summary: summary.to_string(),
+        raw: line.to_string(),
+    })
+}
+
+/// 消息排序方向（db、reader、FFI 共用）
+///
+/// 按 sequence 排列：Asc 从最早一条开始，Desc 从最新一条开始。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum Order {
+    #[default]
     Asc,
     Desc,
 }
 
+impl Order {
+    /// 对应的 SQL 排序关键字
+    pub(crate) fn sql(self) -> &'static str {
+        match self {
+            Order::Asc => "ASC",
+            Order::Desc => "DESC",
+        }
+    }
+
+    /// 键集分页时"下一页"方向的比较运算符
+    pub(crate) fn seek_cmp(self) -> &'static str {
+        match self {
+            Order::Asc => ">",
+            Order::Desc => "<",
+        }
+    }
+}
+
+/// 按方向分页
+///
+/// `offset` 从 `order` 指定的一端开始计数（Asc 从最早一条，Desc 从最新一条），
+/// 页内顺序与 `order` 一致；`limit` 为 None 表示不限制。
+/// 返回 (当前页, 总数, 该方向上是否还有下一页)。
+pub(crate) fn paginate<T>(
+    mut items: Vec<T>,
+    limit: Option<usize>,
+    offset: usize,
+    order: Order,
+) -> (Vec<T>, usize, bool) {
+    let total = items.len();
+    if order == Order::Desc {
+        items.reverse();
+    }
+
+    let start = offset.min(total);
+    let end = match limit {
+        None => total,
+        Some(limit) => start.saturating_add(limit).min(total),
+    };
+    items.truncate(end);
+    items.drain(..start);
+
+    (items, total, end < total)
+}
+
 /// 项目信息
 #[derive(Debug, Clone)]
 pub struct ProjectInfo {
@@ -177,6 +369,33 @@ pub struct ProjectInfo {
     pub last_active: Option<u64>,
 }
 
+/// 数据库与 projects 目录对账后的项目（见 `SessionDB::list_projects_reconciled`）
+///
+/// - `in_db && on_disk`：正常
+/// - `in_db && !on_disk`：已归档，JSONL 已删除但历史仍在数据库中
+/// - `!in_db && on_disk`：尚未采集
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconciledProject {
+    /// 数据库中的项目 ID（尚未采集时为 None）
+    pub id: Option<i64>,
+    pub name: String,
+    #[serde(rename = "projectPath")]
+    pub path: String,
+    /// projects 目录下的编码目录名（数据库未记录且目录不存在时为 None）
+    pub encoded_dir_name: Option<String>,
+    pub in_db: bool,
+    pub on_disk: bool,
+    /// 目录下的 JSONL 文件数（不包含 agent session）
+    pub file_count: i64,
+    /// 数据库中的会话数
+    pub db_session_count: i64,
+    /// 数据库中的消息数
+    pub message_count: i64,
+    /// 最后活跃时间（毫秒时间戳，数据库与文件修改时间中较晚者）
+    pub last_active: Option<i64>,
+}
+
 /// 消息读取结果
 #[derive(Debug, Clone)]
 pub struct MessagesResult {
@@ -199,10 +418,129 @@ pub struct SessionMetrics {
     pub message_count: usize,
     pub user_message_count: usize,
     pub assistant_message_count: usize,
+    /// 全部消息的估算 token 数
     pub estimated_tokens: usize,
+    /// 用户消息的估算 token 数
+    pub user_estimated_tokens: usize,
+    /// 助手消息的估算 token 数
+    pub assistant_estimated_tokens: usize,
     pub duration_seconds: Option<u64>,
 }
 
+/// token 数估算（`SessionReader::calculate_metrics` 使用）
+///
+/// 默认为 `CharsPerTokenEstimator`，可通过 `SessionReader::with_token_estimator`
+/// 替换为按模型 tokenizer 计算的实现。
+pub trait TokenEstimator: Send + Sync {
+    /// 估算一段文本的 token 数
+    fn estimate(&self, text: &str) -> usize;
+
+    /// 估算多段文本的 token 总数（默认逐段相加）
+    fn estimate_all(&self, texts: &[&str]) -> usize {
+        texts.iter().map(|text| self.estimate(text)).sum()
+    }
+}
+
+/// 默认估算：UTF-8 字节数 / 4
+///
+/// 多段文本先合计字节数再相除，与旧版本的 `estimated_tokens` 一致。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CharsPerTokenEstimator;
+
+impl TokenEstimator for CharsPerTokenEstimator {
+    fn estimate(&self, text: &str) -> usize {
+        text.len() / 4
+    }
+
+    fn estimate_all(&self, texts: &[&str]) -> usize {
+        texts.iter().map(|text| text.len()).sum::<usize>() / 4
+    }
+}
+
+/// 估算消息的 token 数，返回 (全部, 用户, 助手)
+fn estimate_message_tokens(
+    estimator: &dyn TokenEstimator,
+    messages: &[ParsedMessage],
+) -> (usize, usize, usize) {
+    let texts_of = |filter: Option<MessageType>| -> Vec<&str> {
+        messages
+            .iter()
+            .filter(|m| filter.map_or(true, |t| m.message_type == t))
+            .map(|m| m.content.full.as_str())
+            .collect()
+    };
+    (
+        estimator.estimate_all(&texts_of(None)),
+        estimator.estimate_all(&texts_of(Some(MessageType::User))),
+        estimator.estimate_all(&texts_of(Some(MessageType::Assistant))),
+    )
+}
+
+/// 同一会话两次解析之间的消息差异（见 `diff_messages`）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessageDiff {
+    /// 只在新解析中出现的 uuid（按新解析中的顺序）
+    pub added: Vec<String>,
+    /// 只在旧解析中出现的 uuid（按旧解析中的顺序）
+    pub removed: Vec<String>,
+    /// 两次都有但内容不同的 uuid（按新解析中的顺序）
+    pub changed: Vec<String>,
+}
+
+impl MessageDiff {
+    /// 两次解析的消息完全相同
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// 按 uuid 比较同一会话的两次解析
+///
+/// 类型、内容、时间戳、模型、工具字段或原始 JSON 不同即视为 changed；
+/// 同一次解析中重复的 uuid 以最后一条为准。
+pub fn diff_messages(old: &[ParsedMessage], new: &[ParsedMessage]) -> MessageDiff {
+    let old_by_uuid: HashMap<&str, &ParsedMessage> =
+        old.iter().map(|m| (m.uuid.as_str(), m)).collect();
+    let new_by_uuid: HashMap<&str, &ParsedMessage> =
+        new.iter().map(|m| (m.uuid.as_str(), m)).collect();
+
+    let mut diff = MessageDiff::default();
+    let mut seen = HashSet::new();
+    for message in new {
+        let uuid = message.uuid.as_str();
+        if !seen.insert(uuid) {
+            continue;
+        }
+        match old_by_uuid.get(uuid) {
+            None => diff.added.push(uuid.to_string()),
+            Some(before) if !same_message(before, new_by_uuid[uuid]) => {
+                diff.changed.push(uuid.to_string())
+            }
+            Some(_) => {}
+        }
+    }
+    for message in old {
+        let uuid = message.uuid.as_str();
+        if !new_by_uuid.contains_key(uuid) && seen.insert(uuid) {
+            diff.removed.push(uuid.to_string());
+        }
+    }
+    diff
+}
+
+/// 两条 uuid 相同的消息内容是否一致
+fn same_message(a: &ParsedMessage, b: &ParsedMessage) -> bool {
+    a.message_type == b.message_type
+        && a.content.text == b.content.text
+        && a.content.full == b.content.full
+        && a.timestamp == b.timestamp
+        && a.model == b.model
+        && a.tool_call_id == b.tool_call_id
+        && a.tool_name == b.tool_name
+        && a.tool_args == b.tool_args
+        && a.raw == b.raw
+}
+
 /// 计算会话文件路径
 ///
 /// 路径规则: `{projects_path}/{encoded_dir_name}/{session_id}.jsonl`
@@ -227,6 +565,12 @@ pub struct SessionReader {
     adapter: ClaudeAdapter,
     /// 编码目录名缓存: project_path -> encoded_dir_name
     encoded_dir_cache: HashMap<String, String>,
+    /// list_sessions 时按行数填充 message_count
+    count_lines: bool,
+    /// 已采集的数据库（解析编码目录名时优先查询）
+    db: Option<SessionDB>,
+    /// calculate_metrics 的 token 估算
+    token_estimator: Box<dyn TokenEstimator>,
 }
 
 impl SessionReader {
@@ -237,9 +581,38 @@ impl SessionReader {
             projects_path,
             adapter,
             encoded_dir_cache: HashMap::new(),
+            count_lines: false,
+            db: None,
+            token_estimator: Box::new(CharsPerTokenEstimator),
         }
     }
 
+    /// 创建读取器，编码目录名和会话路径优先从数据库查询
+    ///
+    /// 数据库中的映射不依赖 JSONL 中的 cwd，也不需要遍历 projects 目录；
+    /// 查不到或目录已不存在时回退到文件系统扫描。
+    pub fn with_db(projects_path: PathBuf, db: &SessionDB) -> Self {
+        Self {
+            db: Some(db.share()),
+            ..Self::new(projects_path)
+        }
+    }
+
+    /// list_sessions 时为缺少 message_count 的会话按 JSONL 行数填充（默认关闭）
+    ///
+    /// 只数行、不解析，是近似值：工具结果、summary、快照等元数据行也会计入，
+    /// 通常大于解析后的消息数。需要准确值时使用 `calculate_metrics`。
+    pub fn with_line_counts(mut self, enabled: bool) -> Self {
+        self.count_lines = enabled;
+        self
+    }
+
+    /// calculate_metrics 使用的 token 估算（默认 `CharsPerTokenEstimator`）
+    pub fn with_token_estimator(mut self, estimator: impl TokenEstimator + 'static) -> Self {
+        self.token_estimator = Box::new(estimator);
+        self
+    }
+
     /// 使用默认路径创建读取器（跨平台）
     pub fn with_default_path() -> Option<Self> {
         let home = dirs::home_dir()?;
@@ -255,16 +628,17 @@ impl SessionReader {
     /// 列出所有项目
     ///
     /// 会话数量不包含 agent session。
-    pub fn list_projects(&mut self, limit: Option<usize>) -> Vec<ProjectInfo> {
+    /// projects 目录不存在时返回空列表，无权访问时返回 `Error::AccessDenied`。
+    pub fn list_projects(&mut self, limit: Option<usize>) -> Result<Vec<ProjectInfo>> {
         let mut results = Vec::new();
 
-        if !self.projects_path.exists() {
-            return results;
+        if !check_dir_access(&self.projects_path)? {
+            return Ok(results);
         }
 
         let entries = match fs::read_dir(&self.projects_path) {
             Ok(e) => e,
-            Err(_) => return results,
+            Err(_) => return Ok(results),
         };
 
         for entry in entries.flatten() {
@@ -306,7 +680,7 @@ impl SessionReader {
             results.truncate(limit);
         }
 
-        results
+        Ok(results)
     }
 
     /// 扫描项目目录，返回 (decoded_path, session_count, last_active)
@@ -399,15 +773,21 @@ impl SessionReader {
     /// # Arguments
     /// * `project_path` - 可选的项目路径过滤
     /// * `include_agents` - 是否包含 agent session (agent-xxx)
+    ///
+    /// projects 目录不存在时返回空列表，无权访问时返回 `Error::AccessDenied`。
     pub fn list_sessions(
         &mut self,
         project_path: Option<&str>,
         include_agents: bool,
-    ) -> Vec<SessionMeta> {
+    ) -> Result<Vec<SessionMeta>> {
+        if !check_dir_access(&self.projects_path)? {
+            return Ok(vec![]);
+        }
+
         // 使用 ClaudeAdapter 获取所有会话
         let mut sessions = match self.adapter.list_sessions() {
             Ok(s) => s,
-            Err(_) => return vec![],
+            Err(_) => return Ok(vec![]),
         };
 
         // 更新 encoded_dir_cache
@@ -431,16 +811,31 @@ impl SessionReader {
         // 按修改时间排序（降序）
         sessions.sort_by(|a, b| b.file_mtime.cmp(&a.file_mtime));
 
-        sessions
+        // 近似消息数（行数）
+        if self.count_lines {
+            for session in &mut sessions {
+                if session.message_count.is_none() {
+                    session.message_count = session
+                        .session_path
+                        .as_deref()
+                        .and_then(count_lines)
+                        .map(|count| count as _);
+                }
+            }
+        }
+
+        Ok(sessions)
     }
 
     /// 列出会话（带最后消息预览）
+    ///
+    /// 以 summary 开头的会话（compact 后续接）预览最新的摘要。
     pub fn list_sessions_with_preview(
         &mut self,
         project_path: Option<&str>,
         include_agents: bool,
-    ) -> Vec<SessionMeta> {
-        let mut sessions = self.list_sessions(project_path, include_agents);
+    ) -> Result<Vec<SessionMeta>> {
+        let mut sessions = self.list_sessions(project_path, include_agents)?;
 
         // 为每个 session 填充 lastMessage 预览
         for session in &mut sessions {
@@ -457,16 +852,19 @@ impl SessionReader {
                         .as_ref()
                         .and_then(|ts| parse_timestamp_to_millis(ts));
                 }
+                if let Some(summary) = read_summary_entries(Path::new(session_path), true).pop() {
+                    session.last_message_preview = Some(truncate_chars(&summary.summary, 100));
+                }
             }
         }
 
-        sessions
+        Ok(sessions)
     }
 
     /// 读取最后一条消息
     fn read_last_message(&self, session_path: &str) -> Option<ParsedMessage> {
         // 使用 read_messages 获取最后一条
-        let result = self.read_messages(session_path, 1, 0, Order::Desc)?;
+        let result = self.read_messages(session_path, Some(1), 0, Order::Desc)?;
         result.messages.into_iter().next()
     }
 
@@ -476,7 +874,7 @@ impl SessionReader {
         project_path: &str,
         within_seconds: Option<u64>,
     ) -> Option<SessionMeta> {
-        let sessions = self.list_sessions(Some(project_path), false);
+        let sessions = self.list_sessions(Some(project_path), false).ok()?;
 
         if sessions.is_empty() {
             return None;
@@ -501,10 +899,59 @@ impl SessionReader {
         Some(latest)
     }
 
+    /// 获取项目最新会话的 ID
+    ///
+    /// 只按 mtime 比较项目编码目录下的 JSONL 文件（不含 agent session），
+    /// 不解析文件内容，比 `find_latest_session` 开销小得多。
+    pub fn latest_session_id(&mut self, project_path: &str) -> Option<String> {
+        let encoded_dir_name = self.get_encoded_dir_name(project_path)?;
+        let files = fs::read_dir(self.projects_path.join(encoded_dir_name)).ok()?;
+
+        let mut latest: Option<(SystemTime, String)> = None;
+        for file_entry in files.flatten() {
+            let file_path = file_entry.path();
+            let session_id = match file_path.file_name().and_then(|s| s.to_str()) {
+                Some(s) if s.ends_with(".jsonl") => s.trim_end_matches(".jsonl"),
+                _ => continue,
+            };
+
+            // 过滤 agent session
+            if session_id.is_empty() || session_id.starts_with("agent-") {
+                continue;
+            }
+
+            let mtime = match file_entry.metadata().and_then(|m| m.modified()) {
+                Ok(t) => t,
+                Err(_) => continue,
+            };
+            let is_newer = match &latest {
+                Some((latest_mtime, _)) => mtime > *latest_mtime,
+                None => true,
+            };
+            if is_newer {
+                latest = Some((mtime, session_id.to_string()));
+            }
+        }
+
+        latest.map(|(_, session_id)| session_id)
+    }
+
     /// 获取会话文件路径
     ///
-    /// 在 projects_path 下搜索 `{session_id}.jsonl` 文件
+    /// 有数据库时先按记录的编码目录名计算路径，否则在 projects_path 下搜索
+    /// `{session_id}.jsonl` 文件
     pub fn get_session_path(&self, session_id: &str) -> Option<String> {
+        if let Some(encoded) = self
+            .db
+            .as_ref()
+            .and_then(|db| db.get_session_encoded_dir_name(session_id).ok().flatten())
+        {
+            let session_path = compute_session_path(&self.projects_path, &encoded, session_id);
+            if session_path.is_file() {
+                return Some(session_path.to_string_lossy().to_string());
+            }
+        }
+
         let target_filename = format!("{}.jsonl", session_id);
 
         // 遍历所有项目目录
@@ -537,13 +984,26 @@ impl SessionReader {
 
     /// 获取项目的编码目录名
     ///
-    /// 优先从缓存获取
+    /// 依次查询缓存、数据库（`with_db` 创建时）和文件系统
     pub fn get_encoded_dir_name(&mut self, project_path: &str) -> Option<String> {
         // 先查缓存
         if let Some(encoded) = self.encoded_dir_cache.get(project_path) {
             return Some(encoded.clone());
         }
 
+        // 再查数据库（记录的目录已被删除时忽略）
+        if let Some(encoded) = self
+            .db
+            .as_ref()
+            .and_then(|db| db.get_project_by_path(project_path).ok().flatten())
+            .and_then(|project| project.encoded_dir_name)
+            .filter(|encoded| self.projects_path.join(encoded).is_dir())
+        {
+            self.encoded_dir_cache
+                .insert(project_path.to_string(), encoded.clone());
+            return Some(encoded);
+        }
+
         // 缓存未命中，刷新项目列表
         let _ = self.list_projects(None);
 
@@ -551,11 +1011,11 @@ impl SessionReader {
         self.encoded_dir_cache.get(project_path).cloned()
     }
 
-    /// 读取会话消息（支持分页）
+    /// 读取会话消息（支持分页，`limit` 为 None 表示不限制）
     pub fn read_messages(
         &self,
         session_path: &str,
-        limit: usize,
+        limit: Option<usize>,
         offset: usize,
         order: Order,
     ) -> Option<MessagesResult> {
@@ -591,20 +1051,7 @@ impl SessionReader {
         };
 
         let result = self.adapter.parse_session(&meta).ok()??;
-
-        let mut all_messages = result.messages;
-        let total = all_messages.len();
-
-        // 排序
-        if order == Order::Desc {
-            all_messages.reverse();
-        }
-
-        // 分页
-        let start = offset.min(total);
-        let end = (offset + limit).min(total);
-        let messages: Vec<_> = all_messages[start..end].to_vec();
-        let has_more = end < total;
+        let (messages, total, has_more) = paginate(result.messages, limit, offset, order);
 
         Some(MessagesResult {
             messages,
@@ -613,11 +1060,11 @@ impl SessionReader {
         })
     }
 
-    /// 读取原始 JSONL 消息（不做格式转换）
+    /// 读取原始 JSONL 消息（不做格式转换，`limit` 为 None 表示不限制）
     pub fn read_messages_raw(
         &self,
         session_path: &str,
-        limit: usize,
+        limit: Option<usize>,
         offset: usize,
         order: Order,
     ) -> Option<RawMessagesResult> {
@@ -635,18 +1082,7 @@ impl SessionReader {
             }
         }
 
-        let total = all_messages.len();
-
-        // 排序
-        if order == Order::Desc {
-            all_messages.reverse();
-        }
-
-        // 分页
-        let start = offset.min(total);
-        let end = (offset + limit).min(total);
-        let messages: Vec<_> = all_messages[start..end].to_vec();
-        let has_more = end < total;
+        let (messages, total, has_more) = paginate(all_messages, limit, offset, order);
 
         Some(RawMessagesResult {
             messages,
@@ -682,9 +1118,8 @@ impl SessionReader {
             .filter(|m| m.message_type == MessageType::Assistant)
             .count();
 
-        // 估算 token 数（简单按字符数 / 4）
-        let total_chars: usize = result.messages.iter().map(|m| m.content.full.len()).sum();
-        let estimated_tokens = total_chars / 4;
+        let (estimated_tokens, user_tokens, assistant_tokens) =
+            estimate_message_tokens(self.token_estimator.as_ref(), &result.messages);
 
         // 计算时长
         let duration = if let (Some(first), Some(last)) = (&result.created_at, &result.updated_at) {
@@ -705,11 +1140,126 @@ impl SessionReader {
             user_message_count: user_count,
             assistant_message_count: assistant_count,
             estimated_tokens,
+            user_estimated_tokens: user_tokens,
+            assistant_estimated_tokens: assistant_tokens,
             duration_seconds: duration,
         })
     }
 }
 
+impl SessionDB {
+    /// 合并数据库中的项目与 projects 目录下的项目目录（按最后活跃时间排序，分页）
+    ///
+    /// 用户删除旧 JSONL 后文件系统列表不再显示这些项目，数据库列表仍然显示，
+    /// 这里合并两侧并标注各自的状态（见 `ReconciledProject`）。
+    ///
+    /// - 只包含 Claude 项目（projects 目录只保存 Claude 会话），不含被忽略的项目
+    /// - 目录按编码目录名与数据库中的项目匹配，匹配不到时按路径匹配
+    /// - projects 目录不存在时只返回数据库中的项目，无权访问时返回 `Error::AccessDenied`
+    pub fn list_projects_reconciled(
+        &self,
+        projects_path: &Path,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<ReconciledProject>> {
+        let on_disk = SessionReader::new(projects_path.to_path_buf()).list_projects(None)?;
+
+        // (项目, 是否被忽略)
+        let mut projects: Vec<(ReconciledProject, bool)> = {
+            let conn = self.conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT
+                    p.id,
+                    p.name,
+                    p.path,
+                    p.encoded_dir_name,
+                    p.ignored,
+                    COUNT(s.id),
+                    COALESCE(SUM(s.message_count), 0),
+                    MAX(COALESCE(s.last_message_at, s.updated_at))
+                FROM projects p
+                LEFT JOIN sessions s ON s.project_id = p.id
+                WHERE p.source = ?1
+                GROUP BY p.id
+                "#,
+            )?;
+            let rows = stmt.query_map(params![Source::Claude.to_string()], |row| {
+                let project = ReconciledProject {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    path: row.get(2)?,
+                    encoded_dir_name: row.get(3)?,
+                    in_db: true,
+                    on_disk: false,
+                    file_count: 0,
+                    db_session_count: row.get(5)?,
+                    message_count: row.get(6)?,
+                    last_active: row.get(7)?,
+                };
+                Ok((project, row.get::<_, bool>(4)?))
+            })?;
+            rows.collect::<std::result::Result<Vec<_>, _>>()?
+        };
+
+        let by_encoded: HashMap<String, usize> = projects
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (p, _))| Some((p.encoded_dir_name.clone()?, i)))
+            .collect();
+        let by_path: HashMap<String, usize> = projects
+            .iter()
+            .enumerate()
+            .map(|(i, (p, _))| (p.path.clone(), i))
+            .collect();
+
+        for dir in on_disk {
+            let last_active = dir.last_active.map(|t| t as i64);
+            let matched = by_encoded
+                .get(&dir.encoded_name)
+                .or_else(|| by_path.get(&normalize_project_path(&dir.path)));
+            let Some(&i) = matched else {
+                projects.push((
+                    ReconciledProject {
+                        id: None,
+                        name: dir.name,
+                        path: dir.path,
+                        encoded_dir_name: Some(dir.encoded_name),
+                        in_db: false,
+                        on_disk: true,
+                        file_count: dir.session_count as i64,
+                        db_session_count: 0,
+                        message_count: 0,
+                        last_active,
+                    },
+                    false,
+                ));
+                continue;
+            };
+            // 同一项目可能有多个目录（如项目改名前后）
+            let project = &mut projects[i].0;
+            project.on_disk = true;
+            project.file_count += dir.session_count as i64;
+            project.encoded_dir_name.get_or_insert(dir.encoded_name);
+            project.last_active = project.last_active.max(last_active);
+        }
+
+        let mut projects: Vec<ReconciledProject> = projects
+            .into_iter()
+            .filter(|(_, ignored)| !ignored)
+            .map(|(project, _)| project)
+            .collect();
+        projects.sort_by(|a, b| {
+            (a.last_active.is_none(), b.last_active, &a.path).cmp(&(
+                b.last_active.is_none(),
+                a.last_active,
+                &b.path,
+            ))
+        });
+        Ok(projects.into_iter().skip(offset).take(limit).collect())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -723,6 +1273,303 @@ mod tests {
         assert_eq!(SessionReader::extract_project_name("/a/b/c/d"), "d");
     }
 
+    #[test]
+    fn test_latest_session_id() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let project_dir = tmp.path().join("-tmp-myproject");
+        fs::create_dir_all(&project_dir).unwrap();
+
+        let base = SystemTime::now() - std::time::Duration::from_secs(3600);
+        // (session_id, mtime 偏移秒数)
+        for (session_id, offset) in [
+            ("old", 0),
+            ("newest", 300),
+            ("middle", 100),
+            ("agent-sub", 600),
+        ] {
+            let path = project_dir.join(format!("{}.jsonl", session_id));
+            fs::write(&path, "{\"type\":\"user\",\"cwd\":\"/tmp/myproject\"}\n").unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(base + std::time::Duration::from_secs(offset))
+                .unwrap();
+        }
+
+        let mut reader = SessionReader::new(tmp.path().to_path_buf());
+        assert_eq!(
+            reader.latest_session_id("/tmp/myproject").as_deref(),
+            Some("newest")
+        );
+        assert_eq!(reader.latest_session_id("/tmp/unknown"), None);
+    }
+
+    #[test]
+    fn test_encoded_dir_name_from_db() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let projects_path = tmp.path().join("projects");
+        // 目录名与项目路径的编码不一致，JSONL 中也没有 cwd
+        let project_dir = projects_path.join("-renamed-checkout");
+        fs::create_dir_all(&project_dir).unwrap();
+        fs::write(
+            project_dir.join("s1.jsonl"),
+            "{\"type\":\"user\",\"sessionId\":\"s1\"}\n",
+        )
+        .unwrap();
+
+        let mut reader = SessionReader::new(projects_path.clone());
+        assert_eq!(reader.get_encoded_dir_name("/work/app"), None);
+
+        let db =
+            SessionDB::connect(crate::config::DbConfig::local(tmp.path().join("test.db"))).unwrap();
+        let project_id = db
+            .get_or_create_project_with_encoded(
+                "app",
+                "/work/app",
+                "claude",
+                Some("-renamed-checkout"),
+            )
+            .unwrap();
+        db.upsert_session("s1", project_id).unwrap();
+
+        let mut reader = SessionReader::with_db(projects_path.clone(), &db);
+        assert_eq!(
+            reader.get_encoded_dir_name("/work/app").as_deref(),
+            Some("-renamed-checkout")
+        );
+        assert_eq!(
+            reader.get_session_path("s1"),
+            Some(project_dir.join("s1.jsonl").to_string_lossy().to_string())
+        );
+        // 数据库中没有的会话回退到文件系统扫描
+        assert_eq!(reader.get_session_path("missing"), None);
+    }
+
+    #[test]
+    fn test_line_count_approximates_message_count() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let project_dir = tmp.path().join("-tmp-counted");
+        fs::create_dir_all(&project_dir).unwrap();
+
+        let mut lines =
+            vec![r#"{"type":"summary","summary":"Counted session","leafUuid":"a9"}"#.to_string()];
+        for i in 0..10 {
+            lines.push(format!(
+                r#"{{"type":"user","uuid":"u{i}","sessionId":"counted","cwd":"/tmp/counted","timestamp":"2025-01-01T00:00:{i:02}Z","message":{{"role":"user","content":"question {i}"}}}}"#
+            ));
+            lines.push(format!(
+                r#"{{"type":"assistant","uuid":"a{i}","parentUuid":"u{i}","sessionId":"counted","cwd":"/tmp/counted","timestamp":"2025-01-01T00:01:{i:02}Z","message":{{"role":"assistant","content":[{{"type":"text","text":"answer {i}"}}]}}}}"#
+            ));
+        }
+        lines.push(String::new());
+        fs::write(project_dir.join("counted.jsonl"), lines.join("\n")).unwrap();
+
+        let mut reader = SessionReader::new(tmp.path().to_path_buf());
+        let sessions = reader.list_sessions(None, false).unwrap();
+        let parsed = reader.parse_session(&sessions[0]).unwrap().messages.len();
+
+        let mut reader = SessionReader::new(tmp.path().to_path_buf()).with_line_counts(true);
+        let sessions = reader.list_sessions(None, false).unwrap();
+        let approx = sessions[0].message_count.unwrap() as usize;
+
+        // 21 行（含 summary 元数据行，空行不计），与解析结果相差不超过 10%
+        assert_eq!(approx, 21);
+        assert!(approx >= parsed);
+        assert!(
+            approx - parsed <= parsed / 10 + 1,
+            "approx={approx}, parsed={parsed}"
+        );
+    }
+
+    #[test]
+    fn test_preview_prefers_leading_summary() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let project_dir = tmp.path().join("-tmp-compacted");
+        fs::create_dir_all(&project_dir).unwrap();
+        let lines = [
+            r#"{"type":"summary","summary":"Old topic","leafUuid":"x1"}"#,
+            r#"{"type":"summary","summary":"Fix the flaky build","leafUuid":"x2"}"#,
+            r#"{"type":"user","uuid":"u1","sessionId":"compacted","cwd":"/tmp/compacted","timestamp":"2025-01-01T00:00:00Z","message":{"role":"user","content":"continue"}}"#,
+            r#"{"type":"summary","summary":"Trailing title","leafUuid":"u1"}"#,
+        ];
+        let path = project_dir.join("compacted.jsonl");
+        fs::write(&path, lines.join("\n")).unwrap();
+
+        let leading = read_summary_entries(&path, true);
+        assert_eq!(leading.len(), 2);
+        assert_eq!(leading[1].leaf_uuid.as_deref(), Some("x2"));
+        assert_eq!(read_summary_entries(&path, false).len(), 3);
+
+        let mut reader = SessionReader::new(tmp.path().to_path_buf());
+        let sessions = reader.list_sessions_with_preview(None, false).unwrap();
+        assert_eq!(
+            sessions[0].last_message_preview.as_deref(),
+            Some("Fix the flaky build")
+        );
+
+        let message = leading[1].to_parsed_message("compacted", 1, None);
+        assert_eq!(message.uuid, "compacted:summary:x2");
+        assert_eq!(message.message_type, MessageType::System);
+    }
+
+    #[test]
+    fn test_preview_image_only_blocks() {
+        let blocks = vec![serde_json::json!({
+            "type": "image",
+            "source": {"type": "base64", "media_type": "image/png", "data": "AAAA"}
+        })];
+        assert_eq!(generate_preview_from_content_blocks(&blocks), "🖼️ image");
+
+        let blocks = vec![
+            serde_json::json!({"type": "text", "text": "看看这张图"}),
+            serde_json::json!({"type": "image", "source": {}}),
+            serde_json::json!({"type": "document", "source": {}}),
+        ];
+        assert_eq!(
+            generate_preview_from_content_blocks(&blocks),
+            "看看这张图 🖼️ image 📄 document"
+        );
+    }
+
+    #[test]
+    fn test_preview_user_image_only_message() {
+        let raw = serde_json::json!({
+            "type": "user",
+            "message": {"role": "user", "content": [{"type": "image", "source": {}}]}
+        });
+        let message = ParsedMessage {
+            uuid: "uuid-1".to_string(),
+            session_id: "session-1".to_string(),
+            message_type: MessageType::User,
+            content: ai_cli_session_collector::ParsedContent {
+                text: String::new(),
+                full: String::new(),
+            },
+            timestamp: None,
+            source: Source::Claude,
+            channel: None,
+            model: None,
+            tool_call_id: None,
+            tool_name: None,
+            tool_args: None,
+            raw: Some(raw.to_string()),
+            cwd: None,
+            stop_reason: None,
+        };
+        assert_eq!(generate_preview(&message), "🖼️ image");
+    }
+
+    fn text_message(message_type: MessageType, text: &str) -> ParsedMessage {
+        ParsedMessage {
+            uuid: "uuid-1".to_string(),
+            session_id: "session-1".to_string(),
+            message_type,
+            content: ai_cli_session_collector::ParsedContent {
+                text: text.to_string(),
+                full: text.to_string(),
+            },
+            timestamp: None,
+            source: Source::Claude,
+            channel: None,
+            model: None,
+            tool_call_id: None,
+            tool_name: None,
+            tool_args: None,
+            raw: None,
+            cwd: None,
+            stop_reason: None,
+        }
+    }
+
+    fn uuid_message(uuid: &str, text: &str) -> ParsedMessage {
+        ParsedMessage {
+            uuid: uuid.to_string(),
+            ..text_message(MessageType::Assistant, text)
+        }
+    }
+
+    #[test]
+    fn test_diff_messages() {
+        let old = vec![
+            uuid_message("u1", "question"),
+            uuid_message("a1", "partial answer"),
+            uuid_message("gone", "rewritten away"),
+        ];
+        let new = vec![
+            uuid_message("u1", "question"),
+            uuid_message("a1", "partial answer, now complete"),
+            uuid_message("u2", "follow-up"),
+        ];
+
+        let diff = diff_messages(&old, &new);
+        assert_eq!(diff.added, vec!["u2"]);
+        assert_eq!(diff.changed, vec!["a1"]);
+        assert_eq!(diff.removed, vec!["gone"]);
+
+        assert!(diff_messages(&new, &new).is_empty());
+        assert_eq!(diff_messages(&[], &new).added, vec!["u1", "a1", "u2"]);
+    }
+
+    #[test]
+    fn test_default_token_estimate_matches_total_bytes() {
+        let messages = vec![
+            text_message(MessageType::User, "abc"),
+            text_message(MessageType::Assistant, "defgh"),
+            text_message(MessageType::User, "你好"),
+        ];
+        let (total, user, assistant) = estimate_message_tokens(&CharsPerTokenEstimator, &messages);
+        // 与旧版本一致：合计字节数后再除以 4（3 + 5 + 6 = 14）
+        assert_eq!(total, 14 / 4);
+        assert_eq!(user, 9 / 4);
+        assert_eq!(assistant, 5 / 4);
+    }
+
+    #[test]
+    fn test_custom_token_estimator() {
+        struct Words;
+        impl TokenEstimator for Words {
+            fn estimate(&self, text: &str) -> usize {
+                text.split_whitespace().count()
+            }
+        }
+
+        let messages = vec![
+            text_message(MessageType::User, "fix the build"),
+            text_message(MessageType::Assistant, "done"),
+        ];
+        assert_eq!(estimate_message_tokens(&Words, &messages), (4, 3, 1));
+
+        let reader = SessionReader::new(PathBuf::from("/nonexistent")).with_token_estimator(Words);
+        assert_eq!(reader.token_estimator.estimate("a b c"), 3);
+    }
+
+    #[test]
+    fn test_paginate_both_orders() {
+        let items: Vec<u32> = (0..10).collect();
+
+        let (page, total, has_more) = paginate(items.clone(), Some(3), 0, Order::Asc);
+        assert_eq!((page, total, has_more), (vec![0, 1, 2], 10, true));
+        let (page, _, has_more) = paginate(items.clone(), Some(3), 9, Order::Asc);
+        assert_eq!((page, has_more), (vec![9], false));
+
+        // 降序：offset 从最新一端计数，页内为新到旧
+        let (page, total, has_more) = paginate(items.clone(), Some(3), 0, Order::Desc);
+        assert_eq!((page, total, has_more), (vec![9, 8, 7], 10, true));
+        let (page, _, has_more) = paginate(items.clone(), Some(3), 9, Order::Desc);
+        assert_eq!((page, has_more), (vec![0], false));
+        let (page, _, has_more) = paginate(items.clone(), Some(3), 6, Order::Desc);
+        assert_eq!((page, has_more), (vec![3, 2, 1], true));
+
+        // None 不限制，Some(0) 返回空页，offset 越界返回空页
+        let (page, _, has_more) = paginate(items.clone(), None, 4, Order::Asc);
+        assert_eq!((page.len(), has_more), (6, false));
+        let (page, _, has_more) = paginate(items.clone(), Some(0), 4, Order::Asc);
+        assert_eq!((page.len(), has_more), (0, true));
+        let (page, total, has_more) = paginate(items, Some(3), 20, Order::Desc);
+        assert_eq!((page.len(), total, has_more), (0, 10, false));
+    }
+
     #[test]
     fn test_compute_session_path() {
         let projects_path = PathBuf::from("/home/user/.claude/projects");
@@ -0,0 +1,292 @@
+//! 从 raw 重新提取 content_text
+//!
+//! content_text 的提取规则会改进（tool_result 文本、去除 thinking 等），但旧版本写入的行
+//! 仍带着旧的提取结果，影响向量和预览。`SessionDB::rederive_content_text` 按 id 顺序
+//! 遍历有 raw 的消息，用新规则重新提取：
+//!
+//! - 每批在一个事务内读取、提取并写回，同时推进持久化的进度（metadata 表的
+//!   `REDERIVE_CONTENT_TEXT_KEY`），中断后从上次提交的位置继续
+//! - 只有文本实际变化的行被更新：`vector_indexed` 重置为 0（向量随之刷新），
+//!   旧的 FTS 索引由 `messages_au` 触发器删除，新内容与写入路径一样在 SAVEPOINT 中建索引
+//! - 被截断的消息（`truncated = 1`）不处理，raw 为空的消息没有可提取的内容
+//!
+//! 提取规则再次改进后用 `reset_rederive_progress` 从头开始。
+
+use std::collections::BTreeMap;
+
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::db::{
+    bump_change_counter, fts_enabled, get_metadata, index_message_fts, set_metadata, SessionDB,
+};
+use crate::error::Result;
+use crate::observer::ChangeEvent;
+
+/// 默认每批处理的消息数
+pub const REDERIVE_BATCH_SIZE: usize = 500;
+
+/// 进度在 metadata 表中的 key（值为已处理到的最大消息 ID）
+pub const REDERIVE_CONTENT_TEXT_KEY: &str = "rederive_content_text.last_id";
+
+/// 本次运行的统计（`last_id` 为持久化的进度）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RederiveProgress {
+    /// 检查的消息数
+    pub examined: usize,
+    /// content_text 发生变化并已更新的消息数
+    pub changed: usize,
+    /// 变化的消息中原已向量索引、需要重新生成向量的数量
+    pub reindex_queued: usize,
+    /// 已提交的批次数
+    pub batches: usize,
+    /// 已处理到的最大消息 ID
+    pub last_id: i64,
+    /// 是否已处理完所有消息（被中断时为 false）
+    pub done: bool,
+}
+
+impl SessionDB {
+    /// 用 `extractor` 从 raw 重新提取所有消息的 content_text（见模块文档）
+    ///
+    /// - batch_size: 每个事务处理的消息数
+    /// - extractor: 返回 None 时保留原值；默认规则为 `extract_content_text`
+    pub fn rederive_content_text(
+        &self,
+        batch_size: usize,
+        extractor: impl Fn(&str) -> Option<String>,
+    ) -> Result<RederiveProgress> {
+        self.rederive_content_text_until(batch_size, extractor, |_| true)
+    }
+
+    /// 同 `rederive_content_text`，每批提交后调用 `should_continue`，返回 false 时停止
+    ///
+    /// 停止后再次调用从已提交的进度继续。
+    pub fn rederive_content_text_until(
+        &self,
+        batch_size: usize,
+        extractor: impl Fn(&str) -> Option<String>,
+        mut should_continue: impl FnMut(&RederiveProgress) -> bool,
+    ) -> Result<RederiveProgress> {
+        let batch_size = batch_size.max(1);
+        let mut progress = RederiveProgress {
+            last_id: self.rederive_last_id()?,
+            ..Default::default()
+        };
+
+        loop {
+            let (count, updated) = self.rederive_batch(batch_size, &extractor, &mut progress)?;
+            if !updated.is_empty() {
+                self.notify(
+                    updated
+                        .into_iter()
+                        .map(|(session_id, count)| ChangeEvent::MessagesUpdated {
+                            session_id,
+                            count,
+                        })
+                        .collect(),
+                );
+            }
+            if count < batch_size {
+                progress.done = true;
+                return Ok(progress);
+            }
+            if !should_continue(&progress) {
+                return Ok(progress);
+            }
+        }
+    }
+
+    /// 已持久化的进度（尚未开始时为 0）
+    pub fn rederive_last_id(&self) -> Result<i64> {
+        let conn = self.conn.lock();
+        let value = get_metadata(&conn, REDERIVE_CONTENT_TEXT_KEY)?;
+        Ok(value.and_then(|v| v.parse().ok()).unwrap_or(0))
+    }
+
+    /// 清除进度，下次 `rederive_content_text` 从第一条消息开始
+    pub fn reset_rederive_progress(&self) -> Result<()> {
+        self.conn.lock().execute(
+            "DELETE FROM metadata WHERE key = ?1",
+            params![REDERIVE_CONTENT_TEXT_KEY],
+        )?;
+        Ok(())
+    }
+
+    /// 处理一批消息，返回 (读取的行数, 各会话更新的消息数)
+    fn rederive_batch(
+        &self,
+        batch_size: usize,
+        extractor: &impl Fn(&str) -> Option<String>,
+        progress: &mut RederiveProgress,
+    ) -> Result<(usize, BTreeMap<String, usize>)> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        let rows = {
+            let mut stmt = tx.prepare(
+                r#"
+                SELECT id, session_id, uuid, raw, content_text, vector_indexed
+                FROM messages
+                WHERE id > ?1 AND raw IS NOT NULL AND truncated = 0
+                ORDER BY id
+                LIMIT ?2
+                "#,
+            )?;
+            let rows = stmt.query_map(params![progress.last_id, batch_size as i64], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, i64>(5)? != 0,
+                ))
+            })?;
+            rows.collect::<std::result::Result<Vec<_>, _>>()?
+        };
+
+        let mut updated: BTreeMap<String, usize> = BTreeMap::new();
+        let mut last_id = progress.last_id;
+        let fts = fts_enabled(&tx)?;
+        for (id, session_id, uuid, raw, content_text, vector_indexed) in &rows {
+            last_id = *id;
+            let Some(text) = extractor(raw) else {
+                continue;
+            };
+            if text == *content_text {
+                continue;
+            }
+            tx.execute(
+                "UPDATE messages SET content_text = ?1, vector_indexed = 0 WHERE id = ?2",
+                params![text, id],
+            )?;
+            if fts {
+                index_message_fts(&tx, *id, uuid)?;
+            }
+            *updated.entry(session_id.clone()).or_default() += 1;
+            if *vector_indexed {
+                progress.reindex_queued += 1;
+            }
+        }
+
+        if !rows.is_empty() {
+            set_metadata(&tx, REDERIVE_CONTENT_TEXT_KEY, &last_id.to_string())?;
+        }
+        if !updated.is_empty() {
+            bump_change_counter(&tx)?;
+        }
+        tx.commit()?;
+
+        progress.examined += rows.len();
+        progress.changed += updated.values().sum::<usize>();
+        progress.last_id = last_id;
+        if !rows.is_empty() {
+            progress.batches += 1;
+        }
+        Ok((rows.len(), updated))
+    }
+}
+
+/// 默认的 content_text 提取规则（当前最佳规则）
+///
+/// 支持 Claude JSONL（`message.content`）和 Codex（`payload.content`）的 raw：
+/// - 保留 text / input_text / output_text 块，以及 tool_result 中的文本
+/// - 去除 thinking / redacted_thinking 块，不包含 tool_use 和图片等
+///
+/// raw 无法解析或不含消息内容时返回 None（保留原值）。
+pub fn extract_content_text(raw: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(raw).ok()?;
+    let content = value
+        .get("message")
+        .and_then(|m| m.get("content"))
+        .or_else(|| value.get("payload").and_then(|p| p.get("content")))?;
+
+    let mut parts = Vec::new();
+    collect_text(content, &mut parts);
+    Some(parts.join("\n").trim().to_string())
+}
+
+/// 收集内容中的文本（字符串或内容块数组）
+fn collect_text(content: &Value, parts: &mut Vec<String>) {
+    match content {
+        Value::String(s) => {
+            if !s.trim().is_empty() {
+                parts.push(s.clone());
+            }
+        }
+        Value::Array(blocks) => {
+            for block in blocks {
+                collect_block_text(block, parts);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn collect_block_text(block: &Value, parts: &mut Vec<String>) {
+    if let Value::String(_) = block {
+        collect_text(block, parts);
+        return;
+    }
+    match block.get("type").and_then(Value::as_str) {
+        Some("text" | "input_text" | "output_text") => {
+            if let Some(text) = block.get("text") {
+                collect_text(text, parts);
+            }
+        }
+        Some("tool_result") => {
+            if let Some(content) = block.get("content") {
+                collect_text(content, parts);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_claude_message() {
+        let raw = r#"{"type":"assistant","message":{"role":"assistant","content":[
+            {"type":"thinking","thinking":"secret plan"},
+            {"type":"text","text":"Here is the fix."},
+            {"type":"tool_use","id":"t1","name":"Bash","input":{"command":"ls"}}
+        ]}}"#;
+        assert_eq!(
+            extract_content_text(raw).as_deref(),
+            Some("Here is the fix.")
+        );
+
+        let raw = r#"{"type":"user","message":{"role":"user","content":"hello"}}"#;
+        assert_eq!(extract_content_text(raw).as_deref(), Some("hello"));
+    }
+
+    #[test]
+    fn test_extract_tool_result_text() {
+        let raw = r#"{"type":"user","message":{"role":"user","content":[
+            {"type":"tool_result","tool_use_id":"t1","content":[{"type":"text","text":"file.rs"}]},
+            {"type":"tool_result","tool_use_id":"t2","content":"exit 0"}
+        ]}}"#;
+        assert_eq!(
+            extract_content_text(raw).as_deref(),
+            Some("file.rs\nexit 0")
+        );
+    }
+
+    #[test]
+    fn test_extract_codex_message() {
+        let raw = r#"{"type":"response_item","payload":{"type":"message","content":[{"type":"output_text","text":"done"}]}}"#;
+        assert_eq!(extract_content_text(raw).as_deref(), Some("done"));
+    }
+
+    #[test]
+    fn test_extract_unparsable_is_none() {
+        assert_eq!(extract_content_text("not json"), None);
+        assert_eq!(extract_content_text(r#"{"type":"summary"}"#), None);
+    }
+}
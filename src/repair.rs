@@ -1,13 +1,14 @@
 //! Database repair — `vimo-agent --repair`
 //!
 //! Handles WAL mismatch (backup restore) and B-tree corruption.
+//! Databases that stay unreadable after WAL cleanup are salvaged via `SessionDB::salvage_into`.
 //! Must be run with no other processes accessing the DB.
 
 use std::path::Path;
 
 use anyhow::{Context, Result, bail};
 
-use crate::DbConfig;
+use crate::{DbConfig, SessionDB};
 
 pub fn run_repair() -> Result<()> {
     let config = DbConfig::from_env();
@@ -96,15 +97,39 @@ fn repair_malformed(db_path: &Path) -> Result<()> {
             repair_with_connection(&conn, db_path)
         }
         Err(e) => {
-            bail!(
-                "DB still malformed after WAL cleanup: {}\n\
-                 The database file itself is corrupted and needs manual recovery.",
-                e
-            );
+            eprintln!("   DB still malformed after WAL cleanup: {}", e);
+            eprintln!("   Attempting salvage...");
+            salvage_and_swap(db_path)
         }
     }
 }
 
+fn salvage_and_swap(db_path: &Path) -> Result<()> {
+    let tmp_path = db_path.with_extension("db-salvage-tmp");
+    if tmp_path.exists() {
+        std::fs::remove_file(&tmp_path).context("Failed to remove old salvage DB")?;
+    }
+
+    let report = SessionDB::salvage_into(db_path, &tmp_path).context("Salvage failed")?;
+    for table in &report.tables {
+        match &table.error {
+            Some(e) => eprintln!(
+                "   {}: {} rows, {} lost ({})",
+                table.table, table.recovered, table.lost, e
+            ),
+            None => eprintln!("   {}: {} rows", table.table, table.recovered),
+        }
+    }
+
+    // Swap files
+    let corrupt_backup = db_path.with_extension("db-pre-repair");
+    std::fs::rename(db_path, &corrupt_backup).context("Failed to backup corrupt DB")?;
+    std::fs::rename(&tmp_path, db_path).context("Failed to move salvaged DB")?;
+
+    eprintln!("   Corrupt DB saved to: {}", corrupt_backup.display());
+    Ok(())
+}
+
 fn dump_and_rebuild(conn: &rusqlite::Connection, db_path: &Path) -> Result<()> {
     let tmp_path = db_path.with_extension("db-repair-tmp");
 
@@ -146,6 +171,11 @@ fn dump_and_rebuild(conn: &rusqlite::Connection, db_path: &Path) -> Result<()> {
         }
     }
 
+    // messages 的 FTS 索引不随复制写入，整体重建
+    if let Err(e) = crate::db::rebuild_messages_fts(&backup_conn) {
+        eprintln!("   FTS rebuild warning: {}", e);
+    }
+
     drop(backup_conn);
 
     // Swap files
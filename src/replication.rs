@@ -0,0 +1,242 @@
+//! 新消息复制到外部系统
+//!
+//! 把消息按 id 顺序投递给 `ReplicationSink`（内置 JSONL 文件和 HTTP POST），
+//! 用户可以把会话历史镜像到自己的系统（个人 Postgres、笔记应用），无需轮询数据库。
+//!
+//! - 至少一次：`SessionDB::replicate_batch` 读取游标之后的一批消息，投递成功后才推进
+//!   metadata 表中的游标（`REPLICATION_CURSOR_KEY`）。投递成功但游标未保存（进程退出）时，
+//!   重启后重新投递这一批，重复不超过一批；接收方可按 uuid 去重
+//! - 不遗漏：messages.id 为 AUTOINCREMENT，提交的消息 id 单调递增
+//! - 只投递新写入的消息，已存在消息的内容更新不会重新投递
+//!
+//! 按来源拆分数据库时每个数据库有各自的游标，不同数据库的消息 id 可能重复。
+//! Agent 的复制任务见 `AgentConfig::replication_*`。
+
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+
+use crate::db::{get_metadata, set_metadata, SessionDB};
+use crate::error::Result;
+use crate::types::Message;
+
+/// 游标在 metadata 表中的 key（值为已投递的最大消息 ID）
+pub const REPLICATION_CURSOR_KEY: &str = "replication.last_delivered_id";
+
+/// 复制的一条消息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplicationRecord {
+    pub message: Message,
+    pub project_id: i64,
+    pub project_path: String,
+    pub project_name: String,
+    /// 项目来源（claude / codex 等）
+    pub source: String,
+}
+
+/// 复制目标
+pub trait ReplicationSink: Send + Sync {
+    /// 投递一批记录（按消息 id 升序）
+    ///
+    /// 返回错误时游标不推进，整批稍后重新投递；部分写入的记录也会再次出现。
+    fn deliver(&self, batch: &[ReplicationRecord]) -> Result<()>;
+}
+
+/// 复制进度
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplicationLag {
+    /// 最新的消息 ID（没有消息时为 0）
+    pub newest_message_id: i64,
+    /// 已投递的最大消息 ID
+    pub cursor: i64,
+    /// 待投递的 ID 跨度（`newest_message_id - cursor`，不小于 0）
+    pub lag: i64,
+}
+
+impl SessionDB {
+    /// 已投递的最大消息 ID（尚未投递时为 0）
+    pub fn replication_cursor(&self) -> Result<i64> {
+        let conn = self.conn.lock();
+        let value = get_metadata(&conn, REPLICATION_CURSOR_KEY)?;
+        Ok(value.and_then(|v| v.parse().ok()).unwrap_or(0))
+    }
+
+    /// 设置游标（如设为当前最大消息 ID 以跳过历史消息，或设为 0 重新投递全部）
+    pub fn set_replication_cursor(&self, message_id: i64) -> Result<()> {
+        let conn = self.conn.lock();
+        set_metadata(&conn, REPLICATION_CURSOR_KEY, &message_id.to_string())?;
+        Ok(())
+    }
+
+    /// 游标之后的最多 `limit` 条消息（按 id 升序）
+    pub fn replication_pending(&self, limit: usize) -> Result<Vec<ReplicationRecord>> {
+        let cursor = self.replication_cursor()?;
+        let rows: Vec<(i64, i64, String, String, String)> = {
+            let conn = self.conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT m.id, p.id, p.path, p.name, p.source
+                FROM messages m
+                JOIN sessions s ON s.session_id = m.session_id
+                JOIN projects p ON p.id = s.project_id
+                WHERE m.id > ?1
+                ORDER BY m.id
+                LIMIT ?2
+                "#,
+            )?;
+            let rows = stmt.query_map(params![cursor, limit as i64], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })?;
+            rows.collect::<std::result::Result<Vec<_>, _>>()?
+        };
+
+        let ids: Vec<i64> = rows.iter().map(|row| row.0).collect();
+        let mut projects: HashMap<i64, (i64, String, String, String)> = rows
+            .into_iter()
+            .map(|(id, project_id, path, name, source)| (id, (project_id, path, name, source)))
+            .collect();
+        // 两次查询之间被删除的消息跳过
+        let records = self
+            .get_messages_by_ids(&ids)?
+            .into_iter()
+            .filter_map(|message| {
+                let (project_id, project_path, project_name, source) =
+                    projects.remove(&message.id)?;
+                Some(ReplicationRecord {
+                    message,
+                    project_id,
+                    project_path,
+                    project_name,
+                    source,
+                })
+            })
+            .collect();
+        Ok(records)
+    }
+
+    /// 投递游标之后的一批消息，成功后推进游标
+    ///
+    /// 返回投递的记录数，小于 `batch_size` 表示已追上。
+    pub fn replicate_batch(&self, sink: &dyn ReplicationSink, batch_size: usize) -> Result<usize> {
+        let batch = self.replication_pending(batch_size.max(1))?;
+        let Some(last) = batch.last() else {
+            return Ok(0);
+        };
+        let last_id = last.message.id;
+        sink.deliver(&batch)?;
+        self.set_replication_cursor(last_id)?;
+        Ok(batch.len())
+    }
+
+    /// 复制进度（最新消息 ID 与游标之差）
+    pub fn replication_lag(&self) -> Result<ReplicationLag> {
+        let cursor = self.replication_cursor()?;
+        let conn = self.conn.lock();
+        let sql = "SELECT COALESCE(MAX(id), 0) FROM messages";
+        let newest_message_id: i64 = conn.query_row(sql, [], |row| row.get(0))?;
+        Ok(ReplicationLag {
+            newest_message_id,
+            cursor,
+            lag: (newest_message_id - cursor).max(0),
+        })
+    }
+}
+
+/// 追加到 JSONL 文件（每条记录一行），每批写入后 fsync
+///
+/// 写入中途失败时文件末尾可能留下不完整的一行，读取方应跳过无法解析的行。
+#[derive(Debug, Clone)]
+pub struct JsonlFileSink {
+    path: PathBuf,
+}
+
+impl JsonlFileSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl ReplicationSink for JsonlFileSink {
+    fn deliver(&self, batch: &[ReplicationRecord]) -> Result<()> {
+        let mut buf = Vec::new();
+        for record in batch {
+            serde_json::to_writer(&mut buf, record)?;
+            buf.push(b'\n');
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(&buf)?;
+        file.sync_data()?;
+        Ok(())
+    }
+}
+
+/// 以 JSON 数组 POST 到 URL，2xx 视为投递成功（需要 `replication-http` feature）
+#[cfg(feature = "replication-http")]
+pub struct HttpPostSink {
+    url: String,
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "replication-http")]
+impl HttpPostSink {
+    /// 单次请求超时
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+    pub fn new(url: impl Into<String>) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Self::TIMEOUT)
+            .build()
+            .map_err(|e| crate::Error::Connection(e.to_string()))?;
+        Ok(Self {
+            url: url.into(),
+            client,
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+#[cfg(feature = "replication-http")]
+impl ReplicationSink for HttpPostSink {
+    /// 在 tokio 运行时的阻塞线程（`spawn_blocking`）或运行时之外调用
+    fn deliver(&self, batch: &[ReplicationRecord]) -> Result<()> {
+        let request = async {
+            self.client
+                .post(&self.url)
+                .json(batch)
+                .send()
+                .await?
+                .error_for_status()
+        };
+        let result = match tokio::runtime::Handle::try_current() {
+            Ok(handle) => handle.block_on(request),
+            Err(_) => tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?
+                .block_on(request),
+        };
+        result
+            .map(|_| ())
+            .map_err(|e| crate::Error::Connection(format!("{}: {}", self.url, e)))
+    }
+}
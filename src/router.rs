@@ -0,0 +1,237 @@
+//! 按来源拆分数据库
+//!
+//! 默认所有来源写入同一个数据库。配置路由后，指定来源（`Source` 的名称，如 `codex`）的
+//! 项目、会话和消息写入各自的数据库文件（各自的保留和同步策略），其余来源写入默认数据库。
+//! 各数据库使用同一 schema。
+//!
+//! - 写入：`Collector::routed` 按会话来源选择数据库
+//! - 单会话 / 单项目查询：路由到会话或项目所在的数据库
+//! - 跨来源查询（项目列表、搜索）：查询所有数据库后合并，结果带 `database` 标记
+//!   （ID 只在所在数据库内唯一，按项目 ID 过滤无法跨数据库合并）
+//!
+//! 采集锁、忽略规则、审批、向量索引和变更计数只使用默认数据库。
+//! 未配置路由时所有查询直接使用默认数据库，不做额外查找。
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::DbConfig;
+use crate::db::SessionDB;
+use crate::error::{Error, Result};
+#[cfg(feature = "search")]
+use crate::search::with_content;
+use crate::types::ProjectWithStats;
+#[cfg(feature = "search")]
+use crate::types::{SearchGroupOptions, SearchOrderBy, SearchResult};
+
+/// 默认数据库的名称（`Routed::database`、`DatabaseInfo::name`）
+pub const DEFAULT_DATABASE: &str = "default";
+
+/// 带来源数据库标记的查询结果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Routed<T> {
+    /// 所在的数据库（`DEFAULT_DATABASE` 或来源名称）
+    pub database: String,
+    #[serde(flatten)]
+    pub item: T,
+}
+
+/// 打开的数据库
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DatabaseInfo {
+    /// `DEFAULT_DATABASE` 或来源名称
+    pub name: String,
+    /// 连接 URL（本地为文件路径）
+    pub url: String,
+}
+
+/// 按来源选择数据库
+pub struct DbRouter {
+    default: Arc<SessionDB>,
+    /// (来源名称（小写）, 数据库)
+    routes: Vec<(String, Arc<SessionDB>)>,
+}
+
+impl DbRouter {
+    /// 只有默认数据库（不拆分）
+    pub fn new(default: Arc<SessionDB>) -> Self {
+        Self {
+            default,
+            routes: Vec::new(),
+        }
+    }
+
+    /// 连接默认数据库和各来源的数据库
+    ///
+    /// 来源名称不区分大小写，重复时返回 `Error::Config`。
+    pub fn connect(
+        default: DbConfig,
+        routes: impl IntoIterator<Item = (String, DbConfig)>,
+    ) -> Result<Self> {
+        let mut router = Self::new(Arc::new(SessionDB::connect(default)?));
+        for (source, config) in routes {
+            let source = source.to_lowercase();
+            if router.routes.iter().any(|(name, _)| *name == source) {
+                return Err(Error::Config(format!(
+                    "Duplicate database route for source: {}",
+                    source
+                )));
+            }
+            router
+                .routes
+                .push((source, Arc::new(SessionDB::connect(config)?)));
+        }
+        Ok(router)
+    }
+
+    /// 是否配置了按来源拆分
+    pub fn is_routed(&self) -> bool {
+        !self.routes.is_empty()
+    }
+
+    /// 默认数据库
+    pub fn default_db(&self) -> &Arc<SessionDB> {
+        &self.default
+    }
+
+    /// 来源对应的数据库（未配置的来源使用默认数据库）
+    pub fn for_source(&self, source: &str) -> &Arc<SessionDB> {
+        self.routes
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(source))
+            .map(|(_, db)| db)
+            .unwrap_or(&self.default)
+    }
+
+    /// 会话所在的数据库（不存在时为默认数据库）
+    pub fn for_session(&self, session_id: &str) -> Result<&Arc<SessionDB>> {
+        for (_, db) in &self.routes {
+            if db.session_exists(session_id)? {
+                return Ok(db);
+            }
+        }
+        Ok(&self.default)
+    }
+
+    /// 项目所在的数据库（不存在时为默认数据库）
+    pub fn for_project_path(&self, project_path: &str) -> Result<&Arc<SessionDB>> {
+        for (_, db) in &self.routes {
+            if db.get_project_by_path(project_path)?.is_some() {
+                return Ok(db);
+            }
+        }
+        Ok(&self.default)
+    }
+
+    /// 所有数据库（默认数据库在前）
+    pub fn all(&self) -> impl Iterator<Item = (&str, &Arc<SessionDB>)> {
+        std::iter::once((DEFAULT_DATABASE, &self.default))
+            .chain(self.routes.iter().map(|(name, db)| (name.as_str(), db)))
+    }
+
+    /// 打开的数据库列表
+    pub fn databases(&self) -> Vec<DatabaseInfo> {
+        self.all()
+            .map(|(name, db)| DatabaseInfo {
+                name: name.to_string(),
+                url: db.config().url.clone(),
+            })
+            .collect()
+    }
+
+    /// 所有数据库的项目列表（排序同 `SessionDB::list_projects_with_stats`）
+    pub fn list_projects_with_stats(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<Routed<ProjectWithStats>>> {
+        let mut projects =
+            self.fan_out(|db| db.list_projects_with_stats(offset.saturating_add(limit), 0))?;
+        projects.sort_by_key(|p| {
+            (
+                p.item.last_active.is_none(),
+                std::cmp::Reverse(p.item.last_active),
+                std::cmp::Reverse(p.item.id),
+            )
+        });
+        Ok(projects.into_iter().skip(offset).take(limit).collect())
+    }
+
+    /// 搜索所有数据库（参数同 `SessionDB::search_fts_full`，不支持按项目 ID 过滤）
+    ///
+    /// `max_per_project` 在各数据库内分别生效。
+    #[cfg(feature = "search")]
+    pub fn search_fts_full(
+        &self,
+        query: &str,
+        limit: usize,
+        order_by: SearchOrderBy,
+        start_timestamp: Option<i64>,
+        end_timestamp: Option<i64>,
+        max_per_project: Option<usize>,
+    ) -> Result<Vec<Routed<SearchResult>>> {
+        let options = SearchGroupOptions {
+            order_by,
+            start_timestamp,
+            end_timestamp,
+            ..Default::default()
+        };
+        self.search_fts_filtered(query, limit, &options, max_per_project)
+    }
+
+    /// 搜索所有数据库（参数同 `SessionDB::search_fts_filtered`，忽略 `options.project_id`）
+    #[cfg(feature = "search")]
+    pub fn search_fts_filtered(
+        &self,
+        query: &str,
+        limit: usize,
+        options: &SearchGroupOptions,
+        max_per_project: Option<usize>,
+    ) -> Result<Vec<Routed<SearchResult>>> {
+        let options = SearchGroupOptions {
+            project_id: None,
+            ..options.clone()
+        };
+        // 各数据库不单独记录，合并后记录到默认数据库
+        let started = std::time::Instant::now();
+        let mut results = self.fan_out(|db| {
+            let results = db.search_unlogged(query, limit, &options, &[], max_per_project)?;
+            Ok(with_content(results, options.include_content))
+        })?;
+        match options.order_by {
+            // bm25 分数越小越相关
+            SearchOrderBy::Score => results.sort_by(|a, b| a.item.score.total_cmp(&b.item.score)),
+            SearchOrderBy::TimeDesc => results.sort_by_key(|r| std::cmp::Reverse(r.item.timestamp)),
+            SearchOrderBy::TimeAsc => results.sort_by_key(|r| r.item.timestamp),
+        }
+        results.truncate(limit);
+        self.default
+            .record_search(query, None, results.len(), started);
+        Ok(results)
+    }
+
+    /// 所有数据库的搜索命中总数（不支持按项目 ID 过滤）
+    #[cfg(feature = "search")]
+    pub fn search_count(&self, query: &str, options: &SearchGroupOptions) -> Result<i64> {
+        let options = SearchGroupOptions {
+            project_id: None,
+            ..options.clone()
+        };
+        self.all()
+            .map(|(_, db)| db.search_count(query, &options))
+            .sum()
+    }
+
+    /// 依次查询所有数据库，结果标记所在数据库
+    fn fan_out<T>(&self, query: impl Fn(&SessionDB) -> Result<Vec<T>>) -> Result<Vec<Routed<T>>> {
+        let mut merged = Vec::new();
+        for (name, db) in self.all() {
+            merged.extend(query(db)?.into_iter().map(|item| Routed {
+                database: name.to_string(),
+                item,
+            }));
+        }
+        Ok(merged)
+    }
+}
@@ -0,0 +1,268 @@
+//! 损坏数据库抢救
+//!
+//! 主文件损坏（`Error::Corrupted`）时，逐表逐行读取仍可访问的数据，写入新初始化的数据库。
+//! rusqlite 未暴露 `sqlite3_recover`，这里按 rowid 顺序扫描：扫描中途出错时
+//! 逐个 rowid 探测跳过损坏页，读取失败的行计为丢失。
+//!
+//! FTS 索引不复制：talks 由触发器重建，messages 复制完成后整体重建。
+
+use std::path::Path;
+
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::config::DbConfig;
+use crate::db::{rebuild_messages_fts, SessionDB};
+use crate::error::{Error, Result};
+
+/// 单个表的抢救结果
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TableSalvage {
+    pub table: String,
+    /// 成功写入新数据库的行数
+    pub recovered: usize,
+    /// 读取失败的行数（按 rowid 探测，无法定位的行不计入）
+    pub lost: usize,
+    /// 读取中遇到的第一个错误（为空表示整表完整读取）
+    pub error: Option<String>,
+}
+
+/// 抢救报告（按新数据库的表顺序）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SalvageReport {
+    pub tables: Vec<TableSalvage>,
+}
+
+impl SalvageReport {
+    /// 指定表的抢救结果
+    pub fn table(&self, name: &str) -> Option<&TableSalvage> {
+        self.tables.iter().find(|t| t.table == name)
+    }
+
+    /// 抢救的总行数
+    pub fn total_recovered(&self) -> usize {
+        self.tables.iter().map(|t| t.recovered).sum()
+    }
+
+    /// 丢失的总行数
+    pub fn total_lost(&self) -> usize {
+        self.tables.iter().map(|t| t.lost).sum()
+    }
+}
+
+impl SessionDB {
+    /// 从损坏的数据库抢救数据到 `dest`
+    ///
+    /// 不需要能正常连接源数据库（`connect` 返回 `Error::Corrupted` 或 quick_check 失败时使用）。
+    /// `dest` 必须不存在，按当前 schema 初始化后逐表复制与源数据库同名的列。
+    /// 完成后由调用方备份原文件并用 `dest` 替换。
+    pub fn salvage_into(source: &Path, dest: &Path) -> Result<SalvageReport> {
+        if dest.exists() {
+            return Err(Error::Config(format!(
+                "Salvage destination already exists: {}",
+                dest.display()
+            )));
+        }
+
+        let src = Connection::open_with_flags(
+            source,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        let dst = SessionDB::connect(DbConfig::local(dest))?;
+        let dst = dst.conn.lock();
+
+        let tables: Vec<String> = dst
+            .prepare(
+                "SELECT name FROM sqlite_master WHERE type = 'table' \
+                 AND name NOT LIKE 'sqlite_%' AND name NOT LIKE '%_fts%' ORDER BY rowid",
+            )?
+            .query_map([], |row| row.get(0))?
+            .collect::<std::result::Result<_, _>>()?;
+
+        let mut report = SalvageReport::default();
+        for table in tables {
+            let salvage = salvage_table(&src, &dst, &table);
+            tracing::info!(
+                "Salvaged {}: {} recovered, {} lost{}",
+                table,
+                salvage.recovered,
+                salvage.lost,
+                salvage
+                    .error
+                    .as_deref()
+                    .map(|e| format!(" ({})", e))
+                    .unwrap_or_default()
+            );
+            report.tables.push(salvage);
+        }
+
+        // 复制消息绕过了写入路径，索引需要整体重建
+        if let Err(e) = rebuild_messages_fts(&dst) {
+            tracing::warn!("Failed to rebuild FTS index after salvage: {}", e);
+        }
+
+        Ok(report)
+    }
+}
+
+/// 表的列名
+fn table_columns(conn: &Connection, table: &str) -> rusqlite::Result<Vec<String>> {
+    conn.prepare(&format!("PRAGMA table_info(\"{}\")", table))?
+        .query_map([], |row| row.get(1))?
+        .collect()
+}
+
+/// 复制单个表（单个事务；源端的错误记录在结果中，不中断其他表）
+fn salvage_table(src: &Connection, dst: &Connection, table: &str) -> TableSalvage {
+    let mut salvage = TableSalvage {
+        table: table.to_string(),
+        ..Default::default()
+    };
+
+    let dst_cols = match table_columns(dst, table) {
+        Ok(cols) => cols,
+        Err(e) => {
+            salvage.error = Some(e.to_string());
+            return salvage;
+        }
+    };
+    let cols: Vec<String> = match table_columns(src, table) {
+        Ok(src_cols) => dst_cols
+            .into_iter()
+            .filter(|c| src_cols.contains(c))
+            .collect(),
+        Err(e) => {
+            salvage.error = Some(e.to_string());
+            return salvage;
+        }
+    };
+    if cols.is_empty() {
+        // 源数据库没有该表（旧版本）或 schema 无法读取
+        return salvage;
+    }
+
+    let col_list = cols
+        .iter()
+        .map(|c| format!("\"{}\"", c))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let placeholders = (1..=cols.len())
+        .map(|i| format!("?{}", i))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let scan_sql = format!(
+        "SELECT rowid, {} FROM \"{}\" WHERE rowid > ?1 ORDER BY rowid",
+        col_list, table
+    );
+    let probe_sql = format!(
+        "SELECT rowid, {} FROM \"{}\" WHERE rowid = ?1",
+        col_list, table
+    );
+    let insert_sql = format!(
+        "INSERT OR REPLACE INTO \"{}\" ({}) VALUES ({})",
+        table, col_list, placeholders
+    );
+
+    let result = (|| -> rusqlite::Result<()> {
+        let tx = dst.unchecked_transaction()?;
+        let mut insert = tx.prepare(&insert_sql)?;
+        let mut copy = |values: Vec<Value>, salvage: &mut TableSalvage| match insert
+            .execute(rusqlite::params_from_iter(values))
+        {
+            Ok(_) => salvage.recovered += 1,
+            Err(_) => salvage.lost += 1,
+        };
+
+        let mut last = i64::MIN;
+        loop {
+            // 顺序扫描，直到读完或遇到损坏
+            let scanned = scan_rows(src, &scan_sql, last, cols.len(), |rowid, values| {
+                last = rowid;
+                copy(values, &mut salvage);
+            });
+            let scan_error = match scanned {
+                Ok(()) => break,
+                Err(e) => e,
+            };
+            if salvage.error.is_none() {
+                salvage.error = Some(scan_error.to_string());
+            }
+
+            // 逐个 rowid 探测，找到下一条可读的行后继续扫描
+            let max_rowid: Option<i64> = match src.query_row(
+                &format!("SELECT max(rowid) FROM \"{}\"", table),
+                [],
+                |row| row.get(0),
+            ) {
+                Ok(max) => max,
+                Err(_) => break,
+            };
+            let Some(max_rowid) = max_rowid else { break };
+
+            let mut resumed = false;
+            let mut rowid = if last == i64::MIN { 1 } else { last + 1 };
+            while rowid <= max_rowid {
+                match probe_row(src, &probe_sql, rowid, cols.len()) {
+                    Ok(Some(values)) => {
+                        last = rowid;
+                        copy(values, &mut salvage);
+                        resumed = true;
+                        break;
+                    }
+                    Ok(None) => {}
+                    Err(_) => salvage.lost += 1,
+                }
+                rowid += 1;
+            }
+            if !resumed {
+                break;
+            }
+        }
+
+        drop(insert);
+        tx.commit()
+    })();
+
+    if let Err(e) = result {
+        salvage.error = Some(e.to_string());
+    }
+    salvage
+}
+
+/// 从 `after` 之后按 rowid 顺序读取，每行回调 (rowid, 列值)
+fn scan_rows(
+    src: &Connection,
+    sql: &str,
+    after: i64,
+    col_count: usize,
+    mut on_row: impl FnMut(i64, Vec<Value>),
+) -> rusqlite::Result<()> {
+    let mut stmt = src.prepare(sql)?;
+    let mut rows = stmt.query(params![after])?;
+    while let Some(row) = rows.next()? {
+        let rowid: i64 = row.get(0)?;
+        let values = (1..=col_count)
+            .map(|i| row.get::<_, Value>(i))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        on_row(rowid, values);
+    }
+    Ok(())
+}
+
+/// 按 rowid 读取单行（行不存在时返回 None）
+fn probe_row(
+    src: &Connection,
+    sql: &str,
+    rowid: i64,
+    col_count: usize,
+) -> rusqlite::Result<Option<Vec<Value>>> {
+    src.query_row(sql, params![rowid], |row| {
+        (1..=col_count)
+            .map(|i| row.get::<_, Value>(i))
+            .collect::<rusqlite::Result<Vec<_>>>()
+    })
+    .optional()
+}
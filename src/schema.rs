@@ -1,4 +1,16 @@
 //! 数据库 Schema 定义
+//!
+//! 所有 SQL（包括查询）只使用 `MIN_SQLITE_VERSION` 支持的语法：系统 SQLite
+//! 可能比 rusqlite 内置的旧。
+
+/// 支持的最低 SQLite 版本（窗口函数需要 3.25）
+///
+/// 不使用的更新语法：`NULLS FIRST/LAST`、聚合 `FILTER`（3.30）、`RETURNING`、
+/// `DROP COLUMN`（3.35）、`->>`（3.38）等，用 `(x IS NULL), x` 之类的写法代替。
+pub const MIN_SQLITE_VERSION: &str = "3.25.0";
+
+/// `MIN_SQLITE_VERSION` 的数字形式（与 `rusqlite::version_number()` 比较）
+pub const MIN_SQLITE_VERSION_NUMBER: i32 = 3_025_000;
 
 /// 表定义 SQL（不包含索引）
 pub const TABLES_SQL: &str = r#"
@@ -10,6 +22,7 @@ CREATE TABLE IF NOT EXISTS projects (
     source TEXT NOT NULL DEFAULT 'claude',
     encoded_dir_name TEXT,
     repo_url TEXT,             -- git remote origin URL（用于跨设备项目归并）
+    ignored INTEGER NOT NULL DEFAULT 0,  -- 是否被采集忽略规则排除（默认列表不显示）
     created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now') * 1000),
     updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now') * 1000)
 );
@@ -20,6 +33,7 @@ CREATE TABLE IF NOT EXISTS sessions (
     session_id TEXT NOT NULL UNIQUE,
     project_id INTEGER NOT NULL REFERENCES projects(id),
     message_count INTEGER NOT NULL DEFAULT 0,
+    first_message_at INTEGER, -- 最早的消息时间 (毫秒时间戳，写入消息时维护)
     last_message_at INTEGER,  -- 用于增量扫描的检查点 (毫秒时间戳)
     -- 会话元数据 (来自 ai-cli-session-collector::SessionMeta)
     cwd TEXT,                 -- 工作目录
@@ -30,6 +44,8 @@ CREATE TABLE IF NOT EXISTS sessions (
     file_size INTEGER,        -- 文件大小 (字节)
     file_offset INTEGER DEFAULT 0,  -- 文件读取偏移量 (字节)
     file_inode INTEGER,       -- 文件 inode (用于检测文件替换)
+    content_hash TEXT,        -- 文件指纹 (大小 + 最后一行的哈希，mtime 不可靠时判断文件是否变化)
+    session_path TEXT,        -- 会话文件路径 (最近一次采集时的位置)
     encoded_dir_name TEXT,    -- 编码后的目录名
     -- 额外元信息
     meta TEXT,                -- 额外元信息 (JSON)
@@ -58,6 +74,10 @@ CREATE TABLE IF NOT EXISTS messages (
     vector_indexed INTEGER DEFAULT 0, -- 是否已向量索引 (0=未索引, 1=已索引)
     approval_status TEXT,           -- 审批状态: pending, approved, rejected, timeout, NULL
     approval_resolved_at INTEGER,   -- 审批解决时间戳（毫秒）
+    truncated INTEGER NOT NULL DEFAULT 0, -- 内容是否因超过 max_content_bytes 被截断（raw 保留完整）
+    turn_index INTEGER,             -- 对话轮次（用户消息开启新轮次，从 0 开始；NULL 表示待回填）
+    sidechain INTEGER NOT NULL DEFAULT 0,  -- 是否为 sidechain 消息（Claude isSidechain，默认不列出）
+    is_summary INTEGER NOT NULL DEFAULT 0, -- 是否为 compact 摘要条目（Claude type=summary，以 system 类型存储）
 
     FOREIGN KEY (session_id) REFERENCES sessions(session_id)
 );
@@ -69,6 +89,8 @@ CREATE TABLE IF NOT EXISTS talks (
     talk_id TEXT NOT NULL,          -- Talk 唯一标识
     summary_l2 TEXT NOT NULL,       -- L2 摘要（每个 Talk 的摘要）
     summary_l3 TEXT,                -- L3 摘要（Session 级别汇总）
+    position INTEGER,               -- Talk 在会话中的序号（从 0 开始）
+    first_message_sequence INTEGER, -- Talk 覆盖的第一条消息 sequence（用于推导 position）
     created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now') * 1000),
     updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now') * 1000),
     UNIQUE(session_id, talk_id),
@@ -101,6 +123,90 @@ CREATE TABLE IF NOT EXISTS continuation_chain_nodes (
     depth             INTEGER NOT NULL DEFAULT 0,                         -- 离 root 的距离
     created_at        INTEGER NOT NULL DEFAULT (strftime('%s','now')*1000)
 );
+
+-- Message Revisions 表（重采集时 uuid 已存在但内容变化的审计记录）
+CREATE TABLE IF NOT EXISTS message_revisions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT NOT NULL,
+    uuid TEXT NOT NULL,             -- 消息 uuid
+    old_hash TEXT NOT NULL,         -- 数据库中 content_full 的哈希
+    new_hash TEXT NOT NULL,         -- 磁盘上 content_full 的哈希
+    new_raw TEXT,                   -- 磁盘上的原始 JSONL（可选）
+    applied INTEGER NOT NULL DEFAULT 0, -- 是否已覆盖写入 messages (0=保留旧行, 1=已更新)
+    detected_at INTEGER NOT NULL DEFAULT (strftime('%s','now')*1000),
+    UNIQUE(uuid, old_hash, new_hash)
+);
+
+-- Vector Tombstones 表（已向量索引的消息被删除后，待外部向量库清理的记录）
+CREATE TABLE IF NOT EXISTS vector_tombstones (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    uuid TEXT NOT NULL,             -- 被删除消息的 uuid
+    message_id INTEGER NOT NULL,    -- 被删除消息的 ID（向量库中的 key）
+    deleted_at INTEGER NOT NULL DEFAULT (strftime('%s','now')*1000)
+);
+
+-- Collection Ignores 表（采集忽略规则）
+CREATE TABLE IF NOT EXISTS collection_ignores (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    pattern TEXT NOT NULL,
+    kind TEXT NOT NULL,             -- path_glob / project_path / session_prefix
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s','now')*1000),
+    UNIQUE(pattern, kind)
+);
+
+-- Migrations Log 表（版本化数据迁移的执行记录）
+CREATE TABLE IF NOT EXISTS migrations_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    version INTEGER NOT NULL,
+    description TEXT NOT NULL,
+    status TEXT NOT NULL,           -- applied / failed
+    backup_path TEXT,               -- 迁移前备份文件（破坏性迁移）
+    error TEXT,                     -- 失败原因及恢复指引
+    applied_at INTEGER NOT NULL DEFAULT (strftime('%s','now')*1000)
+);
+
+-- Collection Lock 表（跨进程采集互斥，最多一行）
+CREATE TABLE IF NOT EXISTS collection_lock (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    holder TEXT NOT NULL,           -- 持有者标识（进程名@pid#随机后缀）
+    acquired_at INTEGER NOT NULL,   -- 获取时间（毫秒）
+    heartbeat_at INTEGER NOT NULL   -- 最近心跳（毫秒），超时后可被接管
+);
+
+-- Collect Failures 表（采集连续失败的会话，达到次数后退避）
+CREATE TABLE IF NOT EXISTS collect_failures (
+    session_id TEXT PRIMARY KEY,
+    path TEXT NOT NULL,               -- 会话文件
+    failures INTEGER NOT NULL,        -- 连续失败次数（成功采集后清除）
+    last_failure_at INTEGER NOT NULL, -- 最近一次失败（毫秒）
+    last_error TEXT NOT NULL
+);
+
+-- Change Counter 表（全局单调递增的变更计数，只有一行）
+-- 每个写事务提交前递增一次，客户端比较计数即可判断是否有新数据
+CREATE TABLE IF NOT EXISTS change_counter (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    value INTEGER NOT NULL DEFAULT 0
+);
+INSERT OR IGNORE INTO change_counter (id, value) VALUES (1, 0);
+
+-- Metadata 表（库内部状态的键值对，如维护任务的进度）
+CREATE TABLE IF NOT EXISTS metadata (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+-- Search Log 表（启用 DbConfig::log_searches 时的搜索记录，超过上限时删除最旧的）
+CREATE TABLE IF NOT EXISTS search_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    query TEXT NOT NULL,
+    normalized_query TEXT NOT NULL,
+    result_count INTEGER NOT NULL,
+    duration_ms INTEGER NOT NULL,
+    project_filter INTEGER,         -- 按项目过滤时的项目 ID
+    created_at INTEGER NOT NULL     -- 毫秒
+);
 "#;
 
 /// 索引定义 SQL
@@ -117,37 +223,70 @@ CREATE INDEX IF NOT EXISTS idx_messages_type ON messages(type);
 CREATE INDEX IF NOT EXISTS idx_messages_vector_indexed ON messages(vector_indexed);
 CREATE INDEX IF NOT EXISTS idx_messages_approval_status ON messages(approval_status) WHERE approval_status IS NOT NULL;
 CREATE INDEX IF NOT EXISTS idx_messages_approval_pending ON messages(session_id, approval_status) WHERE approval_status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_messages_session_turn ON messages(session_id, turn_index);
 CREATE INDEX IF NOT EXISTS idx_talks_session ON talks(session_id);
 CREATE INDEX IF NOT EXISTS idx_talks_talk_id ON talks(talk_id);
+CREATE INDEX IF NOT EXISTS idx_talks_session_position ON talks(session_id, position);
 CREATE INDEX IF NOT EXISTS idx_session_relations_parent ON session_relations(parent_session_id);
 CREATE INDEX IF NOT EXISTS idx_session_relations_child ON session_relations(child_session_id);
 CREATE INDEX IF NOT EXISTS idx_ccn_chain ON continuation_chain_nodes(chain_id, depth);
 CREATE INDEX IF NOT EXISTS idx_ccn_prev ON continuation_chain_nodes(prev_session_id);
+CREATE INDEX IF NOT EXISTS idx_message_revisions_session ON message_revisions(session_id, detected_at);
+
+-- 向量墓碑触发器（依赖 vector_indexed 列，放在列补齐之后创建）
+-- 与删除在同一事务内记录，任何删除路径都不会遗漏
+CREATE TRIGGER IF NOT EXISTS messages_vector_tombstone AFTER DELETE ON messages
+WHEN old.vector_indexed = 1 BEGIN
+    INSERT INTO vector_tombstones (uuid, message_id) VALUES (old.uuid, old.id);
+END;
 "#;
 
-/// FTS5 全文搜索 Schema (索引 content_full)
+/// FTS5 全文搜索 Schema (索引 content_full 和 content_text)
+///
+/// messages_fts / talks_fts 都是外部内容表，只保存倒排索引，内容从源表读取。
+/// 新消息的索引由写入路径显式插入（FTS 失败时只回滚索引部分，消息记入 fts_backlog，
+/// 见 `SessionDB::reindex_fts_backlog`），删除 / 更新和 talks 由触发器同步。
+/// 绕过写入路径或触发器修改源表（如直接 INSERT、批量导入前删除触发器）后必须重建索引：
+/// `INSERT INTO messages_fts(messages_fts) VALUES('rebuild')`。
 pub const FTS_SCHEMA_SQL: &str = r#"
 -- 全文搜索虚拟表 (带触发器自动维护)
--- 使用 content_full 进行 FTS 索引，包含完整对话内容（含 tool_use/tool_result）
+-- content_full: 完整对话内容（含 tool_use/tool_result），默认搜索的列
+-- content_text: 纯对话文本（不含工具框架），按 SearchField::ContentText 搜索
 CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
     content_full,
+    content_text,
     content='messages',
     content_rowid='id',
     tokenize='unicode61'
 );
 
--- FTS 触发器
-CREATE TRIGGER IF NOT EXISTS messages_ai AFTER INSERT ON messages BEGIN
-    INSERT INTO messages_fts(rowid, content_full) VALUES (new.id, new.content_full);
-END;
+-- FTS 补建队列：写入时 FTS 插入失败（索引损坏、分词器错误）的消息
+-- 消息行照常写入，索引由 SessionDB::reindex_fts_backlog 稍后补建
+CREATE TABLE IF NOT EXISTS fts_backlog (
+    message_id INTEGER PRIMARY KEY, -- messages.id
+    uuid TEXT NOT NULL,
+    error TEXT,                     -- 最近一次失败原因
+    attempts INTEGER NOT NULL DEFAULT 1,
+    created_at INTEGER NOT NULL
+);
 
+-- FTS 触发器（插入和更新后的重新索引由写入路径显式完成，触发器只删除旧索引）
+-- fts_backlog 中的消息尚未建索引，不能对其执行 'delete'（会破坏外部内容表的索引）
 CREATE TRIGGER IF NOT EXISTS messages_ad AFTER DELETE ON messages BEGIN
-    INSERT INTO messages_fts(messages_fts, rowid, content_full) VALUES('delete', old.id, old.content_full);
+    INSERT INTO messages_fts(messages_fts, rowid, content_full, content_text)
+        SELECT 'delete', old.id, old.content_full, old.content_text
+        WHERE NOT EXISTS (SELECT 1 FROM fts_backlog WHERE message_id = old.id);
+    DELETE FROM fts_backlog WHERE message_id = old.id;
 END;
 
-CREATE TRIGGER IF NOT EXISTS messages_au AFTER UPDATE ON messages BEGIN
-    INSERT INTO messages_fts(messages_fts, rowid, content_full) VALUES('delete', old.id, old.content_full);
-    INSERT INTO messages_fts(rowid, content_full) VALUES (new.id, new.content_full);
+-- 只在内容变化时触发：审批状态、vector_indexed 等更新不写 FTS
+CREATE TRIGGER IF NOT EXISTS messages_au AFTER UPDATE OF content_full, content_text ON messages
+WHEN old.content_full IS NOT new.content_full OR old.content_text IS NOT new.content_text
+BEGIN
+    INSERT INTO messages_fts(messages_fts, rowid, content_full, content_text)
+        SELECT 'delete', old.id, old.content_full, old.content_text
+        WHERE NOT EXISTS (SELECT 1 FROM fts_backlog WHERE message_id = old.id);
+    DELETE FROM fts_backlog WHERE message_id = old.id;
 END;
 
 -- Talks FTS (索引 summary_l2，供 server 端搜索 L2 摘要)
@@ -314,3 +453,120 @@ pub fn full_schema_parts(fts: bool) -> (String, String, Option<String>) {
     };
     (tables, indexes, fts_sql)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 执行 SQL 的源文件（只检查 `#[cfg(test)]` 之前的非注释行）
+    const SQL_SOURCES: &[(&str, &str)] = &[
+        ("schema.rs", include_str!("schema.rs")),
+        ("migrations.rs", include_str!("migrations.rs")),
+        ("db.rs", include_str!("db.rs")),
+        ("reader.rs", include_str!("reader.rs")),
+        ("pagination.rs", include_str!("pagination.rs")),
+        ("search.rs", include_str!("search.rs")),
+        ("collector.rs", include_str!("collector.rs")),
+        ("writer.rs", include_str!("writer.rs")),
+        ("salvage.rs", include_str!("salvage.rs")),
+        ("repair.rs", include_str!("repair.rs")),
+        ("sync/db.rs", include_str!("sync/db.rs")),
+    ];
+
+    /// 高于 `MIN_SQLITE_VERSION` 的语法及其引入版本
+    ///
+    /// `A … B` 表示以 A 开头的语句在括号外出现 B（可跨行）。
+    const NEWER_SYNTAX: &[(&str, &str)] = &[
+        ("NULLS LAST", "3.30"),
+        ("NULLS FIRST", "3.30"),
+        ("FILTER (WHERE", "3.30"),
+        ("iif(", "3.32"),
+        ("IIF(", "3.32"),
+        ("UPDATE … FROM", "3.33"),
+        ("RETURNING", "3.35"),
+        ("DROP COLUMN", "3.35"),
+        ("MATERIALIZED", "3.35"),
+        (") STRICT", "3.37"),
+        ("->>", "3.38"),
+        ("unixepoch(", "3.38"),
+        ("UNIXEPOCH(", "3.38"),
+    ];
+
+    /// 第 `line_no` 行是否使用了 `syntax`
+    fn uses_syntax(lines: &[&str], line_no: usize, syntax: &str) -> bool {
+        let line = lines[line_no];
+        let Some((head, tail)) = syntax.split_once(" … ") else {
+            return line.contains(syntax);
+        };
+        let Some(start) = line.find(head) else {
+            return false;
+        };
+        // 只匹配语句开头的关键字（排除 `AFTER UPDATE ON`、`DO UPDATE SET` 等）
+        let prefix = line[..start].trim_end();
+        if !(prefix.is_empty() || prefix.ends_with('"') || prefix.ends_with(';')) {
+            return false;
+        }
+
+        let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+        let rest = std::iter::once(&line[start + head.len()..])
+            .chain(lines[line_no + 1..].iter().copied());
+        let mut depth = 0;
+        for text in rest {
+            for (i, c) in text.char_indices() {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    // 语句结束（Rust 字符串结束或分号）
+                    '"' | ';' => return false,
+                    _ if depth == 0
+                        && text[i..].starts_with(tail)
+                        && !text[..i].ends_with(is_word_char)
+                        && !text[i + tail.len()..].starts_with(is_word_char) =>
+                    {
+                        return true
+                    }
+                    _ => {}
+                }
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn test_min_sqlite_version_number() {
+        let parts: Vec<i32> = MIN_SQLITE_VERSION
+            .split('.')
+            .map(|p| p.parse().unwrap())
+            .collect();
+        assert_eq!(
+            parts[0] * 1_000_000 + parts[1] * 1_000 + parts[2],
+            MIN_SQLITE_VERSION_NUMBER
+        );
+        assert!(rusqlite::version_number() >= MIN_SQLITE_VERSION_NUMBER);
+    }
+
+    #[test]
+    fn test_no_syntax_above_min_sqlite_version() {
+        for (file, source) in SQL_SOURCES {
+            let code = source.split("#[cfg(test)]").next().unwrap();
+            let lines: Vec<&str> = code.lines().collect();
+            for (line_no, line) in lines.iter().enumerate() {
+                if line.trim_start().starts_with("//") {
+                    continue;
+                }
+                for (syntax, version) in NEWER_SYNTAX {
+                    assert!(
+                        !uses_syntax(&lines, line_no, syntax),
+                        "{}:{} uses `{}` (SQLite {}, minimum supported is {}): {}",
+                        file,
+                        line_no + 1,
+                        syntax,
+                        version,
+                        MIN_SQLITE_VERSION,
+                        line.trim()
+                    );
+                }
+            }
+        }
+    }
+}
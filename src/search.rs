@@ -4,9 +4,14 @@
 
 use crate::db::SessionDB;
 use crate::error::Result;
-use crate::types::{SearchOrderBy, SearchResult};
+use crate::pagination::{PageApi, PageCursor};
+use crate::types::{
+    DateRange, Page, PageToken, SearchField, SearchGroupOptions, SearchOrderBy, SearchResult,
+    SessionSearchGroup,
+};
 #[allow(unused_imports)]
 use rusqlite::params;
+use std::time::Instant;
 
 /// 转义 LIKE 模式中的通配符（`%` 和 `_`），使用 `\` 作为转义字符
 ///
@@ -52,6 +57,109 @@ pub fn escape_fts5_query(query: &str) -> String {
     terms.join(" OR ")
 }
 
+/// 转义查询并限定匹配的列（messages_fts 同时索引 content_full 和 content_text）
+fn fts_match_query(query: &str, field: SearchField) -> String {
+    let escaped = escape_fts5_query(query);
+    if escaped.is_empty() {
+        return escaped;
+    }
+    format!("{} : ({})", field.column(), escaped)
+}
+
+/// 字段在 messages_fts 中的列序号（`snippet()` 使用）
+fn fts_column_index(field: SearchField) -> usize {
+    match field {
+        SearchField::ContentFull => 0,
+        SearchField::ContentText => 1,
+    }
+}
+
+/// 构建 FTS 搜索的 WHERE 子句和参数（`?1` 为转义后的 MATCH 查询，其余参数依次编号）
+///
+/// 需要 JOIN：`messages m`、`sessions s`
+fn fts_where_clauses(
+    query: &str,
+    options: &SearchGroupOptions,
+    session_ids: &[String],
+) -> (Vec<String>, Vec<Box<dyn rusqlite::ToSql>>) {
+    // 转义查询，防止 FTS5 语法错误
+    let match_query = fts_match_query(query, options.field);
+
+    let mut where_clauses = vec!["messages_fts MATCH ?1".to_string()];
+    let mut params_vec: Vec<Box<dyn rusqlite::ToSql>> =
+        vec![Box::new(match_query) as Box<dyn rusqlite::ToSql>];
+    let mut param_idx = 2;
+
+    if let Some(pid) = options.project_id {
+        where_clauses.push(format!("s.project_id = ?{}", param_idx));
+        params_vec.push(Box::new(pid));
+        param_idx += 1;
+    }
+
+    let (start_timestamp, end_timestamp) = options.time_bounds();
+    if let Some(start_ts) = start_timestamp {
+        where_clauses.push(format!("m.timestamp >= ?{}", param_idx));
+        params_vec.push(Box::new(start_ts));
+        param_idx += 1;
+    }
+
+    if let Some(end_ts) = end_timestamp {
+        where_clauses.push(format!("m.timestamp <= ?{}", param_idx));
+        params_vec.push(Box::new(end_ts));
+        param_idx += 1;
+    }
+
+    // Session ID 前缀过滤
+    if !session_ids.is_empty() {
+        let session_likes: Vec<String> = session_ids
+            .iter()
+            .enumerate()
+            .map(|(i, _)| format!("m.session_id LIKE ?{} ESCAPE '\\'", param_idx + i))
+            .collect();
+        where_clauses.push(format!("({})", session_likes.join(" OR ")));
+        for sid in session_ids {
+            params_vec.push(Box::new(format!("{}%", escape_like_pattern(sid))));
+        }
+    }
+
+    (where_clauses, params_vec)
+}
+
+/// 把 snippet 截断到 `max_chars` 个字符（不含补全的结束标签和省略号）
+///
+/// 截断处的半个 `<mark>` / `</mark>` 标签会被去掉，截断在高亮内时补全 `</mark>`。
+fn cap_snippet(snippet: &mut String, max_chars: usize) {
+    let Some((cut, _)) = snippet.char_indices().nth(max_chars) else {
+        return;
+    };
+    snippet.truncate(cut);
+    if let Some(open) = snippet.rfind('<') {
+        let tail = &snippet[open..];
+        if "<mark>".starts_with(tail) || "</mark>".starts_with(tail) {
+            snippet.truncate(open);
+        }
+    }
+    if snippet.matches("<mark>").count() > snippet.matches("</mark>").count() {
+        snippet.push_str("</mark>");
+    }
+    snippet.push_str("...");
+}
+
+/// 按 `SearchGroupOptions::include_content` 保留或清空正文（None 时保留）
+pub(crate) fn with_content(
+    results: Vec<SearchResult>,
+    include_content: Option<bool>,
+) -> Vec<SearchResult> {
+    if include_content.unwrap_or(true) {
+        results
+    } else {
+        results
+            .into_iter()
+            .map(SearchResult::without_content)
+            .collect()
+    }
+}
+
 impl SessionDB {
     /// FTS5 全文搜索
     pub fn search_fts(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
@@ -82,7 +190,7 @@ impl SessionDB {
         project_id: Option<i64>,
         order_by: SearchOrderBy,
     ) -> Result<Vec<SearchResult>> {
-        self.search_fts_full(query, limit, project_id, order_by, None, None)
+        self.search_fts_full(query, limit, project_id, order_by, None, None, None)
     }
 
     /// FTS5 全文搜索 (完整参数版本，含日期范围)
@@ -96,6 +204,8 @@ impl SessionDB {
     /// - `order_by`: 排序方式
     /// - `start_timestamp`: 开始时间戳（毫秒，可选）
     /// - `end_timestamp`: 结束时间戳（毫秒，可选）
+    /// - `max_per_project`: 每个项目的配额（可选），见 `search_fts_full_with_sessions`
+    #[allow(clippy::too_many_arguments)]
     pub fn search_fts_full(
         &self,
         query: &str,
@@ -104,6 +214,7 @@ impl SessionDB {
         order_by: SearchOrderBy,
         start_timestamp: Option<i64>,
         end_timestamp: Option<i64>,
+        max_per_project: Option<usize>,
     ) -> Result<Vec<SearchResult>> {
         self.search_fts_full_with_sessions(
             query,
@@ -113,9 +224,47 @@ impl SessionDB {
             start_timestamp,
             end_timestamp,
             &[],
+            max_per_project,
+        )
+    }
+
+    /// FTS5 全文搜索，日期范围为 `DateRange`（如 `DateRange::last_n_days(7, offset)`），
+    /// 其余参数同 `search_fts_full`
+    pub fn search_fts_in_range(
+        &self,
+        query: &str,
+        limit: usize,
+        project_id: Option<i64>,
+        order_by: SearchOrderBy,
+        range: DateRange,
+        max_per_project: Option<usize>,
+    ) -> Result<Vec<SearchResult>> {
+        self.search_fts_full(
+            query,
+            limit,
+            project_id,
+            order_by,
+            Some(range.start_ms),
+            Some(range.end_ms),
+            max_per_project,
         )
     }
 
+    /// FTS5 全文搜索（过滤条件和是否返回正文取自 `options`）
+    ///
+    /// `include_content` 为 false 时命中的 content_full 为空，按 message_id 用
+    /// `get_messages_by_ids` 取正文，避免大消息（如工具输出）随每条命中一起返回。
+    pub fn search_fts_filtered(
+        &self,
+        query: &str,
+        limit: usize,
+        options: &SearchGroupOptions,
+        max_per_project: Option<usize>,
+    ) -> Result<Vec<SearchResult>> {
+        let results = self.search_with_fallback(query, limit, options, &[], max_per_project)?;
+        Ok(with_content(results, options.include_content))
+    }
+
     /// FTS5 全文搜索 (完整参数版本，含日期范围和 session 过滤)
     ///
     /// # Arguments
@@ -126,6 +275,13 @@ impl SessionDB {
     /// - `start_timestamp`: 开始时间戳（毫秒，可选）
     /// - `end_timestamp`: 结束时间戳（毫秒，可选）
     /// - `session_ids`: Session ID 前缀列表（空则不过滤）
+    /// - `max_per_project`: 每个项目的配额（可选）
+    ///
+    /// 设置 `max_per_project` 时，先返回每个项目按 `order_by` 排名前 N 的命中
+    /// （TimeDesc 即每个项目最新的 N 条），全局 limit 未满时再按同一排序补充超出配额的命中。
+    /// 单个项目命中过多时其他项目也能出现在结果中。
+    ///
+    /// snippet 截断到 `DbConfig::snippet_max_chars` 个字符。
     #[allow(clippy::too_many_arguments)]
     pub fn search_fts_full_with_sessions(
         &self,
@@ -136,63 +292,285 @@ impl SessionDB {
         start_timestamp: Option<i64>,
         end_timestamp: Option<i64>,
         session_ids: &[String],
+        max_per_project: Option<usize>,
     ) -> Result<Vec<SearchResult>> {
-        // 先用 FTS5 搜索
-        let fts_results = self.search_fts_internal(
-            query,
-            limit,
+        let options = SearchGroupOptions {
             project_id,
             order_by,
             start_timestamp,
             end_timestamp,
-            session_ids,
+            ..Default::default()
+        };
+        self.search_with_fallback(query, limit, &options, session_ids, max_per_project)
+    }
+
+    /// `search_unlogged`，启用 `DbConfig::log_searches` 时记录这次搜索
+    fn search_with_fallback(
+        &self,
+        query: &str,
+        limit: usize,
+        options: &SearchGroupOptions,
+        session_ids: &[String],
+        max_per_project: Option<usize>,
+    ) -> Result<Vec<SearchResult>> {
+        let started = Instant::now();
+        let results = self.search_unlogged(query, limit, options, session_ids, max_per_project)?;
+        self.record_search(query, options.project_id, results.len(), started);
+        Ok(results)
+    }
+
+    /// FTS5 搜索，结果不足且有 project_id 时 LIKE 补充（匹配 `options.field`），不记录搜索
+    pub(crate) fn search_unlogged(
+        &self,
+        query: &str,
+        limit: usize,
+        options: &SearchGroupOptions,
+        session_ids: &[String],
+        max_per_project: Option<usize>,
+    ) -> Result<Vec<SearchResult>> {
+        // 先用 FTS5 搜索
+        let mut results =
+            self.search_fts_internal(query, limit, options, session_ids, max_per_project)?;
+
+        // FTS 结果不足且有 project_id，用 LIKE 补充
+        if options.project_id.is_some() && results.len() < limit {
+            let existing_ids: Vec<i64> = results.iter().map(|r| r.message_id).collect();
+            let remaining = limit - results.len();
+
+            let like_results =
+                self.search_like_fallback(query, remaining, options, &existing_ids, session_ids)?;
+            results.extend(like_results);
+        }
+
+        for result in &mut results {
+            cap_snippet(&mut result.snippet, self.config().snippet_max_chars);
+        }
+        Ok(results)
+    }
+
+    /// 全文搜索的命中总数（过滤条件同 `search_fts_full`，不受 limit 影响）
+    ///
+    /// 用于显示 "共 N 条"。这是一次额外的 FTS 查询，需要遍历全部命中，
+    /// 高频词可能比取一页结果慢，只在需要展示总数时调用。
+    /// 只统计 FTS 命中，不含指定项目时 LIKE 补充的结果。
+    pub fn search_count(&self, query: &str, options: &SearchGroupOptions) -> Result<i64> {
+        if escape_fts5_query(query).is_empty() {
+            return Ok(0);
+        }
+
+        let (where_clauses, params_vec) = fts_where_clauses(query, options, &[]);
+        let sql = format!(
+            r#"
+            SELECT COUNT(*)
+            FROM messages_fts
+            JOIN messages m ON messages_fts.rowid = m.id
+            JOIN sessions s ON m.session_id = s.session_id
+            JOIN projects p ON s.project_id = p.id
+            WHERE {}
+            "#,
+            where_clauses.join(" AND ")
+        );
+
+        let conn = self.conn.lock();
+        let params_refs: Vec<&dyn rusqlite::ToSql> =
+            params_vec.iter().map(|p| p.as_ref()).collect();
+        conn.query_row(&sql, params_refs.as_slice(), |row| row.get(0))
+            .map_err(Into::into)
+    }
+
+    /// 全文搜索的一页（参数含义同 `search_fts_filtered`）
+    ///
+    /// 搜索结果没有稳定的排序键，按偏移分页：第 N 页会重新搜索前 N 页的结果再跳过，
+    /// 翻页越深越慢；翻页期间写入的新消息可能导致结果重复或跳过。
+    /// 搜索记录只记录第一页。
+    pub fn search_fts_page(
+        &self,
+        query: &str,
+        limit: usize,
+        options: &SearchGroupOptions,
+        max_per_project: Option<usize>,
+        page_token: Option<&PageToken>,
+    ) -> Result<Page<SearchResult>> {
+        let scope = format!("{}\n{:?}\n{:?}", query, options, max_per_project);
+        let offset = PageToken::decode_offset(page_token, PageApi::Search, &scope)?;
+        let started = Instant::now();
+        let results = self.search_unlogged(
+            query,
+            offset.saturating_add(limit).saturating_add(1),
+            options,
+            &[],
+            max_per_project,
         )?;
+        if offset == 0 {
+            self.record_search(query, options.project_id, results.len().min(limit), started);
+        }
+        let results = with_content(results, options.include_content)
+            .into_iter()
+            .skip(offset)
+            .collect();
+        Ok(Page::from_overfetch(results, limit, |_| {
+            PageToken::encode(PageApi::Search, &scope, PageCursor::Offset(offset + limit))
+        }))
+    }
 
-        // FTS 结果足够，直接返回
-        if fts_results.len() >= limit {
-            return Ok(fts_results);
+    /// FTS5 全文搜索，按会话分组
+    ///
+    /// 单条查询完成：窗口函数按会话分区排名命中，截取每个会话前 `per_session_limit` 条，
+    /// 再按会话最佳分数（或最新/最早命中时间）取前 `session_limit` 个会话。
+    ///
+    /// # Arguments
+    /// - `query`: 搜索关键词
+    /// - `session_limit`: 返回的会话数
+    /// - `per_session_limit`: 每个会话返回的命中数
+    /// - `options`: 项目过滤、排序方式、日期范围、是否返回正文
+    ///
+    /// 搜索记录的结果数为返回的会话数。
+    pub fn search_fts_grouped(
+        &self,
+        query: &str,
+        session_limit: usize,
+        per_session_limit: usize,
+        options: &SearchGroupOptions,
+    ) -> Result<Vec<SessionSearchGroup>> {
+        if escape_fts5_query(query).is_empty() || session_limit == 0 {
+            return Ok(vec![]);
         }
+        let started = Instant::now();
 
-        // FTS 结果不足且有 project_id，用 LIKE 补充
-        if project_id.is_some() && fts_results.len() < limit {
-            let existing_ids: Vec<i64> = fts_results.iter().map(|r| r.message_id).collect();
-            let remaining = limit - fts_results.len();
-
-            let like_results = self.search_like_fallback(
-                query,
-                remaining,
-                project_id,
-                order_by,
-                start_timestamp,
-                end_timestamp,
-                &existing_ids,
-                session_ids,
-            )?;
-
-            let mut combined = fts_results;
-            combined.extend(like_results);
-            return Ok(combined);
+        // (会话排序, 会话内命中排序)
+        let (group_order, hit_order) = match options.order_by {
+            SearchOrderBy::Score => ("best_score ASC", "score ASC"),
+            SearchOrderBy::TimeDesc => ("latest_ts DESC", "timestamp DESC"),
+            SearchOrderBy::TimeAsc => ("earliest_ts ASC", "timestamp ASC"),
+        };
+
+        let (where_clauses, mut params_vec) = fts_where_clauses(query, options, &[]);
+        let param_idx = params_vec.len() + 1;
+
+        params_vec.push(Box::new(per_session_limit as i64));
+        params_vec.push(Box::new(session_limit as i64));
+
+        let sql = format!(
+            r#"
+            WITH hits AS (
+                SELECT
+                    m.id,
+                    m.session_id,
+                    s.project_id,
+                    p.name as project_name,
+                    m.type,
+                    m.content_full,
+                    snippet(messages_fts, {}, '<mark>', '</mark>', '...', 64) as snippet,
+                    bm25(messages_fts) as score,
+                    m.timestamp
+                FROM messages_fts
+                JOIN messages m ON messages_fts.rowid = m.id
+                JOIN sessions s ON m.session_id = s.session_id
+                JOIN projects p ON s.project_id = p.id
+                WHERE {}
+            ),
+            ranked AS (
+                SELECT
+                    hits.*,
+                    ROW_NUMBER() OVER (PARTITION BY session_id ORDER BY {}, id) as hit_rank,
+                    COUNT(*) OVER (PARTITION BY session_id) as hit_count,
+                    MIN(score) OVER (PARTITION BY session_id) as best_score,
+                    MAX(timestamp) OVER (PARTITION BY session_id) as latest_ts,
+                    MIN(timestamp) OVER (PARTITION BY session_id) as earliest_ts
+                FROM hits
+            ),
+            grouped AS (
+                SELECT
+                    ranked.*,
+                    DENSE_RANK() OVER (ORDER BY {}, session_id) as group_rank
+                FROM ranked
+            )
+            SELECT id, session_id, project_id, project_name, type, content_full,
+                   snippet, score, timestamp, hit_count, best_score
+            FROM grouped
+            WHERE hit_rank <= ?{} AND group_rank <= ?{}
+            ORDER BY group_rank, hit_rank
+            "#,
+            fts_column_index(options.field),
+            where_clauses.join(" AND "),
+            hit_order,
+            group_order,
+            param_idx,
+            param_idx + 1
+        );
+
+        // (session_id, hit_count, best_score, hits)，按分组顺序
+        let mut groups: Vec<(String, i64, f64, Vec<SearchResult>)> = Vec::new();
+        let include_content = options.include_content.unwrap_or(true);
+        {
+            let conn = self.conn.lock();
+            let mut stmt = conn.prepare(&sql)?;
+            let params_refs: Vec<&dyn rusqlite::ToSql> =
+                params_vec.iter().map(|p| p.as_ref()).collect();
+
+            let mut rows = stmt.query(params_refs.as_slice())?;
+            while let Some(row) = rows.next()? {
+                let mut hit = SearchResult {
+                    message_id: row.get(0)?,
+                    session_id: row.get(1)?,
+                    project_id: row.get(2)?,
+                    project_name: row.get(3)?,
+                    r#type: row.get(4)?,
+                    content_full: row.get(5)?,
+                    snippet: row.get(6)?,
+                    score: row.get(7)?,
+                    timestamp: row.get(8)?,
+                };
+                cap_snippet(&mut hit.snippet, self.config().snippet_max_chars);
+                if !include_content {
+                    hit = hit.without_content();
+                }
+                match groups.last_mut() {
+                    Some(group) if group.0 == hit.session_id => group.3.push(hit),
+                    _ => {
+                        groups.push((hit.session_id.clone(), row.get(9)?, row.get(10)?, vec![hit]))
+                    }
+                }
+            }
         }
 
-        Ok(fts_results)
+        let session_ids: Vec<String> = groups.iter().map(|g| g.0.clone()).collect();
+        let mut sessions: std::collections::HashMap<String, _> = self
+            .get_sessions_by_ids(&session_ids)?
+            .into_iter()
+            .map(|s| (s.session_id.clone(), s))
+            .collect();
+
+        let groups: Vec<SessionSearchGroup> = groups
+            .into_iter()
+            .filter_map(|(session_id, hit_count, best_score, hits)| {
+                sessions
+                    .remove(&session_id)
+                    .map(|session| SessionSearchGroup {
+                        session,
+                        hit_count,
+                        best_score,
+                        hits,
+                    })
+            })
+            .collect();
+        self.record_search(query, options.project_id, groups.len(), started);
+        Ok(groups)
     }
 
     /// FTS5 内部搜索实现
-    #[allow(clippy::too_many_arguments)]
+    ///
+    /// 有项目配额时用窗口函数按 project_id 分区排名，配额内的命中排在前面。
     fn search_fts_internal(
         &self,
         query: &str,
         limit: usize,
-        project_id: Option<i64>,
-        order_by: SearchOrderBy,
-        start_timestamp: Option<i64>,
-        end_timestamp: Option<i64>,
+        options: &SearchGroupOptions,
         session_ids: &[String],
+        max_per_project: Option<usize>,
     ) -> Result<Vec<SearchResult>> {
         let conn = self.conn.lock();
-
-        // 转义查询，防止 FTS5 语法错误
-        let escaped_query = escape_fts5_query(query);
+        let order_by = options.order_by;
 
         // 根据排序方式生成 ORDER BY 子句
         let order_clause = match order_by {
@@ -201,48 +579,10 @@ impl SessionDB {
             SearchOrderBy::TimeAsc => "ORDER BY m.timestamp ASC",
         };
 
-        // 动态构建 WHERE 子句和参数
-        let mut where_clauses = vec!["messages_fts MATCH ?1".to_string()];
-        let mut params_vec: Vec<Box<dyn rusqlite::ToSql>> =
-            vec![Box::new(escaped_query) as Box<dyn rusqlite::ToSql>];
-        let mut param_idx = 2;
-
-        if let Some(pid) = project_id {
-            where_clauses.push(format!("s.project_id = ?{}", param_idx));
-            params_vec.push(Box::new(pid));
-            param_idx += 1;
-        }
+        let (where_clauses, mut params_vec) = fts_where_clauses(query, options, session_ids);
+        let param_idx = params_vec.len() + 1;
 
-        if let Some(start_ts) = start_timestamp {
-            where_clauses.push(format!("m.timestamp >= ?{}", param_idx));
-            params_vec.push(Box::new(start_ts));
-            param_idx += 1;
-        }
-
-        if let Some(end_ts) = end_timestamp {
-            where_clauses.push(format!("m.timestamp <= ?{}", param_idx));
-            params_vec.push(Box::new(end_ts));
-            param_idx += 1;
-        }
-
-        // Session ID 前缀过滤
-        if !session_ids.is_empty() {
-            let session_likes: Vec<String> = session_ids
-                .iter()
-                .enumerate()
-                .map(|(i, _)| format!("m.session_id LIKE ?{} ESCAPE '\\'", param_idx + i))
-                .collect();
-            where_clauses.push(format!("({})", session_likes.join(" OR ")));
-            for sid in session_ids {
-                params_vec.push(Box::new(format!("{}%", escape_like_pattern(sid))));
-            }
-            param_idx += session_ids.len();
-        }
-
-        // LIMIT 参数
-        params_vec.push(Box::new(limit as i64));
-
-        let sql = format!(
+        let select = format!(
             r#"
             SELECT
                 m.id,
@@ -251,7 +591,7 @@ impl SessionDB {
                 p.name as project_name,
                 m.type,
                 m.content_full,
-                snippet(messages_fts, 0, '<mark>', '</mark>', '...', 64) as snippet,
+                snippet(messages_fts, {}, '<mark>', '</mark>', '...', 64) as snippet,
                 bm25(messages_fts) as score,
                 m.timestamp
             FROM messages_fts
@@ -259,14 +599,49 @@ impl SessionDB {
             JOIN sessions s ON m.session_id = s.session_id
             JOIN projects p ON s.project_id = p.id
             WHERE {}
-            {}
-            LIMIT ?{}
             "#,
-            where_clauses.join(" AND "),
-            order_clause,
-            param_idx
+            fts_column_index(options.field),
+            where_clauses.join(" AND ")
         );
 
+        let sql = match max_per_project {
+            Some(quota) => {
+                // 同一排序下的项目内排名（hits 中没有 m. 前缀）
+                let rank_order = match order_by {
+                    SearchOrderBy::Score => "score ASC",
+                    SearchOrderBy::TimeDesc => "timestamp DESC",
+                    SearchOrderBy::TimeAsc => "timestamp ASC",
+                };
+                params_vec.push(Box::new(quota as i64));
+                params_vec.push(Box::new(limit as i64));
+                format!(
+                    r#"
+                    WITH hits AS ({}),
+                    ranked AS (
+                        SELECT
+                            hits.*,
+                            ROW_NUMBER() OVER (PARTITION BY project_id ORDER BY {}, id) as project_rank
+                        FROM hits
+                    )
+                    SELECT id, session_id, project_id, project_name, type, content_full,
+                           snippet, score, timestamp
+                    FROM ranked
+                    ORDER BY project_rank > ?{}, {}, id
+                    LIMIT ?{}
+                    "#,
+                    select,
+                    rank_order,
+                    param_idx,
+                    rank_order,
+                    param_idx + 1
+                )
+            }
+            None => {
+                params_vec.push(Box::new(limit as i64));
+                format!("{} {} LIMIT ?{}", select, order_clause, param_idx)
+            }
+        };
+
         let mut stmt = conn.prepare(&sql)?;
         let params_refs: Vec<&dyn rusqlite::ToSql> =
             params_vec.iter().map(|p| p.as_ref()).collect();
@@ -292,40 +667,38 @@ impl SessionDB {
     /// LIKE 回退搜索（FTS 结果不足时使用）
     ///
     /// 仅在指定 project_id 时使用，因为项目内数据量有限，LIKE 性能可接受
-    #[allow(clippy::too_many_arguments)]
     fn search_like_fallback(
         &self,
         query: &str,
         limit: usize,
-        project_id: Option<i64>,
-        order_by: SearchOrderBy,
-        start_timestamp: Option<i64>,
-        end_timestamp: Option<i64>,
+        options: &SearchGroupOptions,
         exclude_ids: &[i64],
         session_ids: &[String],
     ) -> Result<Vec<SearchResult>> {
         let conn = self.conn.lock();
+        let column = options.field.column();
 
         // 排序子句（LIKE 没有相关性分数，默认按时间）
-        let order_clause = match order_by {
+        let order_clause = match options.order_by {
             SearchOrderBy::Score => "ORDER BY m.timestamp DESC", // 无分数，退化为时间排序
             SearchOrderBy::TimeDesc => "ORDER BY m.timestamp DESC",
             SearchOrderBy::TimeAsc => "ORDER BY m.timestamp ASC",
         };
 
         // 构建 WHERE 子句
-        let mut where_clauses = vec!["m.content_full LIKE ?1".to_string()];
+        let mut where_clauses = vec![format!("m.{} LIKE ?1", column)];
         let like_pattern = format!("%{}%", query);
         let mut params_vec: Vec<Box<dyn rusqlite::ToSql>> =
             vec![Box::new(like_pattern) as Box<dyn rusqlite::ToSql>];
         let mut param_idx = 2;
 
-        if let Some(pid) = project_id {
+        if let Some(pid) = options.project_id {
             where_clauses.push(format!("s.project_id = ?{}", param_idx));
             params_vec.push(Box::new(pid));
             param_idx += 1;
         }
 
+        let (start_timestamp, end_timestamp) = options.time_bounds();
         if let Some(start_ts) = start_timestamp {
             where_clauses.push(format!("m.timestamp >= ?{}", param_idx));
             params_vec.push(Box::new(start_ts));
@@ -377,7 +750,7 @@ impl SessionDB {
                 p.name as project_name,
                 m.type,
                 m.content_full,
-                substr(m.content_full, 1, 200) as snippet,
+                substr(m.{}, 1, 200) as snippet,
                 0.0 as score,
                 m.timestamp
             FROM messages m
@@ -387,6 +760,7 @@ impl SessionDB {
             {}
             LIMIT ?{}
             "#,
+            column,
             where_clauses.join(" AND "),
             order_clause,
             param_idx
@@ -549,4 +923,24 @@ mod tests {
             "\"hello\" OR \"world\""
         );
     }
+
+    #[test]
+    fn test_cap_snippet() {
+        let cap = |s: &str, max: usize| {
+            let mut s = s.to_string();
+            cap_snippet(&mut s, max);
+            s
+        };
+        // 未超出：不变
+        assert_eq!(cap("short <mark>hit</mark>", 100), "short <mark>hit</mark>");
+        // 按字符截断（多字节安全）
+        assert_eq!(cap("日志日志日志", 2), "日志...");
+        // 截断在高亮内：补全结束标签
+        assert_eq!(cap("a <mark>long hit</mark>", 10), "a <mark>lo</mark>...");
+        // 截断在标签中间：去掉半个标签
+        assert_eq!(cap("abc <mark>x</mark>", 6), "abc ...");
+        assert_eq!(cap("<mark>x</mark> tail", 9), "<mark>x</mark>...");
+        // 正文中的 < 不受影响
+        assert_eq!(cap("a < b < c", 5), "a < b...");
+    }
 }
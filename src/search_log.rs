@@ -0,0 +1,204 @@
+//! 搜索记录（opt-in）
+//!
+//! 启用 `DbConfig::log_searches` 后，`search_fts_*` 每次搜索记录查询、规范化后的查询、
+//! 结果数和耗时，供 UI 显示最近的搜索（`recent_searches`），并在本地统计无结果的查询
+//! （`zero_result_searches`）以改进分词配置。记录只保存在本地数据库，不上传。
+//!
+//! - 超过 `DbConfig::search_log_cap` 条时删除最旧的记录
+//! - 无法写入时（只读连接、数据库被锁定）记录暂存在内存中，由调用方
+//!   `take_buffered_searches` 取出后经 Agent 写入（`Request::WriteSearchLog`）
+//! - 记录失败不影响搜索本身
+
+use std::collections::VecDeque;
+#[cfg(feature = "search")]
+use std::time::Instant;
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{current_time_ms, SessionDB};
+use crate::error::Result;
+
+/// 默认保留的搜索记录数
+pub const DEFAULT_SEARCH_LOG_CAP: usize = 1000;
+
+/// 内存中暂存的搜索记录（共享句柄共用）
+pub(crate) type SearchLogBuffer = parking_lot::Mutex<VecDeque<SearchLogInput>>;
+
+/// 待写入的搜索记录
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchLogInput {
+    /// 原始查询
+    pub query: String,
+    /// 规范化后的查询（见 `normalize_search_query`）
+    pub normalized_query: String,
+    pub result_count: i64,
+    pub duration_ms: i64,
+    /// 按项目过滤时的项目 ID
+    pub project_filter: Option<i64>,
+    /// 搜索时间（毫秒）
+    pub created_at: i64,
+}
+
+/// 一条搜索记录
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchLogEntry {
+    pub id: i64,
+    pub query: String,
+    pub normalized_query: String,
+    pub result_count: i64,
+    pub duration_ms: i64,
+    pub project_filter: Option<i64>,
+    pub created_at: i64,
+}
+
+/// 规范化查询：去掉首尾空白，连续空白合并为一个空格，转为小写
+pub fn normalize_search_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+impl SessionDB {
+    /// 记录一次搜索（未启用 `log_searches` 或查询为空时忽略）
+    #[cfg(feature = "search")]
+    pub(crate) fn record_search(
+        &self,
+        query: &str,
+        project_filter: Option<i64>,
+        result_count: usize,
+        started: Instant,
+    ) {
+        if !self.config().log_searches {
+            return;
+        }
+        let normalized_query = normalize_search_query(query);
+        if normalized_query.is_empty() {
+            return;
+        }
+        let entry = SearchLogInput {
+            query: query.to_string(),
+            normalized_query,
+            result_count: result_count as i64,
+            duration_ms: started.elapsed().as_millis() as i64,
+            project_filter,
+            created_at: current_time_ms(),
+        };
+        if let Err(e) = self.write_search_log(std::slice::from_ref(&entry)) {
+            tracing::debug!("Search log buffered, write failed: {}", e);
+            let cap = self.config().search_log_cap;
+            let mut buffer = self.search_log_buffer.lock();
+            buffer.push_back(entry);
+            while buffer.len() > cap {
+                buffer.pop_front();
+            }
+        }
+    }
+
+    /// 写入搜索记录并删除超过 `search_log_cap` 的旧记录，返回写入的条数
+    ///
+    /// Agent 用于写入 Reader 转发的记录（不检查 `log_searches`）。
+    pub fn write_search_log(&self, entries: &[SearchLogInput]) -> Result<usize> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        for entry in entries {
+            tx.execute(
+                r#"
+                INSERT INTO search_log
+                    (query, normalized_query, result_count, duration_ms, project_filter, created_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                "#,
+                params![
+                    entry.query,
+                    entry.normalized_query,
+                    entry.result_count,
+                    entry.duration_ms,
+                    entry.project_filter,
+                    entry.created_at,
+                ],
+            )?;
+        }
+        prune_search_log(&tx, self.config().search_log_cap)?;
+        tx.commit()?;
+        Ok(entries.len())
+    }
+
+    /// 取出暂存在内存中、尚未写入的搜索记录（只读连接上的搜索）
+    ///
+    /// 交给 Agent 写入：`AgentClient::write_search_log`。
+    pub fn take_buffered_searches(&self) -> Vec<SearchLogInput> {
+        self.search_log_buffer.lock().drain(..).collect()
+    }
+
+    /// 最近的搜索（按时间倒序）
+    pub fn recent_searches(&self, limit: usize) -> Result<Vec<SearchLogEntry>> {
+        let conn = self.conn.lock();
+        query_search_log(&conn, "1 = 1", limit)
+    }
+
+    /// 最近没有结果的搜索（按时间倒序）
+    pub fn zero_result_searches(&self, limit: usize) -> Result<Vec<SearchLogEntry>> {
+        let conn = self.conn.lock();
+        query_search_log(&conn, "result_count = 0", limit)
+    }
+
+    /// 清空搜索记录（含内存中暂存的），返回删除的行数
+    pub fn clear_search_log(&self) -> Result<usize> {
+        self.search_log_buffer.lock().clear();
+        let conn = self.conn.lock();
+        Ok(conn.execute("DELETE FROM search_log", [])?)
+    }
+}
+
+/// 只保留最新的 `cap` 条记录
+fn prune_search_log(conn: &Connection, cap: usize) -> rusqlite::Result<usize> {
+    conn.execute(
+        r#"
+        DELETE FROM search_log
+        WHERE id <= (SELECT id FROM search_log ORDER BY id DESC LIMIT 1 OFFSET ?1)
+        "#,
+        params![cap as i64],
+    )
+}
+
+fn query_search_log(conn: &Connection, filter: &str, limit: usize) -> Result<Vec<SearchLogEntry>> {
+    let sql = format!(
+        r#"
+        SELECT id, query, normalized_query, result_count, duration_ms, project_filter, created_at
+        FROM search_log
+        WHERE {}
+        ORDER BY created_at DESC, id DESC
+        LIMIT ?1
+        "#,
+        filter
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(params![limit as i64], |row| {
+        Ok(SearchLogEntry {
+            id: row.get(0)?,
+            query: row.get(1)?,
+            normalized_query: row.get(2)?,
+            result_count: row.get(3)?,
+            duration_ms: row.get(4)?,
+            project_filter: row.get(5)?,
+            created_at: row.get(6)?,
+        })
+    })?;
+    Ok(rows.collect::<std::result::Result<Vec<_>, _>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_search_query() {
+        let normalized = normalize_search_query("  Rust   Async\tTrait ");
+        assert_eq!(normalized, "rust async trait");
+        assert_eq!(normalize_search_query("   "), "");
+    }
+}
@@ -0,0 +1,521 @@
+//! 会话分享包
+//!
+//! 把会话的一段（按 sequence 选择）导出为单个可移植的 JSON 文件，发给同事查看：
+//!
+//! - `SessionDB::create_share_bundle`：选择消息范围，按 `ToolMessageMode` 保留 / 摘要 / 省略
+//!   工具消息，按 `RedactionPolicy` 脱敏，项目信息只含名称或包含完整路径
+//! - `render_share_bundle_markdown`：生成便于阅读的 Markdown
+//! - `import_share_bundle`：接收方导入到自己的数据库，会话 source 为 `shared`
+//!
+//! 分享包带 `schema_version`，导入时拒绝更新版本的分享包。sidechain 消息不导出。
+
+use std::path::PathBuf;
+
+use ai_cli_session_collector::MessageType;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::db::{current_time_ms, MessageInput, SessionDB, SessionInput};
+use crate::error::{Error, Result};
+use crate::types::Message;
+
+/// 分享包格式版本（结构不兼容变化时递增）
+pub const SHARE_BUNDLE_SCHEMA_VERSION: u32 = 1;
+
+/// 导入的分享会话的来源
+pub const SHARED_SOURCE: &str = "shared";
+
+/// 工具消息摘要保留的最大字符数
+const TOOL_SUMMARY_MAX_CHARS: usize = 120;
+
+/// 导入后的会话 / 消息 ID 的命名空间（UUID v5）
+const SHARE_NAMESPACE: Uuid = Uuid::from_u128(0x3b8e_41d2_7c6a_4f05_9e13_8d2b_6a4c_0f71);
+
+/// 工具消息的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolMessageMode {
+    /// 保留完整内容
+    Full,
+    /// 替换为一行摘要（工具名 + 输出首行 + 行数）
+    #[default]
+    Summarized,
+    /// 不导出
+    Omitted,
+}
+
+/// 分享包中的项目信息
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectContext {
+    /// 只含项目名称
+    #[default]
+    NameOnly,
+    /// 包含项目完整路径
+    FullPath,
+}
+
+/// 脱敏规则
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedactionPolicy {
+    /// 需要替换的词（不区分 ASCII 大小写）
+    pub words: Vec<String>,
+    /// 替换看起来像密钥的 token（`sk-`、`ghp_`、`AKIA` 等前缀）
+    pub secrets: bool,
+    /// 把该目录前缀替换为 `~`（默认为当前用户主目录）
+    pub home_dir: Option<PathBuf>,
+    /// 替换文本
+    pub replacement: String,
+}
+
+impl Default for RedactionPolicy {
+    fn default() -> Self {
+        Self {
+            words: Vec::new(),
+            secrets: true,
+            home_dir: dirs::home_dir(),
+            replacement: "[REDACTED]".to_string(),
+        }
+    }
+}
+
+/// 密钥 token 的前缀
+const SECRET_PREFIXES: &[&str] = &[
+    "sk-",
+    "ghp_",
+    "gho_",
+    "ghs_",
+    "github_pat_",
+    "glpat-",
+    "xoxb-",
+    "xoxp-",
+    "AKIA",
+];
+
+/// 前缀之后至少的字符数（避免误伤普通单词）
+const SECRET_MIN_BODY_CHARS: usize = 16;
+
+impl RedactionPolicy {
+    /// 对文本应用脱敏规则
+    pub fn apply(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        if let Some(home) = self.home_dir.as_ref().and_then(|h| h.to_str()) {
+            if !home.is_empty() && home != "/" {
+                text = text.replace(home.trim_end_matches('/'), "~");
+            }
+        }
+        if self.secrets {
+            text = self.redact_secrets(&text);
+        }
+        for word in self.words.iter().filter(|w| !w.is_empty()) {
+            text = replace_ignore_ascii_case(&text, word, &self.replacement);
+        }
+        text
+    }
+
+    fn redact_secrets(&self, text: &str) -> String {
+        let is_token_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some((start, prefix)) = find_secret_prefix(rest) {
+            let body_start = start + prefix.len();
+            let body_len = rest[body_start..]
+                .find(|c: char| !is_token_char(c))
+                .unwrap_or(rest.len() - body_start);
+            let at_boundary = !rest[..start].ends_with(is_token_char);
+            out.push_str(&rest[..start]);
+            if at_boundary && body_len >= SECRET_MIN_BODY_CHARS {
+                out.push_str(&self.replacement);
+            } else {
+                out.push_str(&rest[start..body_start + body_len]);
+            }
+            rest = &rest[body_start + body_len..];
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+/// 最早出现的密钥前缀（位置, 前缀）
+fn find_secret_prefix(text: &str) -> Option<(usize, &'static str)> {
+    SECRET_PREFIXES
+        .iter()
+        .filter_map(|prefix| text.find(prefix).map(|pos| (pos, *prefix)))
+        .min_by_key(|(pos, prefix)| (*pos, std::cmp::Reverse(prefix.len())))
+}
+
+/// 不区分 ASCII 大小写的替换
+fn replace_ignore_ascii_case(text: &str, word: &str, replacement: &str) -> String {
+    let haystack = text.to_ascii_lowercase();
+    let needle = word.to_ascii_lowercase();
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for (pos, _) in haystack.match_indices(&needle) {
+        out.push_str(&text[last..pos]);
+        out.push_str(replacement);
+        last = pos + needle.len();
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+/// 分享选项
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShareOptions {
+    pub tool_messages: ToolMessageMode,
+    /// 脱敏规则（None 表示不脱敏）
+    pub redaction: Option<RedactionPolicy>,
+    pub project_context: ProjectContext,
+}
+
+/// 分享包中的项目
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedProject {
+    pub name: String,
+    /// `ProjectContext::FullPath` 时的项目路径
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+/// 分享包中的消息
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedMessage {
+    pub uuid: String,
+    pub r#type: MessageType,
+    pub content: String,
+    pub timestamp: i64,
+    pub sequence: i64,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub tool_name: Option<String>,
+    /// 内容已替换为摘要（`ToolMessageMode::Summarized`）
+    #[serde(default)]
+    pub summarized: bool,
+}
+
+/// 会话分享包（序列化为单个 JSON 文件）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareBundle {
+    pub schema_version: u32,
+    /// 创建时间（毫秒）
+    pub created_at: i64,
+    /// 原会话 ID
+    pub session_id: String,
+    #[serde(default)]
+    pub source: Option<String>,
+    pub project: SharedProject,
+    /// 选择的 sequence 范围（含两端），None 表示整个会话
+    #[serde(default)]
+    pub range: Option<(i64, i64)>,
+    pub tool_messages: ToolMessageMode,
+    /// 是否已脱敏
+    pub redacted: bool,
+    pub messages: Vec<SharedMessage>,
+}
+
+impl ShareBundle {
+    /// 序列化为 JSON
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).map_err(Into::into)
+    }
+
+    /// 从 JSON 解析（拒绝更新版本的分享包）
+    pub fn from_json(json: &str) -> Result<Self> {
+        let bundle: Self = serde_json::from_str(json)?;
+        if bundle.schema_version > SHARE_BUNDLE_SCHEMA_VERSION {
+            return Err(Error::Unsupported(format!(
+                "share bundle schema version {} (supported: {})",
+                bundle.schema_version, SHARE_BUNDLE_SCHEMA_VERSION
+            )));
+        }
+        Ok(bundle)
+    }
+}
+
+impl SessionDB {
+    /// 创建会话分享包
+    ///
+    /// - range: sequence 范围（含两端），None 表示整个会话
+    ///
+    /// 会话不存在时返回 `Error::NotFound`。
+    pub fn create_share_bundle(
+        &self,
+        session_id: &str,
+        range: Option<(i64, i64)>,
+        options: &ShareOptions,
+    ) -> Result<ShareBundle> {
+        let session = self
+            .get_session_with_project(session_id)?
+            .ok_or_else(|| Error::NotFound(format!("session {}", session_id)))?;
+        let redact = |text: &str| match &options.redaction {
+            Some(policy) => policy.apply(text),
+            None => text.to_string(),
+        };
+
+        let messages = self
+            .get_messages(session_id)?
+            .into_iter()
+            .filter(|m| !m.sidechain)
+            .filter(|m| match range {
+                Some((start, end)) => (start..=end).contains(&m.sequence),
+                None => true,
+            })
+            .filter_map(|m| share_message(m, options.tool_messages))
+            .map(|m| SharedMessage {
+                content: redact(&m.content),
+                ..m
+            })
+            .collect();
+
+        Ok(ShareBundle {
+            schema_version: SHARE_BUNDLE_SCHEMA_VERSION,
+            created_at: current_time_ms(),
+            session_id: session.session_id,
+            source: session.source,
+            project: SharedProject {
+                name: redact(&session.project_name),
+                path: match options.project_context {
+                    ProjectContext::NameOnly => None,
+                    ProjectContext::FullPath => Some(redact(&session.project_path)),
+                },
+            },
+            range,
+            tool_messages: options.tool_messages,
+            redacted: options.redaction.is_some(),
+            messages,
+        })
+    }
+}
+
+/// 工具消息：tool 类型，或只有工具调用、没有对话文本的消息
+fn is_tool_message(message: &Message) -> bool {
+    message.r#type == MessageType::Tool
+        || (message.tool_name.is_some() && message.content_text.trim().is_empty())
+}
+
+/// 按工具消息处理方式转换消息（省略时返回 None）
+fn share_message(message: Message, mode: ToolMessageMode) -> Option<SharedMessage> {
+    let is_tool = is_tool_message(&message);
+    let (content, summarized) = match mode {
+        ToolMessageMode::Full => (message.content_full.clone(), false),
+        ToolMessageMode::Omitted if is_tool => return None,
+        ToolMessageMode::Summarized if is_tool => (summarize_tool_message(&message), true),
+        // 对话消息只保留纯文本（不含工具调用框架）
+        _ if message.content_text.trim().is_empty() => (message.content_full.clone(), false),
+        _ => (message.content_text.clone(), false),
+    };
+    Some(SharedMessage {
+        uuid: message.uuid,
+        r#type: message.r#type,
+        content,
+        timestamp: message.timestamp,
+        sequence: message.sequence,
+        model: message.model,
+        tool_name: message.tool_name,
+        summarized,
+    })
+}
+
+/// 工具消息的一行摘要：`[tool: 名称] 首行（N lines）`
+fn summarize_tool_message(message: &Message) -> String {
+    let name = message.tool_name.as_deref().unwrap_or("tool");
+    let body = if message.content_text.trim().is_empty() {
+        &message.content_full
+    } else {
+        &message.content_text
+    };
+    let lines = body.lines().count();
+    let first_line = body
+        .lines()
+        .find(|l| !l.trim().is_empty())
+        .unwrap_or("")
+        .trim();
+    let mut summary: String = first_line.chars().take(TOOL_SUMMARY_MAX_CHARS).collect();
+    if first_line.chars().count() > TOOL_SUMMARY_MAX_CHARS {
+        summary.push('…');
+    }
+    format!("[tool: {}] {} ({} lines)", name, summary, lines)
+}
+
+/// 生成分享包的 Markdown
+pub fn render_share_bundle_markdown(bundle: &ShareBundle) -> String {
+    let mut out = format!("# {}\n\n", bundle.project.name);
+    if let Some(path) = &bundle.project.path {
+        out.push_str(&format!("- Project: `{}`\n", path));
+    }
+    out.push_str(&format!("- Session: `{}`\n", bundle.session_id));
+    if let Some((start, end)) = bundle.range {
+        out.push_str(&format!("- Messages: {}–{}\n", start, end));
+    }
+    out.push_str(&format!(
+        "- Shared: {}\n",
+        format_timestamp(bundle.created_at)
+    ));
+    if bundle.redacted {
+        out.push_str("- Redacted\n");
+    }
+
+    for message in &bundle.messages {
+        let role = match message.r#type {
+            MessageType::User => "User",
+            MessageType::Assistant => "Assistant",
+            MessageType::Tool => "Tool",
+            MessageType::System => "System",
+        };
+        out.push_str(&format!(
+            "\n---\n\n### {} · #{} · {}\n\n",
+            role,
+            message.sequence,
+            format_timestamp(message.timestamp)
+        ));
+        if message.summarized {
+            out.push_str(&format!("> {}\n", message.content));
+        } else {
+            out.push_str(message.content.trim_end());
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn format_timestamp(ms: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(ms)
+        .map(|dt| dt.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_default()
+}
+
+/// 导入分享包，返回导入后的会话 ID
+///
+/// - into_project: 归入的已有项目；None 时归入按原项目名创建的 `shared://` 项目
+///
+/// 会话和消息 ID 由分享包确定性生成，重复导入同一分享包只跳过已有消息；
+/// 与接收方已有的原会话互不影响。
+pub fn import_share_bundle(
+    db: &SessionDB,
+    bundle: &ShareBundle,
+    into_project: Option<i64>,
+) -> Result<String> {
+    if bundle.schema_version > SHARE_BUNDLE_SCHEMA_VERSION {
+        return Err(Error::Unsupported(format!(
+            "share bundle schema version {}",
+            bundle.schema_version
+        )));
+    }
+
+    let project_id = match into_project {
+        Some(id) => {
+            db.get_project(id)?
+                .ok_or_else(|| Error::NotFound(format!("project {}", id)))?
+                .id
+        }
+        None => db.get_or_create_project(
+            &bundle.project.name,
+            &format!("{}://{}", SHARED_SOURCE, bundle.project.name),
+            SHARED_SOURCE,
+        )?,
+    };
+
+    let key = format!(
+        "{}:{}:{:?}",
+        bundle.session_id, bundle.created_at, bundle.range
+    );
+    let session_id = Uuid::new_v5(&SHARE_NAMESPACE, key.as_bytes()).to_string();
+    let meta = serde_json::json!({
+        "shared": {
+            "sessionId": bundle.session_id,
+            "createdAt": bundle.created_at,
+            "range": bundle.range,
+            "source": bundle.source,
+        }
+    });
+    db.upsert_session_full(&SessionInput {
+        session_id: session_id.clone(),
+        project_id,
+        source: Some(SHARED_SOURCE.to_string()),
+        meta: Some(meta.to_string()),
+        ..Default::default()
+    })?;
+
+    let messages: Vec<MessageInput> = bundle
+        .messages
+        .iter()
+        .map(|m| MessageInput {
+            uuid: Uuid::new_v5(
+                &SHARE_NAMESPACE,
+                format!("{}:{}", session_id, m.uuid).as_bytes(),
+            )
+            .to_string(),
+            r#type: m.r#type,
+            content_text: m.content.clone(),
+            content_full: m.content.clone(),
+            timestamp: m.timestamp,
+            sequence: m.sequence,
+            source: Some(SHARED_SOURCE.to_string()),
+            channel: None,
+            model: m.model.clone(),
+            tool_call_id: None,
+            tool_name: m.tool_name.clone(),
+            tool_args: None,
+            raw: None,
+            approval_status: None,
+            approval_resolved_at: None,
+        })
+        .collect();
+    db.insert_messages(&session_id, &messages)?;
+    Ok(session_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RedactionPolicy {
+        RedactionPolicy {
+            words: vec!["Acme".to_string()],
+            home_dir: Some(PathBuf::from("/Users/alice")),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_redaction_policy() {
+        let text = "key sk-ant-REDACTED in /Users/alice/acme-app for ACME";
+        assert_eq!(
+            policy().apply(text),
+            "key [REDACTED] in ~/[REDACTED]-app for [REDACTED]"
+        );
+    }
+
+    #[test]
+    fn test_short_or_embedded_prefixes_are_kept() {
+        let policy = RedactionPolicy {
+            home_dir: None,
+            ..Default::default()
+        };
+        assert_eq!(policy.apply("use task-runner"), "use task-runner");
+        assert_eq!(
+            policy.apply("ask-abcdefghijklmnopqrstuvwxyz"),
+            "ask-abcdefghijklmnopqrstuvwxyz"
+        );
+        assert_eq!(
+            policy.apply("token=ghp_0123456789abcdefABCDEF."),
+            "token=[REDACTED]."
+        );
+    }
+
+    #[test]
+    fn test_newer_schema_version_is_rejected() {
+        let json = r#"{"schemaVersion":99,"createdAt":0,"sessionId":"s","project":{"name":"p"},
+            "toolMessages":"full","redacted":false,"messages":[]}"#;
+        assert!(matches!(
+            ShareBundle::from_json(json),
+            Err(Error::Unsupported(_))
+        ));
+    }
+}
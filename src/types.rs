@@ -1,12 +1,17 @@
 //! 数据类型定义
 
 use ai_cli_session_collector::MessageType;
+use chrono::{
+    DateTime, Datelike, Days, FixedOffset, Local, Months, NaiveDate, NaiveTime, Offset, TimeZone,
+    Utc,
+};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt;
 use std::str::FromStr;
 
 /// 审批状态
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum ApprovalStatus {
     Pending,
@@ -40,6 +45,138 @@ impl fmt::Display for ApprovalStatus {
     }
 }
 
+/// 消息的向量索引状态（`messages.vector_indexed` 列）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexState {
+    /// 待索引（0）
+    Pending,
+    /// 已索引（1）
+    Indexed,
+    /// 索引失败（-1）
+    Failed,
+}
+
+impl IndexState {
+    /// `vector_indexed` 列的取值
+    pub fn as_db_value(self) -> i64 {
+        match self {
+            IndexState::Pending => 0,
+            IndexState::Indexed => 1,
+            IndexState::Failed => -1,
+        }
+    }
+}
+
+/// 采集忽略规则类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IgnoreKind {
+    /// glob 匹配项目路径（也匹配会话文件路径）
+    PathGlob,
+    /// 精确匹配项目路径
+    ProjectPath,
+    /// 会话 ID 前缀
+    SessionPrefix,
+}
+
+impl FromStr for IgnoreKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "path_glob" => Ok(IgnoreKind::PathGlob),
+            "project_path" => Ok(IgnoreKind::ProjectPath),
+            "session_prefix" => Ok(IgnoreKind::SessionPrefix),
+            _ => Err(format!("Invalid ignore kind: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for IgnoreKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IgnoreKind::PathGlob => write!(f, "path_glob"),
+            IgnoreKind::ProjectPath => write!(f, "project_path"),
+            IgnoreKind::SessionPrefix => write!(f, "session_prefix"),
+        }
+    }
+}
+
+/// 采集忽略规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionIgnore {
+    pub id: i64,
+    pub pattern: String,
+    pub kind: IgnoreKind,
+    pub created_at: i64,
+}
+
+/// 采集锁状态
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionLock {
+    pub holder: String,
+    pub acquired_at: i64,
+    pub heartbeat_at: i64,
+}
+
+/// 采集连续失败的会话
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectFailure {
+    pub session_id: String,
+    pub path: String,
+    pub failures: i64,
+    pub last_failure_at: i64,
+    pub last_error: String,
+}
+
+/// 按项目汇总的待审批消息（通知摘要，见 `SessionDB::pending_approval_digest`）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApprovalDigestGroup {
+    pub project_id: i64,
+    pub project_name: String,
+    pub count: i64,
+    /// 最早的待审批消息时间（毫秒）
+    pub oldest_at: i64,
+    /// 最新的待审批消息时间（毫秒）
+    pub newest_at: i64,
+    /// tool_name → 待审批数量（没有 tool_name 的消息计入空字符串）
+    pub tools: BTreeMap<String, i64>,
+}
+
+/// claude.ai 数据导出的导入结果（见 `importer::import_claude_export`）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    /// 导出中的对话数
+    pub conversations: usize,
+    /// 没有 uuid 或没有可导入消息的对话数
+    pub conversations_skipped: usize,
+    pub sessions_created: usize,
+    pub messages_created: usize,
+    /// 已导入过（uuid 已存在）而跳过的消息数
+    pub messages_skipped: usize,
+}
+
+/// 恢复增量读取所需的信息（见 `SessionDB::get_session_resume_info`）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResumeInfo {
+    /// 已读取到的文件偏移量（字节）
+    pub file_offset: i64,
+    pub file_mtime: Option<i64>,
+    pub file_size: Option<i64>,
+    pub file_inode: Option<i64>,
+    /// 最近一次采集时的会话文件路径（该列加入前采集的会话为空）
+    pub session_path: Option<String>,
+    pub encoded_dir_name: Option<String>,
+    pub project_path: String,
+}
+
 /// 项目
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Project {
@@ -111,6 +248,13 @@ pub struct Message {
     pub vector_indexed: bool,                    // 是否已向量索引
     pub approval_status: Option<ApprovalStatus>, // 审批状态: pending, approved, rejected, timeout
     pub approval_resolved_at: Option<i64>,       // 审批解决时间戳（毫秒）
+    pub truncated: bool,                         // 内容是否因超过 max_content_bytes 被截断
+    /// 是否为 sidechain 消息（Claude isSidechain）
+    #[serde(default)]
+    pub sidechain: bool,
+    /// 是否为 compact 摘要条目（type 为 system）
+    #[serde(default)]
+    pub is_summary: bool,
 }
 
 // MessageType 直接使用 ai_cli_session_collector::MessageType，在 lib.rs 中 re-export
@@ -128,6 +272,27 @@ pub enum SearchOrderBy {
     TimeAsc,
 }
 
+/// 全文搜索匹配的消息字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchField {
+    /// 完整内容，含工具调用 / 结果的格式化文本（默认）
+    #[default]
+    ContentFull,
+    /// 纯对话文本，不匹配工具框架中的内容
+    ContentText,
+}
+
+impl SearchField {
+    /// messages 表（以及 messages_fts）中的列名
+    pub fn column(self) -> &'static str {
+        match self {
+            SearchField::ContentFull => "content_full",
+            SearchField::ContentText => "content_text",
+        }
+    }
+}
+
 /// 搜索结果
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchResult {
@@ -142,6 +307,292 @@ pub struct SearchResult {
     pub timestamp: Option<i64>,
 }
 
+impl SearchResult {
+    /// 不含正文的命中（只保留 snippet，正文按 message_id 另取）
+    pub fn without_content(self) -> Self {
+        Self {
+            content_full: String::new(),
+            ..self
+        }
+    }
+}
+
+/// 分组搜索选项
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SearchGroupOptions {
+    /// 项目 ID 过滤
+    pub project_id: Option<i64>,
+    /// 排序方式：Score 按会话最佳命中分数，TimeDesc/TimeAsc 按会话最新/最早命中时间
+    pub order_by: SearchOrderBy,
+    /// 开始时间戳（毫秒）
+    pub start_timestamp: Option<i64>,
+    /// 结束时间戳（毫秒）
+    pub end_timestamp: Option<i64>,
+    /// 是否返回命中消息的 content_full
+    ///
+    /// false 时 content_full 为空字符串，调用方按 message_id 用 `get_messages_by_ids` 取正文。
+    /// None 时库 API 返回正文，Agent 协议的 Search 查询不返回（见 `QueryType::Search`）。
+    pub include_content: Option<bool>,
+    /// 匹配的字段（snippet 取自该字段），默认 content_full
+    pub field: SearchField,
+    /// 相对日期范围（如 "last7days"），与 start/end_timestamp 同时设置时取交集
+    pub date_range: Option<DateRangeSpec>,
+    /// 计算 `date_range` 使用的时区（相对 UTC 的分钟偏移），None 时使用系统时区
+    pub tz_offset_minutes: Option<i32>,
+    /// `date_range` 为 thisWeek 时一周是否从周一开始（默认周日）
+    pub week_starts_monday: bool,
+}
+
+impl SearchGroupOptions {
+    /// 实际生效的时间范围（毫秒，闭区间）：`date_range` 解析后与 start/end_timestamp 取交集
+    pub fn time_bounds(&self) -> (Option<i64>, Option<i64>) {
+        let Some(spec) = self.date_range else {
+            return (self.start_timestamp, self.end_timestamp);
+        };
+        // 无法解析的范围（月份越界）不匹配任何消息
+        let Some(range) = spec.resolve(self.tz_offset_minutes, self.week_starts_monday) else {
+            return (Some(0), Some(-1));
+        };
+        let start = self
+            .start_timestamp
+            .map_or(range.start_ms, |s| s.max(range.start_ms));
+        let end = self
+            .end_timestamp
+            .map_or(range.end_ms, |e| e.min(range.end_ms));
+        (Some(start), Some(end))
+    }
+}
+
+/// 时间范围（毫秒，闭区间），边界为本地日期的零点
+///
+/// 不带 `_in` 的构造函数使用固定时区偏移（`tz_offset_minutes`，东八区为 480）；
+/// 需要夏令时的调用方用 `_in` 版本传入带时区的当前时间（如 `chrono::Local::now()`）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DateRange {
+    /// 开始时间（含）
+    pub start_ms: i64,
+    /// 结束时间（含），即下一个边界的前一毫秒
+    pub end_ms: i64,
+}
+
+impl DateRange {
+    /// 今天
+    pub fn today(tz_offset_minutes: i32) -> Self {
+        Self::today_in(&now_with_offset(tz_offset_minutes))
+    }
+
+    /// 含今天在内的最近 n 天（n 为 0 时按 1）
+    pub fn last_n_days(n: u32, tz_offset_minutes: i32) -> Self {
+        Self::last_n_days_in(n, &now_with_offset(tz_offset_minutes))
+    }
+
+    /// 本周
+    pub fn this_week(tz_offset_minutes: i32, week_starts_monday: bool) -> Self {
+        Self::this_week_in(&now_with_offset(tz_offset_minutes), week_starts_monday)
+    }
+
+    /// 指定月份（month 为 1-12，越界时返回 None）
+    pub fn month(year: i32, month: u32, tz_offset_minutes: i32) -> Option<Self> {
+        Self::month_in(year, month, &fixed_offset(tz_offset_minutes))
+    }
+
+    /// `now` 所在的日期
+    pub fn today_in<Tz: TimeZone>(now: &DateTime<Tz>) -> Self {
+        Self::last_n_days_in(1, now)
+    }
+
+    /// 截止 `now` 所在日期（含）的最近 n 天
+    pub fn last_n_days_in<Tz: TimeZone>(n: u32, now: &DateTime<Tz>) -> Self {
+        let today = now.date_naive();
+        let first = today - Days::new(u64::from(n.max(1) - 1));
+        Self::between(&now.timezone(), first, today + Days::new(1))
+    }
+
+    /// `now` 所在的一周
+    pub fn this_week_in<Tz: TimeZone>(now: &DateTime<Tz>, week_starts_monday: bool) -> Self {
+        let today = now.date_naive();
+        let weekday = today.weekday();
+        let elapsed = if week_starts_monday {
+            weekday.num_days_from_monday()
+        } else {
+            weekday.num_days_from_sunday()
+        };
+        let first = today - Days::new(u64::from(elapsed));
+        Self::between(&now.timezone(), first, first + Days::new(7))
+    }
+
+    /// `tz` 时区的指定月份
+    pub fn month_in<Tz: TimeZone>(year: i32, month: u32, tz: &Tz) -> Option<Self> {
+        let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+        let next = first.checked_add_months(Months::new(1))?;
+        Some(Self::between(tz, first, next))
+    }
+
+    /// 时间戳是否在范围内
+    pub fn contains(&self, timestamp_ms: i64) -> bool {
+        (self.start_ms..=self.end_ms).contains(&timestamp_ms)
+    }
+
+    /// 从 `first` 的零点到 `next` 的零点之前
+    fn between<Tz: TimeZone>(tz: &Tz, first: NaiveDate, next: NaiveDate) -> Self {
+        Self {
+            start_ms: local_midnight_ms(tz, first),
+            end_ms: local_midnight_ms(tz, next) - 1,
+        }
+    }
+}
+
+/// 固定偏移的时区（超出 ±24 小时按 UTC）
+fn fixed_offset(tz_offset_minutes: i32) -> FixedOffset {
+    FixedOffset::east_opt(tz_offset_minutes.saturating_mul(60)).unwrap_or_else(|| Utc.fix())
+}
+
+fn now_with_offset(tz_offset_minutes: i32) -> DateTime<FixedOffset> {
+    Utc::now().with_timezone(&fixed_offset(tz_offset_minutes))
+}
+
+/// 本地日期零点的时间戳（毫秒）
+///
+/// 零点重复（夏令时结束）时取较早的时刻；零点不存在（夏令时开始于零点）时
+/// 取当天第一个存在的时刻。
+fn local_midnight_ms<Tz: TimeZone>(tz: &Tz, date: NaiveDate) -> i64 {
+    let midnight = date.and_time(NaiveTime::MIN);
+    let mut local = midnight;
+    while local.date() == date {
+        if let Some(dt) = tz.from_local_datetime(&local).earliest() {
+            return dt.timestamp_millis();
+        }
+        local += chrono::Duration::minutes(15);
+    }
+    midnight.and_utc().timestamp_millis()
+}
+
+/// 相对当前时间的日期范围，协议和 FFI 使用其字符串形式：
+/// `today`、`last{N}days`（如 `last7days`）、`thisWeek`、`thisMonth`、`YYYY-MM`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum DateRangeSpec {
+    Today,
+    /// 含今天在内的最近 N 天（N ≥ 1）
+    LastNDays(u32),
+    ThisWeek,
+    ThisMonth,
+    Month {
+        year: i32,
+        month: u32,
+    },
+}
+
+impl DateRangeSpec {
+    /// 按当前时间计算范围
+    ///
+    /// - tz_offset_minutes: 相对 UTC 的分钟偏移，None 时使用系统时区（含夏令时）
+    ///
+    /// 月份越界时返回 None。
+    pub fn resolve(
+        &self,
+        tz_offset_minutes: Option<i32>,
+        week_starts_monday: bool,
+    ) -> Option<DateRange> {
+        match tz_offset_minutes {
+            Some(offset) => self.resolve_at(&now_with_offset(offset), week_starts_monday),
+            None => self.resolve_at(&Local::now(), week_starts_monday),
+        }
+    }
+
+    /// 以 `now` 及其时区计算范围
+    pub fn resolve_at<Tz: TimeZone>(
+        &self,
+        now: &DateTime<Tz>,
+        week_starts_monday: bool,
+    ) -> Option<DateRange> {
+        match *self {
+            Self::Today => Some(DateRange::today_in(now)),
+            Self::LastNDays(n) => Some(DateRange::last_n_days_in(n, now)),
+            Self::ThisWeek => Some(DateRange::this_week_in(now, week_starts_monday)),
+            Self::ThisMonth => {
+                let today = now.date_naive();
+                DateRange::month_in(today.year(), today.month(), &now.timezone())
+            }
+            Self::Month { year, month } => DateRange::month_in(year, month, &now.timezone()),
+        }
+    }
+}
+
+impl fmt::Display for DateRangeSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Today => write!(f, "today"),
+            Self::LastNDays(n) => write!(f, "last{}days", n),
+            Self::ThisWeek => write!(f, "thisWeek"),
+            Self::ThisMonth => write!(f, "thisMonth"),
+            Self::Month { year, month } => write!(f, "{:04}-{:02}", year, month),
+        }
+    }
+}
+
+impl FromStr for DateRangeSpec {
+    type Err = String;
+
+    /// 不区分大小写
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.trim().to_ascii_lowercase();
+        match lower.as_str() {
+            "today" => return Ok(Self::Today),
+            "thisweek" => return Ok(Self::ThisWeek),
+            "thismonth" => return Ok(Self::ThisMonth),
+            _ => {}
+        }
+        if let Some(n) = lower
+            .strip_prefix("last")
+            .and_then(|rest| rest.strip_suffix("days"))
+        {
+            return match n.parse::<u32>() {
+                Ok(n) if n >= 1 => Ok(Self::LastNDays(n)),
+                _ => Err(format!("Invalid date range: {}", s)),
+            };
+        }
+        if let Some((year, month)) = lower.split_once('-') {
+            if let (Ok(year), Ok(month)) = (year.parse::<i32>(), month.parse::<u32>()) {
+                if (1..=12).contains(&month) {
+                    return Ok(Self::Month { year, month });
+                }
+            }
+        }
+        Err(format!("Invalid date range: {}", s))
+    }
+}
+
+impl TryFrom<String> for DateRangeSpec {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<DateRangeSpec> for String {
+    fn from(spec: DateRangeSpec) -> Self {
+        spec.to_string()
+    }
+}
+
+/// 按会话分组的搜索结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionSearchGroup {
+    /// 会话摘要
+    pub session: SessionWithProject,
+    /// 该会话的命中总数
+    pub hit_count: i64,
+    /// 最佳命中分数（bm25，越小越相关）
+    pub best_score: f64,
+    /// 排名靠前的命中（最多 per_session_limit 条）
+    pub hits: Vec<SearchResult>,
+}
+
 /// 统计信息
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Stats {
@@ -150,6 +601,53 @@ pub struct Stats {
     pub message_count: i64,
 }
 
+/// 历史数据总量（首次全量采集完成时随 `Push::CollectFinished` 推送）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryTotals {
+    pub project_count: i64,
+    pub session_count: i64,
+    pub message_count: i64,
+    /// 最早的消息时间（毫秒时间戳，没有消息时为空）
+    pub oldest_message_at: Option<i64>,
+    /// 最新的消息时间（毫秒时间戳，没有消息时为空）
+    pub newest_message_at: Option<i64>,
+}
+
+/// 单个数据源的历史数据量（按项目的 source 归类）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceHistory {
+    pub source: String,
+    pub project_count: i64,
+    pub session_count: i64,
+    pub message_count: i64,
+}
+
+/// 单个年份的历史数据量（按消息时间的 UTC 年份归类）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YearHistory {
+    pub year: i32,
+    /// 该年有消息的会话数（跨年的会话在每一年都计入）
+    pub session_count: i64,
+    pub message_count: i64,
+}
+
+/// 历史数据概览（首次采集后的引导页统计）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryOverview {
+    #[serde(flatten)]
+    pub totals: HistoryTotals,
+    /// 按消息数倒序
+    pub by_source: Vec<SourceHistory>,
+    /// 按年份升序
+    pub by_year: Vec<YearHistory>,
+    /// 消息数最多的项目（不含已忽略的项目），`session_count` / `message_count` 按实际入库的数据统计
+    pub top_projects: Vec<ProjectWithStats>,
+}
+
 /// 项目（带统计信息）
 /// 使用 camelCase 序列化，与 JSON API 标准保持一致
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -201,6 +699,30 @@ pub struct SessionWithProject {
     // Continuation chain 导航（V7）
     pub continuation_prev_id: Option<String>,
     pub continuation_next_ids: Option<Vec<String>>,
+    // 消息统计（仅 get_session_with_metrics 计算）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<SessionMessageMetrics>,
+}
+
+/// 会话的消息统计（按已入库的消息计算）
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionMessageMetrics {
+    pub message_count: i64,
+    pub user_message_count: i64,
+    pub assistant_message_count: i64,
+    /// 最早的消息时间（毫秒时间戳）
+    pub first_message_at: Option<i64>,
+    /// 最新的消息时间（毫秒时间戳）
+    pub last_message_at: Option<i64>,
+    /// 首末消息的时间跨度（毫秒）
+    pub duration_ms: Option<i64>,
+    /// sidechain 消息数（不计入用户/助手消息数）
+    #[serde(default)]
+    pub sidechain_message_count: i64,
+    /// compact 摘要条目数
+    #[serde(default)]
+    pub summary_count: i64,
 }
 
 /// Talk 摘要 (Compact 结果)
@@ -212,10 +734,26 @@ pub struct TalkSummary {
     pub talk_id: String,
     pub summary_l2: String,         // L2 摘要（每个 Talk 的摘要）
     pub summary_l3: Option<String>, // L3 摘要（Session 级别汇总）
+    pub position: Option<i64>,      // Talk 在会话中的序号
+    pub first_message_sequence: Option<i64>, // Talk 覆盖的第一条消息 sequence
     pub created_at: i64,
     pub updated_at: i64,
 }
 
+/// 对话轮次摘要（一条用户消息及其后的助手/工具消息）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TurnSummary {
+    pub turn_index: i64,
+    pub user_preview: Option<String>,      // 第一条用户消息（前 100 字符）
+    pub assistant_preview: Option<String>, // 最后一条非空助手消息（前 100 字符）
+    pub tool_count: i64,
+    pub message_count: i64,
+    pub start_timestamp: i64,
+    pub end_timestamp: i64,
+    pub pending_approval_count: i64,
+}
+
 /// Talk FTS 搜索结果
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -243,6 +781,75 @@ pub struct SessionRelation {
     pub created_at: i64,
 }
 
+/// 会话 fork 树（session_relations 的传递闭包）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionTree {
+    pub session_id: String,
+    /// 与父节点的关系（根节点为 None）
+    pub relation: Option<SessionRelation>,
+    pub children: Vec<SessionTree>,
+    /// 达到 max_depth 后仍有未展开的子会话
+    pub truncated: bool,
+}
+
+/// 消息修订记录（重采集时 uuid 已存在但内容变化）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageRevision {
+    pub id: i64,
+    pub session_id: String,
+    pub uuid: String,
+    pub old_hash: String,        // 数据库中 content_full 的哈希
+    pub new_hash: String,        // 磁盘上 content_full 的哈希
+    pub new_raw: Option<String>, // 磁盘上的原始 JSONL
+    pub applied: bool,           // 是否已覆盖写入 messages
+    pub detected_at: i64,
+}
+
+/// 向量墓碑（已向量索引的消息被删除，需从外部向量库移除）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VectorTombstone {
+    pub id: i64,
+    pub uuid: String,
+    pub message_id: i64,
+    pub deleted_at: i64,
+}
+
+/// 变更状态摘要（会话 / 项目 / 全局范围）
+///
+/// `state_hash` 覆盖消息数、最大 sequence、最大消息 ID 和修订数，
+/// 任一变化都会改变哈希，用于 WaitForChange 判断是否有新变更。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeState {
+    pub message_count: i64,
+    pub max_sequence: Option<i64>,
+    pub state_hash: u64,
+}
+
+/// 会话实时活跃状态（由 Agent 根据文件事件推导）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SessionActivityState {
+    /// 最近几秒内有文件写入（正在生成）
+    Streaming,
+    /// 2 分钟内有文件写入
+    RecentlyActive,
+    /// 空闲
+    Idle,
+}
+
+/// 会话活跃状态
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionActivity {
+    pub session_id: String,
+    pub state: SessionActivityState,
+    /// 最近一次文件事件时间（毫秒），Agent 启动后没有事件时为 None
+    pub last_event_ms: Option<i64>,
+}
+
 /// Continuation Chain（/continue 产生的会话接续链）
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -263,3 +870,207 @@ pub struct ChainNode {
     pub depth: i32,
     pub created_at: i64,
 }
+
+/// 分页 token（不透明字符串）
+///
+/// 由列表 API 的 `*_page` 变体在 `Page::next_page_token` 中返回，原样传回即可取下一页。
+/// 内部记录分页方式（偏移或键集）和签发的 API，只能用于签发它的 API 和同一查询，
+/// 否则返回 `Error::InvalidPageToken`。
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PageToken(pub(crate) String);
+
+impl PageToken {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for PageToken {
+    fn from(token: String) -> Self {
+        Self(token)
+    }
+}
+
+impl fmt::Display for PageToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// 一页列表结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// 下一页 token，为 None 表示已是最后一页
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_page_token: Option<PageToken>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{LocalResult, NaiveDateTime};
+
+    /// 2024 年的美国东部时间：3 月 10 日 02:00 EST 起为 EDT，11 月 3 日 02:00 EDT 起为 EST
+    #[derive(Debug, Clone, Copy)]
+    struct Eastern2024;
+
+    const EST: i32 = -5 * 3600;
+    const EDT: i32 = -4 * 3600;
+
+    fn naive(y: i32, m: u32, d: u32, h: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, 0, 0)
+            .unwrap()
+    }
+
+    fn offset(secs: i32) -> FixedOffset {
+        FixedOffset::east_opt(secs).unwrap()
+    }
+
+    impl TimeZone for Eastern2024 {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self {
+            Eastern2024
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            if *local < naive(2024, 3, 10, 2) {
+                LocalResult::Single(offset(EST))
+            } else if *local < naive(2024, 3, 10, 3) {
+                LocalResult::None
+            } else if *local < naive(2024, 11, 3, 1) {
+                LocalResult::Single(offset(EDT))
+            } else if *local < naive(2024, 11, 3, 2) {
+                LocalResult::Ambiguous(offset(EDT), offset(EST))
+            } else {
+                LocalResult::Single(offset(EST))
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            if *utc >= naive(2024, 3, 10, 7) && *utc < naive(2024, 11, 3, 6) {
+                offset(EDT)
+            } else {
+                offset(EST)
+            }
+        }
+    }
+
+    fn utc_ms(y: i32, m: u32, d: u32, h: u32) -> i64 {
+        naive(y, m, d, h).and_utc().timestamp_millis()
+    }
+
+    fn eastern_noon(m: u32, d: u32) -> DateTime<Eastern2024> {
+        Eastern2024
+            .from_local_datetime(&naive(2024, m, d, 12))
+            .unwrap()
+    }
+
+    const HOUR: i64 = 3600 * 1000;
+
+    #[test]
+    fn test_day_across_dst_start_is_23_hours() {
+        let range = DateRange::today_in(&eastern_noon(3, 10));
+        assert_eq!(range.start_ms, utc_ms(2024, 3, 10, 5));
+        assert_eq!(range.end_ms, utc_ms(2024, 3, 11, 4) - 1);
+        assert_eq!(range.end_ms + 1 - range.start_ms, 23 * HOUR);
+    }
+
+    #[test]
+    fn test_day_across_dst_end_is_25_hours() {
+        let range = DateRange::today_in(&eastern_noon(11, 3));
+        assert_eq!(range.start_ms, utc_ms(2024, 11, 3, 4));
+        assert_eq!(range.end_ms + 1 - range.start_ms, 25 * HOUR);
+    }
+
+    #[test]
+    fn test_week_containing_dst_start() {
+        // 2024-03-12 是周二
+        let now = eastern_noon(3, 12);
+
+        let sunday = DateRange::this_week_in(&now, false);
+        assert_eq!(sunday.start_ms, utc_ms(2024, 3, 10, 5));
+        assert_eq!(sunday.end_ms, utc_ms(2024, 3, 17, 4) - 1);
+
+        let monday = DateRange::this_week_in(&now, true);
+        assert_eq!(monday.start_ms, utc_ms(2024, 3, 11, 4));
+        assert_eq!(monday.end_ms, utc_ms(2024, 3, 18, 4) - 1);
+
+        // 最近 7 天从 3 月 6 日（EST）零点开始
+        let last7 = DateRange::last_n_days_in(7, &now);
+        assert_eq!(last7.start_ms, utc_ms(2024, 3, 6, 5));
+        assert_eq!(last7.end_ms, utc_ms(2024, 3, 13, 4) - 1);
+    }
+
+    #[test]
+    fn test_leap_day() {
+        let february = DateRange::month(2024, 2, 0).unwrap();
+        assert_eq!(february.start_ms, utc_ms(2024, 2, 1, 0));
+        assert_eq!(february.end_ms, utc_ms(2024, 3, 1, 0) - 1);
+        assert!(february.contains(utc_ms(2024, 2, 29, 23)));
+        assert_eq!(
+            DateRange::month(2023, 2, 0).unwrap().end_ms,
+            utc_ms(2023, 3, 1, 0) - 1
+        );
+
+        // 东八区 3 月 1 日上午，最近 2 天包含 2 月 29 日
+        let tz = offset(8 * 3600);
+        let now = tz.from_local_datetime(&naive(2024, 3, 1, 9)).unwrap();
+        let range = DateRange::last_n_days_in(2, &now);
+        assert_eq!(range.start_ms, utc_ms(2024, 2, 28, 16));
+        assert_eq!(range.end_ms, utc_ms(2024, 3, 1, 16) - 1);
+
+        assert_eq!(DateRange::month(2024, 13, 0), None);
+    }
+
+    #[test]
+    fn test_date_range_spec_strings() {
+        for (text, spec) in [
+            ("today", DateRangeSpec::Today),
+            ("last7days", DateRangeSpec::LastNDays(7)),
+            ("thisWeek", DateRangeSpec::ThisWeek),
+            ("thisMonth", DateRangeSpec::ThisMonth),
+            (
+                "2024-02",
+                DateRangeSpec::Month {
+                    year: 2024,
+                    month: 2,
+                },
+            ),
+        ] {
+            assert_eq!(text.parse::<DateRangeSpec>().unwrap(), spec);
+            assert_eq!(spec.to_string(), text);
+            let json = serde_json::to_string(&spec).unwrap();
+            assert_eq!(json, format!("\"{}\"", text));
+        }
+        assert_eq!("Last30Days".parse(), Ok(DateRangeSpec::LastNDays(30)));
+        for invalid in ["last0days", "lastweek", "2024-13", "yesterday"] {
+            assert!(invalid.parse::<DateRangeSpec>().is_err(), "{}", invalid);
+        }
+        assert!(serde_json::from_str::<DateRangeSpec>("\"soon\"").is_err());
+    }
+
+    #[test]
+    fn test_spec_resolves_in_time_zone() {
+        let now = eastern_noon(2, 29);
+        assert_eq!(
+            DateRangeSpec::ThisMonth.resolve_at(&now, false),
+            DateRange::month_in(2024, 2, &Eastern2024)
+        );
+        let today = DateRangeSpec::Today.resolve_at(&now, false).unwrap();
+        assert_eq!(today.start_ms, utc_ms(2024, 2, 29, 5));
+    }
+}
@@ -4,7 +4,7 @@
 
 use crate::db::{MessageInput, SessionDB};
 use crate::error::Result;
-use ai_cli_session_collector::MessageType;
+use ai_cli_session_collector::{MessageType, ParsedMessage};
 
 /// 安全边界时间 (毫秒)
 /// 增量扫描时回退的时间，防止边界消息丢失
@@ -27,6 +27,20 @@ impl SessionDB {
         session_id: &str,
         project_id: i64,
         messages: Vec<MessageInput>,
+    ) -> Result<usize> {
+        let inserted = self.scan_session_incremental_inner(session_id, project_id, messages)?;
+
+        #[cfg(feature = "paranoid")]
+        self.paranoid_check(session_id, true);
+
+        Ok(inserted)
+    }
+
+    fn scan_session_incremental_inner(
+        &self,
+        session_id: &str,
+        project_id: i64,
+        messages: Vec<MessageInput>,
     ) -> Result<usize> {
         // 确保 session 存在
         self.upsert_session(session_id, project_id)?;
@@ -66,15 +80,116 @@ impl SessionDB {
         // 写入
         let (inserted, _) = self.insert_messages(session_id, &messages_to_process)?;
 
-        // 更新检查点
-        if let Some(last) = messages_to_process.last() {
-            self.update_session_last_message(session_id, last.timestamp)?;
+        // 更新检查点（消息不一定按时间排序，取最新的时间）
+        if let Some(newest) = messages_to_process.iter().map(|m| m.timestamp).max() {
+            self.update_session_last_message(session_id, newest)?;
         }
 
         Ok(inserted)
     }
 }
 
+/// 采集过滤器
+///
+/// 在转换为 MessageInput 前跳过不需要入库的噪声条目（进度提示、快照记录、空消息等）。
+/// 默认只跳过 content_text 和 content_full 都为空的条目。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectionFilter {
+    /// 跳过 content_text 和 content_full 都为空的条目
+    pub skip_empty_messages: bool,
+    /// 跳过的条目类型（匹配原始 JSONL 的 `type` 字段或消息类型，如 "progress"）
+    pub skip_types: Vec<String>,
+    /// content_text 去除首尾空白后的最少字符数（0 表示不限制）
+    pub min_content_chars: usize,
+    /// 跳过的工具名
+    pub skip_tool_names: Vec<String>,
+}
+
+impl Default for CollectionFilter {
+    fn default() -> Self {
+        Self {
+            skip_empty_messages: true,
+            skip_types: Vec::new(),
+            min_content_chars: 0,
+            skip_tool_names: Vec::new(),
+        }
+    }
+}
+
+/// 条目被过滤的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// 内容为空
+    Empty,
+    /// 条目类型在 skip_types 中
+    Type,
+    /// 内容短于 min_content_chars
+    TooShort,
+    /// 工具名在 skip_tool_names 中
+    ToolName,
+}
+
+impl CollectionFilter {
+    /// 不过滤任何条目
+    pub fn disabled() -> Self {
+        Self {
+            skip_empty_messages: false,
+            ..Self::default()
+        }
+    }
+
+    /// 判断条目是否应被跳过，返回跳过原因
+    pub fn skip_reason(&self, msg: &ParsedMessage) -> Option<SkipReason> {
+        if self.skip_empty_messages && msg.content.text.is_empty() && msg.content.full.is_empty() {
+            return Some(SkipReason::Empty);
+        }
+
+        if !self.skip_types.is_empty() {
+            let message_type = msg.message_type.to_string();
+            let raw_type = msg.raw.as_deref().and_then(raw_entry_type);
+            if self
+                .skip_types
+                .iter()
+                .any(|t| *t == message_type || raw_type.as_deref() == Some(t.as_str()))
+            {
+                return Some(SkipReason::Type);
+            }
+        }
+
+        if let Some(tool_name) = &msg.tool_name {
+            if self.skip_tool_names.iter().any(|t| t == tool_name) {
+                return Some(SkipReason::ToolName);
+            }
+        }
+
+        if self.min_content_chars > 0
+            && msg.content.text.trim().chars().count() < self.min_content_chars
+        {
+            return Some(SkipReason::TooShort);
+        }
+
+        None
+    }
+
+    /// 判断条目是否应被跳过
+    pub fn should_skip(&self, msg: &ParsedMessage) -> bool {
+        self.skip_reason(msg).is_some()
+    }
+
+    /// 过滤条目，返回保留的条目和被跳过的数量
+    pub fn apply<'m>(&self, messages: &'m [ParsedMessage]) -> (Vec<&'m ParsedMessage>, usize) {
+        let kept: Vec<_> = messages.iter().filter(|m| !self.should_skip(m)).collect();
+        let skipped = messages.len() - kept.len();
+        (kept, skipped)
+    }
+}
+
+/// 提取原始 JSONL 条目的 `type` 字段
+fn raw_entry_type(raw: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(raw).ok()?;
+    value.get("type")?.as_str().map(|s| s.to_string())
+}
+
 /// 从 ai-cli-session-collector 消息转换为 MessageInput
 pub fn convert_message(
     msg: &ai_cli_session_collector::ParsedMessage,
@@ -137,3 +252,23 @@ pub fn convert_messages_with_start_sequence(
         .map(|(i, m)| convert_message(m, start_sequence + i as i64))
         .collect()
 }
+
+/// 过滤并批量转换消息
+///
+/// 被过滤的条目不占用 sequence，保留的消息 sequence 从 start_sequence 连续递增。
+///
+/// # 返回
+/// (转换后的 MessageInput 列表, 被过滤的条目数)
+pub fn convert_messages_filtered(
+    messages: &[ai_cli_session_collector::ParsedMessage],
+    start_sequence: i64,
+    filter: &CollectionFilter,
+) -> (Vec<MessageInput>, usize) {
+    let (kept, skipped) = filter.apply(messages);
+    let inputs = kept
+        .into_iter()
+        .enumerate()
+        .map(|(i, m)| convert_message(m, start_sequence + i as i64))
+        .collect();
+    (inputs, skipped)
+}
@@ -3,7 +3,16 @@
 #[cfg(feature = "agent")]
 mod tests {
     use ai_cli_session_db::agent::{Agent, AgentConfig};
-    use ai_cli_session_db::protocol::{HookEvent, Request, Response};
+    use ai_cli_session_db::config_file::ConfigFile;
+    use ai_cli_session_db::migrations::{MIN_WRITER_SCHEMA_KEY, SUPPORTED_SCHEMA_VERSION};
+    use ai_cli_session_db::protocol::{
+        error_code, ApprovalStatus, HookEvent, Push, QueryType, Request, Response,
+        MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+    };
+    use ai_cli_session_db::{
+        CollectLimits, CollectionFilter, DbConfig, MessageInput, MessageType, SearchLogEntry,
+        SearchLogInput, SessionDB,
+    };
     use std::sync::Arc;
     use std::time::Duration;
     use tempfile::tempdir;
@@ -17,6 +26,28 @@ mod tests {
         AgentConfig {
             data_dir: temp_dir.into_path(),
             idle_timeout_secs: 5,
+            max_waiters: 32,
+            max_connections: 256,
+            collection_filter: CollectionFilter::default(),
+            collect_limits: CollectLimits::default(),
+            streaming_window_secs: 5,
+            session_idle_secs: 120,
+            integrity_check_interval_secs: 0,
+            listen_fd: None,
+            max_content_bytes: None,
+            wal_autocheckpoint_pages: None,
+            log_searches: false,
+            search_log_cap: 1000,
+            source_databases: Default::default(),
+            max_rss_mb: None,
+            memory_hard_limit: false,
+            approval_digest_interval_secs: 0,
+            approval_digest_min_age_secs: 0,
+            status_file_interval_secs: 0,
+            replication_file: None,
+            replication_url: None,
+            replication_batch_size: 500,
+            replication_interval_secs: 30,
         }
     }
 
@@ -46,6 +77,7 @@ mod tests {
         let handshake = Request::Handshake {
             component: "test".to_string(),
             version: "1.0.0".to_string(),
+            protocol_version: PROTOCOL_VERSION,
         };
         let handshake_json = serde_json::to_string(&handshake).unwrap();
         writer
@@ -59,7 +91,7 @@ mod tests {
         let response: Response = serde_json::from_str(&line).unwrap();
 
         match response {
-            Response::HandshakeOk { agent_version } => {
+            Response::HandshakeOk { agent_version, .. } => {
                 assert!(!agent_version.is_empty());
             }
             _ => panic!("Expected HandshakeOk"),
@@ -73,6 +105,608 @@ mod tests {
         agent_handle.abort();
     }
 
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_agent_socket_and_pid_file_are_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let config = test_config();
+        let agent = Arc::new(Agent::new(config.clone()).unwrap());
+        let agent_handle = {
+            let agent = agent.clone();
+            tokio::spawn(async move {
+                agent.run().await.unwrap();
+            })
+        };
+        sleep(Duration::from_millis(500)).await;
+
+        for path in [config.socket_path(), config.pid_path()] {
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600, "{}", path.display());
+        }
+
+        agent_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_agent_writes_status_file() {
+        let config = AgentConfig {
+            status_file_interval_secs: 1,
+            ..test_config()
+        };
+        let status_path = config.status_file_path();
+        let agent = Arc::new(Agent::new(config.clone()).unwrap());
+        let agent_handle = {
+            let agent = agent.clone();
+            tokio::spawn(async move {
+                agent.run().await.unwrap();
+            })
+        };
+        // 状态文件在启动扫描完成后写入
+        for _ in 0..100 {
+            if status_path.exists() {
+                break;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+
+        let status: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&status_path).unwrap()).unwrap();
+        for key in [
+            "agent_version",
+            "uptime_secs",
+            "connections",
+            "last_collect",
+            "writer_role",
+        ] {
+            assert!(status.get(key).is_some(), "missing {}", key);
+        }
+        assert_eq!(status["pid"], std::process::id());
+        assert_eq!(status["writer_role"]["is_writer"], true);
+        // 启动扫描完成后写入的状态带有采集结果
+        assert_eq!(status["last_collect"]["trigger"], "startup");
+
+        // 临时文件已 rename，不会残留
+        assert!(!config.data_dir.join("agent.status.json.tmp").exists());
+
+        agent_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_agent_replicates_to_jsonl_file() {
+        let base = test_config();
+        let mirror = base.data_dir.join("mirror.jsonl");
+        let config = AgentConfig {
+            replication_file: Some(mirror.clone()),
+            replication_interval_secs: 1,
+            ..base
+        };
+
+        // Agent 启动前已有的消息
+        std::fs::create_dir_all(config.db_path().parent().unwrap()).unwrap();
+        {
+            let db = SessionDB::connect(DbConfig::local(config.db_path())).unwrap();
+            let project_id = db.get_or_create_project("p", "/p", "claude").unwrap();
+            db.upsert_session("session-1", project_id).unwrap();
+            let messages: Vec<MessageInput> = (0..3)
+                .map(|i| MessageInput {
+                    uuid: format!("m-{}", i),
+                    r#type: MessageType::User,
+                    content_text: format!("message {}", i),
+                    content_full: format!("message {}", i),
+                    timestamp: i,
+                    sequence: i,
+                    source: None,
+                    channel: None,
+                    model: None,
+                    tool_call_id: None,
+                    tool_name: None,
+                    tool_args: None,
+                    raw: None,
+                    approval_status: None,
+                    approval_resolved_at: None,
+                })
+                .collect();
+            db.insert_messages("session-1", &messages).unwrap();
+        }
+
+        let agent = Arc::new(Agent::new(config.clone()).unwrap());
+        let agent_handle = {
+            let agent = agent.clone();
+            tokio::spawn(async move {
+                agent.run().await.unwrap();
+            })
+        };
+
+        let read_lines = || -> Vec<serde_json::Value> {
+            std::fs::read_to_string(&mirror)
+                .unwrap_or_default()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        };
+        for _ in 0..100 {
+            if read_lines().len() >= 3 {
+                break;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+
+        let records = read_lines();
+        let uuids: Vec<&str> = records
+            .iter()
+            .map(|r| r["message"]["uuid"].as_str().unwrap())
+            .collect();
+        assert_eq!(uuids, vec!["m-0", "m-1", "m-2"]);
+        assert_eq!(records[0]["project_path"], "/p");
+        assert_eq!(records[0]["message"]["session_id"], "session-1");
+
+        agent_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_agent_read_only_when_database_requires_newer_writer() {
+        let config = test_config();
+
+        // 模拟更新版本的库迁移过数据库：提高最低写入方版本
+        std::fs::create_dir_all(config.db_path().parent().unwrap()).unwrap();
+        drop(SessionDB::connect(DbConfig::local(config.db_path())).unwrap());
+        let required = SUPPORTED_SCHEMA_VERSION + 1;
+        rusqlite::Connection::open(config.db_path())
+            .unwrap()
+            .execute(
+                "UPDATE metadata SET value = ?1 WHERE key = ?2",
+                rusqlite::params![required.to_string(), MIN_WRITER_SCHEMA_KEY],
+            )
+            .unwrap();
+
+        let agent = Arc::new(Agent::new(config.clone()).unwrap());
+        let agent_handle = {
+            let agent = agent.clone();
+            tokio::spawn(async move {
+                agent.run().await.unwrap();
+            })
+        };
+        sleep(Duration::from_millis(500)).await;
+
+        let stream = UnixStream::connect(config.socket_path()).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        let mut line = String::new();
+        let handshake = Request::Handshake {
+            component: "test".to_string(),
+            version: "1.0.0".to_string(),
+            protocol_version: PROTOCOL_VERSION,
+        };
+        writer
+            .write_all(format!("{}\n", serde_json::to_string(&handshake).unwrap()).as_bytes())
+            .await
+            .unwrap();
+        reader.read_line(&mut line).await.unwrap();
+        match serde_json::from_str::<Response>(&line).unwrap() {
+            Response::HandshakeOk { degraded, .. } => {
+                let degraded = degraded.expect("handshake should report read-only mode");
+                assert_eq!(degraded.min_writer_schema_version, required);
+                assert!(!degraded.writable());
+            }
+            other => panic!("Expected HandshakeOk, got {:?}", other),
+        }
+
+        // 握手后推送 AgentDegraded
+        line.clear();
+        reader.read_line(&mut line).await.unwrap();
+        match serde_json::from_str::<Push>(&line).unwrap() {
+            Push::AgentDegraded { compatibility, .. } => {
+                assert_eq!(
+                    compatibility.supported_schema_version,
+                    SUPPORTED_SCHEMA_VERSION
+                );
+            }
+            other => panic!("Expected AgentDegraded, got {:?}", other),
+        }
+
+        // 读取正常
+        let status = Request::Query {
+            query_type: QueryType::Status,
+        };
+        writer
+            .write_all(format!("{}\n", serde_json::to_string(&status).unwrap()).as_bytes())
+            .await
+            .unwrap();
+        line.clear();
+        reader.read_line(&mut line).await.unwrap();
+        match serde_json::from_str::<Response>(&line).unwrap() {
+            Response::QueryResult { data } => {
+                assert_eq!(data["degraded"]["min_writer_schema_version"], required);
+            }
+            other => panic!("Expected QueryResult, got {:?}", other),
+        }
+
+        // 写入被拒绝
+        writer
+            .write_all(
+                format!("{}\n", serde_json::to_string(&Request::CollectAll).unwrap()).as_bytes(),
+            )
+            .await
+            .unwrap();
+        line.clear();
+        reader.read_line(&mut line).await.unwrap();
+        match serde_json::from_str::<Response>(&line).unwrap() {
+            Response::VersionMismatch { compatibility } => {
+                assert_eq!(compatibility.min_writer_schema_version, required);
+            }
+            other => panic!("Expected VersionMismatch, got {:?}", other),
+        }
+
+        // 旧 Agent 不会降低标记
+        let db = SessionDB::connect(DbConfig::local(config.db_path())).unwrap();
+        let compatibility = db.compatibility_check().unwrap();
+        assert_eq!(compatibility.min_writer_schema_version, required);
+
+        agent_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_agent_logs_searches() {
+        let config = AgentConfig {
+            log_searches: true,
+            ..test_config()
+        };
+        let agent = Arc::new(Agent::new(config.clone()).unwrap());
+        let agent_handle = {
+            let agent = agent.clone();
+            tokio::spawn(async move {
+                agent.run().await.unwrap();
+            })
+        };
+        sleep(Duration::from_millis(500)).await;
+
+        // 只读组件转发的记录
+        let forwarded = SearchLogInput {
+            query: "Rust".to_string(),
+            normalized_query: "rust".to_string(),
+            result_count: 3,
+            duration_ms: 2,
+            project_filter: None,
+            created_at: 1,
+        };
+        let requests = [
+            Request::Handshake {
+                component: "test".to_string(),
+                version: "1.0.0".to_string(),
+                protocol_version: PROTOCOL_VERSION,
+            },
+            Request::WriteSearchLog {
+                entries: vec![forwarded],
+            },
+            Request::Query {
+                query_type: QueryType::Search {
+                    keyword: "nonexistent".to_string(),
+                    limit: 10,
+                    options: Default::default(),
+                    max_per_project: None,
+                    paged: false,
+                    page_token: None,
+                },
+            },
+            Request::Query {
+                query_type: QueryType::RecentSearches {
+                    limit: 10,
+                    zero_results: false,
+                },
+            },
+            Request::Query {
+                query_type: QueryType::RecentSearches {
+                    limit: 10,
+                    zero_results: true,
+                },
+            },
+        ];
+
+        let stream = UnixStream::connect(config.socket_path()).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        let mut responses = Vec::new();
+        for request in &requests {
+            writer
+                .write_all(format!("{}\n", serde_json::to_string(request).unwrap()).as_bytes())
+                .await
+                .unwrap();
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            responses.push(serde_json::from_str::<Response>(&line).unwrap());
+        }
+
+        match &responses[1] {
+            Response::QueryResult { data } => assert_eq!(data["written"], 1),
+            other => panic!("Expected QueryResult, got {:?}", other),
+        }
+        let searches = |response: &Response| -> Vec<SearchLogEntry> {
+            match response {
+                Response::QueryResult { data } => serde_json::from_value(data.clone()).unwrap(),
+                other => panic!("Expected QueryResult, got {:?}", other),
+            }
+        };
+        let recent = searches(&responses[3]);
+        let queries: Vec<&str> = recent.iter().map(|s| s.query.as_str()).collect();
+        assert_eq!(queries, vec!["nonexistent", "Rust"]);
+        let zero = searches(&responses[4]);
+        assert_eq!(zero.len(), 1);
+        assert_eq!(zero[0].query, "nonexistent");
+
+        agent_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_agent_write_approve_result_returns_count() {
+        let config = test_config();
+
+        // 等待审批的工具调用
+        std::fs::create_dir_all(config.db_path().parent().unwrap()).unwrap();
+        {
+            let db = SessionDB::connect(DbConfig::local(config.db_path())).unwrap();
+            let project_id = db.get_or_create_project("p", "/p", "claude").unwrap();
+            db.upsert_session("session-1", project_id).unwrap();
+            let message = MessageInput {
+                uuid: "m-0".to_string(),
+                r#type: MessageType::Assistant,
+                content_text: "run ls".to_string(),
+                content_full: "run ls".to_string(),
+                timestamp: 1,
+                sequence: 0,
+                source: None,
+                channel: None,
+                model: None,
+                tool_call_id: Some("toolu_real".to_string()),
+                tool_name: Some("Bash".to_string()),
+                tool_args: None,
+                raw: None,
+                approval_status: Some(ai_cli_session_db::ApprovalStatus::Pending),
+                approval_resolved_at: None,
+            };
+            db.insert_messages("session-1", &[message]).unwrap();
+        }
+
+        let agent = Arc::new(Agent::new(config.clone()).unwrap());
+        let agent_handle = {
+            let agent = agent.clone();
+            tokio::spawn(async move {
+                agent.run().await.unwrap();
+            })
+        };
+        sleep(Duration::from_millis(500)).await;
+
+        let stream = UnixStream::connect(config.socket_path()).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        let mut pushes = Vec::new();
+        let mut updated = Vec::new();
+        for request in [
+            Request::Handshake {
+                component: "test".to_string(),
+                version: "1.0.0".to_string(),
+                protocol_version: PROTOCOL_VERSION,
+            },
+            Request::WriteApproveResult {
+                tool_call_id: "toolu_real".to_string(),
+                status: ApprovalStatus::Approved,
+                resolved_at: 2,
+            },
+            Request::WriteApproveResult {
+                tool_call_id: "toolu_bogus".to_string(),
+                status: ApprovalStatus::Approved,
+                resolved_at: 3,
+            },
+        ] {
+            writer
+                .write_all(format!("{}\n", serde_json::to_string(&request).unwrap()).as_bytes())
+                .await
+                .unwrap();
+            // 推送与响应共用连接，读到响应为止
+            let response = loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).await.unwrap();
+                match serde_json::from_str::<Response>(&line) {
+                    Ok(response) => break response,
+                    Err(_) => pushes.push(serde_json::from_str::<Push>(&line).unwrap()),
+                }
+            };
+            if let Response::QueryResult { data } = response {
+                updated.push(data["updated"].as_u64().unwrap());
+            }
+        }
+
+        assert_eq!(updated, vec![1, 0]);
+        // 只有实际更新的结果被广播
+        let resolved: Vec<&Push> = pushes
+            .iter()
+            .filter(|push| matches!(push, Push::ApprovalResolved { .. }))
+            .collect();
+        assert_eq!(resolved.len(), 1);
+        match resolved[0] {
+            Push::ApprovalResolved {
+                tool_call_id,
+                status,
+                updated,
+                ..
+            } => {
+                assert_eq!(tool_call_id, "toolu_real");
+                assert_eq!(*status, ApprovalStatus::Approved);
+                assert_eq!(*updated, 1);
+            }
+            other => panic!("Expected ApprovalResolved, got {:?}", other),
+        }
+
+        agent_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_agent_rejects_unsupported_protocol_version() {
+        let config = test_config();
+        let socket_path = config.socket_path();
+
+        let agent = Arc::new(Agent::new(config.clone()).unwrap());
+        let agent_handle = {
+            let agent = agent.clone();
+            tokio::spawn(async move {
+                agent.run().await.unwrap();
+            })
+        };
+
+        sleep(Duration::from_millis(500)).await;
+
+        let stream = UnixStream::connect(&socket_path).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+
+        // 协议版本低于支持下限
+        let handshake = Request::Handshake {
+            component: "test".to_string(),
+            version: "1.0.0".to_string(),
+            protocol_version: MIN_PROTOCOL_VERSION - 1,
+        };
+        writer
+            .write_all(format!("{}\n", serde_json::to_string(&handshake).unwrap()).as_bytes())
+            .await
+            .unwrap();
+
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        let response: Response = serde_json::from_str(&line).unwrap();
+
+        match response {
+            Response::ProtocolVersionUnsupported {
+                protocol_version,
+                min_supported,
+                max_supported,
+            } => {
+                assert_eq!(protocol_version, MIN_PROTOCOL_VERSION - 1);
+                assert_eq!(min_supported, MIN_PROTOCOL_VERSION);
+                assert_eq!(max_supported, PROTOCOL_VERSION);
+            }
+            _ => panic!("Expected ProtocolVersionUnsupported"),
+        }
+
+        agent_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_agent_downgrades_newer_protocol_version() {
+        let config = test_config();
+        let socket_path = config.socket_path();
+
+        let agent = Arc::new(Agent::new(config.clone()).unwrap());
+        let agent_handle = {
+            let agent = agent.clone();
+            tokio::spawn(async move {
+                agent.run().await.unwrap();
+            })
+        };
+
+        sleep(Duration::from_millis(500)).await;
+
+        let stream = UnixStream::connect(&socket_path).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+
+        let handshake = Request::Handshake {
+            component: "test".to_string(),
+            version: "1.0.0".to_string(),
+            protocol_version: PROTOCOL_VERSION + 1,
+        };
+        writer
+            .write_all(format!("{}\n", serde_json::to_string(&handshake).unwrap()).as_bytes())
+            .await
+            .unwrap();
+
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        let response: Response = serde_json::from_str(&line).unwrap();
+
+        match response {
+            Response::HandshakeOk {
+                protocol_version, ..
+            } => assert_eq!(protocol_version, PROTOCOL_VERSION),
+            _ => panic!("Expected HandshakeOk"),
+        }
+
+        agent_handle.abort();
+    }
+
+    /// 发送一行请求并读取一行响应
+    async fn round_trip(
+        reader: &mut BufReader<tokio::net::unix::OwnedReadHalf>,
+        writer: &mut tokio::net::unix::OwnedWriteHalf,
+        request: &Request,
+    ) -> Response {
+        writer
+            .write_all(format!("{}\n", serde_json::to_string(request).unwrap()).as_bytes())
+            .await
+            .unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_agent_rejects_connections_over_limit() {
+        let config = AgentConfig {
+            max_connections: 2,
+            ..test_config()
+        };
+        let socket_path = config.socket_path();
+
+        let agent = Arc::new(Agent::new(config.clone()).unwrap());
+        let agent_handle = {
+            let agent = agent.clone();
+            tokio::spawn(async move {
+                agent.run().await.unwrap();
+            })
+        };
+
+        sleep(Duration::from_millis(500)).await;
+
+        let handshake = Request::Handshake {
+            component: "test".to_string(),
+            version: "1.0.0".to_string(),
+            protocol_version: PROTOCOL_VERSION,
+        };
+
+        // 占满两个名额
+        let mut accepted = Vec::new();
+        for _ in 0..2 {
+            let (reader, mut writer) = UnixStream::connect(&socket_path)
+                .await
+                .unwrap()
+                .into_split();
+            let mut reader = BufReader::new(reader);
+            let response = round_trip(&mut reader, &mut writer, &handshake).await;
+            assert!(matches!(response, Response::HandshakeOk { .. }));
+            accepted.push((reader, writer));
+        }
+
+        // 超出的连接收到错误后被关闭
+        for _ in 0..2 {
+            let stream = UnixStream::connect(&socket_path).await.unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            match serde_json::from_str(&line).unwrap() {
+                Response::Error { code, .. } => assert_eq!(code, error_code::TOO_MANY_CONNECTIONS),
+                other => panic!("Expected Error, got {:?}", other),
+            }
+            line.clear();
+            assert_eq!(reader.read_line(&mut line).await.unwrap(), 0);
+        }
+
+        // 已有连接不受影响
+        for (reader, writer) in accepted.iter_mut() {
+            let response = round_trip(reader, writer, &Request::Heartbeat).await;
+            assert!(matches!(response, Response::Ok));
+        }
+
+        agent_handle.abort();
+    }
 
     #[tokio::test]
     async fn test_protocol_serialization() {
@@ -80,6 +714,7 @@ mod tests {
         let handshake = Request::Handshake {
             component: "test".to_string(),
             version: "1.0.0".to_string(),
+            protocol_version: PROTOCOL_VERSION,
         };
         let json = serde_json::to_string(&handshake).unwrap();
         assert!(json.contains("Handshake"));
@@ -87,6 +722,8 @@ mod tests {
         // 测试 Response 序列化
         let response = Response::HandshakeOk {
             agent_version: "0.1.0".to_string(),
+            protocol_version: PROTOCOL_VERSION,
+            degraded: None,
         };
         let json = serde_json::to_string(&response).unwrap();
         assert!(json.contains("HandshakeOk"));
@@ -94,7 +731,7 @@ mod tests {
         // 测试反序列化
         let parsed: Response = serde_json::from_str(&json).unwrap();
         match parsed {
-            Response::HandshakeOk { agent_version } => {
+            Response::HandshakeOk { agent_version, .. } => {
                 assert_eq!(agent_version, "0.1.0");
             }
             _ => panic!("Expected HandshakeOk"),
@@ -126,6 +763,7 @@ mod tests {
         let handshake = Request::Handshake {
             component: "test".to_string(),
             version: "1.0.0".to_string(),
+            protocol_version: PROTOCOL_VERSION,
         };
         writer
             .write_all(format!("{}\n", serde_json::to_string(&handshake).unwrap()).as_bytes())
@@ -167,6 +805,92 @@ mod tests {
     }
 
 
+    #[test]
+    fn test_agent_config_precedence() {
+        let temp_dir = tempdir().unwrap();
+        std::fs::write(
+            ConfigFile::path(temp_dir.path()),
+            r#"
+            [agent]
+            idle_timeout_secs = 120
+            max_connections = 16
+            replication_batch_size = 100
+            unknown_knob = "ignored"
+
+            [collector]
+            skip_types = ["progress"]
+            max_file_bytes = 1024
+
+            [db]
+            max_content_bytes = 2048
+            wal_autocheckpoint_pages = 200
+            "#,
+        )
+        .unwrap();
+
+        // 默认值 < 配置文件
+        let config = AgentConfig::load(temp_dir.path());
+        let defaults = AgentConfig::default();
+        assert_eq!(config.data_dir, temp_dir.path());
+        assert_eq!(config.idle_timeout_secs, 120);
+        assert_eq!(config.max_connections, 16);
+        assert_eq!(config.max_waiters, defaults.max_waiters);
+        assert_eq!(config.replication_batch_size, 100);
+        assert_eq!(config.replication_file, None);
+        assert_eq!(config.collection_filter.skip_types, vec!["progress"]);
+        assert_eq!(
+            config.collection_filter.skip_empty_messages,
+            defaults.collection_filter.skip_empty_messages
+        );
+        assert_eq!(config.collect_limits.max_file_bytes, 1024);
+        assert_eq!(config.max_content_bytes, Some(2048));
+        assert_eq!(config.wal_autocheckpoint_pages, Some(200));
+
+        // 配置文件 < 环境变量
+        let file = ConfigFile::load_with_env(
+            &ConfigFile::path(temp_dir.path()),
+            vec![(
+                "VIMO_CONFIG_AGENT_IDLE_TIMEOUT_SECS".to_string(),
+                "300".to_string(),
+            )],
+        );
+        let mut config = AgentConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            ..AgentConfig::default()
+        };
+        config.apply_config_file(&file);
+        assert_eq!(config.idle_timeout_secs, 300);
+        assert_eq!(config.max_connections, 16);
+
+        // 生效的配置与配置文件结构一致
+        let effective = config.effective_config();
+        assert_eq!(effective["agent"]["idle_timeout_secs"], 300);
+        assert_eq!(effective["db"]["max_content_bytes"], 2048);
+        assert_eq!(effective["db"]["wal_autocheckpoint_pages"], 200);
+        assert_eq!(
+            effective["agent"]["status_file_interval_secs"],
+            defaults.status_file_interval_secs
+        );
+    }
+
+    #[test]
+    fn test_agent_config_malformed_file() {
+        let temp_dir = tempdir().unwrap();
+        std::fs::write(
+            ConfigFile::path(temp_dir.path()),
+            "[agent]\nidle_timeout_secs = = 5\n",
+        )
+        .unwrap();
+
+        // 格式错误的文件整体忽略，使用默认值
+        let config = AgentConfig::load(temp_dir.path());
+        let expected = AgentConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            ..AgentConfig::default()
+        };
+        assert_eq!(config.effective_config(), expected.effective_config());
+    }
+
     #[tokio::test]
     async fn test_hook_event_serialization() {
         // 测试从 claude_hook.sh 发送的 JSON 格式
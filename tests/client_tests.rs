@@ -54,4 +54,28 @@ mod tests {
         let binary_path = config.default_agent_binary_path();
         assert_eq!(binary_path, PathBuf::from("/tmp/test-vimo/bin/vimo-agent"));
     }
+
+    #[tokio::test]
+    async fn test_start_agent_without_download_or_deploy() {
+        use ai_cli_session_db::client::connect_or_start_agent;
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let config = ClientConfig {
+            data_dir: tmp.path().to_path_buf(),
+            connect_retries: 1,
+            ..ClientConfig::new("test")
+        }
+        .without_download()
+        .without_deploy();
+        assert!(!config.allow_download && !config.allow_deploy);
+
+        // 本地没有 Agent：直接报错，不下载也不部署（即使 Cargo target 中有编译好的 Agent）
+        let message = match connect_or_start_agent(config).await {
+            Ok(_) => panic!("Expected missing Agent error"),
+            Err(e) => e.to_string(),
+        };
+        assert!(message.contains("auto-download is disabled"), "{}", message);
+        assert!(message.contains("allow_deploy"), "{}", message);
+        assert!(!tmp.path().join("bin").exists());
+    }
 }
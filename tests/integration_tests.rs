@@ -13,6 +13,44 @@ fn setup_db() -> (SessionDB, TempDir) {
     (db, tmp)
 }
 
+/// 创建测试消息（content_text 与 content_full 相同，timestamp 为 1000 + sequence，其余字段为空）
+///
+/// 需要其他字段时用结构体更新语法覆盖：`MessageInput { raw: Some(..), ..message(..) }`。
+fn message(uuid: &str, r#type: MessageType, content: &str, sequence: i64) -> MessageInput {
+    MessageInput {
+        uuid: uuid.to_string(),
+        r#type,
+        content_text: content.to_string(),
+        content_full: content.to_string(),
+        timestamp: 1_000 + sequence,
+        sequence,
+        source: None,
+        channel: None,
+        model: None,
+        tool_call_id: None,
+        tool_name: None,
+        tool_args: None,
+        raw: None,
+        approval_status: None,
+        approval_resolved_at: None,
+    }
+}
+
+/// 待审批的 Bash 工具调用消息
+fn pending_tool_call(uuid: &str, sequence: i64) -> MessageInput {
+    MessageInput {
+        tool_call_id: Some(format!("call-{}", uuid)),
+        tool_name: Some("Bash".to_string()),
+        approval_status: Some(ApprovalStatus::Pending),
+        ..message(
+            uuid,
+            MessageType::Assistant,
+            &format!("content {}", uuid),
+            sequence,
+        )
+    }
+}
+
 // ==================== DB 连接测试 ====================
 
 mod connection_tests {
@@ -48,6 +86,25 @@ mod connection_tests {
         assert_eq!(stats.project_count, 0);
     }
 
+    #[test]
+    fn test_wal_autocheckpoint_pages() {
+        let (db, tmp) = setup_db();
+        assert_eq!(
+            db.wal_autocheckpoint_pages().unwrap(),
+            ai_cli_session_db::config::DEFAULT_WAL_AUTOCHECKPOINT_PAGES
+        );
+        drop(db);
+
+        let config = DbConfig::local(tmp.path().join("test.db")).with_wal_autocheckpoint_pages(200);
+        let db = SessionDB::connect(config).unwrap();
+        assert_eq!(db.wal_autocheckpoint_pages().unwrap(), 200);
+
+        // 0 关闭自动 checkpoint
+        let config = DbConfig::local(tmp.path().join("test.db")).with_wal_autocheckpoint_pages(0);
+        let db = SessionDB::connect(config).unwrap();
+        assert_eq!(db.wal_autocheckpoint_pages().unwrap(), 0);
+    }
+
     #[test]
     fn test_default_config_from_env() {
         // 不设置环境变量时应该有默认值
@@ -56,6 +113,161 @@ mod connection_tests {
     }
 }
 
+// ==================== 损坏抢救测试 ====================
+
+mod salvage_tests {
+    use super::*;
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::path::Path;
+
+    /// 写入 2 个项目、3 个会话和足够多的消息（messages 表跨多个页），返回页大小
+    fn populate(db_path: &Path) -> usize {
+        let db = SessionDB::connect(DbConfig::local(db_path)).unwrap();
+        let alpha = db
+            .get_or_create_project("alpha", "/alpha", "claude")
+            .unwrap();
+        let beta = db.get_or_create_project("beta", "/beta", "claude").unwrap();
+        for (session_id, project_id) in [("s-1", alpha), ("s-2", alpha), ("s-3", beta)] {
+            db.upsert_session(session_id, project_id).unwrap();
+            let messages: Vec<MessageInput> = (0..100)
+                .map(|i| MessageInput {
+                    uuid: format!("{}-{}", session_id, i),
+                    r#type: MessageType::User,
+                    content_text: format!("salvage message {} {}", i, "x".repeat(200)),
+                    content_full: format!("salvage message {} {}", i, "x".repeat(200)),
+                    timestamp: 1000 + i as i64,
+                    sequence: i as i64,
+                    source: None,
+                    channel: None,
+                    model: None,
+                    tool_call_id: None,
+                    tool_name: None,
+                    tool_args: None,
+                    raw: None,
+                    approval_status: None,
+                    approval_resolved_at: None,
+                })
+                .collect();
+            db.insert_messages(session_id, &messages).unwrap();
+        }
+
+        // 合并 WAL，确保数据都在主文件中
+        let conn = db.connection().lock();
+        conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")
+            .unwrap();
+        conn.query_row("PRAGMA page_size", [], |row| row.get::<_, i64>(0))
+            .unwrap() as usize
+    }
+
+    /// 表的根页号
+    fn root_page(db_path: &Path, table: &str) -> usize {
+        let conn = rusqlite::Connection::open(db_path).unwrap();
+        conn.query_row(
+            "SELECT rootpage FROM sqlite_master WHERE name = ?1",
+            [table],
+            |row| row.get::<_, i64>(0),
+        )
+        .unwrap() as usize
+    }
+
+    /// 翻转页内 [start, page_size) 的所有字节
+    fn flip_page(db_path: &Path, page: usize, page_size: usize, start: usize) {
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(db_path)
+            .unwrap();
+        let offset = ((page - 1) * page_size + start) as u64;
+        let mut buf = vec![0u8; page_size - start];
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.read_exact(&mut buf).unwrap();
+        buf.iter_mut().for_each(|b| *b ^= 0xFF);
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.write_all(&buf).unwrap();
+    }
+
+    #[test]
+    fn test_salvage_recovers_untouched_tables() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let page_size = populate(&db_path);
+
+        // 损坏 messages 的根页
+        let messages_root = root_page(&db_path, "messages");
+        flip_page(&db_path, messages_root, page_size, 0);
+
+        let dest = tmp.path().join("salvaged.db");
+        let report = SessionDB::salvage_into(&db_path, &dest).unwrap();
+
+        for table in ["projects", "sessions"] {
+            let salvage = report.table(table).unwrap();
+            assert_eq!(salvage.lost, 0, "{:?}", salvage);
+            assert!(salvage.error.is_none(), "{:?}", salvage);
+        }
+        assert_eq!(report.table("projects").unwrap().recovered, 2);
+        assert_eq!(report.table("sessions").unwrap().recovered, 3);
+        let messages = report.table("messages").unwrap();
+        assert!(messages.error.is_some());
+        assert!(messages.recovered < 300);
+
+        // 抢救出的数据库可以正常使用
+        let db = SessionDB::connect(DbConfig::local(&dest)).unwrap();
+        assert!(matches!(
+            db.quick_check().unwrap(),
+            IntegrityCheckResult::Ok
+        ));
+        let projects: Vec<String> = db
+            .list_projects()
+            .unwrap()
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        assert_eq!(projects.len(), 2);
+        assert!(projects.contains(&"alpha".to_string()));
+        assert_eq!(
+            db.get_sessions_by_ids(&["s-3".to_string()]).unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_salvage_intact_database_recovers_everything() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        populate(&db_path);
+
+        let dest = tmp.path().join("salvaged.db");
+        let report = SessionDB::salvage_into(&db_path, &dest).unwrap();
+        assert_eq!(report.total_lost(), 0);
+        assert_eq!(report.table("messages").unwrap().recovered, 300);
+
+        // FTS 由触发器重建
+        let db = SessionDB::connect(DbConfig::local(&dest)).unwrap();
+        #[cfg(feature = "search")]
+        assert_eq!(db.search_fts("salvage", 500).unwrap().len(), 300);
+        assert_eq!(db.get_stats().unwrap().message_count, 300);
+
+        // 目标已存在时拒绝覆盖
+        assert!(SessionDB::salvage_into(&db_path, &dest).is_err());
+    }
+
+    #[test]
+    fn test_connect_corrupted_schema_returns_corrupted() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let page_size = populate(&db_path);
+
+        // 保留 100 字节文件头，损坏 sqlite_master 所在的第 1 页
+        flip_page(&db_path, 1, page_size, 100);
+
+        match SessionDB::connect(DbConfig::local(&db_path)) {
+            Err(Error::Corrupted { path, .. }) => assert_eq!(path, db_path),
+            Err(e) => panic!("Expected Corrupted, got {:?}", e),
+            Ok(_) => panic!("Expected Corrupted, got Ok"),
+        }
+    }
+}
+
 // ==================== Project 测试 ====================
 
 mod project_tests {
@@ -92,6 +304,69 @@ mod project_tests {
         assert_eq!(projects.len(), 1);
     }
 
+    #[test]
+    fn test_normalize_project_path() {
+        use ai_cli_session_db::db::normalize_project_path;
+
+        assert_eq!(normalize_project_path("/a/b"), "/a/b");
+        assert_eq!(normalize_project_path("/a/b/"), "/a/b");
+        assert_eq!(normalize_project_path("/a/./b//"), "/a/b");
+        assert_eq!(normalize_project_path("/a/x/../b"), "/a/b");
+        assert_eq!(normalize_project_path("/../a"), "/a");
+        assert_eq!(normalize_project_path("../a/.."), "..");
+        assert_eq!(normalize_project_path("a/.."), ".");
+        assert_eq!(normalize_project_path("/"), "/");
+        assert_eq!(normalize_project_path(""), "");
+    }
+
+    #[test]
+    fn test_equivalent_paths_share_project() {
+        let (db, _tmp) = setup_db();
+
+        let id = db.get_or_create_project("b", "/a/b", "claude").unwrap();
+        for path in ["/a/b/", "/a/x/../b"] {
+            assert_eq!(db.get_or_create_project("b", path, "claude").unwrap(), id);
+        }
+
+        let projects = db.list_projects().unwrap();
+        assert_eq!(projects.len(), 1);
+        assert_eq!(projects[0].path, "/a/b");
+        let found = db.get_project_by_path("/a/./b/").unwrap().unwrap();
+        assert_eq!(found.id, id);
+
+        // 首次写入即规范化
+        let id2 = db.get_or_create_project("c", "/a/c/", "claude").unwrap();
+        assert_eq!(db.get_project(id2).unwrap().unwrap().path, "/a/c");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_project_symlinks() {
+        let tmp = TempDir::new().unwrap();
+        let real = tmp.path().join("real");
+        let link = tmp.path().join("link");
+        std::fs::create_dir(&real).unwrap();
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+        let link = link.to_str().unwrap();
+
+        // 默认不解析符号链接
+        let (db, _db_tmp) = setup_db();
+        let real_path = std::fs::canonicalize(&real).unwrap();
+        let real_path = real_path.to_str().unwrap();
+        let real_id = db.get_or_create_project("p", real_path, "claude").unwrap();
+        let link_id = db.get_or_create_project("p", link, "claude").unwrap();
+        assert_ne!(link_id, real_id);
+
+        let config = DbConfig::local(tmp.path().join("resolved.db"));
+        let db = SessionDB::connect(config.with_resolve_project_symlinks(true)).unwrap();
+        let id = db.get_or_create_project("p", link, "claude").unwrap();
+        let real_id = db.get_or_create_project("p", real_path, "claude").unwrap();
+        assert_eq!(real_id, id);
+        assert_eq!(db.get_project(id).unwrap().unwrap().path, real_path);
+        // 不存在的路径只按字面规范化
+        assert_eq!(db.canonical_project_path("/no/such/dir/"), "/no/such/dir");
+    }
+
     #[test]
     fn test_multiple_projects() {
         let (db, _tmp) = setup_db();
@@ -107,6 +382,43 @@ mod project_tests {
         assert_eq!(projects.len(), 3);
     }
 
+    #[test]
+    fn test_list_projects_filtered() {
+        let (db, _tmp) = setup_db();
+
+        for i in 0..3 {
+            db.get_or_create_project(&format!("claude{}", i), &format!("/claude{}", i), "claude")
+                .unwrap();
+        }
+        db.get_or_create_project("codex0", "/codex0", "codex")
+            .unwrap();
+
+        let claude = db.list_projects_filtered(Some("claude"), 100, 0).unwrap();
+        assert_eq!(claude.len(), 3);
+        assert!(claude.iter().all(|p| p.source == "claude"));
+
+        let codex = db.list_projects_filtered(Some("codex"), 100, 0).unwrap();
+        assert_eq!(codex.len(), 1);
+        assert_eq!(codex[0].name, "codex0");
+
+        assert_eq!(db.list_projects_filtered(None, 100, 0).unwrap().len(), 4);
+        assert!(db
+            .list_projects_filtered(Some("opencode"), 100, 0)
+            .unwrap()
+            .is_empty());
+
+        // 分页：按 updated_at 降序，页间不重叠
+        let page1 = db.list_projects_filtered(Some("claude"), 2, 0).unwrap();
+        let page2 = db.list_projects_filtered(Some("claude"), 2, 2).unwrap();
+        assert_eq!(page1.len(), 2);
+        assert_eq!(page2.len(), 1);
+        let paged: Vec<i64> = page1.iter().chain(&page2).map(|p| p.id).collect();
+        let all: Vec<i64> = claude.iter().map(|p| p.id).collect();
+        assert_eq!(paged, all);
+        assert!(page1[0].updated_at >= page1[1].updated_at);
+        assert!(page1[1].updated_at >= page2[0].updated_at);
+    }
+
     #[test]
     fn test_project_different_path_same_name() {
         let (db, _tmp) = setup_db();
@@ -121,6 +433,273 @@ mod project_tests {
 
         assert_ne!(id1, id2);
     }
+
+    #[test]
+    fn test_get_project_with_stats_matches_list() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        let other_id = db
+            .get_or_create_project("other", "/other", "claude")
+            .unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+        db.upsert_session("session-002", project_id).unwrap();
+        db.upsert_session("session-003", other_id).unwrap();
+
+        let message = |uuid: &str, timestamp: i64, sequence: i64| MessageInput {
+            uuid: uuid.to_string(),
+            r#type: MessageType::User,
+            content_text: "hello".to_string(),
+            content_full: "hello".to_string(),
+            timestamp,
+            sequence,
+            source: None,
+            channel: None,
+            model: None,
+            tool_call_id: None,
+            tool_name: None,
+            tool_args: None,
+            raw: None,
+            approval_status: None,
+            approval_resolved_at: None,
+        };
+        db.insert_messages(
+            "session-001",
+            &[message("a", 1000, 0), message("b", 2000, 1)],
+        )
+        .unwrap();
+        db.insert_messages("session-002", &[message("c", 3000, 0)])
+            .unwrap();
+        db.insert_messages("session-003", &[message("d", 9000, 0)])
+            .unwrap();
+
+        let stats = db.get_project_with_stats(project_id).unwrap().unwrap();
+        let listed = db
+            .list_projects_with_stats(100, 0)
+            .unwrap()
+            .into_iter()
+            .find(|p| p.id == project_id)
+            .unwrap();
+
+        assert_eq!(stats.name, listed.name);
+        assert_eq!(stats.path, listed.path);
+        assert_eq!(stats.session_count, 2);
+        assert_eq!(stats.session_count, listed.session_count);
+        assert_eq!(stats.message_count, 3);
+        assert_eq!(stats.message_count, listed.message_count);
+        assert_eq!(stats.last_active, listed.last_active);
+
+        assert!(db.get_project_with_stats(9999).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_list_projects_order_with_null_and_tied_last_active() {
+        let (db, _temp) = setup_db();
+
+        let empty = db
+            .get_or_create_project("empty", "/empty", "claude")
+            .unwrap();
+        let old = db.get_or_create_project("old", "/old", "claude").unwrap();
+        let tied_a = db
+            .get_or_create_project("tied-a", "/tied-a", "claude")
+            .unwrap();
+        let tied_b = db
+            .get_or_create_project("tied-b", "/tied-b", "claude")
+            .unwrap();
+
+        let message = |uuid: &str, timestamp: i64| MessageInput {
+            uuid: uuid.to_string(),
+            r#type: MessageType::User,
+            content_text: "hello".to_string(),
+            content_full: "hello".to_string(),
+            timestamp,
+            sequence: 0,
+            source: None,
+            channel: None,
+            model: None,
+            tool_call_id: None,
+            tool_name: None,
+            tool_args: None,
+            raw: None,
+            approval_status: None,
+            approval_resolved_at: None,
+        };
+        for (session_id, project_id, uuid, timestamp) in [
+            ("s-old", old, "a", 1000),
+            ("s-tied-a", tied_a, "b", 5000),
+            ("s-tied-b", tied_b, "c", 5000),
+        ] {
+            db.upsert_session(session_id, project_id).unwrap();
+            db.insert_messages(session_id, &[message(uuid, timestamp)])
+                .unwrap();
+        }
+
+        // 最近活跃在前，last_active 相同时 id 大的在前，没有会话（NULL）的排最后
+        let expected = vec![tied_b, tied_a, old, empty];
+        let ids: Vec<i64> = db
+            .list_projects_with_stats(100, 0)
+            .unwrap()
+            .iter()
+            .map(|p| p.id)
+            .collect();
+        assert_eq!(ids, expected);
+
+        // 顺序稳定：逐页翻取的结果与一次取完相同
+        let mut paged = Vec::new();
+        for offset in 0..expected.len() {
+            paged.extend(
+                db.list_projects_with_stats(1, offset)
+                    .unwrap()
+                    .iter()
+                    .map(|p| p.id),
+            );
+        }
+        assert_eq!(paged, expected);
+    }
+
+    #[test]
+    fn test_rename_project() {
+        let (db, _temp) = setup_db();
+
+        let id = db
+            .get_or_create_project("old-name", "/repos/old-name", "claude")
+            .unwrap();
+        let other = db
+            .get_or_create_project("other", "/repos/other", "claude")
+            .unwrap();
+        db.upsert_session("session-001", id).unwrap();
+        let before = db.get_project(id).unwrap().unwrap();
+
+        db.rename_project(id, "new-name", Some("/repos/new-name"))
+            .unwrap();
+
+        let project = db.get_project(id).unwrap().unwrap();
+        assert_eq!(project.name, "new-name");
+        assert_eq!(project.path, "/repos/new-name");
+        assert!(project.updated_at >= before.updated_at);
+        assert!(db.get_project_by_path("/repos/old-name").unwrap().is_none());
+
+        // 会话仍通过项目解析
+        let session = db.get_session_with_project("session-001").unwrap().unwrap();
+        assert_eq!(session.project_name, "new-name");
+        assert_eq!(session.project_path, "/repos/new-name");
+        let sessions = db
+            .list_sessions_by_project_path("/repos/new-name", 100, 0)
+            .unwrap();
+        assert_eq!(sessions.len(), 1);
+
+        // 只改名称
+        db.rename_project(id, "renamed-again", None).unwrap();
+        let project = db.get_project(id).unwrap().unwrap();
+        assert_eq!(project.name, "renamed-again");
+        assert_eq!(project.path, "/repos/new-name");
+
+        // 路径与其他项目冲突时不做修改
+        let err = db
+            .rename_project(id, "clash", Some("/repos/other"))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::ProjectPathConflict { project_id, .. } if project_id == other
+        ));
+        assert_eq!(db.get_project(id).unwrap().unwrap().name, "renamed-again");
+
+        assert!(matches!(
+            db.rename_project(9999, "missing", None),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_list_projects_reconciled() {
+        let (db, tmp) = setup_db();
+        let projects_path = tmp.path().join("projects");
+
+        // 在 projects 目录下写入会话文件
+        let write_session = |encoded: &str, cwd: &str, session_id: &str| {
+            let dir = projects_path.join(encoded);
+            std::fs::create_dir_all(&dir).unwrap();
+            let line = format!(
+                r#"{{"type":"user","cwd":"{}","sessionId":"{}"}}"#,
+                cwd, session_id
+            );
+            std::fs::write(dir.join(format!("{}.jsonl", session_id)), line).unwrap();
+        };
+
+        // 已采集且文件仍在
+        let both = db
+            .get_or_create_project_with_encoded("both", "/work/both", "claude", Some("-work-both"))
+            .unwrap();
+        db.upsert_session("both-1", both).unwrap();
+        write_session("-work-both", "/work/both", "both-1");
+        write_session("-work-both", "/work/both", "both-2");
+        write_session("-work-both", "/work/both", "agent-both");
+
+        // 已采集但文件已删除
+        let archived = db
+            .get_or_create_project_with_encoded(
+                "archived",
+                "/work/archived",
+                "claude",
+                Some("-work-archived"),
+            )
+            .unwrap();
+        db.upsert_session("archived-1", archived).unwrap();
+        db.upsert_session("archived-2", archived).unwrap();
+
+        // 尚未采集
+        write_session("-work-new", "/work/new", "new-1");
+
+        // 被忽略的项目和其他来源的项目都不列出
+        db.get_or_create_project_with_encoded(
+            "secret",
+            "/work/secret",
+            "claude",
+            Some("-work-secret"),
+        )
+        .unwrap();
+        write_session("-work-secret", "/work/secret", "secret-1");
+        db.add_ignore("/work/secret", IgnoreKind::ProjectPath)
+            .unwrap();
+        db.apply_project_ignores(&IgnoreRules::load(&db).unwrap())
+            .unwrap();
+        db.get_or_create_project("codex", "/work/codex", "codex")
+            .unwrap();
+
+        let projects = db.list_projects_reconciled(&projects_path, 100, 0).unwrap();
+        let mut paths: Vec<&str> = projects.iter().map(|p| p.path.as_str()).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["/work/archived", "/work/both", "/work/new"]);
+        let find = |path: &str| projects.iter().find(|p| p.path == path).unwrap();
+
+        let p = find("/work/both");
+        assert_eq!(p.id, Some(both));
+        assert!(p.in_db && p.on_disk);
+        assert_eq!((p.file_count, p.db_session_count), (2, 1));
+
+        let p = find("/work/archived");
+        assert_eq!(p.id, Some(archived));
+        assert!(p.in_db && !p.on_disk);
+        assert_eq!((p.file_count, p.db_session_count), (0, 2));
+
+        let p = find("/work/new");
+        assert_eq!(p.id, None);
+        assert!(!p.in_db && p.on_disk);
+        assert_eq!((p.file_count, p.db_session_count), (1, 0));
+        assert_eq!(p.encoded_dir_name.as_deref(), Some("-work-new"));
+        assert_eq!(p.name, "new");
+
+        // 分页
+        let page = db.list_projects_reconciled(&projects_path, 2, 1).unwrap();
+        assert_eq!(page, projects[1..].to_vec());
+
+        // projects 目录不存在时只返回数据库中的项目
+        let projects = db
+            .list_projects_reconciled(&tmp.path().join("missing"), 100, 0)
+            .unwrap();
+        assert_eq!(projects.len(), 2);
+        assert!(projects.iter().all(|p| p.in_db && !p.on_disk));
+    }
 }
 
 // ==================== Session 测试 ====================
@@ -175,6 +754,22 @@ mod session_tests {
         assert_eq!(sessions.len(), 3);
     }
 
+    #[test]
+    fn test_get_sessions_excludes_agents_by_default() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+        db.upsert_session("agent-a1b2c3", project_id).unwrap();
+
+        for filter in [None, Some(project_id)] {
+            let sessions = db.get_sessions(filter, 10, false).unwrap();
+            assert_eq!(sessions.len(), 1);
+            assert_eq!(sessions[0].session_id, "session-001");
+            assert_eq!(db.get_sessions(filter, 10, true).unwrap().len(), 2);
+        }
+    }
+
     #[test]
     fn test_scan_checkpoint() {
         let (db, _tmp) = setup_db();
@@ -193,26 +788,23 @@ mod session_tests {
         let checkpoint = db.get_scan_checkpoint("session-001").unwrap();
         assert_eq!(checkpoint, Some(1234567890));
     }
-}
 
-// ==================== Message 测试 ====================
-
-mod message_tests {
-    use super::*;
+    #[test]
+    fn test_get_sessions_by_ids() {
+        let (db, _tmp) = setup_db();
 
-    fn create_test_messages(count: usize) -> Vec<MessageInput> {
-        (0..count)
-            .map(|i| MessageInput {
-                uuid: format!("uuid-{}", i),
-                r#type: if i % 2 == 0 {
-                    MessageType::User
-                } else {
-                    MessageType::Assistant
-                },
-                content_text: format!("Message content {}", i),
-                content_full: format!("Message content {}", i),
-                timestamp: 1000000 + i as i64,
-                sequence: i as i64,
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+        db.upsert_session("session-002", project_id).unwrap();
+        db.insert_messages(
+            "session-002",
+            &[MessageInput {
+                uuid: "uuid-1".to_string(),
+                r#type: MessageType::User,
+                content_text: "Hello preview".to_string(),
+                content_full: "Hello preview".to_string(),
+                timestamp: 1000,
+                sequence: 0,
                 source: None,
                 channel: None,
                 model: None,
@@ -222,34 +814,368 @@ mod message_tests {
                 raw: None,
                 approval_status: None,
                 approval_resolved_at: None,
-            })
-            .collect()
+            }],
+        )
+        .unwrap();
+
+        let ids = vec![
+            "session-002".to_string(),
+            "missing".to_string(),
+            "session-001".to_string(),
+        ];
+        let sessions = db.get_sessions_by_ids(&ids).unwrap();
+
+        // 按输入顺序返回，跳过不存在的 ID
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].session_id, "session-002");
+        assert_eq!(sessions[1].session_id, "session-001");
+        assert_eq!(sessions[0].project_path, "/path");
+        assert_eq!(
+            sessions[0].last_message_preview.as_deref(),
+            Some("Hello preview")
+        );
+        assert!(sessions[1].last_message_preview.is_none());
+
+        assert!(db.get_sessions_by_ids(&[]).unwrap().is_empty());
     }
 
     #[test]
-    fn test_insert_messages() {
+    fn test_find_sessions_by_model() {
         let (db, _tmp) = setup_db();
 
         let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
-        db.upsert_session("session-001", project_id).unwrap();
-
-        let messages = create_test_messages(5);
-        let inserted = db.insert_messages("session-001", &messages).unwrap().0;
-
-        assert_eq!(inserted, 5);
+        for (session_id, model) in [
+            ("session-opus", Some("claude-opus-4")),
+            ("session-sonnet", Some("claude-sonnet-4")),
+            ("session-none", None),
+            ("session-opus-2", Some("claude-opus-4")),
+        ] {
+            db.upsert_session_full(&SessionInput {
+                session_id: session_id.to_string(),
+                project_id,
+                model: model.map(str::to_string),
+                ..Default::default()
+            })
+            .unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        // 会话记录的是另一个模型，但中途有消息使用了 opus
+        db.insert_messages(
+            "session-sonnet",
+            &[MessageInput {
+                uuid: "uuid-1".to_string(),
+                r#type: MessageType::Assistant,
+                content_text: "Switched model".to_string(),
+                content_full: "Switched model".to_string(),
+                timestamp: 1000,
+                sequence: 0,
+                source: None,
+                channel: None,
+                model: Some("claude-opus-4".to_string()),
+                tool_call_id: None,
+                tool_name: None,
+                tool_args: None,
+                raw: None,
+                approval_status: None,
+                approval_resolved_at: None,
+            }],
+        )
+        .unwrap();
 
-        // 验证 session 的 message_count 更新了
-        let sessions = db.list_sessions(project_id).unwrap();
-        assert_eq!(sessions[0].message_count, 5);
+        // 按最近更新降序（insert_messages 更新了 session-sonnet）
+        let ids = |sessions: Vec<SessionWithProject>| -> Vec<String> {
+            sessions.into_iter().map(|s| s.session_id).collect()
+        };
+        let sessions = db.find_sessions_by_model("claude-opus-4", 10).unwrap();
+        assert_eq!(sessions[0].project_path, "/path");
+        assert_eq!(
+            ids(sessions),
+            vec!["session-sonnet", "session-opus-2", "session-opus"]
+        );
+        assert_eq!(
+            ids(db.find_sessions_by_model("claude-opus-4", 1).unwrap()),
+            vec!["session-sonnet"]
+        );
+        assert_eq!(
+            ids(db.find_sessions_by_model("claude-sonnet-4", 10).unwrap()),
+            vec!["session-sonnet"]
+        );
+        assert!(db.find_sessions_by_model("claude", 10).unwrap().is_empty());
     }
 
     #[test]
-    fn test_insert_messages_dedup() {
+    fn test_get_session_tree_with_cycle() {
         let (db, _tmp) = setup_db();
 
-        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
-        db.upsert_session("session-001", project_id).unwrap();
-
+        // root → a → b → c，且 c → a 形成环
+        db.insert_session_relation("root", "a", "fork", "claude")
+            .unwrap();
+        db.insert_session_relation("a", "b", "fork", "claude")
+            .unwrap();
+        db.insert_session_relation("root", "a2", "fork", "claude")
+            .unwrap();
+        db.insert_session_relation("b", "c", "fork", "claude")
+            .unwrap();
+        db.insert_session_relation("c", "a", "fork", "claude")
+            .unwrap();
+
+        let tree = db.get_session_tree("root", 10).unwrap();
+        assert_eq!(tree.session_id, "root");
+        assert!(tree.relation.is_none());
+        let ids: Vec<_> = tree
+            .children
+            .iter()
+            .map(|c| c.session_id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["a", "a2"]);
+
+        let a = &tree.children[0];
+        assert_eq!(a.relation.as_ref().unwrap().parent_session_id, "root");
+        let b = &a.children[0];
+        assert_eq!(b.session_id, "b");
+        let c = &b.children[0];
+        assert_eq!(c.session_id, "c");
+        // c → a 的环不再展开
+        assert!(c.children.is_empty());
+        assert!(!c.truncated);
+
+        // 限制深度
+        let shallow = db.get_session_tree("root", 2).unwrap();
+        let b = &shallow.children[0].children[0];
+        assert_eq!(b.session_id, "b");
+        assert!(b.children.is_empty());
+        assert!(b.truncated);
+
+        let only_root = db.get_session_tree("root", 0).unwrap();
+        assert!(only_root.children.is_empty());
+        assert!(only_root.truncated);
+    }
+
+    #[test]
+    fn test_insert_session_relations_batch() {
+        let (db, _tmp) = setup_db();
+
+        db.insert_session_relation("root", "a", "fork", "claude")
+            .unwrap();
+
+        let inserted = db
+            .insert_session_relations(&[
+                ("root", "a", "fork", "claude"), // 已存在
+                ("root", "b", "fork", "claude"),
+                ("root", "b", "fork", "claude"), // 批内重复
+                ("root", "b", "subagent", "claude"),
+                ("b", "c", "fork", "claude"),
+            ])
+            .unwrap();
+        assert_eq!(inserted, 3);
+
+        assert_eq!(db.get_children_sessions("root").unwrap().len(), 3);
+        assert_eq!(db.get_children_sessions("b").unwrap().len(), 1);
+
+        assert_eq!(db.insert_session_relations(&[]).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_get_session_with_metrics() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+
+        let types = [
+            MessageType::User,
+            MessageType::Assistant,
+            MessageType::Tool,
+            MessageType::Assistant,
+            MessageType::User,
+            MessageType::Assistant,
+        ];
+        let messages: Vec<MessageInput> = types
+            .iter()
+            .enumerate()
+            .map(|(i, message_type)| MessageInput {
+                uuid: format!("uuid-{}", i),
+                r#type: *message_type,
+                content_text: "test".to_string(),
+                content_full: "test".to_string(),
+                timestamp: 10_000 + i as i64 * 1_500,
+                sequence: i as i64,
+                source: None,
+                channel: None,
+                model: None,
+                tool_call_id: None,
+                tool_name: None,
+                tool_args: None,
+                raw: None,
+                approval_status: None,
+                approval_resolved_at: None,
+            })
+            .collect();
+        db.insert_messages("session-001", &messages).unwrap();
+
+        // 不请求统计时不计算
+        let plain = db.get_session_with_project("session-001").unwrap().unwrap();
+        assert!(plain.metrics.is_none());
+
+        let session = db.get_session_with_metrics("session-001").unwrap().unwrap();
+        assert_eq!(session.project_path, "/path");
+        assert_eq!(session.session_id, plain.session_id);
+
+        // 与分别查询的结果一致
+        let stored = db.list_messages("session-001", 100, 0).unwrap();
+        let count_of = |t: MessageType| stored.iter().filter(|m| m.r#type == t).count() as i64;
+        let first = stored.iter().map(|m| m.timestamp).min();
+        let last = stored.iter().map(|m| m.timestamp).max();
+        let metrics = session.metrics.unwrap();
+        assert_eq!(metrics.message_count, stored.len() as i64);
+        assert_eq!(metrics.user_message_count, count_of(MessageType::User));
+        assert_eq!(
+            metrics.assistant_message_count,
+            count_of(MessageType::Assistant)
+        );
+        assert_eq!(metrics.first_message_at, first);
+        assert_eq!(metrics.last_message_at, last);
+        assert_eq!(metrics.duration_ms, Some(7_500));
+        assert_eq!(
+            (metrics.user_message_count, metrics.assistant_message_count),
+            (2, 3)
+        );
+
+        // 没有消息的会话
+        db.upsert_session("session-empty", project_id).unwrap();
+        let empty = db
+            .get_session_with_metrics("session-empty")
+            .unwrap()
+            .unwrap()
+            .metrics
+            .unwrap();
+        assert_eq!(empty.message_count, 0);
+        assert_eq!(empty.duration_ms, None);
+
+        assert!(db.get_session_with_metrics("missing").unwrap().is_none());
+    }
+}
+
+// ==================== Message 测试 ====================
+
+mod message_tests {
+    use super::*;
+
+    fn create_test_messages(count: usize) -> Vec<MessageInput> {
+        (0..count)
+            .map(|i| MessageInput {
+                uuid: format!("uuid-{}", i),
+                r#type: if i % 2 == 0 {
+                    MessageType::User
+                } else {
+                    MessageType::Assistant
+                },
+                content_text: format!("Message content {}", i),
+                content_full: format!("Message content {}", i),
+                timestamp: 1000000 + i as i64,
+                sequence: i as i64,
+                source: None,
+                channel: None,
+                model: None,
+                tool_call_id: None,
+                tool_name: None,
+                tool_args: None,
+                raw: None,
+                approval_status: None,
+                approval_resolved_at: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_messages() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+
+        let messages = create_test_messages(5);
+        let inserted = db.insert_messages("session-001", &messages).unwrap().0;
+
+        assert_eq!(inserted, 5);
+
+        // 验证 session 的 message_count 更新了
+        let sessions = db.list_sessions(project_id).unwrap();
+        assert_eq!(sessions[0].message_count, 5);
+    }
+
+    #[test]
+    fn test_list_messages_filtered_by_role() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+
+        let types = [
+            MessageType::User,
+            MessageType::Assistant,
+            MessageType::Tool,
+            MessageType::Assistant,
+            MessageType::Tool,
+            MessageType::User,
+        ];
+        let mut messages = create_test_messages(types.len());
+        for (message, message_type) in messages.iter_mut().zip(types) {
+            message.r#type = message_type;
+        }
+        db.insert_messages("session-001", &messages).unwrap();
+
+        let roles = [MessageType::User, MessageType::Assistant];
+        let conversation = db
+            .list_messages_filtered("session-001", &roles, 100, 0, Order::Asc)
+            .unwrap();
+        let uuids: Vec<&str> = conversation.iter().map(|m| m.uuid.as_str()).collect();
+        assert_eq!(uuids, vec!["uuid-0", "uuid-1", "uuid-3", "uuid-5"]);
+        assert!(conversation.iter().all(|m| m.r#type != MessageType::Tool));
+
+        // 分页和排序在过滤后进行
+        let page = db
+            .list_messages_filtered("session-001", &roles, 2, 1, Order::Desc)
+            .unwrap();
+        let uuids: Vec<&str> = page.iter().map(|m| m.uuid.as_str()).collect();
+        assert_eq!(uuids, vec!["uuid-3", "uuid-1"]);
+
+        // 空集合表示不过滤
+        let all = db
+            .list_messages_filtered("session-001", &[], 100, 0, Order::Asc)
+            .unwrap();
+        assert_eq!(all.len(), types.len());
+    }
+
+    #[test]
+    fn test_session_duration() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+        assert_eq!(db.get_session_duration("session-001").unwrap(), None);
+
+        // timestamp: 1000002..=1000004
+        let messages = create_test_messages(5);
+        db.insert_messages("session-001", &messages[2..]).unwrap();
+        db.update_session_last_message("session-001", 1000004)
+            .unwrap();
+        assert_eq!(db.get_session_duration("session-001").unwrap(), Some(2));
+
+        // 后写入的更早消息会前移 first_message_at
+        db.insert_messages("session-001", &messages[..2]).unwrap();
+        assert_eq!(db.get_session_duration("session-001").unwrap(), Some(4));
+
+        assert_eq!(db.get_session_duration("missing").unwrap(), None);
+    }
+
+    #[test]
+    fn test_insert_messages_dedup() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+
         let messages = create_test_messages(3);
 
         // 第一次插入
@@ -265,6 +1191,67 @@ mod message_tests {
         assert_eq!(all_messages.len(), 3);
     }
 
+    #[test]
+    fn test_insert_messages_report_skipped_uuids() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+
+        let messages = create_test_messages(5);
+        db.insert_messages("session-001", &messages[1..3]).unwrap();
+
+        // uuid-1、uuid-2 已入库，uuid-4 在同一批次中出现两次
+        let mut batch = messages.clone();
+        batch.push(messages[4].clone());
+        let report = db.insert_messages_report("session-001", &batch).unwrap();
+
+        assert_eq!(report.inserted, 3);
+        assert_eq!(report.new_ids.len(), 3);
+        assert_eq!(report.skipped_uuids, vec!["uuid-1", "uuid-2", "uuid-4"]);
+        assert_eq!(db.list_messages("session-001", 100, 0).unwrap().len(), 5);
+
+        // 全部重复
+        let report = db.insert_messages_report("session-001", &messages).unwrap();
+        assert_eq!(report.inserted, 0);
+        assert!(report.new_ids.is_empty());
+        assert_eq!(report.skipped_uuids.len(), 5);
+    }
+
+    #[test]
+    fn test_insert_messages_report_failed_row() {
+        let (db, tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+
+        // 模拟单条消息写入失败
+        let raw = rusqlite::Connection::open(tmp.path().join("test.db")).unwrap();
+        raw.execute_batch(
+            "CREATE TRIGGER reject_uuid_2 BEFORE INSERT ON messages WHEN new.uuid = 'uuid-2'
+             BEGIN SELECT RAISE(ABORT, 'rejected'); END;",
+        )
+        .unwrap();
+
+        let messages = create_test_messages(4);
+        let report = db.insert_messages_report("session-001", &messages).unwrap();
+
+        // 失败的消息单独跳过，同批次的其他消息照常写入
+        assert_eq!(report.inserted, 3);
+        assert_eq!(report.new_ids.len(), 3);
+        assert!(report.skipped_uuids.is_empty());
+        assert_eq!(report.failed_uuids, vec!["uuid-2"]);
+        let uuids: Vec<String> = db
+            .list_messages("session-001", 100, 0)
+            .unwrap()
+            .into_iter()
+            .map(|m| m.uuid)
+            .collect();
+        assert_eq!(uuids, vec!["uuid-0", "uuid-1", "uuid-3"]);
+        let session = db.get_session("session-001").unwrap().unwrap();
+        assert_eq!(session.message_count, 3);
+    }
+
     #[test]
     fn test_list_messages_pagination() {
         let (db, _tmp) = setup_db();
@@ -291,6 +1278,36 @@ mod message_tests {
         assert_eq!(page2[0].sequence, 3);
     }
 
+    #[test]
+    fn test_get_messages_around() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+        db.insert_messages("session-001", &create_test_messages(10))
+            .unwrap();
+
+        let sequences = |center: i64, before: usize, after: usize| -> Vec<i64> {
+            db.get_messages_around("session-001", center, before, after)
+                .unwrap()
+                .iter()
+                .map(|m| m.sequence)
+                .collect()
+        };
+
+        // 会话中间：前 2 条 + 中心 + 后 3 条，按顺序
+        assert_eq!(sequences(5, 2, 3), vec![3, 4, 5, 6, 7, 8]);
+        // 靠近开头 / 结尾时窗口缩短
+        assert_eq!(sequences(1, 3, 1), vec![0, 1, 2]);
+        assert_eq!(sequences(9, 2, 5), vec![7, 8, 9]);
+        // 只取中心
+        assert_eq!(sequences(4, 0, 0), vec![4]);
+
+        let window = db.get_messages_around("session-001", 5, 1, 1).unwrap();
+        assert_eq!(window[1].uuid, "uuid-5");
+        assert!(window.iter().all(|m| m.raw.is_none()));
+    }
+
     #[test]
     fn test_message_types() {
         let (db, _tmp) = setup_db();
@@ -341,91 +1358,550 @@ mod message_tests {
         assert_eq!(loaded[0].r#type, MessageType::User);
         assert_eq!(loaded[1].r#type, MessageType::Assistant);
     }
-}
 
-// ==================== 增量扫描测试 ====================
+    #[test]
+    fn test_list_messages_omits_raw_by_default() {
+        let (db, _tmp) = setup_db();
 
-mod incremental_scan_tests {
-    use super::*;
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+
+        let mut messages = create_test_messages(2);
+        for msg in &mut messages {
+            msg.raw = Some(format!(r#"{{"uuid":"{}"}}"#, msg.uuid));
+        }
+        db.insert_messages("session-001", &messages).unwrap();
+
+        // 默认不加载 raw
+        let loaded = db.list_messages("session-001", 10, 0).unwrap();
+        assert!(loaded.iter().all(|m| m.raw.is_none()));
+        let loaded = db.get_messages("session-001").unwrap();
+        assert!(loaded.iter().all(|m| m.raw.is_none()));
+
+        // 显式加载
+        let loaded = db
+            .list_messages_ordered("session-001", 10, 0, Order::Asc, true)
+            .unwrap();
+        assert_eq!(loaded[0].raw.as_deref(), Some(r#"{"uuid":"uuid-0"}"#));
+        let loaded = db
+            .get_messages_with_options("session-001", Some(1), Order::Desc, true)
+            .unwrap();
+        assert_eq!(loaded[0].raw.as_deref(), Some(r#"{"uuid":"uuid-1"}"#));
+
+        // 按 uuid 单独获取
+        assert_eq!(
+            db.get_message_raw("uuid-1").unwrap().as_deref(),
+            Some(r#"{"uuid":"uuid-1"}"#)
+        );
+        assert_eq!(db.get_message_raw("missing").unwrap(), None);
+    }
+
+    /// 模拟 compact 改写历史：保持 uuid，修改内容
+    fn rewrite_message(messages: &mut [MessageInput], index: usize) {
+        messages[index].content_text = "Rewritten content".to_string();
+        messages[index].content_full = "Rewritten content".to_string();
+        messages[index].raw = Some(r#"{"uuid":"uuid-1","rewritten":true}"#.to_string());
+    }
 
     #[test]
-    fn test_incremental_scan_first_time() {
+    fn test_revision_detected_keeps_old_row() {
         let (db, _tmp) = setup_db();
 
         let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
 
-        let messages: Vec<MessageInput> = (0..5)
-            .map(|i| MessageInput {
-                uuid: format!("uuid-{}", i),
-                r#type: MessageType::User,
-                content_text: format!("Content {}", i),
-                content_full: format!("Content {}", i),
-                timestamp: 1000000 + i as i64 * 1000,
-                sequence: i as i64,
-                source: None,
-                channel: None,
-                model: None,
-                tool_call_id: None,
-                tool_name: None,
-                tool_args: None,
-                raw: None,
-                approval_status: None,
-                approval_resolved_at: None,
-            })
-            .collect();
+        let mut messages = create_test_messages(3);
+        let (inserted, _, revisions) = db
+            .insert_messages_audited("session-001", &messages, false)
+            .unwrap();
+        assert_eq!(inserted, 3);
+        assert_eq!(revisions, 0);
 
-        // 首次扫描应该全量插入
-        let inserted = db
-            .scan_session_incremental("session-001", project_id, messages)
+        // 重采集：uuid-1 内容被改写
+        rewrite_message(&mut messages, 1);
+        let (inserted, _, revisions) = db
+            .insert_messages_audited("session-001", &messages, false)
             .unwrap();
-        assert_eq!(inserted, 5);
+        assert_eq!(inserted, 0);
+        assert_eq!(revisions, 1);
+
+        let revs = db.list_message_revisions("session-001").unwrap();
+        assert_eq!(revs.len(), 1);
+        assert_eq!(revs[0].uuid, "uuid-1");
+        assert_ne!(revs[0].old_hash, revs[0].new_hash);
+        assert!(!revs[0].applied);
+        assert!(revs[0].new_raw.as_deref().unwrap().contains("rewritten"));
+
+        // 默认保留旧行
+        let loaded = db.list_messages("session-001", 10, 0).unwrap();
+        assert_eq!(loaded[1].content_full, "Message content 1");
+
+        // 同一变更再次采集不重复记录
+        let (_, _, revisions) = db
+            .insert_messages_audited("session-001", &messages, false)
+            .unwrap();
+        assert_eq!(revisions, 0);
+        assert_eq!(db.list_message_revisions("session-001").unwrap().len(), 1);
     }
 
     #[test]
-    fn test_incremental_scan_with_checkpoint() {
+    fn test_revision_detected_updates_in_place() {
         let (db, _tmp) = setup_db();
 
         let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
 
-        // 第一批消息
-        let messages1: Vec<MessageInput> = (0..3)
-            .map(|i| MessageInput {
-                uuid: format!("uuid-{}", i),
-                r#type: MessageType::User,
-                content_text: format!("Content {}", i),
-                content_full: format!("Content {}", i),
-                timestamp: 1000000 + i as i64 * 1000,
-                sequence: i as i64,
-                source: None,
-                channel: None,
-                model: None,
-                tool_call_id: None,
-                tool_name: None,
-                tool_args: None,
-                raw: None,
-                approval_status: None,
-                approval_resolved_at: None,
-            })
+        let mut messages = create_test_messages(3);
+        db.insert_messages_audited("session-001", &messages, true)
+            .unwrap();
+        let ids: Vec<i64> = db
+            .list_messages("session-001", 10, 0)
+            .unwrap()
+            .iter()
+            .map(|m| m.id)
             .collect();
+        db.mark_messages_indexed(&ids).unwrap();
 
-        db.scan_session_incremental("session-001", project_id, messages1)
+        rewrite_message(&mut messages, 1);
+        let (_, _, revisions) = db
+            .insert_messages_audited("session-001", &messages, true)
             .unwrap();
+        assert_eq!(revisions, 1);
 
-        // 第二批消息（包含旧消息 + 新消息）
-        let messages2: Vec<MessageInput> = (0..6)
-            .map(|i| MessageInput {
-                uuid: format!("uuid-{}", i),
-                r#type: MessageType::User,
-                content_text: format!("Content {}", i),
-                content_full: format!("Content {}", i),
-                timestamp: 1000000 + i as i64 * 1000,
-                sequence: i as i64,
-                source: None,
-                channel: None,
-                model: None,
-                tool_call_id: None,
-                tool_name: None,
-                tool_args: None,
+        let revs = db.list_message_revisions("session-001").unwrap();
+        assert!(revs[0].applied);
+
+        // 内容被原地更新，并等待重新向量化
+        let loaded = db.list_messages("session-001", 10, 0).unwrap();
+        assert_eq!(loaded[1].content_full, "Rewritten content");
+        assert!(!loaded[1].vector_indexed);
+        assert_eq!(db.count_unindexed_messages().unwrap(), 1);
+
+        // 更新后内容一致，不再产生修订
+        let (_, _, revisions) = db
+            .insert_messages_audited("session-001", &messages, true)
+            .unwrap();
+        assert_eq!(revisions, 0);
+    }
+
+    #[test]
+    fn test_insert_messages_conflict_policy() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+
+        let mut messages = create_test_messages(3);
+        let (inserted, _, updated) = db
+            .insert_messages_with_policy("session-001", &messages, ConflictPolicy::default())
+            .unwrap();
+        assert_eq!((inserted, updated), (3, 0));
+        let ids: Vec<i64> = db
+            .list_messages("session-001", 10, 0)
+            .unwrap()
+            .iter()
+            .map(|m| m.id)
+            .collect();
+        db.mark_messages_indexed(&ids).unwrap();
+
+        // Ignore：已存在的 uuid 保持不变
+        rewrite_message(&mut messages, 1);
+        let (inserted, _, updated) = db
+            .insert_messages_with_policy("session-001", &messages, ConflictPolicy::Ignore)
+            .unwrap();
+        assert_eq!((inserted, updated), (0, 0));
+        let loaded = db.list_messages("session-001", 10, 0).unwrap();
+        assert_eq!(loaded[1].content_full, "Message content 1");
+        assert!(loaded[1].vector_indexed);
+
+        // UpdateContent：刷新内容并重置向量索引，不重复计数
+        let (inserted, _, updated) = db
+            .insert_messages_with_policy("session-001", &messages, ConflictPolicy::UpdateContent)
+            .unwrap();
+        assert_eq!((inserted, updated), (0, 1));
+        let loaded = db.list_messages("session-001", 10, 0).unwrap();
+        assert_eq!(loaded.len(), 3);
+        assert_eq!(loaded[1].content_full, "Rewritten content");
+        assert_eq!(loaded[1].content_text, "Rewritten content");
+        assert!(!loaded[1].vector_indexed);
+        assert!(loaded[0].vector_indexed && loaded[2].vector_indexed);
+        assert_eq!(db.get_session_message_count("session-001").unwrap(), 3);
+        // 不记录修订
+        assert!(db.list_message_revisions("session-001").unwrap().is_empty());
+
+        // 只有审批状态变化：更新状态，内容未变不重置向量索引
+        db.mark_messages_indexed(&[loaded[1].id]).unwrap();
+        messages[1].approval_status = Some(ApprovalStatus::Approved);
+        let (_, _, updated) = db
+            .insert_messages_with_policy("session-001", &messages, ConflictPolicy::UpdateContent)
+            .unwrap();
+        assert_eq!(updated, 1);
+        let loaded = db.list_messages("session-001", 10, 0).unwrap();
+        assert_eq!(loaded[1].approval_status, Some(ApprovalStatus::Approved));
+        assert!(loaded[1].vector_indexed);
+
+        // 内容一致时不算更新
+        let (_, _, updated) = db
+            .insert_messages_with_policy("session-001", &messages, ConflictPolicy::UpdateContent)
+            .unwrap();
+        assert_eq!(updated, 0);
+    }
+
+    #[test]
+    fn test_insert_messages_does_not_audit() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+
+        let mut messages = create_test_messages(2);
+        db.insert_messages("session-001", &messages).unwrap();
+        rewrite_message(&mut messages, 1);
+        db.insert_messages("session-001", &messages).unwrap();
+
+        assert!(db.list_message_revisions("session-001").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_oversized_content_truncated() {
+        let tmp = TempDir::new().unwrap();
+        let config = DbConfig::local(tmp.path().join("test.db")).with_max_content_bytes(20);
+        let db = SessionDB::connect(config).unwrap();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+
+        let mut messages = create_test_messages(2);
+        // 多字节字符跨越上限，截断需落在字符边界
+        messages[0].content_full = "中文内容".repeat(100);
+        messages[0].content_text = messages[0].content_full.clone();
+        messages[0].raw = Some(messages[0].content_full.clone());
+        db.insert_messages("session-001", &messages).unwrap();
+
+        let loaded = db
+            .list_messages_ordered("session-001", 10, 0, Order::Asc, true)
+            .unwrap();
+        assert!(loaded[0].truncated);
+        assert_eq!(loaded[0].content_full, "中文内容中文");
+        assert_eq!(loaded[0].content_text, "中文内容中文");
+        // raw 保留完整
+        assert_eq!(loaded[0].raw.as_ref().unwrap().len(), 1200);
+
+        // 未超过上限的消息不受影响
+        assert!(!loaded[1].truncated);
+        assert_eq!(loaded[1].content_full, "Message content 1");
+    }
+
+    #[test]
+    fn test_delete_indexed_message_creates_tombstone() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+        let (_, ids) = db
+            .insert_messages("session-001", &create_test_messages(3))
+            .unwrap();
+        db.mark_messages_indexed(&ids[..1]).unwrap();
+
+        assert_eq!(db.delete_session("session-001").unwrap(), 3);
+        assert!(!db.session_exists("session-001").unwrap());
+        assert_eq!(db.count_pending_vector_tombstones().unwrap(), 1);
+
+        // 未确认前重复读取
+        let tombstones = db.drain_vector_tombstones(10).unwrap();
+        assert_eq!(tombstones.len(), 1);
+        assert_eq!(tombstones[0].uuid, "uuid-0");
+        assert_eq!(tombstones[0].message_id, ids[0]);
+        assert_eq!(db.drain_vector_tombstones(10).unwrap().len(), 1);
+
+        // 确认后不再返回
+        let tombstone_ids: Vec<i64> = tombstones.iter().map(|t| t.id).collect();
+        assert_eq!(db.ack_vector_tombstones(&tombstone_ids).unwrap(), 1);
+        assert!(db.drain_vector_tombstones(10).unwrap().is_empty());
+        assert_eq!(db.count_pending_vector_tombstones().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_delete_unindexed_messages_no_tombstone() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+        let (_, ids) = db
+            .insert_messages("session-001", &create_test_messages(2))
+            .unwrap();
+        // 索引失败（-1）的消息同样不在向量库中
+        db.mark_message_index_failed(ids[1]).unwrap();
+
+        db.delete_session("session-001").unwrap();
+        assert!(db.drain_vector_tombstones(10).unwrap().is_empty());
+        assert_eq!(db.count_pending_vector_tombstones().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_approvals_summary() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+        db.upsert_session("session-002", project_id).unwrap();
+
+        let pending = |prefix: &str, count: usize| {
+            let mut messages = create_test_messages(count);
+            for m in &mut messages {
+                m.uuid = format!("{}-{}", prefix, m.uuid);
+                m.approval_status = Some(ApprovalStatus::Pending);
+            }
+            messages
+        };
+        db.insert_messages("session-001", &pending("s1", 6))
+            .unwrap();
+        db.insert_messages("session-002", &pending("s2", 2))
+            .unwrap();
+        // 不需要审批的消息不计入
+        db.insert_messages("session-002", &create_test_messages(3))
+            .unwrap();
+        assert_eq!(db.approvals_summary(None).unwrap().len(), 1);
+
+        db.batch_update_approval_status(
+            &["s1-uuid-0".to_string(), "s1-uuid-1".to_string()],
+            ApprovalStatus::Approved,
+            2000,
+        )
+        .unwrap();
+        db.update_approval_status("s1-uuid-2", ApprovalStatus::Rejected, 2000)
+            .unwrap();
+        db.update_approval_status("s2-uuid-0", ApprovalStatus::Timeout, 2000)
+            .unwrap();
+
+        let all = db.approvals_summary(None).unwrap();
+        assert_eq!(all.get(&ApprovalStatus::Pending), Some(&4));
+        assert_eq!(all.get(&ApprovalStatus::Approved), Some(&2));
+        assert_eq!(all.get(&ApprovalStatus::Rejected), Some(&1));
+        assert_eq!(all.get(&ApprovalStatus::Timeout), Some(&1));
+        assert_eq!(
+            all[&ApprovalStatus::Pending],
+            db.count_pending_approvals(None).unwrap()
+        );
+
+        let session = db.approvals_summary(Some("session-001")).unwrap();
+        assert_eq!(session.get(&ApprovalStatus::Pending), Some(&3));
+        assert_eq!(session.get(&ApprovalStatus::Approved), Some(&2));
+        assert_eq!(session.get(&ApprovalStatus::Rejected), Some(&1));
+        assert_eq!(session.get(&ApprovalStatus::Timeout), None);
+
+        assert!(db.approvals_summary(Some("missing")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_pending_approval_digest() {
+        let (db, _tmp) = setup_db();
+
+        let app = db
+            .get_or_create_project("app", "/work/app", "claude")
+            .unwrap();
+        let ops = db
+            .get_or_create_project("ops", "/work/ops", "claude")
+            .unwrap();
+        db.upsert_session("app-1", app).unwrap();
+        db.upsert_session("app-2", app).unwrap();
+        db.upsert_session("ops-1", ops).unwrap();
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+        let minutes_ago = |m: i64| now - m * 60_000;
+        let pending = |uuid: &str, tool: Option<&str>, timestamp: i64| {
+            let mut m = create_test_messages(1).remove(0);
+            m.uuid = uuid.to_string();
+            m.tool_name = tool.map(str::to_string);
+            m.timestamp = timestamp;
+            m.approval_status = Some(ApprovalStatus::Pending);
+            m
+        };
+        db.insert_messages(
+            "app-1",
+            &[
+                pending("a1", Some("Bash"), minutes_ago(12)),
+                pending("a2", Some("Bash"), minutes_ago(1)),
+            ],
+        )
+        .unwrap();
+        db.insert_messages(
+            "app-2",
+            &[
+                pending("a3", Some("Edit"), minutes_ago(5)),
+                pending("a4", None, minutes_ago(3)),
+            ],
+        )
+        .unwrap();
+        db.insert_messages("ops-1", &[pending("o1", Some("Bash"), minutes_ago(30))])
+            .unwrap();
+        // 已审批和不需要审批的消息不计入
+        let mut approved = pending("o2", Some("Bash"), minutes_ago(40));
+        approved.approval_status = Some(ApprovalStatus::Approved);
+        db.insert_messages("ops-1", &[approved]).unwrap();
+
+        // 按最早的待审批时间排序：ops 在前
+        let digest = db.pending_approval_digest(None).unwrap();
+        assert_eq!(digest.len(), 2);
+        assert_eq!(digest[0].project_id, ops);
+        assert_eq!(digest[0].project_name, "ops");
+        assert_eq!(digest[0].count, 1);
+        assert_eq!(digest[1].project_id, app);
+        assert_eq!(digest[1].count, 4);
+        assert_eq!(digest[1].oldest_at, minutes_ago(12));
+        assert_eq!(digest[1].newest_at, minutes_ago(1));
+        assert_eq!(digest[1].tools.get("Bash"), Some(&2));
+        assert_eq!(digest[1].tools.get("Edit"), Some(&1));
+        assert_eq!(digest[1].tools.get(""), Some(&1));
+
+        // 只统计等待超过 4 分钟的审批
+        let digest = db.pending_approval_digest(Some(4 * 60_000)).unwrap();
+        assert_eq!(digest.len(), 2);
+        assert_eq!(digest[1].count, 2);
+        assert_eq!(digest[1].newest_at, minutes_ago(5));
+        assert_eq!(digest[1].tools.len(), 2);
+        assert!(!digest[1].tools.contains_key(""));
+
+        let digest = db.pending_approval_digest(Some(20 * 60_000)).unwrap();
+        assert_eq!(digest.len(), 1);
+        assert_eq!(digest[0].project_id, ops);
+
+        db.update_approval_status("o1", ApprovalStatus::Approved, now)
+            .unwrap();
+        assert!(db
+            .pending_approval_digest(Some(20 * 60_000))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_list_pending_tool_call_ids() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+        db.upsert_session("session-002", project_id).unwrap();
+
+        let tool_calls = |prefix: &str, count: usize| {
+            let mut messages = create_test_messages(count);
+            for m in &mut messages {
+                m.tool_call_id = Some(format!("{}-call-{}", prefix, m.sequence));
+                m.uuid = format!("{}-{}", prefix, m.uuid);
+                m.approval_status = Some(ApprovalStatus::Pending);
+            }
+            messages
+        };
+        db.insert_messages("session-001", &tool_calls("s1", 2))
+            .unwrap();
+        db.insert_messages("session-002", &tool_calls("s2", 2))
+            .unwrap();
+        // 无 tool_call_id 的待审批消息不返回
+        let mut orphan = create_test_messages(1);
+        orphan[0].uuid = "orphan".to_string();
+        orphan[0].approval_status = Some(ApprovalStatus::Pending);
+        db.insert_messages("session-002", &orphan).unwrap();
+
+        db.update_approval_status("s1-uuid-0", ApprovalStatus::Approved, 2000)
+            .unwrap();
+
+        assert_eq!(
+            db.list_pending_tool_call_ids(10).unwrap(),
+            vec![
+                ("s1-call-1".to_string(), "session-001".to_string()),
+                ("s2-call-0".to_string(), "session-002".to_string()),
+                ("s2-call-1".to_string(), "session-002".to_string()),
+            ]
+        );
+        assert_eq!(db.list_pending_tool_call_ids(1).unwrap().len(), 1);
+    }
+}
+
+// ==================== 增量扫描测试 ====================
+
+mod incremental_scan_tests {
+    use super::*;
+
+    #[test]
+    fn test_incremental_scan_first_time() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+
+        let messages: Vec<MessageInput> = (0..5)
+            .map(|i| MessageInput {
+                uuid: format!("uuid-{}", i),
+                r#type: MessageType::User,
+                content_text: format!("Content {}", i),
+                content_full: format!("Content {}", i),
+                timestamp: 1000000 + i as i64 * 1000,
+                sequence: i as i64,
+                source: None,
+                channel: None,
+                model: None,
+                tool_call_id: None,
+                tool_name: None,
+                tool_args: None,
+                raw: None,
+                approval_status: None,
+                approval_resolved_at: None,
+            })
+            .collect();
+
+        // 首次扫描应该全量插入
+        let inserted = db
+            .scan_session_incremental("session-001", project_id, messages)
+            .unwrap();
+        assert_eq!(inserted, 5);
+    }
+
+    #[test]
+    fn test_incremental_scan_with_checkpoint() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+
+        // 第一批消息
+        let messages1: Vec<MessageInput> = (0..3)
+            .map(|i| MessageInput {
+                uuid: format!("uuid-{}", i),
+                r#type: MessageType::User,
+                content_text: format!("Content {}", i),
+                content_full: format!("Content {}", i),
+                timestamp: 1000000 + i as i64 * 1000,
+                sequence: i as i64,
+                source: None,
+                channel: None,
+                model: None,
+                tool_call_id: None,
+                tool_name: None,
+                tool_args: None,
+                raw: None,
+                approval_status: None,
+                approval_resolved_at: None,
+            })
+            .collect();
+
+        db.scan_session_incremental("session-001", project_id, messages1)
+            .unwrap();
+
+        // 第二批消息（包含旧消息 + 新消息）
+        let messages2: Vec<MessageInput> = (0..6)
+            .map(|i| MessageInput {
+                uuid: format!("uuid-{}", i),
+                r#type: MessageType::User,
+                content_text: format!("Content {}", i),
+                content_full: format!("Content {}", i),
+                timestamp: 1000000 + i as i64 * 1000,
+                sequence: i as i64,
+                source: None,
+                channel: None,
+                model: None,
+                tool_call_id: None,
+                tool_name: None,
+                tool_args: None,
                 raw: None,
                 approval_status: None,
                 approval_resolved_at: None,
@@ -656,6 +2132,55 @@ mod incremental_scan_tests {
         let max_seq = db.get_session_max_sequence("nonexistent").unwrap();
         assert_eq!(max_seq, None);
     }
+
+    #[cfg(feature = "writer")]
+    #[test]
+    fn test_session_resume_info_after_collect() {
+        let (db, tmp) = setup_db();
+        let projects = tmp.path().join(".claude/projects");
+        let dir = projects.join("-tmp-resume");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("resume-session.jsonl");
+        std::fs::write(
+            &path,
+            "{\"type\":\"user\",\"uuid\":\"r-u1\",\"sessionId\":\"resume-session\",\"cwd\":\"/tmp/resume\",\"timestamp\":\"2025-01-01T00:00:00Z\",\"message\":{\"role\":\"user\",\"content\":\"hello\"}}\n",
+        )
+        .unwrap();
+
+        assert_eq!(db.get_session_resume_info("resume-session").unwrap(), None);
+        Collector::new(&db)
+            .with_claude_path(projects)
+            .collect_by_path(path.to_str().unwrap())
+            .unwrap();
+
+        let info = db
+            .get_session_resume_info("resume-session")
+            .unwrap()
+            .unwrap();
+        let file_len = std::fs::metadata(&path).unwrap().len() as i64;
+        assert_eq!(info.file_offset, file_len);
+        assert_eq!(info.file_size, Some(file_len));
+        assert!(info.file_mtime.is_some());
+        assert!(info.file_inode.is_some());
+        assert_eq!(info.session_path.as_deref(), path.to_str());
+        assert_eq!(info.encoded_dir_name.as_deref(), Some("-tmp-resume"));
+        assert_eq!(info.project_path, "/tmp/resume");
+
+        // 与增量状态一致
+        let state = db
+            .get_session_incremental_state("resume-session")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            state,
+            (
+                info.file_offset,
+                info.file_mtime,
+                info.file_size,
+                info.file_inode
+            )
+        );
+    }
 }
 
 // ==================== 搜索测试 ====================
@@ -848,42 +2373,95 @@ mod search_tests {
         let results = db.search_fts("test", 100).unwrap();
         assert_eq!(results.len(), 10);
     }
-}
-
-// ==================== 统计测试 ====================
-
-mod stats_tests {
-    use super::*;
 
     #[test]
-    fn test_stats_empty_db() {
+    fn test_fts_search_count() {
         let (db, _tmp) = setup_db();
 
-        let stats = db.get_stats().unwrap();
-        assert_eq!(stats.project_count, 0);
-        assert_eq!(stats.session_count, 0);
-        assert_eq!(stats.message_count, 0);
-    }
+        let project_a = db.get_or_create_project("a", "/a", "claude").unwrap();
+        let project_b = db.get_or_create_project("b", "/b", "claude").unwrap();
+        // (session, 项目, 命中数)
+        for (session_id, project_id, hits) in
+            [("session-a", project_a, 7), ("session-b", project_b, 5)]
+        {
+            db.upsert_session(session_id, project_id).unwrap();
+            let messages: Vec<MessageInput> = (0..hits + 2)
+                .map(|i| {
+                    let content = if i < hits {
+                        format!("count match {}", i)
+                    } else {
+                        format!("unrelated {}", i)
+                    };
+                    MessageInput {
+                        uuid: format!("{}-{}", session_id, i),
+                        r#type: MessageType::User,
+                        content_text: content.clone(),
+                        content_full: content,
+                        timestamp: 1000 + i as i64,
+                        sequence: i as i64,
+                        source: None,
+                        channel: None,
+                        model: None,
+                        tool_call_id: None,
+                        tool_name: None,
+                        tool_args: None,
+                        raw: None,
+                        approval_status: None,
+                        approval_resolved_at: None,
+                    }
+                })
+                .collect();
+            db.insert_messages(session_id, &messages).unwrap();
+        }
 
-    #[test]
-    fn test_stats_with_data() {
-        let (db, _tmp) = setup_db();
+        // 总数与 limit 无关
+        let options = SearchGroupOptions::default();
+        assert_eq!(db.search_count("match", &options).unwrap(), 12);
+        assert_eq!(db.search_fts("match", 3).unwrap().len(), 3);
+        assert_eq!(db.search_fts("match", 100).unwrap().len(), 12);
 
-        // 创建数据
-        let p1 = db.get_or_create_project("p1", "/p1", "claude").unwrap();
-        let p2 = db.get_or_create_project("p2", "/p2", "claude").unwrap();
+        // 过滤条件与搜索一致
+        let options = SearchGroupOptions {
+            project_id: Some(project_b),
+            ..Default::default()
+        };
+        assert_eq!(db.search_count("match", &options).unwrap(), 5);
+        let options = SearchGroupOptions {
+            start_timestamp: Some(1005),
+            ..Default::default()
+        };
+        assert_eq!(db.search_count("match", &options).unwrap(), 2);
 
-        db.upsert_session("s1", p1).unwrap();
-        db.upsert_session("s2", p1).unwrap();
-        db.upsert_session("s3", p2).unwrap();
+        assert_eq!(
+            db.search_count("nonexistent", &SearchGroupOptions::default())
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            db.search_count("   ", &SearchGroupOptions::default())
+                .unwrap(),
+            0
+        );
+    }
 
-        let messages: Vec<MessageInput> = (0..5)
-            .map(|i| MessageInput {
-                uuid: format!("uuid-{}", i),
+    #[test]
+    fn test_search_with_date_range() {
+        let (db, _tmp) = setup_db();
+        let project_id = db.get_or_create_project("p", "/p", "claude").unwrap();
+        db.upsert_session("session-range", project_id).unwrap();
+
+        let day = 24 * 60 * 60 * 1000;
+        let now = chrono::Utc::now().timestamp_millis();
+        let timestamps = [now, now - 3 * day, now - 30 * day];
+        let messages: Vec<MessageInput> = timestamps
+            .iter()
+            .enumerate()
+            .map(|(i, &timestamp)| MessageInput {
+                uuid: format!("range-{}", i),
                 r#type: MessageType::User,
-                content_text: "test".to_string(),
-                content_full: "test".to_string(),
-                timestamp: i as i64,
+                content_text: format!("ranged {}", i),
+                content_full: format!("ranged {}", i),
+                timestamp,
                 sequence: i as i64,
                 source: None,
                 channel: None,
@@ -896,33 +2474,60 @@ mod stats_tests {
                 approval_resolved_at: None,
             })
             .collect();
+        db.insert_messages("session-range", &messages).unwrap();
 
-        db.insert_messages("s1", &messages).unwrap().0;
+        let results = db
+            .search_fts_in_range(
+                "ranged",
+                10,
+                None,
+                SearchOrderBy::TimeDesc,
+                DateRange::last_n_days(7, 0),
+                None,
+            )
+            .unwrap();
+        let found: Vec<Option<i64>> = results.iter().map(|r| r.timestamp).collect();
+        assert_eq!(found, vec![Some(timestamps[0]), Some(timestamps[1])]);
 
-        let stats = db.get_stats().unwrap();
-        assert_eq!(stats.project_count, 2);
-        assert_eq!(stats.session_count, 3);
-        assert_eq!(stats.message_count, 5);
-    }
-}
+        let options = SearchGroupOptions {
+            date_range: Some(DateRangeSpec::LastNDays(7)),
+            tz_offset_minutes: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(db.search_count("ranged", &options).unwrap(), 2);
 
-// ==================== 边界情况测试 ====================
+        // 与显式的时间范围取交集
+        let narrowed = SearchGroupOptions {
+            start_timestamp: Some(now - day),
+            ..options.clone()
+        };
+        assert_eq!(db.search_count("ranged", &narrowed).unwrap(), 1);
 
-mod edge_case_tests {
-    use super::*;
+        let today = SearchGroupOptions {
+            date_range: Some(DateRangeSpec::Today),
+            ..options
+        };
+        let results = db.search_fts_filtered("ranged", 10, &today, None).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].timestamp, Some(now));
+    }
 
     #[test]
-    fn test_empty_content_message() {
-        let (db, _tmp) = setup_db();
+    fn test_fts_failure_keeps_message_row() {
+        let (db, tmp) = setup_db();
 
         let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
         db.upsert_session("session-001", project_id).unwrap();
 
+        // 模拟 FTS 索引不可用：写入消息不应失败
+        let raw = rusqlite::Connection::open(tmp.path().join("test.db")).unwrap();
+        raw.execute_batch("DROP TABLE messages_fts").unwrap();
+
         let messages = vec![MessageInput {
-            uuid: "uuid-empty".to_string(),
+            uuid: "uuid-1".to_string(),
             r#type: MessageType::User,
-            content_text: "".to_string(), // 空内容
-            content_full: "".to_string(),
+            content_text: "backlog needle".to_string(),
+            content_full: "backlog needle".to_string(),
             timestamp: 1000,
             sequence: 0,
             source: None,
@@ -935,28 +2540,299 @@ mod edge_case_tests {
             approval_status: None,
             approval_resolved_at: None,
         }];
+        assert_eq!(db.insert_messages("session-001", &messages).unwrap().0, 1);
+        assert_eq!(db.list_messages("session-001", 10, 0).unwrap().len(), 1);
+        assert_eq!(db.fts_backlog_count().unwrap(), 1);
+
+        // 索引恢复后补建
+        raw.execute_batch(
+            "CREATE VIRTUAL TABLE messages_fts USING fts5(
+                content_full, content_text,
+                content='messages', content_rowid='id', tokenize='unicode61'
+            )",
+        )
+        .unwrap();
+        assert_eq!(db.fts_backlog_count().unwrap(), 1);
+        assert_eq!(db.reindex_fts_backlog(100).unwrap(), 1);
+        assert_eq!(db.fts_backlog_count().unwrap(), 0);
+        assert_eq!(
+            db.search_count("needle", &SearchGroupOptions::default())
+                .unwrap(),
+            1
+        );
+    }
 
-        let inserted = db.insert_messages("session-001", &messages).unwrap().0;
-        assert_eq!(inserted, 1);
+    #[test]
+    fn test_fts_failure_keeps_unrelated_updates() {
+        let (db, tmp) = setup_db();
 
-        let loaded = db.list_messages("session-001", 10, 0).unwrap();
-        assert_eq!(loaded[0].content_text, "");
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+        let (_, ids) = db
+            .insert_messages("session-001", &[pending_tool_call("uuid-1", 0)])
+            .unwrap();
+
+        // 模拟 FTS 索引不可用：与内容无关的更新不写 FTS，不应失败
+        let raw = rusqlite::Connection::open(tmp.path().join("test.db")).unwrap();
+        raw.execute_batch("DROP TABLE messages_fts").unwrap();
+        assert_eq!(db.mark_messages_indexed(&ids).unwrap(), 1);
+        assert_eq!(
+            db.update_approval_status("uuid-1", ApprovalStatus::Approved, 2000)
+                .unwrap(),
+            1
+        );
     }
 
     #[test]
-    fn test_unicode_content() {
+    fn test_fts_search_grouped() {
         let (db, _tmp) = setup_db();
 
-        let project_id = db
-            .get_or_create_project("测试项目", "/路径/中文", "claude")
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        // (session_id, 命中数, 起始时间戳)
+        let sessions = [
+            ("session-a", 3, 2000),
+            ("session-b", 1, 1000),
+            ("session-c", 2, 3000),
+        ];
+        for (session_id, hits, base_ts) in sessions {
+            db.upsert_session(session_id, project_id).unwrap();
+            let messages: Vec<MessageInput> = (0..hits + 1)
+                .map(|i| {
+                    // 最后一条不命中
+                    let content = if i < hits {
+                        format!("grouped search hit {}", i)
+                    } else {
+                        "unrelated message".to_string()
+                    };
+                    MessageInput {
+                        uuid: format!("{}-{}", session_id, i),
+                        r#type: MessageType::User,
+                        content_text: content.clone(),
+                        content_full: content,
+                        timestamp: base_ts + i as i64,
+                        sequence: i as i64,
+                        source: None,
+                        channel: None,
+                        model: None,
+                        tool_call_id: None,
+                        tool_name: None,
+                        tool_args: None,
+                        raw: None,
+                        approval_status: None,
+                        approval_resolved_at: None,
+                    }
+                })
+                .collect();
+            db.insert_messages(session_id, &messages).unwrap();
+        }
+
+        // 按最新命中排序：c (3001) > a (2002) > b (1000)
+        let options = SearchGroupOptions {
+            order_by: SearchOrderBy::TimeDesc,
+            ..Default::default()
+        };
+        let groups = db.search_fts_grouped("grouped", 10, 2, &options).unwrap();
+        let order: Vec<_> = groups
+            .iter()
+            .map(|g| g.session.session_id.as_str())
+            .collect();
+        assert_eq!(order, vec!["session-c", "session-a", "session-b"]);
+        let counts: Vec<_> = groups.iter().map(|g| g.hit_count).collect();
+        assert_eq!(counts, vec![2, 3, 1]);
+
+        // 每个会话最多 2 条命中，会话内按时间倒序
+        let session_a = &groups[1];
+        assert_eq!(session_a.hits.len(), 2);
+        assert_eq!(session_a.hits[0].timestamp, Some(2002));
+        assert_eq!(session_a.hits[1].timestamp, Some(2001));
+        assert!(session_a.hits.iter().all(|h| h.snippet.contains("<mark>")));
+        assert_eq!(session_a.session.project_path, "/path");
+
+        // 会话数截断
+        let groups = db.search_fts_grouped("grouped", 2, 1, &options).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert!(groups.iter().all(|g| g.hits.len() == 1));
+        assert_eq!(groups[1].hit_count, 3);
+
+        // 按分数排序：会话最佳分数递增（bm25 越小越相关）
+        let groups = db
+            .search_fts_grouped("grouped", 10, 5, &SearchGroupOptions::default())
             .unwrap();
-        db.upsert_session("session-001", project_id).unwrap();
+        assert_eq!(groups.len(), 3);
+        assert!(groups
+            .windows(2)
+            .all(|w| w[0].best_score <= w[1].best_score));
+        assert_eq!(groups.iter().map(|g| g.hits.len()).sum::<usize>(), 6);
+
+        assert!(db
+            .search_fts_grouped("nonexistent", 10, 5, &options)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_fts_search_max_per_project() {
+        let (db, _tmp) = setup_db();
+
+        // (项目, 命中数, 起始时间戳)：大项目最新
+        let projects = [("big", 20, 3000), ("mid", 5, 2000), ("small", 1, 1000)];
+        let mut ids = Vec::new();
+        for (name, hits, base_ts) in projects {
+            let project_id = db
+                .get_or_create_project(name, &format!("/{}", name), "claude")
+                .unwrap();
+            ids.push(project_id);
+            let session_id = format!("session-{}", name);
+            db.upsert_session(&session_id, project_id).unwrap();
+            let messages: Vec<MessageInput> = (0..hits)
+                .map(|i| MessageInput {
+                    uuid: format!("{}-{}", name, i),
+                    r#type: MessageType::User,
+                    content_text: format!("quota hit {}", i),
+                    content_full: format!("quota hit {}", i),
+                    timestamp: base_ts + i as i64,
+                    sequence: i as i64,
+                    source: None,
+                    channel: None,
+                    model: None,
+                    tool_call_id: None,
+                    tool_name: None,
+                    tool_args: None,
+                    raw: None,
+                    approval_status: None,
+                    approval_resolved_at: None,
+                })
+                .collect();
+            db.insert_messages(&session_id, &messages).unwrap();
+        }
+        let (big, mid, small) = (ids[0], ids[1], ids[2]);
+
+        // 不设配额：全部来自最新的大项目
+        let results = db
+            .search_fts_full("quota", 12, None, SearchOrderBy::TimeDesc, None, None, None)
+            .unwrap();
+        assert!(results.iter().all(|r| r.project_id == big));
+
+        // 配额 4：每个项目最新的 4/4/1 条在前，剩余 3 条按时间从大项目补充
+        let results = db
+            .search_fts_full(
+                "quota",
+                12,
+                None,
+                SearchOrderBy::TimeDesc,
+                None,
+                None,
+                Some(4),
+            )
+            .unwrap();
+        let projects: Vec<i64> = results.iter().map(|r| r.project_id).collect();
+        let mut expected = vec![big; 4];
+        expected.extend([mid; 4]);
+        expected.push(small);
+        expected.extend([big; 3]);
+        assert_eq!(projects, expected);
+        let timestamps: Vec<i64> = results.iter().map(|r| r.timestamp.unwrap()).collect();
+        assert_eq!(
+            timestamps,
+            vec![3019, 3018, 3017, 3016, 2004, 2003, 2002, 2001, 1000, 3015, 3014, 3013]
+        );
+
+        // 按分数排序时配额同样生效：前 9 条覆盖全部项目
+        let results = db
+            .search_fts_full("quota", 9, None, SearchOrderBy::Score, None, None, Some(4))
+            .unwrap();
+        assert_eq!(results.iter().filter(|r| r.project_id == big).count(), 4);
+        assert_eq!(results.iter().filter(|r| r.project_id == mid).count(), 4);
+        assert_eq!(results.iter().filter(|r| r.project_id == small).count(), 1);
+    }
+
+    #[test]
+    fn test_search_without_content() {
+        let tmp = TempDir::new().unwrap();
+        let config = DbConfig::local(tmp.path().join("test.db")).with_snippet_max_chars(40);
+        let db = SessionDB::connect(config).unwrap();
+
+        let project_id = db.get_or_create_project("big", "/big", "claude").unwrap();
+        db.upsert_session("big-session", project_id).unwrap();
+        // 大段工具输出
+        let messages: Vec<_> = (0..5)
+            .map(|i| {
+                let content = format!("needle output {} {}", i, "x ".repeat(50_000));
+                MessageInput {
+                    uuid: format!("big-{}", i),
+                    r#type: MessageType::Tool,
+                    content_text: content.clone(),
+                    content_full: content,
+                    timestamp: 1000 + i,
+                    sequence: i,
+                    source: None,
+                    channel: None,
+                    model: None,
+                    tool_call_id: None,
+                    tool_name: None,
+                    tool_args: None,
+                    raw: None,
+                    approval_status: None,
+                    approval_resolved_at: None,
+                }
+            })
+            .collect();
+        db.insert_messages("big-session", &messages).unwrap();
+
+        // 库 API 默认返回正文
+        let full = db
+            .search_fts_filtered("needle", 10, &SearchGroupOptions::default(), None)
+            .unwrap();
+        let lean_options = SearchGroupOptions {
+            include_content: Some(false),
+            ..Default::default()
+        };
+        let lean = db
+            .search_fts_filtered("needle", 10, &lean_options, None)
+            .unwrap();
+        assert_eq!(full.len(), 5);
+        let ids: Vec<i64> = lean.iter().map(|r| r.message_id).collect();
+        assert_eq!(ids, full.iter().map(|r| r.message_id).collect::<Vec<_>>());
+
+        let full_size = serde_json::to_string(&full).unwrap().len();
+        let lean_size = serde_json::to_string(&lean).unwrap().len();
+        assert!(full_size > 500_000, "{}", full_size);
+        assert!(lean_size < 5_000, "{}", lean_size);
+        for hit in &lean {
+            assert!(hit.content_full.is_empty());
+            assert!(hit.snippet.contains("<mark>needle</mark>"));
+            assert!(hit.snippet.chars().count() <= 40 + "...".len());
+        }
+
+        // 按 ID 取回的正文与完整结果一致
+        let bodies = db.get_messages_by_ids(&ids).unwrap();
+        assert_eq!(bodies.len(), 5);
+        for hit in &full {
+            let body = bodies.iter().find(|m| m.id == hit.message_id).unwrap();
+            assert_eq!(body.content_full, hit.content_full);
+        }
+
+        // 分组搜索同样遵循 include_content
+        let groups = db
+            .search_fts_grouped("needle", 10, 10, &lean_options)
+            .unwrap();
+        assert!(groups
+            .iter()
+            .flat_map(|g| &g.hits)
+            .all(|hit| hit.content_full.is_empty() && hit.snippet.chars().count() <= 43));
+    }
 
+    #[test]
+    fn test_search_field_content_text() {
+        let (db, _tmp) = setup_db();
+        let project_id = db.get_or_create_project("proj", "/proj", "claude").unwrap();
+        db.upsert_session("field-session", project_id).unwrap();
+        // 工具调用框架只出现在 content_full 中
         let messages = vec![MessageInput {
-            uuid: "uuid-unicode".to_string(),
-            r#type: MessageType::User,
-            content_text: "你好世界 🌍 مرحبا العالم 🎉 こんにちは".to_string(),
-            content_full: "你好世界 🌍 مرحبا العالم 🎉 こんにちは".to_string(),
+            uuid: "field-1".to_string(),
+            r#type: MessageType::Assistant,
+            content_text: "refactor the parser".to_string(),
+            content_full: "refactor the parser\n[tool_use: Bash toolframe]".to_string(),
             timestamp: 1000,
             sequence: 0,
             source: None,
@@ -969,35 +2845,63 @@ mod edge_case_tests {
             approval_status: None,
             approval_resolved_at: None,
         }];
+        db.insert_messages("field-session", &messages).unwrap();
 
-        db.insert_messages("session-001", &messages).unwrap().0;
+        // 默认匹配 content_full
+        let full = db
+            .search_fts_filtered("toolframe", 10, &SearchGroupOptions::default(), None)
+            .unwrap();
+        assert_eq!(full.len(), 1);
+        assert!(full[0].snippet.contains("<mark>toolframe</mark>"));
 
-        let loaded = db.list_messages("session-001", 10, 0).unwrap();
-        assert_eq!(
-            loaded[0].content_text,
-            "你好世界 🌍 مرحبا العالم 🎉 こんにちは"
-        );
+        let text_only = SearchGroupOptions {
+            field: SearchField::ContentText,
+            ..Default::default()
+        };
+        assert!(db
+            .search_fts_filtered("toolframe", 10, &text_only, None)
+            .unwrap()
+            .is_empty());
+        assert_eq!(db.search_count("toolframe", &text_only).unwrap(), 0);
+        assert!(db
+            .search_fts_grouped("toolframe", 10, 10, &text_only)
+            .unwrap()
+            .is_empty());
+
+        // 指定项目时 LIKE 补充也只匹配 content_text
+        let in_project = SearchGroupOptions {
+            project_id: Some(project_id),
+            ..text_only.clone()
+        };
+        assert!(db
+            .search_fts_filtered("toolframe", 10, &in_project, None)
+            .unwrap()
+            .is_empty());
 
-        // 项目名也应该正确
-        let projects = db.list_projects().unwrap();
-        assert_eq!(projects[0].name, "测试项目");
+        let hits = db
+            .search_fts_filtered("parser", 10, &text_only, None)
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].snippet, "refactor the <mark>parser</mark>");
     }
+}
 
-    #[test]
-    fn test_very_long_content() {
-        let (db, _tmp) = setup_db();
+// ==================== 搜索记录测试 ====================
 
+#[cfg(feature = "search")]
+mod search_log_tests {
+    use super::*;
+
+    /// 连接数据库并写入一条可搜索的消息
+    fn setup_logged_db(config: DbConfig) -> SessionDB {
+        let db = SessionDB::connect(config).unwrap();
         let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
         db.upsert_session("session-001", project_id).unwrap();
-
-        // 创建 100KB 的内容
-        let long_content = "x".repeat(100 * 1024);
-
-        let messages = vec![MessageInput {
-            uuid: "uuid-long".to_string(),
-            r#type: MessageType::Assistant,
-            content_text: long_content.clone(),
-            content_full: long_content.clone(),
+        let message = MessageInput {
+            uuid: "uuid-1".to_string(),
+            r#type: MessageType::User,
+            content_text: "binary search tree".to_string(),
+            content_full: "binary search tree".to_string(),
             timestamp: 1000,
             sequence: 0,
             source: None,
@@ -1009,75 +2913,329 @@ mod edge_case_tests {
             raw: None,
             approval_status: None,
             approval_resolved_at: None,
-        }];
+        };
+        db.insert_messages("session-001", &[message]).unwrap();
+        db
+    }
 
-        db.insert_messages("session-001", &messages).unwrap().0;
+    #[test]
+    fn test_logging_follows_config_flag() {
+        let tmp = TempDir::new().unwrap();
 
-        let loaded = db.list_messages("session-001", 10, 0).unwrap();
-        assert_eq!(loaded[0].content_text.len(), 100 * 1024);
+        // 默认不记录
+        let db = setup_logged_db(DbConfig::local(tmp.path().join("off.db")));
+        db.search_fts("binary", 10).unwrap();
+        assert!(db.recent_searches(10).unwrap().is_empty());
+
+        let config = DbConfig::local(tmp.path().join("on.db")).with_log_searches(true);
+        let db = setup_logged_db(config);
+        db.search_fts("  Binary   Tree ", 10).unwrap();
+        db.search_fts("nonexistent", 10).unwrap();
+        // 空查询不记录
+        db.search_fts("   ", 10).unwrap();
+
+        let recent = db.recent_searches(10).unwrap();
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].query, "nonexistent");
+        assert_eq!(recent[0].result_count, 0);
+        assert_eq!(recent[1].normalized_query, "binary tree");
+        assert_eq!(recent[1].result_count, 1);
+        assert_eq!(recent[1].project_filter, None);
+
+        let zero = db.zero_result_searches(10).unwrap();
+        assert_eq!(zero.len(), 1);
+        assert_eq!(zero[0].query, "nonexistent");
+
+        assert_eq!(db.clear_search_log().unwrap(), 2);
+        assert!(db.recent_searches(10).unwrap().is_empty());
     }
 
     #[test]
-    fn test_special_characters_in_path() {
-        let (db, _tmp) = setup_db();
+    fn test_search_log_pruned_at_cap() {
+        let tmp = TempDir::new().unwrap();
+        let config = DbConfig::local(tmp.path().join("test.db"))
+            .with_log_searches(true)
+            .with_search_log_cap(3);
+        let db = setup_logged_db(config);
 
-        // 路径包含特殊字符
-        let weird_path = "/path/with spaces/and-dashes/and_underscores/and.dots";
-        let project_id = db
-            .get_or_create_project("test", weird_path, "claude")
-            .unwrap();
+        for query in ["one", "two", "three", "four", "five"] {
+            db.search_fts(query, 10).unwrap();
+        }
 
-        let projects = db.list_projects().unwrap();
-        assert_eq!(projects[0].path, weird_path);
+        let recent = db.recent_searches(10).unwrap();
+        let queries: Vec<&str> = recent.iter().map(|s| s.query.as_str()).collect();
+        assert_eq!(queries, vec!["five", "four", "three"]);
+    }
 
-        // 再次获取应该返回同一个 ID
-        let id2 = db
-            .get_or_create_project("test", weird_path, "claude")
-            .unwrap();
-        assert_eq!(project_id, id2);
+    #[test]
+    fn test_read_only_searches_buffered() {
+        let tmp = TempDir::new().unwrap();
+        let config = DbConfig::local(tmp.path().join("test.db")).with_log_searches(true);
+        let writer = setup_logged_db(config.clone());
+
+        // 只读连接上的搜索不报错，记录暂存在内存中
+        let reader = SessionDB::connect_read_only(config).unwrap();
+        let results = reader.search_fts("binary", 10).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(writer.recent_searches(10).unwrap().is_empty());
+
+        let buffered = reader.take_buffered_searches();
+        assert_eq!(buffered.len(), 1);
+        assert_eq!(buffered[0].result_count, 1);
+        assert!(reader.take_buffered_searches().is_empty());
+
+        // 由写入方（Agent）写入
+        assert_eq!(writer.write_search_log(&buffered).unwrap(), 1);
+        let recent = reader.recent_searches(10).unwrap();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].query, "binary");
     }
+}
+
+// ==================== Schema 迁移测试 ====================
+
+#[cfg(feature = "search")]
+mod migration_tests {
+    use super::*;
+    use ai_cli_session_db::migrations::SUPPORTED_SCHEMA_VERSION;
+
+    /// 基线 Schema（user_version = 1）：messages_fts 只索引 content_full
+    const BASELINE_SCHEMA_SQL: &str = r#"
+CREATE TABLE projects (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    path TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    source TEXT NOT NULL DEFAULT 'claude',
+    encoded_dir_name TEXT,
+    repo_url TEXT,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now') * 1000),
+    updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now') * 1000)
+);
+
+CREATE TABLE sessions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT NOT NULL UNIQUE,
+    project_id INTEGER NOT NULL REFERENCES projects(id),
+    message_count INTEGER NOT NULL DEFAULT 0,
+    last_message_at INTEGER,
+    cwd TEXT,
+    model TEXT,
+    channel TEXT,
+    file_mtime INTEGER,
+    file_size INTEGER,
+    file_offset INTEGER DEFAULT 0,
+    file_inode INTEGER,
+    encoded_dir_name TEXT,
+    meta TEXT,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now') * 1000),
+    updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now') * 1000)
+);
+
+CREATE TABLE messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT NOT NULL,
+    uuid TEXT NOT NULL UNIQUE,
+    type TEXT NOT NULL,
+    content_text TEXT NOT NULL,
+    content_full TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    sequence INTEGER NOT NULL,
+    source TEXT DEFAULT 'claude',
+    channel TEXT,
+    model TEXT,
+    tool_call_id TEXT,
+    tool_name TEXT,
+    tool_args TEXT,
+    raw TEXT,
+    vector_indexed INTEGER DEFAULT 0,
+    approval_status TEXT,
+    approval_resolved_at INTEGER,
+    FOREIGN KEY (session_id) REFERENCES sessions(session_id)
+);
+
+CREATE TABLE talks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT NOT NULL,
+    talk_id TEXT NOT NULL,
+    summary_l2 TEXT NOT NULL,
+    summary_l3 TEXT,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now') * 1000),
+    updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now') * 1000),
+    UNIQUE(session_id, talk_id),
+    FOREIGN KEY (session_id) REFERENCES sessions(session_id)
+);
+
+CREATE VIRTUAL TABLE messages_fts USING fts5(
+    content_full,
+    content='messages',
+    content_rowid='id',
+    tokenize='unicode61'
+);
+
+CREATE TRIGGER messages_ai AFTER INSERT ON messages BEGIN
+    INSERT INTO messages_fts(rowid, content_full) VALUES (new.id, new.content_full);
+END;
+
+CREATE TRIGGER messages_ad AFTER DELETE ON messages BEGIN
+    INSERT INTO messages_fts(messages_fts, rowid, content_full) VALUES('delete', old.id, old.content_full);
+END;
+
+CREATE TRIGGER messages_au AFTER UPDATE ON messages BEGIN
+    INSERT INTO messages_fts(messages_fts, rowid, content_full) VALUES('delete', old.id, old.content_full);
+    INSERT INTO messages_fts(rowid, content_full) VALUES (new.id, new.content_full);
+END;
+
+PRAGMA user_version = 1;
+"#;
 
     #[test]
-    fn test_session_without_messages() {
-        let (db, _tmp) = setup_db();
+    fn test_upgrade_baseline_schema_to_latest() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
 
-        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
-        db.upsert_session("empty-session", project_id).unwrap();
+        {
+            let conn = rusqlite::Connection::open(&db_path).unwrap();
+            conn.execute_batch(BASELINE_SCHEMA_SQL).unwrap();
+            conn.execute_batch(
+                r#"
+                INSERT INTO projects (id, path, name) VALUES (1, '/legacy', 'legacy');
+                INSERT INTO sessions (session_id, project_id, message_count)
+                    VALUES ('legacy-session', 1, 3);
+                INSERT INTO messages (session_id, uuid, type, content_text, content_full, timestamp, sequence, raw)
+                    VALUES ('legacy-session', 'm1', 'user', 'migrate the parser', 'migrate the parser', 1000, 0, NULL);
+                INSERT INTO messages (session_id, uuid, type, content_text, content_full, timestamp, sequence, raw)
+                    VALUES ('legacy-session', 'm2', 'assistant', 'done', 'done\n[tool_use: Bash legacyframe]', 2000, 1, '{"isSidechain":true}');
+                INSERT INTO messages (session_id, uuid, type, content_text, content_full, timestamp, sequence, raw)
+                    VALUES ('legacy-session', 'm3', 'user', 'thanks', 'thanks', 3000, 2, '{"isSidechain":false}');
+                INSERT INTO talks (session_id, talk_id, summary_l2, created_at) VALUES ('legacy-session', 't2', 'second', 2000);
+                INSERT INTO talks (session_id, talk_id, summary_l2, created_at) VALUES ('legacy-session', 't1', 'first', 1000);
+                "#,
+            )
+            .unwrap();
+        }
 
-        let messages = db.list_messages("empty-session", 100, 0).unwrap();
-        assert!(messages.is_empty());
+        // 依次执行 v2 到最新版本
+        let db = SessionDB::connect(DbConfig::local(&db_path)).unwrap();
+        assert_eq!(db.schema_version().unwrap(), SUPPORTED_SCHEMA_VERSION);
 
-        let sessions = db.list_sessions(project_id).unwrap();
-        assert_eq!(sessions[0].message_count, 0);
+        // v2：Talk 按创建顺序回填 position
+        let position = |talk_id: &str| {
+            db.get_talk_summary("legacy-session", talk_id)
+                .unwrap()
+                .unwrap()
+                .position
+        };
+        assert_eq!(position("t1"), Some(0));
+        assert_eq!(position("t2"), Some(1));
+
+        // v6：sidechain 回填
+        let messages = db.get_messages("legacy-session").unwrap();
+        let sidechain: Vec<_> = messages.iter().map(|m| m.sidechain).collect();
+        assert_eq!(sidechain, vec![false, true, false]);
+
+        // v7：content_text 与 content_full 均可搜索
+        let text_only = SearchGroupOptions {
+            field: SearchField::ContentText,
+            ..Default::default()
+        };
+        assert_eq!(db.search_count("parser", &text_only).unwrap(), 1);
+        assert_eq!(db.search_count("legacyframe", &text_only).unwrap(), 0);
+        assert_eq!(
+            db.search_count("legacyframe", &SearchGroupOptions::default())
+                .unwrap(),
+            1
+        );
+
+        // 升级后的触发器维护两列索引
+        db.delete_session("legacy-session").unwrap();
+        assert_eq!(db.search_count("parser", &text_only).unwrap(), 0);
+        assert_eq!(
+            db.search_count("legacyframe", &SearchGroupOptions::default())
+                .unwrap(),
+            0
+        );
+    }
+
+    /// messages_fts 索引中的匹配数（不关联 messages，已删除消息的残留词条也会计入）
+    fn indexed_count(db_path: &std::path::Path, query: &str) -> i64 {
+        let conn = rusqlite::Connection::open(db_path).unwrap();
+        conn.query_row(
+            "SELECT COUNT(*) FROM messages_fts WHERE messages_fts MATCH ?1",
+            [query],
+            |row| row.get(0),
+        )
+        .unwrap()
     }
 
     #[test]
-    fn test_nonexistent_session_messages() {
+    fn test_fresh_database_fts_tracks_updates_and_deletes() {
+        let (db, tmp) = setup_db();
+        let db_path = tmp.path().join("test.db");
+        let project_id = db
+            .get_or_create_project("fresh", "/fresh", "claude")
+            .unwrap();
+        db.upsert_session("fresh-session", project_id).unwrap();
+
+        let mut messages = vec![
+            message("f1", MessageType::User, "oldterm", 0),
+            message("f2", MessageType::User, "keepterm", 1),
+        ];
+        db.insert_messages("fresh-session", &messages).unwrap();
+
+        // 内容更新：content_text 的旧词移出索引，新词可搜索
+        messages[0] = message("f1", MessageType::User, "newterm", 0);
+        db.insert_messages_with_policy("fresh-session", &messages, ConflictPolicy::UpdateContent)
+            .unwrap();
+        let text_only = SearchGroupOptions {
+            field: SearchField::ContentText,
+            ..Default::default()
+        };
+        assert_eq!(db.search_count("oldterm", &text_only).unwrap(), 0);
+        assert_eq!(db.search_count("newterm", &text_only).unwrap(), 1);
+        assert_eq!(indexed_count(&db_path, "content_text : oldterm"), 0);
+
+        // 删除：两列的词条都移出索引
+        db.delete_session("fresh-session").unwrap();
+        assert_eq!(indexed_count(&db_path, "content_text : keepterm"), 0);
+        assert_eq!(indexed_count(&db_path, "content_full : keepterm"), 0);
+        assert_eq!(indexed_count(&db_path, "newterm"), 0);
+    }
+}
+
+// ==================== 统计测试 ====================
+
+mod stats_tests {
+    use super::*;
+    use ai_cli_session_db::db::HISTORY_TOP_PROJECTS;
+
+    #[test]
+    fn test_stats_empty_db() {
         let (db, _tmp) = setup_db();
 
-        // 查询不存在的 session
-        let messages = db.list_messages("nonexistent", 100, 0).unwrap();
-        assert!(messages.is_empty());
+        let stats = db.get_stats().unwrap();
+        assert_eq!(stats.project_count, 0);
+        assert_eq!(stats.session_count, 0);
+        assert_eq!(stats.message_count, 0);
     }
 
     #[test]
-    fn test_large_batch_insert() {
+    fn test_stats_with_data() {
         let (db, _tmp) = setup_db();
 
-        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
-        db.upsert_session("session-001", project_id).unwrap();
+        // 创建数据
+        let p1 = db.get_or_create_project("p1", "/p1", "claude").unwrap();
+        let p2 = db.get_or_create_project("p2", "/p2", "claude").unwrap();
 
-        // 插入 1000 条消息
-        let messages: Vec<MessageInput> = (0..1000)
+        db.upsert_session("s1", p1).unwrap();
+        db.upsert_session("s2", p1).unwrap();
+        db.upsert_session("s3", p2).unwrap();
+
+        let messages: Vec<MessageInput> = (0..5)
             .map(|i| MessageInput {
                 uuid: format!("uuid-{}", i),
-                r#type: if i % 2 == 0 {
-                    MessageType::User
-                } else {
-                    MessageType::Assistant
-                },
-                content_text: format!("Message {}", i),
-                content_full: format!("Message {}", i),
+                r#type: MessageType::User,
+                content_text: "test".to_string(),
+                content_full: "test".to_string(),
                 timestamp: i as i64,
                 sequence: i as i64,
                 source: None,
@@ -1092,208 +3250,5105 @@ mod edge_case_tests {
             })
             .collect();
 
-        let inserted = db.insert_messages("session-001", &messages).unwrap().0;
-        assert_eq!(inserted, 1000);
+        db.insert_messages("s1", &messages).unwrap().0;
 
         let stats = db.get_stats().unwrap();
-        assert_eq!(stats.message_count, 1000);
+        assert_eq!(stats.project_count, 2);
+        assert_eq!(stats.session_count, 3);
+        assert_eq!(stats.message_count, 5);
+    }
+
+    /// 指定时间戳的消息（uuid 以 session_id 为前缀）
+    fn messages_at(session_id: &str, timestamps: &[i64]) -> Vec<MessageInput> {
+        timestamps
+            .iter()
+            .enumerate()
+            .map(|(i, &timestamp)| MessageInput {
+                timestamp,
+                ..message(
+                    &format!("{}-{}", session_id, i),
+                    MessageType::User,
+                    "test",
+                    i as i64,
+                )
+            })
+            .collect()
     }
 
     #[test]
-    fn test_timestamp_ordering() {
+    fn test_history_overview_empty_db() {
         let (db, _tmp) = setup_db();
 
-        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
-        db.upsert_session("session-001", project_id).unwrap();
+        let overview = db.history_overview().unwrap();
+        assert_eq!(overview.totals, HistoryTotals::default());
+        assert!(overview.by_source.is_empty());
+        assert!(overview.by_year.is_empty());
+        assert!(overview.top_projects.is_empty());
+    }
 
-        // 乱序插入
-        let messages = vec![
-            MessageInput {
-                uuid: "uuid-3".to_string(),
-                r#type: MessageType::User,
-                content_text: "Third".to_string(),
-                content_full: "Third".to_string(),
-                timestamp: 3000,
-                sequence: 2,
-                source: None,
-                channel: None,
-                model: None,
-                tool_call_id: None,
-                tool_name: None,
-                tool_args: None,
-                raw: None,
-                approval_status: None,
-                approval_resolved_at: None,
-            },
-            MessageInput {
-                uuid: "uuid-1".to_string(),
-                r#type: MessageType::User,
-                content_text: "First".to_string(),
-                content_full: "First".to_string(),
-                timestamp: 1000,
-                sequence: 0,
-                source: None,
-                channel: None,
-                model: None,
-                tool_call_id: None,
-                tool_name: None,
-                tool_args: None,
-                raw: None,
-                approval_status: None,
-                approval_resolved_at: None,
-            },
-            MessageInput {
-                uuid: "uuid-2".to_string(),
-                r#type: MessageType::User,
-                content_text: "Second".to_string(),
-                content_full: "Second".to_string(),
-                timestamp: 2000,
-                sequence: 1,
-                source: None,
-                channel: None,
-                model: None,
-                tool_call_id: None,
-                tool_name: None,
-                tool_args: None,
-                raw: None,
-                approval_status: None,
-                approval_resolved_at: None,
-            },
-        ];
+    #[test]
+    fn test_history_overview() {
+        let (db, _tmp) = setup_db();
 
-        db.insert_messages("session-001", &messages).unwrap().0;
+        const MAR_2022: i64 = 1_647_302_400_000; // 2022-03-15
+        const JUN_2023: i64 = 1_685_577_600_000; // 2023-06-01
+        const DEC_31_2023: i64 = 1_704_024_000_000; // 2023-12-31 12:00 UTC
+        const JAN_1_2024: i64 = 1_704_110_400_000; // 2024-01-01 12:00 UTC
+        const MAY_2024: i64 = 1_714_521_600_000; // 2024-05-01
+
+        let pa = db.get_or_create_project("a", "/a", "claude").unwrap();
+        let pb = db.get_or_create_project("b", "/b", "claude").unwrap();
+        let pc = db.get_or_create_project("c", "/c", "codex").unwrap();
+        for (session_id, project_id, timestamps) in [
+            ("a1", pa, vec![MAR_2022, MAR_2022 + 1, MAR_2022 + 2]),
+            ("a2", pa, vec![MAY_2024, MAY_2024 + 1]),
+            ("b1", pb, vec![JUN_2023]),
+            ("b-empty", pb, vec![]),
+            // 跨年的会话
+            (
+                "c1",
+                pc,
+                vec![DEC_31_2023, DEC_31_2023 + 1, JAN_1_2024, JAN_1_2024 + 1],
+            ),
+        ] {
+            db.upsert_session(session_id, project_id).unwrap();
+            if !timestamps.is_empty() {
+                db.insert_messages(session_id, &messages_at(session_id, &timestamps))
+                    .unwrap();
+            }
+        }
 
-        // 按 sequence 排序返回
-        let loaded = db.list_messages("session-001", 10, 0).unwrap();
-        assert_eq!(loaded[0].content_text, "First");
-        assert_eq!(loaded[1].content_text, "Second");
-        assert_eq!(loaded[2].content_text, "Third");
+        let overview = db.history_overview().unwrap();
+        assert_eq!(
+            overview.totals,
+            HistoryTotals {
+                project_count: 3,
+                session_count: 5,
+                message_count: 10,
+                oldest_message_at: Some(MAR_2022),
+                newest_message_at: Some(MAY_2024 + 1),
+            }
+        );
+
+        // 按消息数倒序
+        assert_eq!(
+            overview.by_source,
+            vec![
+                SourceHistory {
+                    source: "claude".to_string(),
+                    project_count: 2,
+                    session_count: 4,
+                    message_count: 6,
+                },
+                SourceHistory {
+                    source: "codex".to_string(),
+                    project_count: 1,
+                    session_count: 1,
+                    message_count: 4,
+                },
+            ]
+        );
+
+        // 按年份升序，跨年的会话在两年都计入
+        let years: Vec<(i32, i64, i64)> = overview
+            .by_year
+            .iter()
+            .map(|y| (y.year, y.session_count, y.message_count))
+            .collect();
+        assert_eq!(years, vec![(2022, 1, 3), (2023, 2, 3), (2024, 2, 4)]);
+
+        // 各年份之和与总数一致
+        let year_total: i64 = overview.by_year.iter().map(|y| y.message_count).sum();
+        assert_eq!(year_total, overview.totals.message_count);
+
+        let top: Vec<(&str, i64, i64)> = overview
+            .top_projects
+            .iter()
+            .map(|p| (p.path.as_str(), p.session_count, p.message_count))
+            .collect();
+        assert_eq!(top, vec![("/a", 2, 5), ("/c", 1, 4), ("/b", 1, 1)]);
+
+        // camelCase JSON，总量字段展开在顶层
+        let json = serde_json::to_value(&overview).unwrap();
+        assert_eq!(json["messageCount"], 10);
+        assert_eq!(json["oldestMessageAt"], MAR_2022);
+        assert_eq!(json["byYear"][0]["year"], 2022);
+        assert_eq!(json["topProjects"][0]["projectPath"], "/a");
+    }
+
+    #[test]
+    fn test_history_overview_limits_top_projects() {
+        let (db, _tmp) = setup_db();
+
+        for i in 0..HISTORY_TOP_PROJECTS + 2 {
+            let path = format!("/p{}", i);
+            let project_id = db.get_or_create_project("p", &path, "claude").unwrap();
+            let session_id = format!("s{}", i);
+            db.upsert_session(&session_id, project_id).unwrap();
+            let timestamps: Vec<i64> = (0..=i as i64).collect();
+            db.insert_messages(&session_id, &messages_at(&session_id, &timestamps))
+                .unwrap();
+        }
+
+        let overview = db.history_overview().unwrap();
+        assert_eq!(overview.top_projects.len(), HISTORY_TOP_PROJECTS);
+        // 消息最多的项目在前
+        assert_eq!(
+            overview.top_projects[0].path,
+            format!("/p{}", HISTORY_TOP_PROJECTS + 1)
+        );
     }
 }
 
-// ==================== Writer 转换函数测试 ====================
+// ==================== 采集忽略规则测试 ====================
 
-#[cfg(feature = "writer")]
-mod writer_conversion_tests {
-    use ai_cli_session_collector::{MessageType, ParsedContent, ParsedMessage, Source};
-    use ai_cli_session_db::writer::{convert_message, convert_messages};
+mod ignore_tests {
+    use super::*;
 
-    fn create_parsed_message(uuid: &str, msg_type: MessageType, content: &str) -> ParsedMessage {
-        ParsedMessage {
-            uuid: uuid.to_string(),
-            session_id: "session-001".to_string(),
-            message_type: msg_type,
-            content: ParsedContent {
-                text: content.to_string(),
-                full: content.to_string(),
-            },
-            timestamp: Some("2024-01-15T10:30:00Z".to_string()),
-            source: Source::Claude,
-            channel: Some("code".to_string()),
-            model: None,
-            tool_call_id: None,
-            tool_name: None,
-            tool_args: None,
-            raw: None,
-            cwd: None,
-            stop_reason: None,
+    #[test]
+    fn test_add_list_remove_ignore() {
+        let (db, _tmp) = setup_db();
+
+        let id = db.add_ignore("/tmp/**", IgnoreKind::PathGlob).unwrap();
+        // 重复添加幂等，返回同一 id
+        assert_eq!(db.add_ignore("/tmp/**", IgnoreKind::PathGlob).unwrap(), id);
+        db.add_ignore("agent-", IgnoreKind::SessionPrefix).unwrap();
+
+        let ignores = db.list_ignores().unwrap();
+        assert_eq!(ignores.len(), 2);
+        assert_eq!(ignores[0].pattern, "/tmp/**");
+        assert_eq!(ignores[0].kind, IgnoreKind::PathGlob);
+
+        assert!(db.remove_ignore(id).unwrap());
+        assert!(!db.remove_ignore(id).unwrap());
+        assert_eq!(db.list_ignores().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_apply_project_ignores_hides_project() {
+        let (db, _tmp) = setup_db();
+
+        db.get_or_create_project("work", "/Users/test/work", "claude")
+            .unwrap();
+        let secret_id = db
+            .get_or_create_project("secret", "/Users/test/secret", "claude")
+            .unwrap();
+
+        db.add_ignore("/Users/test/secret", IgnoreKind::ProjectPath)
+            .unwrap();
+        let rules = IgnoreRules::load(&db).unwrap();
+        let changed = db.apply_project_ignores(&rules).unwrap();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].0, secret_id);
+        assert!(changed[0].2);
+
+        let projects = db.list_projects().unwrap();
+        assert_eq!(projects.len(), 1);
+        assert_eq!(projects[0].path, "/Users/test/work");
+        assert_eq!(db.list_projects_with_stats(100, 0).unwrap().len(), 1);
+
+        // 再次应用无变化
+        assert!(db.apply_project_ignores(&rules).unwrap().is_empty());
+
+        // 删除规则后项目重新可见
+        let rule_id = db.list_ignores().unwrap()[0].id;
+        db.remove_ignore(rule_id).unwrap();
+        let rules = IgnoreRules::load(&db).unwrap();
+        let changed = db.apply_project_ignores(&rules).unwrap();
+        assert_eq!(changed.len(), 1);
+        assert!(!changed[0].2);
+        assert_eq!(db.list_projects().unwrap().len(), 2);
+    }
+
+    #[cfg(feature = "writer")]
+    #[test]
+    fn test_ignored_path_not_collected() {
+        let (db, tmp) = setup_db();
+
+        let dir = tmp.path().join("scratch");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("ignored-session.jsonl");
+        std::fs::write(
+            &file,
+            "{\"type\":\"user\",\"uuid\":\"u1\",\"sessionId\":\"ignored-session\",\"cwd\":\"/tmp/x\",\"timestamp\":\"2025-01-01T00:00:00Z\",\"message\":{\"role\":\"user\",\"content\":\"hello\"}}\n",
+        )
+        .unwrap();
+
+        db.add_ignore(&format!("{}/**", dir.display()), IgnoreKind::PathGlob)
+            .unwrap();
+
+        let result = Collector::new(&db)
+            .collect_by_path(file.to_str().unwrap())
+            .unwrap();
+        assert_eq!(result.sessions_ignored, 1);
+        assert_eq!(result.messages_inserted, 0);
+        assert!(!db.session_exists("ignored-session").unwrap());
+    }
+}
+
+// ==================== 项目遗忘测试 ====================
+
+#[cfg(feature = "writer")]
+mod forget_tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// 为每个项目写一个会话文件（2 条消息），返回 projects 目录
+    fn write_projects(tmp: &TempDir, names: &[&str]) -> PathBuf {
+        let projects = tmp.path().join(".claude/projects");
+        for name in names {
+            let dir = projects.join(format!("-tmp-{}", name));
+            std::fs::create_dir_all(&dir).unwrap();
+            let lines: String = (0..2)
+                .map(|i| {
+                    format!(
+                        "{{\"type\":\"user\",\"uuid\":\"{name}-{i}\",\"sessionId\":\"{name}\",\"cwd\":\"/tmp/{name}\",\"timestamp\":\"2025-01-01T00:00:0{i}Z\",\"message\":{{\"role\":\"user\",\"content\":\"{name}word {i}\"}}}}\n"
+                    )
+                })
+                .collect();
+            std::fs::write(dir.join(format!("{}.jsonl", name)), lines).unwrap();
         }
+        projects
+    }
+
+    /// 引用 `session_id` 的行数（所有带会话 ID 的表）
+    fn rows_referencing(raw: &rusqlite::Connection, session_id: &str) -> i64 {
+        [
+            "SELECT COUNT(*) FROM sessions WHERE session_id = ?1",
+            "SELECT COUNT(*) FROM messages WHERE session_id = ?1",
+            "SELECT COUNT(*) FROM talks WHERE session_id = ?1",
+            "SELECT COUNT(*) FROM message_revisions WHERE session_id = ?1",
+            "SELECT COUNT(*) FROM session_relations WHERE parent_session_id = ?1 OR child_session_id = ?1",
+            "SELECT COUNT(*) FROM continuation_chains WHERE root_session_id = ?1",
+            "SELECT COUNT(*) FROM continuation_chain_nodes WHERE session_id = ?1",
+        ]
+        .iter()
+        .map(|sql| raw.query_row(sql, [session_id], |row| row.get::<_, i64>(0)).unwrap())
+        .sum()
     }
 
     #[test]
-    fn test_convert_user_message() {
-        let parsed = create_parsed_message("uuid-1", MessageType::User, "Hello");
-        let input = convert_message(&parsed, 0);
+    fn test_forget_project_removes_every_trace() {
+        let (db, tmp) = setup_db();
+        let projects = write_projects(&tmp, &["secret", "work"]);
+        let collector = Collector::new(&db).with_claude_path(projects.clone());
+        collector.collect_all().unwrap();
+
+        db.upsert_talk_summary(
+            "secret",
+            "talk-1",
+            "secretword summary",
+            None,
+            Some(0),
+            None,
+        )
+        .unwrap();
+        db.insert_session_relation("work", "secret", "subagent", "claude")
+            .unwrap();
+        let indexed: Vec<i64> = db
+            .list_messages("secret", 10, 0)
+            .unwrap()
+            .iter()
+            .map(|m| m.id)
+            .collect();
+        db.mark_messages_indexed(&indexed[..1]).unwrap();
+
+        let project_id = db.get_project_by_path("/tmp/secret").unwrap().unwrap().id;
+        let plan = db.plan_forget_project(project_id).unwrap();
+        assert!(plan.dry_run);
+        assert_eq!((plan.sessions, plan.messages, plan.talks), (1, 2, 1));
+        assert_eq!(plan.session_relations, 1);
+        // 预览不修改数据
+        assert!(db.session_exists("secret").unwrap());
+
+        let report = db.forget_project(project_id, true).unwrap();
+        assert!(!report.dry_run);
+        assert_eq!(report.confirm_token, plan.confirm_token);
+        assert_eq!((report.sessions, report.messages, report.talks), (1, 2, 1));
+        assert_eq!(report.session_relations, 1);
+        assert_eq!(report.vector_tombstones, 1);
+
+        let raw = rusqlite::Connection::open(tmp.path().join("test.db")).unwrap();
+        assert_eq!(rows_referencing(&raw, "secret"), 0);
+        let fts_hits: i64 = raw
+            .query_row(
+                "SELECT COUNT(*) FROM messages_fts WHERE messages_fts MATCH 'secretword'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let talk_hits: i64 = raw
+            .query_row(
+                "SELECT COUNT(*) FROM talks_fts WHERE talks_fts MATCH 'secretword'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!((fts_hits, talk_hits), (0, 0));
+        assert!(db.get_project(project_id).unwrap().is_none());
+        // 已索引的消息留下向量墓碑（只含 uuid 和 ID）
+        assert_eq!(db.drain_vector_tombstones(10).unwrap().len(), 1);
+
+        // 其他项目不受影响
+        assert_eq!(db.list_messages("work", 10, 0).unwrap().len(), 2);
+
+        // 再次采集跳过该路径
+        let result = collector.collect_all().unwrap();
+        assert_eq!(result.sessions_ignored, 1);
+        assert_eq!(result.messages_inserted, 0);
+        assert!(!db.session_exists("secret").unwrap());
+        assert!(db.get_project_by_path("/tmp/secret").unwrap().is_none());
+    }
 
-        assert_eq!(input.uuid, "uuid-1");
-        assert_eq!(input.r#type, MessageType::User);
-        assert_eq!(input.content_text, "Hello");
-        assert_eq!(input.sequence, 0);
-        // timestamp 应该被解析为毫秒
-        assert!(input.timestamp > 0);
+    #[test]
+    fn test_confirm_token_changes_with_data() {
+        let (db, tmp) = setup_db();
+        let projects = write_projects(&tmp, &["alpha"]);
+        Collector::new(&db)
+            .with_claude_path(projects)
+            .collect_all()
+            .unwrap();
+
+        let project_id = db.get_project_by_path("/tmp/alpha").unwrap().unwrap().id;
+        let before = db.plan_forget_project(project_id).unwrap().confirm_token;
+        assert_eq!(
+            db.plan_forget_project(project_id).unwrap().confirm_token,
+            before
+        );
+
+        db.upsert_talk_summary("alpha", "talk-1", "summary", None, Some(0), None)
+            .unwrap();
+        assert_ne!(
+            db.plan_forget_project(project_id).unwrap().confirm_token,
+            before
+        );
+
+        assert!(matches!(
+            db.plan_forget_project(project_id + 100),
+            Err(Error::NotFound(_))
+        ));
+    }
+}
+
+// ==================== 采集过滤测试 ====================
+
+#[cfg(feature = "writer")]
+mod collection_filter_tests {
+    use super::*;
+
+    /// 写入包含噪声条目的 Claude 会话文件
+    fn write_fixture(tmp: &TempDir, session_id: &str) -> String {
+        let dir = tmp.path().join(".claude/projects/-tmp-filter-project");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join(format!("{}.jsonl", session_id));
+        let entry = |uuid: &str, role: &str, content: &str| {
+            format!(
+                "{{\"type\":\"{role}\",\"uuid\":\"{uuid}\",\"sessionId\":\"{session_id}\",\"cwd\":\"/tmp/filter-project\",\"timestamp\":\"2025-01-01T00:00:00Z\",\"message\":{{\"role\":\"{role}\",\"content\":\"{content}\"}}}}\n"
+            )
+        };
+        let content = [
+            entry("u1", "user", "How do I read a file?"),
+            entry("u2", "user", "ok"),
+            entry("u3", "assistant", "Use std::fs::read_to_string."),
+            entry("u4", "user", "k"),
+            entry("u5", "user", "Thanks, that works."),
+        ]
+        .concat();
+        std::fs::write(&file, content).unwrap();
+        file.to_str().unwrap().to_string()
+    }
+
+    fn strict_filter() -> CollectionFilter {
+        CollectionFilter {
+            min_content_chars: 3,
+            ..CollectionFilter::default()
+        }
     }
 
     #[test]
-    fn test_convert_assistant_message() {
-        let parsed = create_parsed_message("uuid-2", MessageType::Assistant, "Hi there");
-        let input = convert_message(&parsed, 1);
+    fn test_preview_filter_impact() {
+        let (db, tmp) = setup_db();
+        let path = write_fixture(&tmp, "filter-preview");
 
-        assert_eq!(input.r#type, MessageType::Assistant);
+        let collector = Collector::new(&db);
+        let impact = collector
+            .preview_filter_impact(&path, &strict_filter())
+            .unwrap();
+        assert_eq!(impact.skipped_too_short, 2);
+        assert_eq!(impact.kept, impact.total - 2);
+        assert_eq!(impact.skipped(), 2);
+
+        // dry-run 不写入数据库
+        assert!(!db.session_exists("filter-preview").unwrap());
     }
 
     #[test]
-    fn test_convert_tool_message() {
-        let parsed = create_parsed_message("uuid-3", MessageType::Tool, "Tool output");
-        let input = convert_message(&parsed, 2);
+    fn test_collect_with_filter_skips_noise() {
+        let (db, tmp) = setup_db();
+        let path = write_fixture(&tmp, "filter-collect");
 
-        // Tool 消息现在保留为 Tool 类型
-        assert_eq!(input.r#type, MessageType::Tool);
+        let result = Collector::new(&db)
+            .with_filter(strict_filter())
+            .collect_by_path(&path)
+            .unwrap();
+        assert_eq!(result.skipped_by_filter, 2);
+
+        let messages = db.get_messages("filter-collect").unwrap();
+        assert_eq!(messages.len(), result.messages_inserted);
+        assert!(messages.iter().all(|m| m.uuid != "u2" && m.uuid != "u4"));
+
+        // 保留消息的 sequence 连续递增
+        for (i, m) in messages.iter().enumerate() {
+            assert_eq!(m.sequence, i as i64);
+        }
+    }
+}
+
+// ==================== claude.ai 导出导入测试 ====================
+
+#[cfg(feature = "writer")]
+mod import_tests {
+    use super::*;
+    use ai_cli_session_db::importer::{message_uuid, CLAUDE_WEB_SOURCE};
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+
+    const BORROW_CHAT: &str = "5e0c7a52-6d1b-4f0e-9a0e-1d2b3c4d5e01";
+    const TRIP_CHAT: &str = "5e0c7a52-6d1b-4f0e-9a0e-1d2b3c4d5e02";
+
+    fn fixture() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/claude_export/conversations.json")
+    }
+
+    /// 把 fixture 打包成与 claude.ai 导出相同结构的 ZIP
+    fn export_zip(tmp: &TempDir) -> PathBuf {
+        let path = tmp.path().join("data-2025-03-04.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("users.json", options).unwrap();
+        zip.write_all(b"[]").unwrap();
+        zip.start_file("conversations.json", options).unwrap();
+        zip.write_all(&std::fs::read(fixture()).unwrap()).unwrap();
+        zip.finish().unwrap();
+        path
     }
 
     #[test]
-    fn test_convert_messages_batch() {
-        let messages = vec![
-            create_parsed_message("uuid-1", MessageType::User, "Q1"),
-            create_parsed_message("uuid-2", MessageType::Assistant, "A1"),
-            create_parsed_message("uuid-3", MessageType::User, "Q2"),
-        ];
+    fn test_import_export_archive() {
+        let (db, tmp) = setup_db();
+        let archive = export_zip(&tmp);
 
-        let inputs = convert_messages(&messages);
+        let report = import_claude_export(&db, &archive, &ImportOptions::default()).unwrap();
+        assert_eq!(
+            report,
+            ImportReport {
+                conversations: 3,
+                conversations_skipped: 1,
+                sessions_created: 2,
+                messages_created: 5,
+                messages_skipped: 0,
+            }
+        );
 
-        assert_eq!(inputs.len(), 3);
-        assert_eq!(inputs[0].sequence, 0);
-        assert_eq!(inputs[1].sequence, 1);
-        assert_eq!(inputs[2].sequence, 2);
+        // 所有对话归入导出的合成项目
+        let project = db
+            .get_project_by_path("claude-web://data-2025-03-04.zip")
+            .unwrap()
+            .unwrap();
+        assert_eq!(project.name, "Claude Web (data-2025-03-04)");
+        assert_eq!(project.source, CLAUDE_WEB_SOURCE);
+
+        let session = db.get_session(BORROW_CHAT).unwrap().unwrap();
+        assert_eq!(session.project_id, project.id);
+        assert_eq!(session.source.as_deref(), Some("claude-web"));
+        assert_eq!(session.channel.as_deref(), Some("web"));
+        assert_eq!(session.message_count, 2);
+
+        let messages = db.get_messages(BORROW_CHAT).unwrap();
+        assert_eq!(messages[0].r#type, MessageType::User);
+        assert_eq!(messages[0].uuid, message_uuid(BORROW_CHAT, 0));
+        assert_eq!(messages[0].timestamp, 1_740_819_600_000);
+        assert_eq!(messages[1].r#type, MessageType::Assistant);
+        assert!(messages[1].content_text.starts_with("The closure captures"));
+
+        // 内容块中的文本，空消息跳过，没有时间的消息用对话时间，sequence 连续
+        let messages = db.get_messages(TRIP_CHAT).unwrap();
+        let texts: Vec<&str> = messages.iter().map(|m| m.content_text.as_str()).collect();
+        assert_eq!(
+            texts,
+            [
+                "Plan a weekend in Kyoto",
+                "Day one: Fushimi Inari early in the morning.",
+                "Thanks!"
+            ]
+        );
+        assert_eq!(messages[2].uuid, message_uuid(TRIP_CHAT, 3));
+        assert_eq!(messages[2].sequence, 2);
+        assert_eq!(messages[2].timestamp, 1_740_940_200_000);
+        assert!(messages.iter().all(|m| m.channel.as_deref() == Some("web")));
     }
 
     #[test]
-    fn test_convert_message_without_timestamp() {
-        let mut parsed = create_parsed_message("uuid-1", MessageType::User, "Hello");
-        parsed.timestamp = None;
+    fn test_reimport_skips_existing_messages() {
+        let (db, tmp) = setup_db();
+        let archive = export_zip(&tmp);
+        import_claude_export(&db, &archive, &ImportOptions::default()).unwrap();
+
+        let report = import_claude_export(&db, &archive, &ImportOptions::default()).unwrap();
+        assert_eq!(report.sessions_created, 0);
+        assert_eq!(report.messages_created, 0);
+        assert_eq!(report.messages_skipped, 5);
+        assert_eq!(db.get_messages(BORROW_CHAT).unwrap().len(), 2);
+        assert_eq!(db.get_messages(TRIP_CHAT).unwrap().len(), 3);
+
+        // 解压出的 conversations.json 与 ZIP 生成相同的消息 uuid
+        let report = import_claude_export(&db, &fixture(), &ImportOptions::default()).unwrap();
+        assert_eq!(report.messages_created, 0);
+        assert_eq!(report.messages_skipped, 5);
+    }
 
-        let input = convert_message(&parsed, 0);
-        assert_eq!(input.timestamp, 0); // 默认为 0
+    #[test]
+    fn test_import_matches_projects_by_name() {
+        let (db, _tmp) = setup_db();
+        let app = db
+            .get_or_create_project("app", "/work/app", "claude")
+            .unwrap();
+
+        let options = ImportOptions {
+            match_projects: true,
+        };
+        import_claude_export(&db, &fixture(), &options).unwrap();
+
+        assert_eq!(
+            db.get_session(BORROW_CHAT).unwrap().unwrap().project_id,
+            app
+        );
+        // 没有所属项目的对话仍归入合成项目
+        let synthetic = db
+            .get_project_by_path("claude-web://conversations.json")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            db.get_session(TRIP_CHAT).unwrap().unwrap().project_id,
+            synthetic.id
+        );
     }
 
     #[test]
-    fn test_convert_message_invalid_timestamp() {
-        let mut parsed = create_parsed_message("uuid-1", MessageType::User, "Hello");
-        parsed.timestamp = Some("invalid-date".to_string());
+    fn test_import_archive_without_conversations() {
+        let (db, tmp) = setup_db();
+        let path = tmp.path().join("empty.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        zip.start_file("users.json", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"[]").unwrap();
+        zip.finish().unwrap();
 
-        let input = convert_message(&parsed, 0);
-        assert_eq!(input.timestamp, 0); // 解析失败默认为 0
+        let err = import_claude_export(&db, &path, &ImportOptions::default()).unwrap_err();
+        assert!(matches!(err, Error::NotFound(_)));
     }
 }
 
-// ==================== Agent + Client 集成测试 ====================
+// ==================== content_text 重新提取测试 ====================
 
-#[cfg(all(feature = "agent", feature = "client"))]
-mod agent_client_tests {
-    use ai_cli_session_db::agent::{Agent, AgentConfig};
-    use ai_cli_session_db::protocol::{QueryType, Request, Response};
-    use std::sync::Arc;
-    use std::time::Duration;
-    use tempfile::TempDir;
-    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-    use tokio::net::UnixStream;
-    use tokio::time::sleep;
+mod rederive_tests {
+    use super::*;
 
-    /// 创建测试配置
-    fn test_agent_config() -> (AgentConfig, TempDir) {
-        let temp_dir = TempDir::new().unwrap();
-        let config = AgentConfig {
-            data_dir: temp_dir.path().to_path_buf(),
-            idle_timeout_secs: 60,
-        };
-        (config, temp_dir)
+    /// raw 为 Claude JSONL 的消息（content_text 为旧规则的提取结果）
+    fn message(i: i64, content_text: &str, raw: &str) -> MessageInput {
+        MessageInput {
+            content_full: format!("full {}", i),
+            raw: Some(raw.to_string()),
+            ..super::message(
+                &format!("rederive-{}", i),
+                MessageType::Assistant,
+                content_text,
+                i,
+            )
+        }
+    }
+
+    /// 4 条消息：0 旧规则含 thinking，1 提取结果不变，2 旧规则漏掉 tool_result，3 无法解析
+    fn setup(db: &SessionDB) -> Vec<Message> {
+        let project_id = db
+            .get_or_create_project("rederive", "/rederive", "claude")
+            .unwrap();
+        db.upsert_session("rederive-session", project_id).unwrap();
+        let messages = vec![
+            message(
+                0,
+                "pondering zebras\nHere is the fix",
+                r#"{"message":{"content":[{"type":"thinking","thinking":"pondering zebras"},{"type":"text","text":"Here is the fix"}]}}"#,
+            ),
+            message(
+                1,
+                "unchanged text",
+                r#"{"message":{"content":"unchanged text"}}"#,
+            ),
+            message(
+                2,
+                "",
+                r#"{"message":{"content":[{"type":"tool_result","content":"quokka output"}]}}"#,
+            ),
+            message(3, "legacy", "not json"),
+        ];
+        db.insert_messages("rederive-session", &messages).unwrap();
+        db.get_messages("rederive-session").unwrap()
+    }
+
+    #[test]
+    fn test_rederive_resets_vector_indexed_only_for_changed_rows() {
+        let (db, _tmp) = setup_db();
+        let messages = setup(&db);
+        let ids: Vec<i64> = messages.iter().map(|m| m.id).collect();
+        // 0 和 1 已向量索引，2 尚未索引
+        db.mark_messages_indexed(&ids[..2]).unwrap();
+        let before = db.change_counter().unwrap();
+
+        let progress = db.rederive_content_text(10, extract_content_text).unwrap();
+        assert_eq!(progress.examined, 4);
+        assert_eq!(progress.changed, 2);
+        assert_eq!(progress.reindex_queued, 1);
+        assert_eq!(progress.last_id, ids[3]);
+        assert!(progress.done);
+        assert!(db.change_counter().unwrap() > before);
+
+        let after = db.get_messages("rederive-session").unwrap();
+        assert_eq!(after[0].content_text, "Here is the fix");
+        assert!(!after[0].vector_indexed);
+        assert_eq!(after[1].content_text, "unchanged text");
+        assert!(after[1].vector_indexed);
+        assert_eq!(after[2].content_text, "quokka output");
+        assert_eq!(after[3].content_text, "legacy");
+        // content_full 不变
+        assert_eq!(after[0].content_full, "full 0");
+
+        // 再次运行从进度继续，没有新消息
+        let progress = db.rederive_content_text(10, extract_content_text).unwrap();
+        assert_eq!(progress.examined, 0);
+        assert!(progress.done);
+    }
+
+    #[test]
+    fn test_rederive_resumes_after_interruption() {
+        let (db, _tmp) = setup_db();
+        let messages = setup(&db);
+
+        // 第一批之后中断
+        let progress = db
+            .rederive_content_text_until(2, extract_content_text, |_| false)
+            .unwrap();
+        assert_eq!(progress.examined, 2);
+        assert_eq!(progress.batches, 1);
+        assert!(!progress.done);
+        assert_eq!(db.rederive_last_id().unwrap(), messages[1].id);
+        let partial = db.get_messages("rederive-session").unwrap();
+        assert_eq!(partial[0].content_text, "Here is the fix");
+        assert_eq!(partial[2].content_text, "");
+
+        // 继续处理剩余的消息
+        let progress = db.rederive_content_text(2, extract_content_text).unwrap();
+        assert_eq!(progress.examined, 2);
+        assert_eq!(progress.changed, 1);
+        assert!(progress.done);
+        assert_eq!(
+            db.get_messages("rederive-session").unwrap()[2].content_text,
+            "quokka output"
+        );
+
+        // 重置后从头开始，提取结果已是最新
+        db.reset_rederive_progress().unwrap();
+        assert_eq!(db.rederive_last_id().unwrap(), 0);
+        let progress = db.rederive_content_text(10, extract_content_text).unwrap();
+        assert_eq!(progress.examined, 4);
+        assert_eq!(progress.changed, 0);
+    }
+
+    #[cfg(feature = "search")]
+    #[test]
+    fn test_rederive_updates_fts() {
+        let (db, _tmp) = setup_db();
+        setup(&db);
+        let text_only = SearchGroupOptions {
+            field: SearchField::ContentText,
+            ..Default::default()
+        };
+        assert_eq!(db.search_count("zebras", &text_only).unwrap(), 1);
+        assert_eq!(db.search_count("quokka", &text_only).unwrap(), 0);
+
+        db.rederive_content_text(10, extract_content_text).unwrap();
+
+        assert_eq!(db.search_count("zebras", &text_only).unwrap(), 0);
+        assert_eq!(db.search_count("quokka", &text_only).unwrap(), 1);
+    }
+}
+
+// ==================== 不变量检查测试 ====================
+
+mod invariant_tests {
+    use super::*;
+
+    fn message(uuid: &str, timestamp: i64) -> MessageInput {
+        MessageInput {
+            timestamp,
+            ..super::message(uuid, MessageType::User, &format!("message {}", uuid), 0)
+        }
+    }
+
+    /// 写入一个会话（消息乱序），返回直接操作数据库文件的连接
+    fn setup_session(db: &SessionDB, tmp: &TempDir) -> rusqlite::Connection {
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.scan_session_incremental(
+            "session-001",
+            project_id,
+            vec![
+                message("uuid-1", 1_000_000),
+                message("uuid-2", 1_002_000),
+                message("uuid-3", 1_001_000),
+            ],
+        )
+        .unwrap();
+        rusqlite::Connection::open(tmp.path().join("test.db")).unwrap()
+    }
+
+    #[test]
+    fn test_consistent_session_has_no_violations() {
+        let (db, tmp) = setup_db();
+        setup_session(&db, &tmp);
+
+        // 检查点取最新的时间，而不是最后一条消息的时间
+        assert_eq!(
+            db.get_scan_checkpoint("session-001").unwrap(),
+            Some(1_002_000)
+        );
+        assert!(db
+            .check_session_invariants("session-001")
+            .unwrap()
+            .is_empty());
+
+        let report = db.check_global_invariants(10).unwrap();
+        assert_eq!(report.sessions_checked, 1);
+        assert!(report.violations.is_empty());
+
+        assert!(matches!(
+            db.check_session_invariants("missing"),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_detects_message_count_drift() {
+        let (db, tmp) = setup_db();
+        let raw = setup_session(&db, &tmp);
+        raw.execute("UPDATE sessions SET message_count = 7", [])
+            .unwrap();
+
+        let violations = db.check_session_invariants("session-001").unwrap();
+        assert_eq!(
+            violations,
+            vec![InvariantViolation {
+                kind: InvariantKind::MessageCount,
+                session_id: Some("session-001".to_string()),
+                expected: 3,
+                actual: 7,
+            }]
+        );
+        assert_eq!(
+            db.check_global_invariants(10).unwrap().violations,
+            violations
+        );
+    }
+
+    #[test]
+    fn test_detects_stale_last_message_at() {
+        let (db, tmp) = setup_db();
+        setup_session(&db, &tmp);
+        db.update_session_last_message("session-001", 1_001_000)
+            .unwrap();
+
+        let violations = db.check_session_invariants("session-001").unwrap();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].kind, InvariantKind::LastMessageAt);
+        assert_eq!(violations[0].expected, 1_002_000);
+    }
+
+    #[cfg(feature = "search")]
+    #[test]
+    fn test_detects_fts_row_count_mismatch() {
+        let (db, tmp) = setup_db();
+        let raw = setup_session(&db, &tmp);
+        raw.execute(
+            "INSERT INTO messages_fts(messages_fts, rowid, content_full, content_text)
+             SELECT 'delete', id, content_full, content_text FROM messages WHERE uuid = 'uuid-1'",
+            [],
+        )
+        .unwrap();
+
+        // FTS 行数只在全局检查中检查
+        assert!(db
+            .check_session_invariants("session-001")
+            .unwrap()
+            .is_empty());
+        let report = db.check_global_invariants(10).unwrap();
+        assert_eq!(report.violations.len(), 1);
+        assert_eq!(report.violations[0].kind, InvariantKind::FtsRowCount);
+        assert_eq!(report.violations[0].session_id, None);
+        assert_eq!(
+            (report.violations[0].expected, report.violations[0].actual),
+            (3, 2)
+        );
+    }
+
+    #[test]
+    fn test_checks_are_individually_toggleable() {
+        let tmp = TempDir::new().unwrap();
+        let checks = InvariantChecks {
+            message_count: false,
+            ..Default::default()
+        };
+        let db = SessionDB::connect(
+            DbConfig::local(tmp.path().join("test.db")).with_invariant_checks(checks),
+        )
+        .unwrap();
+        let raw = setup_session(&db, &tmp);
+        raw.execute("UPDATE sessions SET message_count = 7", [])
+            .unwrap();
+        db.update_session_last_message("session-001", 1_001_000)
+            .unwrap();
+
+        let violations = db.check_session_invariants("session-001").unwrap();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].kind, InvariantKind::LastMessageAt);
+
+        let db = SessionDB::connect(
+            DbConfig::local(tmp.path().join("test.db"))
+                .with_invariant_checks(InvariantChecks::none()),
+        )
+        .unwrap();
+        assert!(db
+            .check_global_invariants(10)
+            .unwrap()
+            .violations
+            .is_empty());
+    }
+
+    /// 未启用 paranoid：写入路径不做任何检查，违反只能由显式检查发现
+    #[cfg(not(feature = "paranoid"))]
+    #[test]
+    fn test_write_path_is_unchecked_without_paranoid() {
+        let (db, tmp) = setup_db();
+        let raw = setup_session(&db, &tmp);
+        raw.execute("UPDATE sessions SET message_count = 7", [])
+            .unwrap();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        assert_eq!(
+            db.scan_session_incremental("session-001", project_id, Vec::new())
+                .unwrap(),
+            0
+        );
+        assert_eq!(ai_cli_session_db::invariants::paranoid_violation_count(), 0);
+        assert_eq!(db.check_session_invariants("session-001").unwrap().len(), 1);
+    }
+
+    /// paranoid：写入结束时发现违反，测试（debug 构建）中立即 panic
+    #[cfg(all(feature = "paranoid", debug_assertions))]
+    #[test]
+    #[should_panic(expected = "invariant violated")]
+    fn test_paranoid_panics_on_violation() {
+        let (db, tmp) = setup_db();
+        let raw = setup_session(&db, &tmp);
+        raw.execute("UPDATE sessions SET message_count = 7", [])
+            .unwrap();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        let _ = db.scan_session_incremental("session-001", project_id, Vec::new());
+    }
+}
+
+// ==================== 会话分享测试 ====================
+
+mod share_tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn setup_session(db: &SessionDB) {
+        let project_id = db
+            .get_or_create_project("acme-app", "/Users/alice/acme-app", "claude")
+            .unwrap();
+        db.upsert_session("share-session", project_id).unwrap();
+        let tool = MessageInput {
+            tool_name: Some("Bash".to_string()),
+            ..message("m-2", MessageType::Tool, "Cargo.toml\nsrc\ntarget", 2)
+        };
+        db.insert_messages(
+            "share-session",
+            &[
+                message("m-0", MessageType::User, "list /Users/alice/acme-app", 0),
+                message("m-1", MessageType::Assistant, "Running ls", 1),
+                tool,
+                message(
+                    "m-3",
+                    MessageType::Assistant,
+                    "key sk-abcdefghijklmnopqrstu",
+                    3,
+                ),
+                message("m-4", MessageType::User, "thanks", 4),
+            ],
+        )
+        .unwrap();
+    }
+
+    fn contents(bundle: &ShareBundle) -> Vec<&str> {
+        bundle.messages.iter().map(|m| m.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_range_and_tool_modes() {
+        let (db, _tmp) = setup_db();
+        setup_session(&db);
+
+        let options = ShareOptions::default();
+        let bundle = db
+            .create_share_bundle("share-session", Some((1, 3)), &options)
+            .unwrap();
+        assert_eq!(bundle.range, Some((1, 3)));
+        assert_eq!(bundle.project.name, "acme-app");
+        assert_eq!(bundle.project.path, None);
+        assert_eq!(
+            contents(&bundle),
+            vec![
+                "Running ls",
+                "[tool: Bash] Cargo.toml (3 lines)",
+                "key sk-abcdefghijklmnopqrstu"
+            ]
+        );
+        assert!(bundle.messages[1].summarized);
+
+        let options = ShareOptions {
+            tool_messages: ToolMessageMode::Full,
+            project_context: ProjectContext::FullPath,
+            ..Default::default()
+        };
+        let bundle = db
+            .create_share_bundle("share-session", None, &options)
+            .unwrap();
+        assert_eq!(bundle.messages.len(), 5);
+        assert_eq!(bundle.messages[2].content, "Cargo.toml\nsrc\ntarget");
+        assert_eq!(
+            bundle.project.path.as_deref(),
+            Some("/Users/alice/acme-app")
+        );
+
+        let options = ShareOptions {
+            tool_messages: ToolMessageMode::Omitted,
+            ..Default::default()
+        };
+        let bundle = db
+            .create_share_bundle("share-session", None, &options)
+            .unwrap();
+        let sequences: Vec<i64> = bundle.messages.iter().map(|m| m.sequence).collect();
+        assert_eq!(sequences, vec![0, 1, 3, 4]);
+    }
+
+    #[test]
+    fn test_redaction() {
+        let (db, _tmp) = setup_db();
+        setup_session(&db);
+
+        let options = ShareOptions {
+            redaction: Some(RedactionPolicy {
+                words: vec!["acme".to_string()],
+                home_dir: Some(PathBuf::from("/Users/alice")),
+                ..Default::default()
+            }),
+            project_context: ProjectContext::FullPath,
+            ..Default::default()
+        };
+        let bundle = db
+            .create_share_bundle("share-session", None, &options)
+            .unwrap();
+        assert!(bundle.redacted);
+        assert_eq!(bundle.messages[0].content, "list ~/[REDACTED]-app");
+        assert_eq!(bundle.messages[3].content, "key [REDACTED]");
+        assert_eq!(bundle.project.name, "[REDACTED]-app");
+        assert_eq!(bundle.project.path.as_deref(), Some("~/[REDACTED]-app"));
+
+        let markdown = render_share_bundle_markdown(&bundle);
+        assert!(!markdown.contains("sk-abcdefghijklmnopqrstu"));
+        assert!(!markdown.contains("/Users/alice"));
+    }
+
+    #[test]
+    fn test_missing_session_is_not_found() {
+        let (db, _tmp) = setup_db();
+        let err = db
+            .create_share_bundle("missing", None, &ShareOptions::default())
+            .unwrap_err();
+        assert!(matches!(err, Error::NotFound(_)));
+    }
+
+    #[test]
+    fn test_import_round_trip() {
+        let (db, _tmp) = setup_db();
+        setup_session(&db);
+        let json = db
+            .create_share_bundle("share-session", Some((0, 1)), &ShareOptions::default())
+            .unwrap()
+            .to_json()
+            .unwrap();
+
+        let (other, _other_tmp) = setup_db();
+        let bundle = ShareBundle::from_json(&json).unwrap();
+        let session_id = import_share_bundle(&other, &bundle, None).unwrap();
+
+        let session = other
+            .get_session_with_project(&session_id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(session.source.as_deref(), Some("shared"));
+        assert_eq!(session.project_name, "acme-app");
+        let messages = other.get_messages(&session_id).unwrap();
+        let texts: Vec<&str> = messages.iter().map(|m| m.content_text.as_str()).collect();
+        assert_eq!(texts, vec!["list /Users/alice/acme-app", "Running ls"]);
+
+        // 重复导入同一个分享包不产生重复的会话和消息
+        assert_eq!(
+            import_share_bundle(&other, &bundle, None).unwrap(),
+            session_id
+        );
+        assert_eq!(other.get_messages(&session_id).unwrap().len(), 2);
+    }
+}
+
+// ==================== 复制测试 ====================
+
+mod replication_tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    /// 内存中的目标：`failing` 时投递失败
+    #[derive(Default)]
+    struct MemorySink {
+        failing: AtomicBool,
+        received: Mutex<Vec<String>>,
+    }
+
+    impl ReplicationSink for MemorySink {
+        fn deliver(&self, batch: &[ReplicationRecord]) -> Result<()> {
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(Error::Connection("sink unavailable".to_string()));
+            }
+            let mut received = self.received.lock().unwrap();
+            received.extend(batch.iter().map(|r| r.message.uuid.clone()));
+            Ok(())
+        }
+    }
+
+    fn insert(db: &SessionDB, range: std::ops::Range<i64>) {
+        let messages: Vec<MessageInput> = range
+            .map(|i| MessageInput {
+                uuid: format!("repl-{}", i),
+                r#type: MessageType::User,
+                content_text: format!("message {}", i),
+                content_full: format!("message {}", i),
+                timestamp: 1000 + i,
+                sequence: i,
+                source: None,
+                channel: None,
+                model: None,
+                tool_call_id: None,
+                tool_name: None,
+                tool_args: None,
+                raw: None,
+                approval_status: None,
+                approval_resolved_at: None,
+            })
+            .collect();
+        db.insert_messages("repl-session", &messages).unwrap();
+    }
+
+    fn setup(db: &SessionDB) {
+        let project_id = db.get_or_create_project("repl", "/repl", "claude").unwrap();
+        db.upsert_session("repl-session", project_id).unwrap();
+    }
+
+    fn uuids(range: std::ops::Range<i64>) -> Vec<String> {
+        range.map(|i| format!("repl-{}", i)).collect()
+    }
+
+    #[test]
+    fn test_replicate_resumes_from_cursor() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let db = SessionDB::connect(DbConfig::local(&db_path)).unwrap();
+        setup(&db);
+        insert(&db, 0..5);
+
+        let sink = MemorySink::default();
+        assert_eq!(db.replicate_batch(&sink, 2).unwrap(), 2);
+
+        // 投递失败时游标不推进
+        sink.failing.store(true, Ordering::SeqCst);
+        assert!(db.replicate_batch(&sink, 2).is_err());
+        let cursor = db.replication_cursor().unwrap();
+        assert_eq!(db.replication_lag().unwrap().lag, 3);
+        drop(db);
+
+        // 重启后从游标继续
+        sink.failing.store(false, Ordering::SeqCst);
+        let db = SessionDB::connect(DbConfig::local(&db_path)).unwrap();
+        assert_eq!(db.replication_cursor().unwrap(), cursor);
+        insert(&db, 5..7);
+        assert_eq!(db.replicate_batch(&sink, 2).unwrap(), 2);
+        assert_eq!(db.replicate_batch(&sink, 2).unwrap(), 2);
+        assert_eq!(db.replicate_batch(&sink, 2).unwrap(), 1);
+        assert_eq!(db.replicate_batch(&sink, 2).unwrap(), 0);
+
+        assert_eq!(*sink.received.lock().unwrap(), uuids(0..7));
+        let lag = db.replication_lag().unwrap();
+        assert_eq!(lag.lag, 0);
+        assert_eq!(lag.cursor, lag.newest_message_id);
+    }
+
+    #[test]
+    fn test_replicate_duplicates_bounded_by_batch() {
+        let (db, _tmp) = setup_db();
+        setup(&db);
+        insert(&db, 0..5);
+
+        // 投递成功但游标未保存（如进程在写游标前退出）
+        let sink = MemorySink::default();
+        let batch = db.replication_pending(2).unwrap();
+        sink.deliver(&batch).unwrap();
+
+        while db.replicate_batch(&sink, 2).unwrap() > 0 {}
+
+        // 只重复这一批，没有遗漏
+        let mut expected = uuids(0..2);
+        expected.extend(uuids(0..5));
+        assert_eq!(*sink.received.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_replication_record_project() {
+        let (db, _tmp) = setup_db();
+        setup(&db);
+        insert(&db, 0..1);
+
+        let records = db.replication_pending(10).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].message.uuid, "repl-0");
+        assert_eq!(records[0].project_path, "/repl");
+        assert_eq!(records[0].project_name, "repl");
+        assert_eq!(records[0].source, "claude");
+    }
+
+    #[test]
+    fn test_set_replication_cursor_skips_history() {
+        let (db, _tmp) = setup_db();
+        setup(&db);
+        insert(&db, 0..3);
+
+        let newest = db.replication_lag().unwrap().newest_message_id;
+        db.set_replication_cursor(newest).unwrap();
+        assert!(db.replication_pending(10).unwrap().is_empty());
+        assert_eq!(db.replication_cursor().unwrap(), newest);
+
+        insert(&db, 3..4);
+        let records = db.replication_pending(10).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].message.uuid, "repl-3");
+    }
+
+    #[test]
+    fn test_jsonl_file_sink() {
+        let (db, tmp) = setup_db();
+        setup(&db);
+        insert(&db, 0..3);
+
+        let sink = JsonlFileSink::new(tmp.path().join("mirror.jsonl"));
+        assert_eq!(db.replicate_batch(&sink, 2).unwrap(), 2);
+        assert_eq!(db.replicate_batch(&sink, 2).unwrap(), 1);
+
+        let content = std::fs::read_to_string(sink.path()).unwrap();
+        let records: Vec<ReplicationRecord> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let delivered: Vec<String> = records.into_iter().map(|r| r.message.uuid).collect();
+        assert_eq!(delivered, uuids(0..3));
+    }
+}
+
+// ==================== Talk 摘要测试 ====================
+
+mod talk_tests {
+    use super::*;
+
+    fn setup_session(db: &SessionDB) {
+        let project_id = db
+            .get_or_create_project("talks", "/tmp/talks", "claude")
+            .unwrap();
+        db.upsert_session("talk-session", project_id).unwrap();
+    }
+
+    #[test]
+    fn test_out_of_order_upserts_sorted_by_first_message_sequence() {
+        let (db, _tmp) = setup_db();
+        setup_session(&db);
+
+        // 摘要乱序写入
+        db.upsert_talk_summary("talk-session", "t3", "third", None, None, Some(40))
+            .unwrap();
+        db.upsert_talk_summary("talk-session", "t1", "first", None, None, Some(0))
+            .unwrap();
+        db.upsert_talk_summary("talk-session", "t2", "second", None, None, Some(15))
+            .unwrap();
+
+        let talks = db.get_talk_summaries("talk-session", 10, 0).unwrap();
+        let ids: Vec<_> = talks.iter().map(|t| t.talk_id.as_str()).collect();
+        assert_eq!(ids, vec!["t1", "t2", "t3"]);
+        let positions: Vec<_> = talks.iter().map(|t| t.position).collect();
+        assert_eq!(positions, vec![Some(0), Some(1), Some(2)]);
+
+        // 更新摘要不改变顺序
+        db.upsert_talk_summary("talk-session", "t3", "third v2", Some("l3"), None, None)
+            .unwrap();
+        let t3 = db.get_talk_summary("talk-session", "t3").unwrap().unwrap();
+        assert_eq!(t3.summary_l2, "third v2");
+        assert_eq!(t3.position, Some(2));
+        assert_eq!(t3.first_message_sequence, Some(40));
+    }
+
+    #[test]
+    fn test_talk_summaries_pagination() {
+        let (db, _tmp) = setup_db();
+        setup_session(&db);
+
+        for i in 0..5 {
+            db.upsert_talk_summary(
+                "talk-session",
+                &format!("t{}", i),
+                "summary",
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        }
+
+        let page = db.get_talk_summaries("talk-session", 2, 2).unwrap();
+        let ids: Vec<_> = page.iter().map(|t| t.talk_id.as_str()).collect();
+        assert_eq!(ids, vec!["t2", "t3"]);
+
+        let last = db.get_talk_summaries("talk-session", 10, 4).unwrap();
+        assert_eq!(last.len(), 1);
+        assert_eq!(last[0].position, Some(4));
+    }
+
+    #[test]
+    fn test_get_single_talk_summary() {
+        let (db, _tmp) = setup_db();
+        setup_session(&db);
+
+        db.upsert_talk_summary("talk-session", "t1", "summary", None, Some(7), None)
+            .unwrap();
+
+        let talk = db.get_talk_summary("talk-session", "t1").unwrap().unwrap();
+        assert_eq!(talk.position, Some(7));
+        assert!(db
+            .get_talk_summary("talk-session", "missing")
+            .unwrap()
+            .is_none());
+    }
+}
+
+// ==================== 对话轮次测试 ====================
+
+mod turn_tests {
+    use super::*;
+
+    fn setup_session(db: &SessionDB) {
+        let project_id = db
+            .get_or_create_project("turns", "/tmp/turns", "claude")
+            .unwrap();
+        db.upsert_session("turn-session", project_id).unwrap();
+    }
+
+    fn turn_uuids(db: &SessionDB, turn_index: i64) -> Vec<String> {
+        db.get_turn_messages("turn-session", turn_index)
+            .unwrap()
+            .into_iter()
+            .map(|m| m.uuid)
+            .collect()
+    }
+
+    #[test]
+    fn test_turns_group_interleaved_tool_messages() {
+        let (db, _tmp) = setup_db();
+        setup_session(&db);
+
+        let mut pending = message("t2", MessageType::Tool, "rm -rf build", 7);
+        pending.approval_status = Some(ApprovalStatus::Pending);
+        let messages = vec![
+            message("u1", MessageType::User, "question 1", 0),
+            message("a1", MessageType::Assistant, "let me check", 1),
+            message("t1", MessageType::Tool, "ls output", 2),
+            message("a2", MessageType::Assistant, "answer 1", 3),
+            message("u2", MessageType::User, "question 2", 4),
+            // 连续的用户消息属于同一轮
+            message("u3", MessageType::User, "more context", 5),
+            message("a3", MessageType::Assistant, "running it", 6),
+            pending,
+        ];
+        db.insert_messages("turn-session", &messages).unwrap();
+
+        let turns = db.list_turns("turn-session", 10, 0).unwrap();
+        assert_eq!(turns.len(), 2);
+
+        assert_eq!(turns[0].turn_index, 0);
+        assert_eq!(turns[0].user_preview.as_deref(), Some("question 1"));
+        assert_eq!(turns[0].assistant_preview.as_deref(), Some("answer 1"));
+        assert_eq!(turns[0].tool_count, 1);
+        assert_eq!(turns[0].message_count, 4);
+        assert_eq!(turns[0].start_timestamp, 1_000);
+        assert_eq!(turns[0].end_timestamp, 1_003);
+        assert_eq!(turns[0].pending_approval_count, 0);
+
+        assert_eq!(turns[1].turn_index, 1);
+        assert_eq!(turns[1].user_preview.as_deref(), Some("question 2"));
+        assert_eq!(turns[1].assistant_preview.as_deref(), Some("running it"));
+        assert_eq!(turns[1].tool_count, 1);
+        assert_eq!(turns[1].pending_approval_count, 1);
+
+        assert_eq!(turn_uuids(&db, 0), vec!["u1", "a1", "t1", "a2"]);
+        assert_eq!(turn_uuids(&db, 1), vec!["u2", "u3", "a3", "t2"]);
+        assert!(turn_uuids(&db, 2).is_empty());
+
+        // 分页
+        let page = db.list_turns("turn-session", 1, 1).unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].turn_index, 1);
+    }
+
+    #[test]
+    fn test_incremental_batches_continue_open_turn() {
+        let (db, _tmp) = setup_db();
+        setup_session(&db);
+
+        db.insert_messages(
+            "turn-session",
+            &[
+                message("u1", MessageType::User, "question", 0),
+                message("a1", MessageType::Assistant, "calling tool", 1),
+            ],
+        )
+        .unwrap();
+        // 追加到未结束的轮次
+        db.insert_messages(
+            "turn-session",
+            &[
+                message("t1", MessageType::Tool, "tool output", 2),
+                message("a2", MessageType::Assistant, "done", 3),
+            ],
+        )
+        .unwrap();
+        // 重复批次不影响轮次
+        db.insert_messages(
+            "turn-session",
+            &[message("a2", MessageType::Assistant, "done", 3)],
+        )
+        .unwrap();
+
+        let turns = db.list_turns("turn-session", 10, 0).unwrap();
+        assert_eq!(turns.len(), 1);
+        assert_eq!(turn_uuids(&db, 0), vec!["u1", "a1", "t1", "a2"]);
+
+        db.insert_messages(
+            "turn-session",
+            &[message("u2", MessageType::User, "next question", 4)],
+        )
+        .unwrap();
+        let turns = db.list_turns("turn-session", 10, 0).unwrap();
+        assert_eq!(turns.len(), 2);
+        assert_eq!(turn_uuids(&db, 1), vec!["u2"]);
+    }
+
+    #[test]
+    fn test_backfill_turn_indexes() {
+        let (db, _tmp) = setup_db();
+        setup_session(&db);
+
+        db.insert_messages(
+            "turn-session",
+            &[
+                message("u1", MessageType::User, "q1", 0),
+                message("a1", MessageType::Assistant, "a1", 1),
+                message("u2", MessageType::User, "q2", 2),
+                message("a2", MessageType::Assistant, "a2", 3),
+            ],
+        )
+        .unwrap();
+        let expected = db.list_turns("turn-session", 10, 0).unwrap();
+        assert_eq!(expected.len(), 2);
+
+        // 模拟升级前的老数据
+        db.connection()
+            .lock()
+            .execute("UPDATE messages SET turn_index = NULL", [])
+            .unwrap();
+        assert!(db.list_turns("turn-session", 10, 0).unwrap().is_empty());
+
+        assert_eq!(db.backfill_turn_indexes().unwrap(), 4);
+        assert_eq!(db.list_turns("turn-session", 10, 0).unwrap(), expected);
+        assert_eq!(db.backfill_turn_indexes().unwrap(), 0);
+
+        // 写入新消息时按需回填所在会话
+        db.connection()
+            .lock()
+            .execute("UPDATE messages SET turn_index = NULL", [])
+            .unwrap();
+        db.insert_messages(
+            "turn-session",
+            &[message("t1", MessageType::Tool, "output", 4)],
+        )
+        .unwrap();
+        assert_eq!(turn_uuids(&db, 1), vec!["u2", "a2", "t1"]);
+    }
+
+    #[cfg(feature = "writer")]
+    #[test]
+    fn test_collection_appending_to_open_turn() {
+        use std::io::Write;
+
+        let (db, tmp) = setup_db();
+        let projects = tmp.path().join(".claude/projects");
+        let dir = projects.join("-tmp-turns");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("collect-turns.jsonl");
+
+        let user = |uuid: &str, second: u32| {
+            format!(
+                "{{\"type\":\"user\",\"uuid\":\"{uuid}\",\"sessionId\":\"collect-turns\",\"cwd\":\"/tmp/turns\",\"timestamp\":\"2025-01-01T00:00:{second:02}Z\",\"message\":{{\"role\":\"user\",\"content\":\"question {uuid}\"}}}}\n"
+            )
+        };
+        let assistant = |uuid: &str, second: u32| {
+            format!(
+                "{{\"type\":\"assistant\",\"uuid\":\"{uuid}\",\"sessionId\":\"collect-turns\",\"cwd\":\"/tmp/turns\",\"timestamp\":\"2025-01-01T00:00:{second:02}Z\",\"message\":{{\"role\":\"assistant\",\"content\":[{{\"type\":\"text\",\"text\":\"answer {uuid}\"}}]}}}}\n"
+            )
+        };
+        let append = |line: String| {
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .unwrap()
+                .write_all(line.as_bytes())
+                .unwrap();
+        };
+
+        let collector = Collector::new(&db).with_claude_path(projects);
+        append(user("c-u1", 0));
+        append(assistant("c-a1", 1));
+        collector.collect_by_path(path.to_str().unwrap()).unwrap();
+
+        append(assistant("c-a2", 2));
+        collector.collect_by_path(path.to_str().unwrap()).unwrap();
+        let turns = db.list_turns("collect-turns", 10, 0).unwrap();
+        assert_eq!(turns.len(), 1);
+        assert_eq!(turns[0].message_count, 3);
+
+        append(user("c-u2", 3));
+        collector.collect_by_path(path.to_str().unwrap()).unwrap();
+        let turns = db.list_turns("collect-turns", 10, 0).unwrap();
+        assert_eq!(turns.len(), 2);
+        assert_eq!(turns[1].message_count, 1);
+    }
+}
+
+// ==================== 分页 token 测试 ====================
+
+mod pagination_tests {
+    use super::*;
+
+    /// 从第一页开始翻到最后一页，返回全部条目和页数
+    fn walk<T>(mut fetch: impl FnMut(Option<&PageToken>) -> Result<Page<T>>) -> (Vec<T>, usize) {
+        let mut items = Vec::new();
+        let mut pages = 0;
+        let mut token = None;
+        loop {
+            let page = fetch(token.as_ref()).unwrap();
+            pages += 1;
+            items.extend(page.items);
+            match page.next_page_token {
+                Some(next) => token = Some(next),
+                None => return (items, pages),
+            }
+        }
+    }
+
+    #[test]
+    fn test_walk_projects() {
+        let (db, _tmp) = setup_db();
+        for i in 0..5 {
+            db.get_or_create_project(&format!("p{}", i), &format!("/p{}", i), "claude")
+                .unwrap();
+        }
+
+        let (projects, pages) = walk(|token| db.list_projects_page(2, token));
+        assert_eq!(pages, 3);
+        let ids: Vec<i64> = projects.iter().map(|p| p.id).collect();
+        let expected: Vec<i64> = db
+            .list_projects_with_stats(100, 0)
+            .unwrap()
+            .iter()
+            .map(|p| p.id)
+            .collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn test_walk_sessions_ignores_new_sessions() {
+        let (db, _tmp) = setup_db();
+        let project_id = db.get_or_create_project("p", "/p", "claude").unwrap();
+        for i in 0..7 {
+            db.upsert_session(&format!("s{}", i), project_id).unwrap();
+        }
+        let expected: Vec<String> = db
+            .list_sessions_by_project_path("/p", 100, 0)
+            .unwrap()
+            .into_iter()
+            .map(|s| s.session_id)
+            .collect();
+
+        let first = db.list_sessions_page("/p", 3, None).unwrap();
+        assert_eq!(first.items.len(), 3);
+        // 翻页期间新增的会话排在最前面，不影响后续页
+        db.upsert_session("s-new", project_id).unwrap();
+
+        let (rest, pages) =
+            walk(|token| db.list_sessions_page("/p", 3, token.or(first.next_page_token.as_ref())));
+        assert_eq!(pages, 2);
+        let ids: Vec<String> = first
+            .items
+            .into_iter()
+            .chain(rest)
+            .map(|s| s.session_id)
+            .collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn test_walk_messages() {
+        let (db, _tmp) = setup_db();
+        let project_id = db.get_or_create_project("p", "/p", "claude").unwrap();
+        db.upsert_session("s1", project_id).unwrap();
+        let messages: Vec<MessageInput> = (0..25)
+            .map(|i| message(&format!("m{}", i), MessageType::User, "hello", i))
+            .collect();
+        db.insert_messages("s1", &messages).unwrap();
+
+        let (messages, pages) = walk(|token| db.list_messages_page("s1", 10, false, token));
+        assert_eq!(pages, 3);
+        let sequences: Vec<i64> = messages.iter().map(|m| m.sequence).collect();
+        assert_eq!(sequences, (0..25).collect::<Vec<_>>());
+        assert!(messages.iter().all(|m| m.raw.is_none()));
+
+        // 恰好整页时最后一页没有 next_page_token
+        let page = db.list_messages_page("s1", 25, false, None).unwrap();
+        assert_eq!(page.items.len(), 25);
+        assert!(page.next_page_token.is_none());
+    }
+
+    #[cfg(feature = "search")]
+    #[test]
+    fn test_walk_search() {
+        let (db, _tmp) = setup_db();
+        let project_id = db.get_or_create_project("p", "/p", "claude").unwrap();
+        db.upsert_session("s1", project_id).unwrap();
+        let messages: Vec<MessageInput> = (0..12)
+            .map(|i| {
+                let content = format!("needle number {}", i);
+                message(&format!("m{}", i), MessageType::User, &content, i)
+            })
+            .chain((12..20).map(|i| message(&format!("m{}", i), MessageType::User, "haystack", i)))
+            .collect();
+        db.insert_messages("s1", &messages).unwrap();
+
+        let options = SearchGroupOptions {
+            order_by: SearchOrderBy::TimeDesc,
+            ..Default::default()
+        };
+        let (results, pages) = walk(|token| db.search_fts_page("needle", 5, &options, None, token));
+        assert_eq!(pages, 3);
+        let ids: Vec<i64> = results.iter().map(|r| r.message_id).collect();
+        let expected: Vec<i64> = db
+            .search_fts_full(
+                "needle",
+                100,
+                None,
+                SearchOrderBy::TimeDesc,
+                None,
+                None,
+                None,
+            )
+            .unwrap()
+            .iter()
+            .map(|r| r.message_id)
+            .collect();
+        assert_eq!(expected.len(), 12);
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn test_token_rejected_by_other_api_or_query() {
+        let (db, _tmp) = setup_db();
+        let project_id = db.get_or_create_project("p", "/p", "claude").unwrap();
+        for i in 0..3 {
+            db.upsert_session(&format!("s{}", i), project_id).unwrap();
+        }
+        db.insert_messages(
+            "s0",
+            &[
+                message("m0", MessageType::User, "a", 0),
+                message("m1", MessageType::User, "b", 1),
+            ],
+        )
+        .unwrap();
+
+        let token = db
+            .list_sessions_page("/p", 1, None)
+            .unwrap()
+            .next_page_token
+            .unwrap();
+        assert!(matches!(
+            db.list_messages_page("s0", 1, false, Some(&token)),
+            Err(Error::InvalidPageToken(_))
+        ));
+        assert!(matches!(
+            db.list_projects_page(1, Some(&token)),
+            Err(Error::InvalidPageToken(_))
+        ));
+        assert!(matches!(
+            db.list_sessions_page("/other", 1, Some(&token)),
+            Err(Error::InvalidPageToken(_))
+        ));
+
+        let token = db
+            .list_messages_page("s0", 1, false, None)
+            .unwrap()
+            .next_page_token
+            .unwrap();
+        assert!(matches!(
+            db.list_messages_page("s1", 1, false, Some(&token)),
+            Err(Error::InvalidPageToken(_))
+        ));
+        assert_eq!(
+            db.list_messages_page("s0", 1, false, Some(&token))
+                .unwrap()
+                .items[0]
+                .uuid,
+            "m1"
+        );
+    }
+
+    #[test]
+    fn test_tampered_token_rejected() {
+        use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        use base64::Engine;
+
+        let (db, _tmp) = setup_db();
+        for i in 0..5 {
+            db.get_or_create_project(&format!("p{}", i), &format!("/p{}", i), "claude")
+                .unwrap();
+        }
+        let token = db
+            .list_projects_page(2, None)
+            .unwrap()
+            .next_page_token
+            .unwrap();
+
+        // 改写游标（跳回第一页）但保留原校验和
+        let json = String::from_utf8(URL_SAFE_NO_PAD.decode(token.as_str()).unwrap()).unwrap();
+        assert!(json.contains(r#""offset":2"#));
+        let forged =
+            PageToken::from(URL_SAFE_NO_PAD.encode(json.replace(r#""offset":2"#, r#""offset":0"#)));
+        assert!(matches!(
+            db.list_projects_page(2, Some(&forged)),
+            Err(Error::InvalidPageToken(_))
+        ));
+
+        // 截断
+        let truncated = PageToken::from(token.as_str()[..token.as_str().len() - 4].to_string());
+        assert!(matches!(
+            db.list_projects_page(2, Some(&truncated)),
+            Err(Error::InvalidPageToken(_))
+        ));
+
+        assert!(db.list_projects_page(2, Some(&token)).is_ok());
+    }
+}
+
+// ==================== 审批审计测试 ====================
+
+mod approval_audit_tests {
+    use super::*;
+
+    fn tool_call(
+        uuid: &str,
+        timestamp: i64,
+        args: &str,
+        status: Option<ApprovalStatus>,
+        resolved_at: Option<i64>,
+    ) -> MessageInput {
+        MessageInput {
+            content_text: String::new(),
+            content_full: format!("tool call {}", uuid),
+            timestamp,
+            tool_call_id: Some(format!("call-{}", uuid)),
+            tool_name: Some("Bash".to_string()),
+            tool_args: Some(args.to_string()),
+            approval_status: status,
+            approval_resolved_at: resolved_at,
+            ..message(uuid, MessageType::Assistant, "", timestamp)
+        }
+    }
+
+    /// 两个项目：app 有三次审批（通过 / 超时 / 等待中）和一条普通消息，ops 有一次拒绝
+    fn setup_audit_db() -> (SessionDB, TempDir, i64, i64) {
+        let (db, tmp) = setup_db();
+        let app = db
+            .get_or_create_project("app", "/work/app", "claude")
+            .unwrap();
+        let ops = db
+            .get_or_create_project("ops", "/work/ops", "claude")
+            .unwrap();
+        db.upsert_session("app-session", app).unwrap();
+        db.upsert_session("ops-session", ops).unwrap();
+
+        let mut plain = tool_call("plain", 1_500, "{}", None, None);
+        plain.tool_call_id = None;
+        db.insert_messages(
+            "app-session",
+            &[
+                tool_call(
+                    "approved",
+                    1_000,
+                    r#"{"command":"ls"}"#,
+                    Some(ApprovalStatus::Approved),
+                    Some(1_250),
+                ),
+                plain,
+                tool_call(
+                    "timeout",
+                    2_000,
+                    "{\"command\":\"echo \\\"a, b\\\"\nrm -rf build\"}",
+                    Some(ApprovalStatus::Timeout),
+                    Some(32_000),
+                ),
+                tool_call("pending", 3_000, "{}", Some(ApprovalStatus::Pending), None),
+            ],
+        )
+        .unwrap();
+        db.insert_messages(
+            "ops-session",
+            &[tool_call(
+                "rejected",
+                2_500,
+                "{}",
+                Some(ApprovalStatus::Rejected),
+                Some(2_600),
+            )],
+        )
+        .unwrap();
+        (db, tmp, app, ops)
+    }
+
+    #[test]
+    fn test_latency_and_timeout_flag() {
+        let (db, _tmp, app, _) = setup_audit_db();
+
+        let rows = db.approval_audit(Some(app), None, None, 10, 0).unwrap();
+        let uuids: Vec<&str> = rows.iter().map(|r| r.uuid.as_str()).collect();
+        // 没有审批状态的消息不计入，按请求时间排序
+        assert_eq!(uuids, vec!["approved", "timeout", "pending"]);
+
+        assert_eq!(rows[0].resolution_latency_ms, Some(250));
+        assert!(!rows[0].timed_out);
+        assert_eq!(rows[0].project_name, "app");
+        assert_eq!(rows[0].session_id, "app-session");
+        assert_eq!(rows[0].tool_call_id.as_deref(), Some("call-approved"));
+
+        assert_eq!(rows[1].resolution_latency_ms, Some(30_000));
+        assert!(rows[1].timed_out);
+        assert_eq!(rows[1].approval_status, "timeout");
+
+        // 仍在等待：没有解决时间和耗时
+        assert_eq!(rows[2].resolved_at, None);
+        assert_eq!(rows[2].resolution_latency_ms, None);
+        assert!(!rows[2].timed_out);
+    }
+
+    #[test]
+    fn test_date_and_project_filters() {
+        let (db, _tmp, app, ops) = setup_audit_db();
+
+        // 闭区间
+        let rows = db
+            .approval_audit(None, Some(2_000), Some(2_500), 10, 0)
+            .unwrap();
+        let uuids: Vec<&str> = rows.iter().map(|r| r.uuid.as_str()).collect();
+        assert_eq!(uuids, vec!["timeout", "rejected"]);
+
+        let rows = db.approval_audit(Some(ops), None, None, 10, 0).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].project_path, "/work/ops");
+
+        let rows = db
+            .approval_audit(Some(app), Some(1_001), None, 1, 1)
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].uuid, "pending");
+    }
+
+    #[test]
+    fn test_export_csv_escapes_args() {
+        let (db, _tmp, app, _) = setup_audit_db();
+
+        let mut out = Vec::new();
+        let count = db
+            .export_approval_audit(
+                Some(app),
+                Some(2_000),
+                Some(2_000),
+                AuditFormat::Csv,
+                &mut out,
+            )
+            .unwrap();
+        assert_eq!(count, 1);
+
+        let csv = String::from_utf8(out).unwrap();
+        let (header, body) = csv.split_once('\n').unwrap();
+        assert!(header.starts_with("message_id,uuid,session_id,"));
+        // 含逗号、引号和换行的参数整体加引号，内部引号加倍
+        assert!(
+            body.contains(",\"{\"\"command\"\":\"\"echo \\\"\"a, b\\\"\"\nrm -rf build\"\"}\",")
+        );
+        assert!(body.ends_with(",timeout,2000,32000,30000,true\n"));
+    }
+
+    #[test]
+    fn test_export_json() {
+        let (db, _tmp, _, _) = setup_audit_db();
+
+        let mut out = Vec::new();
+        let count = db
+            .export_approval_audit(None, None, None, AuditFormat::Json, &mut out)
+            .unwrap();
+        assert_eq!(count, 4);
+
+        let rows: Vec<ApprovalAuditRow> = serde_json::from_slice(&out).unwrap();
+        assert_eq!(rows, db.approval_audit(None, None, None, 10, 0).unwrap());
+
+        // 没有记录时输出空数组
+        let mut out = Vec::new();
+        db.export_approval_audit(None, Some(9_000), None, AuditFormat::Json, &mut out)
+            .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().trim(), "[]");
+    }
+}
+
+// ==================== 按来源拆分数据库测试 ====================
+
+mod router_tests {
+    use super::*;
+    use ai_cli_session_db::router::DEFAULT_DATABASE;
+
+    fn batch(source: &str, session_id: &str, project_path: &str, text: &str) -> CollectBatch {
+        let message = |i: i64| MessageInput {
+            uuid: format!("{}-{}", session_id, i),
+            r#type: MessageType::User,
+            content_text: format!("{} {}", text, i),
+            content_full: format!("{} {}", text, i),
+            timestamp: 1000 + i,
+            sequence: i,
+            source: Some(source.to_string()),
+            channel: None,
+            model: None,
+            tool_call_id: None,
+            tool_name: None,
+            tool_args: None,
+            raw: None,
+            approval_status: None,
+            approval_resolved_at: None,
+        };
+        CollectBatch {
+            project_name: source.to_string(),
+            project_path: project_path.to_string(),
+            session_path: Default::default(),
+            encoded_dir_name: None,
+            session: SessionInput {
+                session_id: session_id.to_string(),
+                source: Some(source.to_string()),
+                ..Default::default()
+            },
+            parent_session_id: None,
+            continuation_from: None,
+            messages: vec![message(0), message(1)],
+            skipped_by_filter: 0,
+            messages_truncated: 0,
+            incremental_state: None,
+        }
+    }
+
+    /// 采集 claude 和 codex 各一个会话
+    fn collect_two_sources(router: &DbRouter) {
+        let collector = Collector::routed(router);
+        for source in ["claude", "codex"] {
+            let session_id = format!("{}-session", source);
+            let project_path = format!("/work/{}", source);
+            let text = format!("hello {}", source);
+            collector
+                .apply_batch(&batch(source, &session_id, &project_path, &text))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_sources_land_in_configured_databases() {
+        let tmp = TempDir::new().unwrap();
+        let default_path = tmp.path().join("default.db");
+        let codex_path = tmp.path().join("codex.db");
+        let router = DbRouter::connect(
+            DbConfig::local(&default_path),
+            [("Codex".to_string(), DbConfig::local(&codex_path))],
+        )
+        .unwrap();
+        assert!(router.is_routed());
+
+        collect_two_sources(&router);
+
+        // 各来源写入各自的数据库文件
+        let default_db = SessionDB::connect(DbConfig::local(&default_path)).unwrap();
+        let codex_db = SessionDB::connect(DbConfig::local(&codex_path)).unwrap();
+        assert!(default_db.session_exists("claude-session").unwrap());
+        assert!(!default_db.session_exists("codex-session").unwrap());
+        assert!(codex_db.session_exists("codex-session").unwrap());
+        assert!(!codex_db.session_exists("claude-session").unwrap());
+        let messages = codex_db.list_messages("codex-session", 10, 0).unwrap();
+        assert_eq!(messages.len(), 2);
+
+        // 单会话查询路由到所在的数据库
+        let db = router.for_session("codex-session").unwrap();
+        assert_eq!(db.config().url, codex_path.display().to_string());
+        assert!(router
+            .for_project_path("/work/claude")
+            .unwrap()
+            .get_project_by_path("/work/claude")
+            .unwrap()
+            .is_some());
+
+        // 合并的项目列表包含两个来源
+        let projects = router.list_projects_with_stats(10, 0).unwrap();
+        let mut tagged: Vec<_> = projects
+            .iter()
+            .map(|p| (p.database.as_str(), p.item.path.as_str()))
+            .collect();
+        tagged.sort();
+        assert_eq!(
+            tagged,
+            [("codex", "/work/codex"), (DEFAULT_DATABASE, "/work/claude")]
+        );
+        assert_eq!(router.list_projects_with_stats(1, 1).unwrap().len(), 1);
+
+        let names: Vec<_> = router.databases().into_iter().map(|d| d.name).collect();
+        assert_eq!(names, [DEFAULT_DATABASE, "codex"]);
+    }
+
+    #[test]
+    #[cfg(feature = "search")]
+    fn test_routed_search_merges_databases() {
+        let tmp = TempDir::new().unwrap();
+        let codex = DbConfig::local(tmp.path().join("codex.db"));
+        let router = DbRouter::connect(
+            DbConfig::local(tmp.path().join("default.db")),
+            [("codex".to_string(), codex)],
+        )
+        .unwrap();
+        collect_two_sources(&router);
+
+        let results = router
+            .search_fts_full("hello", 10, SearchOrderBy::TimeAsc, None, None, None)
+            .unwrap();
+        assert_eq!(results.len(), 4);
+        let mut databases: Vec<_> = results.iter().map(|r| r.database.as_str()).collect();
+        databases.sort();
+        databases.dedup();
+        assert_eq!(databases, ["codex", DEFAULT_DATABASE]);
+
+        let total = router
+            .search_count("hello", &SearchGroupOptions::default())
+            .unwrap();
+        assert_eq!(total, 4);
+    }
+}
+
+// ==================== limit 约定测试 ====================
+
+mod limit_tests {
+    use super::*;
+
+    fn message(i: usize) -> MessageInput {
+        let r#type = if i % 2 == 0 {
+            MessageType::User
+        } else {
+            MessageType::Assistant
+        };
+        super::message(
+            &format!("limit-{}", i),
+            r#type,
+            &format!("limit message {}", i),
+            i as i64,
+        )
+    }
+
+    /// Rust API 中 limit 0 一律返回空结果，"不限制"用 Option 的 None 表示
+    #[test]
+    fn test_zero_limit_returns_nothing() {
+        let (db, _tmp) = setup_db();
+        let project_id = db
+            .get_or_create_project("limits", "/limits", "claude")
+            .unwrap();
+        db.upsert_session("limit-session", project_id).unwrap();
+        let messages: Vec<_> = (0..5).map(message).collect();
+        db.insert_messages("limit-session", &messages).unwrap();
+
+        assert!(db.list_projects_with_stats(0, 0).unwrap().is_empty());
+        assert!(db.list_projects_filtered(None, 0, 0).unwrap().is_empty());
+        assert!(db
+            .list_sessions_by_project_path("/limits", 0, 0)
+            .unwrap()
+            .is_empty());
+        assert!(db.get_sessions(None, 0, false).unwrap().is_empty());
+        assert!(db.search_sessions_by_prefix("limit", 0).unwrap().is_empty());
+        assert!(db.list_messages("limit-session", 0, 0).unwrap().is_empty());
+        assert!(db
+            .list_messages_ordered("limit-session", 0, 0, Order::Desc, false)
+            .unwrap()
+            .is_empty());
+        assert!(db.list_turns("limit-session", 0, 0).unwrap().is_empty());
+        assert!(db
+            .get_talk_summaries("limit-session", 0, 0)
+            .unwrap()
+            .is_empty());
+        assert!(db.get_unindexed_messages(0).unwrap().is_empty());
+        assert!(db.list_pending_tool_call_ids(0).unwrap().is_empty());
+
+        let page = db
+            .list_messages_page("limit-session", 0, false, None)
+            .unwrap();
+        assert!(page.items.is_empty());
+
+        // Option 形式：None 不限制，Some(0) 为空
+        assert_eq!(
+            db.get_messages_with_options("limit-session", None, Order::Asc, false)
+                .unwrap()
+                .len(),
+            5
+        );
+        assert!(db
+            .get_messages_with_options("limit-session", Some(0), Order::Asc, false)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[cfg(feature = "search")]
+    #[test]
+    fn test_zero_limit_search_returns_nothing() {
+        let (db, _tmp) = setup_db();
+        let project_id = db
+            .get_or_create_project("limits", "/limits", "claude")
+            .unwrap();
+        db.upsert_session("limit-session", project_id).unwrap();
+        let messages: Vec<_> = (0..5).map(message).collect();
+        db.insert_messages("limit-session", &messages).unwrap();
+
+        assert_eq!(db.search_fts("limit", 10).unwrap().len(), 5);
+        assert!(db.search_fts("limit", 0).unwrap().is_empty());
+        assert!(db
+            .search_fts_with_project("limit", 0, Some(project_id))
+            .unwrap()
+            .is_empty());
+        assert!(db
+            .search_fts_grouped("limit", 0, 10, &Default::default())
+            .unwrap()
+            .is_empty());
+    }
+}
+
+// ==================== 排序方向测试 ====================
+
+mod order_tests {
+    use super::*;
+
+    /// 插入 5 条消息（sequence 0..5），返回 session_id
+    fn setup_session(db: &SessionDB) -> &'static str {
+        let project_id = db
+            .get_or_create_project("order", "/order", "claude")
+            .unwrap();
+        db.upsert_session("order-session", project_id).unwrap();
+        let messages: Vec<_> = (0..5)
+            .map(|i| MessageInput {
+                uuid: format!("order-{}", i),
+                r#type: MessageType::User,
+                content_text: format!("order message {}", i),
+                content_full: format!("order message {}", i),
+                timestamp: 1000 + i,
+                sequence: i,
+                source: None,
+                channel: None,
+                model: None,
+                tool_call_id: None,
+                tool_name: None,
+                tool_args: None,
+                raw: None,
+                approval_status: None,
+                approval_resolved_at: None,
+            })
+            .collect();
+        db.insert_messages("order-session", &messages).unwrap();
+        "order-session"
+    }
+
+    fn sequences(messages: &[Message]) -> Vec<i64> {
+        messages.iter().map(|m| m.sequence).collect()
+    }
+
+    #[test]
+    fn test_default_order_is_asc() {
+        assert_eq!(Order::default(), Order::Asc);
+    }
+
+    /// 每个带排序参数的 API 都遵循 Order，offset 从所选方向的一端计数
+    #[test]
+    fn test_message_apis_respect_order() {
+        let (db, _tmp) = setup_db();
+        let session_id = setup_session(&db);
+
+        let asc = db
+            .list_messages_ordered(session_id, 3, 1, Order::Asc, false)
+            .unwrap();
+        assert_eq!(sequences(&asc), vec![1, 2, 3]);
+        let desc = db
+            .list_messages_ordered(session_id, 3, 1, Order::Desc, false)
+            .unwrap();
+        assert_eq!(sequences(&desc), vec![3, 2, 1]);
+
+        let asc = db
+            .list_messages_including_sidechain(session_id, 2, 0, Order::Asc, false)
+            .unwrap();
+        assert_eq!(sequences(&asc), vec![0, 1]);
+        let desc = db
+            .list_messages_including_sidechain(session_id, 2, 0, Order::Desc, false)
+            .unwrap();
+        assert_eq!(sequences(&desc), vec![4, 3]);
+
+        let asc = db
+            .get_messages_with_options(session_id, None, Order::Asc, false)
+            .unwrap();
+        assert_eq!(sequences(&asc), vec![0, 1, 2, 3, 4]);
+        let desc = db
+            .get_messages_with_options(session_id, Some(2), Order::Desc, false)
+            .unwrap();
+        assert_eq!(sequences(&desc), vec![4, 3]);
+
+        // 不带排序参数的 API 为升序
+        assert_eq!(
+            sequences(&db.list_messages(session_id, 10, 0).unwrap()),
+            vec![0, 1, 2, 3, 4]
+        );
+        assert_eq!(
+            sequences(&db.get_messages(session_id).unwrap()),
+            vec![0, 1, 2, 3, 4]
+        );
+    }
+}
+
+// ==================== 变更计数测试 ====================
+
+mod change_counter_tests {
+    use super::*;
+
+    #[test]
+    fn test_counter_bumps_once_per_committed_write() {
+        let (db, _tmp) = setup_db();
+        assert_eq!(db.change_counter().unwrap(), 0);
+
+        let project_id = db.get_or_create_project("p", "/p", "claude").unwrap();
+        assert_eq!(db.change_counter().unwrap(), 1);
+
+        // 已存在的项目只刷新 updated_at，不算新数据
+        db.get_or_create_project("p", "/p", "claude").unwrap();
+        assert_eq!(db.change_counter().unwrap(), 1);
+
+        db.upsert_session("s1", project_id).unwrap();
+        assert_eq!(db.change_counter().unwrap(), 2);
+
+        // 一个事务写入多条消息只 +1
+        let messages = vec![
+            pending_tool_call("m1", 0),
+            pending_tool_call("m2", 1),
+            pending_tool_call("m3", 2),
+        ];
+        assert_eq!(db.insert_messages("s1", &messages).unwrap().0, 3);
+        assert_eq!(db.change_counter().unwrap(), 3);
+
+        // 全部重复
+        assert_eq!(db.insert_messages("s1", &messages).unwrap().0, 0);
+        assert_eq!(db.change_counter().unwrap(), 3);
+
+        db.update_approval_status("m1", ApprovalStatus::Approved, 2000)
+            .unwrap();
+        assert_eq!(db.change_counter().unwrap(), 4);
+        db.batch_update_approval_status(
+            &["m2".to_string(), "m3".to_string()],
+            ApprovalStatus::Rejected,
+            2000,
+        )
+        .unwrap();
+        assert_eq!(db.change_counter().unwrap(), 5);
+
+        // 没有匹配的行
+        db.update_approval_status("missing", ApprovalStatus::Approved, 2000)
+            .unwrap();
+        assert_eq!(db.change_counter().unwrap(), 5);
+
+        db.delete_session("s1").unwrap();
+        assert_eq!(db.change_counter().unwrap(), 6);
+    }
+
+    #[test]
+    fn test_counter_unchanged_by_reads() {
+        let (db, _tmp) = setup_db();
+        let project_id = db.get_or_create_project("p", "/p", "claude").unwrap();
+        db.upsert_session("s1", project_id).unwrap();
+        db.insert_messages("s1", &[pending_tool_call("m1", 0)])
+            .unwrap();
+        let before = db.change_counter().unwrap();
+
+        db.list_projects().unwrap();
+        db.list_sessions(project_id).unwrap();
+        db.get_messages("s1").unwrap();
+        db.get_pending_approvals("s1").unwrap();
+        db.get_change_state(Some("s1"), None).unwrap();
+        db.get_stats().unwrap();
+
+        assert_eq!(db.change_counter().unwrap(), before);
+    }
+
+    #[test]
+    fn test_counter_unchanged_by_rolled_back_write() {
+        let (db, _tmp) = setup_db();
+        let project_id = db.get_or_create_project("p", "/p", "claude").unwrap();
+        db.upsert_session("s1", project_id).unwrap();
+        let before = db.change_counter().unwrap();
+
+        // 更新 sessions 失败时整个写事务回滚
+        db.connection()
+            .lock()
+            .execute_batch(
+                "CREATE TEMP TRIGGER fail_session_update BEFORE UPDATE ON sessions
+                 BEGIN SELECT RAISE(ABORT, 'boom'); END;",
+            )
+            .unwrap();
+
+        assert!(db.upsert_session("s1", project_id).is_err());
+        assert!(db
+            .insert_messages("s1", &[pending_tool_call("m1", 0)])
+            .is_err());
+        assert_eq!(db.change_counter().unwrap(), before);
+        assert_eq!(db.get_session_message_count("s1").unwrap(), 0);
+    }
+}
+
+// ==================== 变更通知测试 ====================
+
+mod observer_tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// 记录收到的事件
+    fn record(db: &SessionDB) -> Arc<Mutex<Vec<ChangeEvent>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        db.subscribe_changes(move |event| sink.lock().unwrap().push(event));
+        events
+    }
+
+    #[test]
+    fn test_each_mutation_delivered_once() {
+        let (db, _tmp) = setup_db();
+        let events = record(&db);
+
+        let project_id = db.get_or_create_project("p", "/p", "claude").unwrap();
+        db.upsert_session("s1", project_id).unwrap();
+        db.insert_messages(
+            "s1",
+            &[pending_tool_call("m1", 0), pending_tool_call("m2", 1)],
+        )
+        .unwrap();
+        // 全部重复：没有变更，不通知
+        db.insert_messages("s1", &[pending_tool_call("m1", 0)])
+            .unwrap();
+        db.update_approval_status_by_tool_call_id("call-m1", ApprovalStatus::Approved, 2000)
+            .unwrap();
+        db.rename_project(project_id, "renamed", None).unwrap();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                ChangeEvent::ProjectChanged {
+                    project_id,
+                    project_path: "/p".to_string(),
+                    ignored: false
+                },
+                ChangeEvent::SessionUpserted {
+                    session_id: "s1".to_string()
+                },
+                ChangeEvent::NewMessages {
+                    session_id: "s1".to_string(),
+                    count: 2
+                },
+                ChangeEvent::ApprovalChanged {
+                    session_ids: vec!["s1".to_string()],
+                    status: ApprovalStatus::Approved,
+                    count: 1
+                },
+                ChangeEvent::ProjectChanged {
+                    project_id,
+                    project_path: "/p".to_string(),
+                    ignored: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_events_observe_committed_state() {
+        let (db, tmp) = setup_db();
+        let project_id = db.get_or_create_project("p", "/p", "claude").unwrap();
+        db.upsert_session("s1", project_id).unwrap();
+
+        // 回调通过另一个连接读取：只有已提交的数据可见
+        let reader = SessionDB::connect(DbConfig::local(tmp.path().join("test.db"))).unwrap();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        db.subscribe_changes(move |event| {
+            if let ChangeEvent::NewMessages { session_id, .. } = event {
+                let count = reader.get_session_message_count(&session_id).unwrap();
+                sink.lock().unwrap().push(count);
+            }
+        });
+
+        db.insert_messages(
+            "s1",
+            &[pending_tool_call("m1", 0), pending_tool_call("m2", 1)],
+        )
+        .unwrap();
+        db.insert_messages("s1", &[pending_tool_call("m3", 2)])
+            .unwrap();
+        assert_eq!(*seen.lock().unwrap(), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_panicking_observer_is_dropped() {
+        let (db, _tmp) = setup_db();
+        let panicking = db.subscribe_changes(|_| panic!("observer bug"));
+        let events = record(&db);
+
+        let project_id = db.get_or_create_project("p", "/p", "claude").unwrap();
+        db.upsert_session("s1", project_id).unwrap();
+        db.upsert_session("s2", project_id).unwrap();
+
+        // 写入不受影响，其他订阅者照常收到，panic 的订阅已被移除
+        assert_eq!(events.lock().unwrap().len(), 3);
+        assert!(db.session_exists("s2").unwrap());
+        assert!(!db.unsubscribe(panicking));
+    }
+
+    #[test]
+    fn test_unsubscribe_stops_delivery() {
+        let (db, _tmp) = setup_db();
+        let count = Arc::new(Mutex::new(0));
+        let sink = count.clone();
+        let id = db.subscribe_changes(move |_| *sink.lock().unwrap() += 1);
+
+        let project_id = db.get_or_create_project("p", "/p", "claude").unwrap();
+        db.upsert_session("s1", project_id).unwrap();
+        assert!(db.unsubscribe(id));
+        db.upsert_session("s2", project_id).unwrap();
+        assert_eq!(*count.lock().unwrap(), 2);
+    }
+}
+
+// ==================== 采集锁测试 ====================
+
+#[cfg(feature = "writer")]
+mod collection_lock_tests {
+    use super::*;
+    use ai_cli_session_db::collector::{collection_lock_holder, COLLECTION_LOCK_STALE_MS};
+    use std::path::PathBuf;
+    use std::sync::{Arc, Barrier};
+
+    const SESSIONS: usize = 20;
+    const MESSAGES_PER_SESSION: usize = 50;
+
+    /// 写入多个 Claude 会话文件，返回 projects 目录
+    fn write_fixture(tmp: &TempDir) -> PathBuf {
+        let projects = tmp.path().join(".claude/projects");
+        let dir = projects.join("-tmp-lock-project");
+        std::fs::create_dir_all(&dir).unwrap();
+        for s in 0..SESSIONS {
+            let session_id = format!("lock-session-{}", s);
+            let content: String = (0..MESSAGES_PER_SESSION)
+                .map(|i| {
+                    format!(
+                        "{{\"type\":\"user\",\"uuid\":\"{session_id}-{i}\",\"sessionId\":\"{session_id}\",\"cwd\":\"/tmp/lock-project\",\"timestamp\":\"2025-01-01T00:00:{i:02}Z\",\"message\":{{\"role\":\"user\",\"content\":\"message {i} of {session_id}\"}}}}\n"
+                    )
+                })
+                .collect();
+            std::fs::write(dir.join(format!("{}.jsonl", session_id)), content).unwrap();
+        }
+        projects
+    }
+
+    #[test]
+    fn test_concurrent_collect_all_ingests_once() {
+        let tmp = TempDir::new().unwrap();
+        let projects = write_fixture(&tmp);
+        let db_path = tmp.path().join("test.db");
+        // 先建好 schema，避免两个连接同时迁移
+        SessionDB::connect(DbConfig::local(&db_path)).unwrap();
+
+        let barrier = Arc::new(Barrier::new(2));
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let (barrier, projects, db_path) =
+                    (barrier.clone(), projects.clone(), db_path.clone());
+                std::thread::spawn(move || {
+                    let db = SessionDB::connect(DbConfig::local(&db_path)).unwrap();
+                    barrier.wait();
+                    Collector::new(&db)
+                        .with_claude_path(projects)
+                        .collect_all()
+                        .map(|result| result.messages_inserted)
+                        .map_err(|e| collection_lock_holder(&e).map(str::to_string))
+                })
+            })
+            .collect();
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        // 失败的一方只能是采集锁冲突；无论谁先拿到锁，消息只写入一次
+        for result in &results {
+            if let Err(holder) = result {
+                assert!(holder.is_some(), "unexpected collect error");
+            }
+        }
+        let ingested: Vec<usize> = results
+            .iter()
+            .filter_map(|r| r.as_ref().ok().copied())
+            .filter(|&inserted| inserted > 0)
+            .collect();
+        assert_eq!(ingested, vec![SESSIONS * MESSAGES_PER_SESSION]);
+
+        let db = SessionDB::connect(DbConfig::local(&db_path)).unwrap();
+        assert_eq!(
+            db.get_stats().unwrap().message_count as usize,
+            SESSIONS * MESSAGES_PER_SESSION
+        );
+        assert!(db.get_collection_lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_collect_blocked_by_live_lock_and_takes_over_stale_lock() {
+        let (db, tmp) = setup_db();
+        let projects = write_fixture(&tmp);
+        let session_path = projects.join("-tmp-lock-project/lock-session-0.jsonl");
+
+        db.acquire_collection_lock("other-process", COLLECTION_LOCK_STALE_MS)
+            .unwrap();
+
+        let err = Collector::new(&db)
+            .with_claude_path(projects.clone())
+            .collect_all()
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::CollectionInProgress { holder }) if holder == "other-process"
+        ));
+        let err = Collector::new(&db)
+            .collect_by_path(session_path.to_str().unwrap())
+            .unwrap_err();
+        assert_eq!(collection_lock_holder(&err), Some("other-process"));
+        assert_eq!(db.get_stats().unwrap().message_count, 0);
+
+        // 模拟持有者崩溃：心跳超时后可被接管
+        db.connection()
+            .lock()
+            .execute(
+                "UPDATE collection_lock SET heartbeat_at = heartbeat_at - ?1",
+                [COLLECTION_LOCK_STALE_MS + 1000],
+            )
+            .unwrap();
+
+        let result = Collector::new(&db)
+            .with_claude_path(projects)
+            .collect_all()
+            .unwrap();
+        assert_eq!(result.messages_inserted, SESSIONS * MESSAGES_PER_SESSION);
+
+        // 采集结束后释放锁，原持有者无法再续期
+        assert!(db.get_collection_lock().unwrap().is_none());
+        assert!(!db.heartbeat_collection_lock("other-process").unwrap());
+    }
+}
+
+// ==================== 采集错误测试 ====================
+
+#[cfg(feature = "writer")]
+mod collect_error_tests {
+    use super::*;
+    use ai_cli_session_db::collector::{collect_error, CollectStage};
+    use std::path::PathBuf;
+
+    /// projects 目录下的会话文件路径
+    fn session_file(tmp: &TempDir, session_id: &str) -> (PathBuf, PathBuf) {
+        let projects = tmp.path().join(".claude/projects");
+        let dir = projects.join("-tmp-error-project");
+        std::fs::create_dir_all(&dir).unwrap();
+        (projects, dir.join(format!("{}.jsonl", session_id)))
+    }
+
+    #[test]
+    fn test_parse_failure_attributed_to_file() {
+        let (db, tmp) = setup_db();
+        let (projects, path) = session_file(&tmp, "broken-session");
+        // 目录冒充会话文件：元数据可读，读取内容失败
+        std::fs::create_dir_all(&path).unwrap();
+
+        let err = Collector::new(&db)
+            .with_claude_path(projects)
+            .collect_by_path(path.to_str().unwrap())
+            .unwrap_err();
+
+        let collect_err = collect_error(&err).expect("structured collect error");
+        assert_eq!(collect_err.path, path);
+        assert_eq!(collect_err.session_id.as_deref(), Some("broken-session"));
+        assert_eq!(collect_err.source, Some(Source::Claude));
+        assert_eq!(collect_err.stage, CollectStage::Parse);
+        assert!(collect_err
+            .to_string()
+            .starts_with("Failed to parse session broken-session"));
+    }
+
+    #[test]
+    fn test_repeated_failures_back_off() {
+        let (db, tmp) = setup_db();
+        let (projects, path) = session_file(&tmp, "broken-session");
+        std::fs::create_dir_all(&path).unwrap();
+        let path_str = path.to_str().unwrap();
+        let limits = CollectLimits {
+            max_collect_failures: 3,
+            collect_failure_cooldown_secs: 3600,
+            ..Default::default()
+        };
+        let collector = Collector::new(&db)
+            .with_claude_path(projects)
+            .with_limits(limits);
+
+        // 前 3 次照常解析并失败
+        for attempt in 1..=3 {
+            let err = collector.collect_by_path(path_str).unwrap_err();
+            assert_eq!(collect_error(&err).unwrap().stage, CollectStage::Parse);
+            let failure = db.get_collect_failure("broken-session").unwrap().unwrap();
+            assert_eq!(failure.failures, attempt);
+            assert_eq!(failure.path, path_str);
+            assert!(failure.last_error.starts_with("Failed to parse session"));
+        }
+
+        // 之后退避：不再解析，但仍然报告
+        let result = collector.collect_by_path(path_str).unwrap();
+        assert_eq!(result.sessions_backed_off, 1);
+        assert_eq!(result.summary().sessions_backed_off, 1);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].stage, CollectStage::Discover);
+        assert!(result.errors[0]
+            .message
+            .starts_with("skipped after 3 consecutive failures"));
+        assert_eq!(
+            db.get_collect_failure("broken-session")
+                .unwrap()
+                .unwrap()
+                .failures,
+            3
+        );
+        assert_eq!(db.list_collect_failures().unwrap().len(), 1);
+
+        // 退避期过后重试一次，仍失败则再次退避
+        db.connection()
+            .lock()
+            .execute(
+                "UPDATE collect_failures SET last_failure_at = last_failure_at - 3601000",
+                [],
+            )
+            .unwrap();
+        assert!(collector.collect_by_path(path_str).is_err());
+        assert_eq!(
+            collector
+                .collect_by_path(path_str)
+                .unwrap()
+                .sessions_backed_off,
+            1
+        );
+
+        // 修复文件并清除记录后立即重试，成功后不再有失败记录
+        std::fs::remove_dir(&path).unwrap();
+        std::fs::write(
+            &path,
+            "{\"type\":\"user\",\"uuid\":\"fixed-0\",\"sessionId\":\"broken-session\",\"cwd\":\"/tmp/error-project\",\"timestamp\":\"2025-01-01T00:00:00Z\",\"message\":{\"role\":\"user\",\"content\":\"hello\"}}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            collector
+                .collect_by_path(path_str)
+                .unwrap()
+                .sessions_backed_off,
+            1
+        );
+        assert_eq!(
+            db.reset_collect_failures(Some("broken-session")).unwrap(),
+            1
+        );
+        let result = collector.collect_by_path(path_str).unwrap();
+        assert_eq!(result.messages_inserted, 1);
+        assert!(db.get_collect_failure("broken-session").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_collect_all_backs_off_failing_session() {
+        let (db, tmp) = setup_db();
+        let (projects, path) = session_file(&tmp, "insert-session");
+        std::fs::write(
+            &path,
+            "{\"type\":\"user\",\"uuid\":\"insert-0\",\"sessionId\":\"insert-session\",\"cwd\":\"/tmp/error-project\",\"timestamp\":\"2025-01-01T00:00:00Z\",\"message\":{\"role\":\"user\",\"content\":\"hello\"}}\n",
+        )
+        .unwrap();
+        // 每次写入消息都失败；不记录文件状态，下次采集照常重试
+        db.connection()
+            .lock()
+            .execute_batch(
+                "CREATE TEMP TRIGGER fail_insert BEFORE INSERT ON messages
+                 BEGIN SELECT RAISE(ABORT, 'boom'); END;
+                 CREATE TEMP TRIGGER drop_file_state_ai AFTER INSERT ON sessions
+                 BEGIN UPDATE sessions SET file_mtime = NULL, content_hash = NULL
+                       WHERE session_id = new.session_id; END;
+                 CREATE TEMP TRIGGER drop_file_state_au AFTER UPDATE OF file_mtime ON sessions
+                 BEGIN UPDATE sessions SET file_mtime = NULL, content_hash = NULL
+                       WHERE session_id = new.session_id; END;",
+            )
+            .unwrap();
+        let collect_all = || {
+            Collector::new(&db)
+                .with_claude_path(projects.clone())
+                .with_limits(CollectLimits {
+                    max_collect_failures: 2,
+                    ..Default::default()
+                })
+                .collect_all()
+                .unwrap()
+        };
+
+        for _ in 0..2 {
+            let result = collect_all();
+            assert_eq!(result.sessions_backed_off, 0);
+            assert_eq!(result.errors[0].stage, CollectStage::Insert);
+        }
+        let result = collect_all();
+        assert_eq!(result.sessions_backed_off, 1);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(
+            result.errors[0].session_id.as_deref(),
+            Some("insert-session")
+        );
+        assert_eq!(db.reset_collect_failures(None).unwrap(), 1);
+        assert_eq!(collect_all().errors[0].stage, CollectStage::Insert);
+    }
+
+    #[test]
+    fn test_insert_failure_attributed_to_file() {
+        let (db, tmp) = setup_db();
+        let (projects, path) = session_file(&tmp, "insert-session");
+        std::fs::write(
+            &path,
+            "{\"type\":\"user\",\"uuid\":\"insert-0\",\"sessionId\":\"insert-session\",\"cwd\":\"/tmp/error-project\",\"timestamp\":\"2025-01-01T00:00:00Z\",\"message\":{\"role\":\"user\",\"content\":\"hello\"}}\n",
+        )
+        .unwrap();
+
+        // 写入消息后更新 message_count 失败，整个消息事务回滚
+        db.connection()
+            .lock()
+            .execute_batch(
+                "CREATE TEMP TRIGGER fail_message_count BEFORE UPDATE OF message_count ON sessions
+                 BEGIN SELECT RAISE(ABORT, 'boom'); END;",
+            )
+            .unwrap();
+
+        let result = Collector::new(&db)
+            .with_claude_path(projects)
+            .collect_all()
+            .unwrap();
+
+        assert_eq!(result.messages_inserted, 0);
+        assert_eq!(result.errors.len(), 1);
+        let error = &result.errors[0];
+        assert_eq!(error.path, path);
+        assert_eq!(error.session_id.as_deref(), Some("insert-session"));
+        assert_eq!(error.source, Some(Source::Claude));
+        assert_eq!(error.stage, CollectStage::Insert);
+        assert!(error.to_string().starts_with("Failed to insert messages"));
+
+        let summary = result.summary();
+        assert_eq!(summary.error_count, 1);
+        assert_eq!(summary.errors[0].path, path);
+        assert_eq!(summary.errors[0].source.as_deref(), Some("claude"));
+        assert_eq!(summary.errors[0].stage, CollectStage::Insert);
+    }
+
+    #[test]
+    fn test_corrupt_files_do_not_stop_collection() {
+        let (db, tmp) = setup_db();
+        let entry = |session_id: &str, i: usize| {
+            format!(
+                "{{\"type\":\"user\",\"uuid\":\"{session_id}-{i}\",\"sessionId\":\"{session_id}\",\"cwd\":\"/tmp/error-project\",\"timestamp\":\"2025-01-01T00:00:{i:02}Z\",\"message\":{{\"role\":\"user\",\"content\":\"hello {i}\"}}}}\n"
+            )
+        };
+
+        // 损坏的文件与正常会话混在同一目录
+        let (projects, good_a) = session_file(&tmp, "good-a");
+        std::fs::write(
+            &good_a,
+            (0..3).map(|i| entry("good-a", i)).collect::<String>(),
+        )
+        .unwrap();
+        let (_, garbage) = session_file(&tmp, "garbage-session");
+        std::fs::write(
+            &garbage,
+            b"\x00\xff{\"type\":\"user\",\"uuid\n\xfe\xfd not json\n",
+        )
+        .unwrap();
+        let (_, truncated) = session_file(&tmp, "truncated-session");
+        std::fs::write(
+            &truncated,
+            format!(
+                "{}{{\"type\":\"user\",\"uuid\":\"trunc",
+                entry("truncated-session", 0)
+            ),
+        )
+        .unwrap();
+        let (_, good_b) = session_file(&tmp, "good-b");
+        std::fs::write(
+            &good_b,
+            (0..2).map(|i| entry("good-b", i)).collect::<String>(),
+        )
+        .unwrap();
+
+        let result = Collector::new(&db)
+            .with_claude_path(projects)
+            .collect_all()
+            .unwrap();
+
+        // 正常会话全部采集
+        assert_eq!(db.list_messages("good-a", 100, 0).unwrap().len(), 3);
+        assert_eq!(db.list_messages("good-b", 100, 0).unwrap().len(), 2);
+        assert!(result.messages_inserted >= 5);
+
+        // 错误只归因到损坏的文件
+        for error in &result.errors {
+            assert!(
+                error.path == garbage || error.path == truncated,
+                "unexpected error: {}",
+                error
+            );
+        }
+    }
+}
+
+// ==================== 采集防护上限测试 ====================
+
+#[cfg(feature = "writer")]
+mod collect_limits_tests {
+    use super::*;
+    use ai_cli_session_db::collector::CollectStage;
+    use std::path::PathBuf;
+
+    /// 写入 Claude 会话文件（每条内容一行），返回 (projects 目录, 文件路径)
+    fn write_session(tmp: &TempDir, session_id: &str, contents: &[String]) -> (PathBuf, PathBuf) {
+        let projects = tmp.path().join(".claude/projects");
+        let dir = projects.join("-tmp-limits-project");
+        std::fs::create_dir_all(&dir).unwrap();
+        let lines: String = contents
+            .iter()
+            .enumerate()
+            .map(|(i, content)| {
+                format!(
+                    "{{\"type\":\"user\",\"uuid\":\"{session_id}-{i}\",\"sessionId\":\"{session_id}\",\"cwd\":\"/tmp/limits-project\",\"timestamp\":\"2025-01-01T00:00:{i:02}Z\",\"message\":{{\"role\":\"user\",\"content\":\"{content}\"}}}}\n"
+                )
+            })
+            .collect();
+        let path = dir.join(format!("{}.jsonl", session_id));
+        std::fs::write(&path, lines).unwrap();
+        (projects, path)
+    }
+
+    fn messages(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("message {}", i)).collect()
+    }
+
+    #[test]
+    fn test_oversized_file_skipped() {
+        let (db, tmp) = setup_db();
+        let (projects, big_path) = write_session(&tmp, "big-session", &messages(50));
+        write_session(&tmp, "small-session", &messages(2));
+        let limits = CollectLimits {
+            max_file_bytes: 1024,
+            ..Default::default()
+        };
+
+        let result = Collector::new(&db)
+            .with_claude_path(projects.clone())
+            .with_limits(limits)
+            .collect_all()
+            .unwrap();
+
+        assert_eq!(result.files_skipped_size, 1);
+        assert_eq!(result.messages_inserted, 2);
+        assert_eq!(result.errors.len(), 1);
+        let error = &result.errors[0];
+        assert_eq!(error.path, big_path);
+        assert_eq!(error.session_id.as_deref(), Some("big-session"));
+        assert_eq!(error.stage, CollectStage::Discover);
+        assert!(error.message.starts_with("file exceeds size limit"));
+        assert!(db.get_session("big-session").unwrap().is_none());
+        assert_eq!(result.summary().files_skipped_size, 1);
+
+        // 按路径采集同样跳过
+        let result = Collector::new(&db)
+            .with_claude_path(projects)
+            .with_limits(limits)
+            .collect_by_path(big_path.to_str().unwrap())
+            .unwrap();
+        assert_eq!(result.files_skipped_size, 1);
+        assert_eq!(result.messages_inserted, 0);
+        assert_eq!(result.errors[0].stage, CollectStage::Discover);
+        assert_eq!(db.get_stats().unwrap().message_count, 2);
+    }
+
+    #[test]
+    fn test_oversized_message_truncated() {
+        let (db, tmp) = setup_db();
+        let huge = "x".repeat(10 * 1024);
+        let (projects, path) =
+            write_session(&tmp, "huge-line", &["short message".to_string(), huge]);
+
+        let result = Collector::new(&db)
+            .with_claude_path(projects)
+            .with_limits(CollectLimits {
+                max_message_bytes: 1024,
+                ..Default::default()
+            })
+            .collect_by_path(path.to_str().unwrap())
+            .unwrap();
+
+        assert_eq!(result.messages_inserted, 2);
+        assert_eq!(result.messages_truncated, 1);
+
+        let stored = db.list_messages("huge-line", 10, 0).unwrap();
+        assert!(stored[0].content_full.contains("short message"));
+        assert!(!stored[0].content_full.contains("[truncated:"));
+        let truncated = &stored[1];
+        assert!(truncated.content_full.len() < 2 * 1024);
+        assert!(truncated.content_full.contains("[truncated:"));
+        assert!(truncated.content_text.contains("[truncated:"));
+        assert!(truncated.raw.is_none());
+    }
+
+    #[test]
+    fn test_default_limits_leave_normal_files_untouched() {
+        let (db, tmp) = setup_db();
+        let (projects, _) = write_session(&tmp, "normal-session", &messages(20));
+
+        let result = Collector::new(&db)
+            .with_claude_path(projects)
+            .collect_all()
+            .unwrap();
+
+        assert_eq!(result.messages_inserted, 20);
+        assert_eq!(result.files_skipped_size, 0);
+        assert_eq!(result.messages_truncated, 0);
+        assert!(result.errors.is_empty());
+        let stored = db.list_messages("normal-session", 100, 0).unwrap();
+        assert!(stored[19].content_full.contains("message 19"));
+    }
+
+    #[test]
+    fn test_session_message_cap_resumes_next_run() {
+        let (db, tmp) = setup_db();
+        let (projects, path) = write_session(&tmp, "capped-session", &messages(5));
+        let limits = CollectLimits {
+            max_messages_per_session: 2,
+            ..Default::default()
+        };
+        let collect_by_path = || {
+            Collector::new(&db)
+                .with_claude_path(projects.clone())
+                .with_limits(limits)
+                .collect_by_path(path.to_str().unwrap())
+                .unwrap()
+                .messages_inserted
+        };
+
+        // 每次最多新增 2 条，从已保存的偏移继续直到采完
+        assert_eq!(collect_by_path(), 2);
+        assert_eq!(collect_by_path(), 2);
+        assert_eq!(collect_by_path(), 1);
+        assert_eq!(collect_by_path(), 0);
+        assert_eq!(db.get_stats().unwrap().message_count, 5);
+    }
+
+    #[test]
+    fn test_session_message_cap_resumes_in_collect_all() {
+        let (db, tmp) = setup_db();
+        let (projects, _) = write_session(&tmp, "capped-all", &messages(5));
+        let limits = CollectLimits {
+            max_messages_per_session: 2,
+            ..Default::default()
+        };
+        let collect_all = || {
+            Collector::new(&db)
+                .with_claude_path(projects.clone())
+                .with_limits(limits)
+                .collect_all()
+                .unwrap()
+                .messages_inserted
+        };
+
+        // 未采完时不记录 mtime，下次采集不会被剪枝跳过
+        assert_eq!(collect_all(), 2);
+        assert_eq!(collect_all(), 2);
+        assert_eq!(collect_all(), 1);
+        assert_eq!(collect_all(), 0);
+        assert_eq!(db.get_stats().unwrap().message_count, 5);
+    }
+}
+
+// ==================== 采集顺序测试 ====================
+
+#[cfg(feature = "writer")]
+mod collect_order_tests {
+    use super::*;
+    use ai_cli_session_db::collector::RECENT_COLLECT_WINDOW;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    /// 每个项目目录写一个会话文件（2 条消息），mtime 为 `age_secs` 之前，返回 projects 目录
+    fn write_sessions(tmp: &TempDir, sessions: &[(&str, u64)]) -> PathBuf {
+        let projects = tmp.path().join(".claude/projects");
+        for (session_id, age_secs) in sessions {
+            let dir = projects.join(format!("-tmp-{}", session_id));
+            std::fs::create_dir_all(&dir).unwrap();
+            let lines: String = (0..2)
+                .map(|i| {
+                    format!(
+                        "{{\"type\":\"user\",\"uuid\":\"{session_id}-{i}\",\"sessionId\":\"{session_id}\",\"cwd\":\"/tmp/{session_id}\",\"timestamp\":\"2025-01-01T00:00:0{i}Z\",\"message\":{{\"role\":\"user\",\"content\":\"message {i}\"}}}}\n"
+                    )
+                })
+                .collect();
+            let path = dir.join(format!("{}.jsonl", session_id));
+            std::fs::write(&path, lines).unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(SystemTime::now() - Duration::from_secs(*age_secs))
+                .unwrap();
+        }
+        projects
+    }
+
+    /// 目录顺序与 mtime 顺序不同
+    const SESSIONS: [(&str, u64); 4] = [
+        ("a-old", 90 * DAY),
+        ("b-newest", 60),
+        ("c-month", 30 * DAY),
+        ("d-recent", 2 * DAY),
+    ];
+
+    /// 会话第一条消息的 id（反映入库顺序）
+    fn first_message_id(db: &SessionDB, session_id: &str) -> i64 {
+        db.list_messages(session_id, 1, 0).unwrap()[0].id
+    }
+
+    #[test]
+    fn test_newest_first_inserts_by_mtime() {
+        let (db, tmp) = setup_db();
+        let projects = write_sessions(&tmp, &SESSIONS);
+
+        let result = Collector::new(&db)
+            .with_claude_path(projects)
+            .collect_all()
+            .unwrap();
+        assert_eq!(result.sessions_scanned, 4);
+        assert_eq!(result.messages_inserted, 8);
+
+        let ids: Vec<i64> = ["b-newest", "d-recent", "c-month", "a-old"]
+            .iter()
+            .map(|id| first_message_id(&db, id))
+            .collect();
+        assert!(ids.windows(2).all(|w| w[0] < w[1]), "ids: {:?}", ids);
+    }
+
+    #[test]
+    fn test_directory_order_collects_same_totals() {
+        let (db, tmp) = setup_db();
+        let projects = write_sessions(&tmp, &SESSIONS);
+
+        let result = Collector::new(&db)
+            .with_claude_path(projects)
+            .with_order(CollectOrder::DirectoryOrder)
+            .collect_all()
+            .unwrap();
+        assert_eq!(result.sessions_scanned, 4);
+        assert_eq!(result.messages_inserted, 8);
+    }
+
+    #[test]
+    fn test_phased_collect_recent_first() {
+        let (db, tmp) = setup_db();
+        let projects = write_sessions(&tmp, &SESSIONS);
+
+        let mut recent = None;
+        let result = Collector::new(&db)
+            .with_claude_path(projects)
+            .collect_all_phased(RECENT_COLLECT_WINDOW, |r| {
+                recent = Some((r.sessions_scanned, r.messages_inserted));
+                // 回调时最近阶段已入库，其余尚未开始
+                assert!(db.get_session("d-recent").unwrap().is_some());
+                assert!(db.get_session("c-month").unwrap().is_none());
+            })
+            .unwrap();
+
+        assert_eq!(recent, Some((2, 4)));
+        assert_eq!(result.sessions_scanned, 4);
+        assert_eq!(result.messages_inserted, 8);
+        assert_eq!(result.projects_scanned, 1);
+        assert!(first_message_id(&db, "b-newest") < first_message_id(&db, "d-recent"));
+        assert!(first_message_id(&db, "d-recent") < first_message_id(&db, "c-month"));
+        assert!(first_message_id(&db, "c-month") < first_message_id(&db, "a-old"));
+    }
+}
+
+// ==================== Summary / Sidechain 条目测试 ====================
+
+#[cfg(feature = "writer")]
+mod summary_sidechain_tests {
+    use super::*;
+
+    /// compact 后续接的会话：开头是 summary，中间夹一条 sidechain 消息
+    const COMPACTED_SESSION: &str = concat!(
+        r#"{"type":"summary","summary":"Refactor the zebra parser","leafUuid":"prev-leaf"}"#,
+        "\n",
+        r#"{"type":"user","uuid":"cs-u1","parentUuid":null,"isSidechain":false,"sessionId":"compacted","cwd":"/tmp/compacted","timestamp":"2025-01-01T00:00:00Z","message":{"role":"user","content":"continue the refactor"}}"#,
+        "\n",
+        r#"{"type":"user","uuid":"cs-side","parentUuid":"cs-u1","isSidechain":true,"sessionId":"compacted","cwd":"/tmp/compacted","timestamp":"2025-01-01T00:00:01Z","message":{"role":"user","content":"sidechain walrus probe"}}"#,
+        "\n",
+        r#"{"type":"assistant","uuid":"cs-a1","parentUuid":"cs-u1","isSidechain":false,"sessionId":"compacted","cwd":"/tmp/compacted","timestamp":"2025-01-01T00:00:02Z","message":{"role":"assistant","content":[{"type":"text","text":"Done."}]}}"#,
+        "\n",
+    );
+
+    fn collect_compacted(db: &SessionDB, tmp: &TempDir) {
+        let projects = tmp.path().join(".claude/projects");
+        let dir = projects.join("-tmp-compacted");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("compacted.jsonl");
+        std::fs::write(&path, COMPACTED_SESSION).unwrap();
+
+        let result = Collector::new(db)
+            .with_claude_path(projects)
+            .collect_by_path(path.to_str().unwrap())
+            .unwrap();
+        assert_eq!(result.messages_inserted, 4);
+    }
+
+    #[test]
+    fn test_summary_and_sidechain_round_trip() {
+        let (db, tmp) = setup_db();
+        collect_compacted(&db, &tmp);
+
+        // 默认不列出 sidechain；summary 排在会话开头
+        let messages = db.list_messages("compacted", 100, 0).unwrap();
+        let uuids: Vec<&str> = messages.iter().map(|m| m.uuid.as_str()).collect();
+        assert_eq!(uuids, ["compacted:summary:prev-leaf", "cs-u1", "cs-a1"]);
+        assert!(messages[0].is_summary);
+        assert_eq!(messages[0].r#type, MessageType::System);
+        assert_eq!(messages[0].content_text, "Refactor the zebra parser");
+        assert!(!messages[1].is_summary && !messages[1].sidechain);
+
+        let all = db
+            .list_messages_including_sidechain("compacted", 100, 0, Order::Asc, false)
+            .unwrap();
+        assert_eq!(all.len(), 4);
+        assert!(all.iter().any(|m| m.uuid == "cs-side" && m.sidechain));
+
+        let metrics = db
+            .get_session_with_metrics("compacted")
+            .unwrap()
+            .unwrap()
+            .metrics
+            .unwrap();
+        assert_eq!(metrics.message_count, 4);
+        assert_eq!(metrics.user_message_count, 1);
+        assert_eq!(metrics.assistant_message_count, 1);
+        assert_eq!(metrics.sidechain_message_count, 1);
+        assert_eq!(metrics.summary_count, 1);
+    }
+
+    #[cfg(feature = "search")]
+    #[test]
+    fn test_summary_and_sidechain_are_searchable() {
+        let (db, tmp) = setup_db();
+        collect_compacted(&db, &tmp);
+
+        let results = db.search_fts("zebra", 10).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].session_id, "compacted");
+        assert_eq!(results[0].r#type, "system");
+
+        let results = db.search_fts("walrus", 10).unwrap();
+        assert_eq!(results.len(), 1);
+    }
+}
+
+// ==================== Sequence 缺口测试 ====================
+
+#[cfg(feature = "writer")]
+mod sequence_gap_tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// 写入并采集 10 条消息的 Claude 会话（uuid 为 `{session_id}-{i}`，sequence 为 i），返回 projects 目录
+    fn collect_session(db: &SessionDB, tmp: &TempDir, session_id: &str) -> PathBuf {
+        let projects = tmp.path().join(".claude/projects");
+        let dir = projects.join("-tmp-gap-project");
+        std::fs::create_dir_all(&dir).unwrap();
+        let lines: String = (0..10)
+            .map(|i| {
+                format!(
+                    "{{\"type\":\"user\",\"uuid\":\"{session_id}-{i}\",\"sessionId\":\"{session_id}\",\"cwd\":\"/tmp/gap-project\",\"timestamp\":\"2025-01-01T00:00:{i:02}Z\",\"message\":{{\"role\":\"user\",\"content\":\"message {i}\"}}}}\n"
+                )
+            })
+            .collect();
+        let path = dir.join(format!("{}.jsonl", session_id));
+        std::fs::write(&path, lines).unwrap();
+
+        let result = Collector::new(db)
+            .with_claude_path(projects.clone())
+            .collect_by_path(path.to_str().unwrap())
+            .unwrap();
+        assert_eq!(result.messages_inserted, 10);
+        projects
+    }
+
+    /// 模拟增量读取丢失的一段
+    fn delete_sequences(db: &SessionDB, session_id: &str, from: i64, to: i64) {
+        db.connection()
+            .lock()
+            .execute(
+                "DELETE FROM messages WHERE session_id = ?1 AND sequence BETWEEN ?2 AND ?3",
+                rusqlite::params![session_id, from, to],
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_find_sequence_gaps() {
+        let (db, tmp) = setup_db();
+        collect_session(&db, &tmp, "gap-session");
+        collect_session(&db, &tmp, "intact-session");
+        assert!(db.find_sequence_gaps("gap-session").unwrap().is_empty());
+        assert_eq!(db.count_sessions_with_gaps().unwrap(), 0);
+
+        delete_sequences(&db, "gap-session", 3, 5);
+        delete_sequences(&db, "gap-session", 8, 8);
+
+        assert_eq!(
+            db.find_sequence_gaps("gap-session").unwrap(),
+            vec![(3, 5), (8, 8)]
+        );
+        assert!(db.find_sequence_gaps("intact-session").unwrap().is_empty());
+        assert_eq!(
+            db.find_sessions_with_gaps(10).unwrap(),
+            vec![("gap-session".to_string(), 4)]
+        );
+        assert_eq!(db.count_sessions_with_gaps().unwrap(), 1);
+
+        // 末尾缺失不算缺口
+        delete_sequences(&db, "intact-session", 9, 9);
+        assert!(db.find_sequence_gaps("intact-session").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_refill_gaps_restores_missing_rows() {
+        let (db, tmp) = setup_db();
+        let projects = collect_session(&db, &tmp, "gap-session");
+        delete_sequences(&db, "gap-session", 3, 5);
+        let kept_ids: Vec<i64> = db
+            .list_messages("gap-session", 100, 0)
+            .unwrap()
+            .iter()
+            .map(|m| m.id)
+            .collect();
+        assert_eq!(kept_ids.len(), 7);
+
+        let result = Collector::new(&db)
+            .with_claude_path(projects.clone())
+            .refill_gaps("gap-session")
+            .unwrap();
+        assert_eq!(result.messages_inserted, 3);
+        assert_eq!(result.new_message_ids.len(), 3);
+        assert!(db.find_sequence_gaps("gap-session").unwrap().is_empty());
+        assert_eq!(db.count_sessions_with_gaps().unwrap(), 0);
+
+        let messages = db.list_messages("gap-session", 100, 0).unwrap();
+        assert_eq!(messages.len(), 10);
+        for (i, msg) in messages.iter().enumerate() {
+            assert_eq!(msg.sequence, i as i64);
+            assert_eq!(msg.uuid, format!("gap-session-{}", i));
+        }
+        // 已有的行保持不变
+        for id in &kept_ids {
+            assert!(messages.iter().any(|m| m.id == *id));
+        }
+        assert_eq!(db.get_session_message_count("gap-session").unwrap(), 10);
+
+        // 没有缺口时不做任何事
+        let result = Collector::new(&db)
+            .with_claude_path(projects)
+            .refill_gaps("gap-session")
+            .unwrap();
+        assert_eq!(result.messages_inserted, 0);
+    }
+}
+
+// ==================== 文件指纹测试 ====================
+
+#[cfg(feature = "writer")]
+mod content_hash_tests {
+    use super::*;
+    use ai_cli_session_db::collector::file_content_hash;
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, SystemTime};
+
+    fn line(session_id: &str, i: usize) -> String {
+        format!(
+            "{{\"type\":\"user\",\"uuid\":\"{session_id}-{i}\",\"sessionId\":\"{session_id}\",\"cwd\":\"/tmp/hash-project\",\"timestamp\":\"2025-01-01T00:00:{i:02}Z\",\"message\":{{\"role\":\"user\",\"content\":\"message {i}\"}}}}\n"
+        )
+    }
+
+    /// 写入 3 条消息的会话文件，返回 (projects 目录, 文件路径)
+    fn write_session(tmp: &TempDir, session_id: &str) -> (PathBuf, PathBuf) {
+        let projects = tmp.path().join(".claude/projects");
+        let dir = projects.join("-tmp-hash-project");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.jsonl", session_id));
+        let content: String = (0..3).map(|i| line(session_id, i)).collect();
+        std::fs::write(&path, content).unwrap();
+        (projects, path)
+    }
+
+    /// 只改 mtime，不改内容
+    fn touch(path: &Path) {
+        let mtime = SystemTime::now() + Duration::from_secs(3600);
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+    }
+
+    /// 删除已入库的消息：如果会话被重新解析，消息会再次写入
+    fn delete_messages(db: &SessionDB, session_id: &str) {
+        db.connection()
+            .lock()
+            .execute("DELETE FROM messages WHERE session_id = ?1", [session_id])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_content_hash_stored_during_collection() {
+        let (db, tmp) = setup_db();
+        let (projects, path) = write_session(&tmp, "hash-session");
+
+        Collector::new(&db)
+            .with_claude_path(projects)
+            .collect_all()
+            .unwrap();
+
+        let stored = db.get_session_content_hash("hash-session").unwrap();
+        assert!(stored.is_some());
+        assert_eq!(stored, file_content_hash(&path));
+        assert_eq!(db.get_session_content_hash("missing").unwrap(), None);
+    }
+
+    #[test]
+    fn test_mtime_change_without_content_change_skips_reparse() {
+        let (db, tmp) = setup_db();
+        let (projects, path) = write_session(&tmp, "touched-session");
+        let collector = Collector::new(&db).with_claude_path(projects);
+
+        assert_eq!(collector.collect_all().unwrap().messages_inserted, 3);
+
+        let hash_before = file_content_hash(&path);
+        touch(&path);
+        assert_eq!(file_content_hash(&path), hash_before);
+
+        delete_messages(&db, "touched-session");
+        assert_eq!(collector.collect_all().unwrap().messages_inserted, 0);
+        assert_eq!(
+            collector
+                .collect_by_path(path.to_str().unwrap())
+                .unwrap()
+                .messages_inserted,
+            0
+        );
+
+        // 内容真正变化时重新解析
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(line("touched-session", 3).as_bytes())
+            .unwrap();
+        assert_ne!(file_content_hash(&path), hash_before);
+        assert_eq!(collector.collect_all().unwrap().messages_inserted, 4);
+    }
+}
+
+// ==================== 统一读取门面测试 ====================
+
+#[cfg(feature = "writer")]
+mod facade_tests {
+    use super::*;
+    use ai_cli_session_db::facade::{BackendKind, StoreOptions};
+
+    fn line(session_id: &str, i: usize) -> String {
+        format!(
+            "{{\"type\":\"user\",\"uuid\":\"{session_id}-{i}\",\"sessionId\":\"{session_id}\",\"cwd\":\"/tmp/facade-project\",\"timestamp\":\"2025-01-01T00:00:{i:02}Z\",\"message\":{{\"role\":\"user\",\"content\":\"facade message {i}\"}}}}\n"
+        )
+    }
+
+    /// 写入两个会话文件，返回探测选项（db_path 指向尚不存在的数据库）
+    fn fixture(tmp: &TempDir) -> StoreOptions {
+        let projects = tmp.path().join(".claude/projects");
+        let dir = projects.join("-tmp-facade-project");
+        std::fs::create_dir_all(&dir).unwrap();
+        for session_id in ["facade-a", "facade-b"] {
+            let content: String = (0..3).map(|i| line(session_id, i)).collect();
+            std::fs::write(dir.join(format!("{}.jsonl", session_id)), content).unwrap();
+        }
+
+        StoreOptions {
+            data_dir: tmp.path().join("vimo"),
+            db_path: tmp.path().join("facade.db"),
+            projects_path: projects,
+            backend: None,
+        }
+    }
+
+    fn collect_into_db(options: &StoreOptions) {
+        let db = SessionDB::connect(DbConfig::local(&options.db_path)).unwrap();
+        Collector::new(&db)
+            .with_claude_path(options.projects_path.clone())
+            .collect_all()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_probe_falls_back_to_fs() {
+        let tmp = TempDir::new().unwrap();
+        let options = fixture(&tmp);
+
+        let mut store = SessionStore::probe(&options);
+        assert_eq!(store.kind(), BackendKind::Fs);
+        assert!(!store.capabilities().search);
+
+        let projects = store.list_projects(10, 0).unwrap();
+        assert_eq!(projects.len(), 1);
+        assert_eq!(projects[0].session_count, 2);
+        assert!(matches!(
+            store.search("facade", 10),
+            Err(Error::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_probe_prefers_db_over_fs() {
+        let tmp = TempDir::new().unwrap();
+        let options = fixture(&tmp);
+        collect_into_db(&options);
+
+        let store = SessionStore::probe(&options);
+        assert_eq!(store.kind(), BackendKind::Db);
+        assert!(store.capabilities().search == cfg!(feature = "search"));
+    }
+
+    #[test]
+    fn test_forced_backend() {
+        let tmp = TempDir::new().unwrap();
+        let mut options = fixture(&tmp);
+        collect_into_db(&options);
+
+        options.backend = Some(BackendKind::Fs);
+        assert_eq!(SessionStore::probe(&options).kind(), BackendKind::Fs);
+
+        // 指定后端不可用时探测回退，open 返回错误
+        options.db_path = tmp.path().join("missing.db");
+        options.backend = Some(BackendKind::Db);
+        assert_eq!(SessionStore::probe(&options).kind(), BackendKind::Fs);
+        assert!(SessionStore::open(BackendKind::Db, &options).is_err());
+
+        assert_eq!("db".parse::<BackendKind>().unwrap(), BackendKind::Db);
+        assert!("nope".parse::<BackendKind>().is_err());
+    }
+
+    #[test]
+    fn test_db_and_fs_backends_agree() {
+        let tmp = TempDir::new().unwrap();
+        let options = fixture(&tmp);
+        collect_into_db(&options);
+
+        let mut db_store = SessionStore::open(BackendKind::Db, &options).unwrap();
+        let mut fs_store = SessionStore::open(BackendKind::Fs, &options).unwrap();
+
+        let db_projects = db_store.list_projects(10, 0).unwrap();
+        let fs_projects = fs_store.list_projects(10, 0).unwrap();
+        assert_eq!(db_projects.len(), 1);
+        assert_eq!(fs_projects.len(), 1);
+        assert_eq!(db_projects[0].path, fs_projects[0].path);
+        assert_eq!(db_projects[0].session_count, fs_projects[0].session_count);
+
+        let project_path = db_projects[0].path.clone();
+        let session_ids = |store: &mut SessionStore| {
+            let mut ids: Vec<String> = store
+                .list_sessions(&project_path, 10, 0)
+                .unwrap()
+                .into_iter()
+                .map(|s| s.session_id)
+                .collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(session_ids(&mut db_store), vec!["facade-a", "facade-b"]);
+        assert_eq!(session_ids(&mut fs_store), session_ids(&mut db_store));
+
+        let uuids = |store: &mut SessionStore| -> Vec<String> {
+            store
+                .get_messages("facade-a", 10, 1)
+                .unwrap()
+                .into_iter()
+                .map(|m| m.uuid)
+                .collect()
+        };
+        assert_eq!(uuids(&mut db_store), vec!["facade-a-1", "facade-a-2"]);
+        assert_eq!(uuids(&mut fs_store), uuids(&mut db_store));
+    }
+
+    #[cfg(all(feature = "agent", feature = "client"))]
+    #[test]
+    fn test_probe_prefers_running_agent() {
+        use ai_cli_session_db::agent::{Agent, AgentConfig};
+        use ai_cli_session_db::{CollectLimits, CollectionFilter};
+        use std::sync::Arc;
+
+        let tmp = TempDir::new().unwrap();
+        let mut options = fixture(&tmp);
+        let config = AgentConfig {
+            data_dir: options.data_dir.clone(),
+            idle_timeout_secs: 60,
+            max_waiters: 32,
+            max_connections: 256,
+            collection_filter: CollectionFilter::default(),
+            collect_limits: CollectLimits::default(),
+            streaming_window_secs: 5,
+            session_idle_secs: 120,
+            integrity_check_interval_secs: 0,
+            listen_fd: None,
+            max_content_bytes: None,
+            wal_autocheckpoint_pages: None,
+            log_searches: false,
+            search_log_cap: 1000,
+            source_databases: Default::default(),
+            max_rss_mb: None,
+            memory_hard_limit: false,
+            approval_digest_interval_secs: 0,
+            approval_digest_min_age_secs: 0,
+            status_file_interval_secs: 0,
+            replication_file: None,
+            replication_url: None,
+            replication_batch_size: 500,
+            replication_interval_secs: 30,
+        };
+        options.db_path = config.db_path();
+        collect_into_db(&options);
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let agent = {
+            let _guard = rt.enter();
+            Arc::new(Agent::new(config).unwrap())
+        };
+        let handle = rt.spawn(async move {
+            let _ = agent.run().await;
+        });
+        std::thread::sleep(std::time::Duration::from_millis(500));
+
+        let mut store = SessionStore::probe(&options);
+        assert_eq!(store.kind(), BackendKind::Agent);
+        assert!(store.capabilities().search);
+        let projects = store.list_projects(10, 0).unwrap();
+        assert!(projects.iter().any(|p| p.path == "/tmp/facade-project"));
+        drop(store);
+
+        handle.abort();
+    }
+}
+
+// ==================== 访问权限测试 ====================
+
+#[cfg(unix)]
+mod access_tests {
+    use super::*;
+    use ai_cli_session_db::reader::check_dir_access;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+
+    /// 创建 chmod 000 的 projects 目录，权限不生效（root 运行）时返回 None
+    fn denied_projects_dir(tmp: &TempDir) -> Option<PathBuf> {
+        let dir = tmp.path().join(".claude/projects");
+        std::fs::create_dir_all(dir.join("-tmp-denied")).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o000)).unwrap();
+        if std::fs::read_dir(&dir).is_ok() {
+            restore(&dir);
+            return None;
+        }
+        Some(dir)
+    }
+
+    /// 恢复权限，保证 TempDir 能被清理
+    fn restore(dir: &Path) {
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn test_missing_dir_is_not_an_error() {
+        let tmp = TempDir::new().unwrap();
+        let missing = tmp.path().join("missing");
+
+        assert!(!check_dir_access(&missing).unwrap());
+        let mut reader = SessionReader::new(missing);
+        assert!(reader.list_projects(None).unwrap().is_empty());
+        assert!(reader.list_sessions(None, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_reader_reports_access_denied() {
+        let tmp = TempDir::new().unwrap();
+        let Some(dir) = denied_projects_dir(&tmp) else {
+            return;
+        };
+
+        let mut reader = SessionReader::new(dir.clone());
+        let projects = reader.list_projects(None);
+        let sessions = reader.list_sessions_with_preview(None, false);
+        restore(&dir);
+
+        assert!(matches!(projects, Err(Error::AccessDenied(ref p)) if *p == dir));
+        assert!(matches!(sessions, Err(Error::AccessDenied(ref p)) if *p == dir));
+    }
+
+    #[test]
+    fn test_collect_reports_permission_errors() {
+        let (db, tmp) = setup_db();
+        let Some(dir) = denied_projects_dir(&tmp) else {
+            return;
+        };
+
+        let result = Collector::new(&db)
+            .with_claude_path(dir.clone())
+            .collect_all();
+        restore(&dir);
+
+        let result = result.unwrap();
+        assert_eq!(result.permission_errors, vec![dir]);
+        assert_eq!(result.sessions_scanned, 0);
+    }
+}
+
+// ==================== 边界情况测试 ====================
+
+mod edge_case_tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_content_message() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+
+        let messages = vec![MessageInput {
+            uuid: "uuid-empty".to_string(),
+            r#type: MessageType::User,
+            content_text: "".to_string(), // 空内容
+            content_full: "".to_string(),
+            timestamp: 1000,
+            sequence: 0,
+            source: None,
+            channel: None,
+            model: None,
+            tool_call_id: None,
+            tool_name: None,
+            tool_args: None,
+            raw: None,
+            approval_status: None,
+            approval_resolved_at: None,
+        }];
+
+        let inserted = db.insert_messages("session-001", &messages).unwrap().0;
+        assert_eq!(inserted, 1);
+
+        let loaded = db.list_messages("session-001", 10, 0).unwrap();
+        assert_eq!(loaded[0].content_text, "");
+    }
+
+    #[test]
+    fn test_unicode_content() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db
+            .get_or_create_project("测试项目", "/路径/中文", "claude")
+            .unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+
+        let messages = vec![MessageInput {
+            uuid: "uuid-unicode".to_string(),
+            r#type: MessageType::User,
+            content_text: "你好世界 🌍 مرحبا العالم 🎉 こんにちは".to_string(),
+            content_full: "你好世界 🌍 مرحبا العالم 🎉 こんにちは".to_string(),
+            timestamp: 1000,
+            sequence: 0,
+            source: None,
+            channel: None,
+            model: None,
+            tool_call_id: None,
+            tool_name: None,
+            tool_args: None,
+            raw: None,
+            approval_status: None,
+            approval_resolved_at: None,
+        }];
+
+        db.insert_messages("session-001", &messages).unwrap().0;
+
+        let loaded = db.list_messages("session-001", 10, 0).unwrap();
+        assert_eq!(
+            loaded[0].content_text,
+            "你好世界 🌍 مرحبا العالم 🎉 こんにちは"
+        );
+
+        // 项目名也应该正确
+        let projects = db.list_projects().unwrap();
+        assert_eq!(projects[0].name, "测试项目");
+    }
+
+    #[test]
+    fn test_very_long_content() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+
+        // 创建 100KB 的内容
+        let long_content = "x".repeat(100 * 1024);
+
+        let messages = vec![MessageInput {
+            uuid: "uuid-long".to_string(),
+            r#type: MessageType::Assistant,
+            content_text: long_content.clone(),
+            content_full: long_content.clone(),
+            timestamp: 1000,
+            sequence: 0,
+            source: None,
+            channel: None,
+            model: None,
+            tool_call_id: None,
+            tool_name: None,
+            tool_args: None,
+            raw: None,
+            approval_status: None,
+            approval_resolved_at: None,
+        }];
+
+        db.insert_messages("session-001", &messages).unwrap().0;
+
+        let loaded = db.list_messages("session-001", 10, 0).unwrap();
+        assert_eq!(loaded[0].content_text.len(), 100 * 1024);
+    }
+
+    #[test]
+    fn test_special_characters_in_path() {
+        let (db, _tmp) = setup_db();
+
+        // 路径包含特殊字符
+        let weird_path = "/path/with spaces/and-dashes/and_underscores/and.dots";
+        let project_id = db
+            .get_or_create_project("test", weird_path, "claude")
+            .unwrap();
+
+        let projects = db.list_projects().unwrap();
+        assert_eq!(projects[0].path, weird_path);
+
+        // 再次获取应该返回同一个 ID
+        let id2 = db
+            .get_or_create_project("test", weird_path, "claude")
+            .unwrap();
+        assert_eq!(project_id, id2);
+    }
+
+    #[test]
+    fn test_session_without_messages() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("empty-session", project_id).unwrap();
+
+        let messages = db.list_messages("empty-session", 100, 0).unwrap();
+        assert!(messages.is_empty());
+
+        let sessions = db.list_sessions(project_id).unwrap();
+        assert_eq!(sessions[0].message_count, 0);
+    }
+
+    #[test]
+    fn test_nonexistent_session_messages() {
+        let (db, _tmp) = setup_db();
+
+        // 查询不存在的 session
+        let messages = db.list_messages("nonexistent", 100, 0).unwrap();
+        assert!(messages.is_empty());
+    }
+
+    #[test]
+    fn test_large_batch_insert() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+
+        // 插入 1000 条消息
+        let messages: Vec<MessageInput> = (0..1000)
+            .map(|i| MessageInput {
+                uuid: format!("uuid-{}", i),
+                r#type: if i % 2 == 0 {
+                    MessageType::User
+                } else {
+                    MessageType::Assistant
+                },
+                content_text: format!("Message {}", i),
+                content_full: format!("Message {}", i),
+                timestamp: i as i64,
+                sequence: i as i64,
+                source: None,
+                channel: None,
+                model: None,
+                tool_call_id: None,
+                tool_name: None,
+                tool_args: None,
+                raw: None,
+                approval_status: None,
+                approval_resolved_at: None,
+            })
+            .collect();
+
+        let inserted = db.insert_messages("session-001", &messages).unwrap().0;
+        assert_eq!(inserted, 1000);
+
+        let stats = db.get_stats().unwrap();
+        assert_eq!(stats.message_count, 1000);
+    }
+
+    #[test]
+    fn test_timestamp_ordering() {
+        let (db, _tmp) = setup_db();
+
+        let project_id = db.get_or_create_project("test", "/path", "claude").unwrap();
+        db.upsert_session("session-001", project_id).unwrap();
+
+        // 乱序插入
+        let messages = vec![
+            MessageInput {
+                uuid: "uuid-3".to_string(),
+                r#type: MessageType::User,
+                content_text: "Third".to_string(),
+                content_full: "Third".to_string(),
+                timestamp: 3000,
+                sequence: 2,
+                source: None,
+                channel: None,
+                model: None,
+                tool_call_id: None,
+                tool_name: None,
+                tool_args: None,
+                raw: None,
+                approval_status: None,
+                approval_resolved_at: None,
+            },
+            MessageInput {
+                uuid: "uuid-1".to_string(),
+                r#type: MessageType::User,
+                content_text: "First".to_string(),
+                content_full: "First".to_string(),
+                timestamp: 1000,
+                sequence: 0,
+                source: None,
+                channel: None,
+                model: None,
+                tool_call_id: None,
+                tool_name: None,
+                tool_args: None,
+                raw: None,
+                approval_status: None,
+                approval_resolved_at: None,
+            },
+            MessageInput {
+                uuid: "uuid-2".to_string(),
+                r#type: MessageType::User,
+                content_text: "Second".to_string(),
+                content_full: "Second".to_string(),
+                timestamp: 2000,
+                sequence: 1,
+                source: None,
+                channel: None,
+                model: None,
+                tool_call_id: None,
+                tool_name: None,
+                tool_args: None,
+                raw: None,
+                approval_status: None,
+                approval_resolved_at: None,
+            },
+        ];
+
+        db.insert_messages("session-001", &messages).unwrap().0;
+
+        // 按 sequence 排序返回
+        let loaded = db.list_messages("session-001", 10, 0).unwrap();
+        assert_eq!(loaded[0].content_text, "First");
+        assert_eq!(loaded[1].content_text, "Second");
+        assert_eq!(loaded[2].content_text, "Third");
+    }
+}
+
+// ==================== 错误链测试 ====================
+
+mod error_chain_tests {
+    use super::*;
+
+    /// 模拟下游用 `?` 传播 rusqlite 错误
+    fn insert_duplicate_session(db: &SessionDB) -> Result<()> {
+        let conn = db.connection().lock();
+        for _ in 0..2 {
+            conn.execute(
+                "INSERT INTO sessions (session_id, project_id) VALUES ('dup-session', 1)",
+                [],
+            )?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_constraint_violation_keeps_rusqlite_source() {
+        let (db, _tmp) = setup_db();
+
+        let err = insert_duplicate_session(&db).unwrap_err();
+        assert!(matches!(err, Error::Database(_)));
+        assert!(err.to_string().starts_with("数据库错误"));
+
+        let source = std::error::Error::source(&err).expect("error should have a source");
+        let sqlite_err = source
+            .downcast_ref::<rusqlite::Error>()
+            .expect("source should be rusqlite::Error");
+        assert_eq!(
+            sqlite_err.sqlite_error_code(),
+            Some(rusqlite::ErrorCode::ConstraintViolation)
+        );
+
+        // 转成 anyhow 后仍能沿错误链取到底层错误
+        let err = anyhow::Error::from(err);
+        assert!(err
+            .chain()
+            .any(|cause| cause.downcast_ref::<rusqlite::Error>().is_some()));
+    }
+}
+
+// ==================== Writer 转换函数测试 ====================
+
+#[cfg(feature = "writer")]
+mod writer_conversion_tests {
+    use ai_cli_session_collector::{MessageType, ParsedContent, ParsedMessage, Source};
+    use ai_cli_session_db::writer::{
+        convert_message, convert_messages, convert_messages_filtered, CollectionFilter, SkipReason,
+    };
+
+    fn create_parsed_message(uuid: &str, msg_type: MessageType, content: &str) -> ParsedMessage {
+        ParsedMessage {
+            uuid: uuid.to_string(),
+            session_id: "session-001".to_string(),
+            message_type: msg_type,
+            content: ParsedContent {
+                text: content.to_string(),
+                full: content.to_string(),
+            },
+            timestamp: Some("2024-01-15T10:30:00Z".to_string()),
+            source: Source::Claude,
+            channel: Some("code".to_string()),
+            model: None,
+            tool_call_id: None,
+            tool_name: None,
+            tool_args: None,
+            raw: None,
+            cwd: None,
+            stop_reason: None,
+        }
+    }
+
+    #[test]
+    fn test_convert_user_message() {
+        let parsed = create_parsed_message("uuid-1", MessageType::User, "Hello");
+        let input = convert_message(&parsed, 0);
+
+        assert_eq!(input.uuid, "uuid-1");
+        assert_eq!(input.r#type, MessageType::User);
+        assert_eq!(input.content_text, "Hello");
+        assert_eq!(input.sequence, 0);
+        // timestamp 应该被解析为毫秒
+        assert!(input.timestamp > 0);
+    }
+
+    #[test]
+    fn test_convert_assistant_message() {
+        let parsed = create_parsed_message("uuid-2", MessageType::Assistant, "Hi there");
+        let input = convert_message(&parsed, 1);
+
+        assert_eq!(input.r#type, MessageType::Assistant);
+    }
+
+    #[test]
+    fn test_convert_tool_message() {
+        let parsed = create_parsed_message("uuid-3", MessageType::Tool, "Tool output");
+        let input = convert_message(&parsed, 2);
+
+        // Tool 消息现在保留为 Tool 类型
+        assert_eq!(input.r#type, MessageType::Tool);
+    }
+
+    #[test]
+    fn test_convert_messages_batch() {
+        let messages = vec![
+            create_parsed_message("uuid-1", MessageType::User, "Q1"),
+            create_parsed_message("uuid-2", MessageType::Assistant, "A1"),
+            create_parsed_message("uuid-3", MessageType::User, "Q2"),
+        ];
+
+        let inputs = convert_messages(&messages);
+
+        assert_eq!(inputs.len(), 3);
+        assert_eq!(inputs[0].sequence, 0);
+        assert_eq!(inputs[1].sequence, 1);
+        assert_eq!(inputs[2].sequence, 2);
+    }
+
+    #[test]
+    fn test_convert_message_without_timestamp() {
+        let mut parsed = create_parsed_message("uuid-1", MessageType::User, "Hello");
+        parsed.timestamp = None;
+
+        let input = convert_message(&parsed, 0);
+        assert_eq!(input.timestamp, 0); // 默认为 0
+    }
+
+    #[test]
+    fn test_convert_message_invalid_timestamp() {
+        let mut parsed = create_parsed_message("uuid-1", MessageType::User, "Hello");
+        parsed.timestamp = Some("invalid-date".to_string());
+
+        let input = convert_message(&parsed, 0);
+        assert_eq!(input.timestamp, 0); // 解析失败默认为 0
+    }
+
+    #[test]
+    fn test_default_filter_skips_only_empty() {
+        let filter = CollectionFilter::default();
+
+        assert!(filter.should_skip(&create_parsed_message("uuid-1", MessageType::System, "")));
+        assert!(!filter.should_skip(&create_parsed_message("uuid-2", MessageType::User, "ok")));
+
+        // content_full 非空时保留
+        let mut parsed = create_parsed_message("uuid-3", MessageType::Tool, "");
+        parsed.content.full = "{\"result\":1}".to_string();
+        assert!(!filter.should_skip(&parsed));
+
+        let empty = create_parsed_message("uuid-4", MessageType::System, "");
+        assert!(!CollectionFilter::disabled().should_skip(&empty));
+    }
+
+    #[test]
+    fn test_strict_filter_reasons() {
+        let filter = CollectionFilter {
+            skip_empty_messages: true,
+            skip_types: vec!["progress".to_string()],
+            min_content_chars: 3,
+            skip_tool_names: vec!["TodoWrite".to_string()],
+        };
+
+        let mut progress = create_parsed_message("uuid-1", MessageType::System, "Working...");
+        progress.raw = Some("{\"type\":\"progress\"}".to_string());
+        assert_eq!(filter.skip_reason(&progress), Some(SkipReason::Type));
+
+        let mut todo = create_parsed_message("uuid-2", MessageType::Tool, "todo list updated");
+        todo.tool_name = Some("TodoWrite".to_string());
+        assert_eq!(filter.skip_reason(&todo), Some(SkipReason::ToolName));
+
+        let short = create_parsed_message("uuid-3", MessageType::User, " ok ");
+        assert_eq!(filter.skip_reason(&short), Some(SkipReason::TooShort));
+
+        let empty = create_parsed_message("uuid-4", MessageType::User, "");
+        assert_eq!(filter.skip_reason(&empty), Some(SkipReason::Empty));
+
+        let kept = create_parsed_message("uuid-5", MessageType::User, "中文问题");
+        assert_eq!(filter.skip_reason(&kept), None);
+    }
+
+    #[test]
+    fn test_convert_messages_filtered_keeps_sequence_contiguous() {
+        let messages = vec![
+            create_parsed_message("uuid-1", MessageType::User, "Q1"),
+            create_parsed_message("uuid-2", MessageType::System, ""),
+            create_parsed_message("uuid-3", MessageType::Assistant, "A1"),
+            create_parsed_message("uuid-4", MessageType::System, ""),
+            create_parsed_message("uuid-5", MessageType::User, "Q2"),
+        ];
+
+        let (inputs, skipped) =
+            convert_messages_filtered(&messages, 10, &CollectionFilter::default());
+
+        assert_eq!(skipped, 2);
+        let uuids: Vec<_> = inputs.iter().map(|m| m.uuid.as_str()).collect();
+        assert_eq!(uuids, vec!["uuid-1", "uuid-3", "uuid-5"]);
+        let sequences: Vec<_> = inputs.iter().map(|m| m.sequence).collect();
+        assert_eq!(sequences, vec![10, 11, 12]);
+    }
+}
+
+// ==================== FFI 测试 ====================
+
+#[cfg(feature = "ffi")]
+mod ffi_tests {
+    use super::*;
+    use ai_cli_session_db::ffi::*;
+    use ai_cli_session_db::migrations::SUPPORTED_SCHEMA_VERSION;
+    use std::ffi::{CStr, CString};
+
+    #[test]
+    fn test_library_version_matches_constants() {
+        let version = session_db_version();
+        assert!(!version.is_null());
+        let text = unsafe { CStr::from_ptr(version) }
+            .to_str()
+            .unwrap()
+            .to_string();
+        unsafe { session_db_free_string(version) };
+
+        assert_eq!(text, VERSION_FULL);
+        assert_eq!(session_db_build_timestamp(), BUILD_TIMESTAMP);
+        assert!(text.ends_with(&BUILD_TIMESTAMP.to_string()));
+        assert_eq!(
+            session_db_supported_schema_version(),
+            SUPPORTED_SCHEMA_VERSION as i64
+        );
+    }
+
+    #[test]
+    fn test_schema_version_of_new_database() {
+        let tmp = TempDir::new().unwrap();
+        let path = CString::new(tmp.path().join("test.db").to_str().unwrap()).unwrap();
+
+        let mut handle = std::ptr::null_mut();
+        let err = unsafe { session_db_connect(path.as_ptr(), &mut handle) };
+        assert_eq!(err, FfiError::Success);
+
+        // 新建的数据库已迁移到支持的最新版本
+        let mut version = -1;
+        let err = unsafe { session_db_schema_version(handle, &mut version) };
+        assert_eq!(err, FfiError::Success);
+        assert_eq!(version, session_db_supported_schema_version());
+
+        let err = unsafe { session_db_schema_version(handle, std::ptr::null_mut()) };
+        assert_eq!(err, FfiError::NullPointer);
+
+        unsafe { session_db_close(handle) };
+    }
+
+    #[test]
+    fn test_change_counter() {
+        let tmp = TempDir::new().unwrap();
+        let path = CString::new(tmp.path().join("test.db").to_str().unwrap()).unwrap();
+
+        let mut handle = std::ptr::null_mut();
+        let err = unsafe { session_db_connect(path.as_ptr(), &mut handle) };
+        assert_eq!(err, FfiError::Success);
+
+        let mut counter = u64::MAX;
+        let err = unsafe { session_db_change_counter(handle, &mut counter) };
+        assert_eq!(err, FfiError::Success);
+        assert_eq!(counter, 0);
+
+        let name = CString::new("p").unwrap();
+        let project_path = CString::new("/p").unwrap();
+        let source = CString::new("claude").unwrap();
+        let mut project_id = 0;
+        let err = unsafe {
+            session_db_upsert_project(
+                handle,
+                name.as_ptr(),
+                project_path.as_ptr(),
+                source.as_ptr(),
+                &mut project_id,
+            )
+        };
+        assert_eq!(err, FfiError::Success);
+
+        let err = unsafe { session_db_change_counter(handle, &mut counter) };
+        assert_eq!(err, FfiError::Success);
+        assert_eq!(counter, 1);
+
+        let err = unsafe { session_db_change_counter(handle, std::ptr::null_mut()) };
+        assert_eq!(err, FfiError::NullPointer);
+
+        unsafe { session_db_close(handle) };
+    }
+
+    /// 一块消息的 C 字符串（需在 push 返回前保持存活）
+    fn message_chunk(range: std::ops::Range<usize>) -> Vec<(CString, CString)> {
+        range
+            .map(|i| {
+                (
+                    CString::new(format!("bulk-{}", i)).unwrap(),
+                    CString::new(format!("imported message {}", i)).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    fn message_inputs(chunk: &[(CString, CString)], first_sequence: usize) -> Vec<MessageInputC> {
+        chunk
+            .iter()
+            .enumerate()
+            .map(|(i, (uuid, content))| MessageInputC {
+                uuid: uuid.as_ptr(),
+                role: (i % 2) as i32,
+                content: content.as_ptr(),
+                timestamp: 1_000_000 + (first_sequence + i) as i64,
+                sequence: (first_sequence + i) as i64,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chunked_writer() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let path = CString::new(db_path.to_str().unwrap()).unwrap();
+
+        let mut handle = std::ptr::null_mut();
+        assert_eq!(
+            unsafe { session_db_connect(path.as_ptr(), &mut handle) },
+            FfiError::Success
+        );
+        let (name, project_path, source) = (
+            CString::new("import").unwrap(),
+            CString::new("/import").unwrap(),
+            CString::new("claude").unwrap(),
+        );
+        let mut project_id = 0;
+        let session_id = CString::new("imported").unwrap();
+        unsafe {
+            session_db_upsert_project(
+                handle,
+                name.as_ptr(),
+                project_path.as_ptr(),
+                source.as_ptr(),
+                &mut project_id,
+            );
+            session_db_upsert_session(handle, session_id.as_ptr(), project_id);
+        }
+
+        let mut writer = std::ptr::null_mut();
+        assert_eq!(
+            unsafe { session_db_writer_begin(handle, session_id.as_ptr(), &mut writer) },
+            FfiError::Success
+        );
+
+        // 10 块 x 100 条，第 2 块开头 20 条与第 1 块重叠
+        const CHUNK: usize = 100;
+        for chunk_index in 0..10 {
+            let start = if chunk_index == 1 {
+                80
+            } else {
+                chunk_index * CHUNK
+            };
+            let chunk = message_chunk(start..start + CHUNK);
+            let inputs = message_inputs(&chunk, start);
+            let err = unsafe { session_db_writer_push(writer, inputs.as_ptr(), inputs.len()) };
+            assert_eq!(err, FfiError::Success);
+            // 缓冲区只与块大小有关，不随总写入量增长
+            assert!(unsafe { (*writer).buffer_capacity() } <= CHUNK);
+        }
+        assert_eq!(unsafe { (*writer).total_inserted() }, 9 * CHUNK + 80);
+
+        // finish 之前会话计数未更新
+        let db = SessionDB::connect(DbConfig::local(&db_path)).unwrap();
+        let count = |db: &SessionDB| db.get_session("imported").unwrap().unwrap().message_count;
+        assert_eq!(count(&db), 0);
+
+        let mut total = 0;
+        assert_eq!(
+            unsafe { session_db_writer_finish(writer, &mut total) },
+            FfiError::Success
+        );
+        assert_eq!(total, 9 * CHUNK + 80);
+        assert_eq!(count(&db), (9 * CHUNK + 80) as i64);
+
+        // 误用返回错误码而不是未定义行为
+        let chunk = message_chunk(5000..5001);
+        let inputs = message_inputs(&chunk, 5000);
+        assert_eq!(
+            unsafe { session_db_writer_push(writer, inputs.as_ptr(), inputs.len()) },
+            FfiError::WriterFinished
+        );
+        assert_eq!(
+            unsafe { session_db_writer_finish(writer, &mut total) },
+            FfiError::WriterFinished
+        );
+        assert_eq!(count(&db), (9 * CHUNK + 80) as i64);
+
+        unsafe {
+            session_db_writer_free(writer);
+            session_db_close(handle);
+        }
+    }
+
+    #[test]
+    fn test_detect_adapter() {
+        let session = CString::new("/tmp/.claude/projects/-tmp-demo/session.jsonl").unwrap();
+        let mut source = std::ptr::null_mut();
+        let err = unsafe { session_db_detect_adapter(session.as_ptr(), &mut source) };
+        assert_eq!(err, FfiError::Success);
+        let name = unsafe { CStr::from_ptr(source) }
+            .to_str()
+            .unwrap()
+            .to_string();
+        unsafe { session_db_free_string(source) };
+        assert_eq!(name, "claude");
+
+        // 不是会话格式：NotFound，不写出参数
+        let notes = CString::new("/tmp/notes.txt").unwrap();
+        let mut source = std::ptr::null_mut();
+        let err = unsafe { session_db_detect_adapter(notes.as_ptr(), &mut source) };
+        assert_eq!(err, FfiError::NotFound);
+        assert!(source.is_null());
+
+        let err = unsafe { session_db_detect_adapter(notes.as_ptr(), std::ptr::null_mut()) };
+        assert_eq!(err, FfiError::NullPointer);
+    }
+
+    /// FFI 中 limit 0 表示不限制，但最多返回 MAX_QUERY_LIMIT 条
+    #[test]
+    fn test_zero_limit_is_capped() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let db = SessionDB::connect(DbConfig::local(&db_path)).unwrap();
+        let project_id = db.get_or_create_project("big", "/big", "claude").unwrap();
+        db.upsert_session("big-session", project_id).unwrap();
+
+        let cap = ai_cli_session_db::config::MAX_QUERY_LIMIT;
+        let messages: Vec<MessageInput> = (0..cap + 50)
+            .map(|i| MessageInput {
+                uuid: format!("big-{}", i),
+                r#type: MessageType::User,
+                content_text: String::new(),
+                content_full: format!("m{}", i),
+                timestamp: i as i64,
+                sequence: i as i64,
+                source: None,
+                channel: None,
+                model: None,
+                tool_call_id: None,
+                tool_name: None,
+                tool_args: None,
+                raw: None,
+                approval_status: None,
+                approval_resolved_at: None,
+            })
+            .collect();
+        db.insert_messages("big-session", &messages).unwrap();
+
+        let path = CString::new(db_path.to_str().unwrap()).unwrap();
+        let session_id = CString::new("big-session").unwrap();
+        let mut handle = std::ptr::null_mut();
+        assert_eq!(
+            unsafe { session_db_connect(path.as_ptr(), &mut handle) },
+            FfiError::Success
+        );
+
+        let mut array = std::ptr::null_mut();
+        let err = unsafe {
+            session_db_list_messages_with_options(
+                handle,
+                session_id.as_ptr(),
+                0,
+                0,
+                OrderC::Asc,
+                false,
+                &mut array,
+            )
+        };
+        assert_eq!(err, FfiError::Success);
+        assert_eq!(unsafe { (*array).len }, cap);
+        unsafe { session_db_free_messages(array) };
+
+        // 小于上限的 limit 原样生效
+        let mut array = std::ptr::null_mut();
+        let err = unsafe {
+            session_db_list_messages_with_options(
+                handle,
+                session_id.as_ptr(),
+                7,
+                0,
+                OrderC::Asc,
+                false,
+                &mut array,
+            )
+        };
+        assert_eq!(err, FfiError::Success);
+        assert_eq!(unsafe { (*array).len }, 7);
+        unsafe { session_db_free_messages(array) };
+
+        // Desc 时 offset 从最新一条计数
+        let mut array = std::ptr::null_mut();
+        let err = unsafe {
+            session_db_list_messages_with_options(
+                handle,
+                session_id.as_ptr(),
+                2,
+                1,
+                OrderC::Desc,
+                false,
+                &mut array,
+            )
+        };
+        assert_eq!(err, FfiError::Success);
+        let sequences: Vec<i64> = unsafe {
+            std::slice::from_raw_parts((*array).data, (*array).len)
+                .iter()
+                .map(|m| m.sequence)
+                .collect()
+        };
+        let last = (cap + 50) as i64 - 1;
+        assert_eq!(sequences, vec![last - 1, last - 2]);
+        unsafe {
+            session_db_free_messages(array);
+            session_db_close(handle);
+        }
+    }
+
+    /// 搜索不返回正文时按 message_id 取回正文
+    #[cfg(feature = "fts")]
+    #[test]
+    fn test_search_without_content_then_fetch_by_ids() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let db = SessionDB::connect(DbConfig::local(&db_path)).unwrap();
+        let project_id = db.get_or_create_project("big", "/big", "claude").unwrap();
+        db.upsert_session("big-session", project_id).unwrap();
+        let bodies: Vec<String> = (0..3)
+            .map(|i| format!("needle {} {}", i, "y ".repeat(20_000)))
+            .collect();
+        let messages: Vec<MessageInput> = bodies
+            .iter()
+            .enumerate()
+            .map(|(i, body)| MessageInput {
+                uuid: format!("big-{}", i),
+                r#type: MessageType::Tool,
+                content_text: body.clone(),
+                content_full: body.clone(),
+                timestamp: i as i64,
+                sequence: i as i64,
+                source: None,
+                channel: None,
+                model: None,
+                tool_call_id: None,
+                tool_name: None,
+                tool_args: None,
+                raw: None,
+                approval_status: None,
+                approval_resolved_at: None,
+            })
+            .collect();
+        db.insert_messages("big-session", &messages).unwrap();
+
+        let path = CString::new(db_path.to_str().unwrap()).unwrap();
+        let query = CString::new("needle").unwrap();
+        let mut handle = std::ptr::null_mut();
+        assert_eq!(
+            unsafe { session_db_connect(path.as_ptr(), &mut handle) },
+            FfiError::Success
+        );
+
+        let mut array = std::ptr::null_mut();
+        let err = unsafe {
+            session_db_search_fts_full(
+                handle,
+                query.as_ptr(),
+                10,
+                -1,
+                SearchOrderByC::TimeAsc,
+                -1,
+                -1,
+                0,
+                false,
+                &mut array,
+            )
+        };
+        assert_eq!(err, FfiError::Success);
+        let hits = unsafe { std::slice::from_raw_parts((*array).data, (*array).len) };
+        assert_eq!(hits.len(), 3);
+        let ids: Vec<i64> = hits.iter().map(|hit| hit.message_id).collect();
+        for hit in hits {
+            assert_eq!(unsafe { CStr::from_ptr(hit.content) }.to_bytes(), b"");
+            assert!(!unsafe { CStr::from_ptr(hit.snippet) }.to_bytes().is_empty());
+        }
+        unsafe { session_db_free_search_results(array) };
+
+        let mut messages = std::ptr::null_mut();
+        let err = unsafe {
+            session_db_get_messages_by_ids(handle, ids.as_ptr(), ids.len(), &mut messages)
+        };
+        assert_eq!(err, FfiError::Success);
+        let fetched = unsafe { std::slice::from_raw_parts((*messages).data, (*messages).len) };
+        let fetched: Vec<&str> = fetched
+            .iter()
+            .map(|m| unsafe { CStr::from_ptr(m.content) }.to_str().unwrap())
+            .collect();
+        assert_eq!(fetched, bodies);
+        unsafe {
+            session_db_free_messages(messages);
+            session_db_close(handle);
+        }
+    }
+
+    #[cfg(feature = "fts")]
+    #[test]
+    fn test_search_fts_in_range() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        let db = SessionDB::connect(DbConfig::local(&db_path)).unwrap();
+        let project_id = db.get_or_create_project("p", "/p", "claude").unwrap();
+        db.upsert_session("range-session", project_id).unwrap();
+        let now = chrono::Utc::now().timestamp_millis();
+        let messages: Vec<MessageInput> = [now, now - 10 * 24 * 60 * 60 * 1000]
+            .iter()
+            .enumerate()
+            .map(|(i, &timestamp)| MessageInput {
+                uuid: format!("range-{}", i),
+                r#type: MessageType::User,
+                content_text: "weekly report".to_string(),
+                content_full: "weekly report".to_string(),
+                timestamp,
+                sequence: i as i64,
+                source: None,
+                channel: None,
+                model: None,
+                tool_call_id: None,
+                tool_name: None,
+                tool_args: None,
+                raw: None,
+                approval_status: None,
+                approval_resolved_at: None,
+            })
+            .collect();
+        db.insert_messages("range-session", &messages).unwrap();
+
+        let path = CString::new(db_path.to_str().unwrap()).unwrap();
+        let query = CString::new("report").unwrap();
+        let mut handle = std::ptr::null_mut();
+        assert_eq!(
+            unsafe { session_db_connect(path.as_ptr(), &mut handle) },
+            FfiError::Success
+        );
+
+        let search = |range: &str, out: &mut *mut SearchResultArray| {
+            let range = CString::new(range).unwrap();
+            unsafe {
+                session_db_search_fts_in_range(
+                    handle,
+                    query.as_ptr(),
+                    10,
+                    -1,
+                    SearchOrderByC::TimeDesc,
+                    range.as_ptr(),
+                    0,
+                    false,
+                    0,
+                    false,
+                    out,
+                )
+            }
+        };
+
+        let mut array = std::ptr::null_mut();
+        assert_eq!(search("last7days", &mut array), FfiError::Success);
+        let hits = unsafe { std::slice::from_raw_parts((*array).data, (*array).len) };
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].timestamp, now);
+        unsafe { session_db_free_search_results(array) };
+
+        let mut array = std::ptr::null_mut();
+        assert_eq!(search("lastweek", &mut array), FfiError::InvalidArgument);
+        assert!(array.is_null());
+
+        unsafe { session_db_close(handle) };
+    }
+
+    /// 写入 10 条消息的会话文件（uuid 为 msg-0 .. msg-9）
+    fn write_ten_message_session(tmp: &TempDir) -> CString {
+        let path = tmp.path().join("paging-session.jsonl");
+        let content: String = (0..10)
+            .map(|i| {
+                format!(
+                    "{{\"type\":\"user\",\"uuid\":\"msg-{i}\",\"sessionId\":\"paging-session\",\"cwd\":\"/tmp/paging\",\"timestamp\":\"2025-01-01T00:00:{i:02}Z\",\"message\":{{\"role\":\"user\",\"content\":\"message {i}\"}}}}\n"
+                )
+            })
+            .collect();
+        std::fs::write(&path, content).unwrap();
+        CString::new(path.to_str().unwrap()).unwrap()
+    }
+
+    /// 以 limit 3 按指定方向翻完所有页，返回 (各页 uuid, 各页 has_more)
+    fn page_through(path: &CString, order: OrderC) -> (Vec<Vec<String>>, Vec<bool>) {
+        let mut pages = Vec::new();
+        let mut has_more_flags = Vec::new();
+        let mut offset = 0;
+        loop {
+            let mut result = std::ptr::null_mut();
+            let err = unsafe {
+                session_db_read_session_messages(path.as_ptr(), 3, offset, order, &mut result)
+            };
+            assert_eq!(err, FfiError::Success);
+
+            let r = unsafe { &*result };
+            assert_eq!(r.total, 10);
+            let uuids: Vec<String> = (0..r.message_count)
+                .map(|i| unsafe {
+                    CStr::from_ptr((*r.messages.add(i)).uuid)
+                        .to_str()
+                        .unwrap()
+                        .to_string()
+                })
+                .collect();
+            let has_more = r.has_more;
+            unsafe { session_db_free_messages_result(result) };
+
+            offset += uuids.len();
+            pages.push(uuids);
+            has_more_flags.push(has_more);
+            if !has_more {
+                return (pages, has_more_flags);
+            }
+            assert!(pages.len() < 10, "pagination did not terminate");
+        }
+    }
+
+    #[test]
+    fn test_read_session_messages_paging_parity() {
+        let tmp = TempDir::new().unwrap();
+        let path = write_ten_message_session(&tmp);
+        let expected: Vec<String> = (0..10).map(|i| format!("msg-{}", i)).collect();
+
+        for order in [OrderC::Asc, OrderC::Desc] {
+            let (pages, has_more) = page_through(&path, order);
+
+            let sizes: Vec<usize> = pages.iter().map(Vec::len).collect();
+            assert_eq!(sizes, vec![3, 3, 3, 1]);
+            assert_eq!(has_more, vec![true, true, true, false]);
+
+            // 每条消息恰好出现一次，且按所选方向排列
+            let mut all: Vec<String> = pages.concat();
+            if order == OrderC::Desc {
+                all.reverse();
+            }
+            assert_eq!(all, expected);
+        }
+
+        // 降序第一页是最新的消息
+        let (pages, _) = page_through(&path, OrderC::Desc);
+        assert_eq!(pages[0], vec!["msg-9", "msg-8", "msg-7"]);
+        assert_eq!(pages[3], vec!["msg-0"]);
+    }
+
+    #[test]
+    fn test_read_session_messages_offset_past_end() {
+        let tmp = TempDir::new().unwrap();
+        let path = write_ten_message_session(&tmp);
+
+        for order in [OrderC::Asc, OrderC::Desc] {
+            let mut result = std::ptr::null_mut();
+            let err = unsafe {
+                session_db_read_session_messages(path.as_ptr(), 3, 10, order, &mut result)
+            };
+            assert_eq!(err, FfiError::Success);
+            let r = unsafe { &*result };
+            assert_eq!(r.message_count, 0);
+            assert_eq!(r.total, 10);
+            assert!(!r.has_more);
+            unsafe { session_db_free_messages_result(result) };
+        }
+    }
+
+    #[test]
+    fn test_share_bundle_create_and_render() {
+        let tmp = TempDir::new().unwrap();
+        let db_path = tmp.path().join("test.db");
+        {
+            let db = SessionDB::connect(DbConfig::local(&db_path)).unwrap();
+            let project_id = db
+                .get_or_create_project("share", "/tmp/share", "claude")
+                .unwrap();
+            db.upsert_session("ffi-share", project_id).unwrap();
+            let message = |uuid: &str, r#type, content: &str, sequence| MessageInput {
+                uuid: uuid.to_string(),
+                r#type,
+                content_text: content.to_string(),
+                content_full: content.to_string(),
+                timestamp: 1_000 + sequence,
+                sequence,
+                source: None,
+                channel: None,
+                model: None,
+                tool_call_id: None,
+                tool_name: (r#type == MessageType::Tool).then(|| "Bash".to_string()),
+                tool_args: None,
+                raw: None,
+                approval_status: None,
+                approval_resolved_at: None,
+            };
+            db.insert_messages(
+                "ffi-share",
+                &[
+                    message("m-0", MessageType::User, "run the tests", 0),
+                    message("m-1", MessageType::Tool, "ok\ndone", 1),
+                ],
+            )
+            .unwrap();
+        }
+
+        let path = CString::new(db_path.to_str().unwrap()).unwrap();
+        let mut handle = std::ptr::null_mut();
+        assert_eq!(
+            unsafe { session_db_connect(path.as_ptr(), &mut handle) },
+            FfiError::Success
+        );
+
+        let bundle_path = tmp.path().join("bundle.json");
+        let bundle_c = CString::new(bundle_path.to_str().unwrap()).unwrap();
+        let session_id = CString::new("ffi-share").unwrap();
+        let err = unsafe {
+            session_db_create_share_bundle(
+                handle,
+                session_id.as_ptr(),
+                -1,
+                0,
+                ToolMessageModeC::Omitted,
+                true,
+                false,
+                bundle_c.as_ptr(),
+            )
+        };
+        assert_eq!(err, FfiError::Success);
+        let bundle =
+            ShareBundle::from_json(&std::fs::read_to_string(&bundle_path).unwrap()).unwrap();
+        assert_eq!(bundle.range, None);
+        assert!(bundle.redacted);
+        assert_eq!(bundle.messages.len(), 1);
+
+        let mut markdown = std::ptr::null_mut();
+        let err = unsafe { session_db_render_share_bundle(bundle_c.as_ptr(), &mut markdown) };
+        assert_eq!(err, FfiError::Success);
+        let text = unsafe { CStr::from_ptr(markdown) }
+            .to_str()
+            .unwrap()
+            .to_string();
+        unsafe { session_db_free_string(markdown) };
+        assert!(text.contains("run the tests"));
+
+        let missing = CString::new("missing").unwrap();
+        let err = unsafe {
+            session_db_create_share_bundle(
+                handle,
+                missing.as_ptr(),
+                -1,
+                0,
+                ToolMessageModeC::Full,
+                false,
+                false,
+                bundle_c.as_ptr(),
+            )
+        };
+        assert_eq!(err, FfiError::NotFound);
+
+        unsafe { session_db_close(handle) };
+    }
+}
+
+// ==================== Agent + Client 集成测试 ====================
+
+#[cfg(all(feature = "agent", feature = "client"))]
+mod agent_client_tests {
+    use ai_cli_session_db::agent::{Agent, AgentConfig};
+    use ai_cli_session_db::protocol::{QueryType, Request, Response, PROTOCOL_VERSION};
+    use ai_cli_session_db::{CollectLimits, CollectionFilter};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tempfile::TempDir;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::UnixStream;
+    use tokio::time::sleep;
+
+    /// 创建测试配置
+    fn test_agent_config() -> (AgentConfig, TempDir) {
+        let temp_dir = TempDir::new().unwrap();
+        let config = AgentConfig {
+            data_dir: temp_dir.path().to_path_buf(),
+            idle_timeout_secs: 60,
+            max_waiters: 32,
+            max_connections: 256,
+            collection_filter: CollectionFilter::default(),
+            collect_limits: CollectLimits::default(),
+            streaming_window_secs: 5,
+            session_idle_secs: 120,
+            integrity_check_interval_secs: 0,
+            listen_fd: None,
+            max_content_bytes: None,
+            wal_autocheckpoint_pages: None,
+            log_searches: false,
+            search_log_cap: 1000,
+            source_databases: Default::default(),
+            max_rss_mb: None,
+            memory_hard_limit: false,
+            approval_digest_interval_secs: 0,
+            approval_digest_min_age_secs: 0,
+            status_file_interval_secs: 0,
+            replication_file: None,
+            replication_url: None,
+            replication_batch_size: 500,
+            replication_interval_secs: 30,
+        };
+        (config, temp_dir)
+    }
+
+    #[tokio::test]
+    async fn test_agent_client_full_handshake() {
+        let (agent_config, _tmp) = test_agent_config();
+        let socket_path = agent_config.socket_path();
+
+        // 启动 Agent
+        let agent = Arc::new(Agent::new(agent_config).unwrap());
+        let agent_handle = {
+            let agent = agent.clone();
+            tokio::spawn(async move {
+                let _ = agent.run().await;
+            })
+        };
+
+        sleep(Duration::from_millis(500)).await;
+
+        // 连接并握手
+        let stream = UnixStream::connect(&socket_path).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+
+        let handshake = Request::Handshake {
+            component: "integration-test".to_string(),
+            version: "1.0.0".to_string(),
+            protocol_version: PROTOCOL_VERSION,
+        };
+        writer
+            .write_all(format!("{}\n", serde_json::to_string(&handshake).unwrap()).as_bytes())
+            .await
+            .unwrap();
+
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        let response: Response = serde_json::from_str(&line).unwrap();
+
+        match response {
+            Response::HandshakeOk { agent_version, .. } => {
+                assert!(!agent_version.is_empty());
+            }
+            _ => panic!("Expected HandshakeOk"),
+        }
+
+        agent_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_socket_activated_agent_handshake() {
+        use ai_cli_session_db::client::{connect_or_start_agent, ClientConfig};
+        use std::os::unix::io::IntoRawFd;
+
+        // 模拟服务管理器：在另一个目录预先绑定 socket，Agent 启动前就可连接
+        let client_dir = TempDir::new().unwrap();
+        let activated_path = client_dir.path().join("agent.sock");
+        let listener = std::os::unix::net::UnixListener::bind(&activated_path).unwrap();
+
+        let (mut agent_config, _tmp) = test_agent_config();
+        agent_config.listen_fd = Some(listener.into_raw_fd());
+        let own_socket_path = agent_config.socket_path();
+
+        let agent = Arc::new(Agent::new(agent_config).unwrap());
+        let agent_handle = {
+            let agent = agent.clone();
+            tokio::spawn(async move {
+                let _ = agent.run().await;
+            })
+        };
+
+        let config = ClientConfig {
+            data_dir: client_dir.path().to_path_buf(),
+            ..ClientConfig::new("test").with_socket_activation()
+        };
+        let mut client = connect_or_start_agent(config).await.unwrap();
+        let response = client.request(&Request::Heartbeat).await.unwrap();
+        assert!(matches!(response, Response::Ok));
+
+        // Agent 没有自己 bind，移交的 socket 文件保留
+        assert!(!own_socket_path.exists());
+        assert!(activated_path.exists());
+
+        agent_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_agent_client_heartbeat() {
+        let (agent_config, _tmp) = test_agent_config();
+        let socket_path = agent_config.socket_path();
+
+        let agent = Arc::new(Agent::new(agent_config).unwrap());
+        let agent_handle = {
+            let agent = agent.clone();
+            tokio::spawn(async move {
+                let _ = agent.run().await;
+            })
+        };
+
+        sleep(Duration::from_millis(500)).await;
+
+        let stream = UnixStream::connect(&socket_path).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+
+        // 握手
+        let handshake = Request::Handshake {
+            component: "test".to_string(),
+            version: "1.0.0".to_string(),
+            protocol_version: PROTOCOL_VERSION,
+        };
+        writer
+            .write_all(format!("{}\n", serde_json::to_string(&handshake).unwrap()).as_bytes())
+            .await
+            .unwrap();
+
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+
+        // 心跳
+        line.clear();
+        let heartbeat = Request::Heartbeat;
+        writer
+            .write_all(format!("{}\n", serde_json::to_string(&heartbeat).unwrap()).as_bytes())
+            .await
+            .unwrap();
+
+        reader.read_line(&mut line).await.unwrap();
+        let response: Response = serde_json::from_str(&line).unwrap();
+        assert!(matches!(response, Response::Ok));
+
+        agent_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_agent_client_query_status() {
+        let (agent_config, _tmp) = test_agent_config();
+        let socket_path = agent_config.socket_path();
+
+        let agent = Arc::new(Agent::new(agent_config).unwrap());
+        let agent_handle = {
+            let agent = agent.clone();
+            tokio::spawn(async move {
+                let _ = agent.run().await;
+            })
+        };
+
+        sleep(Duration::from_millis(500)).await;
+
+        let stream = UnixStream::connect(&socket_path).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+
+        // 握手
+        let handshake = Request::Handshake {
+            component: "test".to_string(),
+            version: "1.0.0".to_string(),
+            protocol_version: PROTOCOL_VERSION,
+        };
+        writer
+            .write_all(format!("{}\n", serde_json::to_string(&handshake).unwrap()).as_bytes())
+            .await
+            .unwrap();
+
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+
+        // 查询状态
+        line.clear();
+        let query = Request::Query {
+            query_type: QueryType::Status,
+        };
+        writer
+            .write_all(format!("{}\n", serde_json::to_string(&query).unwrap()).as_bytes())
+            .await
+            .unwrap();
+
+        reader.read_line(&mut line).await.unwrap();
+        let response: Response = serde_json::from_str(&line).unwrap();
+
+        match response {
+            Response::QueryResult { data } => {
+                assert!(data.get("agent_version").is_some());
+                assert!(data.get("connections").is_some());
+                // 生效的配置
+                assert_eq!(data["config"]["agent"]["max_waiters"], 32);
+                assert!(data["config"]["collector"]["skip_types"].is_array());
+                assert_eq!(data["sqlite_version"], rusqlite::version());
+                // 未按来源拆分时只有默认数据库
+                let databases = data["databases"].as_array().unwrap();
+                assert_eq!(databases.len(), 1);
+                assert_eq!(databases[0]["name"], "default");
+            }
+            _ => panic!("Expected QueryResult"),
+        }
+
+        agent_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_multiple_clients_concurrent() {
+        let (agent_config, _tmp) = test_agent_config();
+        let socket_path = agent_config.socket_path();
+
+        let agent = Arc::new(Agent::new(agent_config).unwrap());
+        let agent_handle = {
+            let agent = agent.clone();
+            tokio::spawn(async move {
+                let _ = agent.run().await;
+            })
+        };
+
+        sleep(Duration::from_millis(500)).await;
+
+        // 创建多个并发客户端
+        let mut handles = vec![];
+
+        for i in 0..3 {
+            let socket_path = socket_path.clone();
+            let handle = tokio::spawn(async move {
+                let stream = UnixStream::connect(&socket_path).await.unwrap();
+                let (reader, mut writer) = stream.into_split();
+                let mut reader = BufReader::new(reader);
+
+                // 握手
+                let handshake = Request::Handshake {
+                    component: format!("client-{}", i),
+                    version: "1.0.0".to_string(),
+                    protocol_version: PROTOCOL_VERSION,
+                };
+                writer
+                    .write_all(format!("{}\n", serde_json::to_string(&handshake).unwrap()).as_bytes())
+                    .await
+                    .unwrap();
+
+                let mut line = String::new();
+                reader.read_line(&mut line).await.unwrap();
+
+                let response: Response = serde_json::from_str(&line).unwrap();
+                assert!(matches!(response, Response::HandshakeOk { .. }));
+
+                sleep(Duration::from_millis(100)).await;
+                i
+            });
+            handles.push(handle);
+        }
+
+        // 等待所有客户端
+        for handle in handles {
+            let client_id = handle.await.unwrap();
+            assert!(client_id < 3);
+        }
+
+        agent_handle.abort();
     }
 
+
     #[tokio::test]
-    async fn test_agent_client_full_handshake() {
+    async fn test_connection_count_query() {
         let (agent_config, _tmp) = test_agent_config();
         let socket_path = agent_config.socket_path();
 
-        // 启动 Agent
         let agent = Arc::new(Agent::new(agent_config).unwrap());
         let agent_handle = {
             let agent = agent.clone();
@@ -1304,14 +8359,15 @@ mod agent_client_tests {
 
         sleep(Duration::from_millis(500)).await;
 
-        // 连接并握手
         let stream = UnixStream::connect(&socket_path).await.unwrap();
         let (reader, mut writer) = stream.into_split();
         let mut reader = BufReader::new(reader);
 
+        // 握手
         let handshake = Request::Handshake {
-            component: "integration-test".to_string(),
+            component: "test".to_string(),
             version: "1.0.0".to_string(),
+            protocol_version: PROTOCOL_VERSION,
         };
         writer
             .write_all(format!("{}\n", serde_json::to_string(&handshake).unwrap()).as_bytes())
@@ -1320,22 +8376,38 @@ mod agent_client_tests {
 
         let mut line = String::new();
         reader.read_line(&mut line).await.unwrap();
+
+        // 查询连接数
+        line.clear();
+        let query = Request::Query {
+            query_type: QueryType::ConnectionCount,
+        };
+        writer
+            .write_all(format!("{}\n", serde_json::to_string(&query).unwrap()).as_bytes())
+            .await
+            .unwrap();
+
+        reader.read_line(&mut line).await.unwrap();
         let response: Response = serde_json::from_str(&line).unwrap();
 
         match response {
-            Response::HandshakeOk { agent_version } => {
-                assert!(!agent_version.is_empty());
+            Response::QueryResult { data } => {
+                let count = data.get("count").and_then(|v| v.as_i64()).unwrap_or(0);
+                assert!(count >= 1); // 至少有当前连接
             }
-            _ => panic!("Expected HandshakeOk"),
+            _ => panic!("Expected QueryResult"),
         }
 
         agent_handle.abort();
     }
 
     #[tokio::test]
-    async fn test_agent_client_heartbeat() {
-        let (agent_config, _tmp) = test_agent_config();
+    async fn test_reader_collect_lands_via_agent() {
+        use ai_cli_session_db::{Collector, DbConfig, SessionDB};
+
+        let (agent_config, tmp) = test_agent_config();
         let socket_path = agent_config.socket_path();
+        let db_path = agent_config.db_path();
 
         let agent = Arc::new(Agent::new(agent_config).unwrap());
         let agent_handle = {
@@ -1347,6 +8419,28 @@ mod agent_client_tests {
 
         sleep(Duration::from_millis(500)).await;
 
+        // 组件侧：只读解析会话文件，生成采集批次
+        let dir = tmp.path().join(".claude/projects/-tmp-reader-project");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("reader-session.jsonl");
+        std::fs::write(
+            &file,
+            concat!(
+                "{\"type\":\"user\",\"uuid\":\"r1\",\"sessionId\":\"reader-session\",\"cwd\":\"/tmp/reader-project\",\"timestamp\":\"2025-01-01T00:00:00Z\",\"message\":{\"role\":\"user\",\"content\":\"hello from reader\"}}\n",
+                "{\"type\":\"user\",\"uuid\":\"r2\",\"sessionId\":\"reader-session\",\"cwd\":\"/tmp/reader-project\",\"timestamp\":\"2025-01-01T00:00:01Z\",\"message\":{\"role\":\"user\",\"content\":\"second message\"}}\n",
+            ),
+        )
+        .unwrap();
+
+        let reader_db = SessionDB::connect(DbConfig::local(&db_path)).unwrap();
+        let batch = Collector::new(&reader_db)
+            .prepare_by_path(file.to_str().unwrap())
+            .unwrap()
+            .expect("session should produce a batch");
+        assert_eq!(batch.messages.len(), 2);
+        // prepare 不写入数据库
+        assert!(!reader_db.session_exists("reader-session").unwrap());
+
         let stream = UnixStream::connect(&socket_path).await.unwrap();
         let (reader, mut writer) = stream.into_split();
         let mut reader = BufReader::new(reader);
@@ -1355,6 +8449,7 @@ mod agent_client_tests {
         let handshake = Request::Handshake {
             component: "test".to_string(),
             version: "1.0.0".to_string(),
+            protocol_version: PROTOCOL_VERSION,
         };
         writer
             .write_all(format!("{}\n", serde_json::to_string(&handshake).unwrap()).as_bytes())
@@ -1364,23 +8459,36 @@ mod agent_client_tests {
         let mut line = String::new();
         reader.read_line(&mut line).await.unwrap();
 
-        // 心跳
+        // 交给 Agent 写入
         line.clear();
-        let heartbeat = Request::Heartbeat;
+        let request = Request::WriteCollectBatch { batch };
         writer
-            .write_all(format!("{}\n", serde_json::to_string(&heartbeat).unwrap()).as_bytes())
+            .write_all(format!("{}\n", serde_json::to_string(&request).unwrap()).as_bytes())
             .await
             .unwrap();
 
         reader.read_line(&mut line).await.unwrap();
         let response: Response = serde_json::from_str(&line).unwrap();
-        assert!(matches!(response, Response::Ok));
+        match response {
+            Response::QueryResult { data } => {
+                assert_eq!(data["messages_inserted"].as_u64(), Some(2));
+            }
+            _ => panic!("Expected QueryResult, got {:?}", response),
+        }
+
+        // 由 Agent 写入后组件可读取
+        let messages = reader_db.get_messages("reader-session").unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].sequence, 0);
+        assert_eq!(messages[1].sequence, 1);
 
         agent_handle.abort();
     }
 
     #[tokio::test]
-    async fn test_agent_client_query_status() {
+    async fn test_collect_all_pushes_started_and_finished() {
+        use ai_cli_session_db::protocol::{collect_trigger, Push};
+
         let (agent_config, _tmp) = test_agent_config();
         let socket_path = agent_config.socket_path();
 
@@ -1402,6 +8510,7 @@ mod agent_client_tests {
         let handshake = Request::Handshake {
             component: "test".to_string(),
             version: "1.0.0".to_string(),
+            protocol_version: PROTOCOL_VERSION,
         };
         writer
             .write_all(format!("{}\n", serde_json::to_string(&handshake).unwrap()).as_bytes())
@@ -1411,34 +8520,67 @@ mod agent_client_tests {
         let mut line = String::new();
         reader.read_line(&mut line).await.unwrap();
 
-        // 查询状态
-        line.clear();
-        let query = Request::Query {
-            query_type: QueryType::Status,
-        };
+        // 触发全量采集
         writer
-            .write_all(format!("{}\n", serde_json::to_string(&query).unwrap()).as_bytes())
+            .write_all(
+                format!("{}\n", serde_json::to_string(&Request::CollectAll).unwrap()).as_bytes(),
+            )
             .await
             .unwrap();
 
-        reader.read_line(&mut line).await.unwrap();
-        let response: Response = serde_json::from_str(&line).unwrap();
+        // 依次收到 CollectStarted、CollectFinished 和请求响应
+        let mut pushes = Vec::new();
+        let response = loop {
+            line.clear();
+            tokio::time::timeout(Duration::from_secs(30), reader.read_line(&mut line))
+                .await
+                .expect("collect should finish")
+                .unwrap();
+            match serde_json::from_str::<Push>(&line) {
+                Ok(push) => pushes.push(push),
+                Err(_) => break serde_json::from_str::<Response>(&line).unwrap(),
+            }
+        };
+
+        assert_eq!(pushes.len(), 2);
+        assert!(matches!(
+            &pushes[0],
+            Push::CollectStarted { trigger, .. } if trigger == collect_trigger::REQUEST
+        ));
+        let finished = match &pushes[1] {
+            Push::CollectFinished {
+                trigger,
+                summary,
+                error,
+                ..
+            } => {
+                assert_eq!(trigger, collect_trigger::REQUEST);
+                assert!(error.is_none());
+                summary.clone()
+            }
+            other => panic!("Expected CollectFinished, got {:?}", other),
+        };
 
         match response {
             Response::QueryResult { data } => {
-                assert!(data.get("agent_version").is_some());
-                assert!(data.get("connections").is_some());
+                assert_eq!(
+                    data["sessions_scanned"].as_u64(),
+                    Some(finished.sessions_scanned as u64)
+                );
             }
-            _ => panic!("Expected QueryResult"),
+            _ => panic!("Expected QueryResult, got {:?}", response),
         }
 
         agent_handle.abort();
     }
 
     #[tokio::test]
-    async fn test_multiple_clients_concurrent() {
-        let (agent_config, _tmp) = test_agent_config();
-        let socket_path = agent_config.socket_path();
+    async fn test_client_writer_role() {
+        use ai_cli_session_db::client::{connect_or_start_agent, ClientConfig};
+        use ai_cli_session_db::protocol::writer_type;
+
+        let (agent_config, tmp) = test_agent_config();
+        let before = chrono::Utc::now().timestamp_millis();
 
         let agent = Arc::new(Agent::new(agent_config).unwrap());
         let agent_handle = {
@@ -1450,52 +8592,111 @@ mod agent_client_tests {
 
         sleep(Duration::from_millis(500)).await;
 
-        // 创建多个并发客户端
-        let mut handles = vec![];
+        let config = ClientConfig {
+            data_dir: tmp.path().to_path_buf(),
+            ..ClientConfig::new("test")
+        };
+        let mut client = connect_or_start_agent(config).await.unwrap();
 
-        for i in 0..3 {
-            let socket_path = socket_path.clone();
-            let handle = tokio::spawn(async move {
-                let stream = UnixStream::connect(&socket_path).await.unwrap();
-                let (reader, mut writer) = stream.into_split();
-                let mut reader = BufReader::new(reader);
+        // 新启动的 Agent 即为 Writer，取得时间不早于启动
+        let role = client.writer_role().await.unwrap();
+        assert!(role.is_writer);
+        assert_eq!(role.writer_type, writer_type::AGENT);
+        assert!(role.since >= before);
+        assert!(role.since <= chrono::Utc::now().timestamp_millis());
 
-                // 握手
-                let handshake = Request::Handshake {
-                    component: format!("client-{}", i),
-                    version: "1.0.0".to_string(),
-                };
-                writer
-                    .write_all(format!("{}\n", serde_json::to_string(&handshake).unwrap()).as_bytes())
-                    .await
-                    .unwrap();
+        agent_handle.abort();
+    }
 
-                let mut line = String::new();
-                reader.read_line(&mut line).await.unwrap();
+    #[tokio::test]
+    async fn test_client_mark_indexed() {
+        use ai_cli_session_db::client::{connect_or_start_agent, ClientConfig};
+        use ai_cli_session_db::db::MessageInput;
+        use ai_cli_session_db::{DbConfig, IndexState, MessageType, SessionDB};
 
-                let response: Response = serde_json::from_str(&line).unwrap();
-                assert!(matches!(response, Response::HandshakeOk { .. }));
+        let (agent_config, tmp) = test_agent_config();
+        let db_path = agent_config.db_path();
 
-                sleep(Duration::from_millis(100)).await;
-                i
-            });
-            handles.push(handle);
-        }
+        let agent = Arc::new(Agent::new(agent_config).unwrap());
+        let agent_handle = {
+            let agent = agent.clone();
+            tokio::spawn(async move {
+                let _ = agent.run().await;
+            })
+        };
 
-        // 等待所有客户端
-        for handle in handles {
-            let client_id = handle.await.unwrap();
-            assert!(client_id < 3);
-        }
+        sleep(Duration::from_millis(500)).await;
+
+        let db = SessionDB::connect(DbConfig::local(&db_path)).unwrap();
+        let project_id = db
+            .get_or_create_project("index", "/index", "claude")
+            .unwrap();
+        db.upsert_session("index-session", project_id).unwrap();
+        let messages: Vec<_> = (0..4)
+            .map(|i| MessageInput {
+                uuid: format!("index-{}", i),
+                r#type: MessageType::Assistant,
+                content_text: format!("answer {}", i),
+                content_full: format!("answer {}", i),
+                timestamp: 1000 + i,
+                sequence: i,
+                source: None,
+                channel: None,
+                model: None,
+                tool_call_id: None,
+                tool_name: None,
+                tool_args: None,
+                raw: None,
+                approval_status: None,
+                approval_resolved_at: None,
+            })
+            .collect();
+        let (_, ids) = db.insert_messages("index-session", &messages).unwrap();
+
+        let config = ClientConfig {
+            data_dir: tmp.path().to_path_buf(),
+            ..ClientConfig::new("test")
+        };
+        let mut client = connect_or_start_agent(config).await.unwrap();
+
+        client
+            .mark_indexed(ids[..2].to_vec(), IndexState::Indexed)
+            .await
+            .unwrap();
+        client
+            .mark_indexed(vec![ids[2]], IndexState::Failed)
+            .await
+            .unwrap();
+
+        let indexed: Vec<bool> = db
+            .list_messages("index-session", 10, 0)
+            .unwrap()
+            .iter()
+            .map(|m| m.vector_indexed)
+            .collect();
+        assert_eq!(indexed[..2], [true, true]);
+        assert!(!indexed[3]);
+        assert_eq!(db.count_failed_indexed_messages().unwrap(), 1);
+        assert_eq!(db.count_unindexed_messages().unwrap(), 1);
+
+        // 失败的消息可以重置为待索引
+        client
+            .mark_indexed(vec![ids[2]], IndexState::Pending)
+            .await
+            .unwrap();
+        assert_eq!(db.count_failed_indexed_messages().unwrap(), 0);
+        assert_eq!(db.count_unindexed_messages().unwrap(), 2);
 
         agent_handle.abort();
     }
 
-
     #[tokio::test]
-    async fn test_connection_count_query() {
-        let (agent_config, _tmp) = test_agent_config();
-        let socket_path = agent_config.socket_path();
+    async fn test_client_forget_project_requires_confirm_token() {
+        use ai_cli_session_db::client::{connect_or_start_agent, ClientConfig};
+        use ai_cli_session_db::{DbConfig, IgnoreKind, SessionDB};
+
+        let (agent_config, tmp) = test_agent_config();
+        let db_path = agent_config.db_path();
 
         let agent = Arc::new(Agent::new(agent_config).unwrap());
         let agent_handle = {
@@ -1507,44 +8708,270 @@ mod agent_client_tests {
 
         sleep(Duration::from_millis(500)).await;
 
-        let stream = UnixStream::connect(&socket_path).await.unwrap();
-        let (reader, mut writer) = stream.into_split();
-        let mut reader = BufReader::new(reader);
+        let db = SessionDB::connect(DbConfig::local(&db_path)).unwrap();
+        let project_id = db
+            .get_or_create_project("forget", "/forget", "claude")
+            .unwrap();
+        db.upsert_session("forget-session", project_id).unwrap();
 
-        // 握手
-        let handshake = Request::Handshake {
-            component: "test".to_string(),
-            version: "1.0.0".to_string(),
+        let config = ClientConfig {
+            data_dir: tmp.path().to_path_buf(),
+            ..ClientConfig::new("test")
         };
-        writer
-            .write_all(format!("{}\n", serde_json::to_string(&handshake).unwrap()).as_bytes())
+        let mut client = connect_or_start_agent(config).await.unwrap();
+
+        let preview = client.forget_project(project_id, true, None).await.unwrap();
+        assert!(preview.dry_run);
+        assert_eq!(preview.sessions, 1);
+        assert!(db.session_exists("forget-session").unwrap());
+
+        let err = client
+            .forget_project(project_id, true, Some("stale".to_string()))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("code=409"), "{}", err);
+        assert!(db.session_exists("forget-session").unwrap());
+
+        let report = client
+            .forget_project(project_id, true, Some(preview.confirm_token))
             .await
             .unwrap();
+        assert!(!report.dry_run);
+        assert_eq!(report.sessions, 1);
+        assert!(!db.session_exists("forget-session").unwrap());
+        assert!(db
+            .list_ignores()
+            .unwrap()
+            .iter()
+            .any(|rule| rule.pattern == "/forget" && rule.kind == IgnoreKind::ProjectPath));
 
-        let mut line = String::new();
-        reader.read_line(&mut line).await.unwrap();
+        agent_handle.abort();
+    }
 
-        // 查询连接数
-        line.clear();
-        let query = Request::Query {
-            query_type: QueryType::ConnectionCount,
+    #[tokio::test]
+    async fn test_client_search_omits_content_by_default() {
+        use ai_cli_session_db::client::{connect_or_start_agent, ClientConfig};
+        use ai_cli_session_db::db::MessageInput;
+        use ai_cli_session_db::{DbConfig, MessageType, SearchGroupOptions, SessionDB};
+
+        let (agent_config, tmp) = test_agent_config();
+        let db_path = agent_config.db_path();
+
+        let agent = Arc::new(Agent::new(agent_config).unwrap());
+        let agent_handle = {
+            let agent = agent.clone();
+            tokio::spawn(async move {
+                let _ = agent.run().await;
+            })
         };
-        writer
-            .write_all(format!("{}\n", serde_json::to_string(&query).unwrap()).as_bytes())
+
+        sleep(Duration::from_millis(500)).await;
+
+        let db = SessionDB::connect(DbConfig::local(&db_path)).unwrap();
+        let project_id = db
+            .get_or_create_project("search", "/search", "claude")
+            .unwrap();
+        db.upsert_session("search-session", project_id).unwrap();
+        let body = format!("needle {}", "z ".repeat(10_000));
+        let message = MessageInput {
+            uuid: "search-0".to_string(),
+            r#type: MessageType::Tool,
+            content_text: body.clone(),
+            content_full: body.clone(),
+            timestamp: 1000,
+            sequence: 0,
+            source: None,
+            channel: None,
+            model: None,
+            tool_call_id: None,
+            tool_name: None,
+            tool_args: None,
+            raw: None,
+            approval_status: None,
+            approval_resolved_at: None,
+        };
+        db.insert_messages("search-session", &[message]).unwrap();
+
+        let config = ClientConfig {
+            data_dir: tmp.path().to_path_buf(),
+            ..ClientConfig::new("test")
+        };
+        let mut client = connect_or_start_agent(config).await.unwrap();
+
+        let hits = client
+            .search("needle", 10, SearchGroupOptions::default(), None)
             .await
             .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].content_full.is_empty());
+        assert!(hits[0].snippet.contains("needle"));
 
-        reader.read_line(&mut line).await.unwrap();
-        let response: Response = serde_json::from_str(&line).unwrap();
+        let messages = client
+            .get_messages_by_ids(&[hits[0].message_id])
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content_full, body);
 
-        match response {
-            Response::QueryResult { data } => {
-                let count = data.get("count").and_then(|v| v.as_i64()).unwrap_or(0);
-                assert!(count >= 1); // 至少有当前连接
+        // 显式要求时返回正文
+        let options = SearchGroupOptions {
+            include_content: Some(true),
+            ..Default::default()
+        };
+        let hits = client.search("needle", 10, options, None).await.unwrap();
+        assert_eq!(hits[0].content_full, body);
+
+        agent_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_client_collect_now() {
+        use ai_cli_session_db::client::{connect_or_start_agent, ClientConfig};
+        use ai_cli_session_db::protocol::Push;
+        use ai_cli_session_db::{DbConfig, SessionDB};
+
+        let (agent_config, tmp) = test_agent_config();
+        let db_path = agent_config.db_path();
+
+        let agent = Arc::new(Agent::new(agent_config).unwrap());
+        let agent_handle = {
+            let agent = agent.clone();
+            tokio::spawn(async move {
+                let _ = agent.run().await;
+            })
+        };
+
+        sleep(Duration::from_millis(500)).await;
+
+        let config = ClientConfig {
+            data_dir: tmp.path().to_path_buf(),
+            ..ClientConfig::new("test")
+        };
+        let mut client = connect_or_start_agent(config).await.unwrap();
+
+        let summary = tokio::time::timeout(Duration::from_secs(30), client.collect_now())
+            .await
+            .expect("collect should finish")
+            .unwrap();
+
+        // 响应前已推送 CollectFinished，摘要一致
+        let mut finished = None;
+        while let Some(push) = client.try_recv_push() {
+            if let Push::CollectFinished { summary, .. } = push {
+                finished = Some(summary);
             }
-            _ => panic!("Expected QueryResult"),
         }
+        assert_eq!(finished, Some(summary));
+
+        // 采集结束后采集锁已释放
+        let db = SessionDB::connect(DbConfig::local(&db_path)).unwrap();
+        assert!(db.get_collection_lock().unwrap().is_none());
 
         agent_handle.abort();
     }
+
+    #[tokio::test]
+    async fn test_client_validates_connection_on_resume() {
+        use ai_cli_session_db::client::{connect_or_start_agent, ClientConfig};
+        use ai_cli_session_db::protocol::Push;
+        use tokio::net::UnixListener;
+
+        let tmp = TempDir::new().unwrap();
+        let listener = UnixListener::bind(tmp.path().join("agent.sock")).unwrap();
+
+        // 模拟 Agent：唤醒后第一次正常响应 Heartbeat，第二次直接断开
+        let fake_agent = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut reader = BufReader::new(reader);
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+
+            let send = |value: serde_json::Value| format!("{}\n", value);
+            let resumed = serde_json::to_value(Push::AgentResumed {
+                slept_secs: 600,
+                change_counter: 0,
+            })
+            .unwrap();
+            let handshake_ok = serde_json::to_value(Response::HandshakeOk {
+                agent_version: ai_cli_session_db::VERSION_FULL.to_string(),
+                protocol_version: PROTOCOL_VERSION,
+                degraded: None,
+            })
+            .unwrap();
+            writer
+                .write_all(send(handshake_ok).as_bytes())
+                .await
+                .unwrap();
+            writer
+                .write_all(send(resumed.clone()).as_bytes())
+                .await
+                .unwrap();
+
+            line.clear();
+            reader.read_line(&mut line).await.unwrap();
+            assert!(matches!(
+                serde_json::from_str::<Request>(&line).unwrap(),
+                Request::Heartbeat
+            ));
+            let ok = serde_json::to_value(Response::Ok).unwrap();
+            writer.write_all(send(ok).as_bytes()).await.unwrap();
+
+            writer.write_all(send(resumed).as_bytes()).await.unwrap();
+        });
+
+        let config = ClientConfig {
+            data_dir: tmp.path().to_path_buf(),
+            connect_retries: 1,
+            ..ClientConfig::new("test")
+        };
+        let mut client = connect_or_start_agent(config).await.unwrap();
+
+        let push = client.recv_push().await.unwrap();
+        assert!(matches!(
+            push,
+            Push::AgentResumed {
+                slept_secs: 600,
+                ..
+            }
+        ));
+        assert!(!client.is_degraded());
+
+        // Agent 已断开：Heartbeat 失败，标记为 degraded
+        fake_agent.await.unwrap();
+        let push = client.recv_push().await.unwrap();
+        assert!(matches!(push, Push::AgentResumed { .. }));
+        assert!(client.is_degraded());
+    }
+
+    #[tokio::test]
+    async fn test_start_agent_reports_early_exit() {
+        use ai_cli_session_db::client::{connect_or_start_agent, ClientConfig};
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = TempDir::new().unwrap();
+        let script = tmp.path().join("vimo-agent");
+        std::fs::write(
+            &script,
+            "#!/bin/sh\necho 'fatal: cannot open database' >&2\nexit 3\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let config = ClientConfig {
+            data_dir: tmp.path().to_path_buf(),
+            connect_retries: 1,
+            ..ClientConfig::new("test")
+        }
+        .with_agent_binary(script);
+
+        // 不再等到超时：直接报告退出码和 agent.log 末尾
+        let message = match connect_or_start_agent(config).await {
+            Ok(_) => panic!("Expected startup failure"),
+            Err(e) => e.to_string(),
+        };
+        assert!(message.contains("exited during startup"), "{}", message);
+        assert!(message.contains('3'), "{}", message);
+        assert!(message.contains("fatal: cannot open database"), "{}", message);
+    }
 }